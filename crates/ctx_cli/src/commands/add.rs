@@ -1,12 +1,14 @@
-//! Add commands for notes and tasks.
+//! Add commands for notes, tasks and documents.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use chrono::Local;
-use ctx_core::CtxRepo;
+use ctx_core::{CtxRepo, ImportFormat};
+use serde_json::json;
+use std::collections::BTreeMap;
 
 /// Add a note to today's log.
-pub fn note(text: &str) -> Result<()> {
-    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+pub fn note(text: &str, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
     let ns = repo.narrative();
     ns.ensure_structure()?;
 
@@ -16,30 +18,121 @@ pub fn note(text: &str) -> Result<()> {
 
     let path = ns.append_log(&date, &time, text)?;
 
+    if json {
+        return crate::output::print_json(&json!({ "path": path }));
+    }
     println!("Added note to {}", path);
     Ok(())
 }
 
 /// Create a new task.
-pub fn task(title: &str, body: Option<&str>) -> Result<()> {
-    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+pub fn task(title: &str, body: Option<&str>, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
     let ns = repo.narrative();
     ns.ensure_structure()?;
 
     let task = ns.create_task(title, body.unwrap_or(""))?;
 
+    if json {
+        return crate::output::print_json(&task);
+    }
     println!("Created task #{:04}: {}", task.id, task.relative_path);
     Ok(())
 }
 
 /// Update a task's status.
-pub fn task_update(id: u32, status: &str, note: Option<&str>) -> Result<()> {
-    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+pub fn task_update(id: u32, status: &str, note: Option<&str>, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
     let ns = repo.narrative();
 
     let path = ns.update_task(id, status, note.unwrap_or(""))?;
 
+    if json {
+        return crate::output::print_json(&json!({
+            "id": id,
+            "status": status,
+            "relative_path": path,
+        }));
+    }
     println!("Updated task #{:04}: status -> {}", id, status);
     println!("  File: {}", path);
     Ok(())
 }
+
+/// Largest document `ctx add doc` downloads.
+const MAX_IMPORT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Create a document of a configured type, or import the file or URL
+/// `kind` names if it isn't one, and commit it.
+pub fn document(kind: &str, fields: &[String], json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".").context("Not a CTX repository")?;
+
+    let is_source = kind.starts_with("http://")
+        || kind.starts_with("https://")
+        || std::path::Path::new(kind).is_file();
+    if is_source && !repo.narrative().document_types().contains_key(kind) {
+        if !fields.is_empty() {
+            bail!("--field only applies to document types, not imports");
+        }
+        return import(&mut repo, kind, json);
+    }
+
+    let mut values = BTreeMap::new();
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .with_context(|| format!("Invalid field '{}': expected key=value", field))?;
+        values.insert(key.trim().to_string(), value.trim().to_string());
+    }
+
+    let document = repo.create_document(kind, &values)?;
+
+    if json {
+        return crate::output::print_json(&document);
+    }
+    println!(
+        "Created {} #{:04}: {}",
+        document.kind, document.id, document.relative_path
+    );
+    Ok(())
+}
+
+/// Import the file or URL `source` into the narrative.
+fn import(repo: &mut CtxRepo, source: &str, json: bool) -> Result<()> {
+    let (content, content_type) = if source.starts_with("http://") || source.starts_with("https://")
+    {
+        let response = ureq::get(source)
+            .timeout(std::time::Duration::from_secs(30))
+            .call()
+            .with_context(|| format!("Failed to fetch {}", source))?;
+        let content_type = response.header("content-type").map(str::to_string);
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(
+            &mut std::io::Read::take(response.into_reader(), MAX_IMPORT_BYTES + 1),
+            &mut content,
+        )
+        .with_context(|| format!("Failed to read {}", source))?;
+        if content.len() as u64 > MAX_IMPORT_BYTES {
+            bail!("{} is larger than {} bytes", source, MAX_IMPORT_BYTES);
+        }
+        (content, content_type)
+    } else {
+        let content =
+            std::fs::read(source).with_context(|| format!("Failed to read {}", source))?;
+        (content, None)
+    };
+
+    let format = ImportFormat::detect(source, content_type.as_deref());
+    let info = repo.import_document(source, &content, format)?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "source": source,
+            "kind": info.kind,
+            "id": info.id,
+            "relative_path": info.relative_path,
+        }));
+    }
+    println!("Imported {} as {}", source, info.relative_path);
+    Ok(())
+}
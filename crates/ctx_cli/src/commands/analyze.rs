@@ -1,77 +1,204 @@
 //! Analyze commands for semantic code analysis.
 
 use anyhow::Result;
-use ctx_core::{CtxRepo, RustAnalyzer};
+use ctx_core::{AnalysisReport, CtxRepo, DryRunReport, ExecPolicy, RustAnalyzer};
+use serde_json::json;
 use std::path::Path;
 
 /// Analyze Rust code using rust-analyzer.
-pub fn analyze_rust(file: Option<&Path>) -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+///
+/// With `dry_run`, reports the commit and edges the analysis would record
+/// without writing them.
+pub fn analyze_rust(file: Option<&Path>, dry_run: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
 
     match file {
         Some(path) => {
-            // Analyze single file
-            println!("Analyzing {}...", path.display());
-
-            let report = repo.analyze_rust_file(path)?;
+            if !json {
+                println!("Analyzing {}...", path.display());
+            }
+            let (report, dry_run) = run(&mut repo, dry_run, |repo| repo.analyze_rust_file(path))?;
+            if json && dry_run.is_none() {
+                return crate::output::print_json(&report);
+            }
 
-            println!("Analysis complete:");
-            println!("  Symbols found: {}", report.symbols);
-            println!("  Calls resolved: {}", report.calls);
-            println!("  Edges generated: {}", report.edges);
-            println!("  Edge batch ID: {}", report.edge_batch_id.as_hex());
+            if !json {
+                println!("Analysis complete:");
+                println!("  Symbols found: {}", report.symbols);
+                println!("  Calls resolved: {}", report.calls);
+                println!("  Edges generated: {}", report.edges);
+                println!("  Edge batch ID: {}", report.edge_batch_id.as_hex());
+            }
+            if let Some(dry_run) = dry_run {
+                crate::output::print_dry_run(&report, &dry_run, json)?;
+            }
         }
         None => {
-            // Analyze all Rust files
-            println!("Analyzing all Rust files in project...");
-
-            let report = repo.analyze_rust()?;
+            if !json {
+                println!("Analyzing all Rust files in project...");
+            }
+            let (report, dry_run) = run(&mut repo, dry_run, |repo| analyze_all(repo, json))?;
+            if json && dry_run.is_none() {
+                return crate::output::print_json(&report);
+            }
 
-            println!("Analysis complete:");
-            println!("  Files analyzed: {}", report.files_analyzed);
-            println!("  Symbols found: {}", report.symbols_found);
-            println!("  Calls resolved: {}", report.calls_resolved);
-            println!("  Edges generated: {}", report.edges_generated);
-            println!("  Edge batch ID: {}", report.edge_batch_id.as_hex());
+            if !json {
+                println!("Analysis complete:");
+                println!("  Files analyzed: {}", report.files_analyzed);
+                println!("  Symbols found: {}", report.symbols_found);
+                println!("  Calls resolved: {}", report.calls_resolved);
+                println!("  Edges generated: {}", report.edges_generated);
+                println!("  Edge batch ID: {}", report.edge_batch_id.as_hex());
+            }
+            if let Some(dry_run) = dry_run {
+                crate::output::print_dry_run(&report, &dry_run, json)?;
+            }
         }
     }
 
     Ok(())
 }
 
+/// Runs `analyze`, as a dry run if `dry_run` is set.
+fn run<T>(
+    repo: &mut CtxRepo,
+    dry_run: bool,
+    analyze: impl FnOnce(&mut CtxRepo) -> ctx_core::Result<T>,
+) -> Result<(T, Option<DryRunReport>)> {
+    if dry_run {
+        let (report, dry_run) = repo.dry_run(analyze)?;
+        Ok((report, Some(dry_run)))
+    } else {
+        Ok((analyze(repo)?, None))
+    }
+}
+
+/// Analyzes every Rust file behind a progress bar that Ctrl-C cancels.
+fn analyze_all(repo: &mut CtxRepo, json: bool) -> ctx_core::Result<AnalysisReport> {
+    let (progress, pb) = crate::progress::progress_bar(json);
+    let report = repo.analyze_rust_with_progress(&progress);
+    pb.finish_and_clear();
+    report
+}
+
 /// Analyze Cargo workspace metadata.
-pub fn analyze_cargo() -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+///
+/// With `full`, the transitive dependency graph is resolved too. With
+/// `dry_run`, reports the commit the analysis would record without writing
+/// it.
+pub fn analyze_cargo(full: bool, dry_run: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let analyze = |repo: &mut CtxRepo| {
+        if full {
+            repo.analyze_cargo_full()
+        } else {
+            repo.analyze_cargo()
+        }
+    };
 
-    println!("Analyzing Cargo workspace...");
+    if !json {
+        println!("Analyzing Cargo workspace...");
+    }
+    let (report, dry_run) = run(&mut repo, dry_run, analyze)?;
+    if json && dry_run.is_none() {
+        return crate::output::print_json(&report);
+    }
 
-    let report = repo.analyze_cargo()?;
+    if !json {
+        println!("Cargo analysis complete:");
+        println!("  Packages found: {}", report.packages_found);
+        println!("  Targets found: {}", report.targets_found);
+        println!("  Dependencies found: {}", report.dependencies_found);
+        if full {
+            println!("  Resolved packages: {}", report.resolved_packages);
+        }
+        println!("  Edges generated: {}", report.edges_generated);
+        println!("  Snapshot ID: {}", report.snapshot_id.as_hex());
+        println!("  Edge batch ID: {}", report.edge_batch_id.as_hex());
+        println!("  Commit ID: {}", report.commit_id.as_hex());
+    }
+    if let Some(dry_run) = dry_run {
+        crate::output::print_dry_run(&report, &dry_run, json)?;
+    }
 
-    println!("Cargo analysis complete:");
-    println!("  Packages found: {}", report.packages_found);
-    println!("  Targets found: {}", report.targets_found);
-    println!("  Dependencies found: {}", report.dependencies_found);
-    println!("  Edges generated: {}", report.edges_generated);
-    println!("  Snapshot ID: {}", report.snapshot_id.as_hex());
-    println!("  Edge batch ID: {}", report.edge_batch_id.as_hex());
-    println!("  Commit ID: {}", report.commit_id.as_hex());
+    Ok(())
+}
+
+/// List the symbols recorded by the last Rust analysis, without running
+/// rust-analyzer.
+pub fn symbols(file: Option<&str>, json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let Some(snapshot) = repo.rust_snapshot()? else {
+        anyhow::bail!("No Rust analysis recorded. Run 'ctx analyze rust' first.");
+    };
+    let files: Vec<_> = snapshot
+        .files
+        .iter()
+        .filter(|(path, _)| file.map_or(true, |file| file == path.as_str()))
+        .collect();
+    if let (Some(file), true) = (file, files.is_empty()) {
+        anyhow::bail!("{} was not analyzed", file);
+    }
+
+    if json {
+        let files: serde_json::Map<_, _> = files
+            .into_iter()
+            .map(|(path, analysis)| Ok((path.clone(), serde_json::to_value(&analysis.items)?)))
+            .collect::<Result<_>>()?;
+        return crate::output::print_json(&files);
+    }
+
+    for (path, analysis) in files {
+        println!("{}", path);
+        for item in &analysis.items {
+            println!(
+                "  {:?} {} (line {})",
+                item.kind,
+                item.qualified_name,
+                item.range.start_line + 1
+            );
+        }
+        for warning in &analysis.warnings {
+            println!("  Warning: {}", warning);
+        }
+    }
 
     Ok(())
 }
 
 /// Show analysis tool availability status.
-pub fn status() -> Result<()> {
+pub fn status(json: bool) -> Result<()> {
+    // Outside a repository there is no config, so nothing is restricted
+    let policy = match super::open_repo(".") {
+        Ok(repo) => repo.exec_policy().clone(),
+        Err(_) => ExecPolicy::default(),
+    };
+
+    if json {
+        let available = RustAnalyzer::is_available(&policy);
+        let version = available
+            .then(|| policy.output(std::process::Command::new("rust-analyzer").arg("--version")))
+            .and_then(|output| output.ok())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|version| version.trim().to_string());
+        return crate::output::print_json(&json!({
+            "rust_analyzer": {
+                "available": available,
+                "version": version,
+            },
+        }));
+    }
+
     println!("Analysis Tool Status:");
     println!();
 
     // Check rust-analyzer
-    if RustAnalyzer::is_available() {
+    if RustAnalyzer::is_available(&policy) {
         println!("  rust-analyzer: installed ✓");
 
         // Try to get version
-        if let Ok(output) = std::process::Command::new("rust-analyzer")
-            .arg("--version")
-            .output()
+        if let Ok(output) =
+            policy.output(std::process::Command::new("rust-analyzer").arg("--version"))
         {
             if let Ok(version) = String::from_utf8(output.stdout) {
                 println!("    Version: {}", version.trim());
@@ -0,0 +1,32 @@
+//! Retrieval audit log command.
+
+use anyhow::Result;
+use console::style;
+use std::io::{self, Write};
+
+use super::debug::parse_since;
+
+/// Print the retrievals recorded since `since`, one JSON record per line.
+pub fn export(since: &str, json: bool) -> Result<()> {
+    let since = parse_since(since)?;
+    let repo = super::open_repo(".")?;
+    let records = repo.retrieval_records(since)?;
+    if json {
+        return crate::output::print_json(&records);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for record in &records {
+        writeln!(out, "{}", serde_json::to_string(record)?)?;
+    }
+    out.flush()?;
+
+    eprintln!(
+        "{} Exported {} retrieval record{}",
+        style("✓").green(),
+        records.len(),
+        if records.len() == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Bench command - compare retrieval configurations.
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use ctx_core::{BenchQuery, BenchRun, CtxRepo, RetrievalConfig};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Compare the packs two retrieval configurations build for the same
+/// queries, and print a summary, or the full report with `json`.
+///
+/// `a` and `b` are TOML files of [`RetrievalConfig`] fields; a missing
+/// file, or a field it doesn't set, means the default. Queries come from the
+/// `queries` expectations file, or else are the `limit` most recent distinct
+/// queries recorded by `ctx query`, unlabeled.
+pub fn retrieval(
+    a: Option<&Path>,
+    b: Option<&Path>,
+    queries: Option<&Path>,
+    limit: usize,
+    json: bool,
+) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    let queries = match queries {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            ctx_core::parse_expectations(&text)?
+        }
+        None => recorded_queries(&repo, limit)?,
+    };
+    if queries.is_empty() {
+        bail!("No queries to run: pass --queries or build some packs with `ctx query` first");
+    }
+
+    let report = repo
+        .bench_retrieval(&queries, &load_config(a)?, &load_config(b)?)
+        .context("Failed to run benchmark")?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    println!(
+        "{:<40} {:>9} {:>9} {:>7} {:>7} {:>8}",
+        style("Query").bold(),
+        style("Tokens A").bold(),
+        style("Tokens B").bold(),
+        style("Hits A").bold(),
+        style("Hits B").bold(),
+        style("Overlap").bold()
+    );
+    for query in &report.queries {
+        println!(
+            "{:<40} {:>9} {:>9} {:>7} {:>7} {:>8}",
+            truncate(&query.query, 40),
+            query.a.tokens,
+            query.b.tokens,
+            hits(&query.a, query.expect.len()),
+            hits(&query.b, query.expect.len()),
+            ratio(Some(query.overlap))
+        );
+    }
+    println!(
+        "{:<40} {:>9} {:>9} {:>7} {:>7} {:>8}",
+        style("mean").bold(),
+        report.a.mean_tokens,
+        report.b.mean_tokens,
+        ratio(report.a.hit_rate),
+        ratio(report.b.hit_rate),
+        ratio(Some(report.mean_overlap))
+    );
+    Ok(())
+}
+
+/// Expected entries met, as `hits/expected`, or `-` for unlabeled queries.
+fn hits(run: &BenchRun, expected: usize) -> String {
+    match run.hits {
+        Some(hits) => format!("{}/{}", hits, expected),
+        None => "-".to_string(),
+    }
+}
+
+/// A fixed-point ratio as a decimal, or `-` without one.
+fn ratio(value: Option<u32>) -> String {
+    match value {
+        Some(value) => format!("{:.2}", value as f64 / 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Shortens `text` to `width` characters, marking the cut.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(width - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Reads a retrieval configuration, or the defaults without a file.
+fn load_config(path: Option<&Path>) -> Result<RetrievalConfig> {
+    let Some(path) = path else {
+        return Ok(RetrievalConfig::default());
+    };
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    ctx_core::parse_retrieval_config(&text)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// The `limit` most recent distinct queries of recorded packs.
+fn recorded_queries(repo: &CtxRepo, limit: usize) -> Result<Vec<BenchQuery>> {
+    let mut seen = BTreeSet::new();
+    let mut queries = Vec::new();
+    // Each query may have been recorded many times
+    for entry in repo.recent_packs(usize::MAX)? {
+        if queries.len() == limit {
+            break;
+        }
+        if !entry.query.trim().is_empty() && seen.insert(entry.query.clone()) {
+            queries.push(BenchQuery {
+                query: entry.query,
+                expect: Vec::new(),
+            });
+        }
+    }
+    Ok(queries)
+}
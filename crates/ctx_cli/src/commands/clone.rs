@@ -0,0 +1,60 @@
+//! Repository clone command.
+
+use anyhow::{Context, Result};
+use console::style;
+use std::path::Path;
+
+use super::du::format_bytes;
+
+/// Copy the repository at `source` into a new one at `dest`, keeping only
+/// the last `depth` commits if given.
+pub fn run(source: &Path, dest: &Path, depth: Option<usize>, json: bool) -> Result<()> {
+    let repo = super::open_repo(source)?;
+    let report = repo
+        .clone_to(dest, depth)
+        .with_context(|| format!("Failed to clone into {}", dest.display()))?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    println!(
+        "{} Cloned {} into {}",
+        style("✓").green(),
+        source.display(),
+        dest.display()
+    );
+    println!("  HEAD:    {}", &report.head.as_hex()[..12]);
+    println!(
+        "  Objects: {} ({}), {} linked, {} copied",
+        report.objects,
+        format_bytes(report.bytes),
+        report.linked,
+        report.copied
+    );
+    println!("  Refs:    {}", report.refs.join(", "));
+    println!(
+        "  Files:   {} narrative, hook and config files",
+        report.files
+    );
+    if let Some(depth) = report.depth {
+        println!(
+            "  Shallow: last {} commit{}, history ends before {}",
+            depth,
+            if depth == 1 { "" } else { "s" },
+            report
+                .grafts
+                .iter()
+                .map(|id| id.as_hex()[..12].to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if !report.skipped_refs.is_empty() {
+        println!(
+            "  {} Skipped refs outside the kept history: {}",
+            style("⚠").yellow(),
+            report.skipped_refs.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -1,11 +1,17 @@
 //! Commit command for creating canonical commits.
 
 use anyhow::{Context, Result};
-use ctx_core::CtxRepo;
+use ctx_core::{AgentIdentity, Commit, CtxRepo, ObjectId};
+use serde_json::json;
 
 /// Create a new commit with the current narrative state.
-pub fn run(message: &str, no_narrative: bool) -> Result<()> {
-    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+///
+/// With `dry_run`, reports the commit that would be created without
+/// writing it.
+pub fn run(message: &str, no_narrative: bool, dry_run: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")
+        .context("Not a CTX repository")?
+        .with_identity(AgentIdentity::from_env());
 
     let narrative_refs = if no_narrative {
         Some(vec![]) // Explicit empty
@@ -13,12 +19,46 @@ pub fn run(message: &str, no_narrative: bool) -> Result<()> {
         None // Auto-detect
     };
 
-    let commit_id = repo.commit(message, narrative_refs, "user")?;
+    let commit = |repo: &mut CtxRepo| -> ctx_core::Result<(ObjectId, Commit)> {
+        let commit_id = repo.commit(message, narrative_refs, "user")?;
+        Ok((commit_id, repo.object_store().get_typed(commit_id)?))
+    };
+    let (report, (commit_id, commit)) = if dry_run {
+        let (created, report) = repo.dry_run(commit)?;
+        (Some(report), created)
+    } else {
+        (None, commit(&mut repo)?)
+    };
+
+    let output = || {
+        let narrative: Vec<_> = commit
+            .narrative_refs
+            .iter()
+            .map(|nr| json!({ "path": nr.path, "blob_id": nr.blob_id.as_hex() }))
+            .collect();
+        json!({
+            "commit_id": commit_id.as_hex(),
+            "author": repo.identity().map(|author| author.to_string()),
+            "narrative": narrative,
+        })
+    };
+    if json {
+        return match &report {
+            Some(report) => crate::output::print_dry_run(&output(), report, json),
+            None => crate::output::print_json(&output()),
+        };
+    }
 
-    println!("Created commit {}", commit_id.as_hex());
+    if report.is_some() {
+        println!("Would create commit {}", commit_id.as_hex());
+    } else {
+        println!("Created commit {}", commit_id.as_hex());
+    }
+    if let Some(author) = repo.identity() {
+        println!("Author: {}", author);
+    }
 
     // Show what was included
-    let commit: ctx_core::Commit = repo.object_store().get_typed(commit_id)?;
 
     if !commit.narrative_refs.is_empty() {
         println!("\nNarrative files snapshotted:");
@@ -27,5 +67,54 @@ pub fn run(message: &str, no_narrative: bool) -> Result<()> {
         }
     }
 
+    if let Some(report) = &report {
+        crate::output::print_dry_run(&output(), report, json)?;
+    }
+    Ok(())
+}
+
+/// Attach a note to a past commit, such as that it introduced a bug.
+pub fn annotate(commit: &str, text: &str, json: bool) -> Result<()> {
+    let repo = super::open_repo(".")
+        .context("Not a CTX repository")?
+        .with_identity(AgentIdentity::from_env());
+    let commit_id = repo
+        .resolve_commit(commit)
+        .with_context(|| format!("Unknown commit '{}'", commit))?;
+    let annotation_id = repo
+        .annotate(commit_id, text)
+        .context("Failed to annotate commit")?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "annotation_id": annotation_id.as_hex(),
+            "commit_id": commit_id.as_hex(),
+            "author": repo.identity().map(|author| author.to_string()),
+        }));
+    }
+    println!(
+        "Annotated commit {} ({} note(s))",
+        &commit_id.as_hex()[..8],
+        repo.note_table()?.get(commit_id).len()
+    );
+    Ok(())
+}
+
+/// Point a tag at a commit so it can be named instead of by hex ID.
+pub fn tag(name: &str, commit: &str, force: bool, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    let commit_id = repo
+        .resolve_commit(commit)
+        .with_context(|| format!("Unknown commit '{}'", commit))?;
+    repo.tag(name, commit_id, force)
+        .with_context(|| format!("Failed to tag '{}'", name))?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "tag": name,
+            "commit_id": commit_id.as_hex(),
+        }));
+    }
+    println!("Tagged {} as {}", &commit_id.as_hex()[..8], name);
     Ok(())
 }
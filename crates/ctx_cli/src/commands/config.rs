@@ -0,0 +1,66 @@
+//! Config commands for reading and changing `.ctx/config.toml`.
+
+use anyhow::{Context, Result};
+use ctx_core::Config;
+use serde_json::json;
+
+/// Show the effective value of a key.
+pub fn get(key: &str, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    let config = Config::load(&repo.ctx_dir())?;
+    let value = config.get(key)?;
+
+    if json {
+        return crate::output::print_json(&value);
+    }
+    // Strings print unquoted so the output can be used in scripts
+    if let Some(value) = value {
+        match value.as_str() {
+            Some(text) => println!("{}", text),
+            None => println!("{}", value),
+        }
+    }
+    Ok(())
+}
+
+/// Set a key in the config file, keeping its comments.
+pub fn set(key: &str, value: &str, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    Config::set_value(&repo.ctx_dir(), key, value)?;
+
+    let var = Config::env_var(key);
+    let overridden = std::env::var_os(&var).is_some_and(|v| !v.is_empty());
+
+    if json {
+        let value = Config::load(&repo.ctx_dir())?.get(key)?;
+        return crate::output::print_json(&json!({
+            "key": key,
+            "value": value,
+            "overridden_by": overridden.then_some(var),
+        }));
+    }
+    if overridden {
+        eprintln!("Note: {} is set and overrides this value", var);
+    }
+    Ok(())
+}
+
+/// List every setting with its effective value.
+pub fn list(json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    let config = Config::load(&repo.ctx_dir())?;
+    let values = config.values()?;
+
+    if json {
+        return crate::output::print_json(&values);
+    }
+    for (key, value) in &values {
+        let var = Config::env_var(key);
+        if std::env::var_os(&var).is_some_and(|v| !v.is_empty()) {
+            println!("{} = {} (from {})", key, value, var);
+        } else {
+            println!("{} = {}", key, value);
+        }
+    }
+    Ok(())
+}
@@ -2,8 +2,7 @@
 
 use anyhow::{Context, Result};
 use chrono::DateTime;
-use ctx_core::{Commit, CtxRepo, ObjectId, ObjectStore};
-use std::collections::{HashSet, VecDeque};
+use ctx_core::{CtxRepo, Event, EventKind, LogFilter, ObjectId, ObjectStore};
 use std::path::Path;
 
 /// Print the raw contents of an object.
@@ -133,6 +132,16 @@ fn print_typed_object(store: &ObjectStore, id: ObjectId) -> Result<()> {
         if let Some(commit_type) = &commit.commit_type {
             println!("Commit type: {:?}", commit_type);
         }
+        if let Some(author) = &commit.author {
+            println!("Author: {}", author);
+        }
+        if let Some(task) = &commit.task {
+            println!("Task: {}", task);
+        }
+        for (tag, paths) in &commit.tags {
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            println!("Tag: {} ({})", tag, paths.join(", "));
+        }
         if let Some(cargo) = commit.cargo_snapshot {
             println!("Cargo snapshot: {}", cargo.as_hex());
         }
@@ -142,6 +151,9 @@ fn print_typed_object(store: &ObjectStore, id: ObjectId) -> Result<()> {
         if let Some(diag) = commit.diagnostics_snapshot {
             println!("Diagnostics snapshot: {}", diag.as_hex());
         }
+        if let Some(glossary) = commit.glossary {
+            println!("Glossary: {}", glossary.as_hex());
+        }
         return Ok(());
     }
 
@@ -160,6 +172,9 @@ fn print_typed_object(store: &ObjectStore, id: ObjectId) -> Result<()> {
         println!("Narrative refs: {} ref(s)", work.narrative_refs.len());
         println!("Session state: {:?}", work.session_state);
         println!("Task: {}", work.task_description);
+        if let Some(author) = &work.author {
+            println!("Author: {}", author);
+        }
         return Ok(());
     }
 
@@ -253,28 +268,56 @@ pub fn refs() -> Result<()> {
     Ok(())
 }
 
-/// Show commit history from HEAD.
-pub fn history(limit: Option<usize>) -> Result<()> {
+/// Show the reflog of a ref, newest first.
+pub fn reflog(ref_name: &str, limit: Option<usize>) -> Result<()> {
     let repo = CtxRepo::open(".").context("Not a CTX repository (no .ctx directory found)")?;
-    let head_id = repo.head_id().context("HEAD not found")?;
+    let entries = repo.reflog(ref_name)?;
 
-    let mut count = 0;
-    let max_count = limit.unwrap_or(usize::MAX);
+    if entries.is_empty() {
+        println!("No reflog entries for {}.", ref_name);
+        return Ok(());
+    }
+    for (n, entry) in entries.iter().take(limit.unwrap_or(usize::MAX)).enumerate() {
+        let timestamp =
+            DateTime::from_timestamp(entry.timestamp_unix as i64, 0).unwrap_or_default();
+        println!(
+            "{}  {}@{{{}}}  {}  {}",
+            &entry.new.as_hex()[..12],
+            ref_name,
+            n,
+            timestamp.format("%Y-%m-%d %H:%M:%S"),
+            entry.reason
+        );
+    }
+    println!("\nMove back to one with: ctx reset --to {}@{{N}}", ref_name);
+    Ok(())
+}
 
-    let mut queue = VecDeque::new();
-    let mut seen = HashSet::new();
+/// Show commit history from HEAD, optionally filtered.
+///
+/// `since` accepts a date (`YYYY-MM-DD`) or a relative age such as `3d`,
+/// `12h` or `2w`.
+pub fn history(
+    limit: Option<usize>,
+    path: Option<&str>,
+    commit_type: Option<&str>,
+    since: Option<&str>,
+) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository (no .ctx directory found)")?;
 
-    queue.push_back(head_id);
+    let filter = LogFilter {
+        commit_type: commit_type.map(str::parse).transpose()?,
+        since: since.map(parse_since).transpose()?,
+        path: path.map(str::to_string),
+        ..Default::default()
+    };
 
-    while let Some(id) = queue.pop_front() {
-        if !seen.insert(id) || count >= max_count {
-            continue;
-        }
+    let notes = repo.note_table().context("Failed to read commit notes")?;
+    let mut count = 0;
+    let max_count = limit.unwrap_or(usize::MAX);
 
-        let commit: Commit = repo
-            .object_store()
-            .get_typed(id)
-            .with_context(|| format!("Failed to read commit {}", id.as_hex()))?;
+    for entry in repo.log(filter).context("HEAD not found")?.take(max_count) {
+        let (id, commit) = entry.context("Failed to read commit")?;
 
         // Format timestamp
         let timestamp =
@@ -290,17 +333,22 @@ pub fn history(limit: Option<usize>) -> Result<()> {
             }
             println!();
         }
+        if let Some(author) = &commit.author {
+            println!("Author: {}", author);
+        }
         println!("Date:   {}", formatted_time);
         println!();
         println!("    {}", commit.message);
         println!();
+        if !notes.get(id).is_empty() {
+            println!("Notes:");
+            for annotation in repo.annotations(id)? {
+                println!("    {}", annotation.text);
+            }
+            println!();
+        }
 
         count += 1;
-
-        // Add parents to queue
-        for parent in &commit.parents {
-            queue.push_back(*parent);
-        }
     }
 
     if count == 0 {
@@ -310,9 +358,76 @@ pub fn history(limit: Option<usize>) -> Result<()> {
     Ok(())
 }
 
+/// Show the commits that changed a path, newest first.
+pub fn path_history(path: &str, limit: Option<usize>) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository (no .ctx directory found)")?;
+
+    let history = repo
+        .path_history(path)
+        .with_context(|| format!("Failed to read history of {}", path))?;
+
+    if history.is_empty() {
+        println!("No commits changed {}", path);
+        return Ok(());
+    }
+
+    for entry in history.iter().take(limit.unwrap_or(usize::MAX)) {
+        let timestamp =
+            DateTime::from_timestamp(entry.timestamp_unix as i64, 0).unwrap_or_default();
+        let change = match (&entry.renamed_from, entry.previous_blob_id) {
+            (Some(_), _) => "renamed",
+            (None, Some(_)) => "modified",
+            (None, None) => "added",
+        };
+
+        println!(
+            "{} {} {:<8} {}",
+            &entry.commit_id.as_hex()[..8],
+            timestamp.format("%Y-%m-%d %H:%M:%S"),
+            change,
+            entry.message
+        );
+        if let Some(from) = &entry.renamed_from {
+            println!("         from {}", from);
+        }
+        if let Some(author) = &entry.author {
+            println!("         by {}", author);
+        }
+        for annotation in repo.annotations(entry.commit_id)? {
+            println!("         note: {}", annotation.text);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a `--since` value into a Unix timestamp.
+pub fn parse_since(value: &str) -> Result<u64> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        return Ok(midnight.and_utc().timestamp().max(0) as u64);
+    }
+
+    let (amount, unit) = value.split_at(value.len().saturating_sub(1));
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("Invalid time: {}", value))?;
+    let secs = match unit {
+        "h" => amount * 60 * 60,
+        "d" => amount * 24 * 60 * 60,
+        "w" => amount * 7 * 24 * 60 * 60,
+        _ => anyhow::bail!(
+            "Invalid time: {} (use YYYY-MM-DD or e.g. 12h, 3d, 2w)",
+            value
+        ),
+    };
+
+    Ok((chrono::Utc::now().timestamp().max(0) as u64).saturating_sub(secs))
+}
+
 /// Look up a file path in the index.
 pub fn index_path(path: &str) -> Result<()> {
-    let mut repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
 
     let index = repo.index().context("Failed to load index")?;
 
@@ -331,7 +446,7 @@ pub fn index_path(path: &str) -> Result<()> {
 
 /// Look up entities by name in the index.
 pub fn index_name(namespace: &str, name: &str) -> Result<()> {
-    let mut repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
 
     let ns = parse_name_namespace(namespace)?;
     let index = repo.index().context("Failed to load index")?;
@@ -352,9 +467,9 @@ pub fn index_name(namespace: &str, name: &str) -> Result<()> {
 
 /// Show edges for a node in the index.
 pub fn index_edges(kind: &str, id: &str, label: Option<&str>) -> Result<()> {
-    use ctx_core::{EdgeLabel, NodeId};
+    use ctx_core::NodeId;
 
-    let mut repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
 
     let node_kind = parse_node_kind(kind)?;
     let node = NodeId {
@@ -370,23 +485,7 @@ pub fn index_edges(kind: &str, id: &str, label: Option<&str>) -> Result<()> {
     let labels_to_check = if let Some(l) = label {
         vec![parse_edge_label(l)?]
     } else {
-        // All labels
-        vec![
-            EdgeLabel::Contains,
-            EdgeLabel::Defines,
-            EdgeLabel::HasVersion,
-            EdgeLabel::DependsOn,
-            EdgeLabel::TargetOf,
-            EdgeLabel::CrateFromTarget,
-            EdgeLabel::Imports,
-            EdgeLabel::References,
-            EdgeLabel::Calls,
-            EdgeLabel::Implements,
-            EdgeLabel::UsesType,
-            EdgeLabel::Mentions,
-            EdgeLabel::UpdatedIn,
-            EdgeLabel::DerivedFrom,
-        ]
+        ALL_EDGE_LABELS.to_vec()
     };
 
     println!("Outgoing edges:");
@@ -415,11 +514,130 @@ pub fn index_edges(kind: &str, id: &str, label: Option<&str>) -> Result<()> {
     Ok(())
 }
 
+/// Show the evidence behind edges from `from` to `to`.
+///
+/// Nodes are given as `Kind::id`, or as a bare path for files. Without
+/// `label`, every label connecting the two nodes is shown.
+pub fn edge(from: &str, to: &str, label: Option<&str>) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let from = parse_node_spec(from)?;
+    let to = parse_node_spec(to)?;
+    let labels = match label {
+        Some(l) => vec![parse_edge_label(l)?],
+        None => ALL_EDGE_LABELS.to_vec(),
+    };
+
+    let index = repo.index().context("Failed to load index")?;
+    let mut found = false;
+    for label in labels {
+        let evidence = index.get_edge_details(&from, &to, label)?;
+        if evidence.is_empty() {
+            continue;
+        }
+        found = true;
+
+        println!(
+            "{:?} \"{}\" -{:?}-> {:?} \"{}\"",
+            from.kind, from.id, label, to.kind, to.id
+        );
+        if let Some(merged) = index.get_merged_edge(&from, &to, label)? {
+            let last = DateTime::from_timestamp(merged.last_asserted_unix as i64, 0)
+                .map(|t| t.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| merged.last_asserted_unix.to_string());
+            println!(
+                "  merged confidence {:.2} from {} tool(s), last asserted {}",
+                merged.confidence(),
+                merged.sources.len(),
+                last
+            );
+        }
+        if let Some(blob_id) = index.get_superseded_by(&from, &to, label)? {
+            println!(
+                "  superseded by blob {}: the file changed since this was asserted",
+                &blob_id.as_hex()[..8]
+            );
+        }
+        for (i, record) in evidence.iter().enumerate() {
+            println!(
+                "  [{}] commit {}  tool {:?}  confidence {:?}",
+                i + 1,
+                &record.commit_id.as_hex()[..8],
+                record.tool,
+                record.confidence
+            );
+            if let Some(blob_id) = record.blob_id {
+                println!("      blob {}", &blob_id.as_hex()[..8]);
+            }
+            if let Some(span) = &record.span {
+                println!(
+                    "      span {}:{}-{}:{} (bytes {}..{})",
+                    span.start_line + 1,
+                    span.start_col + 1,
+                    span.end_line + 1,
+                    span.end_col + 1,
+                    span.start_byte,
+                    span.end_byte
+                );
+            }
+        }
+        println!();
+    }
+
+    if !found {
+        println!(
+            "No edges from {:?} \"{}\" to {:?} \"{}\"",
+            from.kind, from.id, to.kind, to.id
+        );
+    }
+
+    Ok(())
+}
+
+/// Show the shortest chains of edges connecting two nodes.
+pub fn path(from: &str, to: &str, labels: Option<&str>) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let from = parse_node_spec(from)?;
+    let to = parse_node_spec(to)?;
+    let labels = match labels {
+        Some(labels) => labels
+            .split(',')
+            .map(|label| parse_edge_label(label.trim()))
+            .collect::<Result<Vec<_>>>()?,
+        None => ALL_EDGE_LABELS.to_vec(),
+    };
+
+    let index = repo.index().context("Failed to load index")?;
+    let paths = ctx_core::shortest_paths(&index, &from, &to, &labels)?;
+    if paths.is_empty() {
+        println!(
+            "No path from {:?} \"{}\" to {:?} \"{}\"",
+            from.kind, from.id, to.kind, to.id
+        );
+        return Ok(());
+    }
+
+    for (i, path) in paths.iter().enumerate() {
+        println!("Path {} ({} hops):", i + 1, path.hops.len());
+        println!("  {:?} \"{}\"", path.start.kind, path.start.id);
+        for hop in &path.hops {
+            let arrow = if hop.reversed {
+                format!("<-{:?}-", hop.label)
+            } else {
+                format!("-{:?}->", hop.label)
+            };
+            println!("  {} {:?} \"{}\"", arrow, hop.node.kind, hop.node.id);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
 /// Show index statistics.
 pub fn index_stats() -> Result<()> {
     use ctx_core::INDEX_SCHEMA_VERSION;
 
-    let mut repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
 
     let index = repo.index().context("Failed to load index")?;
 
@@ -429,8 +647,48 @@ pub fn index_stats() -> Result<()> {
     Ok(())
 }
 
+/// Every edge label, for commands that check all of them.
+const ALL_EDGE_LABELS: [ctx_core::EdgeLabel; 17] = {
+    use ctx_core::EdgeLabel;
+    [
+        EdgeLabel::Contains,
+        EdgeLabel::Defines,
+        EdgeLabel::HasVersion,
+        EdgeLabel::DeclaresModule,
+        EdgeLabel::RenamedTo,
+        EdgeLabel::ChildOf,
+        EdgeLabel::DependsOn,
+        EdgeLabel::TargetOf,
+        EdgeLabel::CrateFromTarget,
+        EdgeLabel::Imports,
+        EdgeLabel::References,
+        EdgeLabel::Calls,
+        EdgeLabel::Implements,
+        EdgeLabel::UsesType,
+        EdgeLabel::Mentions,
+        EdgeLabel::UpdatedIn,
+        EdgeLabel::DerivedFrom,
+    ]
+};
+
+/// Parse a node given as `Kind::id`, or as a bare path for files.
+pub(crate) fn parse_node_spec(spec: &str) -> Result<ctx_core::NodeId> {
+    use ctx_core::{NodeId, NodeKind};
+
+    match spec.split_once("::") {
+        Some((kind, id)) if !kind.contains('/') => Ok(NodeId {
+            kind: parse_node_kind(kind)?,
+            id: id.to_string(),
+        }),
+        _ => Ok(NodeId {
+            kind: NodeKind::File,
+            id: spec.trim_start_matches("./").to_string(),
+        }),
+    }
+}
+
 /// Parse a node kind from a string.
-fn parse_node_kind(s: &str) -> Result<ctx_core::NodeKind> {
+pub(crate) fn parse_node_kind(s: &str) -> Result<ctx_core::NodeKind> {
     use ctx_core::NodeKind;
 
     match s.to_lowercase().as_str() {
@@ -444,18 +702,26 @@ fn parse_node_kind(s: &str) -> Result<ctx_core::NodeKind> {
         "note" => Ok(NodeKind::Note),
         "decision" => Ok(NodeKind::Decision),
         "diagnostic" => Ok(NodeKind::Diagnostic),
-        _ => anyhow::bail!("Unknown node kind: {}. Valid kinds: file, module, item, package, target, crate, task, note, decision, diagnostic", s),
+        "term" => Ok(NodeKind::Term),
+        "fact" => Ok(NodeKind::Fact),
+        "qa" => Ok(NodeKind::Qa),
+        "document" => Ok(NodeKind::Document),
+        "session" => Ok(NodeKind::Session),
+        _ => anyhow::bail!("Unknown node kind: {}. Valid kinds: file, module, item, package, target, crate, task, note, decision, diagnostic, term, fact, qa, document, session", s),
     }
 }
 
 /// Parse an edge label from a string.
-fn parse_edge_label(s: &str) -> Result<ctx_core::EdgeLabel> {
+pub(crate) fn parse_edge_label(s: &str) -> Result<ctx_core::EdgeLabel> {
     use ctx_core::EdgeLabel;
 
     match s.to_lowercase().as_str() {
         "contains" => Ok(EdgeLabel::Contains),
         "defines" => Ok(EdgeLabel::Defines),
         "hasversion" => Ok(EdgeLabel::HasVersion),
+        "declaresmodule" => Ok(EdgeLabel::DeclaresModule),
+        "renamedto" => Ok(EdgeLabel::RenamedTo),
+        "childof" => Ok(EdgeLabel::ChildOf),
         "dependson" => Ok(EdgeLabel::DependsOn),
         "targetof" => Ok(EdgeLabel::TargetOf),
         "cratefromtarget" => Ok(EdgeLabel::CrateFromTarget),
@@ -467,7 +733,7 @@ fn parse_edge_label(s: &str) -> Result<ctx_core::EdgeLabel> {
         "mentions" => Ok(EdgeLabel::Mentions),
         "updatedin" => Ok(EdgeLabel::UpdatedIn),
         "derivedfrom" => Ok(EdgeLabel::DerivedFrom),
-        _ => anyhow::bail!("Unknown edge label: {}. Valid labels: contains, defines, hasversion, dependson, targetof, cratefromtarget, imports, references, calls, implements, usestype, mentions, updatedin, derivedfrom", s),
+        _ => anyhow::bail!("Unknown edge label: {}. Valid labels: contains, defines, hasversion, declaresmodule, renamedto, childof, dependson, targetof, cratefromtarget, imports, references, calls, implements, usestype, mentions, updatedin, derivedfrom", s),
     }
 }
 
@@ -492,7 +758,7 @@ fn parse_name_namespace(s: &str) -> Result<ctx_core::NameNamespace> {
 pub fn graph(format: &str, _labels: Option<&str>, _max_nodes: usize) -> Result<()> {
     use ctx_core::{adjacency_to_dot, AdjacencyList};
 
-    let mut repo = CtxRepo::open(".")?;
+    let repo = CtxRepo::open(".")?;
 
     // Get edge batch IDs from HEAD commit
     // Note: CommitInfo stores ObjectIds that reference EdgeBatch objects
@@ -534,7 +800,7 @@ pub fn graph(format: &str, _labels: Option<&str>, _max_nodes: usize) -> Result<(
 pub fn scc(show_members: bool) -> Result<()> {
     use ctx_core::{compute_scc, AdjacencyList};
 
-    let mut repo = CtxRepo::open(".")?;
+    let repo = CtxRepo::open(".")?;
 
     // Get edge batch IDs from HEAD commit
     let edge_batch_ids = {
@@ -585,6 +851,101 @@ pub fn scc(show_members: bool) -> Result<()> {
     Ok(())
 }
 
+/// Show how the graph changed between two commits.
+pub fn graph_diff(from: &str, to: &str) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let from_id = repo
+        .resolve_commit(from)
+        .with_context(|| format!("Unknown commit '{}'", from))?;
+    let to_id = repo
+        .resolve_commit(to)
+        .with_context(|| format!("Unknown commit '{}'", to))?;
+
+    let diff = repo
+        .graph_diff(from_id, to_id)
+        .context("Failed to compare graphs")?;
+
+    println!(
+        "graph diff {}..{}",
+        &from_id.as_hex()[..8],
+        &to_id.as_hex()[..8]
+    );
+    if diff.is_empty() {
+        println!("\nNo differences.");
+        return Ok(());
+    }
+
+    for group in &diff.groups {
+        println!(
+            "\n{:?} from {:?} (+{} -{}):",
+            group.label,
+            group.kind,
+            group.added.len(),
+            group.removed.len()
+        );
+        for (sign, edge) in group
+            .added
+            .iter()
+            .map(|edge| ('+', edge))
+            .chain(group.removed.iter().map(|edge| ('-', edge)))
+        {
+            println!(
+                "  {} \"{}\" -> {:?} \"{}\"",
+                sign, edge.from.id, edge.to.kind, edge.to.id
+            );
+        }
+    }
+
+    if !diff.added_nodes.is_empty() || !diff.removed_nodes.is_empty() {
+        println!(
+            "\nNodes (+{} -{}):",
+            diff.added_nodes.len(),
+            diff.removed_nodes.len()
+        );
+        for (sign, node) in diff
+            .added_nodes
+            .iter()
+            .map(|node| ('+', node))
+            .chain(diff.removed_nodes.iter().map(|node| ('-', node)))
+        {
+            println!("  {} {:?} \"{}\"", sign, node.kind, node.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// List items nothing in the graph uses.
+pub fn orphans() -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository")?;
+    let orphans = repo.orphan_items()?;
+
+    if orphans.is_empty() {
+        println!("No unused items found");
+        return Ok(());
+    }
+    println!(
+        "Items with no incoming calls or references: {}",
+        orphans.len()
+    );
+    println!();
+    for orphan in &orphans {
+        let location = match orphan.line {
+            Some(line) => format!("{}:{}", orphan.file, line + 1),
+            None => orphan.file.clone(),
+        };
+        println!(
+            "  {:<40} {}  (confidence {:?})",
+            orphan.item.id, location, orphan.confidence
+        );
+    }
+    println!();
+    println!("Public items and tests are not listed. Calls the analysis couldn't");
+    println!("resolve, such as through macros or trait objects, also count as none.");
+
+    Ok(())
+}
+
 /// Show parsed Cargo workspace structure.
 pub fn cargo_show() -> Result<()> {
     use ctx_core::CargoMetadataSnapshot;
@@ -596,11 +957,16 @@ pub fn cargo_show() -> Result<()> {
         Some(snapshot_id) => {
             let snapshot: CargoMetadataSnapshot = repo.object_store().get_typed(snapshot_id)?;
 
+            let members: Vec<_> = snapshot
+                .packages
+                .iter()
+                .filter(|pkg| snapshot.is_workspace_member(pkg))
+                .collect();
             println!("Workspace root: {}", snapshot.workspace_root);
-            println!("Packages ({}):", snapshot.packages.len());
+            println!("Packages ({}):", members.len());
             println!();
 
-            for pkg in &snapshot.packages {
+            for pkg in members {
                 println!("  {} v{}", pkg.name, pkg.version);
                 println!("    Edition: {}", pkg.edition);
                 println!("    Manifest: {}", pkg.manifest_path);
@@ -700,3 +1066,161 @@ pub fn cargo_deps(package_name: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Show the dependency chains that pull a package into the graph.
+pub fn cargo_why(package_name: &str) -> Result<()> {
+    use ctx_core::CargoMetadataSnapshot;
+
+    let repo = ctx_core::CtxRepo::open(".")?;
+    let Some(snapshot_id) = repo.head()?.cargo_snapshot else {
+        println!("No Cargo snapshot in HEAD. Run `ctx analyze cargo --full` first.");
+        return Ok(());
+    };
+    let snapshot: CargoMetadataSnapshot = repo.object_store().get_typed(snapshot_id)?;
+    if snapshot.resolve.is_none() {
+        println!("The Cargo snapshot has no resolved dependencies. Run `ctx analyze cargo --full` first.");
+        return Ok(());
+    }
+
+    let chains = snapshot.why(package_name);
+    if chains.is_empty() {
+        println!("Nothing in the workspace depends on '{}'.", package_name);
+        return Ok(());
+    }
+    for chain in &chains {
+        println!("{}", chain.packages.join(" -> "));
+        if chain.features.is_empty() {
+            println!("  Features: (none)");
+        } else {
+            println!("  Features: {}", chain.features.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Print journal events, optionally waiting for new ones.
+///
+/// With `json`, prints one JSON object per line, including its cursor.
+pub fn events(since: u64, follow: bool, json: bool) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository (no .ctx directory found)")?;
+
+    let mut cursor = since;
+    loop {
+        let page = repo.events_since(cursor)?;
+        for event in &page.events {
+            if json {
+                let mut value = serde_json::to_value(event)?;
+                value["cursor"] = event.cursor.into();
+                println!("{}", value);
+            } else {
+                print_event(event);
+            }
+        }
+        cursor = page.next_cursor;
+
+        if !follow {
+            if !json && page.events.is_empty() {
+                println!("No events.");
+            }
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+}
+
+/// List the most recently recorded prompt packs.
+pub fn packs(limit: usize, json: bool) -> Result<()> {
+    let repo = CtxRepo::open(".").context("Not a CTX repository (no .ctx directory found)")?;
+    let packs = repo.recent_packs(limit)?;
+
+    if json {
+        return crate::output::print_json(&packs);
+    }
+    if packs.is_empty() {
+        println!("No packs recorded.");
+        return Ok(());
+    }
+    for pack in &packs {
+        let timestamp = DateTime::from_timestamp(pack.timestamp_unix as i64, 0).unwrap_or_default();
+        println!(
+            "{}  {}  {:>3} chunks  {:>6} tokens  {}",
+            &pack.pack_id.as_hex()[..12],
+            timestamp.format("%Y-%m-%d %H:%M:%S"),
+            pack.chunks,
+            pack.tokens,
+            pack.query
+        );
+    }
+    println!("\nReplay one with: ctx query --replay <pack-id> (full IDs with --json)");
+    Ok(())
+}
+
+/// Print one event as a single line.
+fn print_event(event: &Event) {
+    let timestamp = DateTime::from_timestamp(event.timestamp_unix as i64, 0).unwrap_or_default();
+    let short = |id: &str| id.chars().take(12).collect::<String>();
+    let (label, detail) = match &event.kind {
+        EventKind::SessionStarted { session_id, task } => {
+            ("session started", format!("{} \"{}\"", session_id, task))
+        }
+        EventKind::SessionFlushed {
+            session_id,
+            steps,
+            staging_head,
+        } => (
+            "session flushed",
+            format!("{} step {} -> {}", session_id, steps, short(staging_head)),
+        ),
+        EventKind::SessionCompacted {
+            session_id,
+            commit_id,
+            commit_type,
+        } => (
+            "session compacted",
+            format!("{} -> {} ({:?})", session_id, short(commit_id), commit_type),
+        ),
+        EventKind::Committed { commit_id, message } => (
+            "committed",
+            format!(
+                "{} {}",
+                short(commit_id),
+                message.lines().next().unwrap_or("")
+            ),
+        ),
+        EventKind::Analyzed {
+            analysis,
+            commit_id,
+        } => ("analyzed", format!("{} -> {}", analysis, short(commit_id))),
+        EventKind::PackBuilt {
+            pack_id,
+            query,
+            chunks,
+            tokens,
+        } => (
+            "pack built",
+            format!(
+                "{} \"{}\" ({} chunks, {} tokens)",
+                short(pack_id),
+                query,
+                chunks,
+                tokens
+            ),
+        ),
+        EventKind::GcCompleted {
+            objects_deleted,
+            bytes_freed,
+            dry_run,
+        } => (
+            if *dry_run { "gc (dry run)" } else { "gc" },
+            format!("{} objects, {} bytes", objects_deleted, bytes_freed),
+        ),
+    };
+    println!(
+        "{:>8}  {}  {:<17}  {}",
+        event.cursor,
+        timestamp.format("%Y-%m-%d %H:%M:%S"),
+        label,
+        detail
+    );
+}
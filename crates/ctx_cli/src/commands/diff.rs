@@ -0,0 +1,27 @@
+//! Diff command - compare two commits.
+
+use anyhow::{Context, Result};
+
+/// Show files, edges and narrative that changed between two commits.
+pub fn run(from: &str, to: &str, format: &str) -> Result<()> {
+    let repo = super::open_repo(".")?;
+
+    let from_id = repo
+        .resolve_commit(from)
+        .with_context(|| format!("Unknown commit '{}'", from))?;
+    let to_id = repo
+        .resolve_commit(to)
+        .with_context(|| format!("Unknown commit '{}'", to))?;
+
+    let diff = repo
+        .diff(from_id, to_id)
+        .context("Failed to compute diff")?;
+
+    match format {
+        "json" => println!("{}", diff.to_json().context("Failed to serialize to JSON")?),
+        "text" => print!("{}", diff.to_text()),
+        _ => anyhow::bail!("Unsupported format: {}. Use 'json' or 'text'.", format),
+    }
+
+    Ok(())
+}
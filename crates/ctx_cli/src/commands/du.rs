@@ -0,0 +1,114 @@
+//! Storage usage report.
+
+use anyhow::Result;
+use console::style;
+use ctx_core::UsageTotals;
+
+/// Show object store usage by category, with the largest blobs and paths.
+pub fn run(top: usize, json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let report = repo.storage_report(top)?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    println!(
+        "{:<14} {:>8} {:>12} {:>12} {:>12}",
+        style("Category").bold(),
+        style("Objects").bold(),
+        style("On disk").bold(),
+        style("Raw").bold(),
+        style("Unreachable").bold()
+    );
+    for usage in &report.categories {
+        let total = combined(&usage.referenced, &usage.unreachable);
+        println!(
+            "{:<14} {:>8} {:>12} {:>12} {:>12}",
+            usage.category.as_str(),
+            total.objects,
+            format_bytes(total.compressed_bytes),
+            format_bytes(total.uncompressed_bytes),
+            format_bytes(usage.unreachable.compressed_bytes)
+        );
+    }
+    println!(
+        "{:<14} {:>8} {:>12} {:>12} {:>12}",
+        style("total").bold(),
+        report.total.objects,
+        format_bytes(report.total.compressed_bytes),
+        format_bytes(report.total.uncompressed_bytes),
+        format_bytes(report.unreachable.compressed_bytes)
+    );
+
+    if report.unreachable.objects > 0 {
+        println!();
+        println!(
+            "{} {} unreachable objects ({}); run {} to reclaim them",
+            style("⚠").yellow(),
+            report.unreachable.objects,
+            format_bytes(report.unreachable.compressed_bytes),
+            style("ctx gc").cyan()
+        );
+    }
+
+    if !report.largest_blobs.is_empty() {
+        println!();
+        println!("{}", style("Largest blobs").bold());
+        for blob in &report.largest_blobs {
+            println!(
+                "  {:>10}  {}  {}{}",
+                format_bytes(blob.compressed_bytes),
+                style(&blob.id.as_hex()[..12]).yellow(),
+                blob.path.as_deref().unwrap_or("(no path)"),
+                if blob.referenced {
+                    ""
+                } else {
+                    " (unreachable)"
+                }
+            );
+        }
+    }
+
+    if !report.largest_paths.is_empty() {
+        println!();
+        println!("{}", style("Largest paths").bold());
+        for path in &report.largest_paths {
+            println!(
+                "  {:>10}  {} ({} version{})",
+                format_bytes(path.compressed_bytes),
+                path.path,
+                path.versions,
+                if path.versions == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    for error in &report.errors {
+        eprintln!("{} {}", style("×").red(), error);
+    }
+
+    Ok(())
+}
+
+fn combined(a: &UsageTotals, b: &UsageTotals) -> UsageTotals {
+    UsageTotals {
+        objects: a.objects + b.objects,
+        compressed_bytes: a.compressed_bytes + b.compressed_bytes,
+        uncompressed_bytes: a.uncompressed_bytes + b.uncompressed_bytes,
+    }
+}
+
+/// Formats a byte count with a binary unit.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
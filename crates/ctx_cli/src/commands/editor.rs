@@ -0,0 +1,228 @@
+//! Editor integration: record files opened and saved in an editor.
+//!
+//! `ctx editor listen` serves the line protocol described in `ctx_client`
+//! on `.ctx/editor.sock`. Each connection gets a thread that validates and
+//! normalizes events; the main loop debounces them and records each batch
+//! in the active session. The repository is only opened while a batch is
+//! recorded, so other `ctx` commands keep working while it listens.
+
+use anyhow::Result;
+use ctx_client::EventKind;
+use std::path::Path;
+
+/// Report a file event to a running listener.
+pub fn notify(event: &str, path: &Path, json: bool) -> Result<()> {
+    let kind = match event {
+        "opened" => EventKind::Opened,
+        "saved" => EventKind::Saved,
+        other => anyhow::bail!("Unknown event '{}' (expected opened or saved)", other),
+    };
+    send(kind, path)?;
+    if json {
+        return crate::output::print_json(&serde_json::json!({ "queued": true }));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn send(kind: EventKind, path: &Path) -> Result<()> {
+    use anyhow::Context;
+
+    let mut client = ctx_client::EditorClient::for_path(path)
+        .context("Failed to reach the editor listener (`ctx editor listen`)")?;
+    client.send(kind, path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_kind: EventKind, _path: &Path) -> Result<()> {
+    anyhow::bail!("Editor integration needs Unix domain sockets")
+}
+
+#[cfg(not(unix))]
+pub fn listen(_debounce_ms: u64, _json: bool) -> Result<()> {
+    anyhow::bail!("Editor integration needs Unix domain sockets")
+}
+
+#[cfg(unix)]
+pub use listener::listen;
+
+#[cfg(unix)]
+mod listener {
+    use anyhow::{Context, Result};
+    use console::style;
+    use ctx_client::{Debouncer, EditorEvent, EventKind, Reply};
+    use ctx_core::AgentIdentity;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tracing::debug;
+
+    /// Listen for editor events until interrupted.
+    pub fn listen(debounce_ms: u64, json: bool) -> Result<()> {
+        let (root, socket) = {
+            let repo = super::super::open_repo(".")?;
+            (
+                repo.root().to_path_buf(),
+                ctx_client::socket_path(&repo.ctx_dir()),
+            )
+        };
+
+        if socket.exists() {
+            if UnixStream::connect(&socket).is_ok() {
+                anyhow::bail!(
+                    "Another listener is already running on {}",
+                    socket.display()
+                );
+            }
+            // Left behind by a listener that didn't shut down cleanly
+            std::fs::remove_file(&socket)?;
+        }
+        let listener = UnixListener::bind(&socket)
+            .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+        if !json {
+            println!(
+                "{} Listening for editor events on {} (Ctrl-C to stop)",
+                style("✓").green(),
+                style(socket.display()).cyan()
+            );
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || accept(listener, root, tx));
+
+        let mut debouncer = Debouncer::new(Duration::from_millis(debounce_ms));
+        loop {
+            let received = match debouncer.next_due() {
+                Some(due) => rx.recv_timeout(due.saturating_duration_since(Instant::now())),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok((kind, path)) => debouncer.push(kind, path, Instant::now()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            let due = debouncer.due(Instant::now());
+            if !due.is_empty() {
+                record(&due, json);
+            }
+        }
+
+        record(&debouncer.drain(), json);
+        Ok(())
+    }
+
+    /// Accepts connections, serving each on its own thread.
+    fn accept(listener: UnixListener, root: PathBuf, tx: Sender<(EventKind, String)>) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let root = root.clone();
+                    let tx = tx.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve(stream, &root, &tx) {
+                            debug!(error = %e, "Editor connection failed");
+                        }
+                    });
+                }
+                Err(e) => debug!(error = %e, "Failed to accept editor connection"),
+            }
+        }
+    }
+
+    /// Reads events from one connection, replying to each line.
+    fn serve(stream: UnixStream, root: &Path, tx: &Sender<(EventKind, String)>) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<EditorEvent>(&line) {
+                Ok(event) => match ctx_client::normalize_path(root, &event.path) {
+                    Some(path) => {
+                        tx.send((event.event, path))?;
+                        Reply::ok()
+                    }
+                    None => Reply::error(format!("{} is outside the repository", event.path)),
+                },
+                Err(e) => Reply::error(format!("invalid event: {}", e)),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&reply)?)?;
+        }
+        Ok(())
+    }
+
+    /// Records a batch of events in the active session, reporting each one.
+    fn record(events: &[(EventKind, String)], json: bool) {
+        if events.is_empty() {
+            return;
+        }
+        match record_batch(events) {
+            Ok(true) => {
+                for (kind, path) in events {
+                    if json {
+                        println!("{}", json!({ "event": kind, "path": path }));
+                    } else {
+                        let label = match kind {
+                            EventKind::Opened => "opened",
+                            EventKind::Saved => "saved",
+                        };
+                        println!("  {} {}", style(label).cyan(), path);
+                    }
+                }
+            }
+            Ok(false) => eprintln!(
+                "{} Dropped {} event(s): no active session",
+                style("ℹ").blue(),
+                events.len()
+            ),
+            Err(e) => eprintln!(
+                "{} Failed to record {} event(s): {:#}",
+                style("⚠").yellow(),
+                events.len(),
+                e
+            ),
+        }
+    }
+
+    /// Observes and flushes `events`. Returns false if there is no session
+    /// to record into.
+    fn record_batch(events: &[(EventKind, String)]) -> Result<bool> {
+        let mut repo = super::super::open_repo(".")?.with_identity(AgentIdentity::from_env());
+        if !repo.has_active_session() && repo.recover_session()?.is_none() {
+            return Ok(false);
+        }
+
+        for (kind, path) in events {
+            if repo.ignore_rules().is_ignored(path) {
+                continue;
+            }
+            // Read the file now rather than when the event arrived, so a
+            // burst of saves records the final content
+            let content = std::fs::read(repo.root().join(path));
+            match (kind, content) {
+                (EventKind::Saved, Ok(content)) => {
+                    repo.observe_file_write(path, &content)?;
+                }
+                (EventKind::Opened, Ok(content)) => {
+                    repo.observe_file_read_with_content(path, &content)?;
+                }
+                // Deleted or unreadable since the event; keep the access
+                (_, Err(_)) => repo.observe_file_read(path)?,
+            }
+        }
+        if repo
+            .active_session()
+            .is_some_and(|session| session.has_pending_observations())
+        {
+            repo.flush_active_session()?;
+        }
+        Ok(true)
+    }
+}
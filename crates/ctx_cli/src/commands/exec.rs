@@ -0,0 +1,270 @@
+//! Run shell commands and record them in the active session.
+
+use anyhow::{Context, Result};
+use console::style;
+use ctx_core::{AgentIdentity, Config};
+use serde_json::json;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Most output kept per command; older output is dropped first, since the
+/// end of a build or test log is usually what matters.
+const MAX_OUTPUT_BYTES: usize = 256 * 1024;
+
+/// Run `command`, echo its output, and record it in the active session.
+///
+/// Exits with the command's exit code. Failing to record the command is
+/// reported on stderr but doesn't change the exit code.
+pub fn run(command: &[String], actor: Option<&str>, json: bool) -> Result<()> {
+    let (program, args) = command.split_first().context("No command given")?;
+    let command_line = join_command_line(command);
+
+    // Don't hold the repository open while the command runs, so it can
+    // use ctx itself
+    let policy = super::open_repo(".")?.exec_policy().clone();
+
+    let mut child = policy.spawn(
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?;
+
+    // In JSON mode the output goes into the report instead of the terminal
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let stdout = child.stdout.take().map(|pipe| {
+        let echo: Box<dyn Write + Send> = if json {
+            Box::new(io::sink())
+        } else {
+            Box::new(io::stdout())
+        };
+        tee(pipe, echo, Arc::clone(&captured))
+    });
+    let stderr = child.stderr.take().map(|pipe| {
+        let echo: Box<dyn Write + Send> = if json {
+            Box::new(io::sink())
+        } else {
+            Box::new(io::stderr())
+        };
+        tee(pipe, echo, Arc::clone(&captured))
+    });
+    let status = child.wait()?;
+    for reader in stdout.into_iter().chain(stderr) {
+        let _ = reader.join();
+    }
+
+    let output = captured.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let output = truncate_output(output);
+    let exit_code = status.code();
+    let recorded = record_in_session(&command_line, exit_code, Some(&output), actor);
+
+    if json {
+        crate::output::print_json(&json!({
+            "command": command_line,
+            "exit_code": exit_code,
+            "output": String::from_utf8_lossy(&output),
+            "session_id": recorded.as_ref().ok().cloned().flatten(),
+            "error": recorded.as_ref().err().map(|e| format!("{:#}", e)),
+        }))?;
+    } else {
+        match &recorded {
+            Ok(Some(_)) => {}
+            Ok(None) => eprintln!(
+                "{} Not recorded: no active session. Start one with {} or set {}.",
+                style("ℹ").blue(),
+                style("ctx stage start").cyan(),
+                style("session.exec_task").cyan()
+            ),
+            Err(e) => eprintln!("{} Failed to record command: {:#}", style("⚠").yellow(), e),
+        }
+    }
+
+    exit_with(status)
+}
+
+/// Record a command that already ran, without running it.
+///
+/// Used by the shell hooks, which only see the command line and exit code.
+pub fn record(command: &[String], exit_code: i32, actor: Option<&str>, json: bool) -> Result<()> {
+    let command_line = command.join(" ");
+    let session_id = record_in_session(&command_line, Some(exit_code), None, actor)?;
+    if json {
+        return crate::output::print_json(&json!({
+            "command": command_line,
+            "exit_code": exit_code,
+            "session_id": session_id,
+        }));
+    }
+    if session_id.is_none() {
+        println!("Not recorded: no active session");
+    }
+    Ok(())
+}
+
+/// Print a snippet that records every interactive command when sourced.
+pub fn shell_hook(shell: &str) -> Result<()> {
+    let snippet = match shell {
+        "bash" => BASH_HOOK,
+        "zsh" => ZSH_HOOK,
+        "fish" => FISH_HOOK,
+        other => anyhow::bail!(
+            "Unsupported shell '{}' (expected bash, zsh, or fish)",
+            other
+        ),
+    };
+    print!("{}", snippet);
+    Ok(())
+}
+
+/// Observes a command, attributed to `actor` if given, and flushes it to
+/// staging. Returns the session it was recorded in, or `None` if there is
+/// no session to record into.
+fn record_in_session(
+    command_line: &str,
+    exit_code: Option<i32>,
+    output: Option<&[u8]>,
+    actor: Option<&str>,
+) -> Result<Option<String>> {
+    let mut repo = super::open_repo(".")?.with_identity(AgentIdentity::from_env());
+    if !repo.has_active_session() && repo.recover_session()?.is_none() {
+        let config = Config::load(&repo.ctx_dir())?;
+        let Some(task) = config.session.exec_task else {
+            return Ok(None);
+        };
+        repo.start_session(&task)?;
+    }
+
+    match actor {
+        Some(actor) => repo.observe_as(actor, |repo| {
+            repo.observe_command(command_line, exit_code, output)
+        })?,
+        None => repo.observe_command(command_line, exit_code, output)?,
+    }
+    if repo
+        .active_session()
+        .is_some_and(|session| session.has_pending_observations())
+    {
+        repo.flush_active_session()?;
+    }
+    Ok(repo
+        .active_session()
+        .map(|session| session.session_id().to_string()))
+}
+
+/// Copies `pipe` to `echo` while appending it to `captured`.
+fn tee(
+    mut pipe: impl Read + Send + 'static,
+    mut echo: Box<dyn Write + Send>,
+    captured: Arc<Mutex<Vec<u8>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match pipe.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let _ = echo.write_all(&buf[..n]).and_then(|()| echo.flush());
+
+            let mut captured = captured.lock().unwrap_or_else(|e| e.into_inner());
+            captured.extend_from_slice(&buf[..n]);
+            // Trim in batches rather than on every read
+            if captured.len() > 2 * MAX_OUTPUT_BYTES {
+                let excess = captured.len() - MAX_OUTPUT_BYTES;
+                captured.drain(..excess);
+            }
+        }
+    })
+}
+
+/// Keeps the last [`MAX_OUTPUT_BYTES`] of `output`, marking the cut.
+fn truncate_output(mut output: Vec<u8>) -> Vec<u8> {
+    if output.len() <= MAX_OUTPUT_BYTES {
+        return output;
+    }
+    let excess = output.len() - MAX_OUTPUT_BYTES;
+    output.drain(..excess);
+    let mut truncated = b"[earlier output truncated]\n".to_vec();
+    truncated.extend_from_slice(&output);
+    truncated
+}
+
+/// Renders argv as a command line, quoting arguments a shell would split.
+fn join_command_line(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Exits with the same status as the command.
+fn exit_with(status: ExitStatus) -> Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            std::process::exit(128 + signal);
+        }
+    }
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+const BASH_HOOK: &str = r#"# Record interactive commands in the active CTX session.
+# Add to ~/.bashrc: eval "$(ctx exec --shell-hook bash)"
+__ctx_record() {
+    local status=$?
+    local cmd
+    cmd=$(HISTTIMEFORMAT= history 1 | sed 's/^ *[0-9]* *//')
+    if [ -n "$cmd" ] && [ "$cmd" != "$__ctx_last_cmd" ]; then
+        __ctx_last_cmd=$cmd
+        case "$cmd" in
+            ctx|ctx\ *) ;;
+            *) command ctx exec --no-run --exit-code "$status" -- "$cmd" >/dev/null 2>&1 ;;
+        esac
+    fi
+    return $status
+}
+PROMPT_COMMAND="__ctx_record${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#;
+
+const ZSH_HOOK: &str = r#"# Record interactive commands in the active CTX session.
+# Add to ~/.zshrc: eval "$(ctx exec --shell-hook zsh)"
+__ctx_preexec() { __ctx_cmd=$1 }
+__ctx_precmd() {
+    local exit_status=$?
+    if [[ -n $__ctx_cmd && $__ctx_cmd != ctx && $__ctx_cmd != "ctx "* ]]; then
+        command ctx exec --no-run --exit-code $exit_status -- "$__ctx_cmd" >/dev/null 2>&1
+    fi
+    unset __ctx_cmd
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __ctx_preexec
+add-zsh-hook precmd __ctx_precmd
+"#;
+
+const FISH_HOOK: &str = r#"# Record interactive commands in the active CTX session.
+# Add to ~/.config/fish/config.fish: ctx exec --shell-hook fish | source
+function __ctx_record --on-event fish_postexec
+    set -l exit_status $status
+    if test -n "$argv[1]"; and not string match -qr '^ctx( |$)' -- $argv[1]
+        command ctx exec --no-run --exit-code $exit_status -- $argv[1] >/dev/null 2>&1
+    end
+end
+"#;
@@ -0,0 +1,164 @@
+//! Export commands.
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use ctx_core::{export_corpus, export_dataset, CorpusConfig, DatasetConfig, Redactor};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Write the files of a commit's tree to a directory.
+pub fn tree(commit: Option<&str>, out: Option<&Path>, json: bool) -> Result<()> {
+    let (Some(commit), Some(out)) = (commit, out) else {
+        bail!("Usage: ctx export <commit> --out <dir>");
+    };
+
+    let repo = super::open_repo(".")?;
+    let commit_id = repo
+        .resolve_commit(commit)
+        .with_context(|| format!("Unknown commit '{}'", commit))?;
+
+    let written = repo
+        .export_tree(commit_id, out)
+        .with_context(|| format!("Failed to export to {}", out.display()))?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "commit_id": commit_id.as_hex(),
+            "out": out,
+            "files_written": written,
+        }));
+    }
+
+    println!(
+        "{} Exported {} files from {} to {}",
+        style("✓").green(),
+        written,
+        &commit_id.as_hex()[..8],
+        out.display()
+    );
+
+    Ok(())
+}
+
+/// Export session history as a JSONL dataset.
+pub fn dataset(
+    format: &str,
+    out: Option<&Path>,
+    redact: &[String],
+    no_default_redaction: bool,
+    json: bool,
+) -> Result<()> {
+    if format != "jsonl" {
+        bail!("Unsupported dataset format '{}' (expected jsonl)", format);
+    }
+    // The dataset itself goes to stdout without --out
+    if json && out.is_none() {
+        bail!("--json requires --out for dataset export");
+    }
+
+    let repo = super::open_repo(".")?;
+    let config = DatasetConfig {
+        redactor: redactor(redact, no_default_redaction)?,
+        ..Default::default()
+    };
+
+    let report = match out {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let mut writer = BufWriter::new(file);
+            let report = export_dataset(&repo, &config, &mut writer)?;
+            writer.flush()?;
+            report
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut writer = stdout.lock();
+            export_dataset(&repo, &config, &mut writer)?
+        }
+    };
+
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    eprintln!(
+        "{} Exported {} session and {} retrieval records from {} commits",
+        style("✓").green(),
+        report.session_records,
+        report.retrieval_records,
+        report.commits_scanned
+    );
+    if report.binary_files_skipped > 0 {
+        eprintln!("  Skipped {} non-UTF-8 files", report.binary_files_skipped);
+    }
+
+    Ok(())
+}
+
+/// Export the content at HEAD as a chunked JSONL corpus in `out`.
+pub fn corpus(
+    format: &str,
+    out: &Path,
+    max_chunk_lines: usize,
+    no_narrative: bool,
+    redact: &[String],
+    no_default_redaction: bool,
+    json: bool,
+) -> Result<()> {
+    if format != "jsonl" {
+        bail!("Unsupported corpus format '{}' (expected jsonl)", format);
+    }
+
+    let repo = super::open_repo(".")?;
+    let config = CorpusConfig {
+        redactor: redactor(redact, no_default_redaction)?,
+        max_chunk_lines,
+        include_narrative: !no_narrative,
+    };
+
+    std::fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let path = out.join("corpus.jsonl");
+    let file =
+        File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let report = export_corpus(&repo, &config, &mut writer)?;
+    writer.flush()?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "path": path,
+            "report": report,
+        }));
+    }
+
+    println!(
+        "{} Exported {} file and {} narrative records from {} files and {} narrative files to {}",
+        style("✓").green(),
+        report.file_records,
+        report.narrative_records,
+        report.files,
+        report.narrative_files,
+        path.display()
+    );
+    if report.binary_files_skipped > 0 {
+        println!("  Skipped {} non-UTF-8 files", report.binary_files_skipped);
+    }
+
+    Ok(())
+}
+
+/// The built-in secret patterns (unless disabled) plus `redact`.
+fn redactor(redact: &[String], no_default_redaction: bool) -> Result<Redactor> {
+    let mut redactor = if no_default_redaction {
+        Redactor::empty()
+    } else {
+        Redactor::default()
+    };
+    for pattern in redact {
+        redactor = redactor.with_pattern(pattern)?;
+    }
+    Ok(redactor)
+}
@@ -2,19 +2,38 @@
 
 use anyhow::Result;
 use console::style;
-use ctx_core::{CtxRepo, GcConfig};
-use indicatif::{ProgressBar, ProgressStyle};
+use ctx_core::{AutoGcReport, Config, GcConfig};
 
 /// Run garbage collection.
-pub fn run(dry_run: bool, aggressive: bool) -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+pub fn run(dry_run: bool, aggressive: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let repo_config = Config::load(&repo.ctx_dir())?;
 
     let config = GcConfig {
         dry_run,
         aggressive,
-        grace_period_days: 7,
+        grace_period_days: repo_config.gc.grace_period_days,
+        read_content_retention_days: repo_config.gc.read_content_retention_days,
+        reflog_expire_days: repo_config.gc.reflog_expire_days,
     };
 
+    if json {
+        // Keep stdout parseable: confirm on stderr, then print only the report
+        if !dry_run {
+            eprint!("Permanently delete unreferenced objects? [y/N]: ");
+            use std::io::{self, Write};
+            io::stderr().flush()?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            if !input.trim().eq_ignore_ascii_case("y") {
+                anyhow::bail!("Garbage collection cancelled");
+            }
+        }
+        let report = repo.gc(config)?;
+        return crate::output::print_json(&report);
+    }
+
     if dry_run {
         println!(
             "{} Running GC in dry-run mode (no objects will be deleted)...",
@@ -73,24 +92,10 @@ pub fn run(dry_run: bool, aggressive: bool) -> Result<()> {
         }
     }
 
-    // Create progress bar
-    let pb = ProgressBar::new(100);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} {msg:20} [{bar:40.cyan/blue}] {pos}/{len}")
-            .unwrap()
-            .progress_chars("█▓▒░  "),
-    );
-
-    // Clone pb for the closure
-    let pb_clone = pb.clone();
-    let report = repo.gc_with_progress(config, &move |current, total, phase| {
-        pb_clone.set_length(total as u64);
-        pb_clone.set_position(current as u64);
-        pb_clone.set_message(format!("Phase: {}", phase));
-    })?;
-
+    let (progress, pb) = crate::progress::progress_bar(json);
+    let report = repo.gc_with_progress(config, &progress);
     pb.finish_and_clear();
+    let report = report?;
 
     println!();
     println!("{}", style("Garbage Collection Report:").bold());
@@ -116,6 +121,20 @@ pub fn run(dry_run: bool, aggressive: bool) -> Result<()> {
         report.bytes_freed as f64 / 1_048_576.0
     );
 
+    if let Some(reason) = &report.retention.skipped {
+        println!(
+            "  {} Read-content retention skipped: {}",
+            style("⚠").yellow(),
+            reason
+        );
+    } else if report.retention.steps_rewritten > 0 {
+        println!(
+            "  Reads dropped:     {} (in {} staged steps)",
+            style(report.retention.reads_dropped).yellow(),
+            report.retention.steps_rewritten
+        );
+    }
+
     if !report.errors.is_empty() {
         println!();
         println!("{}", style("Errors encountered:").red().bold());
@@ -140,3 +159,40 @@ pub fn run(dry_run: bool, aggressive: bool) -> Result<()> {
 
     Ok(())
 }
+
+/// Run garbage collection only if a `gc.auto_*` threshold is crossed.
+pub fn run_auto(json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let report = repo.auto_gc()?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    match &report {
+        Some(report) => print_auto(report),
+        None => println!(
+            "{} No GC needed (or gc.auto_gc is off).",
+            style("✓").green()
+        ),
+    }
+    Ok(())
+}
+
+/// Print one line about an automatic GC run.
+pub fn print_auto(report: &AutoGcReport) {
+    match (&report.gc, &report.skipped) {
+        (Some(gc), _) => println!(
+            "{} Auto GC, {}: deleted {} objects, freed {:.2} MB",
+            style("→").cyan(),
+            report.reason,
+            gc.objects_deleted,
+            gc.bytes_freed as f64 / 1_048_576.0
+        ),
+        (None, skipped) => println!(
+            "{} Auto GC due, {}, but skipped: {}",
+            style("⚠").yellow(),
+            report.reason,
+            skipped.as_deref().unwrap_or("unknown reason")
+        ),
+    }
+}
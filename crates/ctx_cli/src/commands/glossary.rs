@@ -0,0 +1,130 @@
+//! Glossary commands for defining project terms.
+
+use anyhow::{Context, Result};
+use ctx_core::{AgentIdentity, CtxRepo, GlossarySource};
+use serde_json::json;
+
+fn open_repo() -> Result<CtxRepo> {
+    Ok(super::open_repo(".")
+        .context("Not a CTX repository")?
+        .with_identity(AgentIdentity::from_env()))
+}
+
+/// Define a term.
+pub fn add(term: &str, definition: &str, json: bool) -> Result<()> {
+    let mut repo = open_repo()?;
+
+    let commit_id = repo
+        .add_glossary_term(term, definition)
+        .context("Failed to add glossary term")?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "term": term.trim(),
+            "commit_id": commit_id.as_hex(),
+        }));
+    }
+
+    println!(
+        "Defined '{}' in commit {}",
+        term.trim(),
+        &commit_id.as_hex()[..8]
+    );
+    Ok(())
+}
+
+/// List defined terms.
+pub fn list(json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    let glossary = repo.glossary()?;
+
+    if json {
+        return crate::output::print_json(&glossary.entries);
+    }
+
+    if glossary.is_empty() {
+        println!("No glossary terms defined.");
+        return Ok(());
+    }
+
+    for entry in &glossary.entries {
+        let marker = match entry.source {
+            GlossarySource::Manual => "",
+            GlossarySource::Extracted => " (extracted)",
+        };
+        println!("{}{}", entry.term, marker);
+        println!("    {}", entry.definition);
+    }
+    Ok(())
+}
+
+/// Remove a term.
+pub fn remove(term: &str, json: bool) -> Result<()> {
+    let mut repo = open_repo()?;
+
+    let commit_id = repo.remove_glossary_term(term)?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "term": term,
+            "commit_id": commit_id.as_hex(),
+        }));
+    }
+
+    println!("Removed '{}' in commit {}", term, &commit_id.as_hex()[..8]);
+    Ok(())
+}
+
+/// Suggest recurring narrative terms, optionally adding them all.
+pub fn suggest(min_occurrences: usize, accept: bool, json: bool) -> Result<()> {
+    let mut repo = open_repo()?;
+
+    let candidates = repo
+        .suggest_glossary_terms(min_occurrences)
+        .context("Failed to scan narrative")?;
+
+    if json {
+        let mut commit_id = None;
+        if accept && !candidates.is_empty() {
+            let now = chrono::Utc::now().timestamp().max(0) as u64;
+            let entries = candidates
+                .iter()
+                .cloned()
+                .map(|c| c.into_entry(now))
+                .collect();
+            commit_id = Some(repo.add_glossary_entries(entries)?.as_hex());
+        }
+        return crate::output::print_json(&json!({
+            "candidates": candidates,
+            "commit_id": commit_id,
+        }));
+    }
+
+    if candidates.is_empty() {
+        println!("No new terms found.");
+        return Ok(());
+    }
+
+    for candidate in &candidates {
+        println!(
+            "{} ({} occurrences in {} files)",
+            candidate.term,
+            candidate.occurrences,
+            candidate.files.len()
+        );
+        println!("    {}", candidate.context);
+    }
+
+    if accept {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let count = candidates.len();
+        let entries = candidates.into_iter().map(|c| c.into_entry(now)).collect();
+        let commit_id = repo.add_glossary_entries(entries)?;
+        println!(
+            "\nAdded {} terms in commit {}",
+            count,
+            &commit_id.as_hex()[..8]
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Repository health command.
+
+use anyhow::Result;
+use console::style;
+use ctx_core::{HealthLevel, IndexFreshness};
+
+/// Rate the repository's health against the configured thresholds.
+pub fn run(json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let health = repo.health()?;
+    if json {
+        return crate::output::print_json(&health);
+    }
+
+    println!(
+        "{} {}",
+        style("Health:").bold(),
+        styled(health.level, &health.level.to_string().to_uppercase())
+    );
+    println!();
+
+    let index = match &health.index.freshness {
+        IndexFreshness::Current => "up to date with HEAD".to_string(),
+        IndexFreshness::Missing => "not built yet (run 'ctx rebuild')".to_string(),
+        IndexFreshness::Behind { commits } => format!(
+            "{} commit{} behind HEAD (run 'ctx rebuild')",
+            commits,
+            if *commits == 1 { "" } else { "s" }
+        ),
+        IndexFreshness::Unreadable { reason } => {
+            format!("unreadable: {} (run 'ctx rebuild')", reason)
+        }
+    };
+    line(health.index.level, "Index", &index);
+
+    let stage = match (&health.stage.problem, health.stage.stage) {
+        (Some(problem), _) => problem.clone(),
+        (None, Some(_)) => "session in progress".to_string(),
+        (None, None) => "no session in progress".to_string(),
+    };
+    line(health.stage.level, "Stage", &stage);
+
+    let objects = &health.objects;
+    line(
+        objects.level,
+        "Objects",
+        &format!(
+            "{} corrupt of {} sampled ({} total){}",
+            objects.corrupted.len(),
+            objects.sampled,
+            objects.total,
+            if objects.corrupted.is_empty() {
+                ""
+            } else {
+                " (run 'ctx verify --full')"
+            }
+        ),
+    );
+    for id in &objects.corrupted {
+        println!("      {} {}", style("×").red(), id.as_hex());
+    }
+
+    let sessions = &health.sessions;
+    line(
+        sessions.level,
+        "Sessions",
+        &format!(
+            "{} of the last {} abandoned ({:.0}%)",
+            sessions.abandoned,
+            sessions.sessions,
+            sessions.abandoned_ratio * 100.0
+        ),
+    );
+
+    let growth = &health.growth;
+    line(
+        growth.level,
+        "Growth",
+        &format!(
+            "{:.2} MB/day over the last {} days",
+            growth.bytes_per_day as f64 / 1_000_000.0,
+            growth.window_days
+        ),
+    );
+
+    Ok(())
+}
+
+/// Prints one rated signal.
+fn line(level: HealthLevel, name: &str, detail: &str) {
+    let mark = match level {
+        HealthLevel::Green => "✓",
+        HealthLevel::Yellow => "⚠",
+        HealthLevel::Red => "×",
+    };
+    println!("  {} {:<9} {}", styled(level, mark), name, detail);
+}
+
+/// `text` in the color of `level`.
+fn styled(level: HealthLevel, text: &str) -> console::StyledObject<String> {
+    let text = style(text.to_string());
+    match level {
+        HealthLevel::Green => text.green(),
+        HealthLevel::Yellow => text.yellow(),
+        HealthLevel::Red => text.red(),
+    }
+}
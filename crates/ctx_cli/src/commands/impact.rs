@@ -0,0 +1,61 @@
+//! Impact command implementation.
+
+use anyhow::{Context, Result};
+use ctx_core::ImpactConfig;
+
+use super::debug::parse_edge_label;
+
+/// Show what a change to an item or file could affect.
+pub fn run(
+    target: &str,
+    depth: u32,
+    labels: Option<&str>,
+    max_nodes: usize,
+    format: &str,
+) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let mut config = ImpactConfig {
+        max_depth: depth,
+        max_nodes,
+        ..Default::default()
+    };
+    if let Some(labels) = labels {
+        config.labels = labels
+            .split(',')
+            .map(|label| parse_edge_label(label.trim()))
+            .collect::<Result<_>>()?;
+    }
+
+    let report = repo
+        .impact(target, &config)
+        .with_context(|| format!("Failed to analyze impact of '{}'", target))?;
+
+    match format {
+        "json" => crate::output::print_json(&report)?,
+        "text" => {
+            println!("Impact of {}", report.target);
+            for (title, entries) in [
+                ("Items", &report.items),
+                ("Files", &report.files),
+                ("Packages", &report.packages),
+                ("Tests", &report.tests),
+            ] {
+                println!();
+                println!("{} ({}):", title, entries.len());
+                for entry in entries {
+                    println!("  {}", entry);
+                }
+            }
+            if report.truncated {
+                println!();
+                println!(
+                    "Stopped after {} nodes; raise --max-nodes to see more.",
+                    max_nodes
+                );
+            }
+        }
+        _ => anyhow::bail!("Unsupported format: {}. Use 'json' or 'text'.", format),
+    }
+
+    Ok(())
+}
@@ -2,15 +2,25 @@
 
 use anyhow::{Context, Result};
 use ctx_core::CtxRepo;
+use serde_json::json;
 
 /// Initialize a new CTX repository in the current directory.
-pub fn run() -> Result<()> {
+pub fn run(json: bool) -> Result<()> {
     let repo = CtxRepo::init(".").context("Failed to initialize CTX repository")?;
 
     // Get initial commit info
     let head_id = repo.head_id()?;
     let head = repo.head()?;
 
+    if json {
+        return crate::output::print_json(&json!({
+            "ctx_dir": repo.ctx_dir(),
+            "commit_id": head_id.as_hex(),
+            "message": head.message,
+            "timestamp_unix": head.timestamp_unix,
+        }));
+    }
+
     println!("Initialized CTX repository in .ctx/");
     println!();
     println!("Directory structure:");
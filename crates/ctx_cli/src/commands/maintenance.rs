@@ -0,0 +1,114 @@
+//! Unattended maintenance command, for cron jobs and systemd timers.
+
+use anyhow::{Context, Result};
+use console::style;
+use ctx_core::{Config, IndexFreshness, Maintenance, MaintenanceConfig, StaleSessionStatus};
+
+/// Compact stale sessions, optionally collect garbage and verify a batch
+/// of objects, and catch the index up.
+pub fn run(gc: bool, verify: Option<usize>, dry_run: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".").context("Not a CTX repository")?;
+    let config = Config::load(&repo.ctx_dir())?;
+    let maintenance = Maintenance::new(MaintenanceConfig {
+        dry_run,
+        verify_objects: verify,
+        ..MaintenanceConfig::from_config(&config, gc)
+    });
+    let report = maintenance.run_once(&mut repo)?;
+
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    let would = if dry_run {
+        "Would compact"
+    } else {
+        "Compacted"
+    };
+    if let Some(commit_id) = &report.expired_session {
+        println!(
+            "  Session:  expired session of an exited process ended in {}",
+            style(&commit_id.as_hex()[..12]).cyan()
+        );
+    }
+    match &report.session {
+        StaleSessionStatus::NoSession => println!("  Session:  none"),
+        StaleSessionStatus::Fresh { task, idle_secs } => {
+            println!("  Session:  {} (idle {}s)", task, idle_secs)
+        }
+        StaleSessionStatus::ShouldAsk { task, idle_secs } => println!(
+            "  Session:  {} (idle {}s, {})",
+            task,
+            idle_secs,
+            style("stale; continue or compact it with ctx stage").yellow()
+        ),
+        StaleSessionStatus::ShouldAutoCompact { .. } => {
+            for task in &report.cleanup.compacted_tasks {
+                println!(
+                    "  Session:  {} stale session: {}",
+                    would,
+                    style(task).cyan()
+                );
+            }
+            if let Some(reason) = &report.session_skipped {
+                println!(
+                    "  Session:  {} stale session left alone: {}",
+                    style("⚠").yellow(),
+                    reason
+                );
+            }
+        }
+    }
+
+    if let Some(gc) = &report.gc {
+        println!(
+            "  GC:       {} objects {}, {:.2} MB",
+            gc.objects_deleted,
+            if dry_run { "collectable" } else { "deleted" },
+            gc.bytes_freed as f64 / 1_048_576.0
+        );
+        for error in &gc.errors {
+            println!("            {} {}", style("×").red(), error);
+        }
+    }
+
+    if let Some(verify) = &report.verify {
+        let position = if verify.next_cursor.is_some() {
+            "continuing next run"
+        } else {
+            "reached the end of the store"
+        };
+        println!(
+            "  Verify:   {} objects checked, {} corrupted ({})",
+            verify.objects_checked,
+            verify.objects_corrupted.len(),
+            position
+        );
+        for id in &verify.objects_corrupted {
+            println!("            {} {}", style("×").red(), id.as_hex());
+        }
+    }
+
+    let index = match &report.index {
+        IndexFreshness::Current => "current".to_string(),
+        IndexFreshness::Missing => "missing".to_string(),
+        IndexFreshness::Behind { commits } => format!("{} commits behind", commits),
+        IndexFreshness::Unreadable { reason } => format!("unreadable ({})", reason),
+    };
+    if report.index_rebuilt {
+        println!("  Index:    {}, rebuilt", index);
+    } else {
+        println!("  Index:    {}", index);
+    }
+
+    println!(
+        "{} Maintenance {}",
+        style("✓").green(),
+        if dry_run {
+            "dry run complete"
+        } else {
+            "complete"
+        }
+    );
+    Ok(())
+}
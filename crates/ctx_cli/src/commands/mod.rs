@@ -1,12 +1,61 @@
 //! CLI commands.
 
+use ctx_core::CtxRepo;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
 pub mod add;
 pub mod analyze;
+pub mod audit;
+pub mod bench;
+pub mod clone;
 pub mod commit;
+pub mod config;
 pub mod debug;
+pub mod diff;
+pub mod du;
+pub mod editor;
+pub mod exec;
+pub mod export;
 pub mod gc;
+pub mod glossary;
+pub mod health;
+pub mod impact;
 pub mod init;
+pub mod maintenance;
+pub mod narrative;
+pub mod prune;
 pub mod query;
 pub mod rebuild;
+pub mod reset;
+pub mod restore;
+pub mod serve;
+pub mod serve_graph;
 pub mod stage;
+pub mod status;
+pub mod summarize;
+pub mod unlock;
 pub mod verify;
+
+/// Opens the repository at `path`, asking on the terminal before running
+/// a command when the exec policy is in confirm mode.
+///
+/// Every command that can run hooks, summarizers or analyzers opens the
+/// repository this way, so confirm mode works wherever commands run.
+pub fn open_repo(path: impl AsRef<Path>) -> ctx_core::Result<CtxRepo> {
+    Ok(CtxRepo::open(path)?.with_exec_prompt(confirm_command))
+}
+
+/// Asks whether `command` may run. Refuses when stdin is not a terminal.
+fn confirm_command(command: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("Run `{}`? [y/N] ", command);
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,92 @@
+//! Narrative commands for digesting and searching logs and sessions.
+
+use anyhow::{Context, Result};
+use console::style;
+use ctx_core::AgentIdentity;
+use serde_json::json;
+
+/// Write the weekly digest for the week containing `date` (default: today).
+pub fn digest(date: Option<&str>, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")
+        .context("Not a CTX repository")?
+        .with_identity(AgentIdentity::from_env());
+
+    let at = match date {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date: {} (expected YYYY-MM-DD)", date))?
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc()
+            .timestamp()
+            .max(0) as u64,
+        None => chrono::Utc::now().timestamp().max(0) as u64,
+    };
+
+    let digest = repo
+        .write_weekly_digest(at)
+        .context("Failed to write digest")?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "relative_path": digest.relative_path(),
+            "week_start": digest.week_start,
+            "week_end": digest.week_end,
+            "sessions": digest.sessions.len(),
+            "completed_tasks": digest.completed_tasks.len(),
+            "log_entries": digest.log_entries.len(),
+        }));
+    }
+
+    println!(
+        "Wrote {} ({} to {})",
+        digest.relative_path(),
+        digest.week_start,
+        digest.week_end
+    );
+    println!(
+        "  {} session{}, {} completed task{}, {} log entr{}",
+        digest.sessions.len(),
+        if digest.sessions.len() == 1 { "" } else { "s" },
+        digest.completed_tasks.len(),
+        if digest.completed_tasks.len() == 1 {
+            ""
+        } else {
+            "s"
+        },
+        digest.log_entries.len(),
+        if digest.log_entries.len() == 1 {
+            "y"
+        } else {
+            "ies"
+        }
+    );
+    Ok(())
+}
+
+/// Search the narrative and print the matching sections.
+pub fn search(query: &str, limit: Option<usize>, json: bool) -> Result<()> {
+    let repo = super::open_repo(".").context("Not a CTX repository")?;
+    let hits = repo.search_narrative(query, limit)?;
+
+    if json {
+        return crate::output::print_json(&hits);
+    }
+    if hits.is_empty() {
+        println!("No narrative matches '{}'", query);
+        return Ok(());
+    }
+    for hit in &hits {
+        let mut location = hit.path.clone();
+        if let Some(date) = &hit.date {
+            location.push_str(&format!(" ({})", date));
+        }
+        match &hit.heading {
+            Some(heading) if hit.date.as_deref().map_or(true, |d| !d.ends_with(heading)) => {
+                println!("{} {}", style(location).cyan(), style(heading).bold())
+            }
+            _ => println!("{}", style(location).cyan()),
+        }
+        println!("  {}", hit.excerpt);
+    }
+    Ok(())
+}
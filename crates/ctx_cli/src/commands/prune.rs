@@ -0,0 +1,74 @@
+//! History pruning command.
+
+use anyhow::Result;
+use console::style;
+
+use super::debug::parse_since;
+use super::du::format_bytes;
+
+/// Fold the commits made before `keep_since` into a snapshot commit.
+pub fn run(keep_since: &str, json: bool) -> Result<()> {
+    let keep_since = parse_since(keep_since)?;
+    let mut repo = super::open_repo(".")?;
+    let report = repo.prune_history(keep_since)?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    let Some(snapshot) = report.snapshot else {
+        println!(
+            "Nothing to prune: {} commit{} kept, none older to fold.",
+            report.kept,
+            if report.kept == 1 { "" } else { "s" }
+        );
+        return Ok(());
+    };
+    println!(
+        "{} Folded {} commits into snapshot {}",
+        style("✓").green(),
+        report.pruned,
+        &snapshot.as_hex()[..12]
+    );
+    println!(
+        "  Kept:        {} commit{}",
+        report.kept,
+        if report.kept == 1 { "" } else { "s" }
+    );
+    println!(
+        "  Reclaimable: {} objects ({}), deleted by the next 'ctx gc'",
+        report.objects,
+        format_bytes(report.bytes)
+    );
+    if !report.retained_refs.is_empty() {
+        println!(
+            "  {} Still referenced by {}, which keep their history",
+            style("⚠").yellow(),
+            report.retained_refs.join(", ")
+        );
+    }
+    if let Some(backup) = &report.backup {
+        println!(
+            "  Undo with {}",
+            style(format!("ctx prune-history --undo {}", backup)).cyan()
+        );
+    }
+    Ok(())
+}
+
+/// Put back the history a prune folded away.
+pub fn undo(backup: &str, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let report = repo.undo_prune(backup)?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    println!(
+        "{} Undid {}: {} objects restored, {} still present",
+        style("✓").green(),
+        report.backup,
+        report.objects_restored,
+        report.objects_present
+    );
+    Ok(())
+}
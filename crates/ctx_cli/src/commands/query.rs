@@ -1,27 +1,264 @@
 //! Query command - build prompt packs.
 
 use anyhow::{Context, Result};
-use ctx_core::{CtxRepo, RetrievalConfig};
+use ctx_core::{
+    AuthorFilter, CitedChunk, Config, CtxRepo, Feedback, LayerBudgets, ModelProfile, ObjectId,
+    PackCursor, PackSession, PackStreamItem, PromptPack, RepoScope, RetrievalConfig,
+};
+use serde_json::json;
+use std::io::{ErrorKind, Write};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+/// Token budget without `--budget` or `--model`.
+const DEFAULT_BUDGET: u32 = 16_000;
+
+/// Options for the query command, as parsed from the command line.
+pub struct QueryOptions {
+    /// The query or question.
+    pub query: String,
+    /// Token budget, overriding the model profile's.
+    pub budget: Option<u32>,
+    /// Graph expansion depth.
+    pub depth: u32,
+    /// Output format (json, text), overriding the model profile's.
+    pub format: Option<String>,
+    /// Model profile picking the budget, tokenizer and format.
+    pub model: Option<String>,
+    /// Exclude narrative content.
+    pub no_narrative: bool,
+    /// Narrative token sub-budget.
+    pub narrative_budget: u32,
+    /// Return a single page and print a continuation cursor.
+    pub paged: bool,
+    /// Cursor from a previous paged query.
+    pub cursor: Option<String>,
+    /// Author filter ("human", "agent", or an author name).
+    pub author: Option<String>,
+    /// Tags files must carry.
+    pub tag: Vec<String>,
+    /// Sub-agents that must have read or written files.
+    pub actor: Vec<String>,
+    /// Disable frecency ranking.
+    pub no_frecency: bool,
+    /// Disable feedback ranking.
+    pub no_feedback: bool,
+    /// Always rebuild the pack instead of reusing a cached one.
+    pub no_cache: bool,
+    /// Stream the pack as NDJSON instead of printing it whole.
+    pub stream: bool,
+    /// Record why each file was included or dropped.
+    pub explain: bool,
+    /// Pinned files or `Kind::id` nodes.
+    pub pin: Vec<String>,
+    /// Exclude patterns.
+    pub exclude: Vec<String>,
+    /// Expand strongly connected components as units.
+    pub scc: bool,
+    /// Merge packs from the whole workspace of nested repositories.
+    pub workspace: bool,
+    /// File holding the pack session for delta packs.
+    pub delta: Option<PathBuf>,
+    /// Lead with overview and module layers.
+    pub layered: bool,
+    /// Drill into the query as an anchor from a layered pack.
+    pub zoom: bool,
+    /// Return only recorded decisions.
+    pub decisions_only: bool,
+    /// Cargo features of the build the pack is for.
+    pub features: Option<Vec<String>>,
+    /// Recorded pack to print instead of building one.
+    pub replay: Option<String>,
+    /// Commit to build the pack as of, instead of HEAD.
+    pub at: Option<String>,
+}
 
 /// Run the query command to build a prompt pack.
-pub fn run(query: &str, budget: u32, depth: u32, format: &str, no_narrative: bool) -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+///
+/// With `paged` (or a `cursor`), only one page is built and the cursor for
+/// the next page is printed after the pack. Unpaged packs are cached unless
+/// `no_cache` is set or caching is disabled in the config. With `stream`,
+/// the pack is written as NDJSON while it is built and never cached. With
+/// `workspace`, the outermost repository and every nested one are queried
+/// and their packs merged; merged packs are not cached. With `delta`, files
+/// the conversation already received are referenced instead of repeated,
+/// and the pack session in that file is updated; delta packs are not
+/// cached. With `layered`, the pack leads with a workspace overview and
+/// module graph; with `zoom`, the query is one of their anchors and the
+/// pack drills into that file. Neither is cached. With `decisions_only`, the
+/// pack holds only the decisions recorded about the files the query reaches.
+///
+/// Every pack built (except streamed ones) is recorded and its ID printed,
+/// so `replay` can print it again later exactly as it was.
+///
+/// With `at`, the pack is built from the trees, edges and narrative as of
+/// that commit rather than HEAD; such packs are not cached.
+///
+/// With `model`, the named profile sets the token budget, how tokens are
+/// counted, and the output format; `budget` and `format` override it.
+pub fn run(opts: QueryOptions) -> Result<()> {
+    let profile = match &opts.model {
+        Some(name) => Some(model_profile(name)?),
+        None => None,
+    };
+    let format = opts
+        .format
+        .clone()
+        .or_else(|| {
+            let format = profile.as_ref()?.preferred_format()?;
+            Some(format.as_str().to_string())
+        })
+        .unwrap_or_else(|| "json".to_string());
+
+    if let Some(pack_id) = &opts.replay {
+        let repo = super::open_repo(".")?;
+        let pack_id = ObjectId::from_hex(pack_id).context("Invalid pack ID")?;
+        let pack = repo
+            .replay_pack(pack_id)
+            .context("Failed to load recorded pack")?;
+        return print_pack(&pack, &format);
+    }
+
+    let mut repo = if opts.workspace {
+        CtxRepo::open_with_scope(".", RepoScope::Workspace)?
+    } else {
+        super::open_repo(".")?
+    };
+    let repo_config = Config::load(&repo.ctx_dir()).context("Failed to load config")?;
 
     // Configure retrieval
-    let config = RetrievalConfig {
-        token_budget: budget,
-        expansion_depth: depth,
-        include_active_task: !no_narrative,
-        include_log: !no_narrative,
+    let mut config = RetrievalConfig {
+        expansion_depth: opts.depth,
+        include_active_task: !opts.no_narrative,
+        include_log: !opts.no_narrative,
+        narrative_budget: opts.narrative_budget,
+        author_filter: parse_author_filter(opts.author.as_deref()),
+        tags: opts.tag.clone(),
+        actors: opts.actor.clone(),
+        frecency_boost: !opts.no_frecency,
+        feedback_boost: !opts.no_feedback,
+        explain: opts.explain,
+        pinned: opts
+            .pin
+            .iter()
+            .map(|pin| super::debug::parse_node_spec(pin))
+            .collect::<Result<_>>()?,
+        exclude: opts.exclude,
+        use_scc: opts.scc,
+        edge_decay: repo_config.edge_decay,
+        decisions_only: opts.decisions_only,
+        active_features: opts.features.clone(),
         ..Default::default()
     };
+    match &profile {
+        Some(profile) => profile.apply(&mut config),
+        None => config.token_budget = DEFAULT_BUDGET,
+    }
+    if let Some(budget) = opts.budget {
+        config.token_budget = budget;
+    }
+
+    if opts.stream {
+        return stream(&mut repo, &opts.query, &config);
+    }
 
     // Build prompt pack
-    let pack = repo
-        .build_pack(query, &config)
+    let (pack, next_cursor) = if opts.workspace {
+        let pack = repo
+            .build_federated_pack(&opts.query, &config)
+            .context("Failed to build prompt pack")?;
+        (pack, None)
+    } else if let Some(path) = &opts.delta {
+        let mut session = match std::fs::read_to_string(path) {
+            Ok(token) => PackSession::from_token(&token).context("Failed to read pack session")?,
+            Err(e) if e.kind() == ErrorKind::NotFound => PackSession::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read pack session {}", path.display()))
+            }
+        };
+        let pack = session
+            .build(&repo, &opts.query, &config)
+            .context("Failed to build prompt pack")?;
+        std::fs::write(path, session.to_token()?)
+            .with_context(|| format!("Failed to write pack session {}", path.display()))?;
+        (pack, None)
+    } else if let Some(commit) = &opts.at {
+        let pack = repo
+            .at_commit(commit, |repo| {
+                if opts.layered {
+                    repo.build_layered_pack(&opts.query, &config, &LayerBudgets::default())
+                } else if opts.zoom {
+                    repo.build_zoom_pack(&opts.query, &config)
+                } else {
+                    repo.build_pack(&opts.query, &config)
+                }
+            })
+            .with_context(|| format!("Failed to build prompt pack at {}", commit))?;
+        (pack, None)
+    } else if opts.layered {
+        let pack = repo
+            .build_layered_pack(&opts.query, &config, &LayerBudgets::default())
+            .context("Failed to build prompt pack")?;
+        (pack, None)
+    } else if opts.zoom {
+        let pack = repo
+            .build_zoom_pack(&opts.query, &config)
+            .context("Failed to build prompt pack")?;
+        (pack, None)
+    } else if opts.paged || opts.cursor.is_some() {
+        let cursor = opts
+            .cursor
+            .as_deref()
+            .map(PackCursor::from_token)
+            .transpose()
+            .context("Failed to decode cursor")?;
+        let page = repo
+            .build_pack_paged(&opts.query, &config, cursor.as_ref())
+            .context("Failed to build prompt pack")?;
+        let next = page.next_cursor.map(|c| c.to_token()).transpose()?;
+        (page.pack, Some(next))
+    } else {
+        let cache = repo.pack_cache().context("Failed to load cache config")?;
+        let pack = if opts.no_cache || !cache.config().enabled {
+            let (progress, pb) = crate::progress::progress_bar(false);
+            let pack = repo.build_pack_with_progress(&opts.query, &config, &progress);
+            pb.finish_and_clear();
+            pack
+        } else {
+            repo.build_pack_cached(&opts.query, &config, &cache)
+        }
         .context("Failed to build prompt pack")?;
+        (pack, None)
+    };
+
+    print_pack(&pack, &format)?;
+
+    // Building the pack recorded it, for `--replay` and so
+    // `ctx resolve-citation` can map "[3]" back to its source
+    let pack_id = repo
+        .pack_id(&pack, &config)
+        .context("Failed to record pack")?;
+    eprintln!("Pack: {}", pack_id);
+
+    match next_cursor {
+        Some(Some(token)) => eprintln!("Next cursor: {}", token),
+        Some(None) => eprintln!("No more results."),
+        None => {}
+    }
+
+    Ok(())
+}
 
-    // Output in requested format
+/// The model profile named `name` in the repository's config.
+fn model_profile(name: &str) -> Result<ModelProfile> {
+    let repo = super::open_repo(".")?;
+    let config = Config::load(&repo.ctx_dir()).context("Failed to load config")?;
+    Ok(config.model_profile(name)?)
+}
+
+/// Print `pack` as `format` (json or text).
+fn print_pack(pack: &PromptPack, format: &str) -> Result<()> {
     match format {
         "json" => {
             let json = pack.to_json().context("Failed to serialize to JSON")?;
@@ -35,6 +272,86 @@ pub fn run(query: &str, budget: u32, depth: u32, format: &str, no_narrative: boo
             anyhow::bail!("Unsupported format: {}. Use 'json' or 'text'.", format);
         }
     }
+    Ok(())
+}
+
+/// Print the source of chunk `number` in the pack stored as `pack`.
+pub fn resolve_citation(number: usize, pack: &str, json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let pack_id = ObjectId::from_hex(pack).context("Invalid pack ID")?;
+    let chunk = repo
+        .resolve_citation(pack_id, number)
+        .with_context(|| format!("Failed to resolve citation [{}]", number))?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "number": number,
+            "title": chunk.title,
+            "kind": chunk.chunk_kind,
+            "path": chunk.path,
+            "object_id": chunk.citation.object_id.as_hex(),
+            "start_line": chunk.citation.start_line,
+            "end_line": chunk.citation.end_line,
+            "location": chunk.location(),
+        }));
+    }
+    println!("[{}] {}", number, chunk.location());
+    Ok(())
+}
+
+/// Record which chunks of a pack were useful and which weren't.
+pub fn feedback(pack: &str, useful: &[usize], useless: &[usize], json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let pack_id = ObjectId::from_hex(pack).context("Invalid pack ID")?;
+    let feedback_id = repo
+        .record_feedback(pack_id, useful, useless)
+        .context("Failed to record feedback")?;
+    let feedback: Feedback = repo.object_store().get_typed(feedback_id)?;
+
+    if json {
+        let locations =
+            |chunks: &[CitedChunk]| chunks.iter().map(CitedChunk::location).collect::<Vec<_>>();
+        return crate::output::print_json(&json!({
+            "feedback_id": feedback_id.as_hex(),
+            "pack_id": pack_id.as_hex(),
+            "useful": locations(&feedback.useful),
+            "useless": locations(&feedback.useless),
+        }));
+    }
+    for chunk in &feedback.useful {
+        println!("+ {}", chunk.location());
+    }
+    for chunk in &feedback.useless {
+        println!("- {}", chunk.location());
+    }
+    println!("Recorded feedback {}", &feedback_id.as_hex()[..12]);
+    Ok(())
+}
 
+/// Write the pack as NDJSON, one [`PackStreamItem`] per line.
+///
+/// Stops quietly if the reader closes the pipe, so consumers like `head`
+/// can cut the stream short.
+fn stream(repo: &mut CtxRepo, query: &str, config: &RetrievalConfig) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    repo.build_pack_streaming(query, config, |item: PackStreamItem| {
+        let line = item.to_json_line()?;
+        match writeln!(stdout, "{}", line).and_then(|()| stdout.flush()) {
+            Ok(()) => Ok(ControlFlow::Continue(())),
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(ControlFlow::Break(())),
+            Err(e) => Err(e.into()),
+        }
+    })
+    .context("Failed to stream prompt pack")?;
     Ok(())
 }
+
+/// Parse the `--author` flag ("human", "agent", or an author name).
+fn parse_author_filter(author: Option<&str>) -> AuthorFilter {
+    match author {
+        None => AuthorFilter::Any,
+        Some("human") | Some("humans") => AuthorFilter::Humans,
+        Some("agent") | Some("agents") => AuthorFilter::Agents,
+        Some(name) => AuthorFilter::Named(name.to_string()),
+    }
+}
@@ -1,20 +1,31 @@
 //! Rebuild command implementation.
 
 use anyhow::{Context, Result};
-use ctx_core::CtxRepo;
+use serde_json::json;
 use std::time::Instant;
 
 /// Rebuild all indexes from immutable objects.
-pub fn run() -> Result<()> {
+pub fn run(json: bool) -> Result<()> {
     let start = Instant::now();
 
-    let mut repo = CtxRepo::open(".").context("Not a CTX repository (no .ctx directory found)")?;
+    let mut repo =
+        super::open_repo(".").context("Not a CTX repository (no .ctx directory found)")?;
 
-    println!("Rebuilding index...");
+    if !json {
+        println!("Rebuilding index...");
+    }
 
-    repo.rebuild_index().context("Failed to rebuild index")?;
+    let (progress, pb) = crate::progress::progress_bar(json);
+    let rebuilt = repo.rebuild_index_with_progress(&progress);
+    pb.finish_and_clear();
+    rebuilt.context("Failed to rebuild index")?;
 
     let elapsed = start.elapsed();
+    if json {
+        return crate::output::print_json(&json!({
+            "elapsed_secs": elapsed.as_secs_f64(),
+        }));
+    }
     println!(
         "Index rebuilt successfully in {:.2}s",
         elapsed.as_secs_f64()
@@ -0,0 +1,29 @@
+//! Reset command, for moving HEAD back to an earlier position.
+
+use anyhow::{Context, Result};
+use console::style;
+use serde_json::json;
+
+/// Move HEAD and refs/main to `to`, typically a reflog entry.
+pub fn run(to: &str, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".").context("Not a CTX repository")?;
+    let head_before = repo.head_id()?;
+    let commit_id = repo
+        .reset(to)
+        .with_context(|| format!("Failed to reset to '{}'", to))?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "head_before": head_before.as_hex(),
+            "head_after": commit_id.as_hex(),
+        }));
+    }
+    println!(
+        "{} HEAD is now at {} (was {})",
+        style("✓").green(),
+        &commit_id.as_hex()[..12],
+        &head_before.as_hex()[..12]
+    );
+    println!("  Undo with {}", style("ctx reset --to HEAD@{1}").cyan());
+    Ok(())
+}
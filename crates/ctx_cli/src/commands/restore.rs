@@ -0,0 +1,77 @@
+//! Backup listing and restore command.
+
+use anyhow::Result;
+use console::style;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::status::format_age;
+
+/// List the backups of refs and narrative, newest first.
+pub fn list(json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let backups = repo.list_backups()?;
+    if json {
+        return crate::output::print_json(&backups);
+    }
+
+    if backups.is_empty() {
+        println!("No backups yet; one is taken after each compaction.");
+        return Ok(());
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    for backup in &backups {
+        let head = backup
+            .head
+            .map_or_else(|| "no HEAD".to_string(), |id| id.as_hex()[..12].to_string());
+        println!(
+            "{}  {} ago  HEAD {}  {} files, {} bytes",
+            style(&backup.name).cyan(),
+            format_age(now.saturating_sub(backup.created_at)),
+            head,
+            backup.files,
+            backup.size
+        );
+    }
+    println!();
+    println!(
+        "Restore one with {}",
+        style("ctx restore --apply <name>").cyan()
+    );
+    Ok(())
+}
+
+/// Replace the refs and narrative with those in `backup`.
+pub fn apply(backup: &str, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let report = repo.restore_backup(backup)?;
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    println!(
+        "{} Restored {} ({} files)",
+        style("✓").green(),
+        report.backup,
+        report.files
+    );
+    match report.head_before {
+        Some(before) if before != report.head_after => println!(
+            "  HEAD: {} -> {}",
+            &before.as_hex()[..12],
+            &report.head_after.as_hex()[..12]
+        ),
+        Some(_) => println!("  HEAD: {} (unchanged)", &report.head_after.as_hex()[..12]),
+        None => println!(
+            "  HEAD: unreadable -> {}",
+            &report.head_after.as_hex()[..12]
+        ),
+    }
+    println!(
+        "  The replaced files were saved as {}; restore it to undo.",
+        style(&report.previous).cyan()
+    );
+    Ok(())
+}
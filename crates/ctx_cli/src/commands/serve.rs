@@ -0,0 +1,105 @@
+//! Local HTTP server for prompt packs and metrics.
+//!
+//! `ctx serve` keeps the repository open between requests so packs are
+//! built against a warm index, and exposes:
+//! - `/api/query?q=..&budget=..` returns a prompt pack as JSON.
+//! - `/api/status` returns the repository status as JSON.
+//! - `/metrics` returns object store, pack and index metrics in the
+//!   Prometheus text format.
+//!
+//! Like the graph viewer, the server only binds to localhost and handles
+//! one request at a time.
+
+use super::serve_graph::{parse_query, read_request, respond};
+use anyhow::{Context, Result};
+use console::style;
+use ctx_core::{CtxRepo, MetricsRegistry, RetrievalConfig};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Serve the repository on `127.0.0.1:port` until interrupted.
+pub fn run(port: u16) -> Result<()> {
+    let registry = Arc::new(MetricsRegistry::new());
+    let mut repo = super::open_repo(".")?.with_metrics(registry.clone());
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .with_context(|| format!("Failed to listen on port {}", port))?;
+    println!(
+        "{} Serving {} at {} (Ctrl-C to stop)",
+        style("✓").green(),
+        repo.root().display(),
+        style(format!("http://{}/", listener.local_addr()?)).cyan()
+    );
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle(stream, &mut repo, &registry));
+        if let Err(e) = result {
+            debug!(error = %e, "Request failed");
+        }
+    }
+
+    Ok(())
+}
+
+/// Read one request from `stream` and write the response.
+fn handle(mut stream: TcpStream, repo: &mut CtxRepo, registry: &MetricsRegistry) -> Result<()> {
+    let Some(target) = read_request(&mut stream)? else {
+        return Ok(());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let params = parse_query(query);
+
+    match path {
+        "/metrics" => respond(
+            &mut stream,
+            "200 OK",
+            "text/plain; version=0.0.4",
+            registry.render_prometheus().as_bytes(),
+        ),
+        "/api/status" => match repo.status() {
+            Ok(status) => respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                &serde_json::to_vec(&status)?,
+            ),
+            Err(e) => server_error(&mut stream, e),
+        },
+        "/api/query" => {
+            let Some(q) = params.get("q").filter(|q| !q.is_empty()) else {
+                return respond(&mut stream, "400 Bad Request", "text/plain", b"missing q");
+            };
+            let mut config = RetrievalConfig::default();
+            if let Some(budget) = params.get("budget") {
+                match budget.parse() {
+                    Ok(budget) => config.token_budget = budget,
+                    Err(_) => {
+                        return respond(
+                            &mut stream,
+                            "400 Bad Request",
+                            "text/plain",
+                            b"invalid budget",
+                        )
+                    }
+                }
+            }
+            match repo.build_pack(q, &config).and_then(|pack| pack.to_json()) {
+                Ok(json) => respond(&mut stream, "200 OK", "application/json", json.as_bytes()),
+                Err(e) => server_error(&mut stream, e),
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+fn server_error(stream: &mut TcpStream, error: ctx_core::CtxError) -> Result<()> {
+    respond(
+        stream,
+        "500 Internal Server Error",
+        "text/plain",
+        error.to_string().as_bytes(),
+    )
+}
@@ -0,0 +1,507 @@
+//! Interactive graph viewer served over local HTTP.
+//!
+//! `ctx debug graph --serve` loads the edges of HEAD and serves a single
+//! page that draws them with a d3 force layout. The page talks to two
+//! endpoints:
+//! - `/api/graph?labels=..&kinds=..` returns nodes and edges, filtered by
+//!   comma-separated edge labels and node kinds (case-insensitive).
+//! - `/api/node?node=Kind::id` returns the edges, blobs and commits behind
+//!   one node.
+//!
+//! The server only binds to localhost and handles one request at a time.
+
+use anyhow::{Context, Result};
+use console::style;
+use ctx_core::{Commit, CtxRepo, Edge, NodeId, NodeKind};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use tracing::debug;
+
+/// The viewer page.
+const PAGE: &str = include_str!("serve_graph.html");
+
+/// How long a client may take to send its request before it's dropped, so
+/// one idle connection can't stall the server.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serve the HEAD graph on `127.0.0.1:port` until interrupted.
+///
+/// `labels` preselects the edge label filter; `max_nodes` caps how many
+/// nodes one response may contain.
+pub fn run(port: u16, labels: Option<&str>, max_nodes: usize) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+
+    let edge_batch_ids = {
+        let head_id = repo.head_id()?;
+        let index = repo.index()?;
+        let commit_info = index
+            .get_commit_info(head_id)?
+            .context("HEAD commit not in index")?;
+        commit_info.edge_batches.clone()
+    };
+    let edges: Vec<Edge> = repo
+        .load_edge_batches(&edge_batch_ids)?
+        .into_iter()
+        .flat_map(|batch| batch.edges)
+        .collect();
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .with_context(|| format!("Failed to listen on port {}", port))?;
+    let mut url = format!("http://{}/", listener.local_addr()?);
+    if let Some(labels) = labels {
+        url.push_str(&format!("?labels={}", percent_encode(labels)));
+    }
+    println!(
+        "{} Serving {} edges at {} (Ctrl-C to stop)",
+        style("✓").green(),
+        edges.len(),
+        style(&url).cyan()
+    );
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle(stream, &mut repo, &edges, max_nodes));
+        if let Err(e) = result {
+            debug!(error = %e, "Graph viewer request failed");
+        }
+    }
+
+    Ok(())
+}
+
+/// Read one request from `stream` and write the response.
+fn handle(
+    mut stream: TcpStream,
+    repo: &mut CtxRepo,
+    edges: &[Edge],
+    max_nodes: usize,
+) -> Result<()> {
+    let Some(target) = read_request(&mut stream)? else {
+        return Ok(());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let params = parse_query(query);
+    match path {
+        "/" => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            PAGE.as_bytes(),
+        ),
+        "/api/graph" => {
+            let body = graph_json(edges, &params, max_nodes);
+            respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                body.to_string().as_bytes(),
+            )
+        }
+        "/api/node" => {
+            let key = params.get("node").map(String::as_str).unwrap_or_default();
+            match node_json(repo, edges, key) {
+                Ok(Some(body)) => respond(
+                    &mut stream,
+                    "200 OK",
+                    "application/json",
+                    body.to_string().as_bytes(),
+                ),
+                Ok(None) => respond(&mut stream, "404 Not Found", "text/plain", b"unknown node"),
+                Err(e) => respond(
+                    &mut stream,
+                    "500 Internal Server Error",
+                    "text/plain",
+                    format!("{:#}", e).as_bytes(),
+                ),
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+/// Read a GET request from `stream` and return its target.
+///
+/// Malformed and non-GET requests are answered here and yield `None`.
+pub(crate) fn read_request(stream: &mut TcpStream) -> Result<Option<String>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&*stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip headers; requests carry no body
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        respond(stream, "400 Bad Request", "text/plain", b"bad request")?;
+        return Ok(None);
+    };
+    if method != "GET" {
+        respond(
+            stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"only GET is supported",
+        )?;
+        return Ok(None);
+    }
+    Ok(Some(target.to_string()))
+}
+
+/// Nodes and edges passing the `labels` and `kinds` filters.
+fn graph_json(edges: &[Edge], params: &BTreeMap<String, String>, max_nodes: usize) -> Value {
+    let label_filter = filter_set(params.get("labels"));
+    let kind_filter = filter_set(params.get("kinds"));
+    let allowed =
+        |filter: &BTreeSet<String>, name: String| filter.is_empty() || filter.contains(&name);
+
+    let mut labels = BTreeSet::new();
+    let mut kinds = BTreeSet::new();
+    let mut nodes: BTreeMap<String, &NodeId> = BTreeMap::new();
+    let mut shown = BTreeSet::new();
+    let mut truncated = false;
+
+    for edge in edges {
+        labels.insert(format!("{:?}", edge.label));
+        kinds.insert(format!("{:?}", edge.from.kind));
+        kinds.insert(format!("{:?}", edge.to.kind));
+
+        if !allowed(&label_filter, format!("{:?}", edge.label).to_lowercase())
+            || !allowed(&kind_filter, format!("{:?}", edge.from.kind).to_lowercase())
+            || !allowed(&kind_filter, format!("{:?}", edge.to.kind).to_lowercase())
+        {
+            continue;
+        }
+
+        let (from, to) = (node_key(&edge.from), node_key(&edge.to));
+        let new_nodes: BTreeSet<&String> = [&from, &to]
+            .into_iter()
+            .filter(|key| !nodes.contains_key(*key))
+            .collect();
+        if nodes.len() + new_nodes.len() > max_nodes {
+            truncated = true;
+            continue;
+        }
+        nodes.insert(from.clone(), &edge.from);
+        nodes.insert(to.clone(), &edge.to);
+        shown.insert((from, to, format!("{:?}", edge.label)));
+    }
+
+    json!({
+        "labels": labels,
+        "kinds": kinds,
+        "nodes": nodes
+            .iter()
+            .map(|(key, node)| json!({
+                "key": key,
+                "kind": format!("{:?}", node.kind),
+                "id": node.id,
+            }))
+            .collect::<Vec<_>>(),
+        "edges": shown
+            .into_iter()
+            .map(|(source, target, label)| json!({
+                "source": source,
+                "target": target,
+                "label": label,
+            }))
+            .collect::<Vec<_>>(),
+        "truncated": truncated,
+    })
+}
+
+/// Edges, blobs and commits behind the node with `key`, or `None` if no
+/// edge touches it.
+fn node_json(repo: &mut CtxRepo, edges: &[Edge], key: &str) -> Result<Option<Value>> {
+    let mut node = None;
+    let mut outgoing = BTreeSet::new();
+    let mut incoming = BTreeSet::new();
+    let mut blobs = BTreeSet::new();
+    let mut commits = BTreeSet::new();
+
+    for edge in edges {
+        let (from, to) = (node_key(&edge.from), node_key(&edge.to));
+        if from == key || to == key {
+            blobs.extend(edge.evidence.blob_id);
+            commits.insert(edge.evidence.commit_id);
+        }
+        if from == key {
+            node = Some(&edge.from);
+            outgoing.insert((format!("{:?}", edge.label), to.clone()));
+        }
+        if to == key {
+            node = Some(&edge.to);
+            incoming.insert((format!("{:?}", edge.label), from));
+        }
+    }
+    let Some(node) = node else {
+        return Ok(None);
+    };
+
+    // The current version of a file, even if no edge cites it
+    if node.kind == NodeKind::File {
+        blobs.extend(repo.index()?.lookup_path(&node.id)?);
+    }
+
+    let commits: Vec<Value> = commits
+        .into_iter()
+        .map(|id| match repo.object_store().get_typed::<Commit>(id) {
+            Ok(commit) => json!({
+                "id": id.as_hex(),
+                "message": commit.message,
+                "timestamp": commit.timestamp_unix,
+            }),
+            Err(_) => json!({ "id": id.as_hex() }),
+        })
+        .collect();
+    let edge_list = |edges: BTreeSet<(String, String)>| -> Vec<Value> {
+        edges
+            .into_iter()
+            .map(|(label, node)| json!({ "label": label, "node": node }))
+            .collect()
+    };
+
+    Ok(Some(json!({
+        "key": key,
+        "kind": format!("{:?}", node.kind),
+        "id": node.id,
+        "outgoing": edge_list(outgoing),
+        "incoming": edge_list(incoming),
+        "blobs": blobs.iter().map(|id| id.as_hex()).collect::<Vec<_>>(),
+        "commits": commits,
+    })))
+}
+
+/// `Kind::id`, as shown in prompt pack graph context.
+fn node_key(node: &NodeId) -> String {
+    format!("{:?}::{}", node.kind, node.id)
+}
+
+/// Lowercased entries of a comma-separated filter parameter.
+fn filter_set(value: Option<&String>) -> BTreeSet<String> {
+    value
+        .map(|v| {
+            v.split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse and percent-decode a URL query string.
+pub(crate) fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Percent-encode everything but unreserved characters and commas, for a
+/// query parameter value.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b',' => {
+                out.push(byte as char)
+            }
+            byte => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+pub(crate) fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctx_core::{Confidence, EdgeLabel, Evidence, EvidenceTool, ObjectId};
+    use tempfile::TempDir;
+
+    fn node(kind: NodeKind, id: &str) -> NodeId {
+        NodeId {
+            kind,
+            id: id.to_string(),
+        }
+    }
+
+    fn edge(from: NodeId, to: NodeId, label: EdgeLabel) -> Edge {
+        Edge {
+            from,
+            to,
+            label,
+            weight: None,
+            evidence: Evidence {
+                commit_id: ObjectId::from_bytes([1; 32]),
+                tool: EvidenceTool::Parser,
+                confidence: Confidence::High,
+                span: None,
+                blob_id: Some(ObjectId::from_bytes([2; 32])),
+                condition: None,
+            },
+        }
+    }
+
+    fn edges() -> Vec<Edge> {
+        vec![
+            edge(
+                node(NodeKind::File, "src/lib.rs"),
+                node(NodeKind::Item, "parse"),
+                EdgeLabel::Defines,
+            ),
+            edge(
+                node(NodeKind::Item, "parse"),
+                node(NodeKind::Item, "lex"),
+                EdgeLabel::Calls,
+            ),
+            edge(
+                node(NodeKind::Module, "parser"),
+                node(NodeKind::Module, "lexer"),
+                EdgeLabel::Imports,
+            ),
+        ]
+    }
+
+    fn params(query: &str) -> BTreeMap<String, String> {
+        parse_query(query)
+    }
+
+    #[test]
+    fn test_parse_query_decodes_pairs() {
+        let parsed = params("labels=Calls%2CImports&q=where+is+auth&flag&bad=%zz&tail=%4");
+        assert_eq!(parsed["labels"], "Calls,Imports");
+        assert_eq!(parsed["q"], "where is auth");
+        assert_eq!(parsed["flag"], "");
+        assert_eq!(parsed["bad"], "%zz");
+        assert_eq!(parsed["tail"], "%4");
+        assert!(params("").is_empty());
+
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_encode("Calls,Uses Type&x"), "Calls,Uses%20Type%26x");
+        assert_eq!(percent_decode(&percent_encode("a b&c=d")), "a b&c=d");
+    }
+
+    #[test]
+    fn test_graph_json_filters_by_label_and_kind() {
+        let edges = edges();
+
+        let all = graph_json(&edges, &params(""), 100);
+        assert_eq!(all["edges"].as_array().unwrap().len(), 3);
+        assert_eq!(all["nodes"].as_array().unwrap().len(), 5);
+        assert_eq!(all["labels"], json!(["Calls", "Defines", "Imports"]));
+        assert_eq!(all["truncated"], false);
+
+        let calls = graph_json(&edges, &params("labels=calls,IMPORTS"), 100);
+        let labels: Vec<&str> = calls["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["Calls", "Imports"]);
+        // The label list still offers every label
+        assert_eq!(calls["labels"], all["labels"]);
+
+        let items = graph_json(&edges, &params("kinds=item"), 100);
+        assert_eq!(items["edges"].as_array().unwrap().len(), 1);
+        assert_eq!(items["edges"][0]["source"], "Item::parse");
+        assert_eq!(items["edges"][0]["target"], "Item::lex");
+    }
+
+    #[test]
+    fn test_graph_json_truncates_at_max_nodes() {
+        let graph = graph_json(&edges(), &params(""), 3);
+        // The first two edges share `parse`; the third would add two more
+        assert_eq!(graph["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(graph["edges"].as_array().unwrap().len(), 2);
+        assert_eq!(graph["truncated"], true);
+    }
+
+    #[test]
+    fn test_node_json() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let edges = edges();
+
+        let parse = node_json(&mut repo, &edges, "Item::parse")
+            .unwrap()
+            .unwrap();
+        assert_eq!(parse["kind"], "Item");
+        assert_eq!(parse["id"], "parse");
+        assert_eq!(
+            parse["incoming"],
+            json!([{ "label": "Defines", "node": "File::src/lib.rs" }])
+        );
+        assert_eq!(
+            parse["outgoing"],
+            json!([{ "label": "Calls", "node": "Item::lex" }])
+        );
+        assert_eq!(
+            parse["blobs"],
+            json!([ObjectId::from_bytes([2; 32]).as_hex()])
+        );
+        // The evidence commit isn't stored, so only its id is known
+        assert_eq!(
+            parse["commits"],
+            json!([{ "id": ObjectId::from_bytes([1; 32]).as_hex() }])
+        );
+
+        assert!(node_json(&mut repo, &edges, "Item::missing")
+            .unwrap()
+            .is_none());
+    }
+}
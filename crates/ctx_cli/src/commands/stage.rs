@@ -1,7 +1,12 @@
 //! Session (staging area) management commands.
 
-use anyhow::Result;
-use ctx_core::CtxRepo;
+use anyhow::{Context, Result};
+use ctx_core::{
+    apply_actions, AgentIdentity, Config, CtxRepo, ObjectId, RecoverySummary, SessionEvent,
+    SessionHandler, StepDisposition, UserChoice,
+};
+use serde_json::json;
+use std::io::{BufRead, IsTerminal, Write};
 
 /// Ensures the repository has an active session, recovering from STAGE if needed.
 ///
@@ -16,16 +21,30 @@ fn ensure_session_recovered(repo: &mut CtxRepo) -> Result<()> {
     Ok(())
 }
 
-pub fn start(task: &str) -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+pub fn start(task: &str, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?.with_identity(AgentIdentity::from_env());
 
     // Check for stale sessions
     if repo.has_active_session() {
         return Err(anyhow::anyhow!("A session is already active. Use 'ctx stage compact' or 'ctx stage abort' to finish it."));
     }
 
+    // A session its agent abandoned long ago isn't resumed under a new task
+    if let Some(commit_id) = repo.expire_session()? {
+        eprintln!(
+            "Expired abandoned session into commit: {}",
+            commit_id.as_hex()
+        );
+    }
+
     // Try to recover existing session first
     if repo.recover_session()?.is_some() {
+        if json {
+            return crate::output::print_json(&json!({
+                "recovered": true,
+                "session": repo.session_status()?,
+            }));
+        }
         println!("Recovered existing session from staging area");
         println!("Use 'ctx stage status' to see details");
         return Ok(());
@@ -33,18 +52,34 @@ pub fn start(task: &str) -> Result<()> {
 
     // Start new session
     let session = repo.start_session(task)?;
+    if json {
+        let author = session.author().map(|author| author.to_string());
+        return crate::output::print_json(&json!({
+            "recovered": false,
+            "session_id": session.session_id(),
+            "task": session.task_description(),
+            "author": author,
+        }));
+    }
     println!("Started new session: {}", session.task_description());
     println!("Session ID: {}", session.session_id());
+    if let Some(author) = session.author() {
+        println!("Author: {}", author);
+    }
 
     Ok(())
 }
 
-pub fn status() -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+pub fn status(json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
 
     // Try to recover session if one exists
     let _ = repo.recover_session()?;
 
+    if json {
+        return crate::output::print_json(&repo.session_status()?);
+    }
+
     match repo.active_session() {
         Some(session) => {
             println!("Active session:");
@@ -57,6 +92,14 @@ pub fn status() -> Result<()> {
                 "  Idle time: {:.0} seconds",
                 session.idle_time().as_secs_f64()
             );
+            if let Some(status) = repo.session_status()? {
+                if !status.actors.is_empty() {
+                    println!("  Actors:");
+                    for (actor, activity) in &status.actors {
+                        println!("    {}: {}", actor, activity.describe());
+                    }
+                }
+            }
 
             // Show progress summary if available
             if let Ok(summary) = session.generate_progress_summary(repo.object_store()) {
@@ -71,59 +114,285 @@ pub fn status() -> Result<()> {
     Ok(())
 }
 
-pub fn flush() -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+pub fn flush(json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
     ensure_session_recovered(&mut repo)?;
 
     let work_id = repo.flush_active_session()?;
     let step_count = repo.active_session().unwrap().step_count();
 
+    if json {
+        return crate::output::print_json(&json!({
+            "work_commit_id": work_id.as_hex(),
+            "steps": step_count,
+        }));
+    }
+
     println!("Flushed step to staging: {}", work_id.as_hex());
     println!("Steps completed: {}", step_count);
 
     Ok(())
 }
 
-pub fn compact(message: &str) -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+/// Compact the session. Without a message, the configured summary provider
+/// writes it along with a log entry.
+pub fn compact(message: Option<String>, dry_run: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
     ensure_session_recovered(&mut repo)?;
 
-    let commit_id = repo.compact_session(message)?;
+    let summarized = message.is_none();
+    let compact = |repo: &mut CtxRepo| -> ctx_core::Result<(ObjectId, String)> {
+        match message {
+            Some(message) => Ok((repo.compact_session(&message)?, message)),
+            None => {
+                let provider = repo.summary_provider()?;
+                let (commit_id, summary) = repo.compact_session_summarized(provider.as_ref())?;
+                Ok((commit_id, summary.message))
+            }
+        }
+    };
+    let compacted = if dry_run {
+        repo.dry_run(compact)
+            .map(|(compacted, report)| (compacted, Some(report)))
+    } else {
+        compact(&mut repo).map(|compacted| (compacted, None))
+    };
+    let ((commit_id, message), report) = if summarized {
+        compacted.context("Failed to summarize the session")?
+    } else {
+        compacted?
+    };
 
-    println!("Compacted session into commit: {}", commit_id.as_hex());
-    println!("Session complete!");
+    let auto_gc = repo.take_auto_gc_report();
+    let output = json!({
+        "commit_id": commit_id.as_hex(),
+        "message": message,
+        "auto_gc": auto_gc,
+    });
+    if json {
+        return match &report {
+            Some(report) => crate::output::print_dry_run(&output, report, json),
+            None => crate::output::print_json(&output),
+        };
+    }
+
+    match &report {
+        Some(report) => {
+            println!("Would compact session into commit: {}", commit_id.as_hex());
+            for line in message.lines() {
+                println!("    {}", line);
+            }
+            crate::output::print_dry_run(&output, report, json)?;
+        }
+        None => {
+            println!("Compacted session into commit: {}", commit_id.as_hex());
+            for line in message.lines() {
+                println!("    {}", line);
+            }
+            println!("Session complete!");
+            if let Some(auto_gc) = &auto_gc {
+                crate::commands::gc::print_auto(auto_gc);
+            }
+        }
+    }
 
     Ok(())
 }
 
-pub fn abort(reason: Option<String>) -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+pub fn abort(reason: Option<String>, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
     ensure_session_recovered(&mut repo)?;
 
     let reason_text = reason.unwrap_or_else(|| "User aborted".to_string());
     let commit_id = repo.abort_session(&reason_text)?;
 
+    if json {
+        return crate::output::print_json(&json!({
+            "reason": reason_text,
+            "commit_id": commit_id.as_hex(),
+        }));
+    }
+
     println!("Aborted session: {}", reason_text);
     println!("Created abort commit: {}", commit_id.as_hex());
 
     Ok(())
 }
 
-pub fn recover() -> Result<()> {
-    let mut repo = CtxRepo::open(".")?;
+/// Save or abandon the staged session if its process has exited and it has
+/// been idle past the auto-compact threshold.
+pub fn expire(json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+    let commit_id = repo.expire_session()?;
 
-    match repo.recover_session()? {
-        Some(session) => {
-            println!("Recovered session from staging area:");
-            println!("  Task: {}", session.task_description());
-            println!("  Session ID: {}", session.session_id());
-            println!("  Steps completed: {}", session.step_count());
-            println!("  State: {:?}", session.state());
-            Ok(())
+    if json {
+        return crate::output::print_json(&json!({
+            "commit_id": commit_id.map(|id| id.as_hex()),
+        }));
+    }
+
+    match commit_id {
+        Some(commit_id) => println!("Expired session into commit: {}", commit_id.as_hex()),
+        None => println!("No expired session."),
+    }
+    Ok(())
+}
+
+/// Recover the staged session, repairing a broken staging chain.
+///
+/// With `interactive`, then asks whether to resume, save, or abandon it.
+pub fn recover(interactive: bool, json: bool) -> Result<()> {
+    if interactive && json {
+        return Err(anyhow::anyhow!(
+            "--interactive can't be combined with --json"
+        ));
+    }
+    if interactive && !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!("--interactive needs a terminal"));
+    }
+    let mut repo = super::open_repo(".")?;
+    let summary = repo.recover_session_with_summary()?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "recovery": summary,
+            "session": repo.session_status()?,
+        }));
+    }
+
+    if summary.stage.is_none() {
+        println!("No staging area found - nothing to recover");
+        return Ok(());
+    }
+    print_recovery(&summary);
+    let Some(session) = repo.active_session() else {
+        println!("No readable steps - the staging area was removed");
+        return Ok(());
+    };
+    println!("Recovered session from staging area:");
+    println!("  Task: {}", session.task_description());
+    println!("  Session ID: {}", session.session_id());
+    println!("  Steps completed: {}", session.step_count());
+    println!("  State: {:?}", session.state());
+
+    if interactive {
+        choose_recovery_action(&mut repo, &summary)?;
+    }
+    Ok(())
+}
+
+/// Prints the disposition of each step examined by recovery.
+fn print_recovery(summary: &RecoverySummary) {
+    if !summary.is_repaired() && summary.count(StepDisposition::Orphaned) == 0 {
+        return;
+    }
+    println!("Staging chain repaired:");
+    for step in &summary.steps {
+        let kind = step
+            .step_kind
+            .map_or_else(|| "unreadable".to_string(), |kind| format!("{:?}", kind));
+        let disposition = match step.disposition {
+            StepDisposition::Kept => "kept",
+            StepDisposition::Truncated => "truncated",
+            StepDisposition::Orphaned => "orphaned",
+        };
+        println!("  {:<9} {} {}", disposition, &step.id.as_hex()[..12], kind);
+    }
+    println!();
+}
+
+/// Asks on the terminal what to do with the recovered session and applies
+/// the answer.
+fn choose_recovery_action(repo: &mut CtxRepo, summary: &RecoverySummary) -> Result<()> {
+    let stale = Config::load(&repo.ctx_dir())?.session.stale_config();
+    let mut handler = SessionHandler::for_repo(stale, repo);
+    let now = chrono::Utc::now().timestamp();
+    let response = handler.handle(SessionEvent::SessionRecovered {
+        steps_kept: summary.count(StepDisposition::Kept),
+        steps_lost: summary.steps.len() - summary.count(StepDisposition::Kept),
+        at: now,
+    })?;
+
+    let choice = loop {
+        println!();
+        if let Some(prompt) = &response.prompt {
+            println!("{}", prompt);
+        }
+        print!("> ");
+        let _ = std::io::stdout().flush();
+        let mut answer = String::new();
+        if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+            println!();
+            println!("No choice made - the session stays staged");
+            return Ok(());
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "a" | "resume" => break UserChoice::Continue,
+            "b" | "save" | "compact" => break UserChoice::StartFresh,
+            "c" | "abandon" => break UserChoice::Abandon,
+            _ => println!("Please answer A, B, or C"),
+        }
+    };
+
+    let response = handler.handle(SessionEvent::UserChose { choice, at: now })?;
+    apply_actions(repo, &response.actions)?;
+    match choice {
+        UserChoice::Continue => {
+            repo.flush_active_session()?;
+            println!("Resumed session - continue with 'ctx stage status'");
+        }
+        UserChoice::StartFresh => {
+            println!("Saved session as commit {}", repo.head_id()?.as_hex());
+        }
+        UserChoice::Abandon => {
+            println!("Abandoned session in commit {}", repo.head_id()?.as_hex());
+        }
+    }
+    Ok(())
+}
+
+/// Show the conversation recorded for a session: the one compacted into
+/// `commit`, or else the active session's, or else HEAD's.
+pub fn transcript(commit: Option<String>, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+
+    let transcript = match &commit {
+        Some(spec) => {
+            let commit_id = repo.resolve_commit(spec)?;
+            repo.transcript(commit_id)?
         }
         None => {
-            println!("No staging area found - nothing to recover");
-            Ok(())
+            if repo.recover_session()?.is_some() {
+                repo.active_transcript()?
+            } else {
+                repo.transcript(repo.head_id()?)?
+            }
         }
+    };
+    let Some(transcript) = transcript else {
+        return Err(anyhow::anyhow!(
+            "No conversation recorded for {}",
+            commit.as_deref().unwrap_or("this session")
+        ));
+    };
+    let turns = transcript
+        .turns(repo.object_store())
+        .context("Failed to load transcript messages")?;
+
+    if json {
+        return crate::output::print_json(&json!({
+            "task": transcript.task,
+            "session_id": transcript.session_id,
+            "turns": turns,
+        }));
     }
+
+    println!("Task: {}", transcript.task);
+    println!("Session ID: {}", transcript.session_id);
+    for turn in &turns {
+        println!("\n{}:", turn.role);
+        println!("{}", turn.content.trim_end());
+    }
+
+    Ok(())
 }
@@ -0,0 +1,146 @@
+//! Repository status command.
+
+use anyhow::Result;
+use console::style;
+use ctx_core::{IndexFreshness, StaleSessionStatus};
+
+/// Show a summary of repository state.
+pub fn run(json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".")?;
+
+    // Pick up a session left behind by a previous process
+    let _ = repo.recover_session()?;
+
+    let status = repo.status()?;
+    if json {
+        return crate::output::print_json(&status);
+    }
+
+    println!("{}", style("HEAD").bold());
+    println!(
+        "  {} {}",
+        style(&status.head.id.as_hex()[..12]).yellow(),
+        status.head.message.lines().next().unwrap_or("")
+    );
+    println!("  Committed {} ago", format_age(status.head.age_secs));
+
+    println!();
+    println!("{}", style("Session").bold());
+    match &status.session {
+        Some(session) => {
+            println!("  Task: {}", session.task);
+            println!("  Session ID: {}", session.session_id);
+            println!("  State: {:?}", session.state);
+            println!(
+                "  Steps: {} ({} work commits staged)",
+                session.steps, session.staging_chain_len
+            );
+            match &session.staleness {
+                StaleSessionStatus::ShouldAsk { idle_secs, .. } => println!(
+                    "  {} Idle for {}; consider compacting it",
+                    style("⚠").yellow(),
+                    format_age(*idle_secs)
+                ),
+                StaleSessionStatus::ShouldAutoCompact { idle_secs, .. } => println!(
+                    "  {} Idle for {}; it will be auto-compacted",
+                    style("⚠").red(),
+                    format_age(*idle_secs)
+                ),
+                StaleSessionStatus::Fresh { idle_secs, .. } => {
+                    println!("  Idle for {}", format_age(*idle_secs))
+                }
+                StaleSessionStatus::NoSession => {}
+            }
+        }
+        None => println!("  No active session"),
+    }
+    // Our own lock is the recovered session's; only report someone else's
+    if let Some(lock) = status.lock.as_ref().filter(|lock| !lock.owned) {
+        let holder = lock.pid.map_or_else(
+            || "another process".to_string(),
+            |pid| format!("PID {}", pid),
+        );
+        let age = lock
+            .age_secs
+            .map(|secs| format!(" for {}", format_age(secs)))
+            .unwrap_or_default();
+        if lock.holder_alive {
+            println!("  {} Locked by {}{}", style("⚠").yellow(), holder, age);
+        } else {
+            println!(
+                "  {} Stale lock from {}{} (run {})",
+                style("⚠").yellow(),
+                holder,
+                age,
+                style("ctx unlock").cyan()
+            );
+        }
+    }
+
+    println!();
+    println!("{}", style("Index").bold());
+    match &status.index {
+        IndexFreshness::Current => println!("  {} Up to date with HEAD", style("✓").green()),
+        IndexFreshness::Behind { commits } => println!(
+            "  {} {} commit{} behind HEAD (run {})",
+            style("⚠").yellow(),
+            commits,
+            if *commits == 1 { "" } else { "s" },
+            style("ctx rebuild").cyan()
+        ),
+        IndexFreshness::Missing => println!("  Not built yet (built on first query)"),
+        IndexFreshness::Unreadable { reason } => println!(
+            "  {} Unreadable: {} (run {})",
+            style("×").red(),
+            reason,
+            style("ctx rebuild").cyan()
+        ),
+    }
+
+    if let Some(cargo) = &status.cargo {
+        println!();
+        println!("{}", style("Cargo").bold());
+        if cargo.stale {
+            println!(
+                "  {} Snapshot predates changes to {} (run {})",
+                style("⚠").yellow(),
+                cargo.changed_manifests.join(", "),
+                style("ctx analyze cargo").cyan()
+            );
+        } else {
+            println!("  {} Snapshot matches the manifests", style("✓").green());
+        }
+    }
+
+    let objects = &status.objects;
+    println!();
+    println!("{}", style("Objects").bold());
+    println!(
+        "  Total:       {} ({:.2} MB)",
+        style(objects.total).cyan(),
+        objects.total_bytes as f64 / 1_048_576.0
+    );
+    println!("  Reachable:   {}", style(objects.reachable).green());
+    println!(
+        "  Collectable: {} ({:.2} MB)",
+        if objects.collectable > 0 {
+            style(objects.collectable).yellow()
+        } else {
+            style(objects.collectable).green()
+        },
+        objects.collectable_bytes as f64 / 1_048_576.0
+    );
+
+    Ok(())
+}
+
+/// Formats a duration in seconds as its largest whole unit.
+pub fn format_age(secs: u64) -> String {
+    let (value, unit) = match secs {
+        s if s < 60 => (s, "second"),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s => (s / (24 * 60 * 60), "day"),
+    };
+    format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" })
+}
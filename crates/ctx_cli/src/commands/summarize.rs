@@ -0,0 +1,44 @@
+//! Summarize files so packs can include them when the budget is tight.
+
+use anyhow::{Context, Result};
+use console::style;
+
+/// Summarize the file or directory at `path` with the configured summarizer.
+pub fn run(path: &str, force: bool, json: bool) -> Result<()> {
+    let mut repo = super::open_repo(".").context("Not a CTX repository")?;
+    let summarizer = repo.summarizer()?;
+    let report = repo
+        .summarize(path, summarizer.as_ref(), force)
+        .context("Failed to summarize")?;
+
+    if json {
+        return crate::output::print_json(&report);
+    }
+
+    for file in &report.summarized {
+        println!("  {} {}", style("summarized").green(), file);
+    }
+    for (file, reason) in &report.skipped {
+        println!("  {} {} ({})", style("skipped").yellow(), file, reason);
+    }
+    println!(
+        "{} {} summarized with {}, {} already cached, {} skipped",
+        style("✓").green(),
+        report.summarized.len(),
+        summarizer.name(),
+        report.cached.len(),
+        report.skipped.len()
+    );
+    if report.summarized.len() + report.cached.len() == 1 {
+        let file = report.summarized.first().or(report.cached.first());
+        if let Some(summary) = file
+            .and_then(|file| repo.index().ok()?.lookup_path(file).ok().flatten())
+            .map(|blob_id| repo.summary(blob_id))
+            .transpose()?
+            .flatten()
+        {
+            println!("\n{}", summary.text);
+        }
+    }
+    Ok(())
+}
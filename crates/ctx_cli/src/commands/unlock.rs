@@ -0,0 +1,39 @@
+//! Stale lock removal command.
+
+use anyhow::Result;
+use console::style;
+use serde_json::json;
+
+/// Remove the repository LOCK if its holder is gone.
+pub fn run(force: bool, json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
+    let removed = repo.unlock(force)?;
+    if json {
+        return crate::output::print_json(&json!({ "removed": removed }));
+    }
+
+    match removed {
+        Some(lock) => {
+            let holder = lock.pid.map_or_else(
+                || "an unknown process".to_string(),
+                |pid| format!("PID {}", pid),
+            );
+            let age = lock
+                .age_secs
+                .map(|secs| format!(", taken {}s ago", secs))
+                .unwrap_or_default();
+            println!(
+                "{} Removed lock held by {}{}",
+                style("✓").green(),
+                holder,
+                age
+            );
+            println!(
+                "  Run {} to pick up the interrupted session.",
+                style("ctx stage recover").cyan()
+            );
+        }
+        None => println!("{} Repository is not locked.", style("✓").green()),
+    }
+    Ok(())
+}
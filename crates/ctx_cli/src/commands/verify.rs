@@ -2,26 +2,32 @@
 
 use anyhow::Result;
 use console::style;
-use ctx_core::{CtxRepo, VerifyConfig};
+use ctx_core::{VerifyConfig, VerifySample};
 use indicatif::{ProgressBar, ProgressStyle};
 
 /// Verify repository integrity.
-pub fn run(objects: bool, full: bool) -> Result<()> {
-    let repo = CtxRepo::open(".")?;
+///
+/// With `sample`, the object check covers only today's `sample` percent of
+/// the objects.
+pub fn run(objects: bool, full: bool, sample: Option<u32>, json: bool) -> Result<()> {
+    let repo = super::open_repo(".")?;
 
+    let sample = sample.map(VerifySample::today);
     let config = if full {
         VerifyConfig {
             check_objects: true,
             check_refs: true,
             check_commits: true,
-            verbose: false,
+            sample,
+            ..Default::default()
         }
-    } else if objects {
+    } else if objects || sample.is_some() {
         VerifyConfig {
             check_objects: true,
             check_refs: false,
             check_commits: false,
-            verbose: false,
+            sample,
+            ..Default::default()
         }
     } else {
         VerifyConfig::default()
@@ -29,6 +35,10 @@ pub fn run(objects: bool, full: bool) -> Result<()> {
 
     let check_objects = config.check_objects;
 
+    if json {
+        return crate::output::print_json(&repo.verify(config)?);
+    }
+
     // Show a spinner for verification
     let spinner = if check_objects {
         let pb = ProgressBar::new_spinner();
@@ -62,10 +72,17 @@ pub fn run(objects: bool, full: bool) -> Result<()> {
     println!("{}", style("Verification Report:").bold());
 
     if check_objects {
-        println!(
-            "  Objects checked:    {}",
-            style(report.objects_checked).cyan()
-        );
+        match sample {
+            Some(sample) => println!(
+                "  Objects checked:    {} (today's {}% sample)",
+                style(report.objects_checked).cyan(),
+                sample.percent
+            ),
+            None => println!(
+                "  Objects checked:    {}",
+                style(report.objects_checked).cyan()
+            ),
+        }
         if !report.objects_corrupted.is_empty() {
             println!(
                 "  Corrupted objects:  {}",
@@ -105,6 +122,16 @@ pub fn run(objects: bool, full: bool) -> Result<()> {
         }
     }
 
+    if !report.rust_snapshot_drift.is_empty() {
+        println!(
+            "  Stale Rust analysis: {} (run 'ctx analyze rust')",
+            style(report.rust_snapshot_drift.len()).yellow()
+        );
+        for path in &report.rust_snapshot_drift {
+            println!("    {} {}", style("⚠").yellow(), path);
+        }
+    }
+
     println!();
     if report.has_issues() {
         println!("{}", style(&report.summary()).yellow().bold());
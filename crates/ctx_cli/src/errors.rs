@@ -0,0 +1,106 @@
+//! Exit codes and error reports for failed commands.
+//!
+//! Each kind of [`CtxError`] maps to an exit code, so wrappers can tell a
+//! lock held by another process (worth retrying) from a corrupt repository
+//! (worth a `ctx verify`) without parsing messages. With `--json`, the
+//! error is also printed on stdout with its stable
+//! [`code`](CtxError::code).
+
+use ctx_core::CtxError;
+use serde_json::json;
+
+/// Any other failure.
+pub const FAILURE: i32 = 1;
+/// Invalid arguments, as for clap's usage errors.
+pub const USAGE: i32 = 2;
+/// A repository, object or ref doesn't exist.
+pub const NOT_FOUND: i32 = 3;
+/// Another process holds a lock; retrying later may succeed.
+pub const LOCKED: i32 = 4;
+/// A lock file couldn't be created or locked.
+pub const LOCK_ERROR: i32 = 5;
+/// The command needs no session, or a session, and the state disagrees.
+pub const SESSION: i32 = 6;
+/// A hook, the exec policy or an ignore rule refused the operation.
+pub const REJECTED: i32 = 7;
+/// Stored objects, refs or staging data failed an integrity check.
+pub const CORRUPT: i32 = 8;
+/// The index failed or is damaged; `ctx rebuild` regenerates it.
+pub const INDEX: i32 = 9;
+/// A ref file couldn't be read or written.
+pub const REF: i32 = 10;
+/// The configuration is invalid.
+pub const CONFIG: i32 = 11;
+/// A filesystem or remote object store error.
+pub const IO: i32 = 12;
+/// cargo, rust-analyzer or the LSP connection failed.
+pub const TOOLCHAIN: i32 = 13;
+
+/// The first [`CtxError`] in the chain of `error`, if any.
+fn ctx_error(error: &anyhow::Error) -> Option<&CtxError> {
+    error.chain().find_map(|e| e.downcast_ref::<CtxError>())
+}
+
+/// The exit code for `error`.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    ctx_error(error).map_or(FAILURE, ctx_exit_code)
+}
+
+fn ctx_exit_code(error: &CtxError) -> i32 {
+    match error {
+        CtxError::InvalidArgument(_) | CtxError::InvalidCursor(_) | CtxError::InvalidHex(_) => {
+            USAGE
+        }
+        CtxError::ObjectNotFound(_) | CtxError::RefNotFound(_) => NOT_FOUND,
+        CtxError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => NOT_FOUND,
+        CtxError::RepositoryLocked
+        | CtxError::SessionLockHeld { .. }
+        | CtxError::IndexLocked { .. } => LOCKED,
+        CtxError::LockError { .. } => LOCK_ERROR,
+        CtxError::NoActiveSession
+        | CtxError::SessionAlreadyActive(_)
+        | CtxError::InvalidStateTransition { .. } => SESSION,
+        CtxError::CommandDenied { .. }
+        | CtxError::HookRejected { .. }
+        | CtxError::PathIgnored { .. } => REJECTED,
+        CtxError::HashMismatch { .. }
+        | CtxError::CorruptedObject { .. }
+        | CtxError::ObjectCorrupt { .. }
+        | CtxError::InvalidRef { .. }
+        | CtxError::StagingCorrupted { .. } => CORRUPT,
+        CtxError::IndexError(_) | CtxError::IndexCorrupted { .. } => INDEX,
+        CtxError::RefError { .. } => REF,
+        CtxError::ConfigError(_) => CONFIG,
+        CtxError::Io(_) | CtxError::RemoteStore(_) => IO,
+        CtxError::RustAnalyzerNotFound
+        | CtxError::RustAnalyzerStartFailed(_)
+        | CtxError::RustAnalyzerCrashed(_)
+        | CtxError::LspTimeout { .. }
+        | CtxError::LspProtocolError(_)
+        | CtxError::LspError { .. }
+        | CtxError::CargoNotFound
+        | CtxError::CargoMetadataFailed(_)
+        | CtxError::NoCargoManifest(_)
+        | CtxError::CargoMetadataParseFailed(_) => TOOLCHAIN,
+        _ => FAILURE,
+    }
+}
+
+/// Prints `error` on stderr, and with `json` also as a JSON document on
+/// stdout.
+pub fn report(error: &anyhow::Error, json: bool) {
+    eprintln!("Error: {:?}", error);
+    if json {
+        let ctx_error = ctx_error(error);
+        let report = json!({
+            "error": {
+                "code": ctx_error.map_or("error", CtxError::code),
+                "message": format!("{:#}", error),
+                "exit_code": exit_code(error),
+                "suggestion": ctx_error.and_then(CtxError::recovery_suggestion),
+            }
+        });
+        // Reporting an error must not fail in turn
+        let _ = crate::output::print_json(&report);
+    }
+}
@@ -4,20 +4,39 @@ use anyhow::Result;
 use clap::{Parser, Subcommand};
 
 mod commands;
+mod errors;
+mod output;
+mod progress;
 
 #[derive(Parser)]
 #[command(name = "ctx")]
 #[command(about = "Context management for coding agents", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Print machine-readable JSON instead of text (also CTX_OUTPUT=json)
+    #[arg(long, global = true)]
+    json: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of the largest variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a new CTX repository
     Init,
+    /// Copy a repository into a new one, hard-linking objects where possible
+    Clone {
+        /// Directory containing the repository to copy
+        source: std::path::PathBuf,
+        /// Directory to create the new repository in
+        dest: std::path::PathBuf,
+        /// Keep only the last N commits of history
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+    },
     /// Add content to the repository
     Add {
         #[command(subcommand)]
@@ -31,6 +50,27 @@ enum Commands {
         /// Don't snapshot narrative files
         #[arg(long)]
         no_narrative: bool,
+        /// Report the commit that would be created without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Attach a note to a past commit, e.g. that it introduced a bug
+    Annotate {
+        /// Commit to annotate (ID, prefix, ref name, HEAD or HEAD~N)
+        commit: String,
+        /// The note
+        text: String,
+    },
+    /// Name a commit, e.g. a release milestone, as refs/tags/<name>
+    Tag {
+        /// Tag name
+        name: String,
+        /// Commit to tag (ID, prefix, ref name, HEAD or HEAD~N; default HEAD)
+        #[arg(default_value = "HEAD")]
+        commit: String,
+        /// Move the tag if it already points at another commit
+        #[arg(long)]
+        force: bool,
     },
     /// Rebuild indexes from objects
     Rebuild,
@@ -39,22 +79,191 @@ enum Commands {
         #[command(subcommand)]
         command: StageCommands,
     },
+    /// Run a command and record it in the active session
+    Exec {
+        /// Record the command without running it (used by shell hooks)
+        #[arg(long, requires = "exit_code")]
+        no_run: bool,
+        /// Exit code to record with --no-run
+        #[arg(long, requires = "no_run")]
+        exit_code: Option<i32>,
+        /// Print a hook that records interactive commands (bash, zsh, fish)
+        #[arg(long, value_name = "SHELL", conflicts_with_all = ["no_run", "command"])]
+        shell_hook: Option<String>,
+        /// Attribute the command to this sub-agent of the session, e.g. reviewer
+        #[arg(long, value_name = "ID")]
+        actor: Option<String>,
+        /// The command and its arguments, after --
+        #[arg(last = true, required_unless_present = "shell_hook")]
+        command: Vec<String>,
+    },
+    /// Measure retrieval quality
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommands,
+    },
+    /// Record files opened and saved in an editor
+    Editor {
+        #[command(subcommand)]
+        command: EditorCommands,
+    },
     /// Build a prompt pack from a query
     Query {
         /// The query or question
-        query: String,
-        /// Token budget
-        #[arg(long, default_value = "16000")]
-        budget: u32,
+        #[arg(required_unless_present = "replay")]
+        query: Option<String>,
+        /// Token budget [default: 16000, or the model's]
+        #[arg(long)]
+        budget: Option<u32>,
         /// Graph expansion depth
         #[arg(long, default_value = "2")]
         depth: u32,
+        /// Output format (json, text) [default: json, or the model's]
+        #[arg(long)]
+        format: Option<String>,
+        /// Model the pack is for, e.g. gpt-4o: its profile sets the budget, tokenizer and format
+        #[arg(long, value_name = "NAME")]
+        model: Option<String>,
+        /// Exclude narrative content
+        #[arg(long)]
+        no_narrative: bool,
+        /// Most tokens narrative content may use
+        #[arg(long, default_value = "4000")]
+        narrative_budget: u32,
+        /// Return one page and print a continuation cursor
+        #[arg(long)]
+        paged: bool,
+        /// Continue from a cursor printed by a previous paged query
+        #[arg(long)]
+        cursor: Option<String>,
+        /// Only include content written by these authors (human, agent, or a name)
+        #[arg(long)]
+        author: Option<String>,
+        /// Only include files from session steps with this tag, as key:value or key (repeatable)
+        #[arg(long, value_name = "TAG")]
+        tag: Vec<String>,
+        /// Only include files read or written by this sub-agent of a session (repeatable)
+        #[arg(long, value_name = "ID")]
+        actor: Vec<String>,
+        /// Don't rank by recent file usage (for reproducible output)
+        #[arg(long)]
+        no_frecency: bool,
+        /// Don't rank by feedback recorded with `ctx feedback`
+        #[arg(long)]
+        no_feedback: bool,
+        /// Rebuild the pack even if an identical query was cached
+        #[arg(long)]
+        no_cache: bool,
+        /// Emit the pack as NDJSON, one line per part, as it is built
+        #[arg(long, conflicts_with_all = ["paged", "cursor"])]
+        stream: bool,
+        /// Explain why each file was included or dropped
+        #[arg(long, conflicts_with_all = ["paged", "cursor", "stream"])]
+        explain: bool,
+        /// Always include this file, or seed from a node given as Kind::id (repeatable)
+        #[arg(long, value_name = "PATH|KIND::ID")]
+        pin: Vec<String>,
+        /// Never include files matching this gitignore-style pattern (repeatable)
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Expand import cycles and other strongly connected components as units
+        #[arg(long)]
+        scc: bool,
+        /// Query the outermost enclosing repository and every nested one, merged under one budget
+        #[arg(long, conflicts_with_all = ["paged", "cursor", "stream", "explain"])]
+        workspace: bool,
+        /// Only reference files this conversation already received, tracking them in FILE
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["paged", "cursor", "stream", "workspace"])]
+        delta: Option<std::path::PathBuf>,
+        /// Lead with a workspace overview and module graph, each within its own sub-budget
+        #[arg(long, conflicts_with_all = ["paged", "cursor", "stream", "workspace", "delta"])]
+        layered: bool,
+        /// Treat the query as an anchor from a layered pack and drill into that file
+        #[arg(long, conflicts_with_all = ["paged", "cursor", "stream", "workspace", "delta", "layered"])]
+        zoom: bool,
+        /// Only return recorded decisions about the files the query reaches
+        #[arg(long, conflicts_with_all = ["paged", "cursor", "stream", "workspace", "layered", "zoom"])]
+        decisions_only: bool,
+        /// Only follow edges present in a build with these Cargo features (comma-separated)
+        #[arg(long, value_name = "FEATURES", value_delimiter = ',')]
+        features: Option<Vec<String>>,
+        /// Print a pack recorded by an earlier query instead of building one
+        #[arg(long, value_name = "PACK_ID", conflicts_with_all = ["query", "paged", "cursor", "stream", "workspace", "delta", "layered", "zoom"])]
+        replay: Option<String>,
+        /// Build the pack from the code and narrative as of this commit (ID, prefix, ref name, HEAD or HEAD~N)
+        #[arg(long, value_name = "COMMIT", conflicts_with_all = ["paged", "cursor", "stream", "workspace", "delta", "replay"])]
+        at: Option<String>,
+    },
+    /// Map a numbered chunk of a pack built by `ctx query` back to its source
+    ResolveCitation {
+        /// Chunk number, as in "[3]"
+        number: usize,
+        /// Pack ID printed by `ctx query`
+        #[arg(long)]
+        pack: String,
+    },
+    /// Rate the numbered chunks of a pack built by `ctx query`, so later
+    /// packs rank their files higher or lower
+    Feedback {
+        /// Pack ID printed by `ctx query`
+        pack: String,
+        /// Chunks that helped, e.g. 1,3
+        #[arg(long, value_name = "CHUNKS", value_delimiter = ',')]
+        useful: Vec<usize>,
+        /// Chunks that didn't
+        #[arg(long, value_name = "CHUNKS", value_delimiter = ',')]
+        useless: Vec<usize>,
+    },
+    /// Show what changed between two commits
+    Diff {
+        /// Older commit (ID, prefix, ref name, HEAD or HEAD~N)
+        from: String,
+        /// Newer commit
+        #[arg(default_value = "HEAD")]
+        to: String,
+        /// Output format (json, text)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+    /// Show what depends on an item or file: the blast radius of changing it
+    Impact {
+        /// File path, or item, module or package name
+        target: String,
+        /// Hops to follow back from the target
+        #[arg(long, default_value = "3")]
+        depth: u32,
+        /// Edge labels to follow backwards (comma-separated; default: calls,dependson,contains)
+        #[arg(long)]
+        labels: Option<String>,
+        /// Most nodes to reach
+        #[arg(long, default_value = "500")]
+        max_nodes: usize,
         /// Output format (json, text)
         #[arg(long, default_value = "json")]
         format: String,
-        /// Exclude narrative content
+    },
+    /// Read or change repository settings
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Summarize files for packs that run short on budget
+    Summarize {
+        /// File or directory to summarize, relative to the repository root
+        path: String,
+        /// Summarize again even if the current content has a summary
         #[arg(long)]
-        no_narrative: bool,
+        force: bool,
+    },
+    /// Manage the project glossary
+    Glossary {
+        #[command(subcommand)]
+        command: GlossaryCommands,
+    },
+    /// Roll up the narrative
+    Narrative {
+        #[command(subcommand)]
+        command: NarrativeCommands,
     },
     /// Debug and inspection commands
     Debug {
@@ -66,6 +275,28 @@ enum Commands {
         #[command(subcommand)]
         command: AnalyzeCommands,
     },
+    /// Write a commit's files to a directory, or export history
+    #[command(args_conflicts_with_subcommands = true)]
+    Export {
+        #[command(subcommand)]
+        command: Option<ExportCommands>,
+        /// Commit to export (ID, prefix, ref name, HEAD or HEAD~N)
+        commit: Option<String>,
+        /// Destination directory
+        #[arg(long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Read the log of prompt packs built and the chunks they exposed
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Report object store usage by category, blob, and path
+    Du {
+        /// Number of largest blobs and paths to list
+        #[arg(long, default_value = "10")]
+        top: usize,
+    },
     /// Garbage collect unreferenced objects
     Gc {
         /// Show what would be deleted without deleting
@@ -74,6 +305,64 @@ enum Commands {
         /// Skip grace period, delete immediately
         #[arg(long)]
         aggressive: bool,
+        /// Only collect if a gc.auto_* threshold is crossed, without asking
+        #[arg(long, conflicts_with_all = ["dry_run", "aggressive"])]
+        auto: bool,
+    },
+    /// Fold old commits into a snapshot so GC can reclaim them
+    PruneHistory {
+        /// Keep commits made since this time (YYYY-MM-DD, or e.g. 30d, 12w)
+        #[arg(long, value_name = "TIME", required_unless_present = "undo")]
+        keep_since: Option<String>,
+        /// Undo a prune by the archive it reported
+        #[arg(long, value_name = "BACKUP", conflicts_with = "keep_since")]
+        undo: Option<String>,
+    },
+    /// Compact stale sessions, optionally collect garbage, and catch the
+    /// index up, in one unattended run for cron or systemd timers
+    Maintenance {
+        /// Also garbage collect with the configured grace period
+        #[arg(long)]
+        gc: bool,
+        /// Also verify this many objects, continuing where the last run
+        /// stopped
+        #[arg(long, value_name = "OBJECTS")]
+        verify: Option<usize>,
+        /// Report what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Serve prompt packs, status, and Prometheus metrics over local HTTP
+    Serve {
+        /// Port to listen on (0 picks a free port)
+        #[arg(long, default_value = "7879")]
+        port: u16,
+    },
+    /// Summarize HEAD, the active session, index freshness, and storage
+    Status,
+    /// Rate repository health (index, stage, objects, sessions, growth)
+    /// against the `health` thresholds in the config
+    Health,
+    /// List backups of refs and narrative, or restore one
+    Restore {
+        /// List the backups, newest first (the default)
+        #[arg(long, conflicts_with = "apply")]
+        list: bool,
+        /// Replace refs and narrative with those in this backup
+        #[arg(long, value_name = "BACKUP")]
+        apply: Option<String>,
+    },
+    /// Move HEAD and main to an earlier position, e.g. a reflog entry
+    Reset {
+        /// Where to move to (HEAD@{N}, main@{N}, or any commit)
+        #[arg(long, value_name = "REFLOG_ENTRY")]
+        to: String,
+    },
+    /// Remove a repository lock left behind by a crashed process
+    Unlock {
+        /// Remove the lock even if its PID belongs to a running process
+        #[arg(long)]
+        force: bool,
     },
     /// Verify repository integrity
     Verify {
@@ -83,6 +372,10 @@ enum Commands {
         /// Check all (objects + refs + commits)
         #[arg(long)]
         full: bool,
+        /// Check only this percentage of objects, a different share each
+        /// day (implies --objects)
+        #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u32).range(1..=100))]
+        sample: Option<u32>,
     },
 }
 
@@ -97,11 +390,16 @@ enum StageCommands {
     Status,
     /// Flush pending observations to staging
     Flush,
+
     /// Compact session into canonical commit
     Compact {
-        /// Commit message
+        /// Commit message (generated from the session if omitted)
         #[arg(short, long)]
-        message: String,
+        message: Option<String>,
+        /// Report the commit that would be created without writing it or
+        /// ending the session
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Abort current session
     Abort {
@@ -110,7 +408,75 @@ enum StageCommands {
         reason: Option<String>,
     },
     /// Recover session from staging (after crash)
-    Recover,
+    Recover {
+        /// Ask whether to resume, save, or abandon the recovered session
+        #[arg(short, long)]
+        interactive: bool,
+    },
+    /// Save or abandon a session left staged by a process that has exited
+    /// (see session.expired_sessions)
+    Expire,
+    /// Show the conversation recorded for a session
+    Transcript {
+        /// Commit the session was compacted into (default: the active
+        /// session, or else HEAD)
+        commit: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BenchCommands {
+    /// Compare the packs two retrieval configs build for the same queries
+    Retrieval {
+        /// TOML file of retrieval settings for the first config (defaults if omitted)
+        #[arg(long, value_name = "FILE")]
+        a: Option<std::path::PathBuf>,
+        /// TOML file of retrieval settings for the second config
+        #[arg(long, value_name = "FILE")]
+        b: Option<std::path::PathBuf>,
+        /// Expectations file of queries and the files they should retrieve
+        /// (defaults to recent `ctx query` queries, unlabeled)
+        #[arg(long, value_name = "FILE")]
+        queries: Option<std::path::PathBuf>,
+        /// Most recorded queries to run without an expectations file
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum EditorCommands {
+    /// Accept editor events on .ctx/editor.sock and record them in the active session
+    Listen {
+        /// Wait this long after a file's last event before recording it
+        #[arg(long, default_value = "500")]
+        debounce_ms: u64,
+    },
+    /// Report an event to a running listener (for editor plugins and scripts)
+    Notify {
+        /// What happened (opened, saved)
+        event: String,
+        /// The file
+        path: std::path::PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Show the effective value of a key (e.g. gc.grace_period_days)
+    Get {
+        /// Dotted key
+        key: String,
+    },
+    /// Set a key in .ctx/config.toml, keeping comments
+    Set {
+        /// Dotted key
+        key: String,
+        /// New value, parsed as TOML (true, 3, ["a", "b"]) or else a string
+        value: String,
+    },
+    /// List every setting with its effective value
+    List,
 }
 
 #[derive(Subcommand)]
@@ -139,6 +505,15 @@ enum AddCommands {
         #[arg(short, long)]
         note: Option<String>,
     },
+    /// Create a document of a configured type (experiment, incident, meeting, ...),
+    /// or import a Markdown, HTML or text file or URL
+    Doc {
+        /// Document type, or the path or URL of a document to import
+        kind: String,
+        /// Frontmatter field as key=value (repeatable)
+        #[arg(short, long = "field", value_name = "KEY=VALUE")]
+        fields: Vec<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -150,11 +525,37 @@ enum DebugCommands {
     },
     /// List all references (HEAD, STAGE, refs/*)
     Refs,
+    /// Show where a ref has pointed, newest first (entries are <ref>@{N})
+    Reflog {
+        /// Ref to show (HEAD, main, tags/<name>, ...)
+        #[arg(default_value = "HEAD")]
+        ref_name: String,
+        /// Maximum number of entries to show
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
     /// Show commit history from HEAD
     History {
         /// Maximum number of commits to show
         #[arg(short, long)]
         limit: Option<usize>,
+        /// Only show commits that changed this path
+        #[arg(long)]
+        path: Option<String>,
+        /// Only show commits of this type (normal, abandoned, stale, interrupted, untyped)
+        #[arg(long = "type")]
+        commit_type: Option<String>,
+        /// Only show commits since a date (YYYY-MM-DD) or age (e.g. 3d, 12h)
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// List commits that changed a path
+    PathHistory {
+        /// Repository-relative file path
+        path: String,
+        /// Maximum number of commits to show
+        #[arg(short, long)]
+        limit: Option<usize>,
     },
     /// Query the index
     Index {
@@ -172,6 +573,40 @@ enum DebugCommands {
         /// Maximum nodes to include
         #[arg(long, default_value = "100")]
         max_nodes: usize,
+        /// Serve an interactive viewer on localhost instead of printing
+        #[arg(long)]
+        serve: bool,
+        /// Port for --serve (0 picks a free port)
+        #[arg(long, default_value = "7878", requires = "serve")]
+        port: u16,
+    },
+    /// Show edges added and removed between two commits, by label and node kind
+    GraphDiff {
+        /// Older commit (ID, prefix, ref name, HEAD or HEAD~N)
+        from: String,
+        /// Newer commit
+        #[arg(default_value = "HEAD")]
+        to: String,
+    },
+    /// Show where the edges between two nodes came from
+    Edge {
+        /// Source node (Kind::id, or a file path)
+        from: String,
+        /// Target node (Kind::id, or a file path)
+        to: String,
+        /// Only show edges with this label
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Show the shortest chains of edges connecting two nodes
+    Path {
+        /// Start node (Kind::id, or a file path)
+        from: String,
+        /// End node (Kind::id, or a file path)
+        to: String,
+        /// Only follow edges of these labels (comma-separated)
+        #[arg(long)]
+        labels: Option<String>,
     },
     /// Show SCC analysis
     Scc {
@@ -179,11 +614,28 @@ enum DebugCommands {
         #[arg(long)]
         show_members: bool,
     },
+    /// List items nothing calls or references (likely dead code)
+    Orphans,
     /// Show Cargo workspace info
     Cargo {
         #[command(subcommand)]
         command: CargoDebugCommands,
     },
+    /// Show the repository event journal
+    Events {
+        /// Only show events at or after this cursor
+        #[arg(long, default_value = "0")]
+        since: u64,
+        /// Keep waiting for new events
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// List recently recorded prompt packs, newest first
+    Packs {
+        /// Maximum number of packs to show
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+    },
 }
 
 #[derive(Subcommand)]
@@ -195,6 +647,11 @@ enum CargoDebugCommands {
         /// Package name
         package: String,
     },
+    /// Show how a package got into the dependency graph (needs `analyze cargo --full`)
+    Why {
+        /// Package name
+        package: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -225,15 +682,130 @@ enum IndexDebugCommands {
     Stats,
 }
 
+#[derive(Subcommand)]
+enum ExportCommands {
+    /// Export sessions as (task, summary, diff) and (query, pack, answer) records
+    Dataset {
+        /// Output format (jsonl)
+        #[arg(long, default_value = "jsonl")]
+        format: String,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<std::path::PathBuf>,
+        /// Additional regex to redact (repeatable)
+        #[arg(long)]
+        redact: Vec<String>,
+        /// Disable the built-in secret patterns
+        #[arg(long)]
+        no_default_redaction: bool,
+    },
+    /// Export the files and narrative at HEAD as chunked records with their
+    /// edges and last-modified commits, for external indexing
+    Corpus {
+        /// Output format (jsonl)
+        #[arg(long, default_value = "jsonl")]
+        format: String,
+        /// Directory to write corpus.jsonl to
+        #[arg(long)]
+        out: std::path::PathBuf,
+        /// Most lines per file chunk
+        #[arg(long, default_value = "200")]
+        max_chunk_lines: usize,
+        /// Leave out narrative files
+        #[arg(long)]
+        no_narrative: bool,
+        /// Additional regex to redact (repeatable)
+        #[arg(long)]
+        redact: Vec<String>,
+        /// Disable the built-in secret patterns
+        #[arg(long)]
+        no_default_redaction: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Print the retrievals recorded since a date as JSON lines
+    Export {
+        /// Earliest retrieval to include (YYYY-MM-DD, or a duration like 3d)
+        #[arg(long)]
+        since: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum NarrativeCommands {
+    /// Write a digest of a week's sessions, completed tasks and log entries
+    Digest {
+        /// Digest one week (Monday to Sunday, UTC)
+        #[arg(long, required = true)]
+        week: bool,
+        /// A day in the week to digest (YYYY-MM-DD, default: today)
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Search logs, tasks and documents, including deleted ones
+    Search {
+        /// Search terms
+        #[arg(required = true)]
+        terms: Vec<String>,
+        /// Maximum results (default: search.max_results)
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum GlossaryCommands {
+    /// Define a term (replaces any existing definition)
+    Add {
+        /// The term, e.g. "SCC"
+        term: String,
+        /// What the term means in this project
+        definition: String,
+    },
+    /// List defined terms
+    List,
+    /// Remove a term
+    Remove {
+        /// The term to remove
+        term: String,
+    },
+    /// Suggest recurring narrative terms that have no definition
+    Suggest {
+        /// Minimum number of occurrences
+        #[arg(long, default_value_t = ctx_core::DEFAULT_MIN_OCCURRENCES)]
+        min: usize,
+        /// Add every suggestion, using its context line as the definition
+        #[arg(long)]
+        accept: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum AnalyzeCommands {
     /// Analyze Rust code using rust-analyzer
     Rust {
         /// Specific file to analyze (or all if omitted)
         file: Option<std::path::PathBuf>,
+        /// Report what the analysis would record without writing it
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Analyze Cargo workspace metadata
-    Cargo,
+    Cargo {
+        /// Also resolve the full transitive dependency graph, including external crates
+        #[arg(long)]
+        full: bool,
+        /// Report what the analysis would record without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List symbols recorded by the last Rust analysis
+    Symbols {
+        /// Only this file (relative to the repository root)
+        file: Option<String>,
+    },
     /// Check analysis tool availability
     Status,
 }
@@ -250,40 +822,215 @@ fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let json = cli.json || output::json_from_env();
 
-    match cli.command {
-        Commands::Init => commands::init::run(),
+    let result = match cli.command {
+        Commands::Init => commands::init::run(json),
+        Commands::Clone {
+            source,
+            dest,
+            depth,
+        } => commands::clone::run(&source, &dest, depth, json),
         Commands::Add { command } => match command {
-            AddCommands::Note { text } => commands::add::note(&text),
-            AddCommands::Task { title, body } => commands::add::task(&title, body.as_deref()),
+            AddCommands::Note { text } => commands::add::note(&text, json),
+            AddCommands::Task { title, body } => commands::add::task(&title, body.as_deref(), json),
             AddCommands::TaskUpdate { id, status, note } => {
-                commands::add::task_update(id, &status, note.as_deref())
+                commands::add::task_update(id, &status, note.as_deref(), json)
             }
+            AddCommands::Doc { kind, fields } => commands::add::document(&kind, &fields, json),
         },
         Commands::Commit {
             message,
             no_narrative,
-        } => commands::commit::run(&message, no_narrative),
-        Commands::Rebuild => commands::rebuild::run(),
+            dry_run,
+        } => commands::commit::run(&message, no_narrative, dry_run, json),
+        Commands::Annotate { commit, text } => commands::commit::annotate(&commit, &text, json),
+        Commands::Tag {
+            name,
+            commit,
+            force,
+        } => commands::commit::tag(&name, &commit, force, json),
+        Commands::Rebuild => commands::rebuild::run(json),
         Commands::Query {
             query,
             budget,
             depth,
             format,
+            model,
             no_narrative,
-        } => commands::query::run(&query, budget, depth, &format, no_narrative),
+            narrative_budget,
+            paged,
+            cursor,
+            author,
+            tag,
+            actor,
+            no_frecency,
+            no_feedback,
+            no_cache,
+            stream,
+            explain,
+            pin,
+            exclude,
+            scc,
+            workspace,
+            delta,
+            layered,
+            zoom,
+            decisions_only,
+            features,
+            replay,
+            at,
+        } => commands::query::run(commands::query::QueryOptions {
+            query: query.unwrap_or_default(),
+            budget,
+            depth,
+            format: if json {
+                Some("json".to_string())
+            } else {
+                format
+            },
+            model,
+            no_narrative,
+            narrative_budget,
+            paged,
+            cursor,
+            author,
+            tag,
+            actor,
+            no_frecency,
+            no_feedback,
+            no_cache,
+            stream,
+            explain,
+            pin,
+            exclude,
+            scc,
+            workspace,
+            delta,
+            layered,
+            zoom,
+            decisions_only,
+            features,
+            replay,
+            at,
+        }),
+        Commands::ResolveCitation { number, pack } => {
+            commands::query::resolve_citation(number, &pack, json)
+        }
+        Commands::Feedback {
+            pack,
+            useful,
+            useless,
+        } => commands::query::feedback(&pack, &useful, &useless, json),
         Commands::Stage { command } => match command {
-            StageCommands::Start { task } => commands::stage::start(&task),
-            StageCommands::Status => commands::stage::status(),
-            StageCommands::Flush => commands::stage::flush(),
-            StageCommands::Compact { message } => commands::stage::compact(&message),
-            StageCommands::Abort { reason } => commands::stage::abort(reason),
-            StageCommands::Recover => commands::stage::recover(),
+            StageCommands::Start { task } => commands::stage::start(&task, json),
+            StageCommands::Status => commands::stage::status(json),
+            StageCommands::Flush => commands::stage::flush(json),
+            StageCommands::Compact { message, dry_run } => {
+                commands::stage::compact(message, dry_run, json)
+            }
+            StageCommands::Abort { reason } => commands::stage::abort(reason, json),
+            StageCommands::Recover { interactive } => commands::stage::recover(interactive, json),
+            StageCommands::Expire => commands::stage::expire(json),
+            StageCommands::Transcript { commit } => commands::stage::transcript(commit, json),
+        },
+        Commands::Exec {
+            no_run,
+            exit_code,
+            shell_hook,
+            actor,
+            command,
+        } => match (shell_hook, exit_code) {
+            (Some(shell), _) => commands::exec::shell_hook(&shell),
+            (None, Some(exit_code)) if no_run => {
+                commands::exec::record(&command, exit_code, actor.as_deref(), json)
+            }
+            _ => commands::exec::run(&command, actor.as_deref(), json),
+        },
+        Commands::Bench { command } => match command {
+            BenchCommands::Retrieval {
+                a,
+                b,
+                queries,
+                limit,
+            } => commands::bench::retrieval(
+                a.as_deref(),
+                b.as_deref(),
+                queries.as_deref(),
+                limit,
+                json,
+            ),
+        },
+        Commands::Editor { command } => match command {
+            EditorCommands::Listen { debounce_ms } => commands::editor::listen(debounce_ms, json),
+            EditorCommands::Notify { event, path } => commands::editor::notify(&event, &path, json),
+        },
+        Commands::Diff { from, to, format } => {
+            let format = if json { "json" } else { format.as_str() };
+            commands::diff::run(&from, &to, format)
+        }
+        Commands::Impact {
+            target,
+            depth,
+            labels,
+            max_nodes,
+            format,
+        } => {
+            let format = if json { "json" } else { format.as_str() };
+            commands::impact::run(&target, depth, labels.as_deref(), max_nodes, format)
+        }
+        Commands::Config { command } => match command {
+            ConfigCommands::Get { key } => commands::config::get(&key, json),
+            ConfigCommands::Set { key, value } => commands::config::set(&key, &value, json),
+            ConfigCommands::List => commands::config::list(json),
         },
+        Commands::Summarize { path, force } => commands::summarize::run(&path, force, json),
+        Commands::Glossary { command } => match command {
+            GlossaryCommands::Add { term, definition } => {
+                commands::glossary::add(&term, &definition, json)
+            }
+            GlossaryCommands::List => commands::glossary::list(json),
+            GlossaryCommands::Remove { term } => commands::glossary::remove(&term, json),
+            GlossaryCommands::Suggest { min, accept } => {
+                commands::glossary::suggest(min, accept, json)
+            }
+        },
+        Commands::Narrative { command } => match command {
+            NarrativeCommands::Digest { week: _, date } => {
+                commands::narrative::digest(date.as_deref(), json)
+            }
+            NarrativeCommands::Search { terms, limit } => {
+                commands::narrative::search(&terms.join(" "), limit, json)
+            }
+        },
+        // Debug output is for people inspecting internals; it has no stable shape
+        Commands::Debug {
+            command: DebugCommands::Events { since, follow },
+        } => commands::debug::events(since, follow, json),
+        Commands::Debug {
+            command: DebugCommands::Packs { limit },
+        } => commands::debug::packs(limit, json),
+        Commands::Debug { .. } if json => {
+            anyhow::bail!("--json is not supported by debug commands")
+        }
         Commands::Debug { command } => match command {
             DebugCommands::Cat { object_id } => commands::debug::cat(&object_id),
             DebugCommands::Refs => commands::debug::refs(),
-            DebugCommands::History { limit } => commands::debug::history(limit),
+            DebugCommands::Reflog { ref_name, limit } => commands::debug::reflog(&ref_name, limit),
+            DebugCommands::History {
+                limit,
+                path,
+                commit_type,
+                since,
+            } => commands::debug::history(
+                limit,
+                path.as_deref(),
+                commit_type.as_deref(),
+                since.as_deref(),
+            ),
+            DebugCommands::PathHistory { path, limit } => {
+                commands::debug::path_history(&path, limit)
+            }
             DebugCommands::Index { command } => match command {
                 IndexDebugCommands::Path { path } => commands::debug::index_path(&path),
                 IndexDebugCommands::Name { namespace, name } => {
@@ -298,22 +1045,127 @@ fn main() -> Result<()> {
                 format,
                 labels,
                 max_nodes,
-            } => commands::debug::graph(&format, labels.as_deref(), max_nodes),
+                serve,
+                port,
+            } => {
+                if serve {
+                    commands::serve_graph::run(port, labels.as_deref(), max_nodes)
+                } else {
+                    commands::debug::graph(&format, labels.as_deref(), max_nodes)
+                }
+            }
+            DebugCommands::GraphDiff { from, to } => commands::debug::graph_diff(&from, &to),
+            DebugCommands::Edge { from, to, label } => {
+                commands::debug::edge(&from, &to, label.as_deref())
+            }
+            DebugCommands::Path { from, to, labels } => {
+                commands::debug::path(&from, &to, labels.as_deref())
+            }
             DebugCommands::Scc { show_members } => commands::debug::scc(show_members),
+            DebugCommands::Orphans => commands::debug::orphans(),
             DebugCommands::Cargo { command } => match command {
                 CargoDebugCommands::Show => commands::debug::cargo_show(),
                 CargoDebugCommands::Deps { package } => commands::debug::cargo_deps(&package),
+                CargoDebugCommands::Why { package } => commands::debug::cargo_why(&package),
             },
+            DebugCommands::Events { .. } | DebugCommands::Packs { .. } => {
+                unreachable!("handled above")
+            }
         },
         Commands::Analyze { command } => match command {
-            AnalyzeCommands::Rust { file } => commands::analyze::analyze_rust(file.as_deref()),
-            AnalyzeCommands::Cargo => commands::analyze::analyze_cargo(),
-            AnalyzeCommands::Status => commands::analyze::status(),
+            AnalyzeCommands::Rust { file, dry_run } => {
+                commands::analyze::analyze_rust(file.as_deref(), dry_run, json)
+            }
+            AnalyzeCommands::Cargo { full, dry_run } => {
+                commands::analyze::analyze_cargo(full, dry_run, json)
+            }
+            AnalyzeCommands::Symbols { file } => commands::analyze::symbols(file.as_deref(), json),
+            AnalyzeCommands::Status => commands::analyze::status(json),
+        },
+        Commands::Export {
+            command,
+            commit,
+            out,
+        } => match command {
+            Some(ExportCommands::Dataset {
+                format,
+                out,
+                redact,
+                no_default_redaction,
+            }) => commands::export::dataset(
+                &format,
+                out.as_deref(),
+                &redact,
+                no_default_redaction,
+                json,
+            ),
+            Some(ExportCommands::Corpus {
+                format,
+                out,
+                max_chunk_lines,
+                no_narrative,
+                redact,
+                no_default_redaction,
+            }) => commands::export::corpus(
+                &format,
+                &out,
+                max_chunk_lines,
+                no_narrative,
+                &redact,
+                no_default_redaction,
+                json,
+            ),
+            None => commands::export::tree(commit.as_deref(), out.as_deref(), json),
+        },
+        Commands::Audit { command } => match command {
+            AuditCommands::Export { since } => commands::audit::export(&since, json),
         },
+        Commands::Du { top } => commands::du::run(top, json),
+        Commands::Gc { auto: true, .. } => commands::gc::run_auto(json),
         Commands::Gc {
             dry_run,
             aggressive,
-        } => commands::gc::run(dry_run, aggressive),
-        Commands::Verify { objects, full } => commands::verify::run(objects, full),
+            auto: false,
+        } => commands::gc::run(dry_run, aggressive, json),
+        Commands::PruneHistory { keep_since, undo } => match (keep_since, undo) {
+            (_, Some(backup)) => commands::prune::undo(&backup, json),
+            (Some(keep_since), None) => commands::prune::run(&keep_since, json),
+            (None, None) => unreachable!("clap requires --keep-since or --undo"),
+        },
+        Commands::Maintenance {
+            gc,
+            verify,
+            dry_run,
+        } => commands::maintenance::run(gc, verify, dry_run, json),
+        Commands::Serve { port } => commands::serve::run(port),
+        Commands::Status => commands::status::run(json),
+        Commands::Health => commands::health::run(json),
+        Commands::Restore { list: _, apply } => match apply {
+            Some(backup) => commands::restore::apply(&backup, json),
+            None => commands::restore::list(json),
+        },
+        Commands::Reset { to } => commands::reset::run(&to, json),
+        Commands::Unlock { force } => commands::unlock::run(force, json),
+        Commands::Verify {
+            objects,
+            full,
+            sample,
+        } => commands::verify::run(objects, full, sample, json),
+    };
+
+    match result {
+        Err(e) => {
+            if let Some(changed) = progress::cancelled(&e) {
+                if changed {
+                    eprintln!("Cancelled part-way; changes made before stopping were kept.");
+                } else {
+                    eprintln!("Cancelled; the repository was left unchanged.");
+                }
+                std::process::exit(progress::CANCELLED_EXIT_CODE);
+            }
+            errors::report(&e, json);
+            std::process::exit(errors::exit_code(&e));
+        }
+        Ok(()) => Ok(()),
     }
 }
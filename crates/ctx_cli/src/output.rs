@@ -0,0 +1,65 @@
+//! Machine-readable output shared by all commands.
+//!
+//! With `--json` (or `CTX_OUTPUT=json`), each command prints one JSON
+//! document on stdout instead of its human-readable report. Progress and
+//! prompts go to stderr so stdout stays parseable.
+
+use anyhow::{Context, Result};
+use ctx_core::{DryRunReport, ObjectId};
+use serde::Serialize;
+
+/// Environment variable selecting the output format.
+const OUTPUT_ENV: &str = "CTX_OUTPUT";
+
+/// Returns true if `CTX_OUTPUT=json` is set.
+pub fn json_from_env() -> bool {
+    std::env::var(OUTPUT_ENV).is_ok_and(|value| value.eq_ignore_ascii_case("json"))
+}
+
+/// Prints `value` as pretty JSON on stdout.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).context("Failed to serialize to JSON")?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Prints what a `--dry-run` would have changed. As JSON, the command's
+/// usual output is under `result` and the report under `dry_run`.
+pub fn print_dry_run<T: Serialize + ?Sized>(
+    result: &T,
+    report: &DryRunReport,
+    json: bool,
+) -> Result<()> {
+    if json {
+        return print_json(&serde_json::json!({ "result": result, "dry_run": report }));
+    }
+
+    println!("\nDry run, nothing was written. It would:");
+    for commit in &report.commits {
+        println!(
+            "  create commit {} ({} edges in {} batches): {}",
+            &commit.commit_id.as_hex()[..8],
+            commit.edges,
+            commit.edge_batches,
+            commit.message.lines().next().unwrap_or("")
+        );
+    }
+    println!(
+        "  write {} objects ({} bytes)",
+        report.objects.len(),
+        report.object_bytes
+    );
+    for update in &report.refs {
+        let short = |id: Option<ObjectId>| match id {
+            Some(id) => id.as_hex()[..8].to_string(),
+            None => "(none)".to_string(),
+        };
+        println!(
+            "  move {} from {} to {}",
+            update.name,
+            short(update.before),
+            short(update.after)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Progress bars and Ctrl-C handling for long-running commands.
+//!
+//! Commands that take a [`Progress`] show a bar on stderr and install a
+//! Ctrl-C handler that cancels the operation. The operation then stops,
+//! usually before changing refs or the index, and `main` exits with
+//! [`CANCELLED_EXIT_CODE`]. A second Ctrl-C exits immediately.
+
+use ctx_core::{CancellationToken, CtxError, Progress};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// Exit status after Ctrl-C, as shells report a process killed by SIGINT.
+pub const CANCELLED_EXIT_CODE: i32 = 130;
+
+static CANCELLATION: OnceLock<CancellationToken> = OnceLock::new();
+
+/// Returns the token Ctrl-C cancels, installing the handler on first use.
+fn cancellation() -> CancellationToken {
+    CANCELLATION
+        .get_or_init(|| {
+            let token = CancellationToken::new();
+            let handler = token.clone();
+            let installed = ctrlc::set_handler(move || {
+                if handler.is_cancelled() {
+                    std::process::exit(CANCELLED_EXIT_CODE);
+                }
+                handler.cancel();
+            });
+            if let Err(e) = installed {
+                tracing::warn!("Ctrl-C will not cancel cleanly: {}", e);
+            }
+            token
+        })
+        .clone()
+}
+
+/// Returns a [`Progress`] that Ctrl-C cancels, and the bar it drives.
+///
+/// The bar is hidden with `--json` or when stderr is not a terminal; call
+/// `finish_and_clear` on it once the operation returns.
+pub fn progress_bar(json: bool) -> (Progress, ProgressBar) {
+    let pb = if json || !std::io::stderr().is_terminal() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(0)
+    };
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} {msg:20} [{bar:40.cyan/blue}] {pos}/{len}")
+            .unwrap()
+            .progress_chars("█▓▒░  "),
+    );
+
+    let bar = pb.clone();
+    let progress = Progress::new(move |phase: &str, current: u64, total: u64| {
+        bar.set_message(format!("Phase: {}", phase));
+        // Phases that can't count ahead grow with their position
+        bar.set_length(total.max(current));
+        bar.set_position(current);
+    })
+    .with_cancellation(cancellation());
+    (progress, pb)
+}
+
+/// If `err` is an operation stopped by Ctrl-C, returns whether it had
+/// already changed the repository.
+pub fn cancelled(err: &anyhow::Error) -> Option<bool> {
+    err.chain().find_map(|cause| match cause.downcast_ref() {
+        Some(CtxError::Cancelled { changed }) => Some(*changed),
+        _ => None,
+    })
+}
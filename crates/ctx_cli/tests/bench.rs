@@ -0,0 +1,66 @@
+//! End-to-end tests of `ctx bench`, run against the built binary.
+
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// Runs `ctx` with `args` in `dir`.
+fn ctx(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ctx"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("CTX_OUTPUT")
+        .output()
+        .expect("failed to run ctx")
+}
+
+fn init_repo() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    assert!(ctx(tmp.path(), &["init"]).status.success());
+    fs::write(
+        tmp.path().join("queries.toml"),
+        "[[queries]]\nquery = \"where are packs cached\"\nexpect = [\"src/cache.rs\"]\n",
+    )
+    .unwrap();
+    tmp
+}
+
+#[test]
+fn test_bench_retrieval_prints_summary() {
+    let tmp = init_repo();
+
+    let output = ctx(
+        tmp.path(),
+        &["bench", "retrieval", "--queries", "queries.toml"],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Overlap"), "{}", stdout);
+    assert!(stdout.contains("where are packs cached"), "{}", stdout);
+    assert!(stdout.contains("0/1"), "{}", stdout);
+}
+
+#[test]
+fn test_bench_retrieval_json() {
+    let tmp = init_repo();
+
+    for output in [
+        ctx(
+            tmp.path(),
+            &["--json", "bench", "retrieval", "--queries", "queries.toml"],
+        ),
+        Command::new(env!("CARGO_BIN_EXE_ctx"))
+            .args(["bench", "retrieval", "--queries", "queries.toml"])
+            .current_dir(tmp.path())
+            .env("CTX_OUTPUT", "json")
+            .output()
+            .unwrap(),
+    ] {
+        assert!(output.status.success());
+        let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["queries"][0]["query"], "where are packs cached");
+        assert_eq!(report["queries"][0]["a"]["hits"], 0);
+    }
+}
@@ -0,0 +1,257 @@
+//! End-to-end tests of `ctx exec`, run against the built binary.
+
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+/// Runs `ctx` with `args` in `dir`.
+fn ctx(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ctx"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("CTX_OUTPUT")
+        .env_remove("CTX_SESSION_EXEC_TASK")
+        .output()
+        .expect("failed to run ctx")
+}
+
+/// Runs `ctx --json exec -- <command>` and parses its report.
+fn exec_json(dir: &Path, command: &[&str]) -> (Output, Value) {
+    let mut args = vec!["--json", "exec", "--"];
+    args.extend_from_slice(command);
+    let output = ctx(dir, &args);
+    let report = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "invalid report ({}): {}",
+            e,
+            String::from_utf8_lossy(&output.stdout)
+        )
+    });
+    (output, report)
+}
+
+fn init_repo() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    assert!(ctx(tmp.path(), &["init"]).status.success());
+    tmp
+}
+
+fn edit_config(dir: &Path, edit: impl FnOnce(String) -> String) {
+    let path = dir.join(".ctx/config.toml");
+    let config = fs::read_to_string(&path).unwrap();
+    fs::write(&path, edit(config)).unwrap();
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_exec_records_successful_command_in_session() {
+    let tmp = init_repo();
+    assert!(ctx(tmp.path(), &["stage", "start", "fix parser"])
+        .status
+        .success());
+
+    let (output, report) = exec_json(tmp.path(), &["sh", "-c", "echo hello"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(report["command"], "sh -c 'echo hello'");
+    assert_eq!(report["exit_code"], 0);
+    assert_eq!(report["output"], "hello\n");
+    assert!(report["session_id"].is_string());
+    assert!(report["error"].is_null());
+
+    // Without --json the output is echoed instead
+    let output = ctx(tmp.path(), &["exec", "--", "sh", "-c", "echo plain"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "plain\n");
+}
+
+#[test]
+fn test_exec_exits_with_command_status() {
+    let tmp = init_repo();
+    assert!(ctx(tmp.path(), &["stage", "start", "fix parser"])
+        .status
+        .success());
+
+    let (output, report) = exec_json(tmp.path(), &["sh", "-c", "echo broken >&2; exit 3"]);
+    assert_eq!(output.status.code(), Some(3));
+    // A failing command is still recorded, with its output
+    assert_eq!(report["exit_code"], 3);
+    assert_eq!(report["output"], "broken\n");
+    assert!(report["session_id"].is_string());
+
+    // Recording a command that ran elsewhere keeps ctx's own status
+    let output = ctx(
+        tmp.path(),
+        &["exec", "--no-run", "--exit-code", "2", "--", "make test"],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn test_exec_without_session_runs_but_does_not_record() {
+    let tmp = init_repo();
+
+    let output = ctx(tmp.path(), &["exec", "--", "true"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("Not recorded: no active session"));
+
+    let (output, report) = exec_json(tmp.path(), &["true"]);
+    assert!(output.status.success());
+    assert!(report["session_id"].is_null());
+}
+
+#[test]
+fn test_exec_task_config_starts_session() {
+    let tmp = init_repo();
+    edit_config(tmp.path(), |config| {
+        config.replace(
+            "[session]\n",
+            "[session]\nexec_task = \"ad-hoc commands\"\n",
+        )
+    });
+
+    let (output, report) = exec_json(tmp.path(), &["true"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let session_id = report["session_id"].as_str().unwrap().to_string();
+
+    let status = ctx(tmp.path(), &["stage", "status"]);
+    assert!(String::from_utf8_lossy(&status.stdout).contains("ad-hoc commands"));
+
+    // Later commands join the same session
+    let (_, report) = exec_json(tmp.path(), &["true"]);
+    assert_eq!(report["session_id"], session_id.as_str());
+}
+
+#[test]
+fn test_exec_policy_denies_command() {
+    let tmp = init_repo();
+    assert!(ctx(tmp.path(), &["stage", "start", "fix parser"])
+        .status
+        .success());
+    edit_config(tmp.path(), |config| {
+        config + "\n[exec]\ndeny = [\"sh *\"]\n"
+    });
+
+    let marker = tmp.path().join("ran");
+    let output = ctx(
+        tmp.path(),
+        &[
+            "exec",
+            "--",
+            "sh",
+            "-c",
+            &format!("touch {}", marker.display()),
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("denied"), "{}", stderr(&output));
+    assert!(!marker.exists());
+
+    // Commands outside the deny list still run
+    assert!(ctx(tmp.path(), &["exec", "--", "true"]).status.success());
+}
+
+#[test]
+fn test_exec_confirm_mode_refuses_without_terminal() {
+    let tmp = init_repo();
+    edit_config(tmp.path(), |config| {
+        config + "\n[exec]\nmode = \"confirm\"\n"
+    });
+
+    let marker = tmp.path().join("ran");
+    let output = Command::new(env!("CARGO_BIN_EXE_ctx"))
+        .args(["exec", "--", "touch"])
+        .arg(&marker)
+        .current_dir(tmp.path())
+        .env_remove("CTX_OUTPUT")
+        .env_remove("CTX_SESSION_EXEC_TASK")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    // The prompt is installed, and refuses since nobody can answer it
+    assert!(
+        stderr(&output).contains("declined at confirmation prompt"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!marker.exists());
+}
+
+#[test]
+fn test_exec_confirm_mode_asks_on_terminal() {
+    // `script` runs ctx with a terminal on stdin; skip where it's missing
+    if Command::new("script").arg("--version").output().is_err() {
+        return;
+    }
+    let tmp = init_repo();
+    edit_config(tmp.path(), |config| {
+        config + "\n[exec]\nmode = \"confirm\"\n"
+    });
+
+    let run = |answer: &str, marker: &str| {
+        let mut child = Command::new("script")
+            .args(["-qec"])
+            .arg(format!(
+                "{} exec -- touch {}",
+                env!("CARGO_BIN_EXE_ctx"),
+                marker
+            ))
+            .arg("/dev/null")
+            .current_dir(tmp.path())
+            .env_remove("CTX_OUTPUT")
+            .env_remove("CTX_SESSION_EXEC_TASK")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(answer.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let transcript = run("n\n", "declined");
+    assert!(
+        transcript.contains("Run `touch declined`?"),
+        "{}",
+        transcript
+    );
+    assert!(!tmp.path().join("declined").exists());
+
+    let transcript = run("y\n", "accepted");
+    assert!(tmp.path().join("accepted").exists(), "{}", transcript);
+}
+
+#[test]
+fn test_exec_policy_denies_wrapped_command() {
+    let tmp = init_repo();
+    edit_config(tmp.path(), |config| {
+        config + "\n[exec]\ndeny = [\"touch\"]\n"
+    });
+
+    let marker = tmp.path().join("ran");
+    let marker = marker.to_str().unwrap();
+    for command in [
+        &["touch", marker][..],
+        &["/usr/bin/env", "touch", marker],
+        &["env", "FOO=1", "nice", "-n", "5", "touch", marker],
+    ] {
+        let mut args = vec!["exec", "--"];
+        args.extend_from_slice(command);
+        let output = ctx(tmp.path(), &args);
+        assert!(!output.status.success(), "{:?} ran", command);
+        assert!(stderr(&output).contains("denied"), "{}", stderr(&output));
+    }
+    assert!(!Path::new(marker).exists());
+}
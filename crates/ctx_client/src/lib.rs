@@ -0,0 +1,405 @@
+//! Editor integration for CTX.
+//!
+//! `ctx editor listen` accepts file activity from editors on a Unix socket
+//! at `.ctx/editor.sock` and records it in the active session, so files a
+//! person opens and saves inform retrieval the same way agent reads and
+//! writes do.
+//!
+//! The protocol is one JSON object per line. A client sends
+//!
+//! ```json
+//! {"event": "opened", "path": "/abs/or/repo-relative/path.rs"}
+//! {"event": "saved", "path": "src/lib.rs"}
+//! ```
+//!
+//! and the listener answers each line with `{"ok": true}` or
+//! `{"ok": false, "error": "..."}`. Events are acknowledged when queued;
+//! the listener records them once the file has been quiet for a moment.
+//!
+//! This crate has no dependency on the repository itself, so editor
+//! plugins can link it cheaply. [`EditorClient`] speaks the protocol;
+//! [`normalize_path`] and [`Debouncer`] are what the listener uses to turn
+//! raw events into observations.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Name of the socket inside the `.ctx` directory.
+pub const SOCKET_FILE: &str = "editor.sock";
+
+/// Name of the repository metadata directory.
+const CTX_DIR: &str = ".ctx";
+
+/// Returns the socket path for the repository metadata directory `ctx_dir`.
+pub fn socket_path(ctx_dir: &Path) -> PathBuf {
+    ctx_dir.join(SOCKET_FILE)
+}
+
+/// What happened to a file in the editor.
+///
+/// Ordered by strength: a save implies the file was read, so a save and an
+/// open of the same file coalesce into a save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// The file was opened or focused.
+    Opened,
+    /// The file was written to disk.
+    Saved,
+}
+
+/// One line sent by an editor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditorEvent {
+    /// What happened.
+    pub event: EventKind,
+    /// The file, absolute or relative to the repository root.
+    pub path: String,
+}
+
+/// The listener's answer to one event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reply {
+    /// True if the event was queued.
+    pub ok: bool,
+    /// Why the event was refused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reply {
+    /// An acknowledgement.
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+        }
+    }
+
+    /// A refusal with the reason.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(message.into()),
+        }
+    }
+}
+
+/// Converts an editor path into a repository-relative path with `/`
+/// separators.
+///
+/// Relative paths are taken relative to `root`. Returns `None` for paths
+/// outside the repository, for the root itself, and for anything inside
+/// `.ctx`.
+pub fn normalize_path(root: &Path, path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        root.join(path)
+    };
+
+    // Resolve symlinks where possible (e.g. /tmp on macOS) so both sides
+    // agree, falling back to lexical cleanup for files that don't exist
+    let root = root.canonicalize().unwrap_or_else(|_| clean(root));
+    let path = path.canonicalize().unwrap_or_else(|_| clean(&path));
+
+    let relative = path.strip_prefix(&root).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    if parts.is_empty() || parts[0] == CTX_DIR {
+        return None;
+    }
+    Some(parts.join("/"))
+}
+
+/// Removes `.` and resolves `..` without touching the filesystem.
+fn clean(path: &Path) -> PathBuf {
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                cleaned.pop();
+            }
+            other => cleaned.push(other),
+        }
+    }
+    cleaned
+}
+
+/// Coalesces bursts of events per file.
+///
+/// Editors with autosave or format-on-save can report the same file many
+/// times a second. An event becomes due once its file has been quiet for
+/// the quiet period, or once it has waited ten quiet periods, so a file
+/// that never stops changing is still recorded.
+#[derive(Debug)]
+pub struct Debouncer {
+    quiet: Duration,
+    pending: BTreeMap<String, Pending>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    kind: EventKind,
+    first: Instant,
+    last: Instant,
+}
+
+impl Pending {
+    fn due_at(&self, quiet: Duration) -> Instant {
+        (self.last + quiet).min(self.first + quiet * 10)
+    }
+}
+
+impl Debouncer {
+    /// Creates a debouncer with the given quiet period.
+    pub fn new(quiet: Duration) -> Self {
+        Self {
+            quiet,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Queues an event for `path`, merging it with any pending one.
+    pub fn push(&mut self, kind: EventKind, path: String, now: Instant) {
+        self.pending
+            .entry(path)
+            .and_modify(|pending| {
+                pending.kind = pending.kind.max(kind);
+                pending.last = now;
+            })
+            .or_insert(Pending {
+                kind,
+                first: now,
+                last: now,
+            });
+    }
+
+    /// Removes and returns the events due at `now`, ordered by path.
+    pub fn due(&mut self, now: Instant) -> Vec<(EventKind, String)> {
+        let quiet = self.quiet;
+        let due: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.due_at(quiet) <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|path| self.pending.remove(&path).map(|p| (p.kind, path)))
+            .collect()
+    }
+
+    /// Removes and returns every pending event, ordered by path.
+    pub fn drain(&mut self) -> Vec<(EventKind, String)> {
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(path, pending)| (pending.kind, path))
+            .collect()
+    }
+
+    /// When the next event becomes due, if any are pending.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|pending| pending.due_at(self.quiet))
+            .min()
+    }
+
+    /// Returns true if no events are pending.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// A connection to `ctx editor listen`.
+#[cfg(unix)]
+pub struct EditorClient {
+    writer: std::os::unix::net::UnixStream,
+    reader: io::BufReader<std::os::unix::net::UnixStream>,
+}
+
+#[cfg(unix)]
+impl EditorClient {
+    /// Connects to the listener socket at `socket`.
+    pub fn connect(socket: impl AsRef<Path>) -> io::Result<Self> {
+        let writer = std::os::unix::net::UnixStream::connect(socket)?;
+        let reader = io::BufReader::new(writer.try_clone()?);
+        Ok(Self { writer, reader })
+    }
+
+    /// Connects to the listener of the repository containing `path`,
+    /// searching its ancestors for a `.ctx` directory.
+    pub fn for_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = absolute(path.as_ref())?;
+        let socket = path
+            .ancestors()
+            .map(|dir| dir.join(CTX_DIR))
+            .find(|ctx_dir| ctx_dir.is_dir())
+            .map(|ctx_dir| socket_path(&ctx_dir))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in a CTX repository", path.display()),
+                )
+            })?;
+        Self::connect(socket)
+    }
+
+    /// Reports that `path` was opened.
+    pub fn opened(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.send(EventKind::Opened, path.as_ref())
+    }
+
+    /// Reports that `path` was saved.
+    pub fn saved(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.send(EventKind::Saved, path.as_ref())
+    }
+
+    /// Sends one event and waits for the listener's reply.
+    ///
+    /// Relative paths are resolved against the current directory, since
+    /// the listener's working directory may differ.
+    pub fn send(&mut self, kind: EventKind, path: &Path) -> io::Result<()> {
+        use std::io::{BufRead, Write};
+
+        let path = absolute(path)?;
+        let event = EditorEvent {
+            event: kind,
+            path: path.to_string_lossy().into_owned(),
+        };
+        let mut line = serde_json::to_string(&event).map_err(io::Error::other)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        let mut reply = String::new();
+        if self.reader.read_line(&mut reply)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "listener closed the connection",
+            ));
+        }
+        let reply: Reply = serde_json::from_str(&reply).map_err(io::Error::other)?;
+        match reply {
+            Reply { ok: true, .. } => Ok(()),
+            Reply { error, .. } => Err(io::Error::other(
+                error.unwrap_or_else(|| "event refused".to_string()),
+            )),
+        }
+    }
+}
+
+/// Makes `path` absolute against the current directory.
+#[cfg(unix)]
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_normalize_path() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+
+        let absolute = root.join("src/lib.rs");
+        assert_eq!(
+            normalize_path(root, absolute.to_str().unwrap()).as_deref(),
+            Some("src/lib.rs")
+        );
+        assert_eq!(
+            normalize_path(root, "src/../src/./new.rs").as_deref(),
+            Some("src/new.rs")
+        );
+        assert_eq!(normalize_path(root, "../outside.rs"), None);
+        assert_eq!(normalize_path(root, ".ctx/HEAD"), None);
+        assert_eq!(normalize_path(root, "."), None);
+    }
+
+    #[test]
+    fn test_debouncer_coalesces_bursts() {
+        let quiet = Duration::from_millis(100);
+        let mut debouncer = Debouncer::new(quiet);
+        let start = Instant::now();
+
+        debouncer.push(EventKind::Opened, "a.rs".into(), start);
+        debouncer.push(EventKind::Saved, "a.rs".into(), start + quiet / 2);
+        debouncer.push(EventKind::Opened, "a.rs".into(), start + quiet);
+        debouncer.push(EventKind::Opened, "b.rs".into(), start);
+
+        // b.rs has been quiet long enough; a.rs was touched again
+        assert_eq!(
+            debouncer.due(start + quiet),
+            vec![(EventKind::Opened, "b.rs".to_string())]
+        );
+        assert_eq!(debouncer.next_due(), Some(start + quiet * 2));
+        assert_eq!(
+            debouncer.due(start + quiet * 2),
+            vec![(EventKind::Saved, "a.rs".to_string())]
+        );
+        assert!(debouncer.is_empty());
+
+        // A file that keeps changing is still flushed eventually
+        for i in 0..20 {
+            debouncer.push(EventKind::Saved, "c.rs".into(), start + quiet / 2 * i);
+        }
+        assert_eq!(debouncer.due(start + quiet * 10).len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_client_round_trip() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixListener;
+
+        let tmp = TempDir::new().unwrap();
+        let ctx_dir = tmp.path().join(CTX_DIR);
+        std::fs::create_dir(&ctx_dir).unwrap();
+        let listener = UnixListener::bind(socket_path(&ctx_dir)).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut events = Vec::new();
+            for line in BufReader::new(stream).lines() {
+                let event: EditorEvent = serde_json::from_str(&line.unwrap()).unwrap();
+                let reply = if event.path.ends_with("bad.rs") {
+                    Reply::error("nope")
+                } else {
+                    Reply::ok()
+                };
+                writeln!(writer, "{}", serde_json::to_string(&reply).unwrap()).unwrap();
+                events.push(event);
+            }
+            events
+        });
+
+        let file = tmp.path().join("src/main.rs");
+        let mut client = EditorClient::for_path(&file).unwrap();
+        client.opened(&file).unwrap();
+        client.saved(&file).unwrap();
+        let err = client.saved(tmp.path().join("bad.rs")).unwrap_err();
+        assert_eq!(err.to_string(), "nope");
+        drop(client);
+
+        let events = server.join().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].event, EventKind::Saved);
+        assert_eq!(events[1].path, file.to_string_lossy());
+    }
+}
@@ -0,0 +1,533 @@
+//! Async facade for tokio-based agents.
+//!
+//! Every repository operation touches the filesystem, and observations
+//! fsync the objects they write. Calling them from an async task stalls the
+//! executor thread, so [`AsyncCtxRepo`] and [`AsyncObjectStore`] run them on
+//! tokio's blocking pool instead.
+//!
+//! Agents that record many small observations can use an
+//! [`ObservationQueue`]: sends only wait for channel capacity, and a
+//! background task applies queued observations in batches, flushing the
+//! session once per batch rather than once per call.
+//!
+//! Enabled by the `tokio` feature.
+//!
+//! ```no_run
+//! use ctx_core::{AsyncCtxRepo, QueuedObservation, RetrievalConfig};
+//!
+//! # async fn run() -> ctx_core::Result<()> {
+//! let repo = AsyncCtxRepo::open(".").await?;
+//! repo.start_session("fix token refresh bug").await?;
+//!
+//! let queue = repo.observation_queue(64);
+//! queue.send(QueuedObservation::Read { path: "src/auth.rs".into() }).await?;
+//! queue.flush().await?;
+//!
+//! let pack = repo.build_pack("token refresh", RetrievalConfig::default()).await?;
+//! println!("{}", pack.to_text());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{CtxError, Result};
+use crate::pack::{PromptPack, RetrievalConfig};
+use crate::status::StatusReport;
+use crate::{CtxRepo, ObjectId, ObjectStore, Session};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+/// Most observations applied in one blocking call.
+const MAX_BATCH: usize = 256;
+
+/// Runs `f` on the blocking pool, re-raising its panics.
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(CtxError::Io(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            format!("blocking task cancelled: {}", e),
+        ))),
+    }
+}
+
+/// An [`ObjectStore`] whose reads and writes run on the blocking pool.
+///
+/// Cloning is cheap; clones share the store.
+#[derive(Clone)]
+pub struct AsyncObjectStore {
+    inner: Arc<ObjectStore>,
+}
+
+impl AsyncObjectStore {
+    /// Creates a store rooted at `root`, like [`ObjectStore::new`].
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self::from_store(ObjectStore::new(root))
+    }
+
+    fn from_store(store: ObjectStore) -> Self {
+        Self {
+            inner: Arc::new(store),
+        }
+    }
+
+    /// The synchronous store, for calls that don't block.
+    pub fn store(&self) -> &ObjectStore {
+        &self.inner
+    }
+
+    /// Stores a blob. See [`ObjectStore::put_blob`].
+    pub async fn put_blob(&self, data: Vec<u8>) -> Result<ObjectId> {
+        let store = Arc::clone(&self.inner);
+        blocking(move || store.put_blob(&data)).await
+    }
+
+    /// Reads a blob. See [`ObjectStore::get_blob`].
+    pub async fn get_blob(&self, id: ObjectId) -> Result<Vec<u8>> {
+        let store = Arc::clone(&self.inner);
+        blocking(move || store.get_blob(id)).await
+    }
+
+    /// Stores a typed object. See [`ObjectStore::put_typed`].
+    pub async fn put_typed<T>(&self, value: T) -> Result<ObjectId>
+    where
+        T: Serialize + Send + 'static,
+    {
+        let store = Arc::clone(&self.inner);
+        blocking(move || store.put_typed(&value)).await
+    }
+
+    /// Reads a typed object. See [`ObjectStore::get_typed`].
+    pub async fn get_typed<T>(&self, id: ObjectId) -> Result<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let store = Arc::clone(&self.inner);
+        blocking(move || store.get_typed(id)).await
+    }
+
+    /// Whether an object exists. See [`ObjectStore::exists`].
+    pub async fn exists(&self, id: ObjectId) -> Result<bool> {
+        let store = Arc::clone(&self.inner);
+        blocking(move || Ok(store.exists(id))).await
+    }
+}
+
+/// A [`CtxRepo`] whose operations run on the blocking pool.
+///
+/// Cloning is cheap; clones share the repository. Pack builds share it for
+/// reading and run concurrently; observations and session changes take it
+/// exclusively.
+#[derive(Clone)]
+pub struct AsyncCtxRepo {
+    inner: Arc<RwLock<CtxRepo>>,
+    root: PathBuf,
+    objects: AsyncObjectStore,
+}
+
+impl AsyncCtxRepo {
+    /// Wraps an open repository.
+    pub fn new(repo: CtxRepo) -> Self {
+        Self {
+            root: repo.root().to_path_buf(),
+            objects: AsyncObjectStore::from_store(repo.object_store().share()),
+            inner: Arc::new(RwLock::new(repo)),
+        }
+    }
+
+    /// Opens an existing repository. See [`CtxRepo::open`].
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        blocking(move || CtxRepo::open(path)).await.map(Self::new)
+    }
+
+    /// Initializes a new repository. See [`CtxRepo::init`].
+    pub async fn init(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        blocking(move || CtxRepo::init(path)).await.map(Self::new)
+    }
+
+    /// Repository root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The repository's object store, with async access. Like the
+    /// repository's own, it writes through to the configured remote.
+    pub fn object_store(&self) -> AsyncObjectStore {
+        self.objects.clone()
+    }
+
+    /// Runs `f` with shared access to the repository on the blocking pool.
+    pub async fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&CtxRepo) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = Arc::clone(&self.inner);
+        blocking(move || f(&inner.read().unwrap_or_else(|e| e.into_inner()))).await
+    }
+
+    /// Runs `f` with exclusive access to the repository on the blocking pool.
+    pub async fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut CtxRepo) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = Arc::clone(&self.inner);
+        blocking(move || f(&mut inner.write().unwrap_or_else(|e| e.into_inner()))).await
+    }
+
+    /// Starts a session and returns its ID. See [`CtxRepo::start_session`].
+    pub async fn start_session(&self, task: impl Into<String>) -> Result<String> {
+        let task = task.into();
+        self.write(move |repo| Ok(repo.start_session(&task)?.session_id().to_string()))
+            .await
+    }
+
+    /// Records a file read. See [`CtxRepo::observe_file_read`].
+    pub async fn observe_file_read(&self, path: impl Into<String>) -> Result<()> {
+        self.observe(QueuedObservation::Read { path: path.into() })
+            .await
+    }
+
+    /// Records a file write. See [`CtxRepo::observe_file_write`].
+    pub async fn observe_file_write(
+        &self,
+        path: impl Into<String>,
+        content: Vec<u8>,
+    ) -> Result<()> {
+        self.observe(QueuedObservation::Write {
+            path: path.into(),
+            content,
+        })
+        .await
+    }
+
+    /// Records a note. See [`CtxRepo::observe_note`].
+    pub async fn observe_note(&self, note: impl Into<String>) -> Result<()> {
+        self.observe(QueuedObservation::Note { note: note.into() })
+            .await
+    }
+
+    /// Records one observation without flushing.
+    pub async fn observe(&self, observation: QueuedObservation) -> Result<()> {
+        self.write(move |repo| observation.apply(repo)).await
+    }
+
+    /// Flushes the session's current step. See
+    /// [`CtxRepo::flush_active_session`].
+    pub async fn flush(&self) -> Result<ObjectId> {
+        self.write(|repo| repo.flush_active_session()).await
+    }
+
+    /// Compacts the session into a commit. See [`CtxRepo::compact_session`].
+    pub async fn compact_session(&self, message: impl Into<String>) -> Result<ObjectId> {
+        let message = message.into();
+        self.write(move |repo| repo.compact_session(&message)).await
+    }
+
+    /// Builds a prompt pack. See [`CtxRepo::build_pack`].
+    pub async fn build_pack(
+        &self,
+        query: impl Into<String>,
+        config: RetrievalConfig,
+    ) -> Result<PromptPack> {
+        let query = query.into();
+        self.read(move |repo| repo.build_pack(&query, &config))
+            .await
+    }
+
+    /// Summarizes repository state. See [`CtxRepo::status`].
+    pub async fn status(&self) -> Result<StatusReport> {
+        self.write(|repo| repo.status()).await
+    }
+
+    /// Starts a background task that applies observations in batches.
+    ///
+    /// `capacity` bounds how many observations may wait; sends beyond it
+    /// wait for the batch in progress. Must be called within a tokio
+    /// runtime. The task stops, after applying what's queued, once every
+    /// clone of the queue is dropped.
+    pub fn observation_queue(&self, capacity: usize) -> ObservationQueue {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        tokio::spawn(drain_queue(self.clone(), receiver));
+        ObservationQueue { sender }
+    }
+}
+
+/// An observation recorded through an [`ObservationQueue`] or
+/// [`AsyncCtxRepo::observe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueuedObservation {
+    /// A file read, without its content.
+    Read {
+        /// File path.
+        path: String,
+    },
+    /// A file read, keeping the content the agent saw.
+    ReadWithContent {
+        /// File path.
+        path: String,
+        /// Content read.
+        content: Vec<u8>,
+    },
+    /// A file write.
+    Write {
+        /// File path.
+        path: String,
+        /// New content.
+        content: Vec<u8>,
+    },
+    /// A command run.
+    Command {
+        /// Command line.
+        command: String,
+        /// Exit code, if it finished.
+        exit_code: Option<i32>,
+        /// Captured output.
+        output: Option<Vec<u8>>,
+    },
+    /// A note.
+    Note {
+        /// Note text.
+        note: String,
+    },
+}
+
+impl QueuedObservation {
+    fn apply(self, repo: &mut CtxRepo) -> Result<()> {
+        match self {
+            QueuedObservation::Read { path } => repo.observe_file_read(&path),
+            QueuedObservation::ReadWithContent { path, content } => {
+                repo.observe_file_read_with_content(&path, &content)
+            }
+            QueuedObservation::Write { path, content } => {
+                repo.observe_file_write(&path, &content).map(|_| ())
+            }
+            QueuedObservation::Command {
+                command,
+                exit_code,
+                output,
+            } => repo.observe_command(&command, exit_code, output.as_deref()),
+            QueuedObservation::Note { note } => repo.observe_note(&note),
+        }
+    }
+}
+
+enum QueueMessage {
+    Observe(QueuedObservation),
+    Flush(oneshot::Sender<Result<()>>),
+}
+
+/// Sends observations to a background task that applies them in batches.
+///
+/// Created by [`AsyncCtxRepo::observation_queue`]. Errors from queued
+/// observations are logged, and the first one since the last flush is
+/// returned by [`ObservationQueue::flush`].
+#[derive(Clone)]
+pub struct ObservationQueue {
+    sender: mpsc::Sender<QueueMessage>,
+}
+
+impl ObservationQueue {
+    /// Queues an observation, waiting only if the queue is full.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the background task has stopped.
+    pub async fn send(&self, observation: QueuedObservation) -> Result<()> {
+        self.sender
+            .send(QueueMessage::Observe(observation))
+            .await
+            .map_err(|_| queue_closed())
+    }
+
+    /// Waits until everything queued so far is applied and flushed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error since the last flush, or an error if the
+    /// background task has stopped.
+    pub async fn flush(&self) -> Result<()> {
+        let (reply, done) = oneshot::channel();
+        self.sender
+            .send(QueueMessage::Flush(reply))
+            .await
+            .map_err(|_| queue_closed())?;
+        done.await.map_err(|_| queue_closed())?
+    }
+}
+
+fn queue_closed() -> CtxError {
+    CtxError::Io(std::io::Error::new(
+        std::io::ErrorKind::BrokenPipe,
+        "observation queue stopped",
+    ))
+}
+
+/// Applies queued observations until every sender is dropped.
+async fn drain_queue(repo: AsyncCtxRepo, mut receiver: mpsc::Receiver<QueueMessage>) {
+    let mut first_error: Option<CtxError> = None;
+    while let Some(message) = receiver.recv().await {
+        // Take whatever else is already waiting, up to a batch
+        let mut batch = vec![message];
+        while batch.len() < MAX_BATCH {
+            match receiver.try_recv() {
+                Ok(message) => batch.push(message),
+                Err(_) => break,
+            }
+        }
+
+        let mut observations = Vec::new();
+        let mut replies = Vec::new();
+        for message in batch {
+            match message {
+                QueueMessage::Observe(observation) => observations.push(observation),
+                QueueMessage::Flush(reply) => replies.push(reply),
+            }
+        }
+
+        let result = repo
+            .write(move |repo| {
+                let mut errors = Vec::new();
+                let applied = observations.len();
+                for observation in observations {
+                    if let Err(e) = observation.apply(repo) {
+                        errors.push(e);
+                    }
+                }
+                if applied > errors.len()
+                    && repo
+                        .active_session()
+                        .is_some_and(Session::has_pending_observations)
+                {
+                    repo.flush_active_session()?;
+                }
+                Ok(errors)
+            })
+            .await;
+        let errors = match result {
+            Ok(errors) => errors,
+            Err(e) => vec![e],
+        };
+        for e in errors {
+            warn!(error = %e, "Failed to apply queued observation");
+            first_error.get_or_insert(e);
+        }
+
+        for reply in replies {
+            let result = match first_error.take() {
+                Some(e) => Err(e),
+                None => Ok(()),
+            };
+            let _ = reply.send(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FsBackend, ObjectBackend};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_async_repo_round_trip() {
+        let tmp = TempDir::new().unwrap();
+        let repo = AsyncCtxRepo::init(tmp.path()).await.unwrap();
+        repo.start_session("Add files").await.unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}".to_vec())
+            .await
+            .unwrap();
+        repo.flush().await.unwrap();
+        repo.compact_session("Added files").await.unwrap();
+
+        let pack = repo
+            .build_pack("src/a.rs", RetrievalConfig::default())
+            .await
+            .unwrap();
+        assert!(pack.retrieved.iter().any(|c| c.title == "src/a.rs"));
+
+        let store = repo.object_store();
+        let id = store.put_blob(b"hello".to_vec()).await.unwrap();
+        assert!(store.exists(id).await.unwrap());
+        assert_eq!(store.get_blob(id).await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_object_store_uses_configured_remote() {
+        let tmp = TempDir::new().unwrap();
+        let remote = tmp.path().join("remote");
+        CtxRepo::init(tmp.path().join("repo")).unwrap();
+        let config = tmp.path().join("repo/.ctx/config.toml");
+        let mut text = std::fs::read_to_string(&config).unwrap();
+        text.push_str(&format!(
+            "\n[storage]\nremote = \"file://{}\"\n",
+            remote.display()
+        ));
+        std::fs::write(&config, text).unwrap();
+
+        let repo = AsyncCtxRepo::open(tmp.path().join("repo")).await.unwrap();
+        let id = repo
+            .object_store()
+            .put_blob(b"shared".to_vec())
+            .await
+            .unwrap();
+        assert!(FsBackend::new(&remote).exists(id).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_observation_queue_batches_and_reports_errors() {
+        let tmp = TempDir::new().unwrap();
+        let repo = AsyncCtxRepo::init(tmp.path()).await.unwrap();
+        let queue = repo.observation_queue(8);
+
+        // Without a session every observation fails
+        queue
+            .send(QueuedObservation::Note {
+                note: "too early".into(),
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            queue.flush().await,
+            Err(CtxError::NoActiveSession)
+        ));
+
+        repo.start_session("Queue work").await.unwrap();
+        for i in 0..20 {
+            queue
+                .send(QueuedObservation::Write {
+                    path: format!("src/f{}.rs", i),
+                    content: format!("pub fn f{}() {{}}", i).into_bytes(),
+                })
+                .await
+                .unwrap();
+        }
+        queue.flush().await.unwrap();
+
+        let steps = repo
+            .read(|repo| Ok(repo.active_session().unwrap().step_count()))
+            .await
+            .unwrap();
+        // The session start plus at least one batch, but fewer than one
+        // flush per observation
+        assert!((2..21).contains(&steps), "steps: {}", steps);
+
+        repo.compact_session("Queued files").await.unwrap();
+        let paths = repo
+            .read(|repo| repo.index()?.indexed_paths())
+            .await
+            .unwrap();
+        assert!((0..20).all(|i| paths.contains_key(&format!("src/f{}.rs", i))));
+    }
+}
@@ -0,0 +1,299 @@
+//! Audit log of retrievals.
+//!
+//! Every prompt pack a repository builds appends one JSON line to
+//! `.ctx/logs/retrievals.jsonl`: who asked, what they asked, and which
+//! chunks the pack handed over. Teams read it back with
+//! [`crate::CtxRepo::retrieval_records`] (or `ctx audit export`) to find
+//! out what code was exposed to which model.
+//!
+//! Packs built in a dry run are recorded too, and a pack whose record
+//! can't be written is not handed over.
+//!
+//! The log is append-only; nothing in ctx rewrites or prunes it. With
+//! `redact_queries` set the query text is left out, and only its hash is
+//! kept so repeated queries can still be told apart. The hash is keyed with
+//! a random per-repository key in `.ctx/audit.key`, so a leaked log can't be
+//! checked against guessed queries without the key.
+
+use crate::error::{CtxError, Result};
+use crate::object_id::ObjectId;
+use crate::pack::RetrievedChunk;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Path of the audit log inside `.ctx`.
+pub(crate) const AUDIT_FILE: &str = "logs/retrievals.jsonl";
+
+/// Path of the key queries are hashed with, inside `.ctx`.
+pub(crate) const AUDIT_KEY_FILE: &str = "audit.key";
+
+/// Distinguishes the temp files of concurrent creators of the key.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Retrieval audit configuration (the `[audit]` section).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Record every pack built in `.ctx/logs/retrievals.jsonl`
+    /// (default: true).
+    pub retrievals: bool,
+
+    /// Leave the query text out of the log, keeping only its keyed hash
+    /// (default: false).
+    pub redact_queries: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            retrievals: true,
+            redact_queries: false,
+        }
+    }
+}
+
+/// One pack build recorded in the audit log. Object IDs are hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetrievalRecord {
+    /// When the pack was built (Unix seconds).
+    pub timestamp_unix: u64,
+    /// Identity of the handle that built it, if any.
+    pub actor: Option<String>,
+    /// How the pack was built: `pack`, `federated`, `delta`, `layered`,
+    /// `zoom`, `cached`, `paged` or `streaming`.
+    pub kind: String,
+    /// The query, or the anchor for a zoom pack. None when redacted.
+    pub query: Option<String>,
+    /// BLAKE3 hash of the query, keyed with the repository's audit key.
+    pub query_hash: String,
+    /// Commit the pack was built from.
+    pub head_commit: String,
+    /// Chunks handed over, in pack order.
+    pub chunks: Vec<AuditedChunk>,
+}
+
+/// A chunk recorded in a [`RetrievalRecord`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditedChunk {
+    /// Object the chunk was taken from.
+    pub object_id: String,
+    /// The chunk's title, usually a path and line range.
+    pub title: String,
+}
+
+impl AuditedChunk {
+    pub(crate) fn new(chunk: &RetrievedChunk) -> Self {
+        Self {
+            object_id: chunk.object_id.as_hex(),
+            title: chunk.title.clone(),
+        }
+    }
+}
+
+impl RetrievalRecord {
+    /// A record of a pack built at `timestamp_unix` for `query`, with the
+    /// query text left out if `redact` is set. The query is hashed with
+    /// `key`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        timestamp_unix: u64,
+        actor: Option<String>,
+        kind: &str,
+        query: &str,
+        redact: bool,
+        key: &[u8; 32],
+        head_commit: ObjectId,
+        chunks: Vec<AuditedChunk>,
+    ) -> Self {
+        Self {
+            timestamp_unix,
+            actor,
+            kind: kind.to_string(),
+            query: (!redact).then(|| query.to_string()),
+            query_hash: blake3::keyed_hash(key, query.as_bytes())
+                .to_hex()
+                .to_string(),
+            head_commit: head_commit.as_hex(),
+            chunks,
+        }
+    }
+}
+
+/// Append-only log at `.ctx/logs/retrievals.jsonl`.
+#[derive(Debug, Clone)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`. The file is created on first append.
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Appends `record`.
+    pub(crate) fn append(&self, record: &RetrievalRecord) -> Result<()> {
+        let mut line =
+            serde_json::to_vec(record).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        line.push(b'\n');
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // One append-mode write, so concurrent writers don't interleave
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Reads every complete record made at or after `since` (Unix
+    /// seconds), oldest first.
+    pub(crate) fn read_since(&self, since: u64) -> Result<Vec<RetrievalRecord>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut records = Vec::new();
+        let mut line = String::new();
+        let mut number = 0;
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            // Stop at EOF or at a line still being written
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            number += 1;
+            let record: RetrievalRecord = serde_json::from_str(line.trim_end()).map_err(|e| {
+                CtxError::Serialization(format!("audit record on line {}: {}", number, e))
+            })?;
+            if record.timestamp_unix >= since {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Loads the repository's key for hashing queries from `path`, creating a
+/// random one on first use.
+///
+/// # Errors
+///
+/// Returns an error if the key can't be read or created, or is malformed.
+pub(crate) fn load_or_create_key(path: &Path) -> Result<[u8; 32]> {
+    match fs::read_to_string(path) {
+        Ok(hex) => return parse_key(&hex, path),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut key = [0; 32];
+    key[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+    key[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+
+    // Link a complete file into place, so nobody reads a partial key and
+    // the first process to create one wins
+    let tmp = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, blake3::Hash::from_bytes(key).to_hex().as_bytes())?;
+    let linked = fs::hard_link(&tmp, path);
+    let _ = fs::remove_file(&tmp);
+    match linked {
+        Ok(()) => Ok(key),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            parse_key(&fs::read_to_string(path)?, path)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn parse_key(hex: &str, path: &Path) -> Result<[u8; 32]> {
+    blake3::Hash::from_hex(hex.trim())
+        .map(|hash| *hash.as_bytes())
+        .map_err(|e| CtxError::InvalidHex(format!("audit key {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_append_and_read_since() {
+        let tmp = TempDir::new().unwrap();
+        let log = AuditLog::new(tmp.path().join(AUDIT_FILE));
+        assert!(log.read_since(0).unwrap().is_empty());
+
+        let head = ObjectId::from_bytes([1; 32]);
+        let chunk = AuditedChunk {
+            object_id: ObjectId::from_bytes([2; 32]).as_hex(),
+            title: "src/lib.rs:1-20".to_string(),
+        };
+        let old = RetrievalRecord::new(
+            100,
+            Some("agent".to_string()),
+            "pack",
+            "where is auth",
+            false,
+            &KEY,
+            head,
+            vec![chunk.clone()],
+        );
+        log.append(&old).unwrap();
+        let redacted =
+            RetrievalRecord::new(200, None, "zoom", "where is auth", true, &KEY, head, vec![]);
+        log.append(&redacted).unwrap();
+        // A writer that hasn't finished its line yet
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(tmp.path().join(AUDIT_FILE))
+            .unwrap();
+        file.write_all(b"{\"timestamp_unix\":1,").unwrap();
+
+        let all = log.read_since(0).unwrap();
+        assert_eq!(all, vec![old.clone(), redacted.clone()]);
+        assert_eq!(all[0].query.as_deref(), Some("where is auth"));
+        assert_eq!(all[0].chunks, vec![chunk]);
+        assert_eq!(all[1].query, None);
+        assert_eq!(all[1].query_hash, old.query_hash);
+        // The hash depends on the key, not just the query
+        assert_ne!(
+            old.query_hash,
+            blake3::hash(b"where is auth").to_hex().to_string()
+        );
+
+        assert_eq!(log.read_since(101).unwrap(), vec![redacted]);
+    }
+
+    #[test]
+    fn test_key_created_once() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join(AUDIT_KEY_FILE);
+        let key = load_or_create_key(&path).unwrap();
+        assert_eq!(load_or_create_key(&path).unwrap(), key);
+        assert_ne!(
+            load_or_create_key(&TempDir::new().unwrap().path().join(AUDIT_KEY_FILE)).unwrap(),
+            key
+        );
+        // Only the key is left behind
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+
+        fs::write(&path, "not hex").unwrap();
+        assert!(load_or_create_key(&path).is_err());
+    }
+}
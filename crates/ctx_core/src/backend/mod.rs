@@ -0,0 +1,182 @@
+//! Where object bytes live.
+//!
+//! An [`ObjectBackend`] stores the compressed canonical bytes of objects by
+//! ID. [`ObjectStore`](crate::ObjectStore) keeps every object on local disk
+//! in an [`FsBackend`] and can add a remote backend, set by
+//! `storage.remote` in `.ctx/config.toml`, that several machines share:
+//! - Writes go to local disk and then to the remote.
+//! - Reads of objects missing locally fetch them from the remote and keep
+//!   them on disk, so the local store is a read-through cache.
+//!
+//! Remotes are `file:///path` (a shared mount, using the same layout as
+//! `.ctx/objects`) or, with the `s3` feature, `s3://bucket/prefix` (see
+//! [`S3Backend`]). GC, `du` and `verify` only look at local disk; nothing
+//! is ever deleted from a remote.
+
+#[cfg(feature = "s3")]
+mod s3;
+
+#[cfg(feature = "s3")]
+pub use s3::S3Backend;
+
+use crate::config::StorageConfig;
+use crate::error::{CtxError, Result};
+use crate::ObjectId;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Distinguishes the temp files of concurrent writers of the same object.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Storage for objects' stored bytes, keyed by ID.
+///
+/// Objects are immutable and content-addressed, so `put` may be repeated
+/// and concurrent puts of one ID write the same bytes.
+pub trait ObjectBackend: Send + Sync {
+    /// Returns the stored bytes of `id`, or None if it isn't stored.
+    fn get(&self, id: ObjectId) -> Result<Option<Vec<u8>>>;
+
+    /// Stores `bytes` as `id`.
+    fn put(&self, id: ObjectId, bytes: &[u8]) -> Result<()>;
+
+    /// Returns true if `id` is stored.
+    fn exists(&self, id: ObjectId) -> Result<bool>;
+
+    /// Lists every stored ID, in no particular order.
+    fn list(&self) -> Result<Vec<ObjectId>>;
+}
+
+/// Objects as files under a directory, sharded by the first byte of their
+/// ID: `<root>/ab/abcd...`.
+#[derive(Debug, Clone)]
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+impl FsBackend {
+    /// Creates a backend rooted at `root`, created on first write.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the file an object is stored in.
+    pub fn path(&self, id: ObjectId) -> PathBuf {
+        self.root.join(id.shard()).join(id.as_hex())
+    }
+}
+
+impl ObjectBackend for FsBackend {
+    fn get(&self, id: ObjectId) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(id)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, id: ObjectId, bytes: &[u8]) -> Result<()> {
+        let path = self.path(id);
+        let dir = path.parent().unwrap();
+
+        // Ensure shard directory exists
+        fs::create_dir_all(dir)?;
+
+        // Atomic write: temp file + fsync + rename. The temp name is unique
+        // so parallel writers of the same object don't share it.
+        let tmp_path = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&tmp_path, &path)?;
+
+        // fsync parent directory (Unix-specific for crash safety)
+        #[cfg(unix)]
+        {
+            if let Ok(dir_file) = File::open(dir) {
+                let _ = dir_file.sync_all();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exists(&self, id: ObjectId) -> Result<bool> {
+        Ok(self.path(id).exists())
+    }
+
+    fn list(&self) -> Result<Vec<ObjectId>> {
+        let mut ids = Vec::new();
+        if !self.root.exists() {
+            return Ok(ids);
+        }
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&shard)? {
+                let path = entry?.path();
+                // Skip temp files
+                if path.extension().is_some() {
+                    continue;
+                }
+                if let Some(id) = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| ObjectId::from_hex(name).ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// Opens the remote backend `storage.remote` names, if it names one.
+///
+/// # Errors
+///
+/// Returns [`CtxError::ConfigError`] for an unsupported URL, or an `s3://`
+/// URL in a build without the `s3` feature.
+pub(crate) fn open_remote(storage: &StorageConfig) -> Result<Option<Arc<dyn ObjectBackend>>> {
+    let Some(url) = &storage.remote else {
+        return Ok(None);
+    };
+    let unsupported = |reason: &str| {
+        Err(CtxError::ConfigError(format!(
+            "invalid value for storage.remote: {} ({})",
+            url, reason
+        )))
+    };
+    match url.split_once("://") {
+        Some(("file", path)) if !path.is_empty() => Ok(Some(Arc::new(FsBackend::new(path)))),
+        #[cfg(feature = "s3")]
+        Some(("s3", location)) => Ok(Some(Arc::new(S3Backend::new(
+            location,
+            storage.remote_endpoint.as_deref(),
+            storage.remote_region.as_deref(),
+        )?))),
+        #[cfg(not(feature = "s3"))]
+        Some(("s3", _)) => unsupported("this build has no S3 support; enable the `s3` feature"),
+        _ => unsupported("expected file:///path or s3://bucket/prefix"),
+    }
+}
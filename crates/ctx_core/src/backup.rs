@@ -0,0 +1,369 @@
+//! Rotating backups of refs and the narrative.
+//!
+//! Objects are immutable and content-addressed, but refs are single files
+//! and the narrative is edited in place, so one bad write can lose HEAD or
+//! a document. After every compaction, [`CtxRepo`](crate::CtxRepo) archives
+//! the top-level refs (`HEAD`, `STAGE`, ...), `refs/` and `narrative/` into
+//! a zstd-compressed tarball in [`BACKUP_DIRECTORY`], keeping the newest
+//! `backup.keep` of them.
+//!
+//! [`CtxRepo::restore_backup`](crate::CtxRepo::restore_backup) puts an
+//! archive's files back, after backing up the current ones so a restore
+//! can itself be undone.
+
+use crate::error::{CtxError, Result};
+use crate::object_id::ObjectId;
+use crate::refs::TOP_LEVEL_REFS;
+use serde::Serialize;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Directory in `.ctx` backups are written to.
+pub const BACKUP_DIRECTORY: &str = "backups";
+
+/// Extension of backup archives.
+pub(crate) const EXTENSION: &str = ".tar.zst";
+
+/// Directories archived along with the top-level refs.
+const DIRECTORIES: [&str; 2] = ["refs", "narrative"];
+
+/// A backup archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackupInfo {
+    /// Name to restore it by, such as `backup-1769083200`.
+    pub name: String,
+    /// When it was taken (Unix seconds).
+    pub created_at: u64,
+    /// Size of the archive in bytes.
+    pub size: u64,
+    /// The commit HEAD pointed to, if the archive has a readable HEAD.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub head: Option<ObjectId>,
+    /// Files in the archive.
+    pub files: usize,
+}
+
+/// Outcome of [`CtxRepo::restore_backup`](crate::CtxRepo::restore_backup).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestoreReport {
+    /// The backup restored.
+    pub backup: String,
+    /// Backup of the files the restore replaced.
+    pub previous: String,
+    /// HEAD before the restore, if it was readable.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub head_before: Option<ObjectId>,
+    /// HEAD after the restore.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub head_after: ObjectId,
+    /// Files written.
+    pub files: usize,
+}
+
+/// Archives the refs and narrative of `ctx_dir`, then deletes all but the
+/// newest `keep` archives.
+pub(crate) fn create(ctx_dir: &Path, now: u64, keep: usize) -> Result<BackupInfo> {
+    let dir = ctx_dir.join(BACKUP_DIRECTORY);
+    fs::create_dir_all(&dir)?;
+
+    // Backups taken in the same second get a suffix
+    let mut name = format!("backup-{}", now);
+    let mut n = 1;
+    while dir.join(format!("{}{}", name, EXTENSION)).exists() {
+        name = format!("backup-{}-{}", now, n);
+        n += 1;
+    }
+
+    let files = collect_files(ctx_dir)?;
+    let path = dir.join(format!("{}{}", name, EXTENSION));
+    let tmp = path.with_extension("tmp");
+    let written = write_archive(&tmp, &files, now).and_then(|()| Ok(fs::rename(&tmp, &path)?));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    for old in list(ctx_dir)?.into_iter().skip(keep.max(1)) {
+        fs::remove_file(dir.join(format!("{}{}", old.name, EXTENSION)))?;
+    }
+
+    Ok(BackupInfo {
+        name,
+        created_at: now,
+        size: fs::metadata(&path)?.len(),
+        head: head(&files),
+        files: files.len(),
+    })
+}
+
+/// Lists the backups in `ctx_dir`, newest first.
+pub(crate) fn list(ctx_dir: &Path) -> Result<Vec<BackupInfo>> {
+    let dir = ctx_dir.join(BACKUP_DIRECTORY);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(name) = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(EXTENSION))
+        else {
+            continue;
+        };
+        let Some((created_at, seq)) = parse_name(name) else {
+            continue;
+        };
+        let files = read(ctx_dir, name)?;
+        backups.push((
+            seq,
+            BackupInfo {
+                name: name.to_string(),
+                created_at,
+                size: entry.metadata()?.len(),
+                head: head(&files),
+                files: files.len(),
+            },
+        ));
+    }
+    backups.sort_by(|(a_seq, a), (b_seq, b)| (b.created_at, b_seq).cmp(&(a.created_at, a_seq)));
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Reads the files of backup `name`, as paths relative to `.ctx` and
+/// their contents.
+///
+/// # Errors
+///
+/// Returns [`CtxError::InvalidArgument`] if there's no such backup, or
+/// [`CtxError::CorruptedObject`] if it isn't a valid archive or holds
+/// files outside the refs and narrative.
+pub(crate) fn read(ctx_dir: &Path, name: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let name = name.strip_suffix(EXTENSION).unwrap_or(name);
+    if parse_name(name).is_none() {
+        return Err(CtxError::InvalidArgument(format!(
+            "'{}' isn't a backup name (see ctx restore --list)",
+            name
+        )));
+    }
+    let path = ctx_dir
+        .join(BACKUP_DIRECTORY)
+        .join(format!("{}{}", name, EXTENSION));
+    if !path.exists() {
+        return Err(CtxError::InvalidArgument(format!(
+            "no backup named '{}' (see ctx restore --list)",
+            name
+        )));
+    }
+
+    let corrupt = |reason: String| CtxError::CorruptedObject {
+        path: path.clone(),
+        reason,
+    };
+    let decoder = zstd::Decoder::new(fs::File::open(&path)?)
+        .map_err(|e| corrupt(format!("can't decompress: {}", e)))?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut files = Vec::new();
+    for entry in archive
+        .entries()
+        .map_err(|e| corrupt(format!("can't read archive: {}", e)))?
+    {
+        let mut entry = entry.map_err(|e| corrupt(format!("can't read archive: {}", e)))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_path = entry
+            .path()
+            .map_err(|e| corrupt(format!("invalid path: {}", e)))?
+            .to_string_lossy()
+            .into_owned();
+        if !is_backed_up(&entry_path) {
+            return Err(corrupt(format!("unexpected file {}", entry_path)));
+        }
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .map_err(|e| corrupt(format!("can't read {}: {}", entry_path, e)))?;
+        files.push((entry_path, content));
+    }
+    Ok(files)
+}
+
+/// Replaces the refs and narrative of `ctx_dir` with `files`.
+pub(crate) fn restore_files(ctx_dir: &Path, files: &[(String, Vec<u8>)]) -> Result<()> {
+    for name in TOP_LEVEL_REFS {
+        let path = ctx_dir.join(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    for dir in DIRECTORIES {
+        let path = ctx_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(path)?;
+    }
+    for (path, content) in files {
+        let path = ctx_dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+/// The files to back up, as paths relative to `ctx_dir` (with `/`
+/// separators) and their contents, sorted by path.
+fn collect_files(ctx_dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Vec::new();
+    for name in TOP_LEVEL_REFS {
+        let path = ctx_dir.join(name);
+        if path.is_file() {
+            files.push((name.to_string(), fs::read(path)?));
+        }
+    }
+    for dir in DIRECTORIES {
+        let mut pending = vec![PathBuf::from(dir)];
+        while let Some(relative) = pending.pop() {
+            let absolute = ctx_dir.join(&relative);
+            if !absolute.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&absolute)? {
+                let entry = entry?;
+                let relative = relative.join(entry.file_name());
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    pending.push(relative);
+                } else if file_type.is_file() {
+                    let key = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    files.push((key, fs::read(entry.path())?));
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn write_archive(path: &Path, files: &[(String, Vec<u8>)], now: u64) -> Result<()> {
+    let encoder = zstd::Encoder::new(fs::File::create(path)?, 0)?;
+    let mut builder = tar::Builder::new(encoder);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now);
+        header.set_cksum();
+        builder.append_data(&mut header, name, content.as_slice())?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Whether a path in an archive is one backups hold.
+fn is_backed_up(path: &str) -> bool {
+    if path.split('/').any(|part| part.is_empty() || part == "..") {
+        return false;
+    }
+    TOP_LEVEL_REFS.contains(&path)
+        || DIRECTORIES.iter().any(|dir| {
+            path.strip_prefix(dir)
+                .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+/// The time and same-second sequence number in a backup name.
+fn parse_name(name: &str) -> Option<(u64, u64)> {
+    let rest = name.strip_prefix("backup-")?;
+    let (time, seq) = match rest.split_once('-') {
+        Some((time, seq)) => (time, seq.parse().ok()?),
+        None => (rest, 0),
+    };
+    Some((time.parse().ok()?, seq))
+}
+
+/// The commit HEAD points to in a backup's files, if it's readable.
+pub(crate) fn head(files: &[(String, Vec<u8>)]) -> Option<ObjectId> {
+    let (_, content) = files.iter().find(|(path, _)| path == "HEAD")?;
+    ObjectId::from_hex(std::str::from_utf8(content).ok()?.trim()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_list_and_rotate() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ctx_dir = tmp.path();
+        let head = ObjectId::from_bytes([7; 32]);
+        fs::write(ctx_dir.join("HEAD"), head.as_hex()).unwrap();
+        fs::create_dir_all(ctx_dir.join("refs")).unwrap();
+        fs::write(ctx_dir.join("refs/main"), head.as_hex()).unwrap();
+        fs::create_dir_all(ctx_dir.join("narrative/log")).unwrap();
+        fs::write(ctx_dir.join("narrative/log/today.md"), "notes").unwrap();
+        fs::write(ctx_dir.join("config.toml"), "").unwrap();
+
+        let first = create(ctx_dir, 100, 2).unwrap();
+        assert_eq!(first.name, "backup-100");
+        assert_eq!(first.head, Some(head));
+        assert_eq!(first.files, 3);
+        let second = create(ctx_dir, 100, 2).unwrap();
+        assert_eq!(second.name, "backup-100-1");
+        create(ctx_dir, 200, 2).unwrap();
+
+        let names: Vec<String> = list(ctx_dir).unwrap().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, ["backup-200", "backup-100-1"]);
+
+        let files = read(ctx_dir, "backup-100-1.tar.zst").unwrap();
+        let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, ["HEAD", "narrative/log/today.md", "refs/main"]);
+        assert!(matches!(
+            read(ctx_dir, "backup-100"),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        assert!(read(ctx_dir, "../config").is_err());
+    }
+
+    #[test]
+    fn test_restore_files_replaces_refs_and_narrative() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ctx_dir = tmp.path();
+        fs::create_dir_all(ctx_dir.join("narrative")).unwrap();
+        fs::write(ctx_dir.join("narrative/new.md"), "later").unwrap();
+        fs::write(ctx_dir.join("STAGE"), "stage").unwrap();
+
+        let files = vec![
+            ("HEAD".to_string(), b"head".to_vec()),
+            ("narrative/old.md".to_string(), b"earlier".to_vec()),
+        ];
+        restore_files(ctx_dir, &files).unwrap();
+
+        assert_eq!(fs::read(ctx_dir.join("HEAD")).unwrap(), b"head");
+        assert!(!ctx_dir.join("STAGE").exists());
+        assert!(!ctx_dir.join("narrative/new.md").exists());
+        assert!(ctx_dir.join("narrative/old.md").exists());
+        assert!(ctx_dir.join("refs").is_dir());
+    }
+
+    #[test]
+    fn test_is_backed_up() {
+        assert!(is_backed_up("HEAD"));
+        assert!(is_backed_up("refs/main"));
+        assert!(is_backed_up("narrative/log/2026-01-22.md"));
+        assert!(!is_backed_up("config.toml"));
+        assert!(!is_backed_up("refs"));
+        assert!(!is_backed_up("narrative/../config.toml"));
+        assert!(!is_backed_up("refsx/main"));
+    }
+}
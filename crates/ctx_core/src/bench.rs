@@ -0,0 +1,306 @@
+//! A/B comparison of retrieval configurations.
+//!
+//! [`CtxRepo::bench_retrieval`](crate::CtxRepo::bench_retrieval) builds a
+//! pack for every query under two [`RetrievalConfig`]s and reports, per
+//! query and in total, the tokens each used, how much their chunks overlap
+//! and, for queries labeled with the files they should find, the share of
+//! those files each retrieved. Tuning expansion depth or edge weights then
+//! comes down to comparing two numbers instead of eyeballing packs.
+//!
+//! Queries and their labels come from an expectations file:
+//!
+//! ```toml
+//! [[queries]]
+//! query = "where are packs cached"
+//! expect = ["crates/ctx_core/src/cache.rs"]
+//!
+//! [[queries]]
+//! query = "how does gc find roots"
+//! expect = ["crates/ctx_core/src/"]
+//! ```
+//!
+//! An expected entry ending in `/` is met by any file under that directory.
+//! Ratios are fixed-point, like relevance scores: 1000 means 1.0.
+
+use crate::error::{CtxError, Result};
+use crate::pack::{PromptPack, RetrievalConfig};
+use crate::{CtxRepo, ObjectId};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A query to benchmark, with the files it should retrieve.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BenchQuery {
+    /// The query.
+    pub query: String,
+    /// Paths (or directories ending in `/`) a good pack includes. Empty for
+    /// unlabeled queries, which get no hit rate.
+    #[serde(default)]
+    pub expect: Vec<String>,
+}
+
+/// Contents of an expectations file.
+#[derive(Deserialize)]
+struct Expectations {
+    #[serde(default)]
+    queries: Vec<BenchQuery>,
+}
+
+/// What one configuration retrieved for one query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BenchRun {
+    /// Tokens the pack used.
+    pub tokens: u32,
+    /// Titles of the retrieved chunks, in pack order.
+    pub chunks: Vec<String>,
+    /// Expected entries the pack met, if the query is labeled.
+    pub hits: Option<usize>,
+    /// Share of expected entries met (fixed-point), if the query is labeled.
+    pub hit_rate: Option<u32>,
+}
+
+/// Both configurations' results for one query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BenchQueryReport {
+    /// The query.
+    pub query: String,
+    /// The labeled files.
+    pub expect: Vec<String>,
+    /// Result with the first configuration.
+    pub a: BenchRun,
+    /// Result with the second configuration.
+    pub b: BenchRun,
+    /// Chunks both packs share, over chunks either has (fixed-point).
+    pub overlap: u32,
+}
+
+/// One configuration's results over every query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BenchTotals {
+    /// Tokens used by all packs.
+    pub tokens: u64,
+    /// Tokens used per pack, on average.
+    pub mean_tokens: u32,
+    /// Expected entries met over all labeled queries (fixed-point), if any
+    /// query is labeled.
+    pub hit_rate: Option<u32>,
+}
+
+/// Comparison of two retrieval configurations.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    /// Commit the packs were built from.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub head_commit: ObjectId,
+    /// The first configuration.
+    pub config_a: RetrievalConfig,
+    /// The second configuration.
+    pub config_b: RetrievalConfig,
+    /// Per-query results, in the order given.
+    pub queries: Vec<BenchQueryReport>,
+    /// Totals for the first configuration.
+    pub a: BenchTotals,
+    /// Totals for the second configuration.
+    pub b: BenchTotals,
+    /// Mean overlap across queries (fixed-point).
+    pub mean_overlap: u32,
+}
+
+/// Parses an expectations file.
+///
+/// # Errors
+///
+/// Returns [`CtxError::ConfigError`] if the file isn't valid TOML or a
+/// query is empty.
+pub fn parse_expectations(text: &str) -> Result<Vec<BenchQuery>> {
+    let expectations: Expectations = toml::from_str(text)
+        .map_err(|e| CtxError::ConfigError(format!("invalid expectations file: {}", e)))?;
+    if let Some(i) = expectations
+        .queries
+        .iter()
+        .position(|q| q.query.trim().is_empty())
+    {
+        return Err(CtxError::ConfigError(format!(
+            "query {} in the expectations file is empty",
+            i + 1
+        )));
+    }
+    Ok(expectations.queries)
+}
+
+/// Parses a retrieval configuration from TOML, with unset fields at their
+/// defaults.
+///
+/// # Errors
+///
+/// Returns [`CtxError::ConfigError`] if the text isn't a valid
+/// configuration.
+pub fn parse_retrieval_config(text: &str) -> Result<RetrievalConfig> {
+    toml::from_str(text)
+        .map_err(|e| CtxError::ConfigError(format!("invalid retrieval config: {}", e)))
+}
+
+/// Builds every query's pack under both configurations.
+///
+/// Frecency is turned off for both, since building a pack with it records
+/// the pack's files as used, which would favor whichever runs second.
+/// The packs aren't recorded in the retrieval audit log, since only chunk
+/// titles leave this function.
+pub(crate) fn run(
+    repo: &CtxRepo,
+    queries: &[BenchQuery],
+    a: &RetrievalConfig,
+    b: &RetrievalConfig,
+) -> Result<BenchReport> {
+    let config_a = RetrievalConfig {
+        frecency_boost: false,
+        ..a.clone()
+    };
+    let config_b = RetrievalConfig {
+        frecency_boost: false,
+        ..b.clone()
+    };
+
+    let mut reports = Vec::new();
+    for query in queries {
+        let pack_a = crate::pack::build_pack(repo, &query.query, &config_a)?;
+        let pack_b = crate::pack::build_pack(repo, &query.query, &config_b)?;
+        reports.push(BenchQueryReport {
+            query: query.query.clone(),
+            expect: query.expect.clone(),
+            overlap: overlap(&pack_a, &pack_b),
+            a: bench_run(&pack_a, &query.expect),
+            b: bench_run(&pack_b, &query.expect),
+        });
+    }
+
+    let mean_overlap = match reports.len() {
+        0 => 0,
+        n => (reports.iter().map(|r| r.overlap as u64).sum::<u64>() / n as u64) as u32,
+    };
+    Ok(BenchReport {
+        head_commit: repo.head_id()?,
+        a: totals(reports.iter().map(|r| (&r.a, r.expect.len()))),
+        b: totals(reports.iter().map(|r| (&r.b, r.expect.len()))),
+        config_a,
+        config_b,
+        queries: reports,
+        mean_overlap,
+    })
+}
+
+/// Summarizes a pack against the expected entries.
+fn bench_run(pack: &PromptPack, expect: &[String]) -> BenchRun {
+    let chunks: Vec<String> = pack.retrieved.iter().map(|c| c.title.clone()).collect();
+    let hits = (!expect.is_empty()).then(|| {
+        expect
+            .iter()
+            .filter(|expected| chunks.iter().any(|title| meets(title, expected)))
+            .count()
+    });
+    BenchRun {
+        tokens: pack.token_budget.used,
+        hit_rate: hits.map(|hits| ratio(hits as u64, expect.len() as u64)),
+        hits,
+        chunks,
+    }
+}
+
+/// Returns true if a chunk titled `title` meets the expected entry.
+fn meets(title: &str, expected: &str) -> bool {
+    if expected.ends_with('/') {
+        title.starts_with(expected)
+    } else {
+        title == expected
+    }
+}
+
+/// Chunks both packs share over chunks either has (fixed-point). Two empty
+/// packs overlap fully.
+fn overlap(a: &PromptPack, b: &PromptPack) -> u32 {
+    let a: BTreeSet<&str> = a.retrieved.iter().map(|c| c.title.as_str()).collect();
+    let b: BTreeSet<&str> = b.retrieved.iter().map(|c| c.title.as_str()).collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1000;
+    }
+    ratio(a.intersection(&b).count() as u64, union as u64)
+}
+
+/// Totals over `(run, expected entries)` pairs.
+fn totals<'a>(runs: impl Iterator<Item = (&'a BenchRun, usize)>) -> BenchTotals {
+    let mut count = 0u64;
+    let mut tokens = 0u64;
+    let mut hits = 0u64;
+    let mut expected = 0u64;
+    for (run, expect) in runs {
+        count += 1;
+        tokens += run.tokens as u64;
+        hits += run.hits.unwrap_or(0) as u64;
+        expected += expect as u64;
+    }
+    BenchTotals {
+        tokens,
+        mean_tokens: tokens.checked_div(count).unwrap_or(0) as u32,
+        hit_rate: (expected > 0).then(|| ratio(hits, expected)),
+    }
+}
+
+/// `part / whole` as fixed-point.
+fn ratio(part: u64, whole: u64) -> u32 {
+    (part * 1000 / whole) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expectations() {
+        let queries = parse_expectations(
+            "[[queries]]\nquery = \"where is the cache\"\nexpect = [\"src/cache.rs\"]\n\n[[queries]]\nquery = \"gc roots\"\n",
+        )
+        .unwrap();
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[0].expect, ["src/cache.rs"]);
+        assert!(queries[1].expect.is_empty());
+
+        assert!(parse_expectations("[[queries]]\nquery = \" \"\n").is_err());
+        assert!(parse_expectations("queries = 3").is_err());
+        assert!(parse_expectations("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_retrieval_config() {
+        let config = parse_retrieval_config("expansion_depth = 3\n").unwrap();
+        assert_eq!(config.expansion_depth, 3);
+        assert_eq!(config.token_budget, RetrievalConfig::default().token_budget);
+        assert!(parse_retrieval_config("expansion_depth = \"deep\"").is_err());
+    }
+
+    #[test]
+    fn test_meets() {
+        assert!(meets("src/cache.rs", "src/cache.rs"));
+        assert!(!meets("src/cache.rs.bak", "src/cache.rs"));
+        assert!(meets("src/gc/roots.rs", "src/gc/"));
+        assert!(!meets("src/gcx.rs", "src/gc/"));
+    }
+
+    #[test]
+    fn test_totals() {
+        let run = |tokens, hits| BenchRun {
+            tokens,
+            chunks: vec![],
+            hits,
+            hit_rate: None,
+        };
+        let runs = [run(100, Some(1)), run(300, None), run(200, Some(2))];
+        let all = totals(runs.iter().zip([2, 0, 2]));
+        assert_eq!(all.tokens, 600);
+        assert_eq!(all.mean_tokens, 200);
+        assert_eq!(all.hit_rate, Some(750));
+
+        let unlabeled = totals(runs[1..2].iter().zip([0]));
+        assert_eq!(unlabeled.hit_rate, None);
+    }
+}
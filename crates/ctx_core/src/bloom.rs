@@ -0,0 +1,402 @@
+//! Bloom filter over the IDs in the object store.
+//!
+//! [`ObjectStore::exists`](crate::ObjectStore::exists) asks the filter
+//! first, so looking up an object that isn't stored costs no filesystem
+//! lookup. That is the common case when deduplicating writes and when GC
+//! follows references to objects that were never stored. A "maybe" still
+//! checks the file.
+//!
+//! The filter lives in the objects directory as two files:
+//! - `bloom`, a snapshot of every stored ID, rebuilt by GC.
+//! - `bloom.log`, the IDs written since, appended before each object is
+//!   renamed into place.
+//!
+//! Both start with the same generation number; a mismatch (a rebuild
+//! interrupted by a crash) disables the filter until the next rebuild.
+//! Writers hold a shared lock on `bloom.lock` from appending an ID until
+//! the object is in place, and rebuilds hold it exclusively, so no write is
+//! missed. Without a snapshot the filter is off and every lookup checks the
+//! file.
+
+use crate::error::Result;
+use crate::ObjectId;
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Snapshot file name in the objects directory.
+const SNAPSHOT_FILE: &str = "bloom";
+
+/// Log file name in the objects directory.
+const LOG_FILE: &str = "bloom.log";
+
+/// Lock file name in the objects directory.
+const LOCK_FILE: &str = "bloom.lock";
+
+/// Snapshot magic bytes.
+const MAGIC: &[u8; 5] = b"CTXB1";
+
+/// Snapshot header: magic, generation, count, hash count, bit count.
+const SNAPSHOT_HEADER_LEN: usize = 5 + 8 + 8 + 4 + 8;
+
+/// Log header: generation.
+const LOG_HEADER_LEN: u64 = 8;
+
+/// Bit positions set per ID, optimal for a 1% false-positive rate.
+const HASHES: u32 = 7;
+
+/// Bits per expected ID for a 1% false-positive rate.
+const BITS_PER_ID: usize = 10;
+
+/// Fewest IDs a filter is sized for.
+const MIN_CAPACITY: usize = 4096;
+
+/// A fixed-size bloom filter of object IDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+    /// IDs inserted, counting repeats.
+    count: u64,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for twice `ids` IDs, leaving room to
+    /// grow before the next rebuild.
+    pub(crate) fn with_capacity(ids: usize) -> Self {
+        let capacity = (ids * 2).max(MIN_CAPACITY);
+        Self {
+            bits: vec![0; capacity * BITS_PER_ID / 8],
+            hashes: HASHES,
+            count: 0,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, id: ObjectId) {
+        for bit in self.positions(id) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+        self.count += 1;
+    }
+
+    /// Returns false if `id` was never inserted; true if it may have been.
+    pub(crate) fn contains(&self, id: ObjectId) -> bool {
+        self.positions(id)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// IDs inserted, counting repeats.
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Bit positions for `id`. IDs are already uniform hashes, so two
+    /// halves of one combine into all positions (double hashing).
+    fn positions(&self, id: ObjectId) -> impl Iterator<Item = usize> {
+        let bytes = id.as_bytes();
+        let h1 = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        let len = (self.bits.len() * 8) as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    fn to_snapshot(&self, generation: u64) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SNAPSHOT_HEADER_LEN + self.bits.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&generation.to_le_bytes());
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&self.hashes.to_le_bytes());
+        out.extend_from_slice(&(self.bits.len() as u64 * 8).to_le_bytes());
+        out.extend_from_slice(&self.bits);
+        out
+    }
+
+    /// Parses a snapshot into its generation and filter.
+    fn from_snapshot(bytes: &[u8]) -> Option<(u64, Self)> {
+        if bytes.len() < SNAPSHOT_HEADER_LEN || &bytes[..5] != MAGIC {
+            return None;
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let generation = u64_at(5);
+        let count = u64_at(13);
+        let hashes = u32::from_le_bytes(bytes[21..25].try_into().unwrap());
+        let bit_len = u64_at(25);
+        let bits = &bytes[SNAPSHOT_HEADER_LEN..];
+        if hashes == 0 || bits.is_empty() || bit_len != bits.len() as u64 * 8 {
+            return None;
+        }
+        Some((
+            generation,
+            Self {
+                bits: bits.to_vec(),
+                hashes,
+                count,
+            },
+        ))
+    }
+}
+
+/// The persisted filter as loaded by one store, kept up to date with the
+/// log.
+#[derive(Debug, Default)]
+pub(crate) struct ExistenceFilter {
+    state: Option<Loaded>,
+    /// Set once loading found no usable snapshot.
+    disabled: bool,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    generation: u64,
+    filter: BloomFilter,
+    log: File,
+    /// Log bytes already inserted.
+    read_to: u64,
+}
+
+impl ExistenceFilter {
+    /// Returns true if `id` is definitely not stored under `root`.
+    ///
+    /// Picks up IDs other writers logged before answering, so a stored
+    /// object is never reported missing.
+    pub(crate) fn excludes(&mut self, root: &Path, id: ObjectId) -> bool {
+        if self.disabled {
+            return false;
+        }
+        if let Some(loaded) = &self.state {
+            if loaded.filter.contains(id) {
+                return false;
+            }
+        }
+        if self.refresh(root).is_err() {
+            self.state = None;
+            return false;
+        }
+        self.state
+            .as_ref()
+            .is_some_and(|loaded| !loaded.filter.contains(id))
+    }
+
+    /// Number of IDs the filter holds, counting repeats, or None if it is
+    /// off.
+    pub(crate) fn count(&mut self, root: &Path) -> Option<u64> {
+        if self.disabled || self.refresh(root).is_err() {
+            return None;
+        }
+        self.state.as_ref().map(|loaded| loaded.filter.count())
+    }
+
+    /// Replaces the loaded filter after a rebuild.
+    fn replace(&mut self, generation: u64, filter: BloomFilter, log: File) {
+        self.disabled = false;
+        self.state = Some(Loaded {
+            generation,
+            filter,
+            log,
+            read_to: LOG_HEADER_LEN,
+        });
+    }
+
+    /// Inserts the IDs logged since the last refresh, reloading everything
+    /// if a rebuild replaced the snapshot.
+    fn refresh(&mut self, root: &Path) -> Result<()> {
+        let Some(loaded) = &mut self.state else {
+            return self.load(root);
+        };
+        // Like a seqlock: the entries read are only trusted if no rebuild
+        // changed the generation meanwhile
+        let before = read_log_generation(&mut loaded.log)?;
+        let mut entries = Vec::new();
+        if before == Some(loaded.generation) {
+            entries = read_log_entries(&mut loaded.log, loaded.read_to)?;
+        }
+        let after = read_log_generation(&mut loaded.log)?;
+        if before != Some(loaded.generation) || after != before {
+            return self.load(root);
+        }
+        for id in &entries {
+            loaded.filter.insert(*id);
+        }
+        loaded.read_to += entries.len() as u64 * 32;
+        Ok(())
+    }
+
+    /// Loads the snapshot and log under a shared lock.
+    fn load(&mut self, root: &Path) -> Result<()> {
+        self.state = None;
+        let _lock = lock(root, false)?;
+        let snapshot = match fs::read(root.join(SNAPSHOT_FILE)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.disabled = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut log = match File::open(root.join(LOG_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.disabled = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let Some((generation, mut filter)) = BloomFilter::from_snapshot(&snapshot) else {
+            self.disabled = true;
+            return Ok(());
+        };
+        if read_log_generation(&mut log)? != Some(generation) {
+            self.disabled = true;
+            return Ok(());
+        }
+        let entries = read_log_entries(&mut log, LOG_HEADER_LEN)?;
+        for id in &entries {
+            filter.insert(*id);
+        }
+        self.state = Some(Loaded {
+            generation,
+            filter,
+            log,
+            read_to: LOG_HEADER_LEN + entries.len() as u64 * 32,
+        });
+        Ok(())
+    }
+}
+
+/// A shared lock held while an object is written, with its ID logged.
+pub(crate) struct WriteGuard {
+    _lock: File,
+}
+
+/// Logs `id` as about to be written under `root`. Keep the guard until the
+/// object is in place.
+pub(crate) fn log_write(root: &Path, id: ObjectId) -> Result<WriteGuard> {
+    let lock = lock(root, false)?;
+    // Without a snapshot nothing reads the log; the next rebuild lists
+    // every object instead
+    if root.join(SNAPSHOT_FILE).exists() {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(root.join(LOG_FILE))?;
+        log.write_all(id.as_bytes())?;
+    }
+    Ok(WriteGuard { _lock: lock })
+}
+
+/// Rebuilds the snapshot from `ids`, every object in the store, and empties
+/// the log. The caller lists `ids` while holding [`lock_rebuild`]'s lock.
+pub(crate) fn rebuild(
+    root: &Path,
+    ids: impl ExactSizeIterator<Item = ObjectId>,
+    filter_state: &mut ExistenceFilter,
+) -> Result<()> {
+    let mut filter = BloomFilter::with_capacity(ids.len());
+    for id in ids {
+        filter.insert(id);
+    }
+    let generation = fs::read(root.join(SNAPSHOT_FILE))
+        .ok()
+        .and_then(|bytes| BloomFilter::from_snapshot(&bytes))
+        .map_or(1, |(generation, _)| generation.wrapping_add(1));
+
+    // Snapshot first: if the log isn't reset after a crash, the
+    // generations differ and the filter stays off
+    let path = root.join(SNAPSHOT_FILE);
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&filter.to_snapshot(generation))?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, &path)?;
+
+    let mut log = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(root.join(LOG_FILE))?;
+    log.set_len(0)?;
+    log.write_all(&generation.to_le_bytes())?;
+    log.sync_all()?;
+
+    filter_state.replace(generation, filter, log);
+    Ok(())
+}
+
+/// Takes the exclusive lock a rebuild holds while listing objects.
+pub(crate) fn lock_rebuild(root: &Path) -> Result<File> {
+    lock(root, true)
+}
+
+/// Opens and locks the lock file, creating the store directory if needed.
+fn lock(root: &Path, exclusive: bool) -> Result<File> {
+    fs::create_dir_all(root)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(root.join(LOCK_FILE))?;
+    // fs2's, not the newer std methods of the same names
+    if exclusive {
+        FileExt::lock_exclusive(&file)?;
+    } else {
+        FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
+}
+
+/// Reads the log's generation, or None if it has no header.
+fn read_log_generation(log: &mut File) -> Result<Option<u64>> {
+    use std::io::{Seek, SeekFrom};
+    log.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; LOG_HEADER_LEN as usize];
+    match log.read_exact(&mut header) {
+        Ok(()) => Ok(Some(u64::from_le_bytes(header))),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads the whole IDs logged from byte `from` on.
+fn read_log_entries(log: &mut File, from: u64) -> Result<Vec<ObjectId>> {
+    use std::io::{Seek, SeekFrom};
+    log.seek(SeekFrom::Start(from))?;
+    let mut bytes = Vec::new();
+    log.read_to_end(&mut bytes)?;
+    Ok(bytes
+        .chunks_exact(32)
+        .map(|id| ObjectId::from_bytes(id.try_into().unwrap()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let ids: Vec<ObjectId> = (0..2000u32)
+            .map(|i| ObjectId::hash_blob(&i.to_le_bytes()))
+            .collect();
+        let mut filter = BloomFilter::with_capacity(1000);
+        for id in &ids[..1000] {
+            filter.insert(*id);
+        }
+        assert!(ids[..1000].iter().all(|id| filter.contains(*id)));
+        let false_positives = ids[1000..]
+            .iter()
+            .filter(|id| filter.contains(**id))
+            .count();
+        assert!(false_positives < 20, "{} false positives", false_positives);
+        assert_eq!(filter.count(), 1000);
+
+        let (generation, parsed) = BloomFilter::from_snapshot(&filter.to_snapshot(3)).unwrap();
+        assert_eq!(generation, 3);
+        assert_eq!(parsed, filter);
+        assert!(BloomFilter::from_snapshot(b"CTXB1").is_none());
+    }
+}
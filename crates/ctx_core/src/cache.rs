@@ -0,0 +1,262 @@
+//! On-disk cache of built prompt packs.
+//!
+//! Packs are stored one file per key under `.ctx/cache/packs/`. Keys are
+//! content hashes of everything that determines a pack (see
+//! [`build_pack_cached`](crate::CtxRepo::build_pack_cached)), so a changed HEAD or
+//! configuration simply misses. Entries expire after a TTL, and the oldest
+//! entries are evicted once the cache grows past its size limit. The cache
+//! is disposable: deleting the directory is always safe.
+
+use crate::config::CacheConfig;
+use crate::error::{CtxError, Result};
+use crate::pack::PromptPack;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// File extension for cache entries.
+const ENTRY_EXTENSION: &str = "json";
+
+/// Distinguishes the temp files of concurrent writers of the same entry.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A cached pack and when it was built.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// Build time (Unix seconds).
+    created_at: u64,
+    /// The cached pack.
+    pack: PromptPack,
+}
+
+/// Prompt pack cache rooted at a directory.
+#[derive(Debug, Clone)]
+pub struct PackCache {
+    dir: PathBuf,
+    config: CacheConfig,
+}
+
+impl PackCache {
+    /// Creates a cache storing entries in `dir`.
+    ///
+    /// The directory is created on first write.
+    pub fn new(dir: impl Into<PathBuf>, config: CacheConfig) -> Self {
+        Self {
+            dir: dir.into(),
+            config,
+        }
+    }
+
+    /// Returns the cache configuration.
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// Looks up a pack, returning `None` on a miss or an expired entry.
+    ///
+    /// Expired and unreadable entries are deleted.
+    pub fn get(&self, key: &str, now_unix: u64) -> Result<Option<PromptPack>> {
+        let path = self.entry_path(key);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entry: CacheEntry = match serde_json::from_slice(&data) {
+            Ok(entry) => entry,
+            Err(_) => {
+                let _ = fs::remove_file(&path);
+                return Ok(None);
+            }
+        };
+        if now_unix.saturating_sub(entry.created_at) >= self.config.ttl_secs {
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+
+        Ok(Some(entry.pack))
+    }
+
+    /// Stores a pack, then evicts the oldest entries if over the size limit.
+    pub fn put(&self, key: &str, pack: &PromptPack, now_unix: u64) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let entry = CacheEntry {
+            created_at: now_unix,
+            pack: pack.clone(),
+        };
+        let data =
+            serde_json::to_vec(&entry).map_err(|e| CtxError::Serialization(e.to_string()))?;
+
+        // Write to a temp file and rename so readers never see partial
+        // entries. The temp name is unique so concurrent writers of the same
+        // entry, in this process or another, don't share it.
+        let path = self.entry_path(key);
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = fs::write(&tmp, &data).and_then(|()| fs::rename(&tmp, &path)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+
+        self.evict()
+    }
+
+    /// Removes every entry, returning how many were removed.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for (path, _, _) in &entries {
+            fs::remove_file(path)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Deletes the least recently written entries until the cache fits in
+    /// `max_bytes`.
+    fn evict(&self) -> Result<()> {
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= self.config.max_bytes {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, size, _) in entries {
+            if total <= self.config.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total = total.saturating_sub(size),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists entries as (path, size, modification time).
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = Vec::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(ENTRY_EXTENSION) {
+                continue;
+            }
+            let metadata = dir_entry.metadata()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((path, metadata.len(), modified));
+        }
+        Ok(entries)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, ENTRY_EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::{GraphContext, TokenBudget};
+    use crate::ObjectId;
+    use tempfile::TempDir;
+
+    fn pack(task: &str) -> PromptPack {
+        PromptPack {
+            task: task.to_string(),
+            head_commit: ObjectId::from_bytes([1; 32]),
+            retrieved: vec![],
+            graph_context: GraphContext {
+                seed_nodes: vec![],
+                expanded_nodes: vec![],
+                expansion_depth: 2,
+                scc_dag_used: false,
+            },
+            recent_narrative: "x".repeat(200),
+            token_budget: TokenBudget {
+                total: 100,
+                used: 0,
+                reserved_for_response: 10,
+                narrative: 50,
+            },
+            explanation: None,
+            unchanged: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_pack_cache_ttl() {
+        let tmp = TempDir::new().unwrap();
+        let config = CacheConfig {
+            ttl_secs: 60,
+            ..Default::default()
+        };
+        let cache = PackCache::new(tmp.path().join("packs"), config);
+
+        assert!(cache.get("abc", 1000).unwrap().is_none());
+        cache.put("abc", &pack("query"), 1000).unwrap();
+        assert_eq!(cache.get("abc", 1059).unwrap().unwrap().task, "query");
+        assert!(cache.get("abc", 1060).unwrap().is_none());
+        // Expired entries are removed
+        assert_eq!(cache.clear().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_pack_cache_evicts_over_size_limit() {
+        let tmp = TempDir::new().unwrap();
+        let cache = PackCache::new(tmp.path().join("packs"), CacheConfig::default());
+        cache.put("probe", &pack("probe"), 0).unwrap();
+        let entry_size = fs::metadata(cache.entry_path("probe")).unwrap().len();
+        cache.clear().unwrap();
+
+        let config = CacheConfig {
+            max_bytes: entry_size * 2,
+            ..Default::default()
+        };
+        let cache = PackCache::new(tmp.path().join("packs"), config);
+        for key in ["a", "b", "c"] {
+            cache.put(key, &pack("probe"), 0).unwrap();
+        }
+
+        let remaining = ["a", "b", "c"]
+            .iter()
+            .filter(|key| cache.get(key, 0).unwrap().is_some())
+            .count();
+        assert_eq!(remaining, 2);
+    }
+
+    #[test]
+    fn test_pack_cache_concurrent_puts() {
+        let tmp = TempDir::new().unwrap();
+        let cache = PackCache::new(tmp.path().join("packs"), CacheConfig::default());
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        cache.put("same", &pack("query"), 0).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(cache.get("same", 0).unwrap().unwrap().task, "query");
+        // No temp files are left behind
+        assert_eq!(fs::read_dir(tmp.path().join("packs")).unwrap().count(), 1);
+    }
+}
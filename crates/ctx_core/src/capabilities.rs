@@ -0,0 +1,145 @@
+//! Capability limits for repository handles given to agents.
+//!
+//! A host embedding ctx_core can hand an agent a handle made with
+//! [`CtxRepo::restricted`](crate::CtxRepo::restricted). The handle refuses
+//! what its [`Capabilities`] don't allow with
+//! [`CtxError::PermissionDenied`]:
+//!
+//! - Without `can_commit`, nothing that creates a commit, moves a ref,
+//!   deletes objects or changes the repository's files: compacting or
+//!   aborting a session, plain and analysis commits, decisions, facts,
+//!   answers, glossary terms, documents, imports and digests, summaries,
+//!   feedback, tags, annotations, resets, GC, pruning, backups and their
+//!   restores, clones, unlocking and index writes. The handle's
+//!   [narrative](crate::CtxRepo::narrative) is read-only, and
+//!   [`checked_object_store`](crate::CtxRepo::checked_object_store) and
+//!   [`checked_refs`](crate::CtxRepo::checked_refs) don't give out the
+//!   object store or refs. The agent can still stage its session for the
+//!   host to compact.
+//! - Without `can_write_files`, file writes aren't recorded.
+//! - With `readable_paths`, reads are only recorded for paths matching one
+//!   of the patterns, in `.ctxignore` syntax, and the handle gives out no
+//!   other path's content: packs and their replays leave those files out,
+//!   as do tree exports and diffs, and their histories and citations are
+//!   refused.
+//!
+//! [`object_store`](crate::CtxRepo::object_store) and
+//! [`refs`](crate::CtxRepo::refs) are raw access for the host and bypass
+//! every check, so code acting for an agent shouldn't call them.
+//!
+//! Restrictions only add up: a restricted handle restricted again must
+//! satisfy both. Checks happen before anything is stored, so a refused
+//! operation leaves the repository unchanged.
+
+use crate::error::{Capability, CtxError, Result};
+use crate::glob::glob_match;
+
+/// What a [restricted](crate::CtxRepo::restricted) handle may do.
+///
+/// The default allows everything.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctx_core::{Capabilities, CtxRepo};
+///
+/// let repo = CtxRepo::open(".").unwrap().restricted(Capabilities {
+///     can_commit: false,
+///     can_write_files: true,
+///     readable_paths: Some(vec!["src/".to_string(), "*.md".to_string()]),
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Create commits and move refs.
+    pub can_commit: bool,
+    /// Record file writes.
+    pub can_write_files: bool,
+    /// Patterns of the paths whose reads may be recorded, or None for
+    /// every path.
+    pub readable_paths: Option<Vec<String>>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            can_commit: true,
+            can_write_files: true,
+            readable_paths: None,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Capabilities for a handle that can observe reads but change nothing.
+    pub fn read_only() -> Self {
+        Self {
+            can_commit: false,
+            can_write_files: false,
+            readable_paths: None,
+        }
+    }
+
+    /// Returns true if reads of `path` may be recorded.
+    pub fn can_read(&self, path: &str) -> bool {
+        self.readable_paths.as_ref().map_or(true, |patterns| {
+            patterns.iter().any(|pattern| glob_match(pattern, path))
+        })
+    }
+
+    /// Returns `PermissionDenied` unless `capability` is allowed for
+    /// `target`, a path for reads and writes or an operation otherwise.
+    pub(crate) fn check(&self, capability: Capability, target: &str) -> Result<()> {
+        let allowed = match capability {
+            Capability::Commit => self.can_commit,
+            Capability::WriteFiles => self.can_write_files,
+            Capability::ReadPath => self.can_read(target),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(CtxError::PermissionDenied {
+                capability,
+                target: target.to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let caps = Capabilities {
+            can_commit: false,
+            can_write_files: true,
+            readable_paths: Some(vec!["src/".to_string(), "*.md".to_string()]),
+        };
+        assert!(caps.check(Capability::WriteFiles, "src/lib.rs").is_ok());
+        assert!(caps.check(Capability::ReadPath, "src/io/mod.rs").is_ok());
+        assert!(caps.check(Capability::ReadPath, "docs/guide.md").is_ok());
+
+        let err = caps.check(Capability::ReadPath, ".env").unwrap_err();
+        assert!(matches!(
+            err,
+            CtxError::PermissionDenied {
+                capability: Capability::ReadPath,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "permission denied: this handle can't read (.env)"
+        );
+        assert!(caps.check(Capability::Commit, "commit").is_err());
+
+        assert!(Capabilities::default()
+            .check(Capability::ReadPath, ".env")
+            .is_ok());
+        assert!(Capabilities::read_only()
+            .check(Capability::WriteFiles, "src/lib.rs")
+            .is_err());
+    }
+}
@@ -5,6 +5,15 @@
 //! - Parse the JSON output into deterministically serializable types
 //! - Generate relationship edges between packages, targets, and files
 //!
+//! By default only workspace members are read (`cargo metadata --no-deps`).
+//! [`run_cargo_metadata_full`] also resolves the transitive dependency
+//! graph, so external crates get versioned package nodes and
+//! [`CargoMetadataSnapshot::why`] can explain how a crate got into the tree.
+//!
+//! Snapshots record the content hashes of the workspace's manifests, so
+//! [`CargoMetadataSnapshot::changed_manifests`] can tell when a
+//! `Cargo.toml` or `Cargo.lock` edit has made one stale.
+//!
 //! # Example
 //!
 //! ```no_run
@@ -16,11 +25,12 @@
 //! ```
 
 use crate::error::{CtxError, Result};
+use crate::policy::ExecPolicy;
 use crate::types::{Confidence, Edge, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind};
 use crate::ObjectId;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::process::Command;
 
@@ -29,12 +39,127 @@ use std::process::Command;
 pub struct CargoMetadataSnapshot {
     /// Workspace root path.
     pub workspace_root: String,
-    /// Packages in workspace (sorted by name for determinism).
+    /// Packages in workspace (sorted by name for determinism), and with a
+    /// full resolve every package in the dependency graph.
     pub packages: Vec<Package>,
-    /// Resolved dependency graph (optional).
+    /// Resolved dependency graph (only with a full resolve).
     pub resolve: Option<Resolve>,
     /// Metadata format version.
     pub metadata_version: u32,
+    /// Package IDs of the workspace members (sorted).
+    pub workspace_members: Vec<String>,
+    /// Content hashes of the root and member `Cargo.toml` files and
+    /// `Cargo.lock` when the snapshot was taken, keyed by path relative to
+    /// the workspace root. Missing files are left out.
+    pub manifest_hashes: BTreeMap<String, ObjectId>,
+}
+
+/// How a workspace member comes to depend on a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyChain {
+    /// Packages from the workspace member to the package asked about, as
+    /// `name@version`.
+    pub packages: Vec<String>,
+    /// Features enabled on the package after unification across the
+    /// whole graph (sorted).
+    pub features: Vec<String>,
+}
+
+impl CargoMetadataSnapshot {
+    /// Returns true if `package` is a workspace member. Snapshots without
+    /// recorded members treat every package as one.
+    pub fn is_workspace_member(&self, package: &Package) -> bool {
+        self.workspace_members.is_empty() || self.workspace_members.contains(&package.id)
+    }
+
+    /// Records the current content hashes of the workspace manifests in
+    /// `manifest_hashes`.
+    pub fn record_manifest_hashes(&mut self) {
+        self.manifest_hashes = self
+            .manifest_paths()
+            .into_iter()
+            .filter_map(|path| Some((path.clone(), self.manifest_hash(&path)?)))
+            .collect();
+    }
+
+    /// Manifests that changed, appeared or disappeared since the hashes
+    /// were recorded (sorted). Non-empty means the snapshot is stale.
+    pub fn changed_manifests(&self) -> Vec<String> {
+        let mut paths = self.manifest_paths();
+        paths.extend(self.manifest_hashes.keys().cloned());
+        paths
+            .into_iter()
+            .filter(|path| self.manifest_hashes.get(path).copied() != self.manifest_hash(path))
+            .collect()
+    }
+
+    /// Manifests relative to the workspace root: the root `Cargo.toml`
+    /// and `Cargo.lock`, and each member's `Cargo.toml`.
+    fn manifest_paths(&self) -> BTreeSet<String> {
+        let root = Path::new(&self.workspace_root);
+        let mut paths = BTreeSet::from(["Cargo.toml".to_string(), "Cargo.lock".to_string()]);
+        for package in self.packages.iter().filter(|p| self.is_workspace_member(p)) {
+            if let Ok(relative) = Path::new(&package.manifest_path).strip_prefix(root) {
+                paths.insert(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        paths
+    }
+
+    /// Hash of the manifest at `path` under the workspace root, or None if
+    /// it can't be read.
+    fn manifest_hash(&self, path: &str) -> Option<ObjectId> {
+        let content = std::fs::read(Path::new(&self.workspace_root).join(path)).ok()?;
+        Some(ObjectId::hash_blob(&content))
+    }
+
+    /// Explains why packages named `name` are in the dependency graph:
+    /// the shortest chain from each workspace member reaching each of them.
+    ///
+    /// Empty without a full resolve, or if nothing depends on `name`.
+    pub fn why(&self, name: &str) -> Vec<DependencyChain> {
+        let Some(resolve) = &self.resolve else {
+            return Vec::new();
+        };
+        let nodes: BTreeMap<&str, &ResolveNode> =
+            resolve.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+        let label = |id: &str| {
+            self.packages
+                .iter()
+                .find(|p| p.id == id)
+                .map_or_else(|| id.to_string(), |p| format!("{}@{}", p.name, p.version))
+        };
+        let is_target = |id: &str| self.packages.iter().any(|p| p.id == id && p.name == name);
+
+        let mut chains = Vec::new();
+        for member in &self.workspace_members {
+            // Breadth-first, so the first way found to each package is shortest
+            let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+            let mut queue = std::collections::VecDeque::from([member.as_str()]);
+            let mut seen = BTreeSet::from([member.as_str()]);
+            while let Some(id) = queue.pop_front() {
+                if id != member && is_target(id) {
+                    let mut path = vec![id];
+                    while let Some(&prev) = previous.get(path[path.len() - 1]) {
+                        path.push(prev);
+                    }
+                    path.reverse();
+                    chains.push(DependencyChain {
+                        packages: path.into_iter().map(label).collect(),
+                        features: nodes.get(id).map_or_else(Vec::new, |n| n.features.clone()),
+                    });
+                    continue;
+                }
+                for dep in nodes.get(id).map_or(&[][..], |n| &n.deps) {
+                    if seen.insert(dep.pkg.as_str()) {
+                        previous.insert(&dep.pkg, id);
+                        queue.push_back(&dep.pkg);
+                    }
+                }
+            }
+        }
+        chains
+    }
 }
 
 /// A Cargo package.
@@ -199,7 +324,7 @@ pub struct DepKindInfo {
 }
 
 /// Report from cargo analysis.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CargoAnalysisReport {
     /// Number of packages found.
     pub packages_found: usize,
@@ -209,23 +334,20 @@ pub struct CargoAnalysisReport {
     pub dependencies_found: usize,
     /// Number of edges generated.
     pub edges_generated: usize,
+    /// Number of packages in the resolved dependency graph (0 unless the
+    /// analysis was full).
+    pub resolved_packages: usize,
     /// ObjectId of the stored snapshot.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
     pub snapshot_id: ObjectId,
     /// ObjectId of the stored EdgeBatch.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
     pub edge_batch_id: ObjectId,
     /// ObjectId of the created commit.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
     pub commit_id: ObjectId,
 }
 
-/// Check if cargo is available.
-pub fn is_available() -> bool {
-    Command::new("cargo")
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
-}
-
 /// Run `cargo metadata` and return raw JSON.
 ///
 /// # Errors
@@ -234,21 +356,43 @@ pub fn is_available() -> bool {
 /// - Cargo.toml doesn't exist in the path
 /// - cargo is not installed
 /// - cargo metadata command fails
-pub fn run_cargo_metadata(path: &Path) -> Result<String> {
+/// - `policy` refuses to run cargo
+pub fn run_cargo_metadata(path: &Path, policy: &ExecPolicy) -> Result<String> {
+    cargo_metadata(path, policy, false)
+}
+
+/// Run `cargo metadata` with dependencies and return raw JSON, including
+/// the resolved transitive dependency graph.
+///
+/// Slower than [`run_cargo_metadata`], and may need the network to fetch
+/// dependency manifests.
+///
+/// # Errors
+///
+/// Returns the errors [`run_cargo_metadata`] does.
+pub fn run_cargo_metadata_full(path: &Path, policy: &ExecPolicy) -> Result<String> {
+    cargo_metadata(path, policy, true)
+}
+
+fn cargo_metadata(path: &Path, policy: &ExecPolicy, with_deps: bool) -> Result<String> {
     // Check for Cargo.toml
     let manifest = path.join("Cargo.toml");
     if !manifest.exists() {
         return Err(CtxError::NoCargoManifest(path.display().to_string()));
     }
 
-    let output = Command::new("cargo")
-        .arg("metadata")
-        .arg("--format-version")
-        .arg("1")
-        .arg("--no-deps") // Faster, workspace only
-        .current_dir(path)
-        .output()
-        .map_err(|e| CtxError::CargoMetadataFailed(e.to_string()))?;
+    let mut command = Command::new("cargo");
+    command.arg("metadata").arg("--format-version").arg("1");
+    if !with_deps {
+        command.arg("--no-deps"); // Faster, workspace only
+    }
+    let output = policy
+        .output(command.current_dir(path))
+        .map_err(|e| match e {
+            CtxError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => CtxError::CargoNotFound,
+            CtxError::Io(e) => CtxError::CargoMetadataFailed(e.to_string()),
+            other => other,
+        })?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -300,11 +444,24 @@ pub fn parse_cargo_metadata(json: &str) -> Result<CargoMetadataSnapshot> {
         None
     };
 
+    let mut workspace_members: Vec<String> = value["workspace_members"]
+        .as_array()
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    workspace_members.sort();
+
     Ok(CargoMetadataSnapshot {
         workspace_root,
         packages,
         resolve,
         metadata_version,
+        workspace_members,
+        manifest_hashes: BTreeMap::new(),
     })
 }
 
@@ -555,10 +712,65 @@ fn parse_resolved_dep(val: &Value) -> Result<ResolvedDep> {
 /// - Target → TargetOf → Package (target membership)
 /// - Crate → CrateFromTarget → Target (for lib/proc-macro targets)
 /// - File → Contains → Target (source file entry points)
+///
+/// With a full resolve, also Package → DependsOn → Package between the
+/// resolved `name@version` of every package in the graph, including
+/// external crates. Targets are only extracted for workspace members.
 pub fn extract_cargo_edges(snapshot: &CargoMetadataSnapshot, commit_id: ObjectId) -> Vec<Edge> {
     let mut edges = Vec::new();
+    let evidence = Evidence {
+        commit_id,
+        tool: EvidenceTool::Cargo,
+        confidence: Confidence::High,
+        span: None,
+        blob_id: None,
+        condition: None,
+    };
 
-    for package in &snapshot.packages {
+    // Package → DependsOn → Package, as resolved
+    if let Some(resolve) = &snapshot.resolve {
+        let versioned: BTreeMap<&str, String> = snapshot
+            .packages
+            .iter()
+            .map(|p| (p.id.as_str(), format!("{}@{}", p.name, p.version)))
+            .collect();
+        for node in &resolve.nodes {
+            let Some(from) = versioned.get(node.id.as_str()) else {
+                continue;
+            };
+            for dep in &node.deps {
+                let Some(to) = versioned.get(dep.pkg.as_str()) else {
+                    continue;
+                };
+                // Strongest of the ways it is depended on
+                let weight = dep
+                    .dep_kinds
+                    .iter()
+                    .map(|k| dep_weight(k.kind))
+                    .max()
+                    .unwrap_or(dep_weight(DepKind::Normal));
+                edges.push(Edge {
+                    from: NodeId {
+                        kind: NodeKind::Package,
+                        id: from.clone(),
+                    },
+                    to: NodeId {
+                        kind: NodeKind::Package,
+                        id: to.clone(),
+                    },
+                    label: EdgeLabel::DependsOn,
+                    weight: Some(weight),
+                    evidence: evidence.clone(),
+                });
+            }
+        }
+    }
+
+    for package in snapshot
+        .packages
+        .iter()
+        .filter(|p| snapshot.is_workspace_member(p))
+    {
         let pkg_id = format!("{}@{}", package.name, package.version);
 
         // Package → DependsOn → Package
@@ -574,17 +786,14 @@ pub fn extract_cargo_edges(snapshot: &CargoMetadataSnapshot, commit_id: ObjectId
                     id: dep_pkg.clone(),
                 },
                 label: EdgeLabel::DependsOn,
-                weight: Some(match dep.kind {
-                    DepKind::Normal => 1000, // Strong coupling
-                    DepKind::Build => 500,   // Build-time only
-                    DepKind::Dev => 200,     // Test/dev only
-                }),
+                weight: Some(dep_weight(dep.kind)),
                 evidence: Evidence {
                     commit_id,
                     tool: EvidenceTool::Cargo,
                     confidence: Confidence::High,
                     span: None,
                     blob_id: None,
+                    condition: dependency_condition(package, dep),
                 },
             });
         }
@@ -611,6 +820,7 @@ pub fn extract_cargo_edges(snapshot: &CargoMetadataSnapshot, commit_id: ObjectId
                     confidence: Confidence::High,
                     span: None,
                     blob_id: None,
+                    condition: None,
                 },
             });
 
@@ -634,6 +844,7 @@ pub fn extract_cargo_edges(snapshot: &CargoMetadataSnapshot, commit_id: ObjectId
                         confidence: Confidence::High,
                         span: None,
                         blob_id: None,
+                        condition: None,
                     },
                 });
             }
@@ -656,6 +867,7 @@ pub fn extract_cargo_edges(snapshot: &CargoMetadataSnapshot, commit_id: ObjectId
                     confidence: Confidence::High,
                     span: None,
                     blob_id: None,
+                    condition: None,
                 },
             });
         }
@@ -667,6 +879,59 @@ pub fn extract_cargo_edges(snapshot: &CargoMetadataSnapshot, commit_id: ObjectId
     edges
 }
 
+/// The `cfg` condition `dep` of `package` is only built under: one of the
+/// features enabling it if it is optional, and its target platform.
+fn dependency_condition(package: &Package, dep: &PackageDep) -> Option<String> {
+    let mut conditions = Vec::new();
+    if dep.optional {
+        let explicit = format!("dep:{}", dep.name);
+        let enables = |value: &String| {
+            *value == explicit
+                || *value == dep.name
+                || value
+                    .strip_prefix(dep.name.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        };
+        let mut features: Vec<String> = package
+            .features
+            .iter()
+            .filter(|(_, values)| values.iter().any(enables))
+            .map(|(feature, _)| format!("feature = \"{}\"", feature))
+            .collect();
+        // Without `dep:` syntax, the dependency is a feature of its own
+        if !package
+            .features
+            .values()
+            .flatten()
+            .any(|value| *value == explicit)
+        {
+            features.push(format!("feature = \"{}\"", dep.name));
+        }
+        conditions.extend(crate::cfg::any_of(features));
+    }
+    if let Some(target) = &dep.target {
+        conditions.push(
+            match target
+                .strip_prefix("cfg(")
+                .and_then(|t| t.strip_suffix(')'))
+            {
+                Some(predicate) => predicate.to_string(),
+                None => format!("target = \"{}\"", target),
+            },
+        );
+    }
+    crate::cfg::all_of(conditions)
+}
+
+/// Weight of a DependsOn edge for a dependency of `kind`.
+fn dep_weight(kind: DepKind) -> u32 {
+    match kind {
+        DepKind::Normal => 1000, // Strong coupling
+        DepKind::Build => 500,   // Build-time only
+        DepKind::Dev => 200,     // Test/dev only
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -706,6 +971,8 @@ mod tests {
             packages: vec![],
             resolve: None,
             metadata_version: 1,
+            workspace_members: vec![],
+            manifest_hashes: BTreeMap::new(),
         };
 
         // Same content = same ID
@@ -748,6 +1015,8 @@ mod tests {
             }],
             resolve: None,
             metadata_version: 1,
+            workspace_members: vec![],
+            manifest_hashes: BTreeMap::new(),
         };
 
         let edges = extract_cargo_edges(&snapshot, commit_id);
@@ -769,4 +1038,158 @@ mod tests {
             assert_eq!(edge.evidence.confidence, Confidence::High);
         }
     }
+
+    #[test]
+    fn test_resolved_edges_and_why() {
+        let package = |name: &str| Package {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            id: format!("{} 1.0.0", name),
+            manifest_path: format!("/test/{}/Cargo.toml", name),
+            edition: "2021".to_string(),
+            targets: vec![],
+            features: BTreeMap::new(),
+            dependencies: vec![],
+            default_features: vec![],
+        };
+        let dep = |name: &str, kind: DepKind| ResolvedDep {
+            pkg: format!("{} 1.0.0", name),
+            name: name.to_string(),
+            dep_kinds: vec![DepKindInfo { kind, target: None }],
+        };
+        let node = |name: &str, deps: Vec<ResolvedDep>, features: &[&str]| ResolveNode {
+            id: format!("{} 1.0.0", name),
+            deps,
+            features: features.iter().map(|f| f.to_string()).collect(),
+        };
+        let snapshot = CargoMetadataSnapshot {
+            workspace_root: "/test".to_string(),
+            packages: vec![package("app"), package("a"), package("b")],
+            resolve: Some(Resolve {
+                root: None,
+                nodes: vec![
+                    node("app", vec![dep("a", DepKind::Normal)], &[]),
+                    node("a", vec![dep("b", DepKind::Dev)], &[]),
+                    node("b", vec![], &["default", "std"]),
+                ],
+            }),
+            metadata_version: 1,
+            workspace_members: vec!["app 1.0.0".to_string()],
+            manifest_hashes: BTreeMap::new(),
+        };
+
+        let edges = extract_cargo_edges(&snapshot, ObjectId::from_bytes([1; 32]));
+        let depends_on: Vec<_> = edges
+            .iter()
+            .filter(|e| e.label == EdgeLabel::DependsOn)
+            .map(|e| (e.from.id.as_str(), e.to.id.as_str(), e.weight))
+            .collect();
+        assert_eq!(
+            depends_on,
+            vec![
+                ("a@1.0.0", "b@1.0.0", Some(200)),
+                ("app@1.0.0", "a@1.0.0", Some(1000)),
+            ]
+        );
+
+        let chains = snapshot.why("b");
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].packages, vec!["app@1.0.0", "a@1.0.0", "b@1.0.0"]);
+        assert_eq!(chains[0].features, vec!["default", "std"]);
+        assert!(snapshot.why("app").is_empty());
+    }
+
+    #[test]
+    fn test_dependency_condition() {
+        let dep = |name: &str, optional, target: Option<&str>| PackageDep {
+            name: name.to_string(),
+            package: None,
+            req: "^1".to_string(),
+            kind: DepKind::Normal,
+            optional,
+            target: target.map(str::to_string),
+            features: vec![],
+            default_features: true,
+        };
+        let package = Package {
+            name: "app".to_string(),
+            version: "0.1.0".to_string(),
+            id: "app 0.1.0".to_string(),
+            manifest_path: "/test/Cargo.toml".to_string(),
+            edition: "2021".to_string(),
+            targets: vec![],
+            features: BTreeMap::from([
+                ("async".to_string(), vec!["dep:tokio".to_string()]),
+                (
+                    "full".to_string(),
+                    vec!["async".to_string(), "serde/derive".to_string()],
+                ),
+            ]),
+            dependencies: vec![],
+            default_features: vec![],
+        };
+
+        let condition = |dep: &PackageDep| dependency_condition(&package, dep);
+        assert_eq!(condition(&dep("regex", false, None)), None);
+        assert_eq!(
+            condition(&dep("tokio", true, None)).as_deref(),
+            Some(r#"feature = "async""#)
+        );
+        assert_eq!(
+            condition(&dep("serde", true, None)).as_deref(),
+            Some(r#"any(feature = "full", feature = "serde")"#)
+        );
+        assert_eq!(
+            condition(&dep("libc", false, Some("cfg(unix)"))).as_deref(),
+            Some("unix")
+        );
+    }
+
+    #[test]
+    fn test_changed_manifests() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("crates/core")).unwrap();
+        std::fs::write(root.join("Cargo.toml"), "[workspace]").unwrap();
+        std::fs::write(root.join("crates/core/Cargo.toml"), "[package]").unwrap();
+        let mut snapshot = CargoMetadataSnapshot {
+            workspace_root: root.to_string_lossy().to_string(),
+            packages: vec![Package {
+                name: "core".to_string(),
+                version: "0.1.0".to_string(),
+                id: "core 0.1.0".to_string(),
+                manifest_path: root
+                    .join("crates/core/Cargo.toml")
+                    .to_string_lossy()
+                    .to_string(),
+                edition: "2021".to_string(),
+                targets: vec![],
+                features: BTreeMap::new(),
+                dependencies: vec![],
+                default_features: vec![],
+            }],
+            resolve: None,
+            metadata_version: 1,
+            workspace_members: vec![],
+            manifest_hashes: BTreeMap::new(),
+        };
+        snapshot.record_manifest_hashes();
+        assert_eq!(
+            snapshot.manifest_hashes.keys().collect::<Vec<_>>(),
+            vec!["Cargo.toml", "crates/core/Cargo.toml"]
+        );
+        assert!(snapshot.changed_manifests().is_empty());
+
+        // Edits, and a lock file appearing, make the snapshot stale
+        std::fs::write(
+            root.join("crates/core/Cargo.toml"),
+            "[package]\nname = \"core\"",
+        )
+        .unwrap();
+        std::fs::write(root.join("Cargo.lock"), "version = 3").unwrap();
+        assert_eq!(
+            snapshot.changed_manifests(),
+            vec!["Cargo.lock", "crates/core/Cargo.toml"]
+        );
+    }
 }
@@ -0,0 +1,251 @@
+//! `cfg` conditions on edges.
+//!
+//! Code behind `#[cfg(feature = "x")]` and optional or target-specific
+//! dependencies only exist in some builds. Edges extracted from them record
+//! the condition as a `cfg` predicate string in
+//! [`Evidence::condition`](crate::Evidence), and retrieval can drop edges
+//! whose condition fails for the features it was told are active.
+//!
+//! Only `feature = "..."` predicates can be decided from a feature set.
+//! Anything else (`unix`, `test`, `target_os = "..."`) is unknown, and an
+//! unknown condition never excludes an edge.
+
+use std::collections::BTreeSet;
+
+/// Returns false only if `condition` is known to fail with exactly
+/// `features` enabled. Conditions that can't be parsed hold.
+pub(crate) fn condition_holds(condition: &str, features: &BTreeSet<String>) -> bool {
+    let mut parser = Parser {
+        input: condition,
+        pos: 0,
+    };
+    match parser.predicate() {
+        Some(cfg) if parser.at_end() => cfg.evaluate(features) != Some(false),
+        _ => true,
+    }
+}
+
+/// Combines conditions that must all hold into one, or None if there are
+/// none.
+pub(crate) fn all_of(conditions: Vec<String>) -> Option<String> {
+    let mut conditions: Vec<String> = conditions
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    match conditions.len() {
+        0 => None,
+        1 => conditions.pop(),
+        _ => Some(format!("all({})", conditions.join(", "))),
+    }
+}
+
+/// Combines conditions of which any one suffices, or None if there are
+/// none.
+pub(crate) fn any_of(conditions: Vec<String>) -> Option<String> {
+    let mut conditions: Vec<String> = conditions
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    match conditions.len() {
+        0 => None,
+        1 => conditions.pop(),
+        _ => Some(format!("any({})", conditions.join(", "))),
+    }
+}
+
+/// The condition of the `#[cfg(...)]` attributes directly above the
+/// declaration on `line` (0-based) of `source`, or None if it has none.
+pub(crate) fn declaration_condition(source: &str, line: u32) -> Option<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let above = lines.get(..line as usize)?;
+    let conditions = above
+        .iter()
+        .rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("#[") || line.starts_with("///"))
+        .filter_map(attribute_condition)
+        .collect();
+    all_of(conditions)
+}
+
+/// The predicate of a `#[cfg(...)]` attribute line.
+pub(crate) fn attribute_condition(line: &str) -> Option<String> {
+    let inner = line.trim().strip_prefix("#[cfg(")?;
+    let inner = inner.trim_end().strip_suffix(")]")?;
+    Some(inner.trim().to_string())
+}
+
+/// A parsed `cfg` predicate.
+#[derive(Debug, PartialEq, Eq)]
+enum Cfg {
+    Feature(String),
+    Other,
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+    Not(Box<Cfg>),
+}
+
+impl Cfg {
+    /// Some(true/false) if decided by `features`, None if unknown.
+    fn evaluate(&self, features: &BTreeSet<String>) -> Option<bool> {
+        match self {
+            Cfg::Feature(name) => Some(features.contains(name)),
+            Cfg::Other => None,
+            Cfg::Not(inner) => inner.evaluate(features).map(|value| !value),
+            Cfg::All(items) => {
+                let values: Vec<_> = items.iter().map(|c| c.evaluate(features)).collect();
+                if values.contains(&Some(false)) {
+                    Some(false)
+                } else if values.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            Cfg::Any(items) => {
+                let values: Vec<_> = items.iter().map(|c| c.evaluate(features)).collect();
+                if values.contains(&Some(true)) {
+                    Some(true)
+                } else if values.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.rest().is_empty()
+    }
+
+    fn eat(&mut self, token: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Option<&str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return None;
+        }
+        let start = self.pos;
+        self.pos += len;
+        Some(&self.input[start..self.pos])
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let rest = self.rest().strip_prefix('"')?;
+        let end = rest.find('"')?;
+        let value = rest[..end].to_string();
+        self.pos += end + 2;
+        Some(value)
+    }
+
+    fn predicate(&mut self) -> Option<Cfg> {
+        let name = self.ident()?.to_string();
+        if self.eat('=') {
+            let value = self.string()?;
+            return Some(if name == "feature" {
+                Cfg::Feature(value)
+            } else {
+                Cfg::Other
+            });
+        }
+        if !self.eat('(') {
+            return Some(Cfg::Other);
+        }
+        let mut items = Vec::new();
+        while !self.eat(')') {
+            items.push(self.predicate()?);
+            if !self.eat(',') {
+                if !self.eat(')') {
+                    return None;
+                }
+                break;
+            }
+        }
+        match name.as_str() {
+            "all" => Some(Cfg::All(items)),
+            "any" => Some(Cfg::Any(items)),
+            "not" if items.len() == 1 => items.pop().map(|item| Cfg::Not(Box::new(item))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_condition_holds() {
+        let active = features(&["tokio"]);
+        assert!(condition_holds(r#"feature = "tokio""#, &active));
+        assert!(!condition_holds(r#"feature = "s3""#, &active));
+        assert!(condition_holds(r#"not(feature = "s3")"#, &active));
+        assert!(!condition_holds(
+            r#"all(feature = "tokio", feature = "s3")"#,
+            &active
+        ));
+        assert!(condition_holds(
+            r#"any(feature = "tokio", feature = "s3")"#,
+            &active
+        ));
+
+        // Predicates other than features are unknown and never exclude
+        assert!(condition_holds("test", &active));
+        assert!(condition_holds("not(unix)", &active));
+        assert!(condition_holds(r#"all(unix, feature = "tokio")"#, &active));
+        assert!(!condition_holds(r#"all(unix, feature = "s3")"#, &active));
+        assert!(condition_holds("all(unix,", &active));
+    }
+
+    #[test]
+    fn test_declaration_condition() {
+        let source = "#[cfg(feature = \"tokio\")]\nmod async_repo;\n\n/// Docs\n#[cfg(test)]\n#[allow(dead_code)]\nfn helper() {}\nfn plain() {}\n";
+        assert_eq!(
+            declaration_condition(source, 1).as_deref(),
+            Some(r#"feature = "tokio""#)
+        );
+        assert_eq!(declaration_condition(source, 6).as_deref(), Some("test"));
+        assert_eq!(declaration_condition(source, 7), None);
+        assert_eq!(
+            all_of(vec!["test".to_string(), "unix".to_string()]).as_deref(),
+            Some("all(test, unix)")
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Content-defined chunking for large blobs.
+//!
+//! Blobs over [`MAX_CHUNK_SIZE`] are split into chunks whose boundaries
+//! depend on the content itself (FastCDC with a gear rolling hash), not on
+//! fixed offsets. An insertion near the start of a file only changes the
+//! chunks around it, so files that share regions (generated code, lockfiles
+//! across branches) share most of their chunk objects.
+//!
+//! Cut points use normalized chunking: a stricter mask before
+//! [`AVG_CHUNK_SIZE`] and a looser one after it keep chunk sizes close to
+//! the average.
+
+/// Smallest chunk, except for the last one (16 KiB).
+pub(crate) const MIN_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Target chunk size (64 KiB).
+pub(crate) const AVG_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest chunk (256 KiB). Blobs up to this size are never chunked.
+pub(crate) const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Mask checked before the average size: two more bits than the average
+/// needs, so cuts there are rarer.
+const MASK_SMALL: u64 = mask(18);
+
+/// Mask checked after the average size: two fewer bits, so cuts are likelier.
+const MASK_LARGE: u64 = mask(14);
+
+/// Random values the rolling hash adds for each byte.
+const GEAR: [u64; 256] = gear_table();
+
+/// A mask of the `bits` highest bits, which depend on the most recent 64
+/// bytes of the gear hash.
+const fn mask(bits: u32) -> u64 {
+    !0u64 << (64 - bits)
+}
+
+/// Generates the gear table with splitmix64, so it is the same everywhere
+/// and chunk boundaries (and so chunk IDs) are stable.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6374_785f_6364_6331; // "ctx_cdc1"
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Splits `data` into content-defined chunks, in order.
+///
+/// Every chunk is at most [`MAX_CHUNK_SIZE`] bytes and all but the last are
+/// at least [`MIN_CHUNK_SIZE`]. Empty input gives no chunks.
+pub(crate) fn chunks(data: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = Vec::with_capacity(data.len() / AVG_CHUNK_SIZE + 1);
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (chunk, tail) = rest.split_at(cut_point(rest));
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+/// Returns the length of the first chunk of `data`.
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let normal = end.min(AVG_CHUNK_SIZE);
+
+    let mut hash = 0u64;
+    let mut i = MIN_CHUNK_SIZE;
+    while i < normal {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & MASK_SMALL == 0 {
+            return i + 1;
+        }
+        i += 1;
+    }
+    while i < end {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & MASK_LARGE == 0 {
+            return i + 1;
+        }
+        i += 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes.
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks_are_content_defined() {
+        assert!(chunks(b"").is_empty());
+        assert_eq!(chunks(b"small"), vec![b"small".as_slice()]);
+
+        let data = noise(2 * 1024 * 1024, 1);
+        let original = chunks(&data);
+        assert_eq!(original.concat(), data);
+        assert!(original.len() > 8);
+        assert!(original.iter().all(|chunk| chunk.len() <= MAX_CHUNK_SIZE));
+        let (last, rest) = original.split_last().unwrap();
+        assert!(!last.is_empty());
+        assert!(rest.iter().all(|chunk| chunk.len() >= MIN_CHUNK_SIZE));
+
+        // Inserting bytes at the start only changes the first chunk or two
+        let mut shifted = b"inserted header\n".to_vec();
+        shifted.extend_from_slice(&data);
+        let shifted = chunks(&shifted);
+        let shared = shifted.iter().filter(|c| original.contains(c)).count();
+        assert!(
+            shared >= original.len() - 2,
+            "{} of {}",
+            shared,
+            original.len()
+        );
+
+        // Uniform content has no cut points, so chunks are the largest size
+        let zeros = vec![0u8; MAX_CHUNK_SIZE * 2 + 1];
+        let lens: Vec<usize> = chunks(&zeros).iter().map(|c| c.len()).collect();
+        assert_eq!(lens, vec![MAX_CHUNK_SIZE, MAX_CHUNK_SIZE, 1]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Citing the chunks of a prompt pack.
+//!
+//! Every [`RetrievedChunk`](crate::RetrievedChunk) carries a [`Citation`]:
+//! the object its snippet came from and the lines of that object it covers.
+//! Rendered packs number their chunks, so a model can answer "see [3]", and
+//! [`CtxRepo::resolve_citation`](crate::CtxRepo::resolve_citation) maps the
+//! number back to its source using the pack's
+//! [`PackRecord`](crate::PackRecord).
+
+use crate::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Where a chunk's snippet comes from.
+///
+/// The same content always gets the same citation, so it stays valid
+/// across packs and rebuilds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    /// The blob or typed object the snippet came from.
+    pub object_id: ObjectId,
+    /// First line of the object the snippet covers (1-based).
+    pub start_line: u32,
+    /// Last line of the object the snippet covers (inclusive).
+    pub end_line: u32,
+}
+
+impl Citation {
+    /// The citation for a snippet holding all of `object_id`'s text.
+    pub fn whole(object_id: ObjectId, snippet: &str) -> Self {
+        Self {
+            object_id,
+            start_line: 1,
+            end_line: snippet.lines().count().max(1) as u32,
+        }
+    }
+}
+
+impl fmt::Display for Citation {
+    /// Short form, e.g. `3f2a9c01b7de:1-40`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}",
+            &self.object_id.as_hex()[..12],
+            self.start_line,
+            self.end_line
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_citation() {
+        let citation = Citation::whole(ObjectId::from_bytes([0xab; 32]), "one\ntwo\nthree\n");
+        assert_eq!((citation.start_line, citation.end_line), (1, 3));
+        assert_eq!(citation.to_string(), "abababababab:1-3");
+        assert_eq!(
+            Citation::whole(ObjectId::from_bytes([0; 32]), "").end_line,
+            1
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Copying a repository into a new one.
+//!
+//! [`CtxRepo::clone_to`](crate::CtxRepo::clone_to) copies what a
+//! repository needs into a fresh `.ctx`: the objects reachable from its
+//! refs, the refs themselves, the narrative, hooks and configuration.
+//! Objects are immutable, so they're hard-linked when both repositories
+//! are on the same filesystem and copied otherwise. The lock, the ref
+//! journal, the index, caches, backups and the event log belong to the
+//! source and aren't copied; the clone's index is built from its objects.
+//!
+//! A shallow clone keeps the last `depth` commits of HEAD's first-parent
+//! history. The parents it leaves out are recorded as grafts in the
+//! clone's object store (see [`ObjectStore::grafts`]), where history walks
+//! stop, and named refs pointing to commits outside the kept history are
+//! dropped.
+
+use crate::error::{CtxError, Result};
+use crate::gc::{collect_roots, mark_reachable, GcReport};
+use crate::object_id::ObjectId;
+use crate::object_store::{Grafts, ObjectStore};
+use crate::progress::Progress;
+use crate::refs::Refs;
+use crate::types::Commit;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files in `.ctx` copied to a clone.
+const FILES: [&str; 2] = ["config.toml", ".gitignore"];
+
+/// Directories in `.ctx` copied to a clone.
+const DIRECTORIES: [&str; 2] = ["narrative", "hooks"];
+
+/// Outcome of [`CtxRepo::clone_to`](crate::CtxRepo::clone_to).
+#[derive(Debug, Clone, Serialize)]
+pub struct CloneReport {
+    /// Root of the new repository.
+    pub destination: PathBuf,
+    /// The commit HEAD points to in both repositories.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub head: ObjectId,
+    /// Objects in the clone.
+    pub objects: usize,
+    /// Of those, how many were hard-linked to the source's files.
+    pub linked: usize,
+    /// And how many were copied.
+    pub copied: usize,
+    /// Stored (compressed) size of the objects.
+    pub bytes: u64,
+    /// Refs copied, by the names `HEAD`, `STAGE`, ... and `refs/<name>`.
+    pub refs: Vec<String>,
+    /// Named refs a shallow clone dropped because they point outside the
+    /// history it kept.
+    pub skipped_refs: Vec<String>,
+    /// Narrative, hook and configuration files copied.
+    pub files: usize,
+    /// Commits kept, if the clone is shallow.
+    pub depth: Option<usize>,
+    /// Commits the clone left out, where its history ends.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_vec")]
+    pub grafts: Vec<ObjectId>,
+}
+
+/// Clones the repository in `source` (its `.ctx` directory) with `refs`
+/// and `store` into `dest`, which must not exist yet. Leaves the index for
+/// the caller to build.
+pub(crate) fn clone(
+    source: &Path,
+    refs: &Refs,
+    store: &ObjectStore,
+    dest: &Path,
+    depth: Option<usize>,
+) -> Result<CloneReport> {
+    if depth == Some(0) {
+        return Err(CtxError::InvalidArgument(
+            "a shallow clone needs a depth of at least 1".to_string(),
+        ));
+    }
+    let dest_ctx = dest.join(".ctx");
+    if dest_ctx.exists() {
+        return Err(CtxError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("CTX repository already exists in {}", dest.display()),
+        )));
+    }
+
+    let cloned = clone_into(source, refs, store, dest, &dest_ctx, depth);
+    if cloned.is_err() {
+        let _ = fs::remove_dir_all(&dest_ctx);
+    }
+    cloned
+}
+
+fn clone_into(
+    source: &Path,
+    refs: &Refs,
+    store: &ObjectStore,
+    dest: &Path,
+    dest_ctx: &Path,
+    depth: Option<usize>,
+) -> Result<CloneReport> {
+    fs::create_dir_all(dest_ctx.join("index"))?;
+    let head = refs.read_head()?;
+
+    // Commits a shallow clone keeps, and the parents it leaves out
+    let source_grafts = store.grafts();
+    let mut grafts: BTreeMap<ObjectId, Option<ObjectId>> = source_grafts
+        .iter()
+        .map(|(id, stand_in)| (*id, *stand_in))
+        .collect();
+    let kept = match depth {
+        Some(depth) => {
+            let kept = first_parent_history(store, head, depth)?;
+            for id in &kept {
+                let commit: Commit = store.get_typed(*id)?;
+                for parent in &commit.parents {
+                    let followed = source_grafts.get(parent).copied().unwrap_or(Some(*parent));
+                    if followed.is_some_and(|followed| !kept.contains(&followed)) {
+                        grafts.insert(*parent, None);
+                    }
+                }
+            }
+            Some(kept)
+        }
+        None => None,
+    };
+
+    // Named refs outside a shallow clone's history would pull theirs in
+    let mut skipped_refs = Vec::new();
+    let dest_refs = refs.copy_matching_to(dest_ctx, |name, id| {
+        let keep = match &kept {
+            // The note table isn't a commit and comes along whole
+            Some(kept) => {
+                !name.starts_with("refs/") || name.starts_with("refs/notes/") || kept.contains(&id)
+            }
+            None => true,
+        };
+        if !keep {
+            skipped_refs.push(name.to_string());
+        }
+        keep
+    })?;
+    let cloned_refs: Vec<String> = dest_refs.all()?.into_keys().collect();
+
+    // Objects reachable from the clone's refs, read through the source
+    let roots = collect_roots(&dest_refs, store, None)?;
+    let grafted: Grafts = grafts
+        .iter()
+        .map(|(id, stand_in)| (*id, *stand_in))
+        .collect();
+    let reachable = mark_reachable(
+        store,
+        &roots,
+        &grafted,
+        None,
+        &mut GcReport::default(),
+        &Progress::default(),
+    )?;
+
+    let dest_store = ObjectStore::new(dest_ctx.join("objects"));
+    let (mut objects, mut linked, mut copied, mut bytes) = (0, 0, 0, 0);
+    for id in reachable {
+        if !store.exists(id) {
+            continue;
+        }
+        let from = store.local_path(id)?;
+        let to = dest_store.root().join(id.shard()).join(id.as_hex());
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::hard_link(&from, &to).is_ok() {
+            linked += 1;
+        } else {
+            fs::copy(&from, &to)?;
+            copied += 1;
+        }
+        objects += 1;
+        bytes += fs::metadata(&to)?.len();
+    }
+    if !grafts.is_empty() {
+        dest_store.write_grafts(&grafts)?;
+    }
+    dest_store.rebuild_filter()?;
+
+    let mut files = 0;
+    for name in FILES {
+        let from = source.join(name);
+        if from.is_file() {
+            fs::copy(&from, dest_ctx.join(name))?;
+            files += 1;
+        }
+    }
+    for dir in DIRECTORIES {
+        files += copy_dir(&source.join(dir), &dest_ctx.join(dir))?;
+    }
+
+    Ok(CloneReport {
+        destination: dest.to_path_buf(),
+        head,
+        objects,
+        linked,
+        copied,
+        bytes,
+        refs: cloned_refs,
+        skipped_refs,
+        files,
+        depth,
+        grafts: grafts.into_keys().collect(),
+    })
+}
+
+/// The first `depth` commits of `head`'s first-parent history.
+fn first_parent_history(
+    store: &ObjectStore,
+    head: ObjectId,
+    depth: usize,
+) -> Result<HashSet<ObjectId>> {
+    let mut kept = HashSet::new();
+    let mut next = Some(head);
+    while let Some(id) = next {
+        if kept.len() >= depth {
+            break;
+        }
+        kept.insert(id);
+        let commit: Commit = store.get_typed(id)?;
+        next = store.first_parent(&commit);
+    }
+    Ok(kept)
+}
+
+/// Copies the files below `from` to `to`, returning how many.
+fn copy_dir(from: &Path, to: &Path) -> Result<usize> {
+    if !from.is_dir() {
+        return Ok(0);
+    }
+    fs::create_dir_all(to)?;
+    let mut files = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            files += copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)?;
+            files += 1;
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_dir_counts_nested_files() {
+        let tmp = TempDir::new().unwrap();
+        let from = tmp.path().join("from");
+        fs::create_dir_all(from.join("a/b")).unwrap();
+        fs::write(from.join("top.md"), "top").unwrap();
+        fs::write(from.join("a/b/deep.md"), "deep").unwrap();
+
+        let to = tmp.path().join("to");
+        assert_eq!(copy_dir(&from, &to).unwrap(), 2);
+        assert_eq!(fs::read_to_string(to.join("a/b/deep.md")).unwrap(), "deep");
+        assert_eq!(copy_dir(&tmp.path().join("missing"), &to).unwrap(), 0);
+    }
+}
@@ -1,10 +1,21 @@
-//! Configuration types for CTX session management.
+//! Repository configuration (`.ctx/config.toml`).
+//!
+//! Settings are addressed by dotted keys such as `gc.grace_period_days`.
+//! Each key can be overridden by an environment variable named after it
+//! (`CTX_GC_GRACE_PERIOD_DAYS`), which takes precedence over the file.
 
 use crate::error::{CtxError, Result};
+use crate::large_file::{ContentLimits, ContentPolicy};
+use crate::model::ModelProfile;
+use crate::object_store::MAX_BLOB_SIZE;
+use crate::transcript::TranscriptRetention;
+use crate::types::ObservationKind;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
+use tracing::warn;
 
 /// Comprehensive configuration for CTX repository.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -24,23 +35,142 @@ pub struct Config {
     /// Session management configuration.
     #[serde(default)]
     pub session: SessionConfig,
+
+    /// Prompt pack cache configuration.
+    #[serde(default)]
+    pub cache: CacheConfig,
+
+    /// External command execution policy.
+    #[serde(default)]
+    pub exec: crate::policy::ExecConfig,
+
+    /// Retrieval audit log.
+    #[serde(default)]
+    pub audit: crate::audit::AuditConfig,
+
+    /// Edge weighting during retrieval.
+    #[serde(default)]
+    pub edge_decay: crate::graph::EdgeDecayConfig,
+
+    /// Lifecycle hook commands.
+    #[serde(default)]
+    pub hooks: crate::hooks::HooksConfig,
+
+    /// File summarization.
+    #[serde(default)]
+    pub summaries: SummaryConfig,
+
+    /// Semantic code analysis.
+    #[serde(default)]
+    pub analysis: AnalysisConfig,
+
+    /// Health score thresholds.
+    #[serde(default)]
+    pub health: HealthConfig,
+
+    /// Backups of refs and the narrative.
+    #[serde(default)]
+    pub backup: BackupConfig,
+
+    /// Narrative document types by kind, added to or replacing the
+    /// built-in ones (see [`builtin_types`](crate::builtin_types)).
+    #[serde(default)]
+    pub documents: BTreeMap<String, crate::document::DocumentType>,
+
+    /// Model profiles by name, added to or replacing the built-in ones
+    /// (see [`builtin_profiles`](crate::builtin_profiles)).
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelProfile>,
 }
 
+/// Keys written by `ctx init` before the config was typed. They are
+/// accepted without a warning but have no effect.
+const LEGACY_KEYS: &[&str] = &[
+    "repository.version",
+    "ingestion.snapshot_on_read",
+    "ingestion.extract_on_read",
+    "ingestion.parse_diagnostics",
+    "session.idle_timeout_hours",
+    "session.stale_timeout_days",
+];
+
+/// Optional keys, which don't appear when the defaults are serialized.
+const OPTIONAL_KEYS: &[&str] = &[
+    "gc.read_content_retention_days",
+    "session.auto_flush_interval_secs",
+    "session.auto_flush_observations",
+    "session.exec_task",
+    "session.summary_command",
+    "summaries.command",
+];
+
+/// Prefix of environment variables overriding config keys.
+const ENV_PREFIX: &str = "CTX_";
+
 impl Config {
-    /// Load configuration from a file.
+    /// Load configuration from `config.toml` in `ctx_root`, applying
+    /// environment overrides.
+    ///
+    /// A missing file yields the defaults. Unknown keys are logged as
+    /// warnings and ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::ConfigError`] if the file can't be read or
+    /// parsed, or if a value has the wrong type or fails [`Config::validate`].
     pub fn load(ctx_root: &Path) -> Result<Self> {
+        let (config, warnings) = Self::load_with_warnings(ctx_root)?;
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+        Ok(config)
+    }
+
+    /// Like [`Config::load`], but returns the warnings instead of logging
+    /// them.
+    pub fn load_with_warnings(ctx_root: &Path) -> Result<(Self, Vec<String>)> {
         let path = ctx_root.join("config.toml");
-        if path.exists() {
+        let mut table = if path.exists() {
             let content = fs::read_to_string(&path)
                 .map_err(|e| CtxError::ConfigError(format!("failed to read config: {}", e)))?;
-            toml::from_str(&content)
-                .map_err(|e| CtxError::ConfigError(format!("failed to parse config: {}", e)))
+            content
+                .parse::<toml::Table>()
+                .map_err(|e| CtxError::ConfigError(format!("failed to parse config: {}", e)))?
         } else {
-            Ok(Config::default())
+            toml::Table::new()
+        };
+
+        let warnings = unknown_keys(&table)
+            .into_iter()
+            .map(|key| format!("unknown config key '{}' in {}", key, path.display()))
+            .collect();
+
+        for key in known_keys() {
+            if let Some(raw) = std::env::var(Self::env_var(&key))
+                .ok()
+                .filter(|v| !v.is_empty())
+            {
+                set_path(&mut table, &key, parse_value(&raw));
+            }
         }
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| CtxError::ConfigError(format!("invalid config: {}", e)))?;
+        config.validate()?;
+        Ok((config, warnings))
+    }
+
+    /// Environment variable overriding `key`: `gc.grace_period_days` is
+    /// overridden by `CTX_GC_GRACE_PERIOD_DAYS`.
+    pub fn env_var(key: &str) -> String {
+        format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
     }
 
     /// Save configuration to a file.
+    ///
+    /// This rewrites the whole file; use [`Config::set_value`] to change
+    /// one key and keep comments.
     pub fn save(&self, ctx_root: &Path) -> Result<()> {
         let path = ctx_root.join("config.toml");
         let content = toml::to_string_pretty(self)
@@ -49,45 +179,489 @@ impl Config {
             .map_err(|e| CtxError::ConfigError(format!("failed to write config: {}", e)))?;
         Ok(())
     }
+
+    /// Checks that values are within their allowed ranges.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::ConfigError`] naming the first invalid key.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |key: &str, reason: &str| {
+            Err(CtxError::ConfigError(format!(
+                "invalid value for {}: {}",
+                key, reason
+            )))
+        };
+
+        if !(1..=22).contains(&self.storage.compression_level) {
+            return invalid("storage.compression_level", "must be between 1 and 22");
+        }
+        if !(1..=MAX_BLOB_SIZE as u64).contains(&self.storage.max_blob_size) {
+            return invalid(
+                "storage.max_blob_size",
+                &format!("must be between 1 and {}", MAX_BLOB_SIZE),
+            );
+        }
+        if self.search.max_results == 0 {
+            return invalid("search.max_results", "must be at least 1");
+        }
+        if self.session.auto_flush_interval_secs == Some(0) {
+            return invalid("session.auto_flush_interval_secs", "must be at least 1");
+        }
+        if self.session.auto_flush_observations == Some(0) {
+            return invalid("session.auto_flush_observations", "must be at least 1");
+        }
+        if self.session.auto_compact_threshold_hours < self.session.stale_session_threshold_hours {
+            return invalid(
+                "session.auto_compact_threshold_hours",
+                "must be at least session.stale_session_threshold_hours",
+            );
+        }
+        if self.summaries.max_lines == 0 {
+            return invalid("summaries.max_lines", "must be at least 1");
+        }
+        let decay = &self.edge_decay;
+        if !(decay.half_life_days >= 0.0 && decay.half_life_days.is_finite()) {
+            return invalid("edge_decay.half_life_days", "must be a non-negative number");
+        }
+        for (label, days) in &decay.labels {
+            if !(*days >= 0.0 && days.is_finite()) {
+                return invalid(
+                    &format!("edge_decay.labels.{}", label),
+                    "must be a non-negative number",
+                );
+            }
+        }
+        if !(0.0..=1.0).contains(&decay.min_weight) {
+            return invalid("edge_decay.min_weight", "must be between 0 and 1");
+        }
+        let health = &self.health;
+        for (key, yellow, red) in [
+            (
+                "health.index_behind_red",
+                health.index_behind_yellow as f64,
+                health.index_behind_red as f64,
+            ),
+            (
+                "health.corrupted_red",
+                health.corrupted_yellow as f64,
+                health.corrupted_red as f64,
+            ),
+            (
+                "health.abandoned_ratio_red",
+                health.abandoned_ratio_yellow as f64,
+                health.abandoned_ratio_red as f64,
+            ),
+            (
+                "health.growth_red_bytes_per_day",
+                health.growth_yellow_bytes_per_day as f64,
+                health.growth_red_bytes_per_day as f64,
+            ),
+        ] {
+            if red < yellow {
+                return invalid(key, "must be at least its yellow threshold");
+            }
+        }
+        if !(0.0..=1.0).contains(&health.abandoned_ratio_yellow) {
+            return invalid("health.abandoned_ratio_yellow", "must be between 0 and 1");
+        }
+        if !(0.0..=1.0).contains(&health.abandoned_ratio_red) {
+            return invalid("health.abandoned_ratio_red", "must be between 0 and 1");
+        }
+        if health.growth_window_days == 0 {
+            return invalid("health.growth_window_days", "must be at least 1");
+        }
+        for (kind, document_type) in &self.documents {
+            if kind.is_empty()
+                || !kind
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+            {
+                return invalid(
+                    &format!("documents.{}", kind),
+                    "kind must be letters, digits, '_' or '-'",
+                );
+            }
+            let directory = document_type.directory_for(kind);
+            if directory.is_empty()
+                || Path::new(&directory).is_absolute()
+                || directory.split('/').any(|part| part == "..")
+            {
+                return invalid(
+                    &format!("documents.{}.directory", kind),
+                    "must be a relative path inside the narrative directory",
+                );
+            }
+        }
+        for (name, profile) in &self.models {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                return invalid(
+                    &format!("models.{}", name),
+                    "name must be letters, digits, '_', '-' or '.'",
+                );
+            }
+            if profile.reserved_output_tokens >= profile.context_tokens {
+                return invalid(
+                    &format!("models.{}.reserved_output_tokens", name),
+                    "must be less than context_tokens",
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The model profile named `name`, configured or built in.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] naming the known profiles if
+    /// there is none by that name.
+    pub fn model_profile(&self, name: &str) -> Result<ModelProfile> {
+        crate::model::resolve_profile(&self.models, name)
+    }
+
+    /// Every setting as a dotted key and its TOML value, sorted by key.
+    ///
+    /// Unset optional keys are omitted.
+    pub fn values(&self) -> Result<BTreeMap<String, toml::Value>> {
+        let table = self.to_table()?;
+        let mut values = BTreeMap::new();
+        flatten(&table, "", &mut values);
+        Ok(values)
+    }
+
+    /// Serializes the settings into a TOML table.
+    fn to_table(&self) -> Result<toml::Table> {
+        let mut table = toml::Table::try_from(self)
+            .map_err(|e| CtxError::ConfigError(format!("failed to serialize config: {}", e)))?;
+        narrow_floats(&mut table);
+        Ok(table)
+    }
+
+    /// The value of a dotted key such as `gc.grace_period_days`.
+    ///
+    /// Returns `None` for an unset optional key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::ConfigError`] if the key is unknown.
+    pub fn get(&self, key: &str) -> Result<Option<toml::Value>> {
+        if !is_known_key(key) {
+            return Err(CtxError::ConfigError(format!(
+                "unknown config key '{}'",
+                key
+            )));
+        }
+        let table = self.to_table()?;
+        let mut current = &table;
+        let mut parts = key.split('.').peekable();
+        while let Some(part) = parts.next() {
+            match current.get(part) {
+                Some(toml::Value::Table(inner)) if parts.peek().is_some() => current = inner,
+                Some(value) if parts.peek().is_none() => return Ok(Some(value.clone())),
+                _ => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sets a dotted key in `config.toml`, keeping comments and layout.
+    ///
+    /// `value` is parsed as a TOML value (`true`, `3`, `["a"]`), falling
+    /// back to a plain string. The file is only written if the resulting
+    /// config loads and validates.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::ConfigError`] if the key is unknown, the value
+    /// is invalid for it, or the file can't be read or written.
+    pub fn set_value(ctx_root: &Path, key: &str, value: &str) -> Result<()> {
+        if !is_known_key(key) {
+            return Err(CtxError::ConfigError(format!(
+                "unknown config key '{}'",
+                key
+            )));
+        }
+
+        let path = ctx_root.join("config.toml");
+        let content = if path.exists() {
+            fs::read_to_string(&path)
+                .map_err(|e| CtxError::ConfigError(format!("failed to read config: {}", e)))?
+        } else {
+            String::new()
+        };
+        let mut doc = content
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|e| CtxError::ConfigError(format!("failed to parse config: {}", e)))?;
+
+        let parts: Vec<&str> = key.split('.').collect();
+        let (last, tables) = parts.split_last().expect("split yields at least one part");
+        let mut table = doc.as_table_mut();
+        for name in tables {
+            let item = table
+                .entry(name)
+                .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+            table = item.as_table_mut().ok_or_else(|| {
+                CtxError::ConfigError(format!("'{}' in {} is not a table", name, key))
+            })?;
+        }
+        let parsed = format!("v = {}", value)
+            .parse::<toml_edit::DocumentMut>()
+            .ok()
+            .and_then(|d| d.get("v").and_then(|v| v.as_value()).cloned())
+            .unwrap_or_else(|| toml_edit::Value::from(value));
+        match table.get_mut(last).and_then(|item| item.as_value_mut()) {
+            // Replace in place so a trailing comment on the line survives
+            Some(existing) => {
+                let decor = existing.decor().clone();
+                *existing = parsed;
+                *existing.decor_mut() = decor;
+            }
+            None => {
+                table.insert(last, toml_edit::value(parsed));
+            }
+        }
+
+        let updated = doc.to_string();
+        let config: Config = toml::from_str(&updated)
+            .map_err(|e| CtxError::ConfigError(format!("invalid value for {}: {}", key, e)))?;
+        config.validate()?;
+        fs::write(&path, updated)
+            .map_err(|e| CtxError::ConfigError(format!("failed to write config: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Dotted keys of every setting, including unset optional ones.
+fn known_keys() -> Vec<String> {
+    let mut keys: Vec<String> = Config::default()
+        .values()
+        .map(|values| values.into_keys().collect())
+        .unwrap_or_default();
+    keys.extend(OPTIONAL_KEYS.iter().map(|key| key.to_string()));
+    keys.sort();
+    keys
+}
+
+/// Returns true for a known key or a key inside a known map such as
+/// `edge_decay.labels.references`.
+fn is_known_key(key: &str) -> bool {
+    known_keys().iter().any(|known| {
+        key == known
+            || (MAP_KEYS.contains(&known.as_str())
+                && key
+                    .strip_prefix(known.as_str())
+                    .is_some_and(|rest| rest.starts_with('.') && !rest[1..].contains('.')))
+    }) || USER_TABLES.iter().any(|table| {
+        key.strip_prefix(table)
+            .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Keys whose value is a map with user-chosen keys.
+const MAP_KEYS: &[&str] = &["edge_decay.labels"];
+
+/// Tables whose contents are declared by the user, at any depth.
+const USER_TABLES: &[&str] = &["documents", "models"];
+
+/// Keys in `table` that no setting reads.
+fn unknown_keys(table: &toml::Table) -> Vec<String> {
+    let mut values = BTreeMap::new();
+    flatten(table, "", &mut values);
+    values
+        .into_keys()
+        .filter(|key| !is_known_key(key) && !LEGACY_KEYS.contains(&key.as_str()))
+        .collect()
+}
+
+/// Flattens nested tables into dotted keys. Tables under [`MAP_KEYS`]
+/// are flattened one level; an empty one is kept as a value.
+fn flatten(table: &toml::Table, prefix: &str, out: &mut BTreeMap<String, toml::Value>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(inner)
+                if !(inner.is_empty() && MAP_KEYS.contains(&key.as_str())) =>
+            {
+                flatten(inner, &key, out)
+            }
+            _ => {
+                out.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// Rounds floats to the `f32` precision settings are stored with, so
+/// `0.1` isn't shown as `0.10000000149011612`.
+fn narrow_floats(table: &mut toml::Table) {
+    for (_, value) in table.iter_mut() {
+        match value {
+            toml::Value::Float(f) => *f = (*f as f32).to_string().parse().unwrap_or(*f),
+            toml::Value::Table(inner) => narrow_floats(inner),
+            _ => {}
+        }
+    }
+}
+
+/// Sets a dotted key in `table`, creating intermediate tables.
+fn set_path(table: &mut toml::Table, key: &str, value: toml::Value) {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().expect("split yields at least one part");
+    let mut current = table;
+    for part in parts {
+        let entry = current
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if !entry.is_table() {
+            *entry = toml::Value::Table(toml::Table::new());
+        }
+        current = entry.as_table_mut().expect("entry was just made a table");
+    }
+    current.insert(last.to_string(), value);
+}
+
+/// Parses `raw` as a TOML value, falling back to a string.
+fn parse_value(raw: &str) -> toml::Value {
+    format!("v = {}", raw)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
 }
 
 /// Storage-related configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct StorageConfig {
     /// Compression level for zstd (1-22, default: 3).
     /// Higher values mean better compression but slower performance.
     pub compression_level: i32,
+
+    /// Gitignore-style patterns for paths that are never observed,
+    /// analyzed or retrieved, ahead of the rules in `.ctxignore`
+    /// (default: `target/`, `node_modules/`, `*.min.js`).
+    pub ignore: Vec<String>,
+
+    /// Largest file content stored as a single blob, in bytes
+    /// (default: 10 MiB).
+    pub max_blob_size: u64,
+
+    /// What to do with file content over `max_blob_size`: `store`, `skip`,
+    /// `stub` or `chunked` (default: `stub`).
+    pub large_file_policy: ContentPolicy,
+
+    /// What to do with binary file content: `store`, `skip`, `stub` or
+    /// `chunked` (default: `stub`).
+    pub binary_policy: ContentPolicy,
+
+    /// Threads used to hash and compress objects written in batches, such
+    /// as analyzed files and compacted trees (default: 0, one per CPU).
+    pub threads: usize,
+
+    /// Shared object store that `.ctx/objects` caches, so several machines
+    /// (such as CI agents) can share objects: `file:///path` or, in builds
+    /// with the `s3` feature, `s3://bucket/prefix`. S3 credentials come
+    /// from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN` (default: none).
+    pub remote: Option<String>,
+
+    /// Endpoint of an S3-compatible service such as MinIO, addressed with
+    /// path-style URLs (default: AWS).
+    pub remote_endpoint: Option<String>,
+
+    /// Region S3 requests are signed for (default: `us-east-1`).
+    pub remote_region: Option<String>,
+}
+
+impl StorageConfig {
+    /// The content limits these settings describe.
+    pub fn content_limits(&self) -> ContentLimits {
+        ContentLimits {
+            max_blob_size: self.max_blob_size,
+            large_file_policy: self.large_file_policy,
+            binary_policy: self.binary_policy,
+        }
+    }
 }
 
 impl Default for StorageConfig {
     fn default() -> Self {
         Self {
             compression_level: 3,
+            ignore: vec![
+                "target/".to_string(),
+                "node_modules/".to_string(),
+                "*.min.js".to_string(),
+            ],
+            max_blob_size: 10 * 1024 * 1024,
+            large_file_policy: ContentPolicy::Stub,
+            binary_policy: ContentPolicy::Stub,
+            threads: 0,
+            remote: None,
+            remote_endpoint: None,
+            remote_region: None,
         }
     }
 }
 
 /// Garbage collection configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GcConfig {
     /// Grace period in days before deleting unreferenced objects (default: 7).
     pub grace_period_days: u32,
 
-    /// Automatically run GC after session compaction (default: false).
+    /// Run GC after a session compaction when one of the `auto_*`
+    /// thresholds below is crossed, like `git gc --auto` (default: true).
     pub auto_gc: bool,
+
+    /// Commits since the last GC that trigger an automatic one (default:
+    /// 50, 0 disables).
+    pub auto_commits: u32,
+
+    /// Estimated objects in the store that trigger an automatic GC
+    /// (default: 20000, 0 disables).
+    pub auto_objects: u64,
+
+    /// Estimated store size in MB that triggers an automatic GC (default:
+    /// 512, 0 disables).
+    pub auto_size_mb: u64,
+
+    /// Days to keep file contents captured on read by session steps.
+    /// Older steps keep the read but drop its content (default: keep).
+    pub read_content_retention_days: Option<u32>,
+
+    /// Days a reflog entry keeps the commits it names from GC, so
+    /// `<ref>@{N}` still resolves (default: 90).
+    pub reflog_expire_days: u32,
 }
 
 impl Default for GcConfig {
     fn default() -> Self {
         Self {
             grace_period_days: 7,
-            auto_gc: false,
+            auto_gc: true,
+            auto_commits: 50,
+            auto_objects: 20_000,
+            auto_size_mb: 512,
+            read_content_retention_days: None,
+            reflog_expire_days: 90,
         }
     }
 }
 
 /// Full-text search configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SearchConfig {
     /// Enable full-text search indexing (default: true).
     pub enabled: bool,
@@ -111,26 +685,259 @@ impl Default for SearchConfig {
 
 /// Session management configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SessionConfig {
     /// Threshold in hours before asking about stale sessions (default: 24).
     pub stale_session_threshold_hours: u64,
 
+    /// Hours idle before `ctx maintenance` compacts a stale session without
+    /// asking (default: 168).
+    pub auto_compact_threshold_hours: u64,
+
     /// Optional auto-flush interval in seconds.
     /// If set, observations are automatically flushed after this interval.
     pub auto_flush_interval_secs: Option<u64>,
+
+    /// Optional number of observations after which they are automatically
+    /// flushed.
+    pub auto_flush_observations: Option<usize>,
+
+    /// Kinds of observation that end a step, such as `command` or
+    /// `file_write`: each is flushed right after it's recorded
+    /// (default: none).
+    pub auto_flush_after: Vec<ObservationKind>,
+
+    /// Task for the session `ctx exec` starts when none is active.
+    /// If unset, commands are only recorded into an existing session.
+    pub exec_task: Option<String>,
+
+    /// Command that writes the commit message and log entry when a session
+    /// is compacted without a message, split on whitespace like hook
+    /// commands. It reads the session digest as JSON on stdin. If unset,
+    /// they list the files, commands and notes of the session.
+    pub summary_command: Option<String>,
+
+    /// What happens to conversation turns recorded with `observe_message`:
+    /// `off` doesn't record them, `session` drops them on compaction, and
+    /// `keep` stores them with the session's commit (default: keep).
+    pub transcripts: TranscriptRetention,
+
+    /// Collapse repeated reads of the same file content within a step into
+    /// one observation with a count (default: true).
+    pub dedup_reads: bool,
+
+    /// Collapse repeated runs of the same command with the same exit code
+    /// and output within a step into one observation with a count
+    /// (default: true).
+    pub dedup_commands: bool,
+
+    /// What [`CtxRepo::expire_session`](crate::CtxRepo::expire_session)
+    /// does with a staged session whose last step is older than
+    /// `auto_compact_threshold_hours` and whose lock holder is gone:
+    /// `compact` saves it, `abandon` discards it, and `off` leaves it
+    /// staged (default: compact).
+    pub expired_sessions: ExpiredSessionPolicy,
+}
+
+impl SessionConfig {
+    /// The auto-flush policy these settings describe.
+    pub fn flush_policy(&self) -> FlushPolicy {
+        FlushPolicy {
+            interval_secs: self.auto_flush_interval_secs,
+            observations: self.auto_flush_observations,
+            after: self.auto_flush_after.clone(),
+        }
+    }
+
+    /// The stale session thresholds these settings describe.
+    pub fn stale_config(&self) -> StaleSessionConfig {
+        StaleSessionConfig {
+            ask_threshold_secs: self.stale_session_threshold_hours * 60 * 60,
+            auto_compact_threshold_secs: self.auto_compact_threshold_hours * 60 * 60,
+        }
+    }
 }
 
 impl Default for SessionConfig {
     fn default() -> Self {
         Self {
             stale_session_threshold_hours: 24,
+            auto_compact_threshold_hours: 7 * 24,
             auto_flush_interval_secs: None,
+            auto_flush_observations: None,
+            auto_flush_after: Vec::new(),
+            exec_task: None,
+            summary_command: None,
+            transcripts: TranscriptRetention::default(),
+            dedup_reads: true,
+            dedup_commands: true,
+            expired_sessions: ExpiredSessionPolicy::default(),
         }
     }
 }
 
-/// Configuration for stale session handling.
+/// Prompt pack cache configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Cache packs built by `ctx query` (default: true).
+    pub enabled: bool,
+
+    /// Seconds before a cached pack expires (default: 3600).
+    pub ttl_secs: u64,
+
+    /// Total size of cached packs before the oldest are evicted
+    /// (default: 64 MiB).
+    pub max_bytes: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 60 * 60,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// File summarization configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryConfig {
+    /// Command that summarizes a file from its content on stdin, split on
+    /// whitespace like hook commands. If unset, summaries are built from
+    /// doc comments and declarations.
+    pub command: Option<String>,
+
+    /// Most lines in a summary built without a command (default: 12).
+    pub max_lines: usize,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            max_lines: 12,
+        }
+    }
+}
+
+/// Semantic code analysis configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    /// rust-analyzer processes `ctx analyze rust` runs at once. Each one
+    /// loads the whole workspace, so they cost memory as well as CPU
+    /// (default: 0, one per CPU up to 4).
+    pub workers: usize,
+    /// Directories holding Rust workspaces, relative to the repository
+    /// root, for repositories whose Rust code isn't at the top, such as
+    /// polyglot monorepos. Each is analyzed with its own rust-analyzer and
+    /// may have its own `.ctxignore` (default: empty, the repository root).
+    pub roots: Vec<String>,
+    /// Re-run Cargo analysis after compacting a session if `Cargo.toml` or
+    /// `Cargo.lock` changed since the last Cargo snapshot (default: false).
+    pub auto_cargo: bool,
+}
+
+/// Backup configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Backups of refs and the narrative kept in `.ctx/backups`, one taken
+    /// per compaction (default: 10, 0 to take none).
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self { keep: 10 }
+    }
+}
+
+/// Thresholds at which `ctx health` rates a signal yellow or red. A value
+/// at or above a threshold takes its rating.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Unindexed commits that make the index yellow (default: 1).
+    pub index_behind_yellow: usize,
+    /// Unindexed commits that make the index red (default: 50).
+    pub index_behind_red: usize,
+
+    /// Objects checked for corruption, spread evenly over the store
+    /// (default: 256, 0 to skip the check).
+    pub sample_size: usize,
+    /// Corrupt sampled objects that make the store yellow (default: 1).
+    pub corrupted_yellow: usize,
+    /// Corrupt sampled objects that make the store red (default: 1).
+    pub corrupted_red: usize,
+
+    /// Recent session commits the abandoned share is taken over
+    /// (default: 50).
+    pub session_window: usize,
+    /// Share of abandoned sessions that is yellow (default: 0.25).
+    pub abandoned_ratio_yellow: f32,
+    /// Share of abandoned sessions that is red (default: 0.5).
+    pub abandoned_ratio_red: f32,
+
+    /// Days storage growth is averaged over (default: 7).
+    pub growth_window_days: u32,
+    /// Bytes written per day that are yellow (default: 64 MiB).
+    pub growth_yellow_bytes_per_day: u64,
+    /// Bytes written per day that are red (default: 512 MiB).
+    pub growth_red_bytes_per_day: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            index_behind_yellow: 1,
+            index_behind_red: 50,
+            sample_size: 256,
+            corrupted_yellow: 1,
+            corrupted_red: 1,
+            session_window: 50,
+            abandoned_ratio_yellow: 0.25,
+            abandoned_ratio_red: 0.5,
+            growth_window_days: 7,
+            growth_yellow_bytes_per_day: 64 * 1024 * 1024,
+            growth_red_bytes_per_day: 512 * 1024 * 1024,
+        }
+    }
+}
+
+/// When a session flushes its pending observations to a step on its own,
+/// bounding what a crash can lose. Any condition that is met triggers a
+/// flush; the default never flushes automatically.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlushPolicy {
+    /// Flush once this many seconds have passed since the last step.
+    pub interval_secs: Option<u64>,
+
+    /// Flush once this many observations are pending.
+    pub observations: Option<usize>,
+
+    /// Flush right after an observation of one of these kinds.
+    pub after: Vec<ObservationKind>,
+}
+
+/// What happens to an expired session when maintenance expires it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiredSessionPolicy {
+    /// Leave the session staged.
+    Off,
+    /// Compact the session into a stale auto-compact commit.
+    #[default]
+    Compact,
+    /// Compact the session into an abandoned commit.
+    Abandon,
+}
+
+/// Configuration for stale session handling.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StaleSessionConfig {
     /// After this duration, ask user before starting new task.
     /// Default: 24 hours.
@@ -163,7 +970,8 @@ impl StaleSessionConfig {
 }
 
 /// Status of stale session check.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
 pub enum StaleSessionStatus {
     /// No active session exists.
     NoSession,
@@ -194,7 +1002,7 @@ pub enum StaleSessionStatus {
 }
 
 /// Report from cleanup operation.
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
 pub struct CleanupReport {
     /// Number of sessions compacted.
     pub sessions_compacted: u32,
@@ -213,6 +1021,189 @@ mod tests {
         assert_eq!(config.auto_compact_threshold_secs, 7 * 24 * 60 * 60);
     }
 
+    #[test]
+    fn test_partial_sections_use_defaults() {
+        let config: Config =
+            toml::from_str("[repository]\nversion = \"1\"\n\n[session]\nidle_timeout_hours = 24\n")
+                .unwrap();
+        assert_eq!(config.session.stale_session_threshold_hours, 24);
+        assert!(config.cache.enabled);
+    }
+
+    #[test]
+    fn test_load_warns_on_unknown_keys_and_validates() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let write = |content: &str| fs::write(tmp.path().join("config.toml"), content).unwrap();
+
+        write("[session]\nidle_timeout_hours = 24\n\n[gc]\ngrace_period_day = 3\n\n[edge_decay.labels]\nreferences = 30.0\n");
+        let (config, warnings) = Config::load_with_warnings(tmp.path()).unwrap();
+        assert_eq!(config.gc.grace_period_days, 7);
+        assert_eq!(config.edge_decay.labels["references"], 30.0);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("gc.grace_period_day"));
+
+        write("[storage]\ncompression_level = 40\n");
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err.to_string().contains("storage.compression_level"));
+
+        write("[session]\nstale_session_threshold_hours = 48\nauto_compact_threshold_hours = 24\n");
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("session.auto_compact_threshold_hours"));
+
+        write("[gc]\ngrace_period_days = \"soon\"\n");
+        assert!(Config::load(tmp.path()).is_err());
+
+        write("[storage]\nmax_blob_size = 0\n");
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err.to_string().contains("storage.max_blob_size"));
+
+        write("[health]\nindex_behind_yellow = 10\nindex_behind_red = 5\n");
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err.to_string().contains("health.index_behind_red"));
+
+        write("[health]\nabandoned_ratio_yellow = 1.5\nabandoned_ratio_red = 2.0\n");
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err.to_string().contains("health.abandoned_ratio_yellow"));
+
+        write(
+            "[storage]\nlarge_file_policy = \"chunked\"\nbinary_policy = \"skip\"\nthreads = 2\n\
+             remote = \"s3://ci-cache/ctx\"\n",
+        );
+        let storage = Config::load(tmp.path()).unwrap().storage;
+        let limits = storage.content_limits();
+        assert_eq!(limits.large_file_policy, ContentPolicy::Chunked);
+        assert_eq!(limits.binary_policy, ContentPolicy::Skip);
+        assert_eq!(storage.threads, 2);
+        assert_eq!(storage.remote.as_deref(), Some("s3://ci-cache/ctx"));
+        assert_eq!(storage.remote_region, None);
+    }
+
+    #[test]
+    fn test_document_types() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let write = |content: &str| fs::write(tmp.path().join("config.toml"), content).unwrap();
+
+        write(
+            "[documents.rfc]
+directory = \"design/rfcs\"
+template = \"## Motivation\\n\"
+
+[documents.rfc.fields.status]
+required = true
+values = [\"draft\", \"accepted\"]
+",
+        );
+        let (config, warnings) = Config::load_with_warnings(tmp.path()).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let rfc = &config.documents["rfc"];
+        assert_eq!(rfc.directory_for("rfc"), "design/rfcs");
+        assert!(rfc.fields["status"].required);
+
+        write(
+            "[documents.rfc]
+directory = \"../outside\"
+",
+        );
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err.to_string().contains("documents.rfc.directory"));
+    }
+
+    #[test]
+    fn test_model_profiles() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let write = |content: &str| fs::write(tmp.path().join("config.toml"), content).unwrap();
+
+        write(
+            "[models.local-llama]
+context_tokens = 8192
+reserved_output_tokens = 1024
+tokenizer = \"dense\"
+formats = [\"text\"]
+",
+        );
+        let (config, warnings) = Config::load_with_warnings(tmp.path()).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let local = config.model_profile("local-llama").unwrap();
+        assert_eq!(local.pack_budget(), 7168);
+        assert_eq!(local.tokenizer, crate::Tokenizer::Dense);
+        assert!(config.model_profile("gpt-4o").is_ok());
+        assert!(config.model_profile("nope").is_err());
+
+        write("[models.tiny]\ncontext_tokens = 1000\nreserved_output_tokens = 1000\n");
+        let err = Config::load(tmp.path()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("models.tiny.reserved_output_tokens"));
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        fs::write(
+            tmp.path().join("config.toml"),
+            "[search]\nsnippet_length = 80\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Config::env_var("search.snippet_length"),
+            "CTX_SEARCH_SNIPPET_LENGTH"
+        );
+        std::env::set_var("CTX_SEARCH_SNIPPET_LENGTH", "200");
+        let config = Config::load(tmp.path()).unwrap();
+        std::env::remove_var("CTX_SEARCH_SNIPPET_LENGTH");
+        assert_eq!(config.search.snippet_length, 200);
+        assert_eq!(Config::load(tmp.path()).unwrap().search.snippet_length, 80);
+    }
+
+    #[test]
+    fn test_get_and_set_value_keep_comments() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            "# Top comment\n[gc]\n# Days to keep\ngrace_period_days = 7 # a week\n",
+        )
+        .unwrap();
+
+        Config::set_value(tmp.path(), "gc.grace_period_days", "3").unwrap();
+        Config::set_value(tmp.path(), "exec.deny", "[\"rm *\"]").unwrap();
+        Config::set_value(tmp.path(), "exec.mode", "confirm").unwrap();
+        Config::set_value(tmp.path(), "edge_decay.labels.references", "30").unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("# Top comment"));
+        assert!(content.contains("# Days to keep\ngrace_period_days = 3 # a week"));
+
+        let config = Config::load(tmp.path()).unwrap();
+        assert_eq!(
+            config.get("gc.grace_period_days").unwrap(),
+            Some(toml::Value::Integer(3))
+        );
+        assert_eq!(config.exec.deny, vec!["rm *".to_string()]);
+        assert_eq!(
+            config.get("exec.mode").unwrap(),
+            Some(toml::Value::String("confirm".into()))
+        );
+        assert_eq!(
+            config.get("session.auto_flush_interval_secs").unwrap(),
+            None
+        );
+        assert!(config
+            .values()
+            .unwrap()
+            .contains_key("edge_decay.labels.references"));
+
+        // Unknown keys and invalid values leave the file untouched
+        assert!(Config::set_value(tmp.path(), "gc.grace", "3").is_err());
+        assert!(Config::set_value(tmp.path(), "storage.compression_level", "99").is_err());
+        assert!(Config::set_value(tmp.path(), "session.auto_flush_observations", "0").is_err());
+        assert!(config.get("gc.grace").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    }
+
     #[test]
     fn test_duration_conversions() {
         let config = StaleSessionConfig::default();
@@ -0,0 +1,290 @@
+//! Decision records: why a change was made, not just what changed.
+//!
+//! A [`Decision`] captures the question, the options considered, the choice
+//! and the reasoning behind it. Decisions are recorded explicitly with
+//! [`CtxRepo::record_decision`](crate::CtxRepo::record_decision), or
+//! automatically when a [`SessionHandler`](crate::SessionHandler) sees a
+//! message classified as [`MessageKind::Decision`](crate::MessageKind::Decision).
+//!
+//! Each decision is stored as its own object and listed in the
+//! [`DecisionLog`] referenced from each commit. It gets a `Decision` node
+//! with `Mentions` edges to the files it concerns, so retrieval can bring
+//! the rationale along with those files, or on its own with
+//! [`RetrievalConfig::decisions_only`](crate::RetrievalConfig::decisions_only).
+
+use crate::error::{CtxError, Result};
+use crate::glossary::contains_word;
+use crate::types::{
+    Confidence, Edge, EdgeBatch, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind,
+};
+use crate::{ObjectId, ObjectStore};
+use serde::{Deserialize, Serialize};
+
+/// Phrases introducing the reasoning in a decision message.
+const REASON_MARKERS: &[&str] = &[" because ", " since ", " so that "];
+
+/// Phrases introducing the rejected alternatives in a decision message.
+const REJECTED_MARKERS: &[&str] = &[" instead of ", " rather than ", " over "];
+
+/// How a decision was recorded.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionSource {
+    /// Recorded explicitly by a user or agent.
+    Manual = 1,
+    /// Extracted from a message the embedder classified as a decision.
+    Classified = 2,
+}
+
+/// A recorded decision and its rationale.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    /// What had to be decided.
+    pub question: String,
+    /// Options considered, including the one chosen.
+    pub options: Vec<String>,
+    /// The option chosen.
+    pub choice: String,
+    /// Why it was chosen.
+    pub reasoning: String,
+    /// Files the decision concerns. Empty when recorded means the files
+    /// the active session has touched.
+    pub paths: Vec<String>,
+    /// Task of the session the decision was made in, if any.
+    pub task: Option<String>,
+    /// How the decision was recorded.
+    pub source: DecisionSource,
+    /// Creation time (Unix seconds). Zero when recorded means now.
+    pub created_at: u64,
+}
+
+impl Decision {
+    /// Creates a manually recorded decision.
+    pub fn new(
+        question: impl Into<String>,
+        choice: impl Into<String>,
+        reasoning: impl Into<String>,
+    ) -> Self {
+        Self {
+            question: question.into(),
+            options: Vec::new(),
+            choice: choice.into(),
+            reasoning: reasoning.into(),
+            paths: Vec::new(),
+            task: None,
+            source: DecisionSource::Manual,
+            created_at: 0,
+        }
+    }
+
+    /// Extracts a decision from a message such as "Use redb instead of
+    /// sled because it needs no compaction".
+    ///
+    /// The text before "because", "since" or "so that" is the choice and
+    /// the rest the reasoning; alternatives after "instead of", "rather
+    /// than" or "over" become the other options. The question is left
+    /// empty for the caller to fill in.
+    pub fn from_message(message: &str) -> Self {
+        let message = message.trim();
+        let (choice, reasoning) = match split_at_marker(message, REASON_MARKERS) {
+            Some((choice, reasoning)) => (choice, reasoning),
+            None => (message, ""),
+        };
+        let (choice, rejected) = match split_at_marker(choice, REJECTED_MARKERS) {
+            Some((choice, rejected)) => (choice, rejected),
+            None => (choice, ""),
+        };
+        let choice = clean(choice);
+
+        let mut options = Vec::new();
+        if !rejected.is_empty() {
+            options.push(choice.clone());
+            options.extend(
+                rejected
+                    .split([',', '/'])
+                    .flat_map(|part| part.split(" or "))
+                    .map(clean)
+                    .filter(|option| !option.is_empty()),
+            );
+        }
+
+        Self {
+            options,
+            source: DecisionSource::Classified,
+            ..Self::new("", choice, clean(reasoning))
+        }
+    }
+
+    /// Renders the decision as markdown for a pack.
+    pub fn render(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.question.is_empty() {
+            lines.push(format!("**Question**: {}", self.question));
+        }
+        if !self.options.is_empty() {
+            lines.push(format!("**Options**: {}", self.options.join("; ")));
+        }
+        lines.push(format!("**Choice**: {}", self.choice));
+        if !self.reasoning.is_empty() {
+            lines.push(format!("**Reasoning**: {}", self.reasoning));
+        }
+        if !self.paths.is_empty() {
+            lines.push(format!("**Files**: {}", self.paths.join(", ")));
+        }
+        lines.join("\n")
+    }
+
+    /// One-line title, for chunk headers and commit messages.
+    pub fn title(&self) -> String {
+        let line = self.choice.lines().next().unwrap_or_default();
+        match line.char_indices().nth(72) {
+            Some((end, _)) => format!("{}...", &line[..end]),
+            None => line.to_string(),
+        }
+    }
+
+    /// Returns true if `word` (lowercase) occurs as a whole word in the
+    /// question, options or choice.
+    pub fn mentions(&self, word: &str) -> bool {
+        std::iter::once(&self.question)
+            .chain(&self.options)
+            .chain(std::iter::once(&self.choice))
+            .any(|text| contains_word(&text.to_lowercase(), word))
+    }
+}
+
+/// Every recorded decision, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecisionLog {
+    /// IDs of the stored [`Decision`] objects.
+    pub decisions: Vec<ObjectId>,
+}
+
+/// Appends stored decisions to the log at `log_id`.
+///
+/// Returns the new log's ID (`log_id` itself if `decision_ids` is empty)
+/// and an edge batch linking the decisions to their files, if they name
+/// any. `commit_id` is recorded as the edges' evidence.
+pub(crate) fn append_decisions(
+    decision_ids: &[ObjectId],
+    log_id: Option<ObjectId>,
+    commit_id: ObjectId,
+    now: u64,
+    object_store: &ObjectStore,
+) -> Result<(Option<ObjectId>, Option<ObjectId>)> {
+    if decision_ids.is_empty() {
+        return Ok((log_id, None));
+    }
+    let mut log: DecisionLog = match log_id {
+        Some(id) => object_store.get_typed(id)?,
+        None => DecisionLog::default(),
+    };
+
+    let mut edges = Vec::new();
+    for &decision_id in decision_ids {
+        let decision: Decision = object_store.get_typed(decision_id)?;
+        edges.extend(mention_edges(&decision, decision_id, commit_id));
+        log.decisions.push(decision_id);
+    }
+
+    let batch_id = if edges.is_empty() {
+        None
+    } else {
+        Some(object_store.put_typed(&EdgeBatch {
+            edges,
+            created_at: now,
+        })?)
+    };
+    Ok((Some(object_store.put_typed(&log)?), batch_id))
+}
+
+/// Builds `Mentions` edges from a decision's node to the files it concerns.
+fn mention_edges(decision: &Decision, decision_id: ObjectId, commit_id: ObjectId) -> Vec<Edge> {
+    let (tool, confidence) = match decision.source {
+        DecisionSource::Manual => (EvidenceTool::Human, Confidence::High),
+        DecisionSource::Classified => (EvidenceTool::Llm, Confidence::Medium),
+    };
+    let from = decision_node(decision_id);
+
+    decision
+        .paths
+        .iter()
+        .map(|path| Edge {
+            from: from.clone(),
+            to: NodeId {
+                kind: NodeKind::File,
+                id: path.clone(),
+            },
+            label: EdgeLabel::Mentions,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool,
+                confidence,
+                span: None,
+                blob_id: Some(decision_id),
+                condition: None,
+            },
+        })
+        .collect()
+}
+
+/// The graph node for a stored decision.
+pub(crate) fn decision_node(decision_id: ObjectId) -> NodeId {
+    NodeId {
+        kind: NodeKind::Decision,
+        id: decision_id.as_hex(),
+    }
+}
+
+/// Validates a decision before it is recorded.
+pub(crate) fn validate(decision: &Decision) -> Result<()> {
+    if decision.choice.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "decision choice must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Splits `text` around the earliest of `markers`, ignoring ASCII case.
+fn split_at_marker<'a>(text: &'a str, markers: &[&str]) -> Option<(&'a str, &'a str)> {
+    let lower = text.to_ascii_lowercase();
+    markers
+        .iter()
+        .filter_map(|marker| lower.find(marker).map(|at| (at, marker.len())))
+        .min()
+        .map(|(at, len)| (&text[..at], &text[at + len..]))
+}
+
+/// Trims whitespace and trailing punctuation.
+fn clean(text: &str) -> String {
+    text.trim()
+        .trim_end_matches(['.', ',', ';', ':', '!'])
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decision_from_message() {
+        let decision = Decision::from_message(
+            "Use redb instead of sled or rocksdb because it needs no compaction.",
+        );
+        assert_eq!(decision.choice, "Use redb");
+        assert_eq!(decision.options, vec!["Use redb", "sled", "rocksdb"]);
+        assert_eq!(decision.reasoning, "it needs no compaction");
+        assert_eq!(decision.source, DecisionSource::Classified);
+        assert!(decision.mentions("redb"));
+        assert!(!decision.mentions("compaction"));
+
+        let plain = Decision::from_message("Keep the parser hand-written");
+        assert_eq!(plain.choice, "Keep the parser hand-written");
+        assert!(plain.options.is_empty());
+        assert!(plain.reasoning.is_empty());
+        assert_eq!(plain.render(), "**Choice**: Keep the parser hand-written");
+    }
+}
@@ -0,0 +1,451 @@
+//! Structured differences between two commits.
+
+use crate::error::{CtxError, Result};
+use crate::graph::AdjacencyList;
+use crate::staging::flatten_tree;
+use crate::types::{Commit, Edge, EdgeBatch, EdgeLabel, NodeId};
+use crate::{ObjectId, ObjectStore};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+/// How an entry differs between the two commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeStatus {
+    /// Present only in the newer commit.
+    Added,
+    /// Present only in the older commit.
+    Removed,
+    /// Present in both with different content.
+    Modified,
+}
+
+impl ChangeStatus {
+    /// Single-letter code used in text output (A, D, M).
+    pub fn code(&self) -> char {
+        match self {
+            ChangeStatus::Added => 'A',
+            ChangeStatus::Removed => 'D',
+            ChangeStatus::Modified => 'M',
+        }
+    }
+
+    /// Lowercase name used in JSON output.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeStatus::Added => "added",
+            ChangeStatus::Removed => "removed",
+            ChangeStatus::Modified => "modified",
+        }
+    }
+}
+
+/// A changed file or narrative path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathChange {
+    /// Repository-relative path.
+    pub path: String,
+    /// Kind of change.
+    pub status: ChangeStatus,
+    /// Blob in the older commit.
+    pub old_blob: Option<ObjectId>,
+    /// Blob in the newer commit.
+    pub new_blob: Option<ObjectId>,
+}
+
+/// Differences between two commits, from `from` to `to`.
+///
+/// Files are compared tree against tree. Edges and narrative files are
+/// compared as the state accumulated over each commit's history, since
+/// commits only record the edge batches and narrative snapshots they add.
+#[derive(Debug, Clone)]
+pub struct CommitDiff {
+    /// The older commit.
+    pub from: ObjectId,
+    /// The newer commit.
+    pub to: ObjectId,
+    /// Files added, removed or modified, sorted by path.
+    pub files: Vec<PathChange>,
+    /// Edges reachable from `to` but not `from`.
+    pub edges_added: Vec<Edge>,
+    /// Edges reachable from `from` but not `to`.
+    pub edges_removed: Vec<Edge>,
+    /// Narrative files whose latest snapshot differs, sorted by path.
+    pub narrative: Vec<PathChange>,
+}
+
+impl CommitDiff {
+    /// Computes the diff between two commits.
+    pub fn compute(from: ObjectId, to: ObjectId, object_store: &ObjectStore) -> Result<Self> {
+        let from_commit: Commit = object_store.get_typed(from)?;
+        let to_commit: Commit = object_store.get_typed(to)?;
+
+        let files = diff_maps(
+            &flatten_tree(from_commit.root_tree, object_store)?,
+            &flatten_tree(to_commit.root_tree, object_store)?,
+        );
+
+        let old_history = HistoryState::collect(from, object_store)?;
+        let new_history = HistoryState::collect(to, object_store)?;
+
+        let edges_added = new_history
+            .edges
+            .iter()
+            .filter(|(key, _)| !old_history.edges.contains_key(key))
+            .map(|(_, edge)| edge.clone())
+            .collect();
+        let edges_removed = old_history
+            .edges
+            .iter()
+            .filter(|(key, _)| !new_history.edges.contains_key(key))
+            .map(|(_, edge)| edge.clone())
+            .collect();
+
+        Ok(Self {
+            from,
+            to,
+            files,
+            edges_added,
+            edges_removed,
+            narrative: diff_maps(&old_history.narrative, &new_history.narrative),
+        })
+    }
+
+    /// Serialize to pretty JSON, with object IDs as hex strings.
+    pub fn to_json(&self) -> Result<String> {
+        let paths = |changes: &[PathChange]| -> Vec<serde_json::Value> {
+            changes
+                .iter()
+                .map(|c| {
+                    serde_json::json!({
+                        "path": c.path,
+                        "status": c.status.as_str(),
+                        "old_blob": c.old_blob.map(|id| id.as_hex()),
+                        "new_blob": c.new_blob.map(|id| id.as_hex()),
+                    })
+                })
+                .collect()
+        };
+        let edges = |edges: &[Edge]| -> Vec<serde_json::Value> {
+            edges
+                .iter()
+                .map(|e| {
+                    serde_json::json!({
+                        "from": format_node(&e.from),
+                        "to": format_node(&e.to),
+                        "label": format!("{:?}", e.label),
+                    })
+                })
+                .collect()
+        };
+
+        let value = serde_json::json!({
+            "from": self.from.as_hex(),
+            "to": self.to.as_hex(),
+            "files": paths(&self.files),
+            "edges_added": edges(&self.edges_added),
+            "edges_removed": edges(&self.edges_removed),
+            "narrative": paths(&self.narrative),
+        });
+        serde_json::to_string_pretty(&value).map_err(|e| CtxError::Serialization(e.to_string()))
+    }
+
+    /// Format as human-readable text.
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+
+        output.push_str(&format!(
+            "diff {}..{}\n",
+            &self.from.as_hex()[..8],
+            &self.to.as_hex()[..8]
+        ));
+        if self.is_empty() {
+            output.push_str("\nNo differences.\n");
+            return output;
+        }
+
+        if !self.files.is_empty() {
+            output.push_str(&format!("\nFiles ({}):\n", self.files.len()));
+            for change in &self.files {
+                output.push_str(&format!("  {} {}\n", change.status.code(), change.path));
+            }
+        }
+
+        if !self.edges_added.is_empty() || !self.edges_removed.is_empty() {
+            output.push_str(&format!(
+                "\nEdges (+{} -{}):\n",
+                self.edges_added.len(),
+                self.edges_removed.len()
+            ));
+            for (sign, edge) in self
+                .edges_added
+                .iter()
+                .map(|e| ('+', e))
+                .chain(self.edges_removed.iter().map(|e| ('-', e)))
+            {
+                output.push_str(&format!(
+                    "  {} {} -[{:?}]-> {}\n",
+                    sign,
+                    format_node(&edge.from),
+                    edge.label,
+                    format_node(&edge.to)
+                ));
+            }
+        }
+
+        if !self.narrative.is_empty() {
+            output.push_str(&format!("\nNarrative ({}):\n", self.narrative.len()));
+            for change in &self.narrative {
+                output.push_str(&format!("  {} {}\n", change.status.code(), change.path));
+            }
+        }
+
+        output
+    }
+
+    /// Returns true if nothing differs.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+            && self.edges_added.is_empty()
+            && self.edges_removed.is_empty()
+            && self.narrative.is_empty()
+    }
+}
+
+fn format_node(node: &NodeId) -> String {
+    format!("{:?}:{}", node.kind, node.id)
+}
+
+type EdgeKey = (NodeId, NodeId, EdgeLabel);
+
+/// Edges and narrative snapshots accumulated over a commit's history.
+struct HistoryState {
+    edges: BTreeMap<EdgeKey, Edge>,
+    narrative: BTreeMap<String, ObjectId>,
+}
+
+impl HistoryState {
+    fn collect(start: ObjectId, object_store: &ObjectStore) -> Result<Self> {
+        let mut state = HistoryState {
+            edges: BTreeMap::new(),
+            narrative: BTreeMap::new(),
+        };
+        let mut queue = VecDeque::from([start]);
+        let mut seen = HashSet::new();
+
+        // Newest first, so the first snapshot seen for a path is the latest
+        while let Some(id) = queue.pop_front() {
+            if !seen.insert(id) {
+                continue;
+            }
+            let commit: Commit = object_store.get_typed(id)?;
+
+            for batch_id in &commit.edge_batches {
+                let batch: EdgeBatch = object_store.get_typed(*batch_id)?;
+                for edge in batch.edges {
+                    let key = (edge.from.clone(), edge.to.clone(), edge.label);
+                    state.edges.entry(key).or_insert(edge);
+                }
+            }
+            for narrative_ref in &commit.narrative_refs {
+                state
+                    .narrative
+                    .entry(narrative_ref.path.clone())
+                    .or_insert(narrative_ref.blob_id);
+            }
+
+            queue.extend(object_store.parents(&commit));
+        }
+
+        Ok(state)
+    }
+}
+
+/// The graph live as of `commit`.
+///
+/// Every edge the commit's history recorded counts, except those whose
+/// evidence only cites file contents no longer in its tree: the edges the
+/// index supersedes when those files change.
+pub(crate) fn graph_at(commit: ObjectId, object_store: &ObjectStore) -> Result<AdjacencyList> {
+    let root_tree = object_store.get_typed::<Commit>(commit)?.root_tree;
+    let current: HashSet<ObjectId> = flatten_tree(root_tree, object_store)?
+        .into_values()
+        .collect();
+
+    // Whether any evidence for each edge is still current
+    let mut edges: BTreeMap<EdgeKey, bool> = BTreeMap::new();
+    let mut queue = VecDeque::from([commit]);
+    let mut seen = HashSet::new();
+    while let Some(id) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let commit: Commit = object_store.get_typed(id)?;
+        for batch_id in &commit.edge_batches {
+            let batch: EdgeBatch = object_store.get_typed(*batch_id)?;
+            for edge in batch.edges {
+                let live = edge
+                    .evidence
+                    .blob_id
+                    .map_or(true, |blob| current.contains(&blob));
+                *edges.entry((edge.from, edge.to, edge.label)).or_default() |= live;
+            }
+        }
+        queue.extend(object_store.parents(&commit));
+    }
+
+    let mut graph = AdjacencyList::new();
+    for ((from, to, label), live) in edges {
+        if live {
+            graph.add_edge(from, label, to);
+        }
+    }
+    Ok(graph)
+}
+
+fn diff_maps(
+    old: &BTreeMap<String, ObjectId>,
+    new: &BTreeMap<String, ObjectId>,
+) -> Vec<PathChange> {
+    let mut changes = Vec::new();
+
+    for (path, &old_id) in old {
+        match new.get(path) {
+            None => changes.push(PathChange {
+                path: path.clone(),
+                status: ChangeStatus::Removed,
+                old_blob: Some(old_id),
+                new_blob: None,
+            }),
+            Some(&new_id) if new_id != old_id => changes.push(PathChange {
+                path: path.clone(),
+                status: ChangeStatus::Modified,
+                old_blob: Some(old_id),
+                new_blob: Some(new_id),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (path, &new_id) in new {
+        if !old.contains_key(path) {
+            changes.push(PathChange {
+                path: path.clone(),
+                status: ChangeStatus::Added,
+                old_blob: None,
+                new_blob: Some(new_id),
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CtxRepo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_graph_diff_between_commits() {
+        use crate::graph::DiffEdge;
+        use crate::types::NodeKind;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add parser").unwrap();
+        repo.observe_file_write("src/lib.rs", b"mod parser;\n")
+            .unwrap();
+        repo.observe_file_write("src/parser.rs", b"pub fn parse() {}\n")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let first = repo.compact_session("Add parser").unwrap();
+
+        repo.start_session("Replace parser with lexer").unwrap();
+        repo.observe_file_write("src/lib.rs", b"mod lexer;\n")
+            .unwrap();
+        repo.observe_file_write("src/lexer.rs", b"pub fn lex() {}\n")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let second = repo.compact_session("Replace parser with lexer").unwrap();
+
+        let file = |path: &str| NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        };
+        let diff = repo.graph_diff(first, second).unwrap();
+        let modules = diff
+            .groups
+            .iter()
+            .find(|group| group.label == EdgeLabel::DeclaresModule)
+            .unwrap();
+        assert_eq!(modules.kind, NodeKind::File);
+        assert_eq!(
+            modules.added,
+            vec![DiffEdge {
+                from: file("src/lib.rs"),
+                to: file("src/lexer.rs")
+            }]
+        );
+        // Superseded by the new src/lib.rs
+        assert_eq!(
+            modules.removed,
+            vec![DiffEdge {
+                from: file("src/lib.rs"),
+                to: file("src/parser.rs")
+            }]
+        );
+        assert_eq!(diff.added_nodes, vec![file("src/lexer.rs")]);
+        assert!(diff.removed_nodes.is_empty());
+
+        let back = repo.graph_diff(second, first).unwrap();
+        assert_eq!(back.added_edges(), diff.removed_edges());
+        assert_eq!(back.removed_nodes, vec![file("src/lexer.rs")]);
+        assert!(repo.graph_diff(second, second).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_commit_diff_files_and_edges() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("First").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() {}").unwrap();
+        repo.observe_file_write("src/b.rs", b"fn b() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        let first = repo.compact_session("First").unwrap();
+
+        repo.start_session("Second").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() { b() }")
+            .unwrap();
+        repo.observe_file_write("src/c.rs", b"fn c() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        let second = repo.compact_session("Second").unwrap();
+
+        let diff = repo.diff(first, second).unwrap();
+        let files: Vec<(&str, ChangeStatus)> = diff
+            .files
+            .iter()
+            .map(|c| (c.path.as_str(), c.status))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("src/a.rs", ChangeStatus::Modified),
+                ("src/b.rs", ChangeStatus::Removed),
+                ("src/c.rs", ChangeStatus::Added),
+            ]
+        );
+        assert!(!diff.edges_added.is_empty());
+        assert!(diff.edges_removed.is_empty());
+
+        let reverse = repo.diff(second, first).unwrap();
+        assert_eq!(reverse.edges_removed.len(), diff.edges_added.len());
+
+        assert!(repo.diff(second, second).unwrap().is_empty());
+
+        let json: serde_json::Value = serde_json::from_str(&diff.to_json().unwrap()).unwrap();
+        assert_eq!(json["files"][2]["status"], "added");
+        assert!(diff.to_text().contains("  D src/b.rs"));
+    }
+}
@@ -0,0 +1,420 @@
+//! Weekly narrative digests.
+//!
+//! Daily logs and session commits pile up on long-running projects. A
+//! [`WeeklyDigest`] rolls one week (Monday to Sunday, UTC) into a single
+//! narrative document: the sessions compacted that week grouped by task,
+//! with the files they wrote; the tasks committed as done; the files most
+//! sessions touched; and the first line of each log entry.
+//!
+//! [`CtxRepo::write_weekly_digest`](crate::CtxRepo::write_weekly_digest)
+//! stores it as `digests/week_YYYY-MM-DD.md` (the Monday) with `kind:
+//! digest` frontmatter, so it is a `Document` node like any other narrative
+//! document, with `DerivedFrom` edges to a `Session` node per session it
+//! covers.
+
+use crate::error::Result;
+use crate::log::{CommitLog, LogFilter};
+use crate::narrative::{log_date_time, log_day, NarrativeSpace};
+use crate::types::{
+    Commit, CommitType, Confidence, Edge, EdgeBatch, EdgeLabel, Evidence, EvidenceTool, NodeId,
+    NodeKind,
+};
+use crate::{ObjectId, ObjectStore};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Document kind of digests.
+pub const DIGEST_KIND: &str = "digest";
+
+/// Most files listed as the week's most changed.
+const MAX_TOP_FILES: usize = 10;
+
+/// Most files listed per task before the rest are counted.
+const MAX_TASK_FILES: usize = 8;
+
+/// One week of activity, ready to render.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WeeklyDigest {
+    /// The week's Monday (`YYYY-MM-DD`).
+    pub week_start: String,
+    /// The week's Sunday (`YYYY-MM-DD`).
+    pub week_end: String,
+    /// Sessions compacted during the week, oldest first.
+    pub sessions: Vec<DigestSession>,
+    /// Tasks committed as done or closed during the week, as (path, title).
+    pub completed_tasks: Vec<(String, String)>,
+    /// First line of each log entry, as (date, line), oldest first.
+    pub log_entries: Vec<(String, String)>,
+}
+
+/// A session covered by a digest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DigestSession {
+    /// The commit the session was compacted into.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub commit_id: ObjectId,
+    /// The session's task.
+    pub task: String,
+    /// First line of the commit message.
+    pub summary: String,
+    /// Commit time (Unix seconds).
+    pub timestamp: u64,
+    /// Files the session wrote, sorted.
+    pub files: Vec<String>,
+}
+
+impl WeeklyDigest {
+    /// Collects the week containing `at_unix` from the history behind
+    /// `head` and the daily logs in `narrative`.
+    ///
+    /// Abandoned sessions are left out.
+    pub(crate) fn collect(
+        object_store: &ObjectStore,
+        head: ObjectId,
+        narrative: &NarrativeSpace,
+        at_unix: u64,
+    ) -> Result<Self> {
+        let (first_day, last_day) = week_days(at_unix);
+        let since = first_day as u64 * 86_400;
+        let until = (last_day as u64 + 1) * 86_400 - 1;
+        let filter = LogFilter {
+            since: Some(since),
+            until: Some(until),
+            ..Default::default()
+        };
+
+        let mut digest = WeeklyDigest {
+            week_start: log_date_time(since).0,
+            week_end: log_date_time(until).0,
+            ..Default::default()
+        };
+        let mut seen_tasks = BTreeSet::new();
+        // Newest first, so the latest version of each task file wins
+        for entry in CommitLog::new(object_store, head, filter) {
+            let (commit_id, commit) = entry?;
+            for narrative_ref in &commit.narrative_refs {
+                if !narrative_ref.path.starts_with("tasks/")
+                    || !seen_tasks.insert(narrative_ref.path.clone())
+                {
+                    continue;
+                }
+                let content = NarrativeSpace::read_from_blob(object_store, narrative_ref.blob_id)?;
+                if let Some(title) = completed_task_title(&content) {
+                    digest
+                        .completed_tasks
+                        .push((narrative_ref.path.clone(), title));
+                }
+            }
+            if let Some(session) = session(object_store, commit_id, &commit)? {
+                digest.sessions.push(session);
+            }
+        }
+        digest.sessions.reverse();
+        digest.completed_tasks.sort();
+
+        for file in narrative.list_files()? {
+            let Some(day) = log_day(&file) else {
+                continue;
+            };
+            if day < first_day || day > last_day {
+                continue;
+            }
+            let Ok(content) = String::from_utf8(narrative.read_file(&file)?) else {
+                continue;
+            };
+            let date = log_date_time(day as u64 * 86_400).0;
+            digest.log_entries.extend(
+                log_entry_lines(&content)
+                    .into_iter()
+                    .map(|line| (date.clone(), line)),
+            );
+        }
+
+        Ok(digest)
+    }
+
+    /// Path of the digest relative to the narrative root.
+    pub fn relative_path(&self) -> String {
+        format!("digests/week_{}.md", self.week_start)
+    }
+
+    /// The title of the digest.
+    pub fn title(&self) -> String {
+        format!("Week of {}", self.week_start)
+    }
+
+    /// Renders the digest as a narrative document.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "---\nkind: {}\ntitle: {}\nweek: {} to {}\n---\n\n# {}\n",
+            DIGEST_KIND,
+            self.title(),
+            self.week_start,
+            self.week_end,
+            self.title()
+        );
+
+        out.push_str("\n## Sessions\n\n");
+        if self.sessions.is_empty() {
+            out.push_str("No sessions.\n");
+        }
+        let mut by_task: BTreeMap<&str, Vec<&DigestSession>> = BTreeMap::new();
+        for session in &self.sessions {
+            by_task.entry(&session.task).or_default().push(session);
+        }
+        for (task, sessions) in &by_task {
+            out.push_str(&format!(
+                "### {} ({} session{})\n\n",
+                task,
+                sessions.len(),
+                if sessions.len() == 1 { "" } else { "s" }
+            ));
+            for session in sessions {
+                out.push_str(&format!(
+                    "- {} {}\n",
+                    log_date_time(session.timestamp).0,
+                    session.summary
+                ));
+            }
+            let files: BTreeSet<&str> = sessions
+                .iter()
+                .flat_map(|session| session.files.iter().map(String::as_str))
+                .collect();
+            if !files.is_empty() {
+                let listed: Vec<&str> = files.iter().copied().take(MAX_TASK_FILES).collect();
+                let rest = files.len() - listed.len();
+                out.push_str(&format!("\nFiles: {}", listed.join(", ")));
+                if rest > 0 {
+                    out.push_str(&format!(" and {} more", rest));
+                }
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+        if !self.sessions.is_empty() {
+            out.pop();
+        }
+
+        if !self.completed_tasks.is_empty() {
+            out.push_str("\n## Completed tasks\n\n");
+            for (path, title) in &self.completed_tasks {
+                out.push_str(&format!("- {} ({})\n", title, path));
+            }
+        }
+
+        let top_files = self.top_files();
+        if !top_files.is_empty() {
+            out.push_str("\n## Most changed files\n\n");
+            for (path, sessions) in top_files {
+                out.push_str(&format!(
+                    "- {} ({} session{})\n",
+                    path,
+                    sessions,
+                    if sessions == 1 { "" } else { "s" }
+                ));
+            }
+        }
+
+        if !self.log_entries.is_empty() {
+            out.push_str("\n## Log\n\n");
+            for (date, line) in &self.log_entries {
+                out.push_str(&format!("- {}: {}\n", date, line));
+            }
+        }
+        out
+    }
+
+    /// Files written by the most sessions, with their session counts.
+    fn top_files(&self) -> Vec<(&str, usize)> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for session in &self.sessions {
+            for file in &session.files {
+                *counts.entry(file).or_default() += 1;
+            }
+        }
+        let mut files: Vec<(&str, usize)> = counts.into_iter().collect();
+        // Stable, so ties stay in path order
+        files.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        files.truncate(MAX_TOP_FILES);
+        files
+    }
+
+    /// `DerivedFrom` edges from the digest's document node to the sessions
+    /// it covers.
+    pub(crate) fn session_edges(&self, blob_id: ObjectId, commit_id: ObjectId) -> Vec<Edge> {
+        let from = crate::document::document_node(&self.relative_path());
+        self.sessions
+            .iter()
+            .map(|session| Edge {
+                from: from.clone(),
+                to: session_node(session.commit_id),
+                label: EdgeLabel::DerivedFrom,
+                weight: None,
+                evidence: Evidence {
+                    commit_id,
+                    tool: EvidenceTool::Parser,
+                    confidence: Confidence::High,
+                    span: None,
+                    blob_id: Some(blob_id),
+                    condition: None,
+                },
+            })
+            .collect()
+    }
+}
+
+/// The graph node for the session compacted into `commit_id`.
+pub(crate) fn session_node(commit_id: ObjectId) -> NodeId {
+    NodeId {
+        kind: NodeKind::Session,
+        id: commit_id.as_hex(),
+    }
+}
+
+/// First and last day of the week (Monday to Sunday) containing `at_unix`,
+/// as days since the Unix epoch.
+fn week_days(at_unix: u64) -> (i64, i64) {
+    let day = (at_unix / 86_400) as i64;
+    // The epoch was a Thursday
+    let monday = day - (day + 3).rem_euclid(7);
+    (monday, monday + 6)
+}
+
+/// The session compacted into `commit`, or None if it isn't a session
+/// commit or the session was abandoned.
+fn session(
+    object_store: &ObjectStore,
+    commit_id: ObjectId,
+    commit: &Commit,
+) -> Result<Option<DigestSession>> {
+    let Some(task) = &commit.task else {
+        return Ok(None);
+    };
+    if matches!(commit.commit_type, Some(CommitType::Abandoned)) {
+        return Ok(None);
+    }
+
+    let mut files = BTreeSet::new();
+    for batch_id in &commit.edge_batches {
+        let batch: EdgeBatch = object_store.get_typed(*batch_id)?;
+        files.extend(
+            batch
+                .edges
+                .into_iter()
+                .filter(|edge| edge.label == EdgeLabel::UpdatedIn)
+                .map(|edge| edge.from.id),
+        );
+    }
+
+    Ok(Some(DigestSession {
+        commit_id,
+        task: task.clone(),
+        summary: commit
+            .message
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        timestamp: commit.timestamp_unix,
+        files: files.into_iter().collect(),
+    }))
+}
+
+/// The title of a task file whose status is done or closed.
+fn completed_task_title(content: &str) -> Option<String> {
+    let done = content.lines().any(|line| {
+        line.strip_prefix("**Status:**")
+            .is_some_and(|status| matches!(status.trim(), "done" | "closed"))
+    });
+    if !done {
+        return None;
+    }
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim().to_string())
+}
+
+/// First non-empty line of each `### HH:MM` entry in a daily log.
+fn log_entry_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut in_entry = false;
+    for line in content.lines() {
+        if line.starts_with("### ") {
+            in_entry = true;
+        } else if in_entry && !line.trim().is_empty() {
+            lines.push(line.trim().to_string());
+            in_entry = false;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_week_days() {
+        // 2026-10-14 is a Wednesday
+        let wednesday = 20_740 * 86_400 + 3_600;
+        let (monday, sunday) = week_days(wednesday);
+        assert_eq!(log_date_time(monday as u64 * 86_400).0, "2026-10-12");
+        assert_eq!(log_date_time(sunday as u64 * 86_400).0, "2026-10-18");
+        assert_eq!(week_days(monday as u64 * 86_400), (monday, sunday));
+        assert_eq!(week_days(sunday as u64 * 86_400 + 86_399).0, monday);
+    }
+
+    #[test]
+    fn test_log_entry_lines_and_completed_tasks() {
+        let log = "# 2026-10-13\n\n### 09:00\n\nFixed the lexer\nSecond line\n\n### 14:30\n\n\nReviewed PRs\n\n";
+        assert_eq!(log_entry_lines(log), ["Fixed the lexer", "Reviewed PRs"]);
+
+        assert_eq!(
+            completed_task_title("# Ship it\n\n**Status:** done\n").as_deref(),
+            Some("Ship it")
+        );
+        assert_eq!(
+            completed_task_title("# Ship it\n\n**Status:** open\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let id = ObjectId::from_bytes([1; 32]);
+        let session = |task: &str, files: &[&str]| DigestSession {
+            commit_id: id,
+            task: task.to_string(),
+            summary: format!("Work on {}", task),
+            timestamp: 20_740 * 86_400,
+            files: files.iter().map(|f| f.to_string()).collect(),
+        };
+        let digest = WeeklyDigest {
+            week_start: "2026-10-12".to_string(),
+            week_end: "2026-10-18".to_string(),
+            sessions: vec![
+                session("Parser", &["src/lexer.rs", "src/parser.rs"]),
+                session("Docs", &[]),
+                session("Parser", &["src/parser.rs"]),
+            ],
+            completed_tasks: vec![("tasks/task_0002.md".to_string(), "Ship it".to_string())],
+            log_entries: vec![("2026-10-13".to_string(), "Fixed the lexer".to_string())],
+        };
+        let rendered = digest.render();
+
+        assert_eq!(digest.relative_path(), "digests/week_2026-10-12.md");
+        assert!(rendered.starts_with("---\nkind: digest\ntitle: Week of 2026-10-12\n"));
+        assert!(rendered.contains("### Parser (2 sessions)\n\n- 2026-10-14 Work on Parser\n"));
+        assert!(rendered.contains("Files: src/lexer.rs, src/parser.rs\n"));
+        assert!(rendered.contains("### Docs (1 session)\n\n- 2026-10-14 Work on Docs\n\n###"));
+        assert!(rendered.contains("- src/parser.rs (2 sessions)\n- src/lexer.rs (1 session)\n"));
+        assert!(rendered.contains("- Ship it (tasks/task_0002.md)\n"));
+        assert!(rendered.ends_with("## Log\n\n- 2026-10-13: Fixed the lexer\n"));
+
+        let document =
+            crate::document::Document::parse(&digest.relative_path(), &rendered).unwrap();
+        assert_eq!(document.kind, DIGEST_KIND);
+        assert_eq!(digest.session_edges(id, id).len(), 3);
+    }
+}
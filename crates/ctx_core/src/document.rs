@@ -0,0 +1,394 @@
+//! Narrative documents of configured types.
+//!
+//! Logs and tasks have fixed formats. Other documents, such as experiment
+//! write-ups, incident reports or meeting notes, are declared as types under
+//! `[documents.<kind>]` in the config: the directory they live in, the
+//! frontmatter fields they take, and a template for the body. Three types,
+//! `experiment`, `incident` and `meeting`, are built in; a configured type
+//! of the same name replaces the built-in one.
+//!
+//! A document is a Markdown file in the narrative space with its fields as
+//! `key: value` lines between `---` markers:
+//!
+//! ```text
+//! ---
+//! kind: incident
+//! title: Index corruption after crash
+//! severity: high
+//! ---
+//!
+//! # Index corruption after crash
+//! ...
+//! ```
+//!
+//! Each document is a `Document` node with `Mentions` edges to the files
+//! its fields and body name, so retrieval can start from it.
+
+use crate::error::{CtxError, Result};
+use crate::types::{Confidence, Edge, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind};
+use crate::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Field every document may have, rendered as its heading.
+pub const TITLE_FIELD: &str = "title";
+
+/// A configured document type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocumentType {
+    /// Directory under the narrative root holding documents of this type
+    /// (default: the kind with an `s` appended, e.g. `incidents`).
+    pub directory: Option<String>,
+    /// Frontmatter fields by name. If empty, any fields are accepted.
+    pub fields: BTreeMap<String, FieldSchema>,
+    /// Body written below the title, with `{{field}}` replaced by the
+    /// field's value (default: empty).
+    pub template: String,
+}
+
+/// Constraints on one frontmatter field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldSchema {
+    /// The field must be given a non-empty value.
+    pub required: bool,
+    /// Allowed values. If empty, any value is allowed.
+    pub values: Vec<String>,
+}
+
+/// A parsed narrative document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Document {
+    /// Document type, from the `kind` field.
+    pub kind: String,
+    /// Frontmatter fields other than `kind`.
+    pub fields: BTreeMap<String, String>,
+    /// Content after the frontmatter.
+    pub body: String,
+    /// Path relative to the narrative root.
+    pub relative_path: String,
+}
+
+impl DocumentType {
+    /// The directory for documents of `kind`.
+    pub fn directory_for(&self, kind: &str) -> String {
+        self.directory
+            .clone()
+            .unwrap_or_else(|| format!("{}s", kind))
+    }
+
+    /// Checks `fields` against the schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] if a required field is missing
+    /// or empty, a value isn't one of the allowed ones, a field isn't
+    /// declared, or a name or value can't be written as a frontmatter line.
+    pub fn validate(&self, kind: &str, fields: &BTreeMap<String, String>) -> Result<()> {
+        for (name, value) in fields {
+            if name.is_empty()
+                || name == "kind"
+                || !name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(CtxError::InvalidArgument(format!(
+                    "invalid field name '{}'",
+                    name
+                )));
+            }
+            if value.contains('\n') {
+                return Err(CtxError::InvalidArgument(format!(
+                    "field '{}' must be a single line",
+                    name
+                )));
+            }
+            if !self.fields.is_empty() && name != TITLE_FIELD && !self.fields.contains_key(name) {
+                return Err(CtxError::InvalidArgument(format!(
+                    "unknown field '{}' for {} (expected one of: {})",
+                    name,
+                    kind,
+                    self.fields.keys().cloned().collect::<Vec<_>>().join(", ")
+                )));
+            }
+        }
+
+        for (name, schema) in &self.fields {
+            let value = fields.get(name).map(|v| v.trim()).unwrap_or_default();
+            if value.is_empty() {
+                if schema.required {
+                    return Err(CtxError::InvalidArgument(format!(
+                        "{} requires field '{}'",
+                        kind, name
+                    )));
+                }
+                continue;
+            }
+            if !schema.values.is_empty() && !schema.values.iter().any(|v| v == value) {
+                return Err(CtxError::InvalidArgument(format!(
+                    "invalid {} '{}' (expected one of: {})",
+                    name,
+                    value,
+                    schema.values.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders a document of `kind` with `fields`: the frontmatter, a
+    /// heading if there's a title, then the template.
+    pub fn render(&self, kind: &str, fields: &BTreeMap<String, String>) -> String {
+        let mut content = format!("---\nkind: {}\n", kind);
+        for (name, value) in fields {
+            content.push_str(&format!("{}: {}\n", name, value.trim()));
+        }
+        content.push_str("---\n");
+        if let Some(title) = fields.get(TITLE_FIELD).filter(|t| !t.trim().is_empty()) {
+            content.push_str(&format!("\n# {}\n", title.trim()));
+        }
+
+        let mut body = self.template.clone();
+        for (name, value) in fields {
+            body = body.replace(&format!("{{{{{}}}}}", name), value.trim());
+        }
+        // Placeholders for fields that weren't given
+        for name in self.fields.keys() {
+            body = body.replace(&format!("{{{{{}}}}}", name), "");
+        }
+        if !body.trim().is_empty() {
+            content.push('\n');
+            content.push_str(body.trim_end());
+            content.push('\n');
+        }
+        content
+    }
+}
+
+impl Document {
+    /// Parses a document, or returns None if `content` has no frontmatter
+    /// with a `kind` field.
+    pub fn parse(relative_path: &str, content: &str) -> Option<Self> {
+        let rest = content.strip_prefix("---\n")?;
+        let end = rest.find("\n---")?;
+        let mut fields = BTreeMap::new();
+        for line in rest[..end].lines() {
+            if let Some((name, value)) = line.split_once(':') {
+                fields.insert(name.trim().to_string(), value.trim().to_string());
+            }
+        }
+        let kind = fields.remove("kind")?;
+        let body = rest[end + "\n---".len()..]
+            .trim_start_matches('\n')
+            .to_string();
+
+        Some(Self {
+            kind,
+            fields,
+            body,
+            relative_path: relative_path.to_string(),
+        })
+    }
+
+    /// The document's title, if it has one.
+    pub fn title(&self) -> Option<&str> {
+        self.fields.get(TITLE_FIELD).map(String::as_str)
+    }
+}
+
+/// The built-in document types.
+pub fn builtin_types() -> BTreeMap<String, DocumentType> {
+    let field = |required: bool, values: &[&str]| FieldSchema {
+        required,
+        values: values.iter().map(|v| v.to_string()).collect(),
+    };
+    let mut types = BTreeMap::new();
+    types.insert(
+        "experiment".to_string(),
+        DocumentType {
+            directory: None,
+            fields: BTreeMap::from([
+                (TITLE_FIELD.to_string(), field(true, &[])),
+                (
+                    "status".to_string(),
+                    field(false, &["planned", "running", "done", "abandoned"]),
+                ),
+            ]),
+            template: "## Hypothesis\n\n## Method\n\n## Results\n".to_string(),
+        },
+    );
+    types.insert(
+        "incident".to_string(),
+        DocumentType {
+            directory: None,
+            fields: BTreeMap::from([
+                (TITLE_FIELD.to_string(), field(true, &[])),
+                (
+                    "severity".to_string(),
+                    field(true, &["low", "medium", "high", "critical"]),
+                ),
+                ("date".to_string(), field(false, &[])),
+            ]),
+            template: "## Impact\n\n## Timeline\n\n## Root cause\n\n## Follow-up\n".to_string(),
+        },
+    );
+    types.insert(
+        "meeting".to_string(),
+        DocumentType {
+            directory: None,
+            fields: BTreeMap::from([
+                (TITLE_FIELD.to_string(), field(true, &[])),
+                ("date".to_string(), field(false, &[])),
+                ("attendees".to_string(), field(false, &[])),
+            ]),
+            template: "Attendees: {{attendees}}\n\n## Notes\n\n## Action items\n".to_string(),
+        },
+    );
+    types
+}
+
+/// The graph node for the document at `relative_path` in the narrative
+/// space.
+pub(crate) fn document_node(relative_path: &str) -> NodeId {
+    NodeId {
+        kind: NodeKind::Document,
+        id: relative_path.to_string(),
+    }
+}
+
+/// Builds `Mentions` edges from a document's node to the files named in its
+/// fields or body.
+pub(crate) fn mention_edges(
+    document: &Document,
+    blob_id: ObjectId,
+    commit_id: ObjectId,
+    known_files: &BTreeSet<String>,
+) -> Vec<Edge> {
+    let text: Vec<&str> = document
+        .fields
+        .values()
+        .map(String::as_str)
+        .chain(std::iter::once(document.body.as_str()))
+        .collect();
+    let from = document_node(&document.relative_path);
+
+    crate::glossary::mentioned_files(&text.join("\n"), known_files)
+        .into_iter()
+        .map(|path| Edge {
+            from: from.clone(),
+            to: NodeId {
+                kind: NodeKind::File,
+                id: path,
+            },
+            label: EdgeLabel::Mentions,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool: EvidenceTool::Human,
+                confidence: Confidence::High,
+                span: None,
+                blob_id: Some(blob_id),
+                condition: None,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let types = builtin_types();
+        let incident = &types["incident"];
+
+        assert!(incident
+            .validate(
+                "incident",
+                &fields(&[("title", "Outage"), ("severity", "high")])
+            )
+            .is_ok());
+
+        let missing = incident
+            .validate("incident", &fields(&[("title", "Outage")]))
+            .unwrap_err();
+        assert!(missing.to_string().contains("severity"));
+
+        let invalid = incident
+            .validate(
+                "incident",
+                &fields(&[("title", "Outage"), ("severity", "dire")]),
+            )
+            .unwrap_err();
+        assert!(invalid.to_string().contains("low, medium, high, critical"));
+
+        assert!(incident
+            .validate(
+                "incident",
+                &fields(&[("title", "Outage"), ("severity", "low"), ("owner", "me")]),
+            )
+            .is_err());
+        assert!(incident
+            .validate(
+                "incident",
+                &fields(&[("title", "Two\nlines"), ("severity", "low")]),
+            )
+            .is_err());
+
+        // Without declared fields, anything goes
+        assert!(DocumentType::default()
+            .validate("note", &fields(&[("owner", "me")]))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_render_and_parse() {
+        let types = builtin_types();
+        let given = fields(&[
+            ("attendees", "ana, bo"),
+            ("title", "Planning"),
+            ("date", "2026-01-02"),
+        ]);
+        let content = types["meeting"].render("meeting", &given);
+
+        assert!(content.starts_with("---\nkind: meeting\n"));
+        assert!(content.contains("\n# Planning\n"));
+        assert!(content.contains("Attendees: ana, bo\n"));
+
+        let document = Document::parse("meetings/meeting_0001.md", &content).unwrap();
+        assert_eq!(document.kind, "meeting");
+        assert_eq!(document.fields, given);
+        assert_eq!(document.title(), Some("Planning"));
+        assert!(document.body.starts_with("# Planning\n"));
+
+        assert!(Document::parse("tasks/task_0001.md", "# Task\n").is_none());
+    }
+
+    #[test]
+    fn test_mention_edges() {
+        let known: BTreeSet<String> = ["src/index.rs".to_string(), "src/repo.rs".to_string()]
+            .into_iter()
+            .collect();
+        let document = Document {
+            kind: "incident".to_string(),
+            fields: fields(&[("title", "Corruption in index.rs")]),
+            body: "Not repo or README.md.\n".to_string(),
+            relative_path: "incidents/incident_0001.md".to_string(),
+        };
+        let id = ObjectId::from_bytes([0; 32]);
+        let edges = mention_edges(&document, id, id, &known);
+
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].from, document_node("incidents/incident_0001.md"));
+        assert_eq!(edges[0].to.id, "src/index.rs");
+    }
+}
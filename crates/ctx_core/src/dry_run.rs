@@ -0,0 +1,178 @@
+//! Trial runs of operations that change the repository.
+//!
+//! [`CtxRepo::dry_run`](crate::CtxRepo::dry_run) runs an operation such as
+//! a commit, a compaction or an analysis against scratch copies of the refs
+//! and the index, with an object store that keeps new objects in a scratch
+//! directory and reads everything else from the repository. The operation
+//! computes exactly what it would for real; the scratch state is then
+//! thrown away, and a [`DryRunReport`] describes what would have changed:
+//! the commits and objects that would be written and the refs that would
+//! move.
+//!
+//! Hooks don't run and no events are recorded during a dry run, and a
+//! session compacted in one stays active.
+
+use crate::error::Result;
+use crate::object_id::ObjectKind;
+use crate::refs::Refs;
+use crate::types::{Commit, EdgeBatch};
+use crate::{ObjectId, ObjectStore};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Distinguishes the scratch directories of concurrent dry runs.
+static SCRATCH_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// What an operation would have changed.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunReport {
+    /// Commits it would create, newest first.
+    pub commits: Vec<DryRunCommit>,
+    /// Objects it would write that aren't stored yet, by ID.
+    pub objects: Vec<DryRunObject>,
+    /// Stored (compressed) size of those objects.
+    pub object_bytes: u64,
+    /// Edges in the new commits' edge batches.
+    pub edges: usize,
+    /// Refs it would change.
+    pub refs: Vec<DryRunRefUpdate>,
+}
+
+/// A commit a dry run would create.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunCommit {
+    /// ID the commit would have.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub commit_id: ObjectId,
+    /// Its message.
+    pub message: String,
+    /// Edge batches it adds.
+    pub edge_batches: usize,
+    /// Edges in those batches.
+    pub edges: usize,
+}
+
+/// An object a dry run would write.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunObject {
+    /// Its ID.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub id: ObjectId,
+    /// `blob`, `typed` or `chunk_list`.
+    pub kind: &'static str,
+    /// Stored (compressed) size in bytes.
+    pub size: u64,
+}
+
+/// A ref a dry run would change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DryRunRefUpdate {
+    /// `HEAD`, `STAGE`, `SUMMARIES`, `FEEDBACK` or `refs/<name>`.
+    pub name: String,
+    /// Current target, or None if the ref isn't set.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub before: Option<ObjectId>,
+    /// Target after the operation, or None if it would be deleted.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub after: Option<ObjectId>,
+}
+
+/// A temporary directory for a dry run's state, removed when dropped.
+pub(crate) struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub(crate) fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "ctx-dry-run-{}-{}",
+            std::process::id(),
+            SCRATCH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+impl DryRunReport {
+    /// Compares the scratch store and refs a dry run wrote to with the
+    /// repository's.
+    pub(crate) fn collect(
+        store: &ObjectStore,
+        refs: &Refs,
+        scratch_store: &ObjectStore,
+        scratch_refs: &Refs,
+    ) -> Result<Self> {
+        let before = refs.all()?;
+        let after = scratch_refs.all()?;
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let ref_updates: Vec<DryRunRefUpdate> = names
+            .into_iter()
+            .map(|name| DryRunRefUpdate {
+                name: name.clone(),
+                before: before.get(name).copied(),
+                after: after.get(name).copied(),
+            })
+            .filter(|update| update.before != update.after)
+            .collect();
+
+        // The scratch store also holds objects it read from the repository
+        let mut objects = Vec::new();
+        for (id, size, _) in scratch_store.list_all_objects()? {
+            if store.exists(id) {
+                continue;
+            }
+            let kind = match scratch_store.object_kind(id)? {
+                ObjectKind::Blob => "blob",
+                ObjectKind::Typed => "typed",
+                ObjectKind::ChunkList => "chunk_list",
+            };
+            objects.push(DryRunObject { id, kind, size });
+        }
+        objects.sort_by_key(|object| object.id);
+        let new: BTreeSet<ObjectId> = objects.iter().map(|object| object.id).collect();
+
+        // New commits, from the new HEAD back to the first existing one
+        let mut commits = Vec::new();
+        let mut next = after.get("HEAD").copied().filter(|id| new.contains(id));
+        while let Some(id) = next {
+            let commit: Commit = scratch_store.get_typed(id)?;
+            let mut edges = 0;
+            for batch_id in &commit.edge_batches {
+                let batch: EdgeBatch = scratch_store.get_typed(*batch_id)?;
+                edges += batch.edges.len();
+            }
+            next = commit
+                .parents
+                .first()
+                .copied()
+                .filter(|id| new.contains(id));
+            commits.push(DryRunCommit {
+                commit_id: id,
+                message: commit.message,
+                edge_batches: commit.edge_batches.len(),
+                edges,
+            });
+        }
+
+        Ok(Self {
+            object_bytes: objects.iter().map(|object| object.size).sum(),
+            edges: commits.iter().map(|commit| commit.edges).sum(),
+            commits,
+            objects,
+            refs: ref_updates,
+        })
+    }
+}
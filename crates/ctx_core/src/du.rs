@@ -0,0 +1,519 @@
+//! Storage accounting.
+//!
+//! [`storage_report`] attributes every stored object to a category by
+//! walking the object graph from HEAD, STAGE, SUMMARIES, FEEDBACK and `refs/*`: commits lead to
+//! trees, edge batches, narrative blobs and snapshots, trees lead to file
+//! blobs, and the staging chain leads to the blobs its observations wrote.
+//! Objects the walk never reaches are unreachable; they are split only into
+//! blobs and other typed objects, since typed objects carry no type tag.
+//!
+//! Sizes are reported both as stored (zstd-compressed on disk) and as the
+//! uncompressed payload, so every object is read once.
+
+use crate::decision::DecisionLog;
+use crate::error::Result;
+use crate::fact::FactSet;
+use crate::feedback::{Feedback, FeedbackLog};
+use crate::notes::NoteTable;
+use crate::object_id::{ObjectId, ObjectKind};
+use crate::object_store::ObjectStore;
+use crate::qa::QaLog;
+use crate::refs::Refs;
+use crate::summary::SummaryTable;
+use crate::transcript::Transcript;
+use crate::types::{Commit, Observation, Tree, TreeEntryKind, WorkCommit};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+/// What an object holds, as determined by how it is referenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectCategory {
+    /// Canonical commits.
+    Commit,
+    /// Work commits in the staging chain.
+    WorkCommit,
+    /// Directory trees.
+    Tree,
+    /// File contents and command output.
+    Blob,
+    /// Edge batches.
+    EdgeBatch,
+    /// Narrative log blobs.
+    Narrative,
+    /// Cargo, Rust and diagnostics snapshots.
+    Snapshot,
+    /// Glossaries.
+    Glossary,
+    /// Decision records and the logs listing them.
+    Decision,
+    /// Facts and the sets listing them.
+    Fact,
+    /// Answered questions and the logs listing them.
+    Answer,
+    /// Session transcripts and the turns they hold.
+    Transcript,
+    /// Cached file summaries and the table listing them.
+    Summary,
+    /// Feedback on pack chunks, the snippets it rates and the log listing it.
+    Feedback,
+    /// Commit annotations and the table listing them.
+    Note,
+    /// Typed objects whose type is unknown.
+    Other,
+}
+
+impl ObjectCategory {
+    /// Human-readable name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectCategory::Commit => "commits",
+            ObjectCategory::WorkCommit => "work commits",
+            ObjectCategory::Tree => "trees",
+            ObjectCategory::Blob => "blobs",
+            ObjectCategory::EdgeBatch => "edge batches",
+            ObjectCategory::Narrative => "narrative",
+            ObjectCategory::Snapshot => "snapshots",
+            ObjectCategory::Glossary => "glossaries",
+            ObjectCategory::Decision => "decisions",
+            ObjectCategory::Fact => "facts",
+            ObjectCategory::Answer => "answers",
+            ObjectCategory::Transcript => "transcripts",
+            ObjectCategory::Summary => "summaries",
+            ObjectCategory::Feedback => "feedback",
+            ObjectCategory::Note => "notes",
+            ObjectCategory::Other => "other",
+        }
+    }
+}
+
+/// Object count and sizes for a set of objects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UsageTotals {
+    /// Number of objects.
+    pub objects: usize,
+    /// Bytes on disk (compressed).
+    pub compressed_bytes: u64,
+    /// Bytes of payload (uncompressed).
+    pub uncompressed_bytes: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, compressed: u64, uncompressed: u64) {
+        self.objects += 1;
+        self.compressed_bytes += compressed;
+        self.uncompressed_bytes += uncompressed;
+    }
+}
+
+/// Usage of one object category.
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryUsage {
+    /// The category.
+    pub category: ObjectCategory,
+    /// Objects reachable from HEAD, STAGE, or refs.
+    pub referenced: UsageTotals,
+    /// Objects nothing references.
+    pub unreachable: UsageTotals,
+}
+
+/// One large blob.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlobUsage {
+    /// Blob ID.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub id: ObjectId,
+    /// Bytes on disk (compressed).
+    pub compressed_bytes: u64,
+    /// Bytes of content (uncompressed).
+    pub uncompressed_bytes: u64,
+    /// A path the blob was stored under, if any.
+    pub path: Option<String>,
+    /// Whether anything references the blob.
+    pub referenced: bool,
+}
+
+/// Storage used by every version of one path.
+#[derive(Debug, Clone, Serialize)]
+pub struct PathUsage {
+    /// Repository-relative file path.
+    pub path: String,
+    /// Distinct versions stored.
+    pub versions: usize,
+    /// Bytes on disk (compressed) across versions.
+    pub compressed_bytes: u64,
+    /// Bytes of content (uncompressed) across versions.
+    pub uncompressed_bytes: u64,
+}
+
+/// Storage report returned by [`crate::CtxRepo::storage_report`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageReport {
+    /// Every stored object.
+    pub total: UsageTotals,
+    /// Objects reachable from HEAD, STAGE, or refs.
+    pub referenced: UsageTotals,
+    /// Objects nothing references.
+    pub unreachable: UsageTotals,
+    /// Usage by category, for categories with any objects.
+    pub categories: Vec<CategoryUsage>,
+    /// Largest blobs by size on disk.
+    pub largest_blobs: Vec<BlobUsage>,
+    /// Paths whose versions use the most space on disk.
+    pub largest_paths: Vec<PathUsage>,
+    /// Objects that couldn't be read (non-fatal).
+    pub errors: Vec<String>,
+}
+
+/// An object to classify, with what referenced it.
+enum Pending {
+    Commit(ObjectId),
+    Work(ObjectId),
+    Tree(ObjectId, String),
+    Blob(ObjectId, Option<String>),
+    Leaf(ObjectId, ObjectCategory),
+}
+
+/// Attributes every stored object to a category and reports sizes, with
+/// the `top` largest blobs and paths.
+///
+/// # Errors
+///
+/// Returns an error if refs or the object store can't be listed.
+/// Unreadable objects are recorded in [`StorageReport::errors`].
+pub fn storage_report(refs: &Refs, store: &ObjectStore, top: usize) -> Result<StorageReport> {
+    let mut report = StorageReport::default();
+    let (categories, paths) = classify(refs, store)?;
+
+    let mut by_category: BTreeMap<ObjectCategory, CategoryUsage> = BTreeMap::new();
+    let mut sizes: HashMap<ObjectId, (u64, u64)> = HashMap::new();
+    let mut blobs = Vec::new();
+
+    for (id, compressed, _) in store.list_all_objects()? {
+        let (kind, uncompressed) = match store.payload_size(id) {
+            Ok(size) => size,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", id.as_hex(), e));
+                continue;
+            }
+        };
+        let referenced = categories.get(&id).copied();
+        let category = referenced.unwrap_or(match kind {
+            ObjectKind::Blob | ObjectKind::ChunkList => ObjectCategory::Blob,
+            ObjectKind::Typed => ObjectCategory::Other,
+        });
+        let usage = by_category.entry(category).or_insert(CategoryUsage {
+            category,
+            referenced: UsageTotals::default(),
+            unreachable: UsageTotals::default(),
+        });
+        report.total.add(compressed, uncompressed);
+        if referenced.is_some() {
+            usage.referenced.add(compressed, uncompressed);
+            report.referenced.add(compressed, uncompressed);
+        } else {
+            usage.unreachable.add(compressed, uncompressed);
+            report.unreachable.add(compressed, uncompressed);
+        }
+
+        if category == ObjectCategory::Blob {
+            blobs.push(BlobUsage {
+                id,
+                compressed_bytes: compressed,
+                uncompressed_bytes: uncompressed,
+                path: None,
+                referenced: referenced.is_some(),
+            });
+        }
+        sizes.insert(id, (compressed, uncompressed));
+    }
+    report.categories = by_category.into_values().collect();
+
+    // Name each large blob after the first path it appears under
+    let mut blob_paths: HashMap<ObjectId, &str> = HashMap::new();
+    for (path, versions) in &paths {
+        for id in versions {
+            blob_paths.entry(*id).or_insert(path);
+        }
+    }
+    blobs.sort_by(|a, b| {
+        b.compressed_bytes
+            .cmp(&a.compressed_bytes)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    blobs.truncate(top);
+    for blob in &mut blobs {
+        blob.path = blob_paths.get(&blob.id).map(|path| path.to_string());
+    }
+    report.largest_blobs = blobs;
+
+    let mut largest_paths: Vec<PathUsage> = paths
+        .into_iter()
+        .map(|(path, versions)| {
+            let mut usage = PathUsage {
+                path,
+                versions: 0,
+                compressed_bytes: 0,
+                uncompressed_bytes: 0,
+            };
+            for (compressed, uncompressed) in versions.iter().filter_map(|id| sizes.get(id)) {
+                usage.versions += 1;
+                usage.compressed_bytes += compressed;
+                usage.uncompressed_bytes += uncompressed;
+            }
+            usage
+        })
+        .filter(|usage| usage.versions > 0)
+        .collect();
+    largest_paths.sort_by(|a, b| {
+        b.compressed_bytes
+            .cmp(&a.compressed_bytes)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    largest_paths.truncate(top);
+    report.largest_paths = largest_paths;
+
+    Ok(report)
+}
+
+/// Walks the object graph from every root, returning the category of each
+/// reachable object and the blob versions stored under each path.
+#[allow(clippy::type_complexity)]
+fn classify(
+    refs: &Refs,
+    store: &ObjectStore,
+) -> Result<(
+    HashMap<ObjectId, ObjectCategory>,
+    BTreeMap<String, BTreeSet<ObjectId>>,
+)> {
+    let mut categories = HashMap::new();
+    let mut paths: BTreeMap<String, BTreeSet<ObjectId>> = BTreeMap::new();
+    let mut seen_trees = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    if let Ok(head) = refs.read_head() {
+        queue.push_back(Pending::Commit(head));
+    }
+    if let Some(stage) = refs.read_stage()? {
+        queue.push_back(Pending::Work(stage));
+    }
+    for (_name, id) in refs.list_refs()? {
+        queue.push_back(Pending::Commit(id));
+    }
+    if let Some(summaries) = refs.read_summaries()? {
+        queue.push_back(Pending::Leaf(summaries, ObjectCategory::Summary));
+        if let Ok(table) = store.get_typed::<SummaryTable>(summaries) {
+            queue.extend(
+                table
+                    .entries
+                    .values()
+                    .map(|s| Pending::Leaf(*s, ObjectCategory::Summary)),
+            );
+        }
+    }
+    if let Some(feedback) = refs.read_feedback()? {
+        queue.push_back(Pending::Leaf(feedback, ObjectCategory::Feedback));
+        if let Ok(log) = store.get_typed::<FeedbackLog>(feedback) {
+            for entry_id in log.entries {
+                queue.push_back(Pending::Leaf(entry_id, ObjectCategory::Feedback));
+                if let Ok(entry) = store.get_typed::<Feedback>(entry_id) {
+                    queue.extend(
+                        entry
+                            .useful
+                            .iter()
+                            .chain(&entry.useless)
+                            .map(|chunk| Pending::Leaf(chunk.snippet_id, ObjectCategory::Feedback)),
+                    );
+                }
+            }
+        }
+    }
+    if let Some(notes) = refs.read_notes()? {
+        queue.push_back(Pending::Leaf(notes, ObjectCategory::Note));
+        if let Ok(table) = store.get_typed::<NoteTable>(notes) {
+            queue.extend(
+                table
+                    .annotation_ids()
+                    .map(|id| Pending::Leaf(id, ObjectCategory::Note)),
+            );
+        }
+    }
+
+    while let Some(pending) = queue.pop_front() {
+        match pending {
+            Pending::Commit(id) => {
+                if categories.contains_key(&id) {
+                    continue;
+                }
+                let Ok(commit) = store.get_typed::<Commit>(id) else {
+                    categories.insert(id, ObjectCategory::Other);
+                    continue;
+                };
+                categories.insert(id, ObjectCategory::Commit);
+                queue.extend(store.parents(&commit).into_iter().map(Pending::Commit));
+                queue.push_back(Pending::Tree(commit.root_tree, String::new()));
+                queue.extend(
+                    commit
+                        .edge_batches
+                        .iter()
+                        .map(|b| Pending::Leaf(*b, ObjectCategory::EdgeBatch)),
+                );
+                queue.extend(
+                    commit
+                        .narrative_refs
+                        .iter()
+                        .map(|r| Pending::Leaf(r.blob_id, ObjectCategory::Narrative)),
+                );
+                queue.extend(
+                    [
+                        commit.cargo_snapshot,
+                        commit.rust_snapshot,
+                        commit.diagnostics_snapshot,
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .map(|s| Pending::Leaf(s, ObjectCategory::Snapshot)),
+                );
+                queue.extend(
+                    commit
+                        .glossary
+                        .map(|g| Pending::Leaf(g, ObjectCategory::Glossary)),
+                );
+                if let Some(log_id) = commit.decisions {
+                    queue.push_back(Pending::Leaf(log_id, ObjectCategory::Decision));
+                    if let Ok(log) = store.get_typed::<DecisionLog>(log_id) {
+                        queue.extend(
+                            log.decisions
+                                .into_iter()
+                                .map(|d| Pending::Leaf(d, ObjectCategory::Decision)),
+                        );
+                    }
+                }
+                if let Some(set_id) = commit.facts {
+                    queue.push_back(Pending::Leaf(set_id, ObjectCategory::Fact));
+                    if let Ok(set) = store.get_typed::<FactSet>(set_id) {
+                        queue.extend(
+                            set.facts
+                                .into_iter()
+                                .map(|f| Pending::Leaf(f, ObjectCategory::Fact)),
+                        );
+                    }
+                }
+                if let Some(log_id) = commit.qa {
+                    queue.push_back(Pending::Leaf(log_id, ObjectCategory::Answer));
+                    if let Ok(log) = store.get_typed::<QaLog>(log_id) {
+                        queue.extend(
+                            log.pairs
+                                .into_iter()
+                                .map(|p| Pending::Leaf(p, ObjectCategory::Answer)),
+                        );
+                    }
+                }
+                if let Some(transcript_id) = commit.transcript {
+                    queue.push_back(Pending::Leaf(transcript_id, ObjectCategory::Transcript));
+                    if let Ok(transcript) = store.get_typed::<Transcript>(transcript_id) {
+                        queue.extend(
+                            transcript
+                                .messages
+                                .into_iter()
+                                .map(|m| Pending::Leaf(m.content_id, ObjectCategory::Transcript)),
+                        );
+                    }
+                }
+            }
+            Pending::Work(id) => {
+                if categories.contains_key(&id) {
+                    continue;
+                }
+                // The chain ends at the canonical base commit
+                let Ok(work) = store.get_typed::<WorkCommit>(id) else {
+                    queue.push_back(Pending::Commit(id));
+                    continue;
+                };
+                categories.insert(id, ObjectCategory::WorkCommit);
+                queue.extend(work.parents.iter().map(|p| Pending::Work(*p)));
+                queue.push_back(Pending::Commit(work.base));
+                queue.extend(
+                    work.narrative_refs
+                        .iter()
+                        .map(|r| Pending::Leaf(r.blob_id, ObjectCategory::Narrative)),
+                );
+                let observations: Vec<Observation> =
+                    postcard::from_bytes(&work.payload).unwrap_or_default();
+                for observation in observations {
+                    match observation {
+                        Observation::FileWrite {
+                            path, content_id, ..
+                        } => queue.push_back(Pending::Blob(content_id, Some(path))),
+                        Observation::FileRead {
+                            path,
+                            content_id: Some(content_id),
+                            ..
+                        } => queue.push_back(Pending::Blob(content_id, Some(path))),
+                        Observation::Command {
+                            output_id: Some(output_id),
+                            ..
+                        } => queue.push_back(Pending::Blob(output_id, None)),
+                        Observation::Decision { decision_id, .. } => {
+                            queue.push_back(Pending::Leaf(decision_id, ObjectCategory::Decision))
+                        }
+                        Observation::Qa { qa_id, .. } => {
+                            queue.push_back(Pending::Leaf(qa_id, ObjectCategory::Answer))
+                        }
+                        Observation::Message { content_id, .. } => {
+                            queue.push_back(Pending::Leaf(content_id, ObjectCategory::Transcript))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Pending::Tree(id, prefix) => {
+                // The same tree can appear under several directories
+                if !seen_trees.insert((id, prefix.clone())) {
+                    continue;
+                }
+                let Ok(tree) = store.get_typed::<Tree>(id) else {
+                    continue;
+                };
+                categories.insert(id, ObjectCategory::Tree);
+                for entry in tree.entries {
+                    let path = if prefix.is_empty() {
+                        entry.name
+                    } else {
+                        format!("{}/{}", prefix, entry.name)
+                    };
+                    queue.push_back(match entry.kind {
+                        TreeEntryKind::Tree => Pending::Tree(entry.id, path),
+                        TreeEntryKind::Blob => Pending::Blob(entry.id, Some(path)),
+                    });
+                }
+            }
+            Pending::Blob(id, path) => {
+                categories.entry(id).or_insert(ObjectCategory::Blob);
+                if let Some(large) = crate::large_file::large_file(store, id) {
+                    queue.extend(
+                        large
+                            .chunks
+                            .into_iter()
+                            .map(|chunk| Pending::Blob(chunk, path.clone())),
+                    );
+                }
+                if let Ok(Some(chunks)) = store.chunk_ids(id) {
+                    queue.extend(
+                        chunks
+                            .into_iter()
+                            .map(|chunk| Pending::Blob(chunk, path.clone())),
+                    );
+                }
+                if let Some(path) = path {
+                    paths.entry(path).or_default().insert(id);
+                }
+            }
+            Pending::Leaf(id, category) => {
+                categories.entry(id).or_insert(category);
+            }
+        }
+    }
+
+    Ok((categories, paths))
+}
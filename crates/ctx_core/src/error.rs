@@ -1,5 +1,6 @@
 //! Error types for ctx_core operations.
 
+use std::fmt;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -28,6 +29,16 @@ pub enum CtxError {
         reason: String,
     },
 
+    /// A stored object couldn't be decoded: its compressed stream is
+    /// damaged, or its payload isn't the type it was read as.
+    #[error("object {id} is corrupt: {reason}")]
+    ObjectCorrupt {
+        /// Hex ID of the object
+        id: String,
+        /// What failed to decode
+        reason: String,
+    },
+
     /// Invalid hex string for ObjectId parsing.
     #[error("invalid hex string: {0}")]
     InvalidHex(String),
@@ -66,6 +77,15 @@ pub enum CtxError {
         reason: String,
     },
 
+    /// A ref file couldn't be read, written or removed.
+    #[error("ref error at {}: {}", path.display(), reason)]
+    RefError {
+        /// Path to the ref file
+        path: PathBuf,
+        /// The underlying failure
+        reason: String,
+    },
+
     /// I/O error during file operations.
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
@@ -91,6 +111,16 @@ pub enum CtxError {
     #[error("repository locked by another process")]
     RepositoryLocked,
 
+    /// A lock file couldn't be created, read or locked, for reasons other
+    /// than another process holding it.
+    #[error("{kind} lock error: {reason}")]
+    LockError {
+        /// Which lock failed
+        kind: LockKind,
+        /// The underlying failure
+        reason: String,
+    },
+
     /// Staging chain is corrupted or inconsistent.
     #[error("staging chain corrupted: {reason}")]
     StagingCorrupted {
@@ -159,6 +189,10 @@ pub enum CtxError {
         message: String,
     },
 
+    /// The index database failed an operation.
+    #[error("index error: {0}")]
+    IndexError(String),
+
     /// Error in narrative file operations.
     #[error("narrative file error: {0}")]
     NarrativeError(String),
@@ -175,41 +209,227 @@ pub enum CtxError {
     #[error("search index error: {0}")]
     SearchError(String),
 
+    /// A caller-supplied argument could not be understood.
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// Pagination cursor is malformed or no longer valid.
+    #[error("invalid cursor: {0}")]
+    InvalidCursor(String),
+
     /// Session lock is held by another process.
     #[error("session lock held by another process (PID: {pid})")]
     SessionLockHeld {
         /// Process ID holding the lock
         pid: u32,
     },
+
+    /// The command execution policy refused to run a command.
+    #[error("command denied by exec policy: {command} ({reason})")]
+    CommandDenied {
+        /// The refused command line
+        command: String,
+        /// Why it was refused
+        reason: String,
+    },
+
+    /// The path matches an ignore rule.
+    #[error("path {path} is ignored by '{pattern}'")]
+    PathIgnored {
+        /// The ignored path
+        path: String,
+        /// The rule that ignores it
+        pattern: String,
+    },
+
+    /// A hook that can veto an operation failed.
+    #[error("hook {hook} rejected the operation: {reason}")]
+    HookRejected {
+        /// The event and command of the hook
+        hook: String,
+        /// Why it failed
+        reason: String,
+    },
+
+    /// Another process is writing the index.
+    #[error("index is being written by another process (PID: {pid})")]
+    IndexLocked {
+        /// Process ID of the writer, or 0 if unknown
+        pid: u32,
+    },
+
+    /// The operation was cancelled through its [`crate::CancellationToken`].
+    #[error("operation cancelled")]
+    Cancelled {
+        /// Whether the operation had already changed the repository when
+        /// it stopped. Those changes are kept.
+        changed: bool,
+    },
+
+    /// A request to the remote object store failed.
+    #[error("remote object store error: {0}")]
+    RemoteStore(String),
+
+    /// A [restricted](crate::CtxRepo::restricted) handle lacks the
+    /// capability an operation needs.
+    #[error("permission denied: this handle can't {capability} ({target})")]
+    PermissionDenied {
+        /// The missing capability
+        capability: Capability,
+        /// The path or operation that was refused
+        target: String,
+    },
+}
+
+/// The lock a [`CtxError::LockError`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// The repository `LOCK` held during a session.
+    Repository,
+    /// The index writer and commit locks.
+    Index,
+}
+
+impl fmt::Display for LockKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Repository => "repository",
+            Self::Index => "index",
+        })
+    }
+}
+
+/// What a [`CtxError::PermissionDenied`] refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Creating commits or moving refs.
+    Commit,
+    /// Recording file writes.
+    WriteFiles,
+    /// Recording reads of a path outside the readable paths.
+    ReadPath,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Commit => "commit",
+            Self::WriteFiles => "write files",
+            Self::ReadPath => "read",
+        })
+    }
 }
 
 impl CtxError {
+    /// A stable, machine-readable code for the kind of error, such as
+    /// `"index_error"` or `"session_lock_held"`.
+    ///
+    /// Codes keep their meaning across releases, so scripts and agent
+    /// wrappers can branch on them rather than on messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ObjectNotFound(_) => "object_not_found",
+            Self::HashMismatch { .. } => "hash_mismatch",
+            Self::CorruptedObject { .. } => "corrupted_object",
+            Self::ObjectCorrupt { .. } => "object_corrupt",
+            Self::InvalidHex(_) => "invalid_hex",
+            Self::Serialization(_) => "serialization",
+            Self::Deserialization(_) => "deserialization",
+            Self::Compression(_) => "compression",
+            Self::BlobTooLarge { .. } => "blob_too_large",
+            Self::RefNotFound(_) => "ref_not_found",
+            Self::InvalidRef { .. } => "invalid_ref",
+            Self::RefError { .. } => "ref_error",
+            Self::Io(_) => "io",
+            Self::SessionAlreadyActive(_) => "session_already_active",
+            Self::NoActiveSession => "no_active_session",
+            Self::InvalidStateTransition { .. } => "invalid_state_transition",
+            Self::RepositoryLocked => "repository_locked",
+            Self::LockError { .. } => "lock_error",
+            Self::StagingCorrupted { .. } => "staging_corrupted",
+            Self::RustAnalyzerNotFound => "rust_analyzer_not_found",
+            Self::RustAnalyzerStartFailed(_) => "rust_analyzer_start_failed",
+            Self::LspTimeout { .. } => "lsp_timeout",
+            Self::LspProtocolError(_) => "lsp_protocol_error",
+            Self::LspError { .. } => "lsp_error",
+            Self::RustAnalyzerCrashed(_) => "rust_analyzer_crashed",
+            Self::CargoNotFound => "cargo_not_found",
+            Self::CargoMetadataFailed(_) => "cargo_metadata_failed",
+            Self::NoCargoManifest(_) => "no_cargo_manifest",
+            Self::CargoMetadataParseFailed(_) => "cargo_metadata_parse_failed",
+            Self::ConfigError(_) => "config_error",
+            Self::IndexCorrupted { .. } => "index_corrupted",
+            Self::IndexError(_) => "index_error",
+            Self::NarrativeError(_) => "narrative_error",
+            Self::TreeBuildError(_) => "tree_build_error",
+            Self::GcError(_) => "gc_error",
+            Self::SearchError(_) => "search_error",
+            Self::InvalidArgument(_) => "invalid_argument",
+            Self::InvalidCursor(_) => "invalid_cursor",
+            Self::SessionLockHeld { .. } => "session_lock_held",
+            Self::CommandDenied { .. } => "command_denied",
+            Self::PathIgnored { .. } => "path_ignored",
+            Self::HookRejected { .. } => "hook_rejected",
+            Self::IndexLocked { .. } => "index_locked",
+            Self::Cancelled { .. } => "cancelled",
+            Self::RemoteStore(_) => "remote_store",
+            Self::PermissionDenied { .. } => "permission_denied",
+        }
+    }
+
     /// Returns a user-friendly recovery suggestion for the error, if available.
     pub fn recovery_suggestion(&self) -> Option<&'static str> {
         match self {
             Self::CorruptedObject { .. } => {
                 Some("Run 'ctx verify' to identify all corrupted objects, then 'ctx gc' to clean them up.")
             }
+            Self::ObjectCorrupt { .. } => {
+                Some("Run 'ctx verify' to identify all corrupted objects, then 'ctx gc' to clean them up.")
+            }
             Self::ObjectNotFound(_) => {
                 Some("Repository might be corrupted. Run 'ctx verify' to check.")
             }
-            Self::IndexCorrupted { .. } => Some("Run 'ctx rebuild' to regenerate the index."),
+            Self::IndexCorrupted { .. } | Self::IndexError(_) => {
+                Some("Run 'ctx rebuild' to regenerate the index.")
+            }
             Self::SessionLockHeld { .. } => {
-                Some("Another process might be using this repo. Check for stale locks with 'ctx stage recover'.")
+                Some("Another process might be using this repo. If it has exited, remove the stale lock with 'ctx unlock'.")
             }
             Self::StagingCorrupted { .. } => {
                 Some("Try 'ctx stage recover' to recover from a crashed session, or 'ctx stage abort' to discard.")
             }
             Self::RepositoryLocked => {
-                Some("Wait for the other process to finish, or manually remove .ctx/LOCK if the process is dead.")
+                Some("Wait for the other process to finish, or run 'ctx unlock' if the process is dead.")
+            }
+            Self::LockError { .. } => {
+                Some("Check that the .ctx directory is writable and on a filesystem that supports file locking.")
             }
             Self::NoActiveSession => Some("Start a new session with 'ctx stage start <task>'."),
             Self::SessionAlreadyActive(_) => {
                 Some("Complete the current session with 'ctx stage compact' or abort it with 'ctx stage abort'.")
             }
+            Self::InvalidCursor(_) => Some("Restart paging by querying again without a cursor."),
+            Self::CommandDenied { .. } => {
+                Some("Add the command to 'allow' under [exec] in .ctx/config.toml, or set mode = \"allow\".")
+            }
+            Self::PathIgnored { .. } => {
+                Some("Add a '!' rule for the path to .ctxignore, or remove its pattern from storage.ignore in .ctx/config.toml.")
+            }
+            Self::HookRejected { .. } => {
+                Some("Fix what the hook reported, or remove it from .ctx/hooks/ or [hooks] in .ctx/config.toml.")
+            }
+            Self::IndexLocked { .. } => {
+                Some("Wait for the other ctx process (such as 'ctx analyze' or 'ctx serve') to finish. Queries still work from a read-only snapshot meanwhile.")
+            }
+            Self::RemoteStore(_) => {
+                Some("Check storage.remote in .ctx/config.toml and the AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables.")
+            }
             Self::RefNotFound(_) => {
                 Some("This might indicate a corrupted repository. Try 'ctx verify --full'.")
             }
+            Self::PermissionDenied { .. } => {
+                Some("The host restricted what this handle may do; ask it to perform the operation.")
+            }
             _ => None,
         }
     }
@@ -217,3 +437,37 @@ impl CtxError {
 
 /// Convenience Result type for ctx_core operations.
 pub type Result<T> = std::result::Result<T, CtxError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_distinct() {
+        let errors = [
+            CtxError::IndexError("boom".to_string()),
+            CtxError::IndexCorrupted {
+                message: "boom".to_string(),
+            },
+            CtxError::RefError {
+                path: PathBuf::from("HEAD"),
+                reason: "boom".to_string(),
+            },
+            CtxError::LockError {
+                kind: LockKind::Index,
+                reason: "boom".to_string(),
+            },
+            CtxError::ObjectCorrupt {
+                id: "ab".repeat(32),
+                reason: "boom".to_string(),
+            },
+            CtxError::CorruptedObject {
+                path: PathBuf::from("objects/ab"),
+                reason: "boom".to_string(),
+            },
+        ];
+        let codes: std::collections::BTreeSet<_> = errors.iter().map(CtxError::code).collect();
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(errors[3].to_string(), "index lock error: boom");
+    }
+}
@@ -0,0 +1,238 @@
+//! Repository event journal.
+//!
+//! Repository operations append one JSON line per event to
+//! `.ctx/events.jsonl`: sessions starting, flushing and compacting, commits,
+//! analysis runs and garbage collection. Consumers such as dashboards and
+//! daemons read it incrementally with [`crate::CtxRepo::events_since`]
+//! instead of polling refs.
+//!
+//! A cursor is a byte offset into the journal. Reading from a cursor returns
+//! every complete line at or after it and the cursor to resume from, so a
+//! line still being appended is picked up by the next read.
+
+use crate::error::{CtxError, Result};
+use crate::types::CommitType;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the journal inside `.ctx`.
+pub(crate) const EVENTS_FILE: &str = "events.jsonl";
+
+/// A recorded repository event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// Byte offset of this event in the journal.
+    #[serde(skip)]
+    pub cursor: u64,
+    /// When the event was recorded (Unix seconds).
+    pub timestamp_unix: u64,
+    /// What happened.
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// What happened in a repository event. Object IDs are hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A session started.
+    SessionStarted {
+        /// Session ID.
+        session_id: String,
+        /// Task description.
+        task: String,
+    },
+    /// A session step was flushed to staging.
+    SessionFlushed {
+        /// Session ID.
+        session_id: String,
+        /// Steps flushed so far.
+        steps: u32,
+        /// The new STAGE commit.
+        staging_head: String,
+    },
+    /// A session was compacted into a canonical commit.
+    SessionCompacted {
+        /// Session ID.
+        session_id: String,
+        /// The canonical commit.
+        commit_id: String,
+        /// How the session ended.
+        commit_type: CommitType,
+    },
+    /// A canonical commit was made outside a session.
+    Committed {
+        /// The new commit.
+        commit_id: String,
+        /// Commit message.
+        message: String,
+    },
+    /// An analysis run committed its results.
+    Analyzed {
+        /// Which analysis ran (`rust`, `rust_file`, or `cargo`).
+        analysis: String,
+        /// The analysis commit.
+        commit_id: String,
+    },
+    /// A prompt pack was recorded.
+    PackBuilt {
+        /// The stored [`PackRecord`](crate::PackRecord).
+        pack_id: String,
+        /// The query.
+        query: String,
+        /// Number of retrieved chunks.
+        chunks: usize,
+        /// Tokens the pack used.
+        tokens: u32,
+    },
+    /// Garbage collection ran.
+    GcCompleted {
+        /// Objects deleted.
+        objects_deleted: usize,
+        /// Bytes freed.
+        bytes_freed: u64,
+        /// Whether nothing was actually deleted.
+        dry_run: bool,
+    },
+}
+
+/// Events read from the journal by [`crate::CtxRepo::events_since`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventPage {
+    /// Events at or after the requested cursor, oldest first.
+    pub events: Vec<Event>,
+    /// Cursor to pass to the next read.
+    pub next_cursor: u64,
+}
+
+/// Append-only journal at `.ctx/events.jsonl`.
+#[derive(Debug, Clone)]
+pub(crate) struct EventLog {
+    path: PathBuf,
+}
+
+impl EventLog {
+    /// Opens the journal at `path`. The file is created on first append.
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Appends an event stamped with the current time.
+    pub(crate) fn append(&self, kind: EventKind) -> Result<()> {
+        let event = Event {
+            cursor: 0,
+            timestamp_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            kind,
+        };
+        let mut line =
+            serde_json::to_vec(&event).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        line.push(b'\n');
+
+        // A single append-mode write keeps concurrent writers from
+        // interleaving within a line
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Reads every complete event at or after `cursor`.
+    ///
+    /// A cursor past the end of the journal (for example after it was
+    /// deleted) restarts from the beginning.
+    pub(crate) fn read_since(&self, cursor: u64) -> Result<EventPage> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(EventPage::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let len = fs::metadata(&self.path)?.len();
+        let mut offset = if cursor > len { 0 } else { cursor };
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut reader = BufReader::new(file);
+        let mut events = Vec::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            // Stop at EOF or at a line still being written
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            let mut event: Event = serde_json::from_str(line.trim_end()).map_err(|e| {
+                CtxError::Serialization(format!("event at offset {}: {}", offset, e))
+            })?;
+            event.cursor = offset;
+            events.push(event);
+            offset += read as u64;
+        }
+
+        Ok(EventPage {
+            events,
+            next_cursor: offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_since_resumes_and_skips_partial_lines() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join(EVENTS_FILE);
+        let log = EventLog::new(&path);
+        assert!(log.read_since(0).unwrap().events.is_empty());
+
+        log.append(EventKind::Committed {
+            commit_id: "aa".to_string(),
+            message: "First".to_string(),
+        })
+        .unwrap();
+        let page = log.read_since(0).unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].cursor, 0);
+
+        log.append(EventKind::GcCompleted {
+            objects_deleted: 2,
+            bytes_freed: 10,
+            dry_run: false,
+        })
+        .unwrap();
+        // A writer that hasn't finished its line yet
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"timestamp_unix\":1,").unwrap();
+
+        let next = log.read_since(page.next_cursor).unwrap();
+        assert_eq!(next.events.len(), 1);
+        assert_eq!(next.events[0].cursor, page.next_cursor);
+        assert!(matches!(
+            next.events[0].kind,
+            EventKind::GcCompleted {
+                objects_deleted: 2,
+                ..
+            }
+        ));
+        assert!(log.read_since(next.next_cursor).unwrap().events.is_empty());
+
+        // A stale cursor from a truncated journal starts over
+        fs::write(&path, "").unwrap();
+        log.append(EventKind::Committed {
+            commit_id: "bb".to_string(),
+            message: "Again".to_string(),
+        })
+        .unwrap();
+        assert_eq!(log.read_since(next.next_cursor).unwrap().events.len(), 1);
+    }
+}
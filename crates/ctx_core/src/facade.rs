@@ -0,0 +1,220 @@
+//! High-level facade for agent integrations.
+//!
+//! [`Ctx`] bundles a [`CtxRepo`] with retrieval settings and exposes the
+//! handful of operations an agent loop needs: start a task, record what the
+//! agent does, ask for context, and finish. Every step returns `&mut Ctx`,
+//! so a whole interaction can be written as one chain:
+//!
+//! ```no_run
+//! use ctx_core::prelude::*;
+//!
+//! # fn main() -> ctx_core::Result<()> {
+//! let pack = Ctx::open(".")?
+//!     .with_budget(8000)
+//!     .task("fix token refresh bug")?
+//!     .observe_write("src/auth.rs", b"// fixed")?
+//!     .query("token refresh")?;
+//! println!("{}", pack.to_text());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The underlying repository stays reachable through [`Ctx::repo`] and
+//! [`Ctx::repo_mut`] for anything the facade doesn't cover.
+
+use crate::error::Result;
+use crate::pack::{PromptPack, RetrievalConfig};
+use crate::types::AgentIdentity;
+use crate::{CtxRepo, ObjectId};
+use std::path::Path;
+
+/// A CTX repository with retrieval settings, for agent integrations.
+pub struct Ctx {
+    repo: CtxRepo,
+    retrieval: RetrievalConfig,
+}
+
+impl Ctx {
+    /// Opens an existing repository at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` has no `.ctx` directory.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_repo(CtxRepo::open(path)?))
+    }
+
+    /// Initializes a new repository at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a repository already exists there.
+    pub fn init(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_repo(CtxRepo::init(path)?))
+    }
+
+    /// Opens the repository at `path`, initializing one if none exists.
+    pub fn open_or_init(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.join(".ctx").exists() {
+            Self::open(path)
+        } else {
+            Self::init(path)
+        }
+    }
+
+    /// Wraps an already opened repository.
+    pub fn from_repo(repo: CtxRepo) -> Self {
+        Self {
+            repo,
+            retrieval: RetrievalConfig::default(),
+        }
+    }
+
+    /// Records `identity` as the author of sessions and commits.
+    pub fn with_identity(mut self, identity: AgentIdentity) -> Self {
+        self.repo.set_identity(Some(identity));
+        self
+    }
+
+    /// Sets the total token budget for queries.
+    pub fn with_budget(mut self, tokens: u32) -> Self {
+        self.retrieval.token_budget = tokens;
+        self
+    }
+
+    /// Sets how many hops queries expand from the matched files.
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.retrieval.expansion_depth = depth;
+        self
+    }
+
+    /// Leaves task and log narrative out of query results.
+    pub fn without_narrative(mut self) -> Self {
+        self.retrieval.include_active_task = false;
+        self.retrieval.include_log = false;
+        self
+    }
+
+    /// Replaces the retrieval settings wholesale.
+    pub fn with_retrieval(mut self, config: RetrievalConfig) -> Self {
+        self.retrieval = config;
+        self
+    }
+
+    /// Returns the retrieval settings used by [`Ctx::query`].
+    pub fn retrieval(&self) -> &RetrievalConfig {
+        &self.retrieval
+    }
+
+    /// Returns the underlying repository.
+    pub fn repo(&self) -> &CtxRepo {
+        &self.repo
+    }
+
+    /// Returns the underlying repository mutably.
+    pub fn repo_mut(&mut self) -> &mut CtxRepo {
+        &mut self.repo
+    }
+
+    /// Consumes the facade, returning the repository.
+    pub fn into_repo(self) -> CtxRepo {
+        self.repo
+    }
+
+    /// Starts a session for `task`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionAlreadyActive` if a task is already in progress.
+    pub fn task(&mut self, task: &str) -> Result<&mut Self> {
+        self.repo.start_session(task)?;
+        Ok(self)
+    }
+
+    /// Records that the agent wrote `content` to `path`.
+    pub fn observe_write(&mut self, path: &str, content: &[u8]) -> Result<&mut Self> {
+        self.repo.observe_file_write(path, content)?;
+        Ok(self)
+    }
+
+    /// Records that the agent read `content` from `path`.
+    pub fn observe_read(&mut self, path: &str, content: &[u8]) -> Result<&mut Self> {
+        self.repo.observe_file_read_with_content(path, content)?;
+        Ok(self)
+    }
+
+    /// Records that the agent ran `command`.
+    pub fn observe_command(
+        &mut self,
+        command: &str,
+        exit_code: Option<i32>,
+        output: Option<&[u8]>,
+    ) -> Result<&mut Self> {
+        self.repo.observe_command(command, exit_code, output)?;
+        Ok(self)
+    }
+
+    /// Records a free-form note.
+    pub fn note(&mut self, note: &str) -> Result<&mut Self> {
+        self.repo.observe_note(note)?;
+        Ok(self)
+    }
+
+    /// Builds a prompt pack for `query` from the last commit.
+    ///
+    /// Work recorded in the current task only becomes retrievable once the
+    /// task is finished.
+    pub fn query(&mut self, query: &str) -> Result<PromptPack> {
+        self.repo.build_pack(query, &self.retrieval)
+    }
+
+    /// Finishes the current task, committing its work with `message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` if no task was started.
+    pub fn finish(&mut self, message: &str) -> Result<ObjectId> {
+        self.repo.flush_active_session()?;
+        self.repo.compact_session(message)
+    }
+
+    /// Abandons the current task, recording `reason`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` if no task was started.
+    pub fn abort(&mut self, reason: &str) -> Result<ObjectId> {
+        self.repo.flush_active_session()?;
+        self.repo.abort_session(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_task_chain_and_query() {
+        let tmp = TempDir::new().unwrap();
+        let mut ctx = Ctx::init(tmp.path()).unwrap().without_narrative();
+
+        ctx.task("Add parser")
+            .unwrap()
+            .observe_write("src/parser.rs", b"pub fn parse() {}")
+            .unwrap()
+            .note("parser is a stub")
+            .unwrap();
+        ctx.finish("Added parser").unwrap();
+        drop(ctx);
+
+        let pack = Ctx::open(tmp.path())
+            .unwrap()
+            .without_narrative()
+            .query("src/parser.rs")
+            .unwrap();
+        assert_eq!(pack.retrieved.len(), 1);
+        assert_eq!(pack.retrieved[0].title, "src/parser.rs");
+    }
+}
@@ -0,0 +1,253 @@
+//! Facts: durable knowledge agents carry across sessions.
+//!
+//! A [`Fact`] states something about a graph node ("`src/db.rs` uses: redb
+//! 2.x"), optionally backed by the file content it was learned from and
+//! limited by a time to live. Agents write facts with
+//! [`CtxRepo::observe_fact`](crate::CtxRepo::observe_fact); they join the
+//! [`FactSet`] referenced from each commit when the session is compacted.
+//!
+//! Each fact gets a `Fact` node with a `Mentions` edge to its subject, so
+//! [`CtxRepo::facts_about`](crate::CtxRepo::facts_about) looks facts up
+//! through the index and packs list those about the files they include
+//! under "Known facts". A fact is invalid once its TTL has passed or its
+//! evidence file's content at HEAD differs from the blob it was learned
+//! from. Invalid facts are never returned; those with changed evidence are
+//! also dropped from the set at the next compaction that adds facts.
+
+use crate::error::{CtxError, Result};
+use crate::staging::lookup_tree_path;
+use crate::types::{
+    Confidence, Edge, EdgeBatch, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind,
+};
+use crate::{ObjectId, ObjectStore};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A statement about a graph node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Fact {
+    /// The node the fact is about.
+    pub subject: NodeId,
+    /// What is stated, such as "uses" or "owner". A newer fact with the same
+    /// subject and predicate replaces an older one.
+    pub predicate: String,
+    /// The stated value.
+    pub value: String,
+    /// File content the fact was learned from, if any.
+    pub evidence: Option<FactEvidence>,
+    /// Seconds after `created_at` the fact stays valid (None for no limit).
+    pub ttl_secs: Option<u64>,
+    /// Creation time (Unix seconds). Zero when observed means now.
+    pub created_at: u64,
+}
+
+/// The file content a fact was learned from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FactEvidence {
+    /// Path of the file.
+    pub path: String,
+    /// Content the fact holds for. None when observed means the path's
+    /// latest content in the session, or else at HEAD.
+    pub blob_id: Option<ObjectId>,
+}
+
+impl Fact {
+    /// Creates a fact with no evidence or TTL.
+    pub fn new(subject: NodeId, predicate: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            subject,
+            predicate: predicate.into(),
+            value: value.into(),
+            evidence: None,
+            ttl_secs: None,
+            created_at: 0,
+        }
+    }
+
+    /// Backs the fact with the current content of `path`.
+    pub fn with_evidence(mut self, path: impl Into<String>) -> Self {
+        self.evidence = Some(FactEvidence {
+            path: path.into(),
+            blob_id: None,
+        });
+        self
+    }
+
+    /// Limits how long the fact stays valid.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl_secs = Some(ttl.as_secs());
+        self
+    }
+
+    /// Returns true if the fact's TTL has passed at `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.ttl_secs
+            .is_some_and(|ttl| self.created_at.saturating_add(ttl) <= now)
+    }
+
+    /// Renders the fact as a markdown list item.
+    pub fn render(&self) -> String {
+        let subject = match self.subject.kind {
+            NodeKind::File => self.subject.id.clone(),
+            kind => format!("{:?}::{}", kind, self.subject.id),
+        };
+        format!("- `{}` {}: {}", subject, self.predicate, self.value)
+    }
+}
+
+/// Facts in effect, oldest first. Some may have expired since.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FactSet {
+    /// IDs of the stored [`Fact`] objects.
+    pub facts: Vec<ObjectId>,
+}
+
+/// Returns true if `fact` is unexpired at `now` and its evidence still
+/// matches the content under `root_tree`.
+pub(crate) fn is_valid(
+    fact: &Fact,
+    now: u64,
+    root_tree: ObjectId,
+    object_store: &ObjectStore,
+) -> Result<bool> {
+    if fact.is_expired(now) {
+        return Ok(false);
+    }
+    match &fact.evidence {
+        Some(evidence) => Ok(evidence.blob_id.is_some()
+            && lookup_tree_path(root_tree, &evidence.path, object_store)? == evidence.blob_id),
+        None => Ok(true),
+    }
+}
+
+/// Adds stored facts to the set at `set_id`.
+///
+/// Facts the new ones supersede, and facts whose evidence no longer matches
+/// `root_tree`, are dropped. Expiry is left to readers, whose clock may
+/// differ. Returns the new set's ID (`set_id` itself if `fact_ids` is
+/// empty) and an edge batch linking the new facts to their subjects, stamped
+/// `now`. `commit_id` is recorded as the edges' evidence.
+pub(crate) fn append_facts(
+    fact_ids: &[ObjectId],
+    set_id: Option<ObjectId>,
+    root_tree: ObjectId,
+    commit_id: ObjectId,
+    now: u64,
+    object_store: &ObjectStore,
+) -> Result<(Option<ObjectId>, Option<ObjectId>)> {
+    if fact_ids.is_empty() {
+        return Ok((set_id, None));
+    }
+    let set: FactSet = match set_id {
+        Some(id) => object_store.get_typed(id)?,
+        None => FactSet::default(),
+    };
+
+    let mut facts = Vec::new();
+    for id in set.facts.into_iter().chain(fact_ids.iter().copied()) {
+        let fact: Fact = object_store.get_typed(id)?;
+        if is_valid(&fact, 0, root_tree, object_store)? {
+            facts.retain(|(_, old): &(ObjectId, Fact)| {
+                old.subject != fact.subject || old.predicate != fact.predicate
+            });
+            facts.push((id, fact));
+        }
+    }
+
+    let edges: Vec<Edge> = facts
+        .iter()
+        .filter(|(id, _)| fact_ids.contains(id))
+        .map(|(id, fact)| subject_edge(fact, *id, commit_id))
+        .collect();
+    let batch_id = if edges.is_empty() {
+        None
+    } else {
+        Some(object_store.put_typed(&EdgeBatch {
+            edges,
+            created_at: now,
+        })?)
+    };
+
+    let set = FactSet {
+        facts: facts.into_iter().map(|(id, _)| id).collect(),
+    };
+    Ok((Some(object_store.put_typed(&set)?), batch_id))
+}
+
+/// Builds the `Mentions` edge from a fact's node to its subject.
+fn subject_edge(fact: &Fact, fact_id: ObjectId, commit_id: ObjectId) -> Edge {
+    Edge {
+        from: fact_node(fact_id),
+        to: fact.subject.clone(),
+        label: EdgeLabel::Mentions,
+        weight: None,
+        evidence: Evidence {
+            commit_id,
+            tool: EvidenceTool::Llm,
+            confidence: match fact.evidence {
+                Some(_) => Confidence::High,
+                None => Confidence::Medium,
+            },
+            span: None,
+            blob_id: Some(fact_id),
+            condition: None,
+        },
+    }
+}
+
+/// The graph node for a stored fact.
+fn fact_node(fact_id: ObjectId) -> NodeId {
+    NodeId {
+        kind: NodeKind::Fact,
+        id: fact_id.as_hex(),
+    }
+}
+
+/// Validates a fact before it is observed.
+pub(crate) fn validate(fact: &Fact) -> Result<()> {
+    if fact.subject.id.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "fact subject must not be empty".to_string(),
+        ));
+    }
+    if fact.predicate.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "fact predicate must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> NodeId {
+        NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fact_expiry_and_rendering() {
+        let mut fact =
+            Fact::new(file("src/db.rs"), "uses", "redb 2.x").with_ttl(Duration::from_secs(60));
+        fact.created_at = 1_000;
+        assert!(!fact.is_expired(1_059));
+        assert!(fact.is_expired(1_060));
+        assert_eq!(fact.render(), "- `src/db.rs` uses: redb 2.x");
+
+        let crate_fact = Fact::new(
+            NodeId {
+                kind: NodeKind::Crate,
+                id: "ctx_core".into(),
+            },
+            "msrv",
+            "1.75",
+        );
+        assert!(!crate_fact.is_expired(u64::MAX));
+        assert_eq!(crate_fact.render(), "- `Crate::ctx_core` msrv: 1.75");
+        assert!(validate(&Fact::new(file("a.rs"), " ", "x")).is_err());
+    }
+}
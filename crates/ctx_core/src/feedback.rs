@@ -0,0 +1,197 @@
+//! Feedback on the chunks of prompt packs.
+//!
+//! After using a pack, an agent or user can say which of its numbered
+//! chunks helped and which were noise with
+//! [`CtxRepo::record_feedback`](crate::CtxRepo::record_feedback). Each call
+//! is stored as a [`Feedback`] object holding the rated chunks, resolved
+//! from the pack's [`PackRecord`](crate::PackRecord), and listed in the
+//! [`FeedbackLog`] the `FEEDBACK` ref points to.
+//!
+//! The log keeps a running tally per file, which
+//! [`build_pack`](crate::CtxRepo::build_pack) folds into its ranking: each net
+//! "useful" vote raises a file's relevance by [`FEEDBACK_STEP`] and each
+//! net "useless" vote lowers it, by at most [`MAX_FEEDBACK_ADJUSTMENT`]
+//! either way. Feedback on chunks that aren't files is kept but doesn't
+//! affect ranking.
+
+use crate::error::{CtxError, Result};
+use crate::pack_record::CitedChunk;
+use crate::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Relevance change per net vote (fixed-point, 1000 = 1.0).
+pub const FEEDBACK_STEP: i32 = 100;
+
+/// Largest relevance change feedback can make to one file.
+pub const MAX_FEEDBACK_ADJUSTMENT: i32 = 300;
+
+/// Ratings given to the chunks of one pack.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Feedback {
+    /// ID of the rated [`PackRecord`](crate::PackRecord).
+    pub pack_id: ObjectId,
+    /// The query the pack was built for.
+    pub query: String,
+    /// Chunks that helped.
+    pub useful: Vec<CitedChunk>,
+    /// Chunks that didn't.
+    pub useless: Vec<CitedChunk>,
+    /// Creation time (Unix seconds).
+    pub created_at: u64,
+}
+
+/// Every recorded [`Feedback`], oldest first, with the tally per file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedbackLog {
+    /// IDs of the stored [`Feedback`] objects.
+    pub entries: Vec<ObjectId>,
+    /// Votes per file path.
+    pub scores: BTreeMap<String, FeedbackScore>,
+}
+
+/// Votes a file has received.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeedbackScore {
+    /// Times a chunk of the file was marked useful.
+    pub useful: u32,
+    /// Times a chunk of the file was marked useless.
+    pub useless: u32,
+}
+
+impl FeedbackScore {
+    /// Relevance change these votes make, within
+    /// ±[`MAX_FEEDBACK_ADJUSTMENT`].
+    pub fn adjustment(&self) -> i32 {
+        let net = self.useful as i64 - self.useless as i64;
+        (net * FEEDBACK_STEP as i64).clamp(
+            -MAX_FEEDBACK_ADJUSTMENT as i64,
+            MAX_FEEDBACK_ADJUSTMENT as i64,
+        ) as i32
+    }
+}
+
+impl FeedbackLog {
+    /// Appends the stored feedback `id` and counts its votes.
+    pub(crate) fn add(&mut self, id: ObjectId, feedback: &Feedback) {
+        self.entries.push(id);
+        for (chunks, useful) in [(&feedback.useful, true), (&feedback.useless, false)] {
+            for path in chunks.iter().filter_map(|chunk| chunk.path.as_ref()) {
+                let score = self.scores.entry(path.clone()).or_default();
+                if useful {
+                    score.useful += 1;
+                } else {
+                    score.useless += 1;
+                }
+            }
+        }
+    }
+
+    /// Relevance change per file path, for files whose votes don't cancel
+    /// out.
+    pub fn adjustments(&self) -> HashMap<String, i32> {
+        self.scores
+            .iter()
+            .map(|(path, score)| (path.clone(), score.adjustment()))
+            .filter(|(_, adjustment)| *adjustment != 0)
+            .collect()
+    }
+}
+
+/// Checks the chunk numbers of a feedback call.
+///
+/// # Errors
+///
+/// Returns [`CtxError::InvalidArgument`] if no chunk is rated or a chunk is
+/// marked both useful and useless.
+pub(crate) fn validate(useful: &[usize], useless: &[usize]) -> Result<()> {
+    if useful.is_empty() && useless.is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "feedback needs at least one useful or useless chunk".to_string(),
+        ));
+    }
+    if let Some(number) = useful.iter().find(|n| useless.contains(n)) {
+        return Err(CtxError::InvalidArgument(format!(
+            "chunk [{}] can't be both useful and useless",
+            number
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::citation::Citation;
+    use crate::pack::ChunkKind;
+
+    fn chunk(path: Option<&str>) -> CitedChunk {
+        let object_id = ObjectId::from_bytes([0xab; 32]);
+        CitedChunk {
+            title: path.unwrap_or("Decision: Use redb").to_string(),
+            chunk_kind: if path.is_some() {
+                ChunkKind::FileContent
+            } else {
+                ChunkKind::Decision
+            },
+            path: path.map(str::to_string),
+            citation: Citation::whole(object_id, "one\n"),
+            snippet_id: object_id,
+            relevance_score: 500,
+        }
+    }
+
+    fn feedback(useful: Vec<CitedChunk>, useless: Vec<CitedChunk>) -> Feedback {
+        Feedback {
+            pack_id: ObjectId::from_bytes([0; 32]),
+            query: "where is the index".to_string(),
+            useful,
+            useless,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_log_tallies_files() {
+        let mut log = FeedbackLog::default();
+        log.add(
+            ObjectId::from_bytes([1; 32]),
+            &feedback(
+                vec![chunk(Some("src/index.rs")), chunk(None)],
+                vec![chunk(Some("src/lib.rs"))],
+            ),
+        );
+        log.add(
+            ObjectId::from_bytes([2; 32]),
+            &feedback(vec![chunk(Some("src/lib.rs"))], vec![]),
+        );
+
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(log.scores.len(), 2);
+        let adjustments = log.adjustments();
+        assert_eq!(adjustments.get("src/index.rs"), Some(&FEEDBACK_STEP));
+        // One vote each way cancels out
+        assert_eq!(adjustments.get("src/lib.rs"), None);
+    }
+
+    #[test]
+    fn test_adjustment_is_bounded() {
+        let score = FeedbackScore {
+            useful: 0,
+            useless: 50,
+        };
+        assert_eq!(score.adjustment(), -MAX_FEEDBACK_ADJUSTMENT);
+        let score = FeedbackScore {
+            useful: 2,
+            useless: 0,
+        };
+        assert_eq!(score.adjustment(), 2 * FEEDBACK_STEP);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&[1], &[]).is_ok());
+        assert!(validate(&[], &[]).is_err());
+        assert!(validate(&[1, 2], &[2]).is_err());
+    }
+}
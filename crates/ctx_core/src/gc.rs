@@ -1,19 +1,36 @@
 //! Garbage collection for unreferenced objects.
 //!
 //! Implements mark-and-sweep garbage collection to remove objects that are no longer
-//! reachable from any references (HEAD, STAGE, or refs/*).
-
-use crate::error::Result;
+//! reachable from any references (HEAD, STAGE, refs/*, SUMMARIES or FEEDBACK),
+//! from the reflog entries younger than its expiry, or from the packs
+//! recorded in the event journal.
+//!
+//! With a read-content retention period, GC also rewrites the staging chain
+//! so steps older than the period keep their file reads but no longer
+//! reference the captured content, which the sweep then deletes. A dropped
+//! read looks like one recorded without content. File writes are kept,
+//! since compaction builds the tree from them.
+
+use crate::decision::DecisionLog;
+use crate::error::{CtxError, Result};
+use crate::events::{EventKind, EventLog, EVENTS_FILE};
+use crate::fact::FactSet;
+use crate::feedback::{Feedback, FeedbackLog};
+use crate::lsp::RustSnapshot;
+use crate::notes::NoteTable;
 use crate::object_id::ObjectId;
-use crate::object_store::ObjectStore;
+use crate::object_store::{Grafts, ObjectStore};
+use crate::pack_record::PackRecord;
+use crate::progress::Progress;
+use crate::qa::QaLog;
 use crate::refs::Refs;
-use crate::types::Commit;
+use crate::staging::{decode_observations, walk_staging_chain};
+use crate::summary::SummaryTable;
+use crate::transcript::Transcript;
+use crate::types::{Commit, Observation, WorkCommit};
+use serde::Serialize;
 use std::collections::{HashSet, VecDeque};
-use std::time::{Duration, SystemTime};
-
-/// Progress callback for GC operations.
-/// Called with (current, total, phase) where phase is "scan", "mark", or "sweep".
-pub type GcProgressCallback = dyn Fn(usize, usize, &str);
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Configuration for garbage collection.
 #[derive(Debug, Clone)]
@@ -28,6 +45,14 @@ pub struct GcConfig {
     /// Skip grace period and delete unreachable objects immediately.
     /// Use with caution!
     pub aggressive: bool,
+
+    /// Drop content captured on read by staged steps older than this many
+    /// days (None keeps it).
+    pub read_content_retention_days: Option<u32>,
+
+    /// Keep the commits named by reflog entries younger than this many
+    /// days, so `<ref>@{N}` still resolves.
+    pub reflog_expire_days: u32,
 }
 
 impl Default for GcConfig {
@@ -36,12 +61,27 @@ impl Default for GcConfig {
             dry_run: false,
             grace_period_days: 7,
             aggressive: false,
+            read_content_retention_days: None,
+            reflog_expire_days: 90,
         }
     }
 }
 
+/// What read-content retention dropped, or would drop in a dry run.
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    /// Staged steps whose read content was dropped.
+    pub steps_rewritten: usize,
+
+    /// File reads whose content reference was dropped.
+    pub reads_dropped: usize,
+
+    /// Why retention didn't run, if it was configured but skipped.
+    pub skipped: Option<String>,
+}
+
 /// Report from garbage collection operation.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct GcReport {
     /// Total number of objects scanned.
     pub objects_scanned: usize,
@@ -57,6 +97,59 @@ pub struct GcReport {
 
     /// Errors encountered during GC (non-fatal).
     pub errors: Vec<String>,
+
+    /// Read-content retention results.
+    pub retention: RetentionReport,
+}
+
+/// An automatic GC run after a compaction crossed one of the `gc.auto_*`
+/// thresholds (see [`CtxRepo::auto_gc`](crate::CtxRepo::auto_gc)).
+#[derive(Debug, Serialize)]
+pub struct AutoGcReport {
+    /// The threshold that was crossed, e.g. `52 commits since the last GC`.
+    pub reason: String,
+
+    /// Why GC didn't run after all, such as another process holding the
+    /// repository.
+    pub skipped: Option<String>,
+
+    /// What GC did, if it ran.
+    pub gc: Option<GcReport>,
+}
+
+/// File in the .ctx directory holding the HEAD of the last GC, from which
+/// [`CtxRepo::auto_gc`](crate::CtxRepo::auto_gc) counts commits.
+pub(crate) const LAST_GC_FILE: &str = "gc.last";
+
+/// Shard sampled by [`estimate_store`]; any one will do, since object IDs
+/// are spread evenly over the 256 shards.
+const SAMPLE_SHARD: &str = "17";
+
+/// Estimates the number and total size of objects on local disk from one
+/// shard directory, as `git gc --auto` does, so the check stays cheap on a
+/// large store.
+pub(crate) fn estimate_store(store: &ObjectStore) -> Result<(u64, u64)> {
+    let shard = store.root().join(SAMPLE_SHARD);
+    let entries = match std::fs::read_dir(&shard) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let (mut count, mut bytes) = (0u64, 0u64);
+    for entry in entries {
+        let entry = entry?;
+        // Temp files have an extension; objects don't
+        if entry.path().extension().is_some() {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            count += 1;
+            bytes += metadata.len();
+        }
+    }
+    Ok((count * 256, bytes * 256))
 }
 
 /// Run garbage collection on the repository.
@@ -90,45 +183,90 @@ pub struct GcReport {
 ///
 /// Note: This is a low-level function. Most users should call `CtxRepo::gc()` instead,
 /// which handles the borrowing internally.
+///
+/// `progress` hears the phases `roots`, `mark` (objects marked so far) and
+/// `sweep` (objects checked of all objects). Cancelling stops before the
+/// sweep or partway through it; everything deleted by then was unreachable.
+/// The [`CtxError::Cancelled`] error says whether anything was deleted or
+/// read content retention had already rewritten the staging chain.
 pub fn gc(
     refs: &Refs,
     object_store: &mut ObjectStore,
     config: GcConfig,
-    progress: Option<&GcProgressCallback>,
+    progress: &Progress,
 ) -> Result<GcReport> {
     let mut report = GcReport::default();
-
-    // Phase 1: Collect roots
-    if let Some(cb) = progress {
-        cb(0, 3, "roots");
+    let dry_run = config.dry_run;
+    match collect_garbage(refs, object_store, config, progress, &mut report) {
+        Ok(()) => Ok(report),
+        Err(CtxError::Cancelled { .. }) => Err(CtxError::Cancelled {
+            changed: !dry_run
+                && (report.objects_deleted > 0 || report.retention.steps_rewritten > 0),
+        }),
+        Err(e) => Err(e),
     }
-    let roots = collect_roots(refs)?;
+}
 
-    // Phase 2: Mark reachable objects
-    if let Some(cb) = progress {
-        cb(1, 3, "mark");
+/// The phases of [`gc`], filling in `report` as they go.
+fn collect_garbage(
+    refs: &Refs,
+    object_store: &mut ObjectStore,
+    config: GcConfig,
+    progress: &Progress,
+    report: &mut GcReport,
+) -> Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    // Steps created before this time lose their read content
+    let read_cutoff = config
+        .read_content_retention_days
+        .map(|days| now.saturating_sub(days as u64 * 24 * 60 * 60));
+    // Reflog entries made after this time keep their commits
+    let reflog_cutoff = now.saturating_sub(config.reflog_expire_days as u64 * 24 * 60 * 60);
+    progress.check()?;
+    if let Some(cutoff) = read_cutoff {
+        report.retention = apply_retention(refs, object_store, cutoff, config.dry_run)?;
     }
-    let reachable = mark_reachable(object_store, &roots, &mut report)?;
 
-    // Phase 3: Sweep unreachable objects
-    if let Some(cb) = progress {
-        cb(2, 3, "sweep");
-    }
-    let (deleted, bytes_freed) =
-        sweep_unreachable(object_store, &reachable, &config, &mut report, progress)?;
+    // Phase 1: Collect roots
+    progress.tick("roots", 0, 0)?;
+    let roots = collect_roots(refs, object_store, Some(reflog_cutoff))?;
 
-    report.objects_deleted = deleted;
-    report.bytes_freed = bytes_freed;
+    // Phase 2: Mark reachable objects
+    let reachable = mark_reachable(
+        object_store,
+        &roots,
+        &object_store.grafts(),
+        read_cutoff,
+        report,
+        progress,
+    )?;
 
-    if let Some(cb) = progress {
-        cb(3, 3, "done");
+    // Phase 3: Sweep unreachable objects
+    sweep_unreachable(object_store, &reachable, &config, report, progress)?;
+
+    // Deleted objects stay in the existence filter until it is rebuilt
+    if !config.dry_run {
+        if let Err(e) = object_store.rebuild_filter() {
+            report
+                .errors
+                .push(format!("Failed to rebuild existence filter: {}", e));
+        }
     }
 
-    Ok(report)
+    Ok(())
 }
 
-/// Collect all GC roots (HEAD, STAGE, refs/*).
-fn collect_roots(refs: &Refs) -> Result<Vec<ObjectId>> {
+/// Collect all GC roots (HEAD, STAGE, refs/*, cached summaries, recorded
+/// feedback, commit annotations and recorded packs), and with
+/// `reflog_since`, the commits named by reflog entries made after it.
+pub(crate) fn collect_roots(
+    refs: &Refs,
+    store: &ObjectStore,
+    reflog_since: Option<u64>,
+) -> Result<Vec<ObjectId>> {
     let mut roots = Vec::new();
 
     // Add HEAD if it exists
@@ -146,31 +284,154 @@ fn collect_roots(refs: &Refs) -> Result<Vec<ObjectId>> {
         roots.push(id);
     }
 
+    // Add the summary table and every summary it lists
+    if let Some(summaries) = refs.read_summaries()? {
+        roots.push(summaries);
+        let table: SummaryTable = store.get_typed(summaries)?;
+        roots.extend(table.entries.values().copied());
+    }
+
+    // Feedback keeps the chunks it rates
+    if let Some(feedback) = refs.read_feedback()? {
+        roots.push(feedback);
+        let log: FeedbackLog = store.get_typed(feedback)?;
+        for entry_id in log.entries {
+            roots.push(entry_id);
+            let entry: Feedback = store.get_typed(entry_id)?;
+            roots.extend(
+                entry
+                    .useful
+                    .iter()
+                    .chain(&entry.useless)
+                    .map(|chunk| chunk.snippet_id),
+            );
+        }
+    }
+
+    // Annotations, but not the commits they're attached to
+    if let Some(notes) = refs.read_notes()? {
+        roots.push(notes);
+        let table: NoteTable = store.get_typed(notes)?;
+        roots.extend(table.annotation_ids());
+    }
+
+    // Positions refs moved from or to recently, which may exist only in
+    // the reflog; earlier GCs may have removed older ones
+    if let Some(since) = reflog_since {
+        for entry in refs.reflog_entries()? {
+            if entry.timestamp_unix > since {
+                roots.extend(entry.old);
+                roots.push(entry.new);
+            }
+        }
+    }
+
+    // Recorded packs and the snippets they hold
+    let journal = EventLog::new(refs.ctx_dir().join(EVENTS_FILE)).read_since(0)?;
+    for event in journal.events {
+        if let EventKind::PackBuilt { pack_id, .. } = event.kind {
+            let pack_id = ObjectId::from_hex(&pack_id)?;
+            // Removed by a GC that didn't keep packs yet
+            if !store.exists(pack_id) {
+                continue;
+            }
+            roots.push(pack_id);
+            let record: PackRecord = store.get_typed(pack_id)?;
+            roots.extend(record.chunks.iter().map(|chunk| chunk.snippet_id));
+            roots.extend(record.recent_narrative);
+        }
+    }
+
     Ok(roots)
 }
 
+/// Rewrites the staging chain so steps created before `cutoff` drop the
+/// content of their file reads, then moves STAGE to the new chain.
+///
+/// In a dry run nothing is written; the report says what would change.
+fn apply_retention(
+    refs: &Refs,
+    store: &ObjectStore,
+    cutoff: u64,
+    dry_run: bool,
+) -> Result<RetentionReport> {
+    let mut report = RetentionReport::default();
+    let Some(stage) = refs.read_stage()? else {
+        return Ok(report);
+    };
+    let head: WorkCommit = store.get_typed(stage)?;
+    let chain = walk_staging_chain(stage, head.base, store)?;
+
+    // Oldest first, so a rewritten step's children can be re-parented
+    let mut rewritten_parent: Option<ObjectId> = None;
+    for (_id, mut work) in chain {
+        let mut changed = false;
+        if let (Some(parent), Some(first)) = (rewritten_parent, work.parents.first_mut()) {
+            *first = parent;
+            changed = true;
+        }
+        if work.created_at < cutoff {
+            let mut observations = decode_observations(&work.payload)?;
+            let mut dropped = 0;
+            for observation in &mut observations {
+                if let Observation::FileRead { content_id, .. } = observation {
+                    if content_id.take().is_some() {
+                        dropped += 1;
+                    }
+                }
+            }
+            if dropped > 0 {
+                work.payload = postcard::to_allocvec(&observations)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                report.steps_rewritten += 1;
+                report.reads_dropped += dropped;
+                changed = true;
+            }
+        }
+        if changed && !dry_run {
+            rewritten_parent = Some(store.put_typed(&work)?);
+        }
+    }
+
+    if let Some(new_stage) = rewritten_parent {
+        refs.write_stage(new_stage)?;
+    }
+    Ok(report)
+}
+
 /// Mark all reachable objects starting from roots.
 ///
 /// Uses BFS to traverse the object graph and mark all reachable objects.
-fn mark_reachable(
+/// Parent links to commits in `grafts` lead to their stand-ins instead,
+/// or nowhere for commits without one. Staged steps
+/// created before `read_cutoff` don't keep their read content.
+pub(crate) fn mark_reachable(
     store: &ObjectStore,
     roots: &[ObjectId],
+    grafts: &Grafts,
+    read_cutoff: Option<u64>,
     report: &mut GcReport,
+    progress: &Progress,
 ) -> Result<HashSet<ObjectId>> {
     let mut reachable = HashSet::new();
     let mut queue = VecDeque::from_iter(roots.iter().copied());
 
     while let Some(id) = queue.pop_front() {
-        // Skip if already marked
-        if !reachable.insert(id) {
+        if reachable.len() % 1000 == 0 {
+            progress.tick("mark", reachable.len() as u64, 0)?;
+        }
+        // Skip if already marked, or never stored
+        if !reachable.insert(id) || !store.exists(id) {
             continue;
         }
 
         // Try to load as commit and traverse its references
         if let Ok(commit) = store.get_typed::<Commit>(id) {
-            // Add parents
+            // Add parents, or what stands in for those left out
             for parent in &commit.parents {
-                queue.push_back(*parent);
+                if let Some(parent) = grafts.get(parent).copied().unwrap_or(Some(*parent)) {
+                    queue.push_back(parent);
+                }
             }
 
             // Add root tree
@@ -192,10 +453,40 @@ fn mark_reachable(
             }
             if let Some(rust_snapshot) = commit.rust_snapshot {
                 queue.push_back(rust_snapshot);
+                if let Ok(snapshot) = store.get_typed::<RustSnapshot>(rust_snapshot) {
+                    queue.extend(snapshot.blobs());
+                }
             }
             if let Some(diagnostics_snapshot) = commit.diagnostics_snapshot {
                 queue.push_back(diagnostics_snapshot);
             }
+            if let Some(glossary) = commit.glossary {
+                queue.push_back(glossary);
+            }
+            if let Some(decisions) = commit.decisions {
+                queue.push_back(decisions);
+                if let Ok(log) = store.get_typed::<DecisionLog>(decisions) {
+                    queue.extend(log.decisions);
+                }
+            }
+            if let Some(facts) = commit.facts {
+                queue.push_back(facts);
+                if let Ok(set) = store.get_typed::<FactSet>(facts) {
+                    queue.extend(set.facts);
+                }
+            }
+            if let Some(qa) = commit.qa {
+                queue.push_back(qa);
+                if let Ok(log) = store.get_typed::<QaLog>(qa) {
+                    queue.extend(log.pairs);
+                }
+            }
+            if let Some(transcript) = commit.transcript {
+                queue.push_back(transcript);
+                if let Ok(transcript) = store.get_typed::<Transcript>(transcript) {
+                    queue.extend(transcript.messages.iter().map(|m| m.content_id));
+                }
+            }
         }
 
         // Try to load as tree and traverse its entries
@@ -204,23 +495,59 @@ fn mark_reachable(
                 queue.push_back(entry.id);
             }
         }
+
+        // Chunked file content lists the blobs holding it
+        if let Some(large) = crate::large_file::large_file(store, id) {
+            queue.extend(large.chunks);
+        }
+
+        // So does a large blob stored in content-defined chunks
+        if let Ok(Some(chunks)) = store.chunk_ids(id) {
+            queue.extend(chunks);
+        }
+
+        // Try to load as a staged step and traverse its observations
+        if let Ok(work) = store.get_typed::<WorkCommit>(id) {
+            queue.extend(work.parents.iter().copied());
+            queue.push_back(work.base);
+            for narrative_ref in &work.narrative_refs {
+                queue.push_back(narrative_ref.blob_id);
+            }
+            let keep_reads = read_cutoff.map_or(true, |cutoff| work.created_at >= cutoff);
+            for observation in decode_observations(&work.payload).unwrap_or_default() {
+                match observation {
+                    Observation::FileWrite { content_id, .. } => queue.push_back(content_id),
+                    Observation::FileRead {
+                        content_id: Some(content_id),
+                        ..
+                    } if keep_reads => queue.push_back(content_id),
+                    Observation::Command {
+                        output_id: Some(output_id),
+                        ..
+                    } => queue.push_back(output_id),
+                    Observation::Decision { decision_id, .. } => queue.push_back(decision_id),
+                    Observation::Fact { fact_id, .. } => queue.push_back(fact_id),
+                    Observation::Qa { qa_id, .. } => queue.push_back(qa_id),
+                    Observation::Message { content_id, .. } => queue.push_back(content_id),
+                    _ => {}
+                }
+            }
+        }
     }
 
     report.objects_reachable = reachable.len();
     Ok(reachable)
 }
 
-/// Sweep unreachable objects that are older than grace period.
+/// Sweep unreachable objects that are older than grace period, counting
+/// them in `report` as they are deleted.
 fn sweep_unreachable(
     store: &mut ObjectStore,
     reachable: &HashSet<ObjectId>,
     config: &GcConfig,
     report: &mut GcReport,
-    progress: Option<&GcProgressCallback>,
-) -> Result<(usize, u64)> {
-    let mut deleted = 0;
-    let mut bytes_freed = 0u64;
-
+    progress: &Progress,
+) -> Result<()> {
     // Get grace period cutoff time
     let grace_period = if config.aggressive {
         Duration::from_secs(0)
@@ -237,10 +564,8 @@ fn sweep_unreachable(
 
     // Sweep unreachable objects
     for (idx, (id, size, mtime)) in all_objects.into_iter().enumerate() {
-        if let Some(cb) = progress {
-            if idx % 100 == 0 || idx == total - 1 {
-                cb(idx + 1, total, "sweep");
-            }
+        if idx % 100 == 0 || idx == total - 1 {
+            progress.tick("sweep", idx as u64 + 1, total as u64)?;
         }
         // Skip reachable objects
         if reachable.contains(&id) {
@@ -255,14 +580,14 @@ fn sweep_unreachable(
         // Delete object
         if config.dry_run {
             // Dry run: just count what would be deleted
-            deleted += 1;
-            bytes_freed += size;
+            report.objects_deleted += 1;
+            report.bytes_freed += size;
         } else {
             // Actually delete
             match store.delete(id) {
                 Ok(()) => {
-                    deleted += 1;
-                    bytes_freed += size;
+                    report.objects_deleted += 1;
+                    report.bytes_freed += size;
                 }
                 Err(e) => {
                     report
@@ -273,12 +598,13 @@ fn sweep_unreachable(
         }
     }
 
-    Ok((deleted, bytes_freed))
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::progress::CancellationToken;
     use crate::types::{Tree, TreeEntry};
     use tempfile::TempDir;
 
@@ -309,13 +635,29 @@ mod tests {
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
         let commit_id = store.put_typed(&commit).unwrap();
 
         // Mark reachable
         let mut report = GcReport::default();
-        let reachable = mark_reachable(&store, &[commit_id], &mut report).unwrap();
+        let reachable = mark_reachable(
+            &store,
+            &[commit_id],
+            &Grafts::new(),
+            None,
+            &mut report,
+            &Progress::default(),
+        )
+        .unwrap();
 
         // Both commit and tree should be reachable
         assert!(reachable.contains(&commit_id));
@@ -356,7 +698,15 @@ mod tests {
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
         let commit_id = store.put_typed(&commit).unwrap();
         refs.write_head(commit_id).unwrap();
@@ -366,15 +716,64 @@ mod tests {
             dry_run: true,
             grace_period_days: 0,
             aggressive: true,
+            read_content_retention_days: None,
+            reflog_expire_days: 90,
         };
 
-        let report = gc(&refs, &mut store, config, None).unwrap();
+        let report = gc(&refs, &mut store, config, &Progress::default()).unwrap();
 
         // blob2 should be marked for deletion but not actually deleted
         assert_eq!(report.objects_deleted, 1);
         assert!(store.exists(blob2)); // Still exists because dry-run
     }
 
+    #[test]
+    fn test_gc_cancelled_mid_sweep_reports_changes() {
+        let tmp = TempDir::new().unwrap();
+        let ctx_root = tmp.path().join(".ctx");
+        std::fs::create_dir_all(&ctx_root).unwrap();
+
+        let mut store = ObjectStore::new(ctx_root.join("objects"));
+        let refs = Refs::new(&ctx_root);
+
+        // Enough unreachable objects that the sweep checks for
+        // cancellation after deleting some of them
+        for i in 0..150 {
+            store.put_blob(format!("garbage {}", i).as_bytes()).unwrap();
+        }
+
+        let config = |dry_run| GcConfig {
+            dry_run,
+            grace_period_days: 0,
+            aggressive: true,
+            read_content_retention_days: None,
+            reflog_expire_days: 90,
+        };
+        // Cancel as soon as the sweep starts
+        let cancel_on_sweep = || {
+            let token = CancellationToken::new();
+            let cancel = token.clone();
+            Progress::new(move |phase: &str, _, _| {
+                if phase == "sweep" {
+                    cancel.cancel();
+                }
+            })
+            .with_cancellation(token)
+        };
+
+        let result = gc(&refs, &mut store, config(true), &cancel_on_sweep());
+        assert!(matches!(
+            result,
+            Err(CtxError::Cancelled { changed: false })
+        ));
+        assert_eq!(store.list_all_objects().unwrap().len(), 150);
+
+        let result = gc(&refs, &mut store, config(false), &cancel_on_sweep());
+        assert!(matches!(result, Err(CtxError::Cancelled { changed: true })));
+        let left = store.list_all_objects().unwrap().len();
+        assert!(left > 0 && left < 150, "{} objects left", left);
+    }
+
     #[test]
     fn test_gc_with_grace_period() {
         let tmp = TempDir::new().unwrap();
@@ -392,12 +791,232 @@ mod tests {
             dry_run: false,
             grace_period_days: 7,
             aggressive: false,
+            read_content_retention_days: None,
+            reflog_expire_days: 90,
         };
 
-        let report = gc(&refs, &mut store, config, None).unwrap();
+        let report = gc(&refs, &mut store, config, &Progress::default()).unwrap();
 
         // Recent object should not be deleted
         assert_eq!(report.objects_deleted, 0);
         assert!(store.exists(blob));
     }
+
+    #[test]
+    fn test_gc_keeps_chunks_of_reachable_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let ctx_root = tmp.path().join(".ctx");
+        std::fs::create_dir_all(&ctx_root).unwrap();
+
+        let mut store = ObjectStore::new(ctx_root.join("objects"));
+        let refs = Refs::new(&ctx_root);
+
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut shared: Vec<u8> = (0..1_000_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let kept = store.put_blob(&shared).unwrap();
+        shared.truncate(600_000);
+        shared.extend_from_slice(&[7u8; 300_000]);
+        let dropped = store.put_blob(&shared).unwrap();
+        let kept_chunks = store.chunk_ids(kept).unwrap().unwrap();
+        let dropped_chunks = store.chunk_ids(dropped).unwrap().unwrap();
+        assert!(kept_chunks.iter().any(|c| dropped_chunks.contains(c)));
+
+        let tree_id = store
+            .put_typed(&Tree {
+                entries: vec![TreeEntry {
+                    name: "Cargo.lock".into(),
+                    kind: crate::types::TreeEntryKind::Blob,
+                    id: kept,
+                }],
+            })
+            .unwrap();
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 0,
+            message: "Test".into(),
+            root_tree: tree_id,
+            edge_batches: vec![],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        refs.write_head(store.put_typed(&commit).unwrap()).unwrap();
+
+        let config = GcConfig {
+            dry_run: false,
+            grace_period_days: 0,
+            aggressive: true,
+            read_content_retention_days: None,
+            reflog_expire_days: 90,
+        };
+        let report = gc(&refs, &mut store, config, &Progress::default()).unwrap();
+
+        // Only the dropped blob's list and the chunks it alone used go
+        let unique = dropped_chunks
+            .iter()
+            .filter(|c| !kept_chunks.contains(c))
+            .count();
+        assert_eq!(report.objects_deleted, unique + 1);
+        assert!(!store.exists(dropped));
+        assert!(kept_chunks.iter().all(|c| store.exists(*c)));
+        assert_eq!(store.get_blob(kept).unwrap().len(), 1_000_000);
+    }
+
+    fn put_step(
+        store: &ObjectStore,
+        parent: ObjectId,
+        base: ObjectId,
+        created_at: u64,
+        observations: &[Observation],
+    ) -> ObjectId {
+        let work = WorkCommit {
+            parents: vec![parent],
+            base,
+            session_id: "test-session".into(),
+            created_at,
+            step_kind: crate::types::StepKind::Note,
+            payload: postcard::to_allocvec(observations).unwrap(),
+            narrative_refs: vec![],
+            session_state: crate::types::SessionState::Running,
+            task_description: "Test task".into(),
+            author: None,
+        };
+        store.put_typed(&work).unwrap()
+    }
+
+    #[test]
+    fn test_read_content_retention_rewrites_staging_chain() {
+        let tmp = TempDir::new().unwrap();
+        let ctx_root = tmp.path().join(".ctx");
+        std::fs::create_dir_all(&ctx_root).unwrap();
+
+        let mut store = ObjectStore::new(ctx_root.join("objects"));
+        let refs = Refs::new(&ctx_root);
+
+        let tree_id = store.put_typed(&Tree { entries: vec![] }).unwrap();
+        let base = store
+            .put_typed(&Commit {
+                parents: vec![],
+                timestamp_unix: 0,
+                message: "Base".into(),
+                root_tree: tree_id,
+                edge_batches: vec![],
+                narrative_refs: vec![],
+                cargo_snapshot: None,
+                rust_snapshot: None,
+                diagnostics_snapshot: None,
+                glossary: None,
+                decisions: None,
+                facts: None,
+                qa: None,
+                transcript: None,
+                commit_type: None,
+                author: None,
+                task: None,
+                tags: Default::default(),
+            })
+            .unwrap();
+        refs.write_head(base).unwrap();
+
+        let old_read = store.put_blob(b"read long ago").unwrap();
+        let old_write = store.put_blob(b"written long ago").unwrap();
+        let new_read = store.put_blob(b"read just now").unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let old_step = put_step(
+            &store,
+            base,
+            base,
+            0,
+            &[
+                Observation::FileRead {
+                    path: "a.rs".into(),
+                    content_id: Some(old_read),
+                    metadata: Default::default(),
+                },
+                Observation::FileWrite {
+                    path: "b.rs".into(),
+                    content_id: old_write,
+                    metadata: Default::default(),
+                },
+            ],
+        );
+        let new_step = put_step(
+            &store,
+            old_step,
+            base,
+            now,
+            &[Observation::FileRead {
+                path: "c.rs".into(),
+                content_id: Some(new_read),
+                metadata: Default::default(),
+            }],
+        );
+        refs.write_stage(new_step).unwrap();
+
+        let config = |dry_run| GcConfig {
+            dry_run,
+            grace_period_days: 0,
+            aggressive: true,
+            read_content_retention_days: Some(30),
+            reflog_expire_days: 90,
+        };
+
+        // Dry run reports the drop without touching the chain
+        let report = gc(&refs, &mut store, config(true), &Progress::default()).unwrap();
+        assert_eq!(report.retention.steps_rewritten, 1);
+        assert_eq!(report.retention.reads_dropped, 1);
+        assert_eq!(report.objects_deleted, 1);
+        assert_eq!(refs.read_stage().unwrap(), Some(new_step));
+        assert!(store.exists(old_read));
+
+        let report = gc(&refs, &mut store, config(false), &Progress::default()).unwrap();
+        assert_eq!(report.retention.reads_dropped, 1);
+        let stage = refs.read_stage().unwrap().unwrap();
+        assert_ne!(stage, new_step);
+        assert!(!store.exists(old_read));
+        assert!(store.exists(old_write));
+        assert!(store.exists(new_read));
+
+        // The old step keeps its write and a tombstone for the read
+        let chain = walk_staging_chain(stage, base, &store).unwrap();
+        assert_eq!(chain.len(), 2);
+        let observations = decode_observations(&chain[0].1.payload).unwrap();
+        assert!(matches!(
+            &observations[0],
+            Observation::FileRead {
+                content_id: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &observations[1],
+            Observation::FileWrite { content_id, .. } if *content_id == old_write
+        ));
+
+        // Nothing left to drop on a second pass
+        let report = gc(&refs, &mut store, config(false), &Progress::default()).unwrap();
+        assert_eq!(report.retention.steps_rewritten, 0);
+        assert_eq!(refs.read_stage().unwrap(), Some(stage));
+    }
 }
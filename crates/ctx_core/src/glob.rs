@@ -0,0 +1,90 @@
+//! Gitignore-style path patterns.
+//!
+//! Patterns are matched against `/`-separated repository paths:
+//! - `*` matches within one path component, `**` across components, and
+//!   `?` matches a single character.
+//! - A pattern without `/` matches the last component at any depth, so
+//!   `*.lock` matches `Cargo.lock` and `web/yarn.lock`.
+//! - A pattern ending in `/` matches everything under a directory of that
+//!   name at any depth, so `target/` matches `target/debug/ctx`.
+//! - Any other pattern is anchored at the repository root.
+
+/// Returns true if `path` matches `pattern`.
+pub(crate) fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim().trim_start_matches("./");
+    let path = path.trim_start_matches("./");
+    if pattern.is_empty() {
+        return false;
+    }
+
+    if let Some(dir) = pattern.strip_suffix('/') {
+        // Match any ancestor directory of the path
+        let components: Vec<&str> = path.split('/').collect();
+        let anchored = dir.contains('/');
+        return (1..components.len()).any(|end| {
+            let prefix = components[..end].join("/");
+            if anchored {
+                matches(dir.as_bytes(), prefix.as_bytes())
+            } else {
+                matches(dir.as_bytes(), components[end - 1].as_bytes())
+            }
+        });
+    }
+
+    if pattern.contains('/') {
+        matches(pattern.trim_start_matches('/').as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        matches(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// Recursive matcher for `*`, `**` and `?`.
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            // `**/` also matches zero directories
+            let rest = &pattern[2..];
+            if let Some(after_slash) = rest.strip_prefix(b"/") {
+                if matches(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            let limit = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=limit).any(|i| matches(rest, &text[i..]))
+        }
+        Some(b'?') => {
+            text.first().is_some_and(|&c| c != b'/') && matches(&pattern[1..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && matches(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.lock", "Cargo.lock"));
+        assert!(glob_match("*.lock", "web/yarn.lock"));
+        assert!(!glob_match("*.lock", "src/lock.rs"));
+
+        assert!(glob_match("target/", "target/debug/ctx"));
+        assert!(glob_match("target/", "crates/a/target/x.rs"));
+        assert!(!glob_match("target/", "target"));
+        assert!(!glob_match("target/", "src/target.rs"));
+
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(!glob_match("src/*.rs", "src/bin/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/bin/main.rs"));
+        assert!(glob_match("docs/**", "docs/a/b.md"));
+        assert!(glob_match("test?.rs", "test1.rs"));
+    }
+}
@@ -0,0 +1,405 @@
+//! Project glossary of domain terms.
+//!
+//! Terms are defined by hand (`ctx glossary add`) or accepted from
+//! candidates extracted from the narrative space. The glossary is stored as
+//! a [`Glossary`] snapshot referenced from each commit, and every entry gets
+//! a `Term` node with `Mentions` edges to the files its definition names, so
+//! a query mentioning the term can reach those files.
+
+use crate::error::{CtxError, Result};
+use crate::staging::flatten_tree;
+use crate::types::{Commit, Confidence, Edge, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind};
+use crate::{ObjectId, ObjectStore};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::sync::OnceLock;
+
+/// Minimum narrative occurrences before a term is suggested.
+pub const DEFAULT_MIN_OCCURRENCES: usize = 3;
+
+/// Longest context line kept for an extracted candidate.
+const MAX_CONTEXT_CHARS: usize = 160;
+
+/// Capitalized tokens that are common in notes but rarely domain terms.
+const STOP_TERMS: &[&str] = &[
+    "AM", "FIXME", "ID", "II", "NB", "OK", "PM", "TBD", "TODO", "UTC", "WIP", "XXX",
+];
+
+/// How a glossary entry was created.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlossarySource {
+    /// Defined explicitly by a user or agent.
+    Manual = 1,
+    /// Accepted from automatic candidate extraction.
+    Extracted = 2,
+}
+
+/// A defined project term.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GlossaryEntry {
+    /// The term as written (e.g. "SCC").
+    pub term: String,
+    /// What the term means in this project.
+    pub definition: String,
+    /// How the entry was created.
+    pub source: GlossarySource,
+    /// Creation time (Unix seconds).
+    pub created_at: u64,
+}
+
+/// Snapshot of every glossary entry, sorted case-insensitively by term.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Glossary {
+    /// The entries, at most one per term (ignoring case).
+    pub entries: Vec<GlossaryEntry>,
+}
+
+impl Glossary {
+    /// Looks up an entry by term, ignoring case.
+    pub fn get(&self, term: &str) -> Option<&GlossaryEntry> {
+        let key = term.to_lowercase();
+        self.entries.iter().find(|e| e.term.to_lowercase() == key)
+    }
+
+    /// Adds an entry, replacing any existing entry for the same term.
+    pub fn upsert(&mut self, entry: GlossaryEntry) {
+        let key = entry.term.to_lowercase();
+        self.entries.retain(|e| e.term.to_lowercase() != key);
+        self.entries.push(entry);
+        self.entries.sort_by_key(|e| e.term.to_lowercase());
+    }
+
+    /// Removes the entry for `term`, returning it if present.
+    pub fn remove(&mut self, term: &str) -> Option<GlossaryEntry> {
+        let key = term.to_lowercase();
+        let pos = self
+            .entries
+            .iter()
+            .position(|e| e.term.to_lowercase() == key)?;
+        Some(self.entries.remove(pos))
+    }
+
+    /// Entries whose term appears in `text` as a whole word, ignoring case.
+    pub fn matching(&self, text: &str) -> Vec<&GlossaryEntry> {
+        let text = text.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|e| contains_word(&text, &e.term.to_lowercase()))
+            .collect()
+    }
+
+    /// Returns true if no terms are defined.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A term that recurs in the narrative but has no glossary entry yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GlossaryCandidate {
+    /// The term as it appears in the narrative.
+    pub term: String,
+    /// Total occurrences across narrative files.
+    pub occurrences: usize,
+    /// Narrative files mentioning the term, sorted.
+    pub files: Vec<String>,
+    /// First line mentioning the term, used as a draft definition.
+    pub context: String,
+}
+
+impl GlossaryCandidate {
+    /// Converts the candidate into an extracted entry.
+    pub fn into_entry(self, created_at: u64) -> GlossaryEntry {
+        GlossaryEntry {
+            term: self.term,
+            definition: self.context,
+            source: GlossarySource::Extracted,
+            created_at,
+        }
+    }
+}
+
+fn term_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        // Acronyms (SCC, LSP2) and CamelCase identifiers (EdgeBatch)
+        Regex::new(r"\b(?:[A-Z][A-Z0-9]+|[A-Z][a-z0-9]+(?:[A-Z][a-z0-9]*)+)\b")
+            .expect("term regex is valid")
+    })
+}
+
+/// Finds repeated domain terms in narrative documents.
+///
+/// `documents` are `(path, content)` pairs. Terms already in `glossary` and
+/// those seen fewer than `min_occurrences` times are skipped. Candidates are
+/// sorted by occurrence count, most frequent first.
+pub fn extract_candidates(
+    documents: &[(String, String)],
+    glossary: &Glossary,
+    min_occurrences: usize,
+) -> Vec<GlossaryCandidate> {
+    let mut found: BTreeMap<String, GlossaryCandidate> = BTreeMap::new();
+
+    for (path, content) in documents {
+        for line in content.lines() {
+            for m in term_regex().find_iter(line) {
+                let term = m.as_str();
+                if STOP_TERMS.contains(&term) || glossary.get(term).is_some() {
+                    continue;
+                }
+                let candidate =
+                    found
+                        .entry(term.to_string())
+                        .or_insert_with(|| GlossaryCandidate {
+                            term: term.to_string(),
+                            occurrences: 0,
+                            files: Vec::new(),
+                            context: context_line(line),
+                        });
+                candidate.occurrences += 1;
+                if !candidate.files.contains(path) {
+                    candidate.files.push(path.clone());
+                }
+            }
+        }
+    }
+
+    let mut candidates: Vec<GlossaryCandidate> = found
+        .into_values()
+        .filter(|c| c.occurrences >= min_occurrences.max(1))
+        .map(|mut c| {
+            c.files.sort();
+            c
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.occurrences
+            .cmp(&a.occurrences)
+            .then_with(|| a.term.cmp(&b.term))
+    });
+    candidates
+}
+
+/// Builds `Mentions` edges from an entry's `Term` node to the files named in
+/// its definition (see [`mentioned_files`]).
+pub(crate) fn mention_edges(
+    entry: &GlossaryEntry,
+    glossary_id: ObjectId,
+    commit_id: ObjectId,
+    known_files: &BTreeSet<String>,
+) -> Vec<Edge> {
+    let targets = mentioned_files(&entry.definition, known_files);
+    let (tool, confidence) = match entry.source {
+        GlossarySource::Manual => (EvidenceTool::Human, Confidence::High),
+        GlossarySource::Extracted => (EvidenceTool::Parser, Confidence::Low),
+    };
+    let from = term_node(&entry.term);
+
+    targets
+        .into_iter()
+        .map(|path| Edge {
+            from: from.clone(),
+            to: NodeId {
+                kind: NodeKind::File,
+                id: path,
+            },
+            label: EdgeLabel::Mentions,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool,
+                confidence,
+                span: None,
+                blob_id: Some(glossary_id),
+                condition: None,
+            },
+        })
+        .collect()
+}
+
+/// Known files named in `text`.
+///
+/// A word names a file if it equals a known path or is the file name of one
+/// or more known paths (`graph.rs` matches `src/graph.rs`).
+pub(crate) fn mentioned_files(text: &str, known_files: &BTreeSet<String>) -> BTreeSet<String> {
+    let mut targets = BTreeSet::new();
+    for word in text.split_whitespace() {
+        let word = word
+            .trim_start_matches(|c: char| !(c.is_alphanumeric() || "_./".contains(c)))
+            .trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_'));
+        if word.is_empty() || !word.contains('.') {
+            continue;
+        }
+        if known_files.contains(word) {
+            targets.insert(word.to_string());
+            continue;
+        }
+        let suffix = format!("/{}", word);
+        targets.extend(known_files.iter().filter(|p| p.ends_with(&suffix)).cloned());
+    }
+    targets
+}
+
+/// The graph node for a glossary term.
+pub(crate) fn term_node(term: &str) -> NodeId {
+    NodeId {
+        kind: NodeKind::Term,
+        id: term.to_string(),
+    }
+}
+
+/// Every file path present in `start` or any of its ancestors.
+///
+/// Commits only snapshot the files a session wrote, so resolving file names
+/// in definitions needs the union over history.
+pub(crate) fn historical_paths(
+    start: ObjectId,
+    object_store: &ObjectStore,
+) -> Result<BTreeSet<String>> {
+    let mut paths = BTreeSet::new();
+    let mut queue = VecDeque::from([start]);
+    let mut seen_commits = HashSet::new();
+    let mut seen_trees = HashSet::new();
+
+    while let Some(id) = queue.pop_front() {
+        if !seen_commits.insert(id) {
+            continue;
+        }
+        let commit: Commit = object_store.get_typed(id)?;
+        if seen_trees.insert(commit.root_tree) {
+            paths.extend(flatten_tree(commit.root_tree, object_store)?.into_keys());
+        }
+        queue.extend(object_store.parents(&commit));
+    }
+
+    Ok(paths)
+}
+
+/// Validates a term and definition supplied by a user.
+pub(crate) fn validate_entry(term: &str, definition: &str) -> Result<()> {
+    if term.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "glossary term must not be empty".to_string(),
+        ));
+    }
+    if definition.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(format!(
+            "definition for '{}' must not be empty",
+            term.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns true if `word` occurs in `text` bounded by non-word characters.
+pub(crate) fn contains_word(text: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
+
+fn context_line(line: &str) -> String {
+    let line = line.trim().trim_start_matches(['#', '-', '*', ' ']);
+    match line.char_indices().nth(MAX_CONTEXT_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(term: &str, definition: &str) -> GlossaryEntry {
+        GlossaryEntry {
+            term: term.to_string(),
+            definition: definition.to_string(),
+            source: GlossarySource::Manual,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_glossary_upsert_and_matching() {
+        let mut glossary = Glossary::default();
+        glossary.upsert(entry("SCC", "strongly connected component"));
+        glossary.upsert(entry("prompt pack", "retrieval output"));
+        glossary.upsert(entry(
+            "scc",
+            "strongly connected component used in graph.rs",
+        ));
+
+        assert_eq!(glossary.entries.len(), 2);
+        assert_eq!(glossary.entries[0].term, "prompt pack");
+        assert!(glossary.get("Scc").unwrap().definition.contains("graph.rs"));
+
+        let terms = |q: &str| -> Vec<String> {
+            glossary
+                .matching(q)
+                .into_iter()
+                .map(|e| e.term.clone())
+                .collect()
+        };
+        assert_eq!(terms("how are SCCs collapsed"), Vec::<String>::new());
+        assert_eq!(
+            terms("build the Prompt Pack for scc"),
+            vec!["prompt pack", "scc"]
+        );
+
+        assert!(glossary.remove("SCC").is_some());
+        assert!(glossary.remove("SCC").is_none());
+    }
+
+    #[test]
+    fn test_extract_candidates() {
+        let docs = vec![
+            (
+                "log/2026-01-01.md".to_string(),
+                "- Collapsed each SCC before expansion\n- TODO: SCC ordering\n".to_string(),
+            ),
+            (
+                "tasks/0001-graph.md".to_string(),
+                "The SCC pass feeds EdgeBatch loading. EdgeBatch once.\n".to_string(),
+            ),
+        ];
+        let mut glossary = Glossary::default();
+
+        let candidates = extract_candidates(&docs, &glossary, 2);
+        let terms: Vec<&str> = candidates.iter().map(|c| c.term.as_str()).collect();
+        assert_eq!(terms, vec!["SCC", "EdgeBatch"]);
+        assert_eq!(candidates[0].occurrences, 3);
+        assert_eq!(candidates[0].files.len(), 2);
+        assert_eq!(candidates[0].context, "Collapsed each SCC before expansion");
+
+        glossary.upsert(entry("scc", "component"));
+        let candidates = extract_candidates(&docs, &glossary, 2);
+        assert_eq!(candidates.len(), 1);
+    }
+
+    #[test]
+    fn test_mention_edges_resolve_file_names() {
+        let files: BTreeSet<String> = ["crates/core/src/graph.rs", "src/lib.rs"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        let id = ObjectId::from_bytes([0; 32]);
+
+        let edges = mention_edges(
+            &entry("SCC", "component used in graph.rs (see src/lib.rs)."),
+            id,
+            id,
+            &files,
+        );
+        let targets: Vec<&str> = edges.iter().map(|e| e.to.id.as_str()).collect();
+        assert_eq!(targets, vec!["crates/core/src/graph.rs", "src/lib.rs"]);
+        assert!(edges.iter().all(|e| e.from == term_node("SCC")));
+    }
+}
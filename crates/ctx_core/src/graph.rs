@@ -1,8 +1,10 @@
 //! Graph operations including traversal and SCC computation.
 
+use crate::cfg::condition_holds;
 use crate::error::Result;
 use crate::index::Index;
-use crate::types::{EdgeBatch, EdgeLabel, NodeId, NodeKind};
+use crate::types::{Confidence, EdgeBatch, EdgeLabel, NodeId, NodeKind};
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 /// In-memory adjacency list for algorithms requiring full graph view.
@@ -19,34 +21,35 @@ pub struct AdjacencyList {
 impl AdjacencyList {
     /// Build adjacency list from edge batches.
     pub fn from_edge_batches<'a>(batches: impl Iterator<Item = &'a EdgeBatch>) -> Self {
-        let mut forward: BTreeMap<NodeId, Vec<(EdgeLabel, NodeId)>> = BTreeMap::new();
-        let mut backward: BTreeMap<NodeId, Vec<(EdgeLabel, NodeId)>> = BTreeMap::new();
-        let mut nodes = BTreeSet::new();
-
+        let mut adjacency = Self::new();
         for batch in batches {
             for edge in &batch.edges {
-                nodes.insert(edge.from.clone());
-                nodes.insert(edge.to.clone());
-
-                forward
-                    .entry(edge.from.clone())
-                    .or_default()
-                    .push((edge.label, edge.to.clone()));
-
-                backward
-                    .entry(edge.to.clone())
-                    .or_default()
-                    .push((edge.label, edge.from.clone()));
+                adjacency.add_edge(edge.from.clone(), edge.label, edge.to.clone());
             }
         }
+        adjacency
+    }
 
+    /// Create an empty adjacency list.
+    pub(crate) fn new() -> Self {
         Self {
-            forward,
-            backward,
-            nodes,
+            forward: BTreeMap::new(),
+            backward: BTreeMap::new(),
+            nodes: BTreeSet::new(),
         }
     }
 
+    /// Add a single edge.
+    pub(crate) fn add_edge(&mut self, from: NodeId, label: EdgeLabel, to: NodeId) {
+        self.nodes.insert(from.clone());
+        self.nodes.insert(to.clone());
+        self.forward
+            .entry(from.clone())
+            .or_default()
+            .push((label, to.clone()));
+        self.backward.entry(to).or_default().push((label, from));
+    }
+
     /// Get outgoing edges for a node.
     pub fn outgoing(&self, node: &NodeId) -> &[(EdgeLabel, NodeId)] {
         self.forward.get(node).map(|v| v.as_slice()).unwrap_or(&[])
@@ -84,6 +87,74 @@ pub struct ExpansionConfig {
     pub max_nodes: usize,
     /// Whether to follow edges bidirectionally.
     pub bidirectional: bool,
+    /// Follow edges backwards only, to the nodes pointing at the seeds
+    /// (ignored if `bidirectional`).
+    pub reverse: bool,
+    /// Treat each strongly connected component as one unit: reaching any
+    /// member pulls in the rest at the same depth. Uses the SCCs persisted
+    /// in the index and falls back to plain expansion while they are stale.
+    pub use_scc: bool,
+    /// Skip edges whose merged confidence, decayed by age, is too low.
+    pub edge_decay: EdgeDecayConfig,
+    /// Current time for edge decay (Unix seconds).
+    pub now_unix: u64,
+    /// Cargo features enabled in the build of interest. When set, edges
+    /// whose every piece of evidence carries a `cfg` condition failing for
+    /// these features are not followed. None follows edges regardless.
+    pub active_features: Option<BTreeSet<String>>,
+}
+
+/// Weighting of edges by merged confidence and age
+/// (`[edge_decay]` in `config.toml`).
+///
+/// An edge's weight is its merged confidence (see
+/// [`MergedEdge::confidence`](crate::MergedEdge::confidence)) halved every
+/// half-life since a commit last asserted it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdgeDecayConfig {
+    /// Apply weights during expansion (default: false).
+    pub enabled: bool,
+    /// Half-life in days for labels without an override (default: 365).
+    /// Zero disables decay.
+    pub half_life_days: f32,
+    /// Per-label half-lives in days, keyed by lowercase label name such as
+    /// `references`.
+    pub labels: BTreeMap<String, f32>,
+    /// Edges weighing less than this are not followed (default: 0.1).
+    pub min_weight: f32,
+}
+
+impl Default for EdgeDecayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            half_life_days: 365.0,
+            labels: BTreeMap::new(),
+            min_weight: 0.1,
+        }
+    }
+}
+
+impl EdgeDecayConfig {
+    /// Half-life in days for edges with `label`.
+    pub fn half_life_for(&self, label: EdgeLabel) -> f32 {
+        let name = format!("{:?}", label).to_lowercase();
+        self.labels
+            .get(&name)
+            .copied()
+            .unwrap_or(self.half_life_days)
+    }
+
+    /// Multiplier for an edge with `label` last asserted `age_secs` ago.
+    pub fn factor(&self, label: EdgeLabel, age_secs: u64) -> f32 {
+        let half_life = self.half_life_for(label);
+        if half_life <= 0.0 {
+            return 1.0;
+        }
+        let age_days = age_secs as f32 / 86_400.0;
+        0.5f32.powf(age_days / half_life)
+    }
 }
 
 impl Default for ExpansionConfig {
@@ -98,6 +169,11 @@ impl Default for ExpansionConfig {
             ],
             max_nodes: 50,
             bidirectional: false,
+            reverse: false,
+            use_scc: false,
+            edge_decay: EdgeDecayConfig::default(),
+            now_unix: 0,
+            active_features: None,
         }
     }
 }
@@ -113,6 +189,38 @@ pub struct ExpansionResult {
     pub seeds: Vec<NodeId>,
     /// Whether expansion was truncated due to max_nodes limit.
     pub truncated: bool,
+    /// Edge through which each non-seed node was first reached.
+    pub discovered_via: HashMap<NodeId, ExpansionStep>,
+    /// Whether SCC members were expanded as units.
+    pub scc_used: bool,
+}
+
+/// One hop of a graph expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpansionStep {
+    /// Node the hop started from.
+    pub from: NodeId,
+    /// Label of the edge followed, or `None` if the node was pulled in as
+    /// a member of the same SCC as `from`.
+    pub label: Option<EdgeLabel>,
+    /// True if the edge was followed backwards (it points at `from`).
+    pub reversed: bool,
+}
+
+impl ExpansionResult {
+    /// Hops from a seed to `node`, seed first.
+    ///
+    /// Empty for seeds and for nodes the expansion didn't reach.
+    pub fn path_to(&self, node: &NodeId) -> Vec<ExpansionStep> {
+        let mut path = Vec::new();
+        let mut current = node;
+        while let Some(step) = self.discovered_via.get(current) {
+            path.push(step.clone());
+            current = &step.from;
+        }
+        path.reverse();
+        path
+    }
 }
 
 /// Expand graph from seed nodes using BFS.
@@ -141,6 +249,7 @@ pub struct ExpansionResult {
 ///     max_nodes: 100,
 ///     follow_labels: vec![EdgeLabel::Imports, EdgeLabel::DependsOn],
 ///     bidirectional: false,
+///     ..Default::default()
 /// };
 ///
 /// let result = expand_from_seeds(&index, seeds, &config)?;
@@ -156,7 +265,13 @@ pub fn expand_from_seeds(
     let mut visited = HashSet::new();
     let mut queue = VecDeque::new();
     let mut depths = HashMap::new();
+    let mut discovered_via = HashMap::new();
     let mut result = Vec::new();
+    let scc_view = if config.use_scc {
+        index.scc_view()?
+    } else {
+        None
+    };
 
     // Initialize with seeds
     for seed in &seeds {
@@ -177,6 +292,28 @@ pub fn expand_from_seeds(
             break;
         }
 
+        // Pull in the rest of the node's SCC at the same depth
+        if let Some(scc) = scc_view.as_ref().and_then(|view| {
+            view.scc_of(&node)
+                .map(|id| view.members(id))
+                .filter(|members| members.len() > 1)
+        }) {
+            for member in scc {
+                if visited.insert(member.clone()) {
+                    queue.push_back((member.clone(), depth));
+                    depths.insert(member.clone(), depth);
+                    discovered_via.insert(
+                        member.clone(),
+                        ExpansionStep {
+                            from: node.clone(),
+                            label: None,
+                            reversed: false,
+                        },
+                    );
+                }
+            }
+        }
+
         // Check depth limit
         if depth >= config.max_depth {
             continue;
@@ -184,23 +321,50 @@ pub fn expand_from_seeds(
 
         // Expand edges
         for label in &config.follow_labels {
-            // Outgoing edges
-            if let Ok(neighbors) = index.get_edges_from(&node, *label) {
+            // Outgoing edges (unless reversed)
+            let outgoing = if config.bidirectional || !config.reverse {
+                index.get_edges_from(&node, *label)
+            } else {
+                Ok(Vec::new())
+            };
+            if let Ok(neighbors) = outgoing {
                 for neighbor in neighbors {
+                    if !edge_allowed(index, config, &node, &neighbor, *label)? {
+                        continue;
+                    }
                     if visited.insert(neighbor.clone()) {
                         queue.push_back((neighbor.clone(), depth + 1));
                         depths.insert(neighbor.clone(), depth + 1);
+                        discovered_via.insert(
+                            neighbor,
+                            ExpansionStep {
+                                from: node.clone(),
+                                label: Some(*label),
+                                reversed: false,
+                            },
+                        );
                     }
                 }
             }
 
-            // Incoming edges (if bidirectional)
-            if config.bidirectional {
+            // Incoming edges (if bidirectional or reversed)
+            if config.bidirectional || config.reverse {
                 if let Ok(neighbors) = index.get_edges_to(&node, *label) {
                     for neighbor in neighbors {
+                        if !edge_allowed(index, config, &neighbor, &node, *label)? {
+                            continue;
+                        }
                         if visited.insert(neighbor.clone()) {
                             queue.push_back((neighbor.clone(), depth + 1));
                             depths.insert(neighbor.clone(), depth + 1);
+                            discovered_via.insert(
+                                neighbor,
+                                ExpansionStep {
+                                    from: node.clone(),
+                                    label: Some(*label),
+                                    reversed: true,
+                                },
+                            );
                         }
                     }
                 }
@@ -213,9 +377,170 @@ pub fn expand_from_seeds(
         node_depths: depths,
         seeds,
         truncated,
+        discovered_via,
+        scc_used: scc_view.is_some(),
+    })
+}
+
+/// Returns true if the `from -label-> to` edge may be followed under the
+/// configured active features and edge decay. Edges without evidence or
+/// merge data are always followed.
+fn edge_allowed(
+    index: &Index,
+    config: &ExpansionConfig,
+    from: &NodeId,
+    to: &NodeId,
+    label: EdgeLabel,
+) -> Result<bool> {
+    if let Some(features) = &config.active_features {
+        let evidence = index.get_edge_details(from, to, label)?;
+        let compiled_out = !evidence.is_empty()
+            && evidence.iter().all(|evidence| {
+                evidence
+                    .condition
+                    .as_deref()
+                    .is_some_and(|condition| !condition_holds(condition, features))
+            });
+        if compiled_out {
+            return Ok(false);
+        }
+    }
+    if !config.edge_decay.enabled {
+        return Ok(true);
+    }
+    Ok(match index.get_merged_edge(from, to, label)? {
+        Some(merged) => {
+            merged.weight(label, &config.edge_decay, config.now_unix)
+                >= config.edge_decay.min_weight
+        }
+        None => true,
     })
 }
 
+/// Most hops [`shortest_paths`] looks for a connection over.
+const MAX_PATH_HOPS: u32 = 8;
+
+/// Most paths [`shortest_paths`] returns.
+const MAX_PATHS: usize = 10;
+
+/// One hop of a [`GraphPath`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathHop {
+    /// Label of the edge taken.
+    pub label: EdgeLabel,
+    /// True if the edge was taken backwards (it points at the previous
+    /// node).
+    pub reversed: bool,
+    /// Node the hop arrives at.
+    pub node: NodeId,
+}
+
+/// A chain of edges connecting two nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphPath {
+    /// Node the path starts at.
+    pub start: NodeId,
+    /// Hops in order; the last arrives at the end of the path.
+    pub hops: Vec<PathHop>,
+}
+
+impl GraphPath {
+    /// Nodes strictly between the two ends, for seeding retrieval with the
+    /// connection.
+    pub fn intermediate_nodes(&self) -> impl Iterator<Item = &NodeId> {
+        let inner = self.hops.len().saturating_sub(1);
+        self.hops[..inner].iter().map(|hop| &hop.node)
+    }
+}
+
+/// Finds the shortest chains of `labels` edges connecting `from` to `to`.
+///
+/// Edges are followed in either direction, so an item reaches another
+/// item in the file that defines both. Every path of the shortest length
+/// is returned, up to 10 and in a stable order; none are if the nodes are
+/// more than 8 hops apart.
+///
+/// # Errors
+///
+/// Returns an error if the index can't be queried.
+pub fn shortest_paths(
+    index: &Index,
+    from: &NodeId,
+    to: &NodeId,
+    labels: &[EdgeLabel],
+) -> Result<Vec<GraphPath>> {
+    if from == to {
+        return Ok(vec![GraphPath {
+            start: from.clone(),
+            hops: Vec::new(),
+        }]);
+    }
+
+    // Breadth-first, one layer at a time, keeping every way each node was
+    // first reached
+    let mut depth_of = HashMap::from([(from.clone(), 0)]);
+    let mut reached_via: HashMap<NodeId, BTreeSet<(NodeId, EdgeLabel, bool)>> = HashMap::new();
+    let mut layer = vec![from.clone()];
+    let mut depth = 0;
+    while !layer.is_empty() && depth < MAX_PATH_HOPS && !depth_of.contains_key(to) {
+        depth += 1;
+        let mut next = Vec::new();
+        for node in &layer {
+            for &label in labels {
+                let outgoing = index.get_edges_from(node, label)?;
+                let incoming = index.get_edges_to(node, label)?;
+                let neighbors = outgoing
+                    .into_iter()
+                    .map(|n| (n, false))
+                    .chain(incoming.into_iter().map(|n| (n, true)));
+                for (neighbor, reversed) in neighbors {
+                    let neighbor_depth = *depth_of.entry(neighbor.clone()).or_insert_with(|| {
+                        next.push(neighbor.clone());
+                        depth
+                    });
+                    if neighbor_depth == depth {
+                        reached_via.entry(neighbor).or_default().insert((
+                            node.clone(),
+                            label,
+                            reversed,
+                        ));
+                    }
+                }
+            }
+        }
+        layer = next;
+    }
+    if !depth_of.contains_key(to) {
+        return Ok(Vec::new());
+    }
+
+    // Walk back from `to`, branching at every recorded way in
+    let mut paths = Vec::new();
+    let mut partial = vec![(to.clone(), Vec::new())];
+    while let Some((node, hops)) = partial.pop() {
+        if paths.len() >= MAX_PATHS {
+            break;
+        }
+        let Some(ways) = reached_via.get(&node) else {
+            let mut hops: Vec<PathHop> = hops;
+            hops.reverse();
+            paths.push(GraphPath { start: node, hops });
+            continue;
+        };
+        // Reversed so the first way is explored first
+        for (previous, label, reversed) in ways.iter().rev() {
+            let mut hops = hops.clone();
+            hops.push(PathHop {
+                label: *label,
+                reversed: *reversed,
+                node: node.clone(),
+            });
+            partial.push((previous.clone(), hops));
+        }
+    }
+    Ok(paths)
+}
+
 /// Strongly Connected Component identifier.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct SccId(pub u32);
@@ -234,6 +559,26 @@ pub struct SccView {
 }
 
 impl SccView {
+    /// Reassemble a view from SCC members and condensation edges, as
+    /// persisted in the index.
+    pub(crate) fn from_parts(
+        scc_members: Vec<Vec<NodeId>>,
+        scc_dag: BTreeMap<SccId, Vec<SccId>>,
+    ) -> Self {
+        let node_to_scc = scc_members
+            .iter()
+            .enumerate()
+            .flat_map(|(i, members)| members.iter().map(move |n| (n.clone(), SccId(i as u32))))
+            .collect();
+        let topo_order = compute_topo_order(&scc_dag, scc_members.len());
+        Self {
+            node_to_scc,
+            scc_members,
+            scc_dag,
+            topo_order,
+        }
+    }
+
     /// Get the SCC ID for a node.
     pub fn scc_of(&self, node: &NodeId) -> Option<SccId> {
         self.node_to_scc.get(node).copied()
@@ -584,10 +929,263 @@ fn escape_dot_label(s: &str) -> String {
         .replace('\n', "\\n")
 }
 
+/// Labels of edges that count as a use of the item they point at.
+const USE_LABELS: [EdgeLabel; 4] = [
+    EdgeLabel::Calls,
+    EdgeLabel::References,
+    EdgeLabel::UsesType,
+    EdgeLabel::Implements,
+];
+
+/// An item defined in a file that nothing in the graph uses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanItem {
+    /// The unused item.
+    pub item: NodeId,
+    /// Path of the file that defines it.
+    pub file: String,
+    /// Line of the definition (0-indexed), if recorded.
+    pub line: Option<u32>,
+    /// Confidence of the evidence for the definition. Lower confidence
+    /// means the graph may just be missing the item's uses.
+    pub confidence: Confidence,
+}
+
+/// Finds items defined in a file that have no incoming `Calls`,
+/// `References`, `UsesType` or `Implements` edges: likely dead code.
+///
+/// Entry points that are used from outside the graph are skipped: `main`,
+/// tests (`test_*` functions and items in `tests` modules), impl blocks and
+/// trait impl methods, which are called through the trait. Whether an item
+/// is public API can't be told from the graph; see
+/// [`is_entry_point_declaration`].
+///
+/// Returns each orphan with the file node defining it, once per file.
+pub fn find_orphans(adjacency: &AdjacencyList) -> Vec<(NodeId, NodeId)> {
+    let mut orphans = Vec::new();
+    for node in adjacency.nodes() {
+        if node.kind != NodeKind::Item || is_entry_point_name(&node.id) {
+            continue;
+        }
+        let incoming = adjacency.incoming(node);
+        if incoming.iter().any(|(label, _)| USE_LABELS.contains(label)) {
+            continue;
+        }
+        for (label, from) in incoming {
+            if *label == EdgeLabel::Defines && from.kind == NodeKind::File {
+                orphans.push((node.clone(), from.clone()));
+            }
+        }
+    }
+    orphans
+}
+
+/// Returns true if an item with qualified name `name` is reached from
+/// outside the graph.
+fn is_entry_point_name(name: &str) -> bool {
+    let segments: Vec<&str> = name.split("::").collect();
+    let simple = segments.last().copied().unwrap_or(name);
+    simple == "main"
+        || simple.starts_with("test_")
+        || segments[..segments.len() - 1].contains(&"tests")
+        // Impl blocks, and methods of trait impls ("impl Display for X::fmt")
+        || (name.starts_with("impl ") && (segments.len() == 1 || name.contains(" for ")))
+}
+
+/// Returns true if the item whose name starts at `line` and `col` of
+/// `source` is public API or a test, judging by its declaration: `pub`
+/// (but not `pub(crate)` and the like) before the name, or a `#[test]`-like
+/// attribute above it.
+pub fn is_entry_point_declaration(source: &str, line: u32, col: u32) -> bool {
+    let lines: Vec<&str> = source.lines().collect();
+    let Some(declaration) = lines.get(line as usize) else {
+        return false;
+    };
+    let before_name = declaration
+        .char_indices()
+        .nth(col as usize)
+        .map_or(*declaration, |(i, _)| &declaration[..i]);
+    if before_name.trim_start().starts_with("pub ") {
+        return true;
+    }
+
+    lines[..line as usize]
+        .iter()
+        .rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("#[") || line.starts_with("///"))
+        .any(|line| line.starts_with("#[") && (line.ends_with("test]") || line == "#[bench]"))
+}
+
+/// An edge's two ends, as listed in a [`GraphDiff`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DiffEdge {
+    /// Source node.
+    pub from: NodeId,
+    /// Target node.
+    pub to: NodeId,
+}
+
+/// Edges of one label, from one kind of node, that differ between two
+/// graphs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgeGroupDiff {
+    /// The edges' label.
+    pub label: EdgeLabel,
+    /// Kind of the edges' source nodes.
+    pub kind: NodeKind,
+    /// Edges only in the newer graph, sorted.
+    pub added: Vec<DiffEdge>,
+    /// Edges only in the older graph, sorted.
+    pub removed: Vec<DiffEdge>,
+}
+
+/// How the graph changed between two snapshots, as from one commit to
+/// another.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphDiff {
+    /// Changed edges, grouped by label and then source node kind.
+    pub groups: Vec<EdgeGroupDiff>,
+    /// Nodes with edges only in the newer graph, sorted.
+    pub added_nodes: Vec<NodeId>,
+    /// Nodes with edges only in the older graph, sorted.
+    pub removed_nodes: Vec<NodeId>,
+}
+
+impl GraphDiff {
+    /// Number of edges only in the newer graph.
+    pub fn added_edges(&self) -> usize {
+        self.groups.iter().map(|group| group.added.len()).sum()
+    }
+
+    /// Number of edges only in the older graph.
+    pub fn removed_edges(&self) -> usize {
+        self.groups.iter().map(|group| group.removed.len()).sum()
+    }
+
+    /// Returns true if the graphs are the same.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.added_nodes.is_empty() && self.removed_nodes.is_empty()
+    }
+}
+
+/// Compares two graphs, `before` and `after`.
+///
+/// Edges are matched by their ends and label, so an edge whose evidence
+/// changed doesn't count as changed.
+pub fn diff_graphs(before: &AdjacencyList, after: &AdjacencyList) -> GraphDiff {
+    let edges = |graph: &AdjacencyList| -> BTreeSet<(EdgeLabel, NodeKind, DiffEdge)> {
+        graph
+            .forward
+            .iter()
+            .flat_map(|(from, targets)| {
+                targets.iter().map(move |(label, to)| {
+                    let edge = DiffEdge {
+                        from: from.clone(),
+                        to: to.clone(),
+                    };
+                    (*label, from.kind, edge)
+                })
+            })
+            .collect()
+    };
+    let old_edges = edges(before);
+    let new_edges = edges(after);
+
+    let mut groups: BTreeMap<(EdgeLabel, NodeKind), EdgeGroupDiff> = BTreeMap::new();
+    for (added, (label, kind, edge)) in new_edges
+        .difference(&old_edges)
+        .map(|edge| (true, edge))
+        .chain(old_edges.difference(&new_edges).map(|edge| (false, edge)))
+    {
+        let group = groups
+            .entry((*label, *kind))
+            .or_insert_with(|| EdgeGroupDiff {
+                label: *label,
+                kind: *kind,
+                added: Vec::new(),
+                removed: Vec::new(),
+            });
+        if added {
+            group.added.push(edge.clone());
+        } else {
+            group.removed.push(edge.clone());
+        }
+    }
+
+    let old_nodes: BTreeSet<&NodeId> = before.nodes().collect();
+    let new_nodes: BTreeSet<&NodeId> = after.nodes().collect();
+    GraphDiff {
+        groups: groups.into_values().collect(),
+        added_nodes: new_nodes
+            .difference(&old_nodes)
+            .map(|&n| n.clone())
+            .collect(),
+        removed_nodes: old_nodes
+            .difference(&new_nodes)
+            .map(|&n| n.clone())
+            .collect(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_diff_graphs_groups_changes() {
+        let node = |kind, id: &str| NodeId {
+            kind,
+            id: id.to_string(),
+        };
+        let lib = node(NodeKind::File, "src/lib.rs");
+        let parser = node(NodeKind::Module, "parser");
+        let lexer = node(NodeKind::Module, "lexer");
+        let serde = node(NodeKind::Crate, "serde");
+
+        let mut before = AdjacencyList::new();
+        before.add_edge(lib.clone(), EdgeLabel::DeclaresModule, parser.clone());
+        before.add_edge(lib.clone(), EdgeLabel::DeclaresModule, lexer.clone());
+        let mut after = AdjacencyList::new();
+        after.add_edge(lib.clone(), EdgeLabel::DeclaresModule, parser.clone());
+        after.add_edge(parser.clone(), EdgeLabel::DependsOn, serde.clone());
+
+        let diff = diff_graphs(&before, &after);
+        assert_eq!(diff.added_edges(), 1);
+        assert_eq!(diff.removed_edges(), 1);
+        assert_eq!(diff.added_nodes, vec![serde.clone()]);
+        assert_eq!(diff.removed_nodes, vec![lexer.clone()]);
+
+        let groups: Vec<_> = diff
+            .groups
+            .iter()
+            .map(|group| (group.label, group.kind))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (EdgeLabel::DeclaresModule, NodeKind::File),
+                (EdgeLabel::DependsOn, NodeKind::Module)
+            ]
+        );
+        assert_eq!(
+            diff.groups[0].removed,
+            vec![DiffEdge {
+                from: lib,
+                to: lexer
+            }]
+        );
+        assert_eq!(
+            diff.groups[1].added,
+            vec![DiffEdge {
+                from: parser,
+                to: serde
+            }]
+        );
+
+        assert!(diff_graphs(&after, &after).is_empty());
+    }
+
     #[test]
     fn test_adjacency_from_edge_batches() {
         use crate::types::Edge;
@@ -693,6 +1291,32 @@ mod tests {
         assert_eq!(scc_view.members(SccId(0)).len(), 3);
     }
 
+    #[test]
+    fn test_expansion_path_to() {
+        let step = |from: &str, reversed| ExpansionStep {
+            from: node_file(from),
+            label: Some(EdgeLabel::Imports),
+            reversed,
+        };
+        let expansion = ExpansionResult {
+            expanded_nodes: vec![node_file("a.rs"), node_file("b.rs"), node_file("c.rs")],
+            node_depths: HashMap::new(),
+            seeds: vec![node_file("a.rs")],
+            truncated: false,
+            discovered_via: HashMap::from([
+                (node_file("b.rs"), step("a.rs", false)),
+                (node_file("c.rs"), step("b.rs", true)),
+            ]),
+            scc_used: false,
+        };
+
+        assert_eq!(
+            expansion.path_to(&node_file("c.rs")),
+            vec![step("a.rs", false), step("b.rs", true)]
+        );
+        assert!(expansion.path_to(&node_file("a.rs")).is_empty());
+    }
+
     #[test]
     fn test_expansion_depth_1() {
         // This would require a full Index setup, so skipping for now
@@ -714,6 +1338,161 @@ mod tests {
             confidence: Confidence::High,
             span: None,
             blob_id: None,
+            condition: None,
+        }
+    }
+
+    #[test]
+    fn test_find_orphans() {
+        let file = NodeId {
+            kind: NodeKind::File,
+            id: "src/lib.rs".to_string(),
+        };
+        let item = |id: &str| NodeId {
+            kind: NodeKind::Item,
+            id: id.to_string(),
+        };
+        let mut adj = AdjacencyList::new();
+        for id in [
+            "used",
+            "unused",
+            "Shape",
+            "main",
+            "tests::helper",
+            "impl Shape",
+            "impl Display for Shape::fmt",
+            "impl Shape::area",
+        ] {
+            adj.add_edge(file.clone(), EdgeLabel::Defines, item(id));
         }
+        adj.add_edge(item("main"), EdgeLabel::Calls, item("used"));
+        adj.add_edge(file.clone(), EdgeLabel::References, item("used"));
+        adj.add_edge(item("used"), EdgeLabel::UsesType, item("Shape"));
+        // Not defined in a file, so not known to be ours
+        adj.add_edge(item("main"), EdgeLabel::Calls, item("external"));
+
+        let orphans: Vec<_> = find_orphans(&adj)
+            .into_iter()
+            .map(|(item, file)| (item.id, file.id))
+            .collect();
+        assert_eq!(
+            orphans,
+            vec![
+                ("impl Shape::area".to_string(), "src/lib.rs".to_string()),
+                ("unused".to_string(), "src/lib.rs".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_entry_point_declaration() {
+        let source = "\
+pub fn api() {}
+pub(crate) fn internal() {}
+fn private() {}
+#[test]
+fn checks() {}
+/// Docs
+#[tokio::test]
+async fn checks_async() {}
+    pub fn method(&self) {}
+";
+        assert!(is_entry_point_declaration(source, 0, 7));
+        assert!(!is_entry_point_declaration(source, 1, 14));
+        assert!(!is_entry_point_declaration(source, 2, 3));
+        assert!(is_entry_point_declaration(source, 4, 3));
+        assert!(is_entry_point_declaration(source, 7, 9));
+        assert!(is_entry_point_declaration(source, 8, 11));
+        assert!(!is_entry_point_declaration(source, 20, 0));
+    }
+
+    #[test]
+    fn test_shortest_paths() {
+        use crate::object_id::ObjectId;
+        use crate::types::{Commit, Edge};
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut index = Index::create(tmp.path().join("index.redb")).unwrap();
+        let item = |id: &str| NodeId {
+            kind: NodeKind::Item,
+            id: id.to_string(),
+        };
+        let file = NodeId {
+            kind: NodeKind::File,
+            id: "src/staging.rs".to_string(),
+        };
+        let edge = |from: &NodeId, label, to: &NodeId| Edge {
+            from: from.clone(),
+            to: to.clone(),
+            label,
+            weight: None,
+            evidence: dummy_evidence(),
+        };
+        let batch = EdgeBatch {
+            edges: vec![
+                edge(&item("main"), EdgeLabel::Calls, &item("run")),
+                edge(&item("run"), EdgeLabel::Calls, &item("compact")),
+                edge(&item("main"), EdgeLabel::Calls, &item("dispatch")),
+                edge(&item("dispatch"), EdgeLabel::Calls, &item("compact")),
+                edge(&item("main"), EdgeLabel::Calls, &item("a")),
+                edge(&item("a"), EdgeLabel::Calls, &item("b")),
+                edge(&item("b"), EdgeLabel::Calls, &item("compact")),
+                edge(&file, EdgeLabel::Defines, &item("compact")),
+                edge(&file, EdgeLabel::Defines, &item("flush")),
+            ],
+            created_at: 0,
+        };
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 0,
+            message: String::new(),
+            root_tree: ObjectId::from_bytes([0; 32]),
+            edge_batches: vec![],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        index
+            .add_commit_edges(ObjectId::from_bytes([2; 32]), &commit, &[batch])
+            .unwrap();
+
+        // Both two-hop call chains, but not the three-hop one
+        let paths =
+            shortest_paths(&index, &item("main"), &item("compact"), &[EdgeLabel::Calls]).unwrap();
+        let middles: Vec<Vec<&str>> = paths
+            .iter()
+            .map(|p| p.intermediate_nodes().map(|n| n.id.as_str()).collect())
+            .collect();
+        assert_eq!(middles, vec![vec!["dispatch"], vec!["run"]]);
+        assert_eq!(paths[0].start, item("main"));
+        assert_eq!(paths[0].hops[1].node, item("compact"));
+
+        // Edges are followed backwards too
+        let paths = shortest_paths(
+            &index,
+            &item("compact"),
+            &item("flush"),
+            &[EdgeLabel::Defines],
+        )
+        .unwrap();
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].hops[0].reversed);
+        assert_eq!(paths[0].hops[0].node, file);
+        assert!(!paths[0].hops[1].reversed);
+
+        // Unconnected under the given labels
+        let paths =
+            shortest_paths(&index, &item("main"), &item("flush"), &[EdgeLabel::Calls]).unwrap();
+        assert!(paths.is_empty());
     }
 }
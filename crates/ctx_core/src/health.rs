@@ -0,0 +1,351 @@
+//! Repository health score.
+//!
+//! [`CtxRepo::health`](crate::CtxRepo::health) rates a handful of signals
+//! that a repository is drifting into trouble before anything fails
+//! outright: an index lagging behind HEAD, a STAGE left by a session whose
+//! base HEAD has moved past, corrupt objects in a sample of the store, a
+//! high share of abandoned sessions, and fast storage growth. Each is rated
+//! green, yellow or red against the thresholds in [`HealthConfig`], and the
+//! report as a whole takes the worst rating.
+
+use crate::config::HealthConfig;
+use crate::error::Result;
+use crate::object_id::ObjectId;
+use crate::object_store::ObjectStore;
+use crate::refs::Refs;
+use crate::status::IndexFreshness;
+use crate::types::{Commit, CommitType, WorkCommit};
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+
+/// How healthy one signal, or the repository, is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthLevel {
+    /// Nothing to do.
+    Green,
+    /// Worth a look.
+    Yellow,
+    /// Needs attention.
+    Red,
+}
+
+impl HealthLevel {
+    /// Rates `value` against thresholds at which it turns yellow and red.
+    fn rate<T: PartialOrd>(value: T, yellow: T, red: T) -> Self {
+        if value >= red {
+            Self::Red
+        } else if value >= yellow {
+            Self::Yellow
+        } else {
+            Self::Green
+        }
+    }
+}
+
+impl std::fmt::Display for HealthLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Red => "red",
+        })
+    }
+}
+
+/// Health of a repository, as returned by
+/// [`CtxRepo::health`](crate::CtxRepo::health).
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// The worst rating below.
+    pub level: HealthLevel,
+    /// How far the index lags behind HEAD.
+    pub index: IndexHealth,
+    /// Whether STAGE belongs to a session that can still be compacted.
+    pub stage: StageHealth,
+    /// Corrupt objects in a sample of the store.
+    pub objects: ObjectHealth,
+    /// Share of recent sessions that were abandoned.
+    pub sessions: SessionHealth,
+    /// How fast the object store is growing.
+    pub growth: GrowthHealth,
+}
+
+/// Index freshness, rated.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexHealth {
+    /// Rating: unindexed commits against `health.index_behind_*`. An index
+    /// that hasn't been built is yellow and an unreadable one red.
+    pub level: HealthLevel,
+    /// How current the index is.
+    pub freshness: IndexFreshness,
+}
+
+/// STAGE, rated.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageHealth {
+    /// Rating: yellow if STAGE is orphaned, red if it can't be read.
+    pub level: HealthLevel,
+    /// Work commit STAGE points to, if it's set.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub stage: Option<ObjectId>,
+    /// Why STAGE is orphaned or unreadable, if it is.
+    pub problem: Option<String>,
+}
+
+/// Object integrity in a sample of the store, rated.
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectHealth {
+    /// Rating: corrupt objects against `health.corrupted_*`.
+    pub level: HealthLevel,
+    /// Objects in the store.
+    pub total: usize,
+    /// Objects checked, spread evenly over the store.
+    pub sampled: usize,
+    /// Sampled objects that failed their check.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_vec")]
+    pub corrupted: Vec<ObjectId>,
+}
+
+/// Session outcomes in recent history, rated.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionHealth {
+    /// Rating: the abandoned share against `health.abandoned_ratio_*`.
+    pub level: HealthLevel,
+    /// Session commits looked at, newest first from HEAD.
+    pub sessions: usize,
+    /// How many of them were abandoned.
+    pub abandoned: usize,
+    /// `abandoned / sessions`, or 0 without sessions.
+    pub abandoned_ratio: f32,
+}
+
+/// Storage growth, rated.
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowthHealth {
+    /// Rating: bytes per day against `health.growth_*_bytes_per_day`.
+    pub level: HealthLevel,
+    /// Days the rate is averaged over.
+    pub window_days: u32,
+    /// Bytes of objects written within the window.
+    pub bytes: u64,
+    /// `bytes / window_days`.
+    pub bytes_per_day: u64,
+}
+
+/// Rates the repository. `index` is the index's freshness, which the
+/// caller works out since it needs the index open.
+pub(crate) fn check(
+    refs: &Refs,
+    store: &ObjectStore,
+    index: IndexFreshness,
+    config: &HealthConfig,
+    now: SystemTime,
+) -> Result<HealthReport> {
+    let index = IndexHealth {
+        level: match &index {
+            IndexFreshness::Current => HealthLevel::Green,
+            IndexFreshness::Missing => HealthLevel::Yellow,
+            IndexFreshness::Behind { commits } => HealthLevel::rate(
+                *commits,
+                config.index_behind_yellow,
+                config.index_behind_red,
+            ),
+            IndexFreshness::Unreadable { .. } => HealthLevel::Red,
+        },
+        freshness: index,
+    };
+    let stage = check_stage(refs, store)?;
+    let sessions = check_sessions(refs, store, config)?;
+
+    let mut objects = store.list_all_objects()?;
+    let (sampled, corrupted) = sample_objects(store, &mut objects, config.sample_size);
+    let objects_health = ObjectHealth {
+        level: HealthLevel::rate(
+            corrupted.len(),
+            config.corrupted_yellow,
+            config.corrupted_red,
+        ),
+        total: objects.len(),
+        sampled,
+        corrupted,
+    };
+
+    let window = Duration::from_secs(u64::from(config.growth_window_days) * 24 * 60 * 60);
+    let since = now.checked_sub(window).unwrap_or(SystemTime::UNIX_EPOCH);
+    let bytes: u64 = objects
+        .iter()
+        .filter(|(_, _, mtime)| *mtime >= since)
+        .map(|(_, size, _)| size)
+        .sum();
+    let bytes_per_day = bytes / u64::from(config.growth_window_days.max(1));
+    let growth = GrowthHealth {
+        level: HealthLevel::rate(
+            bytes_per_day,
+            config.growth_yellow_bytes_per_day,
+            config.growth_red_bytes_per_day,
+        ),
+        window_days: config.growth_window_days,
+        bytes,
+        bytes_per_day,
+    };
+
+    let level = [
+        index.level,
+        stage.level,
+        objects_health.level,
+        sessions.level,
+        growth.level,
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or(HealthLevel::Green);
+    Ok(HealthReport {
+        level,
+        index,
+        stage,
+        objects: objects_health,
+        sessions,
+        growth,
+    })
+}
+
+/// Checks that STAGE, if set, is a work commit based on HEAD. A session
+/// whose base isn't HEAD anymore would drop the commits since from history
+/// when compacted.
+fn check_stage(refs: &Refs, store: &ObjectStore) -> Result<StageHealth> {
+    let stage = match refs.read_stage() {
+        Ok(stage) => stage,
+        Err(e) => {
+            return Ok(StageHealth {
+                level: HealthLevel::Red,
+                stage: None,
+                problem: Some(e.to_string()),
+            })
+        }
+    };
+    let Some(stage_id) = stage else {
+        return Ok(StageHealth {
+            level: HealthLevel::Green,
+            stage: None,
+            problem: None,
+        });
+    };
+
+    let (level, problem) = match store.get_typed::<WorkCommit>(stage_id) {
+        Err(e) => (
+            HealthLevel::Red,
+            Some(format!("STAGE can't be read: {}", e)),
+        ),
+        Ok(work) if work.base != refs.read_head()? => (
+            HealthLevel::Yellow,
+            Some(format!(
+                "session {} started from {}, but HEAD has moved since",
+                work.session_id,
+                &work.base.as_hex()[..12]
+            )),
+        ),
+        Ok(_) => (HealthLevel::Green, None),
+    };
+    Ok(StageHealth {
+        level,
+        stage: Some(stage_id),
+        problem,
+    })
+}
+
+/// Counts abandoned sessions among the last `health.session_window`
+/// session commits on HEAD's first-parent history.
+fn check_sessions(
+    refs: &Refs,
+    store: &ObjectStore,
+    config: &HealthConfig,
+) -> Result<SessionHealth> {
+    let mut sessions = 0;
+    let mut abandoned = 0;
+    let mut next = Some(refs.read_head()?);
+    while let Some(id) = next {
+        if sessions >= config.session_window {
+            break;
+        }
+        let commit: Commit = store.get_typed(id)?;
+        if let Some(commit_type) = &commit.commit_type {
+            sessions += 1;
+            if matches!(commit_type, CommitType::Abandoned) {
+                abandoned += 1;
+            }
+        }
+        next = store.first_parent(&commit);
+    }
+
+    let abandoned_ratio = match sessions {
+        0 => 0.0,
+        n => abandoned as f32 / n as f32,
+    };
+    Ok(SessionHealth {
+        level: HealthLevel::rate(
+            abandoned_ratio,
+            config.abandoned_ratio_yellow,
+            config.abandoned_ratio_red,
+        ),
+        sessions,
+        abandoned,
+        abandoned_ratio,
+    })
+}
+
+/// Verifies up to `sample_size` of `objects`, spread evenly by ID so
+/// repeated runs check the same ones. Returns how many were checked and
+/// which failed.
+fn sample_objects(
+    store: &ObjectStore,
+    objects: &mut [(ObjectId, u64, SystemTime)],
+    sample_size: usize,
+) -> (usize, Vec<ObjectId>) {
+    if objects.is_empty() || sample_size == 0 {
+        return (0, Vec::new());
+    }
+    objects.sort_by_key(|(id, _, _)| *id);
+    let step = objects.len().div_ceil(sample_size);
+    let mut sampled = 0;
+    let mut corrupted = Vec::new();
+    for (id, _, _) in objects.iter().step_by(step) {
+        sampled += 1;
+        if crate::verify::is_corrupted(store, *id) {
+            corrupted.push(*id);
+        }
+    }
+    (sampled, corrupted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate() {
+        assert_eq!(HealthLevel::rate(0, 1, 50), HealthLevel::Green);
+        assert_eq!(HealthLevel::rate(1, 1, 50), HealthLevel::Yellow);
+        assert_eq!(HealthLevel::rate(50, 1, 50), HealthLevel::Red);
+        assert_eq!(HealthLevel::rate(0.3, 0.25, 0.5), HealthLevel::Yellow);
+        assert!(HealthLevel::Red > HealthLevel::Yellow);
+        assert!(HealthLevel::Yellow > HealthLevel::Green);
+    }
+
+    #[test]
+    fn test_sample_spreads_over_store() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path());
+        for i in 0..10u8 {
+            store.put_blob(&[i]).unwrap();
+        }
+        let mut objects = store.list_all_objects().unwrap();
+
+        let (sampled, corrupted) = sample_objects(&store, &mut objects, 4);
+        assert_eq!(sampled, 4);
+        assert!(corrupted.is_empty());
+        let (sampled, _) = sample_objects(&store, &mut objects, 100);
+        assert_eq!(sampled, 10);
+        assert_eq!(sample_objects(&store, &mut objects, 0).0, 0);
+    }
+}
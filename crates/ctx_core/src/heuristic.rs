@@ -0,0 +1,391 @@
+//! Lightweight Rust edge extraction without rust-analyzer.
+//!
+//! Scans source text for `mod foo;` declarations and `use crate::...`,
+//! `use self::...` and `use super::...` imports, resolving them to files by
+//! Cargo's module layout conventions. The results are approximate (macros,
+//! `#[path]` attributes and re-exports are not understood), so edges carry
+//! [`Confidence::Medium`] and are superseded once full analysis runs.
+
+use crate::cfg::declaration_condition;
+use crate::types::{Confidence, Edge, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind};
+use crate::ObjectId;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::OnceLock;
+
+fn mod_decl_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+([A-Za-z_][A-Za-z0-9_]*)\s*;")
+            .expect("mod regex is valid")
+    })
+}
+
+fn use_decl_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+([^;]+);").expect("use regex is valid")
+    })
+}
+
+/// Extracts `DeclaresModule` and `Imports` edges from a Rust source file.
+///
+/// Edges connect `File` nodes. Imports are only emitted when the target
+/// resolves to a path in `known_files`; module declarations are emitted
+/// regardless, pointing at `foo/mod.rs` if known and `foo.rs` otherwise.
+pub(crate) fn rust_source_edges(
+    path: &str,
+    content: &str,
+    blob_id: ObjectId,
+    commit_id: ObjectId,
+    known_files: &BTreeSet<String>,
+) -> Vec<Edge> {
+    let source = strip_line_comments(content);
+    let module_dir = module_dir(path);
+    // Each target with its `cfg` condition; unconditional wins over gated
+    let mut targets: BTreeMap<(EdgeLabel, String), Option<String>> = BTreeMap::new();
+    let mut insert = |key, condition: Option<String>| {
+        let entry = targets.entry(key).or_insert_with(|| condition.clone());
+        if condition.is_none() {
+            *entry = None;
+        }
+    };
+
+    for caps in mod_decl_regex().captures_iter(&source) {
+        let name = &caps[1];
+        let line = source[..caps.get(1).map_or(0, |m| m.start())]
+            .matches('\n')
+            .count();
+        let nested = join(&module_dir, &format!("{}/mod.rs", name));
+        let target = if known_files.contains(&nested) {
+            nested
+        } else {
+            join(&module_dir, &format!("{}.rs", name))
+        };
+        insert(
+            (EdgeLabel::DeclaresModule, target),
+            declaration_condition(&source, line as u32),
+        );
+    }
+
+    for caps in use_decl_regex().captures_iter(&source) {
+        for use_path in expand_use_tree(&caps[1]) {
+            if let Some(target) = resolve_use_path(path, &module_dir, &use_path, known_files) {
+                insert((EdgeLabel::Imports, target), None);
+            }
+        }
+    }
+
+    let from = NodeId {
+        kind: NodeKind::File,
+        id: path.to_string(),
+    };
+
+    targets
+        .into_iter()
+        .filter(|((_, target), _)| target != path)
+        .map(|((label, target), condition)| Edge {
+            from: from.clone(),
+            to: NodeId {
+                kind: NodeKind::File,
+                id: target,
+            },
+            label,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool: EvidenceTool::Parser,
+                confidence: Confidence::Medium,
+                span: None,
+                blob_id: Some(blob_id),
+                condition,
+            },
+        })
+        .collect()
+}
+
+/// Removes `//` comments so commented-out declarations are ignored.
+///
+/// `//` inside string and character literals, such as a URL, is kept.
+fn strip_line_comments(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = String::with_capacity(content.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let rest = &chars[i..];
+        if rest.starts_with(&['/', '/']) {
+            // Drop the rest of the line, keeping the newline
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        let after_ident = i > 0 && (chars[i - 1].is_alphanumeric() || chars[i - 1] == '_');
+        let len = match rest[0] {
+            '"' => Some(string_literal_len(rest)),
+            'r' if !after_ident || chars[i - 1] == 'b' => raw_string_literal_len(rest),
+            '\'' => char_literal_len(rest),
+            _ => None,
+        }
+        .unwrap_or(1);
+        out.extend(&rest[..len]);
+        i += len;
+    }
+    out
+}
+
+/// Length of the string literal opening `rest`, or of the rest of the
+/// source if it's unterminated.
+fn string_literal_len(rest: &[char]) -> usize {
+    let mut j = 1;
+    while j < rest.len() {
+        match rest[j] {
+            '\\' => j += 2,
+            '"' => return j + 1,
+            _ => j += 1,
+        }
+    }
+    rest.len()
+}
+
+/// Length of the raw string literal (`r"…"`, `r#"…"#`) opening `rest`.
+fn raw_string_literal_len(rest: &[char]) -> Option<usize> {
+    let hashes = rest[1..].iter().take_while(|&&c| c == '#').count();
+    if rest.get(1 + hashes) != Some(&'"') {
+        return None;
+    }
+    let body = 2 + hashes;
+    let end = (body..rest.len())
+        .find(|&j| {
+            rest[j] == '"' && rest[j + 1..].iter().take_while(|&&c| c == '#').count() >= hashes
+        })
+        .map_or(rest.len(), |j| j + 1 + hashes);
+    Some(end)
+}
+
+/// Length of the character literal opening `rest` (`'x'`, `'\''`), or
+/// None for a lifetime.
+fn char_literal_len(rest: &[char]) -> Option<usize> {
+    match rest.get(1)? {
+        '\\' => (3..rest.len().min(12))
+            .find(|&j| rest[j] == '\'')
+            .map(|j| j + 1),
+        _ => (rest.get(2) == Some(&'\'')).then_some(3),
+    }
+}
+
+/// Directory holding the submodules declared by the file at `path`.
+///
+/// `lib.rs`, `main.rs` and `mod.rs` own their directory; any other
+/// `foo.rs` owns `foo/`.
+fn module_dir(path: &str) -> String {
+    let (dir, file) = match path.rsplit_once('/') {
+        Some((dir, file)) => (dir, file),
+        None => ("", path),
+    };
+    match file {
+        "lib.rs" | "main.rs" | "mod.rs" => dir.to_string(),
+        _ => join(dir, file.trim_end_matches(".rs")),
+    }
+}
+
+/// The nearest ancestor `src` directory, used as the crate root.
+fn crate_root(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').collect();
+    match parts[..parts.len() - 1].iter().rposition(|p| *p == "src") {
+        Some(i) => parts[..=i].join("/"),
+        None => parts[..parts.len() - 1].join("/"),
+    }
+}
+
+fn join(dir: &str, rest: &str) -> String {
+    if dir.is_empty() {
+        rest.to_string()
+    } else {
+        format!("{}/{}", dir, rest)
+    }
+}
+
+/// Expands a use tree like `crate::a::{b, c::{d as e}}` into flat paths.
+fn expand_use_tree(tree: &str) -> Vec<String> {
+    let tree: String = tree.split_whitespace().collect::<Vec<_>>().join(" ");
+    let tree = tree.trim();
+
+    let Some(open) = tree.find('{') else {
+        let path = tree.split(" as ").next().unwrap_or(tree).trim();
+        let path = path.trim_end_matches("::self").replace(' ', "");
+        return vec![path];
+    };
+    let close = match tree.rfind('}') {
+        Some(close) if close > open => close,
+        _ => return Vec::new(),
+    };
+
+    let prefix = tree[..open].trim().trim_end_matches("::").replace(' ', "");
+    let mut paths = Vec::new();
+    for part in split_top_level(&tree[open + 1..close]) {
+        for sub in expand_use_tree(part) {
+            if sub.is_empty() || sub == "self" {
+                paths.push(prefix.clone());
+            } else if prefix.is_empty() {
+                paths.push(sub);
+            } else {
+                paths.push(format!("{}::{}", prefix, sub));
+            }
+        }
+    }
+    paths
+}
+
+/// Splits on commas that are not nested inside braces.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+/// Resolves a crate-local use path to the file defining the deepest module
+/// it names.
+fn resolve_use_path(
+    path: &str,
+    module_dir: &str,
+    use_path: &str,
+    known_files: &BTreeSet<String>,
+) -> Option<String> {
+    let mut segments = use_path.split("::").filter(|s| !s.is_empty()).peekable();
+
+    let mut base = match segments.next()? {
+        "crate" => crate_root(path),
+        "self" => module_dir.to_string(),
+        "super" => parent_dir(module_dir),
+        _ => return None,
+    };
+    while segments.peek() == Some(&"super") {
+        segments.next();
+        base = parent_dir(&base);
+    }
+
+    let segments: Vec<&str> = segments.collect();
+    (1..=segments.len()).rev().find_map(|len| {
+        let module = join(&base, &segments[..len].join("/"));
+        [format!("{}.rs", module), format!("{}/mod.rs", module)]
+            .into_iter()
+            .find(|candidate| known_files.contains(candidate))
+    })
+}
+
+fn parent_dir(dir: &str) -> String {
+    dir.rsplit_once('/')
+        .map(|(parent, _)| parent.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known(paths: &[&str]) -> BTreeSet<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn targets(path: &str, content: &str, files: &BTreeSet<String>) -> Vec<(EdgeLabel, String)> {
+        let id = ObjectId::from_bytes([0; 32]);
+        rust_source_edges(path, content, id, id, files)
+            .into_iter()
+            .map(|e| (e.label, e.to.id))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_use_tree() {
+        assert_eq!(expand_use_tree("crate::a::B"), vec!["crate::a::B"]);
+        assert_eq!(
+            expand_use_tree("crate::{a::{self, B}, c as d}"),
+            vec!["crate::a", "crate::a::B", "crate::c"]
+        );
+    }
+
+    #[test]
+    fn test_strip_line_comments_keeps_literals() {
+        let source = "let url = \"http://example.com\"; // fetch\n\
+                      let re = r#\"a//b\"#;\n\
+                      let slash = '/'; // mod old;\n\
+                      fn f<'a>(x: &'a str) {} // done\n\
+                      let quote = '\\''; let s = \"\\\"//\";\n";
+        assert_eq!(
+            strip_line_comments(source),
+            "let url = \"http://example.com\"; \n\
+             let re = r#\"a//b\"#;\n\
+             let slash = '/'; \n\
+             fn f<'a>(x: &'a str) {} \n\
+             let quote = '\\''; let s = \"\\\"//\";\n"
+        );
+    }
+
+    #[test]
+    fn test_mod_declarations() {
+        let files = known(&["src/graph/mod.rs"]);
+        let source = "mod graph;\npub mod pack;\n// mod old;\nmod inline { }\n";
+        assert_eq!(
+            targets("src/lib.rs", source, &files),
+            vec![
+                (EdgeLabel::DeclaresModule, "src/graph/mod.rs".to_string()),
+                (EdgeLabel::DeclaresModule, "src/pack.rs".to_string()),
+            ]
+        );
+        assert_eq!(
+            targets("src/lsp.rs", "mod edges;", &files),
+            vec![(EdgeLabel::DeclaresModule, "src/lsp/edges.rs".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_gated_mod_declarations_carry_condition() {
+        let id = ObjectId::from_bytes([0; 32]);
+        let source = "#[cfg(feature = \"tokio\")]\nmod async_repo;\nmod backend;\n";
+        let conditions: Vec<_> = rust_source_edges("src/lib.rs", source, id, id, &known(&[]))
+            .into_iter()
+            .map(|e| (e.to.id, e.evidence.condition))
+            .collect();
+        assert_eq!(
+            conditions,
+            vec![
+                (
+                    "src/async_repo.rs".to_string(),
+                    Some(r#"feature = "tokio""#.to_string())
+                ),
+                ("src/backend.rs".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_use_resolution() {
+        let files = known(&["src/graph.rs", "src/lsp/edges.rs", "src/lsp.rs"]);
+        let source = "use crate::graph::{expand, Adjacency};\n\
+                      use super::edges::build;\n\
+                      use std::collections::HashMap;\n\
+                      use crate::missing::Thing;\n";
+        assert_eq!(
+            targets("src/lsp/analyzer.rs", source, &files),
+            vec![
+                (EdgeLabel::Imports, "src/graph.rs".to_string()),
+                (EdgeLabel::Imports, "src/lsp/edges.rs".to_string()),
+            ]
+        );
+    }
+}
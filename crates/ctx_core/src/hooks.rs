@@ -0,0 +1,319 @@
+//! Lifecycle hooks.
+//!
+//! A hook is a command run at a point in the repository lifecycle: an
+//! executable named after the event in `.ctx/hooks/` (such as
+//! `.ctx/hooks/pre-compact`), or a command listed under `[hooks]` in
+//! `config.toml`. Each hook receives a JSON payload on stdin describing the
+//! event, runs in the repository root with `CTX_HOOK` set to the event
+//! name, and writes its output to stderr.
+//!
+//! `pre-*` hooks can veto the operation by exiting non-zero. Failures of
+//! other hooks are logged and ignored, since the operation has already
+//! happened. Hooks are external commands, so the exec policy applies.
+
+use crate::error::{CtxError, Result};
+use crate::policy::ExecPolicy;
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A point in the lifecycle where hooks run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// Before `ctx commit` creates a narrative commit. Can veto.
+    PreCommit,
+    /// Before a session is compacted. Can veto; aborts are never vetoed.
+    PreCompact,
+    /// After any canonical commit moves HEAD.
+    PostCommit,
+    /// After a session is compacted.
+    PostCompact,
+    /// After an analysis commit (`ctx analyze rust` or `cargo`).
+    PostAnalyze,
+}
+
+impl HookEvent {
+    /// Every event, in lifecycle order.
+    pub const ALL: [HookEvent; 5] = [
+        HookEvent::PreCommit,
+        HookEvent::PreCompact,
+        HookEvent::PostCommit,
+        HookEvent::PostCompact,
+        HookEvent::PostAnalyze,
+    ];
+
+    /// The hook file name, such as `pre-compact`.
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::PreCommit => "pre-commit",
+            HookEvent::PreCompact => "pre-compact",
+            HookEvent::PostCommit => "post-commit",
+            HookEvent::PostCompact => "post-compact",
+            HookEvent::PostAnalyze => "post-analyze",
+        }
+    }
+
+    /// Returns true if a failing hook stops the operation.
+    pub fn can_veto(self) -> bool {
+        matches!(self, HookEvent::PreCommit | HookEvent::PreCompact)
+    }
+}
+
+/// Hook commands declared in config (`[hooks]` in `config.toml`).
+///
+/// Commands are split on whitespace and run without a shell.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Run before `ctx commit`.
+    pub pre_commit: Vec<String>,
+    /// Run before a session is compacted.
+    pub pre_compact: Vec<String>,
+    /// Run after any commit.
+    pub post_commit: Vec<String>,
+    /// Run after a session is compacted.
+    pub post_compact: Vec<String>,
+    /// Run after an analysis commit.
+    pub post_analyze: Vec<String>,
+    /// Seconds a hook may run before it is killed (default: 60).
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_commit: Vec::new(),
+            pre_compact: Vec::new(),
+            post_commit: Vec::new(),
+            post_compact: Vec::new(),
+            post_analyze: Vec::new(),
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl HooksConfig {
+    /// Commands declared for `event`.
+    pub fn commands(&self, event: HookEvent) -> &[String] {
+        match event {
+            HookEvent::PreCommit => &self.pre_commit,
+            HookEvent::PreCompact => &self.pre_compact,
+            HookEvent::PostCommit => &self.post_commit,
+            HookEvent::PostCompact => &self.post_compact,
+            HookEvent::PostAnalyze => &self.post_analyze,
+        }
+    }
+}
+
+/// Runs every hook for `event` with `payload` on stdin.
+///
+/// The hook file in `hooks_dir` runs first, then configured commands in
+/// order. The payload gains an `event` field.
+///
+/// # Errors
+///
+/// For events that [can veto](HookEvent::can_veto), returns
+/// [`CtxError::HookRejected`] for the first hook that exits non-zero, times
+/// out, can't be started, or is refused by the exec policy. Failures of
+/// other hooks are logged.
+pub(crate) fn run_hooks(
+    event: HookEvent,
+    root: &Path,
+    hooks_dir: &Path,
+    config: &HooksConfig,
+    policy: &ExecPolicy,
+    mut payload: serde_json::Value,
+) -> Result<()> {
+    let mut commands = Vec::new();
+    let file = hooks_dir.join(event.name());
+    if is_executable(&file) {
+        commands.push(Command::new(&file));
+    }
+    for line in config.commands(event) {
+        let mut parts = line.split_whitespace();
+        let Some(program) = parts.next() else {
+            continue;
+        };
+        let mut command = Command::new(program);
+        command.args(parts);
+        commands.push(command);
+    }
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(object) = payload.as_object_mut() {
+        object.insert("event".to_string(), event.name().into());
+    }
+    let input = serde_json::to_vec(&payload).map_err(|e| CtxError::Serialization(e.to_string()))?;
+    let timeout = Duration::from_secs(config.timeout_secs);
+
+    for mut command in commands {
+        command
+            .current_dir(root)
+            .env("CTX_HOOK", event.name())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::from(std::io::stderr()))
+            .stderr(Stdio::inherit());
+        let hook = command.get_program().to_string_lossy().into_owned();
+
+        if let Err(reason) = run_one(&mut command, policy, &input, timeout) {
+            if event.can_veto() {
+                return Err(CtxError::HookRejected {
+                    hook: format!("{} ({})", event.name(), hook),
+                    reason,
+                });
+            }
+            warn!(hook = %hook, event = event.name(), reason = %reason, "Hook failed");
+        }
+    }
+    Ok(())
+}
+
+/// Runs one hook to completion, returning why it failed.
+fn run_one(
+    command: &mut Command,
+    policy: &ExecPolicy,
+    input: &[u8],
+    timeout: Duration,
+) -> std::result::Result<(), String> {
+    let mut child = policy.spawn(command).map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its payload may exit before reading it
+        match stdin.write_all(input) {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e.to_string()),
+            _ => {}
+        }
+    }
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => {
+                return Err(match status.code() {
+                    Some(code) => format!("exited with status {}", code),
+                    None => "killed by signal".to_string(),
+                })
+            }
+            None if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}s", timeout.as_secs()));
+            }
+            None => std::thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn write_hook(dir: &Path, name: &str, script: &str) {
+        let path = dir.join(name);
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_hooks_receive_payload_and_veto() {
+        let tmp = TempDir::new().unwrap();
+        let hooks_dir = tmp.path().join("hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        write_hook(
+            &hooks_dir,
+            "post-commit",
+            "#!/bin/sh\ncat > payload.json\necho \"$CTX_HOOK\" > event.txt\n",
+        );
+        write_hook(&hooks_dir, "pre-compact", "#!/bin/sh\nexit 3\n");
+        let config = HooksConfig::default();
+        let policy = ExecPolicy::default();
+        let run = |event| {
+            run_hooks(
+                event,
+                tmp.path(),
+                &hooks_dir,
+                &config,
+                &policy,
+                serde_json::json!({ "message": "hi" }),
+            )
+        };
+
+        run(HookEvent::PostCommit).unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_slice(&fs::read(tmp.path().join("payload.json")).unwrap()).unwrap();
+        assert_eq!(payload["event"], "post-commit");
+        assert_eq!(payload["message"], "hi");
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("event.txt")).unwrap(),
+            "post-commit\n"
+        );
+
+        let err = run(HookEvent::PreCompact).unwrap_err();
+        assert!(matches!(err, CtxError::HookRejected { .. }));
+        assert!(err.to_string().contains("exited with status 3"));
+
+        // Post hooks only warn
+        write_hook(&hooks_dir, "post-analyze", "#!/bin/sh\nexit 1\n");
+        run(HookEvent::PostAnalyze).unwrap();
+    }
+
+    #[test]
+    fn test_config_hooks_obey_policy_and_timeout() {
+        let tmp = TempDir::new().unwrap();
+        let policy = ExecPolicy::new(crate::policy::ExecConfig {
+            deny: vec!["false".to_string()],
+            ..Default::default()
+        });
+        let run = |config: &HooksConfig| {
+            run_hooks(
+                HookEvent::PreCommit,
+                tmp.path(),
+                &tmp.path().join("hooks"),
+                config,
+                &policy,
+                serde_json::json!({}),
+            )
+        };
+
+        let config = HooksConfig {
+            pre_commit: vec!["true".to_string()],
+            ..Default::default()
+        };
+        run(&config).unwrap();
+
+        let config = HooksConfig {
+            pre_commit: vec!["false".to_string()],
+            ..Default::default()
+        };
+        let err = run(&config).unwrap_err();
+        assert!(err.to_string().contains("denied"));
+
+        let config = HooksConfig {
+            pre_commit: vec!["sleep 5".to_string()],
+            timeout_secs: 0,
+            ..Default::default()
+        };
+        assert!(run(&config).unwrap_err().to_string().contains("timed out"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Ignore rules (`.ctxignore`).
+//!
+//! Paths matching the rules are never observed, analyzed, snapshotted or
+//! retrieved. The rules are `storage.ignore` from `.ctx/config.toml`
+//! (`target/`, `node_modules/` and `*.min.js` by default) followed by the
+//! lines of `.ctxignore` in the repository root, in gitignore syntax:
+//! - Blank lines and lines starting with `#` are skipped.
+//! - A line starting with `!` re-includes paths an earlier rule ignored.
+//! - The last rule matching a path decides.
+//!
+//! Patterns are matched like [`RetrievalConfig::exclude`](crate::RetrievalConfig::exclude).
+
+use crate::error::Result;
+use crate::glob::glob_match;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Name of the ignore file in the repository root.
+pub const IGNORE_FILE: &str = ".ctxignore";
+
+/// One ignore rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    /// Gitignore-style pattern, without the leading `!`.
+    pattern: String,
+    /// Whether the rule re-includes what it matches.
+    negated: bool,
+}
+
+/// Ordered ignore rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Parses rules from lines in gitignore syntax.
+    pub fn new<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let rules = lines
+            .into_iter()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.strip_prefix('!') {
+                Some(pattern) => Rule {
+                    pattern: pattern.to_string(),
+                    negated: true,
+                },
+                None => Rule {
+                    pattern: line.to_string(),
+                    negated: false,
+                },
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Loads `defaults` followed by the rules in `root`'s `.ctxignore`, if
+    /// it has one.
+    pub fn load(root: &Path, defaults: &[String]) -> Result<Self> {
+        let file = match fs::read_to_string(root.join(IGNORE_FILE)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self::new(
+            defaults.iter().map(String::as_str).chain(file.lines()),
+        ))
+    }
+
+    /// Returns the pattern that ignores `path`, or None if it isn't ignored.
+    pub fn ignored_by(&self, path: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| glob_match(&rule.pattern, path))
+            .filter(|rule| !rule.negated)
+            .map(|rule| rule.pattern.as_str())
+    }
+
+    /// Returns true if `path` is ignored.
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.ignored_by(path).is_some()
+    }
+
+    /// Returns true if everything under the directory `path` is ignored, so
+    /// walks can skip it.
+    pub fn is_ignored_dir(&self, path: &str) -> bool {
+        // A later negation might re-include something inside
+        !self.rules.iter().any(|rule| rule.negated) && self.is_ignored(&format!("{}/", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::new([
+            "target/",
+            "# generated",
+            "",
+            "*.min.js",
+            "!vendor/keep.min.js",
+        ]);
+        assert_eq!(rules.ignored_by("target/debug/ctx"), Some("target/"));
+        assert_eq!(rules.ignored_by("web/app.min.js"), Some("*.min.js"));
+        assert!(!rules.is_ignored("vendor/keep.min.js"));
+        assert!(!rules.is_ignored("src/main.rs"));
+        assert!(!rules.is_ignored("# generated"));
+
+        // Directories can only be skipped when nothing is re-included
+        assert!(!rules.is_ignored_dir("target"));
+        assert!(IgnoreRules::new(["target/"]).is_ignored_dir("target"));
+        assert!(IgnoreRules::new(["target/"]).is_ignored_dir("crates/a/target"));
+        assert!(!IgnoreRules::new(["target/"]).is_ignored_dir("src"));
+    }
+}
@@ -0,0 +1,313 @@
+//! Impact analysis: what a change to an item or file could affect.
+//!
+//! [`impact_analysis`] expands the graph backwards from the changed nodes,
+//! following the edges that point at them (callers, dependent packages,
+//! enclosing modules), and sorts what it reaches into files, packages and
+//! tests, so the blast radius of a change is known before it is made.
+
+use crate::cargo::CargoMetadataSnapshot;
+use crate::error::Result;
+use crate::graph::{expand_from_seeds, ExpansionConfig};
+use crate::index::Index;
+use crate::lsp::snapshot::relative_path;
+use crate::types::{EdgeLabel, NodeId, NodeKind};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Settings for [`impact_analysis`].
+#[derive(Debug, Clone)]
+pub struct ImpactConfig {
+    /// Hops to follow back from the changed nodes (default: 3).
+    pub max_depth: u32,
+    /// Labels of edges to follow against their direction (default: Calls,
+    /// DependsOn, Contains).
+    pub labels: Vec<EdgeLabel>,
+    /// Most nodes to reach before stopping (default: 500).
+    pub max_nodes: usize,
+}
+
+impl Default for ImpactConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            labels: vec![EdgeLabel::Calls, EdgeLabel::DependsOn, EdgeLabel::Contains],
+            max_nodes: 500,
+        }
+    }
+}
+
+/// What a change could affect.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImpactReport {
+    /// The item or file asked about.
+    pub target: String,
+    /// Nodes the expansion started from.
+    pub seeds: Vec<NodeId>,
+    /// Items that depend on the target, such as its callers.
+    pub items: Vec<String>,
+    /// Files defining the target and everything affected, relative to the
+    /// repository root where possible.
+    pub files: Vec<String>,
+    /// Packages owning the affected files, and packages depending on them.
+    pub packages: Vec<String>,
+    /// Affected files holding tests: those under a `tests` directory or
+    /// defining an affected test function.
+    pub tests: Vec<String>,
+    /// True if `max_nodes` stopped the expansion early.
+    pub truncated: bool,
+}
+
+/// Finds what depends on `seeds`, the nodes standing for `target`.
+///
+/// Files are reported relative to `root`. With `cargo`, files are also
+/// attributed to the packages whose manifest directory holds them.
+///
+/// # Errors
+///
+/// Returns an error if the index can't be queried.
+pub fn impact_analysis(
+    index: &Index,
+    target: &str,
+    seeds: Vec<NodeId>,
+    cargo: Option<&CargoMetadataSnapshot>,
+    root: &Path,
+    config: &ImpactConfig,
+) -> Result<ImpactReport> {
+    let expansion = expand_from_seeds(
+        index,
+        seeds.clone(),
+        &ExpansionConfig {
+            max_depth: config.max_depth,
+            follow_labels: config.labels.clone(),
+            max_nodes: config.max_nodes,
+            reverse: true,
+            ..Default::default()
+        },
+    )?;
+
+    let mut items = BTreeSet::new();
+    let mut files = BTreeSet::new();
+    let mut packages = BTreeSet::new();
+    let mut test_files = BTreeSet::new();
+    for node in &expansion.expanded_nodes {
+        match node.kind {
+            NodeKind::File => {
+                files.insert(node.id.clone());
+            }
+            NodeKind::Package => {
+                packages.insert(package_name(&node.id).to_string());
+            }
+            NodeKind::Item | NodeKind::Module => {
+                let defined_in = index.get_edges_to(node, EdgeLabel::Defines)?;
+                let test = node.kind == NodeKind::Item && is_test_name(&node.id);
+                for file in defined_in.into_iter().filter(|n| n.kind == NodeKind::File) {
+                    if test {
+                        test_files.insert(file.id.clone());
+                    }
+                    files.insert(file.id);
+                }
+                if node.kind == NodeKind::Item && !seeds.contains(node) {
+                    items.insert(node.id.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let display =
+        |path: &str| relative_path(Path::new(path), root).unwrap_or_else(|| path.to_string());
+    let files: BTreeSet<String> = files.iter().map(|path| display(path)).collect();
+    let tests: BTreeSet<String> = files
+        .iter()
+        .filter(|path| path.split('/').any(|part| part == "tests"))
+        .cloned()
+        .chain(test_files.iter().map(|path| display(path)))
+        .collect();
+    if let Some(cargo) = cargo {
+        for file in &files {
+            if let Some(package) = owning_package(cargo, &root.join(file)) {
+                packages.insert(package.to_string());
+            }
+        }
+    }
+
+    Ok(ImpactReport {
+        target: target.to_string(),
+        seeds,
+        items: items.into_iter().collect(),
+        files: files.into_iter().collect(),
+        packages: packages.into_iter().collect(),
+        tests: tests.into_iter().collect(),
+        truncated: expansion.truncated,
+    })
+}
+
+/// Package name of a package node id, which is `name` or `name@version`.
+fn package_name(id: &str) -> &str {
+    id.split_once('@').map_or(id, |(name, _)| name)
+}
+
+/// Returns true if the item with qualified name `name` looks like a test.
+fn is_test_name(name: &str) -> bool {
+    let mut segments: Vec<&str> = name.split("::").collect();
+    let simple = segments.pop().unwrap_or(name);
+    simple.starts_with("test_") || segments.contains(&"tests")
+}
+
+/// Name of the package whose manifest directory most closely holds `file`.
+fn owning_package<'a>(cargo: &'a CargoMetadataSnapshot, file: &Path) -> Option<&'a str> {
+    cargo
+        .packages
+        .iter()
+        .filter_map(|package| {
+            let dir = Path::new(&package.manifest_path).parent()?;
+            file.starts_with(dir)
+                .then_some((dir.components().count(), package.name.as_str()))
+        })
+        .max()
+        .map(|(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_id::ObjectId;
+    use crate::types::{Commit, Confidence, Edge, EdgeBatch, Evidence, EvidenceTool};
+    use tempfile::TempDir;
+
+    fn node(kind: NodeKind, id: &str) -> NodeId {
+        NodeId {
+            kind,
+            id: id.to_string(),
+        }
+    }
+
+    fn edge(from: NodeId, label: EdgeLabel, to: NodeId) -> Edge {
+        Edge {
+            from,
+            to,
+            label,
+            weight: None,
+            evidence: Evidence {
+                commit_id: ObjectId::from_bytes([1; 32]),
+                tool: EvidenceTool::RustAnalyzer,
+                confidence: Confidence::High,
+                span: None,
+                blob_id: None,
+                condition: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_impact_follows_callers_back_to_files_and_tests() {
+        let tmp = TempDir::new().unwrap();
+        let root = Path::new("/work/app");
+        let mut index = Index::create(tmp.path().join("index.redb")).unwrap();
+        let lib = node(NodeKind::File, "/work/app/src/lib.rs");
+        let cli = node(NodeKind::File, "/work/app/src/cli.rs");
+        let parse = node(NodeKind::Item, "parse");
+        let run = node(NodeKind::Item, "run");
+        let test = node(NodeKind::Item, "tests::test_run");
+        let unrelated = node(NodeKind::Item, "unrelated");
+        let batch = EdgeBatch {
+            edges: vec![
+                edge(lib.clone(), EdgeLabel::Defines, parse.clone()),
+                edge(cli.clone(), EdgeLabel::Defines, run.clone()),
+                edge(cli.clone(), EdgeLabel::Defines, test.clone()),
+                edge(lib.clone(), EdgeLabel::Defines, unrelated.clone()),
+                edge(run.clone(), EdgeLabel::Calls, parse.clone()),
+                edge(test.clone(), EdgeLabel::Calls, run.clone()),
+                edge(parse.clone(), EdgeLabel::Calls, unrelated.clone()),
+            ],
+            created_at: 0,
+        };
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 0,
+            message: String::new(),
+            root_tree: ObjectId::from_bytes([0; 32]),
+            edge_batches: vec![],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        index
+            .add_commit_edges(ObjectId::from_bytes([2; 32]), &commit, &[batch])
+            .unwrap();
+
+        let report = impact_analysis(
+            &index,
+            "parse",
+            vec![parse],
+            None,
+            root,
+            &ImpactConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(report.items, vec!["run", "tests::test_run"]);
+        assert_eq!(report.files, vec!["src/cli.rs", "src/lib.rs"]);
+        assert_eq!(report.tests, vec!["src/cli.rs"]);
+        assert!(!report.truncated);
+
+        // One hop only reaches the direct caller
+        let config = ImpactConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let report = impact_analysis(
+            &index,
+            "parse",
+            vec![node(NodeKind::Item, "parse")],
+            None,
+            root,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(report.items, vec!["run"]);
+        assert!(report.tests.is_empty());
+    }
+
+    #[test]
+    fn test_owning_package_prefers_innermost_manifest() {
+        let package = |name: &str, manifest: &str| crate::cargo::Package {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            id: name.to_string(),
+            manifest_path: manifest.to_string(),
+            edition: "2021".to_string(),
+            targets: vec![],
+            features: Default::default(),
+            dependencies: vec![],
+            default_features: vec![],
+        };
+        let cargo = CargoMetadataSnapshot {
+            workspace_root: "/w".to_string(),
+            packages: vec![
+                package("root", "/w/Cargo.toml"),
+                package("core", "/w/crates/core/Cargo.toml"),
+            ],
+            resolve: None,
+            metadata_version: 1,
+            workspace_members: vec![],
+            manifest_hashes: Default::default(),
+        };
+
+        let owner = |path: &str| owning_package(&cargo, Path::new(path));
+        assert_eq!(owner("/w/crates/core/src/lib.rs"), Some("core"));
+        assert_eq!(owner("/w/src/main.rs"), Some("root"));
+        assert_eq!(owner("/elsewhere/lib.rs"), None);
+        assert_eq!(package_name("serde@1.0.0"), "serde");
+    }
+}
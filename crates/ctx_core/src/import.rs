@@ -0,0 +1,315 @@
+//! Importing external documents into the narrative.
+//!
+//! Design docs, RFCs and other writing that lives outside the repository can
+//! be brought in with
+//! [`CtxRepo::import_document`](crate::CtxRepo::import_document). The text
+//! is converted to Markdown-ish plain text, given frontmatter naming its
+//! kind ([`IMPORT_KIND`]), title and source, and written to
+//! [`IMPORT_DIRECTORY`] in the narrative space. From there it's an ordinary
+//! document: committed with `Mentions` edges to the files it names, seeded
+//! by queries that share words with its title, and included in packs.
+//!
+//! Markdown and plain text are taken as they are and HTML has its markup
+//! stripped. PDFs need their text extracted first (for example with
+//! `pdftotext`); the extracted text imports as plain text.
+
+use crate::error::{CtxError, Result};
+use regex::Regex;
+
+/// Document kind of imported documents.
+pub const IMPORT_KIND: &str = "import";
+
+/// Narrative directory imported documents are written to.
+pub const IMPORT_DIRECTORY: &str = "imports";
+
+/// Format of a document being imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Markdown, kept as is.
+    Markdown,
+    /// HTML, reduced to its text.
+    Html,
+    /// Plain text, such as text extracted from a PDF.
+    Text,
+}
+
+impl ImportFormat {
+    /// Guesses the format from a media type such as `text/html;
+    /// charset=utf-8`, if given, or else from the extension of `source`.
+    /// Anything unrecognized is plain text.
+    pub fn detect(source: &str, content_type: Option<&str>) -> Self {
+        let media_type = content_type
+            .and_then(|t| t.split(';').next())
+            .map(|t| t.trim().to_ascii_lowercase());
+        match media_type.as_deref() {
+            Some("text/html") | Some("application/xhtml+xml") => return Self::Html,
+            Some("text/markdown") | Some("text/x-markdown") => return Self::Markdown,
+            _ => {}
+        }
+
+        let path = source.split(['?', '#']).next().unwrap_or(source);
+        let extension = path
+            .rsplit_once('.')
+            .filter(|(_, ext)| !ext.contains('/'))
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("md") | Some("markdown") => Self::Markdown,
+            Some("html") | Some("htm") | Some("xhtml") => Self::Html,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// A document converted for the narrative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ImportedText {
+    /// Title, from the first heading or `<title>`, or else the source's
+    /// file name.
+    pub title: String,
+    /// Body text.
+    pub body: String,
+}
+
+impl ImportedText {
+    /// Converts `content` from `source` in `format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] if `content` is a PDF, isn't
+    /// UTF-8 or has no text.
+    pub fn convert(source: &str, content: &[u8], format: ImportFormat) -> Result<Self> {
+        if content.starts_with(b"%PDF") {
+            return Err(CtxError::InvalidArgument(format!(
+                "{} is a PDF; extract its text first (e.g. with pdftotext) and import that",
+                source
+            )));
+        }
+        let content = std::str::from_utf8(content)
+            .map_err(|_| CtxError::InvalidArgument(format!("{} isn't UTF-8 text", source)))?;
+
+        let (html_title, body) = match format {
+            ImportFormat::Markdown => (None, strip_frontmatter(content).to_string()),
+            ImportFormat::Html => (html_title(content), html_to_text(content)),
+            ImportFormat::Text => (None, content.to_string()),
+        };
+        let body = body.trim().to_string();
+        if body.is_empty() {
+            return Err(CtxError::InvalidArgument(format!("{} has no text", source)));
+        }
+
+        let title = html_title
+            .or_else(|| first_heading(&body))
+            .unwrap_or_else(|| source_name(source));
+        Ok(Self { title, body })
+    }
+
+    /// The narrative document for this text: frontmatter, then the body
+    /// under a heading with the title unless it starts with one.
+    pub fn render(&self, source: &str) -> String {
+        let mut content = format!(
+            "---\nkind: {}\ntitle: {}\nsource: {}\n---\n\n",
+            IMPORT_KIND,
+            single_line(&self.title),
+            single_line(source)
+        );
+        if !self.body.starts_with("# ") {
+            content.push_str(&format!("# {}\n\n", single_line(&self.title)));
+        }
+        content.push_str(&self.body);
+        content.push('\n');
+        content
+    }
+}
+
+/// `content` without a leading frontmatter block.
+fn strip_frontmatter(content: &str) -> &str {
+    content
+        .strip_prefix("---\n")
+        .and_then(|rest| rest.find("\n---").map(|end| &rest[end + "\n---".len()..]))
+        .unwrap_or(content)
+}
+
+/// The text of the first top-level Markdown heading.
+fn first_heading(body: &str) -> Option<String> {
+    body.lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+/// The last path segment of `source`, for documents without a title.
+fn source_name(source: &str) -> String {
+    source
+        .trim_end_matches('/')
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(source)
+        .to_string()
+}
+
+/// `text` with line breaks replaced by spaces, for a frontmatter value.
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The contents of an HTML document's `<title>`.
+fn html_title(html: &str) -> Option<String> {
+    let re = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid regex");
+    re.captures(html)
+        .map(|c| single_line(&decode_entities(&c[1])))
+        .filter(|title| !title.is_empty())
+}
+
+/// Reduces HTML to text, keeping headings as Markdown headings and block
+/// elements as line breaks so the result splits into sections like any
+/// other narrative file.
+fn html_to_text(html: &str) -> String {
+    let hidden = Regex::new(r"(?is)<(script|style|head|title)\b.*?</(script|style|head|title)\s*>")
+        .expect("valid regex");
+    let comments = Regex::new(r"(?s)<!--.*?-->").expect("valid regex");
+    let headings = Regex::new(r"(?is)<h([1-6])[^>]*>(.*?)</h[1-6]\s*>").expect("valid regex");
+    let items = Regex::new(r"(?i)<li\b[^>]*>").expect("valid regex");
+    let breaks =
+        Regex::new(r"(?i)<br\s*/?>|</(p|div|tr|pre|blockquote|section|article|ul|ol|table)\s*>")
+            .expect("valid regex");
+    let tags = Regex::new(r"(?s)<[^>]*>").expect("valid regex");
+
+    let text = hidden.replace_all(html, "");
+    let text = comments.replace_all(&text, "");
+    let text = headings.replace_all(&text, |c: &regex::Captures| {
+        let level: usize = c[1].parse().unwrap_or(1);
+        let heading = single_line(&tags.replace_all(&c[2], ""));
+        format!("\n\n{} {}\n\n", "#".repeat(level), heading)
+    });
+    let text = items.replace_all(&text, "\n- ");
+    let text = breaks.replace_all(&text, "\n");
+    let text = decode_entities(&tags.replace_all(&text, ""));
+
+    // Trim lines and collapse runs of blank lines
+    let mut out = String::new();
+    let mut blank = true;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            if !blank {
+                out.push('\n');
+            }
+            blank = true;
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+        blank = false;
+    }
+    out
+}
+
+/// Decodes the common named entities and numeric character references.
+fn decode_entities(text: &str) -> String {
+    let entity = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").expect("valid regex");
+    entity
+        .replace_all(text, |c: &regex::Captures| {
+            let name = &c[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => name
+                    .strip_prefix("#x")
+                    .or_else(|| name.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16).ok())
+                    .unwrap_or_else(|| name.strip_prefix('#').and_then(|n| n.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            decoded.map_or_else(|| c[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            ImportFormat::detect("https://example.com/rfc", Some("text/html; charset=utf-8")),
+            ImportFormat::Html
+        );
+        assert_eq!(
+            ImportFormat::detect("docs/Design.MD", None),
+            ImportFormat::Markdown
+        );
+        assert_eq!(
+            ImportFormat::detect(
+                "https://example.com/a.html?x=1",
+                Some("application/octet-stream")
+            ),
+            ImportFormat::Html
+        );
+        assert_eq!(ImportFormat::detect("rfc.txt", None), ImportFormat::Text);
+        assert_eq!(
+            ImportFormat::detect("https://example.com/v1.0/spec", None),
+            ImportFormat::Text
+        );
+    }
+
+    #[test]
+    fn test_convert_html() {
+        let html = "<html><title>Storage &amp; GC</title><head><style>p{}</style></head>\
+            <body><h1>Storage</h1><p>Objects live in <code>object_store.rs</code>.</p>\
+            <!-- draft --><h2>Roots</h2><ul><li>HEAD</li><li>refs</li></ul></body></html>";
+        let text = ImportedText::convert("rfc.html", html.as_bytes(), ImportFormat::Html).unwrap();
+
+        assert_eq!(text.title, "Storage & GC");
+        assert_eq!(
+            text.body,
+            "# Storage\n\nObjects live in object_store.rs.\n\n## Roots\n\n- HEAD\n- refs"
+        );
+    }
+
+    #[test]
+    fn test_convert_markdown_and_text() {
+        let markdown = "---\nauthor: bo\n---\n# Index format\n\nSee index.rs.\n";
+        let text = ImportedText::convert("design.md", markdown.as_bytes(), ImportFormat::Markdown)
+            .unwrap();
+        assert_eq!(text.title, "Index format");
+        assert_eq!(text.body, "# Index format\n\nSee index.rs.");
+
+        let text =
+            ImportedText::convert("/tmp/notes/spec.txt", b"Plain words.\n", ImportFormat::Text)
+                .unwrap();
+        assert_eq!(text.title, "spec.txt");
+
+        assert!(ImportedText::convert("a.pdf", b"%PDF-1.7", ImportFormat::Text).is_err());
+        assert!(ImportedText::convert("a.txt", &[0xff, 0xfe], ImportFormat::Text).is_err());
+        assert!(ImportedText::convert("a.html", b"<p> </p>", ImportFormat::Html).is_err());
+    }
+
+    #[test]
+    fn test_render_parses_as_document() {
+        let text = ImportedText {
+            title: "Spec".to_string(),
+            body: "Words about graph.rs.".to_string(),
+        };
+        let content = text.render("https://example.com/spec");
+        let document =
+            crate::document::Document::parse("imports/import_0001.md", &content).unwrap();
+
+        assert_eq!(document.kind, IMPORT_KIND);
+        assert_eq!(document.title(), Some("Spec"));
+        assert_eq!(document.fields["source"], "https://example.com/spec");
+        assert_eq!(document.body, "# Spec\n\nWords about graph.rs.\n");
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &lt;b&gt; &#65;&#x42; &bogus;"),
+            "a <b> AB &bogus;"
+        );
+    }
+}
@@ -2,11 +2,21 @@
 //!
 //! The index system provides fast lookups for paths, names, commits, and graph adjacency.
 //! All indexes are stored in a redb database and can be rebuilt from the object store.
+//!
+//! One process at a time writes the index; others query a read-only snapshot
+//! of it (see [`Index::open_shared`]).
 
 #![allow(clippy::io_other_error)]
 
 use crate::error::{CtxError, Result};
-use crate::types::{Commit, EdgeBatch, EdgeLabel, NarrativeRef, NodeId, Tree, TreeEntryKind};
+use crate::graph::{compute_scc, AdjacencyList, EdgeDecayConfig, SccId, SccView};
+use crate::index_lock::{self, SnapshotFile, WriterLock};
+use crate::metrics::{HistogramMetric, SharedMetrics, Timer};
+use crate::progress::Progress;
+use crate::types::{
+    Commit, Confidence, EdgeBatch, EdgeLabel, Evidence, EvidenceTool, NarrativeRef, NodeId,
+    NodeKind, Tree, TreeEntryKind,
+};
 use crate::{ObjectId, ObjectStore};
 use redb::{Database, ReadableTable, TableDefinition};
 use serde::{Deserialize, Serialize};
@@ -15,7 +25,7 @@ use std::path::{Path, PathBuf};
 use tracing::warn;
 
 /// Index schema version for migration support.
-pub const INDEX_SCHEMA_VERSION: u32 = 1;
+pub const INDEX_SCHEMA_VERSION: u32 = 2;
 
 /// Configuration for index rebuild operation.
 #[derive(Debug, Clone, Default)]
@@ -23,6 +33,10 @@ pub struct RebuildConfig {
     /// If true, skip corrupted objects instead of failing.
     /// Corrupted objects will be logged as warnings.
     pub skip_corrupted: bool,
+
+    /// Hears commits walked, and can cancel the rebuild before the
+    /// previous index is replaced.
+    pub progress: Progress,
 }
 
 /// Report from an index rebuild operation.
@@ -44,6 +58,108 @@ const PATH_TO_ID_TABLE: TableDefinition<&str, &[u8; 32]> = TableDefinition::new(
 const NAME_TO_IDS_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("name_to_ids");
 const COMMIT_INFO_TABLE: TableDefinition<&[u8; 32], &[u8]> = TableDefinition::new("commit_info");
 const ADJACENCY_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("adjacency");
+const FRECENCY_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("frecency");
+const EDGE_EVIDENCE_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("edge_evidence");
+const EDGE_MERGED_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("edge_merged");
+const BLOB_EDGES_TABLE: TableDefinition<&[u8; 32], &[u8]> = TableDefinition::new("blob_edges");
+const SUPERSEDED_TABLE: TableDefinition<&[u8], &[u8; 32]> =
+    TableDefinition::new("superseded_edges");
+const SCC_OF_TABLE: TableDefinition<&[u8], u32> = TableDefinition::new("scc_of");
+const SCC_MEMBERS_TABLE: TableDefinition<u32, &[u8]> = TableDefinition::new("scc_members");
+const SCC_DAG_TABLE: TableDefinition<u32, &[u8]> = TableDefinition::new("scc_dag");
+const TAG_PATHS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("tag_paths");
+
+/// Metadata key that is 1 while the persisted SCCs cover every indexed edge.
+const SCC_CURRENT_KEY: &str = "scc_current";
+
+/// How often and how recently a path was used.
+///
+/// Accesses come from session observations and pack inclusions. Unlike the
+/// rest of the index this is not derivable from objects, so it is carried
+/// over when the index is rebuilt.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FrecencyEntry {
+    /// Number of recorded accesses.
+    pub access_count: u32,
+    /// Time of the most recent access (Unix seconds).
+    pub last_access_unix: u64,
+}
+
+impl FrecencyEntry {
+    /// Combined frequency/recency score at `now_unix`.
+    ///
+    /// The access count is weighted by how long ago the last access was,
+    /// in the same coarse buckets browsers use for URL frecency.
+    pub fn score(&self, now_unix: u64) -> u32 {
+        const DAY: u64 = 24 * 60 * 60;
+        let age = now_unix.saturating_sub(self.last_access_unix);
+        let weight = match age {
+            a if a < 4 * DAY => 100,
+            a if a < 14 * DAY => 70,
+            a if a < 31 * DAY => 50,
+            a if a < 90 * DAY => 30,
+            _ => 10,
+        };
+        self.access_count.saturating_mul(weight)
+    }
+}
+
+/// One logical edge, merging every assertion of it across tools and commits.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct MergedEdge {
+    /// Strongest confidence each tool asserted the edge with.
+    pub sources: Vec<(EvidenceTool, Confidence)>,
+    /// Time of the newest commit asserting the edge (Unix seconds).
+    pub last_asserted_unix: u64,
+}
+
+impl MergedEdge {
+    /// Combined confidence in `0.0..=1.0`.
+    ///
+    /// Tools are treated as independent witnesses: the edge is wrong only
+    /// if every tool is wrong, so agreement raises confidence above any
+    /// single source.
+    pub fn confidence(&self) -> f32 {
+        let doubt: f32 = self
+            .sources
+            .iter()
+            .map(|(_, confidence)| 1.0 - confidence_value(*confidence))
+            .product();
+        1.0 - doubt
+    }
+
+    /// Confidence decayed by the time since the edge was last asserted.
+    pub fn weight(&self, label: EdgeLabel, decay: &EdgeDecayConfig, now_unix: u64) -> f32 {
+        let age = now_unix.saturating_sub(self.last_asserted_unix);
+        self.confidence() * decay.factor(label, age)
+    }
+
+    /// Records one more assertion of the edge.
+    fn assert(&mut self, evidence: &Evidence, timestamp_unix: u64) {
+        match self
+            .sources
+            .iter_mut()
+            .find(|(tool, _)| *tool == evidence.tool)
+        {
+            Some((_, confidence)) => {
+                if confidence_value(evidence.confidence) > confidence_value(*confidence) {
+                    *confidence = evidence.confidence;
+                }
+            }
+            None => self.sources.push((evidence.tool, evidence.confidence)),
+        }
+        self.last_asserted_unix = self.last_asserted_unix.max(timestamp_unix);
+    }
+}
+
+/// Probability that an edge asserted with `confidence` is real.
+fn confidence_value(confidence: Confidence) -> f32 {
+    match confidence {
+        Confidence::High => 0.9,
+        Confidence::Medium => 0.7,
+        Confidence::Low => 0.4,
+    }
+}
 
 /// Cached commit information for fast lookup.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -114,6 +230,98 @@ fn encode_adjacency_key(node: &NodeId, direction: EdgeDirection, label: EdgeLabe
     key
 }
 
+/// Encode edge evidence key: from_kind + from_id_len + from_id + to_kind +
+/// to_id_len + to_id + label.
+fn encode_edge_key(from: &NodeId, to: &NodeId, label: EdgeLabel) -> Vec<u8> {
+    let mut key = Vec::with_capacity(2 * 3 + from.id.len() + to.id.len() + 1);
+    for node in [from, to] {
+        key.push(node.kind as u8);
+        key.extend_from_slice(&(node.id.len() as u16).to_le_bytes());
+        key.extend_from_slice(node.id.as_bytes());
+    }
+    key.push(label as u8);
+    key
+}
+
+/// Decode an adjacency key into its node, direction and label.
+///
+/// Returns `None` for keys written with an unknown kind or label.
+fn decode_adjacency_key(key: &[u8]) -> Option<(NodeId, EdgeDirection, EdgeLabel)> {
+    let (node, rest) = decode_node(key)?;
+    let direction = match rest.first()? {
+        0 => EdgeDirection::Outgoing,
+        1 => EdgeDirection::Incoming,
+        _ => return None,
+    };
+    let label = decode_label(*rest.get(1)?)?;
+    Some((node, direction, label))
+}
+
+/// Decode an edge evidence key into its endpoints and label.
+///
+/// Returns `None` for keys written with an unknown kind or label.
+fn decode_edge_key(key: &[u8]) -> Option<(NodeId, NodeId, EdgeLabel)> {
+    let (from, rest) = decode_node(key)?;
+    let (to, rest) = decode_node(rest)?;
+    let label = decode_label(*rest.first()?)?;
+    Some((from, to, label))
+}
+
+/// Decode a leading node_kind + node_id_len + node_id, returning the node
+/// and the remaining bytes.
+fn decode_node(key: &[u8]) -> Option<(NodeId, &[u8])> {
+    let (&kind, rest) = key.split_first()?;
+    let len = u16::from_le_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+    let id = std::str::from_utf8(rest.get(2..2 + len)?).ok()?;
+    let kind = match kind {
+        1 => NodeKind::File,
+        2 => NodeKind::Module,
+        3 => NodeKind::Item,
+        4 => NodeKind::Package,
+        5 => NodeKind::Target,
+        6 => NodeKind::Crate,
+        7 => NodeKind::Task,
+        8 => NodeKind::Note,
+        9 => NodeKind::Decision,
+        10 => NodeKind::Diagnostic,
+        11 => NodeKind::Term,
+        12 => NodeKind::Fact,
+        13 => NodeKind::Qa,
+        14 => NodeKind::Document,
+        15 => NodeKind::Session,
+        _ => return None,
+    };
+    let node = NodeId {
+        kind,
+        id: id.to_string(),
+    };
+    Some((node, &rest[2 + len..]))
+}
+
+fn decode_label(byte: u8) -> Option<EdgeLabel> {
+    let label = match byte {
+        1 => EdgeLabel::Contains,
+        2 => EdgeLabel::Defines,
+        3 => EdgeLabel::HasVersion,
+        4 => EdgeLabel::DeclaresModule,
+        5 => EdgeLabel::RenamedTo,
+        6 => EdgeLabel::ChildOf,
+        10 => EdgeLabel::DependsOn,
+        11 => EdgeLabel::TargetOf,
+        12 => EdgeLabel::CrateFromTarget,
+        20 => EdgeLabel::Imports,
+        21 => EdgeLabel::References,
+        22 => EdgeLabel::Calls,
+        23 => EdgeLabel::Implements,
+        24 => EdgeLabel::UsesType,
+        30 => EdgeLabel::Mentions,
+        31 => EdgeLabel::UpdatedIn,
+        32 => EdgeLabel::DerivedFrom,
+        _ => return None,
+    };
+    Some(label)
+}
+
 /// Encode name index key: namespace_byte + name_utf8.
 fn encode_name_key(namespace: NameNamespace, name: &str) -> Vec<u8> {
     let mut key = Vec::with_capacity(1 + name.len());
@@ -166,55 +374,147 @@ fn populate_name_index_for_node(
 /// The index is stored in `.ctx/index/index.redb` and can be deleted and
 /// rebuilt from the object store at any time.
 pub struct Index {
+    // Declared first so the database closes before a snapshot file is removed
     db: Database,
     path: PathBuf,
+    /// Receives lookup latencies.
+    metrics: SharedMetrics,
+    /// How this handle shares the index with other processes.
+    access: Access,
+}
+
+/// Whether an [`Index`] handle is the writer or reads a snapshot.
+enum Access {
+    Writer {
+        _lock: WriterLock,
+    },
+    Snapshot {
+        _file: SnapshotFile,
+        /// The process that was writing when the snapshot was taken.
+        writer_pid: u32,
+    },
+}
+
+/// A write transaction that holds off snapshot copies until it ends.
+struct WriteTxn {
+    txn: redb::WriteTransaction,
+    _lock: std::fs::File,
+}
+
+impl WriteTxn {
+    fn commit(self) -> std::result::Result<(), redb::CommitError> {
+        let WriteTxn { txn, _lock } = self;
+        txn.commit()
+    }
+}
+
+impl std::ops::Deref for WriteTxn {
+    type Target = redb::WriteTransaction;
+
+    fn deref(&self) -> &redb::WriteTransaction {
+        &self.txn
+    }
 }
 
 impl Index {
-    /// Opens an existing index database.
+    /// Opens an existing index database as its writer.
     ///
     /// Returns `None` if the index doesn't exist.
     ///
     /// # Errors
     ///
-    /// Returns an error if the database exists but can't be opened or has a schema version mismatch.
+    /// Returns [`CtxError::IndexLocked`] if another process (or handle) is
+    /// writing the index, or another error if the database can't be opened
+    /// or has a schema version mismatch.
     pub fn open(path: impl AsRef<Path>) -> Result<Option<Self>> {
         let path = path.as_ref().to_path_buf();
         if !path.exists() {
             return Ok(None);
         }
 
-        let db = Database::open(&path).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to open index: {}", e),
-            ))
-        })?;
+        let writer = WriterLock::acquire(&path)?;
+        let db = open_database(&path, &path)?;
+        Ok(Some(Self {
+            db,
+            path,
+            metrics: crate::metrics::noop(),
+            access: Access::Writer { _lock: writer },
+        }))
+    }
 
-        // Verify schema version
-        let read_txn = db.begin_read().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to begin read transaction: {}", e),
-            ))
-        })?;
+    /// Opens a read-only snapshot of an existing index database, which
+    /// doesn't block its writer and isn't updated by it.
+    ///
+    /// Returns `None` if the index doesn't exist. Writes to the snapshot
+    /// fail with [`CtxError::IndexLocked`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database can't be copied or opened, or has a
+    /// schema version mismatch.
+    pub fn open_snapshot(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(None);
+        }
 
-        if let Ok(table) = read_txn.open_table(METADATA_TABLE) {
-            if let Some(version) = table.get("version").ok().flatten() {
-                let version_val = version.value();
-                if version_val != INDEX_SCHEMA_VERSION {
-                    return Err(CtxError::Io(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        format!(
-                            "Index schema version mismatch: found {}, expected {}",
-                            version_val, INDEX_SCHEMA_VERSION
-                        ),
-                    )));
-                }
+        let writer_pid = index_lock::writer_pid(&path).unwrap_or(0);
+        let file = SnapshotFile::copy(&path)?;
+        let db = open_database(file.path(), &path)?;
+        Ok(Some(Self {
+            db,
+            path,
+            metrics: crate::metrics::noop(),
+            access: Access::Snapshot {
+                _file: file,
+                writer_pid,
+            },
+        }))
+    }
+
+    /// Copies the index database at `path` to `dest` and opens the copy as
+    /// its writer, so changes never reach the original.
+    ///
+    /// Returns `None` if the index doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database can't be copied or opened, or has a
+    /// schema version mismatch.
+    pub(crate) fn open_copy(path: &Path, dest: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        {
+            let _lock = index_lock::lock_commits(path, false)?;
+            std::fs::copy(path, dest)?;
+        }
+        Self::open(dest)
+    }
+
+    /// Opens an existing index database as its writer or, if another
+    /// process is writing it, as a read-only snapshot.
+    ///
+    /// Returns `None` if the index doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database can't be opened or has a schema
+    /// version mismatch.
+    pub fn open_shared(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        match Self::open(&path) {
+            Err(CtxError::IndexLocked { pid }) => {
+                tracing::debug!(pid, "Index is being written, reading a snapshot");
+                Self::open_snapshot(&path)
             }
+            opened => opened,
         }
+    }
 
-        Ok(Some(Self { db, path }))
+    /// Returns true if this is a read-only snapshot from
+    /// [`Index::open_snapshot`].
+    pub fn is_snapshot(&self) -> bool {
+        matches!(self.access, Access::Snapshot { .. })
     }
 
     /// Creates a new index database.
@@ -232,49 +532,41 @@ impl Index {
             std::fs::create_dir_all(parent)?;
         }
 
+        // Never remove the database under another writer
+        let writer = WriterLock::acquire(&path)?;
+
         // Remove existing database
         if path.exists() {
             std::fs::remove_file(&path)?;
         }
 
-        let db = Database::create(&path).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to create index: {}", e),
-            ))
-        })?;
+        let db = Database::create(&path)
+            .map_err(|e| CtxError::IndexError(format!("Failed to create index: {}", e)))?;
+
+        let index = Self {
+            db,
+            path,
+            metrics: crate::metrics::noop(),
+            access: Access::Writer { _lock: writer },
+        };
 
         // Initialize schema version
-        let write_txn = db.begin_write().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to begin write transaction: {}", e),
-            ))
-        })?;
+        let write_txn = index.begin_write()?;
 
         {
             let mut table = write_txn.open_table(METADATA_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open metadata table: {}", e),
-                ))
-            })?;
-            table.insert("version", INDEX_SCHEMA_VERSION).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to insert version: {}", e),
-                ))
+                CtxError::IndexError(format!("Failed to open metadata table: {}", e))
             })?;
+            table
+                .insert("version", INDEX_SCHEMA_VERSION)
+                .map_err(|e| CtxError::IndexError(format!("Failed to insert version: {}", e)))?;
         }
 
-        write_txn.commit().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to commit: {}", e),
-            ))
-        })?;
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit: {}", e)))?;
 
-        Ok(Self { db, path })
+        Ok(index)
     }
 
     /// Returns the path to the index database.
@@ -282,11 +574,25 @@ impl Index {
         &self.path
     }
 
+    /// Reports lookup latencies to `metrics`.
+    pub(crate) fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics;
+    }
+
+    fn query_timer(&self) -> Timer<'_> {
+        Timer::start(&*self.metrics, HistogramMetric::IndexQuerySeconds)
+    }
+
     /// Adds or updates a file path → blob mapping in the index.
     ///
     /// This is used to manually index files that were analyzed but not yet
     /// part of the commit tree.
     ///
+    /// If the path previously mapped to a different blob, edges whose
+    /// evidence only cites blobs that are no longer the current content of
+    /// any path are superseded: they leave the adjacency index until they
+    /// are asserted again. Returns the number of edges superseded.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -299,72 +605,112 @@ impl Index {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn index_file_path(&mut self, path: &str, blob_id: ObjectId) -> Result<()> {
+    pub fn index_file_path(&mut self, path: &str, blob_id: ObjectId) -> Result<usize> {
         let write_txn = self.begin_write()?;
 
-        {
-            let mut table = write_txn.open_table(PATH_TO_ID_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open path table: {}", e),
-                ))
-            })?;
+        let previous = {
+            let mut table = write_txn
+                .open_table(PATH_TO_ID_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
 
-            table.insert(path, blob_id.as_bytes()).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to insert path: {}", e),
-                ))
-            })?;
-        }
+            let previous = table
+                .insert(path, blob_id.as_bytes())
+                .map_err(|e| CtxError::IndexError(format!("Failed to insert path: {}", e)))?;
+            previous.map(|v| ObjectId::from_bytes(*v.value()))
+        };
 
-        write_txn.commit().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to commit transaction: {}", e),
-            ))
-        })?;
+        let replaced: Vec<(ObjectId, ObjectId)> = previous
+            .filter(|old| *old != blob_id)
+            .map(|old| (old, blob_id))
+            .into_iter()
+            .collect();
+        let superseded = supersede_stale_edges(&write_txn, &replaced)?;
 
-        Ok(())
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(superseded)
     }
 
     /// Batch index multiple file paths in a single transaction.
     /// This is more efficient than calling index_file_path() repeatedly.
-    pub fn index_file_paths(&mut self, paths: &[(String, ObjectId)]) -> Result<()> {
+    ///
+    /// Returns the number of edges superseded, as for
+    /// [`Index::index_file_path`].
+    pub fn index_file_paths(&mut self, paths: &[(String, ObjectId)]) -> Result<usize> {
         if paths.is_empty() {
-            return Ok(());
+            return Ok(0);
         }
 
         let write_txn = self.begin_write()?;
 
+        let mut replaced = Vec::new();
         {
-            let mut table = write_txn.open_table(PATH_TO_ID_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open path table: {}", e),
-                ))
-            })?;
+            let mut table = write_txn
+                .open_table(PATH_TO_ID_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
 
             for (path, blob_id) in paths {
-                table
+                let previous = table
                     .insert(path.as_str(), blob_id.as_bytes())
                     .map_err(|e| {
-                        CtxError::Io(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("Failed to insert path {}: {}", path, e),
-                        ))
-                    })?;
+                        CtxError::IndexError(format!("Failed to insert path {}: {}", path, e))
+                    })?
+                    .map(|v| ObjectId::from_bytes(*v.value()));
+                if let Some(old) = previous.filter(|old| old != blob_id) {
+                    replaced.push((old, *blob_id));
+                }
             }
         }
 
-        write_txn.commit().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to commit transaction: {}", e),
-            ))
-        })?;
+        let superseded = supersede_stale_edges(&write_txn, &replaced)?;
 
-        Ok(())
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(superseded)
+    }
+
+    /// Records that the file at `from` moved to `to`.
+    ///
+    /// Drops the `from` mapping so [`Index::lookup_path`] follows the move,
+    /// and supersedes edges derived only from the old content as
+    /// [`Index::index_file_path`] does. Returns the number of edges
+    /// superseded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be updated.
+    pub fn rename_file_path(&mut self, from: &str, to: &str) -> Result<usize> {
+        let write_txn = self.begin_write()?;
+
+        let replaced = {
+            let mut table = write_txn
+                .open_table(PATH_TO_ID_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
+
+            let old = table
+                .remove(from)
+                .map_err(|e| CtxError::IndexError(format!("Failed to remove path: {}", e)))?
+                .map(|v| ObjectId::from_bytes(*v.value()));
+            let new = table
+                .get(to)
+                .map_err(|e| CtxError::IndexError(format!("Failed to get path: {}", e)))?
+                .map(|v| ObjectId::from_bytes(*v.value()));
+            match (old, new) {
+                (Some(old), Some(new)) if old != new => vec![(old, new)],
+                _ => Vec::new(),
+            }
+        };
+        let superseded = supersede_stale_edges(&write_txn, &replaced)?;
+
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(superseded)
     }
 
     /// Incrementally add edges from a single commit to the index.
@@ -402,10 +748,7 @@ impl Index {
         let commit_info = CommitInfo::from_commit(commit);
         {
             let mut table = write_txn.open_table(COMMIT_INFO_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open commit info table: {}", e),
-                ))
+                CtxError::IndexError(format!("Failed to open commit info table: {}", e))
             })?;
 
             let serialized = postcard::to_allocvec(&commit_info)
@@ -414,11 +757,28 @@ impl Index {
             table
                 .insert(commit_id.as_bytes(), serialized.as_slice())
                 .map_err(|e| {
-                    CtxError::Io(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Failed to insert commit info: {}", e),
-                    ))
+                    CtxError::IndexError(format!("Failed to insert commit info: {}", e))
+                })?;
+        }
+
+        merge_tag_paths(&write_txn, &commit.tags)?;
+
+        // The persisted SCCs survive edges that stay inside one SCC or run
+        // parallel to an existing condensation edge; anything else makes
+        // them stale until the next `ensure_scc`
+        {
+            let mut metadata = write_txn.open_table(METADATA_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open metadata table: {}", e))
+            })?;
+            let current = metadata
+                .get(SCC_CURRENT_KEY)
+                .map_err(|e| CtxError::IndexError(format!("Failed to get metadata: {}", e)))?
+                .is_some_and(|v| v.value() == 1);
+            if current && !edges_keep_scc(&write_txn, edge_batches)? {
+                metadata.insert(SCC_CURRENT_KEY, 0).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to insert metadata: {}", e))
                 })?;
+            }
         }
 
         // Process all edge batches from this commit
@@ -426,17 +786,28 @@ impl Index {
             // Index adjacency and names
             {
                 let mut adjacency_table = write_txn.open_table(ADJACENCY_TABLE).map_err(|e| {
-                    CtxError::Io(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Failed to open adjacency table: {}", e),
-                    ))
+                    CtxError::IndexError(format!("Failed to open adjacency table: {}", e))
                 })?;
 
                 let mut name_table = write_txn.open_table(NAME_TO_IDS_TABLE).map_err(|e| {
-                    CtxError::Io(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Failed to open name table: {}", e),
-                    ))
+                    CtxError::IndexError(format!("Failed to open name table: {}", e))
+                })?;
+
+                let mut evidence_table =
+                    write_txn.open_table(EDGE_EVIDENCE_TABLE).map_err(|e| {
+                        CtxError::IndexError(format!("Failed to open edge evidence table: {}", e))
+                    })?;
+
+                let mut merged_table = write_txn.open_table(EDGE_MERGED_TABLE).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to open merged edge table: {}", e))
+                })?;
+
+                let mut blob_edges_table = write_txn.open_table(BLOB_EDGES_TABLE).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to open blob edges table: {}", e))
+                })?;
+
+                let mut superseded_table = write_txn.open_table(SUPERSEDED_TABLE).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to open superseded edges table: {}", e))
                 })?;
 
                 for edge in &batch.edges {
@@ -455,10 +826,7 @@ impl Index {
                     adjacency_table
                         .insert(out_key.as_slice(), serialized.as_slice())
                         .map_err(|e| {
-                            CtxError::Io(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                format!("Failed to insert adjacency: {}", e),
-                            ))
+                            CtxError::IndexError(format!("Failed to insert adjacency: {}", e))
                         })?;
 
                     // Add incoming adjacency
@@ -476,10 +844,74 @@ impl Index {
                     adjacency_table
                         .insert(in_key.as_slice(), serialized.as_slice())
                         .map_err(|e| {
-                            CtxError::Io(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                format!("Failed to insert adjacency: {}", e),
-                            ))
+                            CtxError::IndexError(format!("Failed to insert adjacency: {}", e))
+                        })?;
+
+                    // Add evidence
+                    let evidence_key = encode_edge_key(&edge.from, &edge.to, edge.label);
+                    let mut evidence: Vec<Evidence> = evidence_table
+                        .get(evidence_key.as_slice())
+                        .ok()
+                        .flatten()
+                        .and_then(|v| postcard::from_bytes(v.value()).ok())
+                        .unwrap_or_default();
+                    if !evidence.contains(&edge.evidence) {
+                        evidence.push(edge.evidence.clone());
+                        let serialized = postcard::to_allocvec(&evidence)
+                            .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                        evidence_table
+                            .insert(evidence_key.as_slice(), serialized.as_slice())
+                            .map_err(|e| {
+                                CtxError::IndexError(format!(
+                                    "Failed to insert edge evidence: {}",
+                                    e
+                                ))
+                            })?;
+                    }
+
+                    // Version the edge by the blob it was derived from
+                    if let Some(blob_id) = edge.evidence.blob_id {
+                        let mut keys: BTreeSet<Vec<u8>> = blob_edges_table
+                            .get(blob_id.as_bytes())
+                            .ok()
+                            .flatten()
+                            .and_then(|v| postcard::from_bytes(v.value()).ok())
+                            .unwrap_or_default();
+                        if keys.insert(evidence_key.clone()) {
+                            let serialized = postcard::to_allocvec(&keys)
+                                .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                            blob_edges_table
+                                .insert(blob_id.as_bytes(), serialized.as_slice())
+                                .map_err(|e| {
+                                    CtxError::IndexError(format!(
+                                        "Failed to insert blob edges: {}",
+                                        e
+                                    ))
+                                })?;
+                        }
+                    }
+
+                    // A fresh assertion revives a superseded edge
+                    superseded_table
+                        .remove(evidence_key.as_slice())
+                        .map_err(|e| {
+                            CtxError::IndexError(format!("Failed to remove superseded edge: {}", e))
+                        })?;
+
+                    // Merge into the logical edge
+                    let mut merged: MergedEdge = merged_table
+                        .get(evidence_key.as_slice())
+                        .ok()
+                        .flatten()
+                        .and_then(|v| postcard::from_bytes(v.value()).ok())
+                        .unwrap_or_default();
+                    merged.assert(&edge.evidence, commit.timestamp_unix);
+                    let serialized = postcard::to_allocvec(&merged)
+                        .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                    merged_table
+                        .insert(evidence_key.as_slice(), serialized.as_slice())
+                        .map_err(|e| {
+                            CtxError::IndexError(format!("Failed to insert merged edge: {}", e))
                         })?;
 
                     // Add name index for from node
@@ -506,10 +938,7 @@ impl Index {
                         name_table
                             .insert(key.as_slice(), serialized.as_slice())
                             .map_err(|e| {
-                                CtxError::Io(std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    format!("Failed to insert name index: {}", e),
-                                ))
+                                CtxError::IndexError(format!("Failed to insert name index: {}", e))
                             })?;
                     }
 
@@ -537,28 +966,25 @@ impl Index {
                         name_table
                             .insert(key.as_slice(), serialized.as_slice())
                             .map_err(|e| {
-                                CtxError::Io(std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    format!("Failed to insert name index: {}", e),
-                                ))
+                                CtxError::IndexError(format!("Failed to insert name index: {}", e))
                             })?;
                     }
                 }
             }
         }
 
-        write_txn.commit().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to commit transaction: {}", e),
-            ))
-        })?;
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
 
         Ok(())
     }
 
     /// Look up a path to get its ObjectId.
     ///
+    /// A path that was renamed away resolves through its `RenamedTo` edges
+    /// to the file's current content.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -579,20 +1005,64 @@ impl Index {
     ///
     /// Returns an error if the index can't be queried.
     pub fn lookup_path(&self, path: &str) -> Result<Option<ObjectId>> {
+        let _timer = self.query_timer();
+        let mut path = path.to_string();
+        let mut seen = HashSet::new();
+        loop {
+            if let Some(id) = self.lookup_exact_path(&path)? {
+                return Ok(Some(id));
+            }
+            // Guard against a file moved back and forth
+            if !seen.insert(path.clone()) {
+                return Ok(None);
+            }
+            let node = NodeId {
+                kind: NodeKind::File,
+                id: path,
+            };
+            match self.get_edges_from(&node, EdgeLabel::RenamedTo)?.pop() {
+                Some(next) => path = next.id,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Every indexed path with the blob it currently maps to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be queried.
+    pub fn indexed_paths(&self) -> Result<BTreeMap<String, ObjectId>> {
         let read_txn = self.begin_read()?;
-        let table = read_txn.open_table(PATH_TO_ID_TABLE).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to open path table: {}", e),
-            ))
-        })?;
+        let table = read_txn
+            .open_table(PATH_TO_ID_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
 
-        match table.get(path).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to get path: {}", e),
-            ))
-        })? {
+        let mut paths = BTreeMap::new();
+        for item in table
+            .iter()
+            .map_err(|e| CtxError::IndexError(format!("Failed to iterate paths: {}", e)))?
+        {
+            let (key, value) =
+                item.map_err(|e| CtxError::IndexError(format!("Failed to read path: {}", e)))?;
+            paths.insert(
+                key.value().to_string(),
+                ObjectId::from_bytes(*value.value()),
+            );
+        }
+        Ok(paths)
+    }
+
+    fn lookup_exact_path(&self, path: &str) -> Result<Option<ObjectId>> {
+        let read_txn = self.begin_read()?;
+        let table = read_txn
+            .open_table(PATH_TO_ID_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
+
+        match table
+            .get(path)
+            .map_err(|e| CtxError::IndexError(format!("Failed to get path: {}", e)))?
+        {
             Some(bytes) => Ok(Some(ObjectId::from_bytes(*bytes.value()))),
             None => Ok(None),
         }
@@ -624,21 +1094,17 @@ impl Index {
     ///
     /// Returns an error if the index can't be queried.
     pub fn lookup_name(&self, namespace: NameNamespace, name: &str) -> Result<Vec<ObjectId>> {
+        let _timer = self.query_timer();
         let key = encode_name_key(namespace, name);
         let read_txn = self.begin_read()?;
-        let table = read_txn.open_table(NAME_TO_IDS_TABLE).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to open name table: {}", e),
-            ))
-        })?;
+        let table = read_txn
+            .open_table(NAME_TO_IDS_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open name table: {}", e)))?;
 
-        match table.get(key.as_slice()).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to get name: {}", e),
-            ))
-        })? {
+        match table
+            .get(key.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get name: {}", e)))?
+        {
             Some(bytes) => {
                 let ids: Vec<[u8; 32]> = postcard::from_bytes(bytes.value())
                     .map_err(|e| CtxError::Deserialization(e.to_string()))?;
@@ -654,20 +1120,16 @@ impl Index {
     ///
     /// Returns an error if the index can't be queried.
     pub fn get_commit_info(&self, commit_id: ObjectId) -> Result<Option<CommitInfo>> {
+        let _timer = self.query_timer();
         let read_txn = self.begin_read()?;
-        let table = read_txn.open_table(COMMIT_INFO_TABLE).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to open commit table: {}", e),
-            ))
-        })?;
+        let table = read_txn
+            .open_table(COMMIT_INFO_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open commit table: {}", e)))?;
 
-        match table.get(commit_id.as_bytes()).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to get commit: {}", e),
-            ))
-        })? {
+        match table
+            .get(commit_id.as_bytes())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get commit: {}", e)))?
+        {
             Some(bytes) => {
                 let info: CommitInfo = postcard::from_bytes(bytes.value())
                     .map_err(|e| CtxError::Deserialization(e.to_string()))?;
@@ -688,21 +1150,17 @@ impl Index {
         direction: EdgeDirection,
         label: EdgeLabel,
     ) -> Result<Vec<NodeId>> {
+        let _timer = self.query_timer();
         let key = encode_adjacency_key(node, direction, label);
         let read_txn = self.begin_read()?;
-        let table = read_txn.open_table(ADJACENCY_TABLE).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to open adjacency table: {}", e),
-            ))
-        })?;
+        let table = read_txn
+            .open_table(ADJACENCY_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open adjacency table: {}", e)))?;
 
-        match table.get(key.as_slice()).map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to get adjacency: {}", e),
-            ))
-        })? {
+        match table
+            .get(key.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get adjacency: {}", e)))?
+        {
             Some(bytes) => {
                 let nodes: Vec<NodeId> = postcard::from_bytes(bytes.value())
                     .map_err(|e| CtxError::Deserialization(e.to_string()))?;
@@ -730,55 +1188,440 @@ impl Index {
         self.get_adjacent(node, EdgeDirection::Incoming, label)
     }
 
-    /// Rebuild the entire index from the object store.
+    /// Get the evidence for every recorded `from -label-> to` edge, across
+    /// all indexed edge batches.
     ///
-    /// This walks the commit DAG from HEAD and indexes:
-    /// - File paths from the HEAD tree
-    /// - Edges from all edge batches
-    /// - Commit metadata
+    /// Returns an empty list if the edge was never recorded.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index can't be created or rebuilt.
-    pub fn rebuild_from_objects(
-        path: impl AsRef<Path>,
-        object_store: &ObjectStore,
-        head_id: ObjectId,
-    ) -> Result<Self> {
-        let (index, _report) = Self::rebuild_from_objects_with_config(
-            path,
-            object_store,
-            head_id,
-            RebuildConfig::default(),
-        )?;
-        Ok(index)
+    /// Returns an error if the index can't be queried.
+    pub fn get_edge_details(
+        &self,
+        from: &NodeId,
+        to: &NodeId,
+        label: EdgeLabel,
+    ) -> Result<Vec<Evidence>> {
+        let _timer = self.query_timer();
+        let key = encode_edge_key(from, to, label);
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(EDGE_EVIDENCE_TABLE) {
+            Ok(table) => table,
+            // Index predates evidence tracking
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open edge evidence table: {}",
+                    e
+                )))
+            }
+        };
+
+        match table
+            .get(key.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get edge evidence: {}", e)))?
+        {
+            Some(bytes) => postcard::from_bytes(bytes.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string())),
+            None => Ok(Vec::new()),
+        }
     }
 
-    /// Rebuilds the index from the object store with configuration.
+    /// Get the logical `from -label-> to` edge merged from all its
+    /// assertions, or `None` if it was never recorded.
     ///
-    /// Like `rebuild_from_objects` but allows configuring behavior for corrupted objects
-    /// and returns a detailed report.
-    pub fn rebuild_from_objects_with_config(
-        path: impl AsRef<Path>,
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be queried.
+    pub fn get_merged_edge(
+        &self,
+        from: &NodeId,
+        to: &NodeId,
+        label: EdgeLabel,
+    ) -> Result<Option<MergedEdge>> {
+        let key = encode_edge_key(from, to, label);
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(EDGE_MERGED_TABLE) {
+            Ok(table) => table,
+            // Index predates edge merging
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open merged edge table: {}",
+                    e
+                )))
+            }
+        };
+
+        match table
+            .get(key.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get merged edge: {}", e)))?
+        {
+            Some(bytes) => postcard::from_bytes(bytes.value())
+                .map(Some)
+                .map_err(|e| CtxError::Deserialization(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the blob that superseded the `from -label-> to` edge, or `None`
+    /// if the edge is live or was never recorded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be queried.
+    pub fn get_superseded_by(
+        &self,
+        from: &NodeId,
+        to: &NodeId,
+        label: EdgeLabel,
+    ) -> Result<Option<ObjectId>> {
+        let key = encode_edge_key(from, to, label);
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(SUPERSEDED_TABLE) {
+            Ok(table) => table,
+            // Index predates edge invalidation
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open superseded edges table: {}",
+                    e
+                )))
+            }
+        };
+
+        let blob = table
+            .get(key.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get superseded edge: {}", e)))?;
+        Ok(blob.map(|v| ObjectId::from_bytes(*v.value())))
+    }
+
+    /// Every superseded edge key with the blob that superseded it.
+    fn superseded_edges(&self) -> Result<BTreeMap<Vec<u8>, ObjectId>> {
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(SUPERSEDED_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeMap::new()),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open superseded edges table: {}",
+                    e
+                )))
+            }
+        };
+
+        let mut edges = BTreeMap::new();
+        for item in table.iter().map_err(|e| {
+            CtxError::IndexError(format!("Failed to iterate superseded edges: {}", e))
+        })? {
+            let (key, value) = item.map_err(|e| {
+                CtxError::IndexError(format!("Failed to read superseded edge: {}", e))
+            })?;
+            edges.insert(key.value().to_vec(), ObjectId::from_bytes(*value.value()));
+        }
+        Ok(edges)
+    }
+
+    /// Returns whether the persisted SCCs cover every indexed edge.
+    pub fn scc_is_current(&self) -> Result<bool> {
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(false),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open metadata table: {}",
+                    e
+                )))
+            }
+        };
+        let current = table
+            .get(SCC_CURRENT_KEY)
+            .map_err(|e| CtxError::IndexError(format!("Failed to get metadata: {}", e)))?
+            .is_some_and(|v| v.value() == 1);
+        Ok(current)
+    }
+
+    /// Loads the persisted SCCs and their condensation DAG.
+    ///
+    /// Returns `None` if they were never computed or new edges have made
+    /// them stale; [`Index::ensure_scc`] recomputes them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be queried.
+    pub fn scc_view(&self) -> Result<Option<SccView>> {
+        if !self.scc_is_current()? {
+            return Ok(None);
+        }
+
+        let read_txn = self.begin_read()?;
+        let members_table = read_txn.open_table(SCC_MEMBERS_TABLE).map_err(|e| {
+            CtxError::IndexError(format!("Failed to open SCC members table: {}", e))
+        })?;
+        let mut members = Vec::new();
+        for item in members_table
+            .iter()
+            .map_err(|e| CtxError::IndexError(format!("Failed to iterate SCC members: {}", e)))?
+        {
+            let (_, value) = item
+                .map_err(|e| CtxError::IndexError(format!("Failed to read SCC members: {}", e)))?;
+            let nodes: Vec<NodeId> = postcard::from_bytes(value.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?;
+            members.push(nodes);
+        }
+
+        let dag_table = read_txn
+            .open_table(SCC_DAG_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open SCC DAG table: {}", e)))?;
+        let mut dag = BTreeMap::new();
+        for item in dag_table
+            .iter()
+            .map_err(|e| CtxError::IndexError(format!("Failed to iterate SCC DAG: {}", e)))?
+        {
+            let (key, value) =
+                item.map_err(|e| CtxError::IndexError(format!("Failed to read SCC DAG: {}", e)))?;
+            let targets: Vec<u32> = postcard::from_bytes(value.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?;
+            dag.insert(SccId(key.value()), targets.into_iter().map(SccId).collect());
+        }
+
+        Ok(Some(SccView::from_parts(members, dag)))
+    }
+
+    /// Returns the persisted SCCs, recomputing them from the adjacency
+    /// index first if they are stale.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be read or written.
+    pub fn ensure_scc(&self) -> Result<SccView> {
+        if let Some(view) = self.scc_view()? {
+            return Ok(view);
+        }
+        let view = compute_scc(&self.adjacency_list()?);
+        self.write_scc(&view)?;
+        Ok(view)
+    }
+
+    /// Loads every indexed edge into memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be queried.
+    pub fn adjacency_list(&self) -> Result<AdjacencyList> {
+        let read_txn = self.begin_read()?;
+        let table = read_txn
+            .open_table(ADJACENCY_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open adjacency table: {}", e)))?;
+
+        let mut graph = AdjacencyList::new();
+        for item in table
+            .iter()
+            .map_err(|e| CtxError::IndexError(format!("Failed to iterate adjacency: {}", e)))?
+        {
+            let (key, value) =
+                item.map_err(|e| CtxError::IndexError(format!("Failed to read adjacency: {}", e)))?;
+            // Incoming entries mirror outgoing ones
+            let Some((from, EdgeDirection::Outgoing, label)) = decode_adjacency_key(key.value())
+            else {
+                continue;
+            };
+            let targets: Vec<NodeId> = postcard::from_bytes(value.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?;
+            for to in targets {
+                graph.add_edge(from.clone(), label, to);
+            }
+        }
+        Ok(graph)
+    }
+
+    fn write_evidence(
+        &self,
+        evidence: &BTreeMap<Vec<u8>, Vec<Evidence>>,
+        merged: &BTreeMap<Vec<u8>, MergedEdge>,
+        superseded: &BTreeMap<Vec<u8>, ObjectId>,
+    ) -> Result<()> {
+        let write_txn = self.begin_write()?;
+        {
+            let mut table = write_txn.open_table(EDGE_EVIDENCE_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open edge evidence table: {}", e))
+            })?;
+            for (key, records) in evidence {
+                let value = postcard::to_allocvec(records)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                table
+                    .insert(key.as_slice(), value.as_slice())
+                    .map_err(|e| {
+                        CtxError::IndexError(format!("Failed to insert edge evidence: {}", e))
+                    })?;
+            }
+
+            let mut table = write_txn.open_table(EDGE_MERGED_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open merged edge table: {}", e))
+            })?;
+            for (key, edge) in merged {
+                let value = postcard::to_allocvec(edge)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                table
+                    .insert(key.as_slice(), value.as_slice())
+                    .map_err(|e| {
+                        CtxError::IndexError(format!("Failed to insert merged edge: {}", e))
+                    })?;
+            }
+
+            let mut blob_edges: BTreeMap<ObjectId, BTreeSet<&Vec<u8>>> = BTreeMap::new();
+            for (key, records) in evidence {
+                for blob_id in records.iter().filter_map(|ev| ev.blob_id) {
+                    blob_edges.entry(blob_id).or_default().insert(key);
+                }
+            }
+            let mut table = write_txn.open_table(BLOB_EDGES_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open blob edges table: {}", e))
+            })?;
+            for (blob_id, keys) in &blob_edges {
+                let value = postcard::to_allocvec(keys)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                table
+                    .insert(blob_id.as_bytes(), value.as_slice())
+                    .map_err(|e| {
+                        CtxError::IndexError(format!("Failed to insert blob edges: {}", e))
+                    })?;
+            }
+
+            let mut table = write_txn.open_table(SUPERSEDED_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open superseded edges table: {}", e))
+            })?;
+            for (key, blob_id) in superseded {
+                table
+                    .insert(key.as_slice(), blob_id.as_bytes())
+                    .map_err(|e| {
+                        CtxError::IndexError(format!("Failed to insert superseded edge: {}", e))
+                    })?;
+            }
+        }
+
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Replaces the persisted SCCs with `view` and marks them current.
+    fn write_scc(&self, view: &SccView) -> Result<()> {
+        let write_txn = self.begin_write()?;
+        write_txn
+            .delete_table(SCC_OF_TABLE)
+            .and_then(|_| write_txn.delete_table(SCC_MEMBERS_TABLE))
+            .and_then(|_| write_txn.delete_table(SCC_DAG_TABLE))
+            .map_err(|e| CtxError::IndexError(format!("Failed to clear SCC tables: {}", e)))?;
+
+        {
+            let mut scc_of = write_txn
+                .open_table(SCC_OF_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open SCC table: {}", e)))?;
+            let mut members_table = write_txn.open_table(SCC_MEMBERS_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open SCC members table: {}", e))
+            })?;
+            let mut dag_table = write_txn.open_table(SCC_DAG_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open SCC DAG table: {}", e))
+            })?;
+
+            for i in 0..view.scc_count() as u32 {
+                let members = view.members(SccId(i));
+                for node in members {
+                    let key = postcard::to_allocvec(node)
+                        .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                    scc_of.insert(key.as_slice(), i).map_err(|e| {
+                        CtxError::IndexError(format!("Failed to insert SCC: {}", e))
+                    })?;
+                }
+                let value = postcard::to_allocvec(members)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                members_table.insert(i, value.as_slice()).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to insert SCC members: {}", e))
+                })?;
+
+                let targets: Vec<u32> = view.dependencies(SccId(i)).iter().map(|s| s.0).collect();
+                if !targets.is_empty() {
+                    let value = postcard::to_allocvec(&targets)
+                        .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                    dag_table.insert(i, value.as_slice()).map_err(|e| {
+                        CtxError::IndexError(format!("Failed to insert SCC DAG: {}", e))
+                    })?;
+                }
+            }
+
+            let mut metadata = write_txn.open_table(METADATA_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open metadata table: {}", e))
+            })?;
+            metadata
+                .insert(SCC_CURRENT_KEY, 1)
+                .map_err(|e| CtxError::IndexError(format!("Failed to insert metadata: {}", e)))?;
+        }
+
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+        Ok(())
+    }
+
+    /// Rebuild the entire index from the object store.
+    ///
+    /// This walks the commit DAG from HEAD and indexes:
+    /// - File paths from the HEAD tree
+    /// - Edges from all edge batches
+    /// - Commit metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be created or rebuilt.
+    pub fn rebuild_from_objects(
+        path: impl AsRef<Path>,
+        object_store: &ObjectStore,
+        head_id: ObjectId,
+    ) -> Result<Self> {
+        let (index, _report) = Self::rebuild_from_objects_with_config(
+            path,
+            object_store,
+            head_id,
+            RebuildConfig::default(),
+        )?;
+        Ok(index)
+    }
+
+    /// Rebuilds the index from the object store with configuration.
+    ///
+    /// Like `rebuild_from_objects` but allows configuring behavior for corrupted objects
+    /// and returns a detailed report.
+    pub fn rebuild_from_objects_with_config(
+        path: impl AsRef<Path>,
         object_store: &ObjectStore,
         head_id: ObjectId,
         config: RebuildConfig,
     ) -> Result<(Self, RebuildReport)> {
         let mut report = RebuildReport::default();
-        // First, preserve any existing file path mappings before rebuilding
+        // First, preserve any existing file path mappings and access statistics
+        // before rebuilding
+        let mut preserved_frecency = BTreeMap::new();
+        let mut preserved_superseded = BTreeMap::new();
         let preserved_paths: Vec<(String, ObjectId)> = if path.as_ref().exists() {
-            match Self::open(&path)? {
+            // An index from another schema version has nothing to preserve
+            match Self::open(&path).or_else(|e| match e {
+                CtxError::IndexCorrupted { .. } => Ok(None),
+                e => Err(e),
+            })? {
                 Some(existing_index) => {
+                    preserved_frecency = existing_index.frecency_entries().unwrap_or_default();
+                    preserved_superseded = existing_index.superseded_edges().unwrap_or_default();
                     let mut paths = Vec::new();
                     if let Ok(read_txn) = existing_index.begin_read() {
                         if let Ok(table) = read_txn.open_table(PATH_TO_ID_TABLE) {
                             for (key, value) in table
                                 .iter()
                                 .map_err(|e| {
-                                    CtxError::Io(std::io::Error::new(
-                                        std::io::ErrorKind::Other,
-                                        format!("Failed to iterate paths: {}", e),
-                                    ))
+                                    CtxError::IndexError(format!("Failed to iterate paths: {}", e))
                                 })?
                                 .flatten()
                             {
@@ -796,14 +1639,13 @@ impl Index {
             Vec::new()
         };
 
-        // Create fresh index
-        let index = Self::create(path)?;
-
         // Collect all data in memory first
         let mut path_index: BTreeMap<String, ObjectId> = BTreeMap::new();
         let mut name_index: BTreeMap<Vec<u8>, BTreeSet<ObjectId>> = BTreeMap::new();
         let mut commit_cache: BTreeMap<ObjectId, CommitInfo> = BTreeMap::new();
-        let mut adjacency: BTreeMap<Vec<u8>, BTreeSet<NodeId>> = BTreeMap::new();
+        let mut evidence: BTreeMap<Vec<u8>, Vec<Evidence>> = BTreeMap::new();
+        let mut merged: BTreeMap<Vec<u8>, MergedEdge> = BTreeMap::new();
+        let mut tag_paths: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
 
         // Walk commit DAG using BFS
         let mut queue = VecDeque::new();
@@ -814,6 +1656,7 @@ impl Index {
             if !seen_commits.insert(commit_id) {
                 continue;
             }
+            config.progress.tick("walk", seen_commits.len() as u64, 0)?;
 
             // Try to load the commit
             let commit: Commit = match object_store.get_typed(commit_id) {
@@ -837,6 +1680,12 @@ impl Index {
 
             // Cache commit info
             commit_cache.insert(commit_id, CommitInfo::from_commit(&commit));
+            for (tag, paths) in &commit.tags {
+                tag_paths
+                    .entry(tag.clone())
+                    .or_default()
+                    .extend(paths.iter().cloned());
+            }
 
             // Index tree paths (only for HEAD to avoid stale paths)
             if commit_id == head_id {
@@ -881,32 +1730,25 @@ impl Index {
                 report.edge_batches_processed += 1;
 
                 for edge in &batch.edges {
-                    // Build adjacency: outgoing
-                    let out_key =
-                        encode_adjacency_key(&edge.from, EdgeDirection::Outgoing, edge.label);
-                    adjacency
-                        .entry(out_key)
-                        .or_default()
-                        .insert(edge.to.clone());
-
-                    // Build adjacency: incoming
-                    let in_key =
-                        encode_adjacency_key(&edge.to, EdgeDirection::Incoming, edge.label);
-                    adjacency
-                        .entry(in_key)
-                        .or_default()
-                        .insert(edge.from.clone());
-
                     // Build name index for both from and to nodes
                     populate_name_index_for_node(&edge.from, &edge.evidence, &mut name_index);
                     populate_name_index_for_node(&edge.to, &edge.evidence, &mut name_index);
+
+                    // Collect evidence across batches and merge it per edge
+                    let edge_key = encode_edge_key(&edge.from, &edge.to, edge.label);
+                    merged
+                        .entry(edge_key.clone())
+                        .or_default()
+                        .assert(&edge.evidence, commit.timestamp_unix);
+                    let records = evidence.entry(edge_key).or_default();
+                    if !records.contains(&edge.evidence) {
+                        records.push(edge.evidence.clone());
+                    }
                 }
             }
 
             // Queue parent commits
-            for parent_id in &commit.parents {
-                queue.push_back(*parent_id);
-            }
+            queue.extend(object_store.parents(&commit));
         }
 
         // Add preserved file path mappings back into the index
@@ -917,8 +1759,49 @@ impl Index {
         // Record paths indexed
         report.paths_indexed = path_index.len();
 
+        // Superseding is not derivable from objects, so carry it over for
+        // edges that still lack evidence from current content
+        let current: HashSet<ObjectId> = path_index.values().copied().collect();
+        let superseded: BTreeMap<Vec<u8>, ObjectId> = preserved_superseded
+            .into_iter()
+            .filter(|(key, _)| {
+                evidence
+                    .get(key)
+                    .is_some_and(|records| !is_backed(records, &current))
+            })
+            .collect();
+
+        // Build adjacency from the live edges
+        let mut adjacency: BTreeMap<Vec<u8>, BTreeSet<NodeId>> = BTreeMap::new();
+        let mut graph = AdjacencyList::new();
+        for key in evidence.keys() {
+            if superseded.contains_key(key) {
+                continue;
+            }
+            let Some((from, to, label)) = decode_edge_key(key) else {
+                continue;
+            };
+            adjacency
+                .entry(encode_adjacency_key(&from, EdgeDirection::Outgoing, label))
+                .or_default()
+                .insert(to.clone());
+            adjacency
+                .entry(encode_adjacency_key(&to, EdgeDirection::Incoming, label))
+                .or_default()
+                .insert(from.clone());
+            graph.add_edge(from, label, to);
+        }
+
+        // Replace the previous index only once everything is collected
+        config.progress.check()?;
+        let index = Self::create(path)?;
+
         // Write all collected data in a single transaction
         index.write_batch(&path_index, &name_index, &commit_cache, &adjacency)?;
+        index.write_frecency(&preserved_frecency)?;
+        index.write_tag_paths(&tag_paths)?;
+        index.write_evidence(&evidence, &merged, &superseded)?;
+        index.write_scc(&compute_scc(&graph))?;
 
         Ok((index, report))
     }
@@ -931,83 +1814,53 @@ impl Index {
         commits: &BTreeMap<ObjectId, CommitInfo>,
         adjacency: &BTreeMap<Vec<u8>, BTreeSet<NodeId>>,
     ) -> Result<()> {
-        let write_txn = self.db.begin_write().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to begin write transaction: {}", e),
-            ))
-        })?;
+        let write_txn = self.begin_write()?;
 
         // Write path index
         {
-            let mut table = write_txn.open_table(PATH_TO_ID_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open path table: {}", e),
-                ))
-            })?;
+            let mut table = write_txn
+                .open_table(PATH_TO_ID_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
             for (path, id) in paths {
-                table.insert(path.as_str(), id.as_bytes()).map_err(|e| {
-                    CtxError::Io(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Failed to insert path: {}", e),
-                    ))
-                })?;
+                table
+                    .insert(path.as_str(), id.as_bytes())
+                    .map_err(|e| CtxError::IndexError(format!("Failed to insert path: {}", e)))?;
             }
         }
 
         // Write name index
         {
-            let mut table = write_txn.open_table(NAME_TO_IDS_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open name table: {}", e),
-                ))
-            })?;
+            let mut table = write_txn
+                .open_table(NAME_TO_IDS_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open name table: {}", e)))?;
             for (key, ids) in names {
                 let ids_bytes: Vec<[u8; 32]> = ids.iter().map(|id| *id.as_bytes()).collect();
                 let value = postcard::to_allocvec(&ids_bytes)
                     .map_err(|e| CtxError::Serialization(e.to_string()))?;
                 table
                     .insert(key.as_slice(), value.as_slice())
-                    .map_err(|e| {
-                        CtxError::Io(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("Failed to insert name: {}", e),
-                        ))
-                    })?;
+                    .map_err(|e| CtxError::IndexError(format!("Failed to insert name: {}", e)))?;
             }
         }
 
         // Write commit info cache
         {
-            let mut table = write_txn.open_table(COMMIT_INFO_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open commit table: {}", e),
-                ))
-            })?;
+            let mut table = write_txn
+                .open_table(COMMIT_INFO_TABLE)
+                .map_err(|e| CtxError::IndexError(format!("Failed to open commit table: {}", e)))?;
             for (commit_id, info) in commits {
                 let value = postcard::to_allocvec(info)
                     .map_err(|e| CtxError::Serialization(e.to_string()))?;
                 table
                     .insert(commit_id.as_bytes(), value.as_slice())
-                    .map_err(|e| {
-                        CtxError::Io(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("Failed to insert commit: {}", e),
-                        ))
-                    })?;
+                    .map_err(|e| CtxError::IndexError(format!("Failed to insert commit: {}", e)))?;
             }
         }
 
         // Write adjacency index
         {
             let mut table = write_txn.open_table(ADJACENCY_TABLE).map_err(|e| {
-                CtxError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to open adjacency table: {}", e),
-                ))
+                CtxError::IndexError(format!("Failed to open adjacency table: {}", e))
             })?;
             for (key, nodes) in adjacency {
                 let nodes_vec: Vec<NodeId> = nodes.iter().cloned().collect();
@@ -1016,41 +1869,457 @@ impl Index {
                 table
                     .insert(key.as_slice(), value.as_slice())
                     .map_err(|e| {
-                        CtxError::Io(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("Failed to insert adjacency: {}", e),
-                        ))
+                        CtxError::IndexError(format!("Failed to insert adjacency: {}", e))
                     })?;
             }
         }
 
-        write_txn.commit().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to commit: {}", e),
-            ))
-        })?;
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit: {}", e)))?;
+        Ok(())
+    }
+
+    /// Records an access to each of `paths` at `now_unix`.
+    ///
+    /// Duplicate paths in one call count once.
+    pub fn record_access(&self, paths: &[String], now_unix: u64) -> Result<()> {
+        let accesses = paths
+            .iter()
+            .map(|path| {
+                let entry = FrecencyEntry {
+                    access_count: 1,
+                    last_access_unix: now_unix,
+                };
+                (path.clone(), entry)
+            })
+            .collect();
+        self.merge_access(&accesses)
+    }
+
+    /// Adds batched access statistics in one write transaction: each
+    /// entry's count is added to the path's, and the later access time is
+    /// kept.
+    pub fn merge_access(&self, accesses: &BTreeMap<String, FrecencyEntry>) -> Result<()> {
+        if accesses.is_empty() {
+            return Ok(());
+        }
+
+        let write_txn = self.begin_write()?;
+        {
+            let mut table = write_txn.open_table(FRECENCY_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open frecency table: {}", e))
+            })?;
+
+            for (path, access) in accesses {
+                let mut entry: FrecencyEntry = match table
+                    .get(path.as_str())
+                    .map_err(|e| CtxError::IndexError(format!("Failed to get frecency: {}", e)))?
+                {
+                    Some(bytes) => postcard::from_bytes(bytes.value())
+                        .map_err(|e| CtxError::Deserialization(e.to_string()))?,
+                    None => FrecencyEntry::default(),
+                };
+                entry.access_count = entry.access_count.saturating_add(access.access_count);
+                entry.last_access_unix = entry.last_access_unix.max(access.last_access_unix);
+
+                let value = postcard::to_allocvec(&entry)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                table.insert(path.as_str(), value.as_slice()).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to insert frecency: {}", e))
+                })?;
+            }
+        }
+
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Returns the access statistics of every recorded path.
+    pub fn frecency_entries(&self) -> Result<BTreeMap<String, FrecencyEntry>> {
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(FRECENCY_TABLE) {
+            Ok(table) => table,
+            // Nothing has been recorded yet
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeMap::new()),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open frecency table: {}",
+                    e
+                )))
+            }
+        };
+
+        let mut entries = BTreeMap::new();
+        for item in table
+            .iter()
+            .map_err(|e| CtxError::IndexError(format!("Failed to iterate frecency: {}", e)))?
+        {
+            let (key, value) =
+                item.map_err(|e| CtxError::IndexError(format!("Failed to read frecency: {}", e)))?;
+            let entry: FrecencyEntry = postcard::from_bytes(value.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?;
+            entries.insert(key.value().to_string(), entry);
+        }
+        Ok(entries)
+    }
+
+    /// Returns up to `limit` paths with the highest frecency score at
+    /// `now_unix`, best first. Ties are broken by path.
+    pub fn frecent_paths(&self, now_unix: u64, limit: usize) -> Result<Vec<(String, u32)>> {
+        let mut scored: Vec<(String, u32)> = self
+            .frecency_entries()?
+            .into_iter()
+            .map(|(path, entry)| (path, entry.score(now_unix)))
+            .filter(|(_, score)| *score > 0)
+            .collect();
+        scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        Ok(scored)
+    }
+
+    /// Returns the paths read or written in steps tagged with `tag`.
+    ///
+    /// A tag without a value, such as `ticket`, matches every value of that
+    /// key as well as the bare tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be queried.
+    pub fn tagged_paths(&self, tag: &str) -> Result<BTreeSet<String>> {
+        let _timer = self.query_timer();
+        let read_txn = self.begin_read()?;
+        let table = match read_txn.open_table(TAG_PATHS_TABLE) {
+            Ok(table) => table,
+            // Nothing has been tagged yet
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeSet::new()),
+            Err(e) => {
+                return Err(CtxError::IndexError(format!(
+                    "Failed to open tag table: {}",
+                    e
+                )))
+            }
+        };
+
+        let mut paths = BTreeSet::new();
+        for item in table
+            .range(tag..)
+            .map_err(|e| CtxError::IndexError(format!("Failed to iterate tags: {}", e)))?
+        {
+            let (key, value) =
+                item.map_err(|e| CtxError::IndexError(format!("Failed to read tag: {}", e)))?;
+            let Some(rest) = key.value().strip_prefix(tag) else {
+                break;
+            };
+            if !rest.is_empty() && (tag.contains(':') || !rest.starts_with(':')) {
+                continue;
+            }
+            let tagged: BTreeSet<String> = postcard::from_bytes(value.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?;
+            paths.extend(tagged);
+        }
+        Ok(paths)
+    }
+
+    fn write_tag_paths(&self, tags: &BTreeMap<String, BTreeSet<String>>) -> Result<()> {
+        if tags.is_empty() {
+            return Ok(());
+        }
+
+        let write_txn = self.begin_write()?;
+        merge_tag_paths(&write_txn, tags)?;
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn write_frecency(&self, entries: &BTreeMap<String, FrecencyEntry>) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let write_txn = self.begin_write()?;
+        {
+            let mut table = write_txn.open_table(FRECENCY_TABLE).map_err(|e| {
+                CtxError::IndexError(format!("Failed to open frecency table: {}", e))
+            })?;
+            for (path, entry) in entries {
+                let value = postcard::to_allocvec(entry)
+                    .map_err(|e| CtxError::Serialization(e.to_string()))?;
+                table.insert(path.as_str(), value.as_slice()).map_err(|e| {
+                    CtxError::IndexError(format!("Failed to insert frecency: {}", e))
+                })?;
+            }
+        }
+
+        write_txn
+            .commit()
+            .map_err(|e| CtxError::IndexError(format!("Failed to commit transaction: {}", e)))?;
+
         Ok(())
     }
 
     /// Helper to begin a read transaction.
     fn begin_read(&self) -> Result<redb::ReadTransaction> {
-        self.db.begin_read().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to begin read transaction: {}", e),
-            ))
-        })
+        self.db
+            .begin_read()
+            .map_err(|e| CtxError::IndexError(format!("Failed to begin read transaction: {}", e)))
     }
 
-    fn begin_write(&self) -> Result<redb::WriteTransaction> {
-        self.db.begin_write().map_err(|e| {
-            CtxError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to begin write transaction: {}", e),
-            ))
-        })
+    fn begin_write(&self) -> Result<WriteTxn> {
+        if let Access::Snapshot { writer_pid, .. } = self.access {
+            return Err(CtxError::IndexLocked { pid: writer_pid });
+        }
+        let lock = index_lock::lock_commits(&self.path, true)?;
+        let txn = self.db.begin_write().map_err(|e| {
+            CtxError::IndexError(format!("Failed to begin write transaction: {}", e))
+        })?;
+        Ok(WriteTxn { txn, _lock: lock })
+    }
+}
+
+/// Opens the database file at `file`, a copy of the index at `index_path`
+/// or the index itself, and checks its schema version.
+fn open_database(file: &Path, index_path: &Path) -> Result<Database> {
+    let db = Database::open(file).map_err(|e| match e {
+        redb::DatabaseError::DatabaseAlreadyOpen => CtxError::IndexLocked {
+            pid: index_lock::writer_pid(index_path).unwrap_or(0),
+        },
+        e => CtxError::IndexError(format!("Failed to open index: {}", e)),
+    })?;
+
+    // Verify schema version
+    let read_txn = db
+        .begin_read()
+        .map_err(|e| CtxError::IndexError(format!("Failed to begin read transaction: {}", e)))?;
+
+    if let Ok(table) = read_txn.open_table(METADATA_TABLE) {
+        if let Some(version) = table.get("version").ok().flatten() {
+            let version_val = version.value();
+            if version_val != INDEX_SCHEMA_VERSION {
+                return Err(CtxError::IndexCorrupted {
+                    message: format!(
+                        "schema version mismatch: found {}, expected {}",
+                        version_val, INDEX_SCHEMA_VERSION
+                    ),
+                });
+            }
+        }
+    }
+    drop(read_txn);
+
+    Ok(db)
+}
+
+/// Returns true if none of `edge_batches` changes the persisted SCCs:
+/// every edge joins known nodes in the same SCC or follows an existing
+/// condensation edge.
+fn edges_keep_scc(write_txn: &redb::WriteTransaction, edge_batches: &[EdgeBatch]) -> Result<bool> {
+    let scc_of = write_txn
+        .open_table(SCC_OF_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open SCC table: {}", e)))?;
+    let dag = write_txn
+        .open_table(SCC_DAG_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open SCC DAG table: {}", e)))?;
+    let lookup = |node: &NodeId| -> Result<Option<u32>> {
+        let key =
+            postcard::to_allocvec(node).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        let scc = scc_of
+            .get(key.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get SCC: {}", e)))?;
+        Ok(scc.map(|v| v.value()))
+    };
+
+    for edge in edge_batches.iter().flat_map(|batch| &batch.edges) {
+        let (Some(from), Some(to)) = (lookup(&edge.from)?, lookup(&edge.to)?) else {
+            return Ok(false);
+        };
+        if from == to {
+            continue;
+        }
+        let targets: Vec<u32> = match dag
+            .get(from)
+            .map_err(|e| CtxError::IndexError(format!("Failed to get SCC DAG: {}", e)))?
+        {
+            Some(bytes) => postcard::from_bytes(bytes.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?,
+            None => Vec::new(),
+        };
+        if !targets.contains(&to) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Adds tagged paths to the tag table, keeping the paths already recorded
+/// for each tag.
+fn merge_tag_paths(
+    write_txn: &redb::WriteTransaction,
+    tags: &BTreeMap<String, BTreeSet<String>>,
+) -> Result<()> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let mut table = write_txn
+        .open_table(TAG_PATHS_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open tag table: {}", e)))?;
+    for (tag, paths) in tags {
+        let mut merged: BTreeSet<String> = table
+            .get(tag.as_str())
+            .ok()
+            .flatten()
+            .and_then(|v| postcard::from_bytes(v.value()).ok())
+            .unwrap_or_default();
+        merged.extend(paths.iter().cloned());
+        let value =
+            postcard::to_allocvec(&merged).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        table
+            .insert(tag.as_str(), value.as_slice())
+            .map_err(|e| CtxError::IndexError(format!("Failed to insert tag: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Supersedes the edges backed by blobs that `replaced` maps away from.
+///
+/// `replaced` pairs a path's previous blob with its new one. An edge is
+/// superseded once none of its evidence cites a blob that is still the
+/// current content of some path; evidence without a blob keeps an edge
+/// alive. Superseded edges are removed from the adjacency index and
+/// recorded with the blob that replaced their source. Returns how many
+/// edges were superseded.
+fn supersede_stale_edges(
+    write_txn: &redb::WriteTransaction,
+    replaced: &[(ObjectId, ObjectId)],
+) -> Result<usize> {
+    if replaced.is_empty() {
+        return Ok(0);
+    }
+
+    let current = current_blobs(write_txn)?;
+    let blob_edges = write_txn
+        .open_table(BLOB_EDGES_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open blob edges table: {}", e)))?;
+    let evidence_table = write_txn
+        .open_table(EDGE_EVIDENCE_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open edge evidence table: {}", e)))?;
+    let mut adjacency = write_txn
+        .open_table(ADJACENCY_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open adjacency table: {}", e)))?;
+    let mut superseded = write_txn.open_table(SUPERSEDED_TABLE).map_err(|e| {
+        CtxError::IndexError(format!("Failed to open superseded edges table: {}", e))
+    })?;
+
+    let mut count = 0;
+    for (old, new) in replaced {
+        // Another path may still hold the old content
+        if current.contains(old) {
+            continue;
+        }
+        let keys: BTreeSet<Vec<u8>> = match blob_edges
+            .get(old.as_bytes())
+            .map_err(|e| CtxError::IndexError(format!("Failed to get blob edges: {}", e)))?
+        {
+            Some(bytes) => postcard::from_bytes(bytes.value())
+                .map_err(|e| CtxError::Deserialization(e.to_string()))?,
+            None => continue,
+        };
+
+        for key in keys {
+            let already = superseded
+                .get(key.as_slice())
+                .map_err(|e| CtxError::IndexError(format!("Failed to get superseded edge: {}", e)))?
+                .is_some();
+            if already {
+                continue;
+            }
+            let evidence: Vec<Evidence> = evidence_table
+                .get(key.as_slice())
+                .ok()
+                .flatten()
+                .and_then(|v| postcard::from_bytes(v.value()).ok())
+                .unwrap_or_default();
+            if is_backed(&evidence, &current) {
+                continue;
+            }
+            let Some((from, to, label)) = decode_edge_key(&key) else {
+                continue;
+            };
+
+            let out_key = encode_adjacency_key(&from, EdgeDirection::Outgoing, label);
+            remove_adjacent(&mut adjacency, &out_key, &to)?;
+            let in_key = encode_adjacency_key(&to, EdgeDirection::Incoming, label);
+            remove_adjacent(&mut adjacency, &in_key, &from)?;
+            superseded
+                .insert(key.as_slice(), new.as_bytes())
+                .map_err(|e| {
+                    CtxError::IndexError(format!("Failed to insert superseded edge: {}", e))
+                })?;
+            count += 1;
+        }
     }
+
+    if count > 0 {
+        let mut metadata = write_txn
+            .open_table(METADATA_TABLE)
+            .map_err(|e| CtxError::IndexError(format!("Failed to open metadata table: {}", e)))?;
+        metadata
+            .insert(SCC_CURRENT_KEY, 0)
+            .map_err(|e| CtxError::IndexError(format!("Failed to insert metadata: {}", e)))?;
+    }
+
+    Ok(count)
+}
+
+/// Returns true if some evidence has no blob or cites a current blob.
+fn is_backed(evidence: &[Evidence], current: &HashSet<ObjectId>) -> bool {
+    evidence
+        .iter()
+        .any(|ev| ev.blob_id.map_or(true, |blob| current.contains(&blob)))
+}
+
+/// Every blob some indexed path currently maps to.
+fn current_blobs(write_txn: &redb::WriteTransaction) -> Result<HashSet<ObjectId>> {
+    let table = write_txn
+        .open_table(PATH_TO_ID_TABLE)
+        .map_err(|e| CtxError::IndexError(format!("Failed to open path table: {}", e)))?;
+    let mut blobs = HashSet::new();
+    for item in table
+        .iter()
+        .map_err(|e| CtxError::IndexError(format!("Failed to iterate paths: {}", e)))?
+    {
+        let (_, value) =
+            item.map_err(|e| CtxError::IndexError(format!("Failed to read path: {}", e)))?;
+        blobs.insert(ObjectId::from_bytes(*value.value()));
+    }
+    Ok(blobs)
+}
+
+/// Removes `node` from the adjacency set stored under `key`.
+fn remove_adjacent(table: &mut redb::Table<&[u8], &[u8]>, key: &[u8], node: &NodeId) -> Result<()> {
+    let mut set: BTreeSet<NodeId> = table
+        .get(key)
+        .ok()
+        .flatten()
+        .and_then(|v| postcard::from_bytes(v.value()).ok())
+        .unwrap_or_default();
+    if !set.remove(node) {
+        return Ok(());
+    }
+    let result = if set.is_empty() {
+        table.remove(key).map(|_| ())
+    } else {
+        let serialized =
+            postcard::to_allocvec(&set).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        table.insert(key, serialized.as_slice()).map(|_| ())
+    };
+    result.map_err(|e| CtxError::IndexError(format!("Failed to update adjacency: {}", e)))
 }
 
 /// Recursively walk a tree and collect all paths.
@@ -1103,6 +2372,51 @@ mod tests {
         assert!(idx2.is_some());
     }
 
+    #[test]
+    fn test_single_writer_and_snapshots() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("index.redb");
+        let first = ObjectId::from_bytes([1; 32]);
+        let second = ObjectId::from_bytes([2; 32]);
+
+        let mut writer = Index::create(&path).unwrap();
+        writer.index_file_path("src/lib.rs", first).unwrap();
+
+        // Another writer can neither open nor recreate it
+        let pid = std::process::id();
+        assert!(matches!(
+            Index::open(&path),
+            Err(CtxError::IndexLocked { pid: p }) if p == pid
+        ));
+        assert!(matches!(
+            Index::create(&path),
+            Err(CtxError::IndexLocked { .. })
+        ));
+        assert_eq!(writer.lookup_path("src/lib.rs").unwrap(), Some(first));
+
+        // Readers get a snapshot that refuses writes
+        let mut snapshot = Index::open_shared(&path).unwrap().unwrap();
+        assert!(snapshot.is_snapshot());
+        assert_eq!(snapshot.lookup_path("src/lib.rs").unwrap(), Some(first));
+        assert!(matches!(
+            snapshot.index_file_path("src/main.rs", second),
+            Err(CtxError::IndexLocked { pid: p }) if p == pid
+        ));
+        assert!(snapshot.record_access(&["src/lib.rs".into()], 0).is_err());
+
+        // Later writes show up in later snapshots only
+        writer.index_file_path("src/main.rs", second).unwrap();
+        assert_eq!(snapshot.indexed_paths().unwrap().len(), 1);
+        let fresh = Index::open_snapshot(&path).unwrap().unwrap();
+        assert_eq!(fresh.lookup_path("src/main.rs").unwrap(), Some(second));
+
+        // Snapshots don't hold the index
+        drop(writer);
+        let reopened = Index::open_shared(&path).unwrap().unwrap();
+        assert!(!reopened.is_snapshot());
+        assert_eq!(snapshot.lookup_path("src/lib.rs").unwrap(), Some(first));
+    }
+
     #[test]
     fn test_open_nonexistent() {
         let tmp = TempDir::new().unwrap();
@@ -1232,6 +2546,34 @@ mod tests {
         // Should fail to open
         let result = Index::open(&path);
         assert!(result.is_err());
+
+        // A rebuild replaces it
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let tree_id = store.put_typed(&Tree::new(vec![])).unwrap();
+        let commit_id = store
+            .put_typed(&Commit {
+                parents: vec![],
+                timestamp_unix: 1234567890,
+                message: "Test commit".to_string(),
+                root_tree: tree_id,
+                edge_batches: vec![],
+                narrative_refs: vec![],
+                cargo_snapshot: None,
+                rust_snapshot: None,
+                diagnostics_snapshot: None,
+                glossary: None,
+                decisions: None,
+                facts: None,
+                qa: None,
+                transcript: None,
+                commit_type: None,
+                author: None,
+                task: None,
+                tags: Default::default(),
+            })
+            .unwrap();
+        drop(Index::rebuild_from_objects(&path, &store, commit_id).unwrap());
+        assert!(Index::open(&path).unwrap().is_some());
     }
 
     #[test]
@@ -1313,6 +2655,7 @@ mod tests {
                         confidence: Confidence::High,
                         span: None,
                         blob_id: Some(blob_id),
+                        condition: None,
                     },
                 },
                 Edge {
@@ -1332,6 +2675,7 @@ mod tests {
                         confidence: Confidence::High,
                         span: None,
                         blob_id: Some(blob_id),
+                        condition: None,
                     },
                 },
             ],
@@ -1354,7 +2698,15 @@ mod tests {
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
 
         let commit_obj_id = store.put_typed(&commit).unwrap();
@@ -1381,4 +2733,582 @@ mod tests {
         assert!(!new_results.is_empty(), "Should find 'new' item");
         assert!(new_results.contains(&blob_id));
     }
+
+    #[test]
+    fn test_edge_details_aggregate_evidence() {
+        use crate::types::{Confidence, Edge, EdgeBatch, Evidence, EvidenceTool};
+
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let main_rs = NodeId {
+            kind: NodeKind::File,
+            id: "src/main.rs".to_string(),
+        };
+        let lib_rs = NodeId {
+            kind: NodeKind::File,
+            id: "src/lib.rs".to_string(),
+        };
+        let evidence = |tool, confidence, seed: u8| Evidence {
+            commit_id: ObjectId::from_bytes([seed; 32]),
+            tool,
+            confidence,
+            span: None,
+            blob_id: Some(ObjectId::from_bytes([seed + 100; 32])),
+            condition: None,
+        };
+        let batch = |evidence: Evidence| EdgeBatch {
+            edges: vec![Edge {
+                from: main_rs.clone(),
+                to: lib_rs.clone(),
+                label: EdgeLabel::Imports,
+                weight: None,
+                evidence,
+            }],
+            created_at: 1234567890,
+        };
+        let commit = |parents, batch: &EdgeBatch| Commit {
+            parents,
+            timestamp_unix: 1234567890,
+            message: "Imports".to_string(),
+            root_tree: store.put_typed(&Tree::new(vec![])).unwrap(),
+            edge_batches: vec![store.put_typed(batch).unwrap()],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+
+        // The same claim made by the parser and by rust-analyzer
+        let parser = evidence(EvidenceTool::Parser, Confidence::Medium, 1);
+        let analyzer = evidence(EvidenceTool::RustAnalyzer, Confidence::High, 2);
+        let first = store
+            .put_typed(&commit(vec![], &batch(parser.clone())))
+            .unwrap();
+        let second_commit = commit(vec![first], &batch(analyzer.clone()));
+        let second = store.put_typed(&second_commit).unwrap();
+        let mut index =
+            Index::rebuild_from_objects(tmp.path().join("index.redb"), &store, second).unwrap();
+
+        let details = index
+            .get_edge_details(&main_rs, &lib_rs, EdgeLabel::Imports)
+            .unwrap();
+        assert_eq!(details.len(), 2);
+        assert!(details.contains(&parser) && details.contains(&analyzer));
+        assert!(index
+            .get_edge_details(&lib_rs, &main_rs, EdgeLabel::Imports)
+            .unwrap()
+            .is_empty());
+
+        // Re-adding known evidence doesn't duplicate it; new evidence is appended
+        let human = evidence(EvidenceTool::Human, Confidence::High, 3);
+        index
+            .add_commit_edges(
+                second,
+                &second_commit,
+                &[batch(analyzer), batch(human.clone())],
+            )
+            .unwrap();
+        let details = index
+            .get_edge_details(&main_rs, &lib_rs, EdgeLabel::Imports)
+            .unwrap();
+        assert_eq!(details.len(), 3);
+        assert_eq!(details[2], human);
+    }
+
+    #[test]
+    fn test_merged_edge_confidence_and_decay() {
+        use crate::graph::{expand_from_seeds, EdgeDecayConfig, ExpansionConfig};
+        use crate::types::{Edge, EdgeBatch};
+
+        const DAY: u64 = 24 * 60 * 60;
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let file = |id: &str| NodeId {
+            kind: NodeKind::File,
+            id: id.to_string(),
+        };
+        let edge = |to: &str, tool, confidence| Edge {
+            from: file("main.rs"),
+            to: file(to),
+            label: EdgeLabel::Imports,
+            weight: None,
+            evidence: Evidence {
+                commit_id: ObjectId::from_bytes([1u8; 32]),
+                tool,
+                confidence,
+                span: None,
+                blob_id: None,
+                condition: None,
+            },
+        };
+
+        // lib.rs is confirmed by two tools, guess.rs only by a weak guess
+        let batch = EdgeBatch {
+            edges: vec![
+                edge("lib.rs", EvidenceTool::Parser, Confidence::Medium),
+                edge("lib.rs", EvidenceTool::Cargo, Confidence::High),
+                edge("lib.rs", EvidenceTool::Parser, Confidence::High),
+                edge("guess.rs", EvidenceTool::Llm, Confidence::Low),
+            ],
+            created_at: 0,
+        };
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 1000 * DAY,
+            message: "Edges".to_string(),
+            root_tree: store.put_typed(&Tree::new(vec![])).unwrap(),
+            edge_batches: vec![store.put_typed(&batch).unwrap()],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        let commit_id = store.put_typed(&commit).unwrap();
+        let index =
+            Index::rebuild_from_objects(tmp.path().join("index.redb"), &store, commit_id).unwrap();
+
+        let lib = index
+            .get_merged_edge(&file("main.rs"), &file("lib.rs"), EdgeLabel::Imports)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            lib.sources,
+            vec![
+                (EvidenceTool::Parser, Confidence::High),
+                (EvidenceTool::Cargo, Confidence::High)
+            ]
+        );
+        assert_eq!(lib.last_asserted_unix, 1000 * DAY);
+        assert!((lib.confidence() - 0.99).abs() < 1e-4);
+
+        let decay = EdgeDecayConfig {
+            enabled: true,
+            half_life_days: 100.0,
+            labels: BTreeMap::from([("imports".to_string(), 50.0)]),
+            min_weight: 0.3,
+        };
+        let weight = lib.weight(EdgeLabel::Imports, &decay, 1050 * DAY);
+        assert!((weight - 0.99 * 0.5).abs() < 1e-4);
+
+        // One half-life on, only the corroborated edge clears the threshold
+        let config = ExpansionConfig {
+            max_depth: 1,
+            edge_decay: decay,
+            now_unix: 1050 * DAY,
+            ..Default::default()
+        };
+        let expansion = expand_from_seeds(&index, vec![file("main.rs")], &config).unwrap();
+        assert_eq!(
+            expansion.expanded_nodes,
+            vec![file("main.rs"), file("lib.rs")]
+        );
+
+        let without_decay = ExpansionConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let expansion = expand_from_seeds(&index, vec![file("main.rs")], &without_decay).unwrap();
+        assert_eq!(expansion.expanded_nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_expansion_skips_compiled_out_edges() {
+        use crate::graph::{expand_from_seeds, ExpansionConfig};
+        use crate::types::{Edge, EdgeBatch};
+
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let file = |id: &str| NodeId {
+            kind: NodeKind::File,
+            id: id.to_string(),
+        };
+        let edge = |to: &str, tool, condition: Option<&str>| Edge {
+            from: file("lib.rs"),
+            to: file(to),
+            label: EdgeLabel::DeclaresModule,
+            weight: None,
+            evidence: Evidence {
+                commit_id: ObjectId::from_bytes([1u8; 32]),
+                tool,
+                confidence: Confidence::High,
+                span: None,
+                blob_id: None,
+                condition: condition.map(str::to_string),
+            },
+        };
+
+        // both.rs is gated by one tool but unconditional for another
+        let batch = EdgeBatch {
+            edges: vec![
+                edge(
+                    "async_repo.rs",
+                    EvidenceTool::Parser,
+                    Some(r#"feature = "tokio""#),
+                ),
+                edge("s3.rs", EvidenceTool::Parser, Some(r#"feature = "s3""#)),
+                edge("unix.rs", EvidenceTool::Parser, Some("unix")),
+                edge("both.rs", EvidenceTool::Parser, Some(r#"feature = "s3""#)),
+                edge("both.rs", EvidenceTool::RustAnalyzer, None),
+            ],
+            created_at: 0,
+        };
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 0,
+            message: "Edges".to_string(),
+            root_tree: store.put_typed(&Tree::new(vec![])).unwrap(),
+            edge_batches: vec![store.put_typed(&batch).unwrap()],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        let commit_id = store.put_typed(&commit).unwrap();
+        let index =
+            Index::rebuild_from_objects(tmp.path().join("index.redb"), &store, commit_id).unwrap();
+
+        let expand = |active_features: Option<&[&str]>| {
+            let config = ExpansionConfig {
+                max_depth: 1,
+                follow_labels: vec![EdgeLabel::DeclaresModule],
+                active_features: active_features
+                    .map(|features| features.iter().map(|f| f.to_string()).collect()),
+                ..Default::default()
+            };
+            let mut nodes: Vec<String> = expand_from_seeds(&index, vec![file("lib.rs")], &config)
+                .unwrap()
+                .expanded_nodes
+                .into_iter()
+                .map(|node| node.id)
+                .collect();
+            nodes.sort();
+            nodes
+        };
+
+        assert_eq!(
+            expand(Some(&["tokio"])),
+            vec!["async_repo.rs", "both.rs", "lib.rs", "unix.rs"]
+        );
+        assert_eq!(expand(Some(&[])), vec!["both.rs", "lib.rs", "unix.rs"]);
+        assert_eq!(expand(None).len(), 5);
+    }
+
+    #[test]
+    fn test_edges_superseded_when_file_changes() {
+        use crate::types::{Confidence, Edge, EdgeBatch, Evidence, EvidenceTool};
+
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let index_path = tmp.path().join("index.redb");
+        let item = |id: &str| NodeId {
+            kind: NodeKind::Item,
+            id: id.to_string(),
+        };
+        let old_blob = store.put_blob(b"fn main() { helper() }").unwrap();
+        let new_blob = store.put_blob(b"fn main() {}").unwrap();
+        let edge = |label, to: &str, blob_id| Edge {
+            from: item("main"),
+            to: item(to),
+            label,
+            weight: None,
+            evidence: Evidence {
+                commit_id: ObjectId::from_bytes([1; 32]),
+                tool: EvidenceTool::Parser,
+                confidence: Confidence::High,
+                span: None,
+                blob_id,
+                condition: None,
+            },
+        };
+        let commit = |parents, edges| {
+            let batch = EdgeBatch {
+                edges,
+                created_at: 1234567890,
+            };
+            Commit {
+                parents,
+                timestamp_unix: 1234567890,
+                message: "Analyze".to_string(),
+                root_tree: store.put_typed(&Tree::new(vec![])).unwrap(),
+                edge_batches: vec![store.put_typed(&batch).unwrap()],
+                narrative_refs: vec![],
+                cargo_snapshot: None,
+                rust_snapshot: None,
+                diagnostics_snapshot: None,
+                glossary: None,
+                decisions: None,
+                facts: None,
+                qa: None,
+                transcript: None,
+                commit_type: None,
+                author: None,
+                task: None,
+                tags: Default::default(),
+            }
+        };
+
+        // A call read from the old content, and a blobless edge
+        let first = store
+            .put_typed(&commit(
+                vec![],
+                vec![
+                    edge(EdgeLabel::Calls, "helper", Some(old_blob)),
+                    edge(EdgeLabel::References, "Config", None),
+                ],
+            ))
+            .unwrap();
+        let mut index = Index::rebuild_from_objects(&index_path, &store, first).unwrap();
+        assert_eq!(index.index_file_path("src/main.rs", old_blob).unwrap(), 0);
+
+        // Rewriting the file supersedes the call but not the blobless edge
+        assert_eq!(index.index_file_path("src/main.rs", new_blob).unwrap(), 1);
+        assert!(index
+            .get_edges_from(&item("main"), EdgeLabel::Calls)
+            .unwrap()
+            .is_empty());
+        assert!(index
+            .get_edges_to(&item("helper"), EdgeLabel::Calls)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .get_edges_from(&item("main"), EdgeLabel::References)
+                .unwrap(),
+            vec![item("Config")]
+        );
+        assert_eq!(
+            index
+                .get_superseded_by(&item("main"), &item("helper"), EdgeLabel::Calls)
+                .unwrap(),
+            Some(new_blob)
+        );
+        assert!(!index.scc_is_current().unwrap());
+        assert_eq!(index.index_file_path("src/main.rs", new_blob).unwrap(), 0);
+
+        // Superseding survives a rebuild
+        drop(index);
+        let mut index = Index::rebuild_from_objects(&index_path, &store, first).unwrap();
+        assert!(index
+            .get_edges_from(&item("main"), EdgeLabel::Calls)
+            .unwrap()
+            .is_empty());
+
+        // Asserting the call from the new content revives it
+        let second_commit = commit(
+            vec![first],
+            vec![edge(EdgeLabel::Calls, "helper", Some(new_blob))],
+        );
+        let second = store.put_typed(&second_commit).unwrap();
+        let batches: Vec<EdgeBatch> = second_commit
+            .edge_batches
+            .iter()
+            .map(|id| store.get_typed(*id).unwrap())
+            .collect();
+        index
+            .add_commit_edges(second, &second_commit, &batches)
+            .unwrap();
+        assert_eq!(
+            index
+                .get_edges_from(&item("main"), EdgeLabel::Calls)
+                .unwrap(),
+            vec![item("helper")]
+        );
+        assert_eq!(
+            index
+                .get_superseded_by(&item("main"), &item("helper"), EdgeLabel::Calls)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scc_persisted_and_invalidated() {
+        use crate::graph::{expand_from_seeds, ExpansionConfig};
+        use crate::types::{Confidence, Edge, EdgeBatch, Evidence, EvidenceTool};
+
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let file = |id: &str| NodeId {
+            kind: NodeKind::File,
+            id: id.to_string(),
+        };
+        let batch = |edges: &[(&str, &str)]| EdgeBatch {
+            edges: edges
+                .iter()
+                .map(|(from, to)| Edge {
+                    from: file(from),
+                    to: file(to),
+                    label: EdgeLabel::Imports,
+                    weight: None,
+                    evidence: Evidence {
+                        commit_id: ObjectId::from_bytes([1u8; 32]),
+                        tool: EvidenceTool::Parser,
+                        confidence: Confidence::High,
+                        span: None,
+                        blob_id: None,
+                        condition: None,
+                    },
+                })
+                .collect(),
+            created_at: 1234567890,
+        };
+
+        // a.rs <-> b.rs form a cycle that imports c.rs
+        let cycle = batch(&[("a.rs", "b.rs"), ("b.rs", "a.rs"), ("b.rs", "c.rs")]);
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 1234567890,
+            message: "Cycle".to_string(),
+            root_tree: store.put_typed(&Tree::new(vec![])).unwrap(),
+            edge_batches: vec![store.put_typed(&cycle).unwrap()],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        let commit_id = store.put_typed(&commit).unwrap();
+        let mut index =
+            Index::rebuild_from_objects(tmp.path().join("index.redb"), &store, commit_id).unwrap();
+
+        let view = index
+            .scc_view()
+            .unwrap()
+            .expect("SCCs persisted on rebuild");
+        assert_eq!(view.scc_count(), 2);
+        assert!(view.same_component(&file("a.rs"), &file("b.rs")));
+        let cycle_scc = view.scc_of(&file("a.rs")).unwrap();
+        let c_scc = view.scc_of(&file("c.rs")).unwrap();
+        assert_eq!(view.dependencies(cycle_scc), &[c_scc]);
+
+        // Expanding zero hops from a.rs still reaches its cycle partner
+        let config = ExpansionConfig {
+            max_depth: 0,
+            use_scc: true,
+            ..Default::default()
+        };
+        let expansion = expand_from_seeds(&index, vec![file("a.rs")], &config).unwrap();
+        assert!(expansion.scc_used);
+        assert_eq!(expansion.expanded_nodes, vec![file("a.rs"), file("b.rs")]);
+        assert_eq!(expansion.discovered_via[&file("b.rs")].label, None);
+
+        // An edge parallel to an existing condensation edge changes nothing
+        index
+            .add_commit_edges(commit_id, &commit, &[batch(&[("a.rs", "c.rs")])])
+            .unwrap();
+        assert!(index.scc_is_current().unwrap());
+
+        // Closing a cycle through c.rs merges everything into one SCC
+        index
+            .add_commit_edges(commit_id, &commit, &[batch(&[("c.rs", "a.rs")])])
+            .unwrap();
+        assert!(index.scc_view().unwrap().is_none());
+        let view = index.ensure_scc().unwrap();
+        assert_eq!(view.scc_count(), 1);
+        assert!(index.scc_is_current().unwrap());
+    }
+
+    #[test]
+    fn test_frecency_record_and_rank() {
+        let tmp = TempDir::new().unwrap();
+        let idx = Index::create(tmp.path().join("index.redb")).unwrap();
+        let day = 24 * 60 * 60;
+
+        assert!(idx.frecency_entries().unwrap().is_empty());
+
+        idx.record_access(&["src/old.rs".into()], 0).unwrap();
+        idx.record_access(&["src/old.rs".into()], 0).unwrap();
+        let now = 100 * day;
+        idx.record_access(&["src/new.rs".into(), "src/new.rs".into()], now)
+            .unwrap();
+
+        let entries = idx.frecency_entries().unwrap();
+        assert_eq!(entries["src/old.rs"].access_count, 2);
+        // Duplicates within one call count once
+        assert_eq!(entries["src/new.rs"].access_count, 1);
+
+        // One recent access outranks two old ones
+        let ranked = idx.frecent_paths(now, 10).unwrap();
+        assert_eq!(
+            ranked,
+            vec![
+                ("src/new.rs".to_string(), 100),
+                ("src/old.rs".to_string(), 20)
+            ]
+        );
+        assert_eq!(idx.frecent_paths(now, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_frecency_survives_rebuild() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let tree_id = store.put_typed(&Tree::new(vec![])).unwrap();
+        let commit_id = store
+            .put_typed(&Commit {
+                parents: vec![],
+                timestamp_unix: 0,
+                message: "root".into(),
+                root_tree: tree_id,
+                edge_batches: vec![],
+                narrative_refs: vec![],
+                cargo_snapshot: None,
+                rust_snapshot: None,
+                diagnostics_snapshot: None,
+                glossary: None,
+                decisions: None,
+                facts: None,
+                qa: None,
+                transcript: None,
+                commit_type: None,
+                author: None,
+                task: None,
+                tags: Default::default(),
+            })
+            .unwrap();
+
+        let index_path = tmp.path().join("index.redb");
+        let idx = Index::rebuild_from_objects(&index_path, &store, commit_id).unwrap();
+        idx.record_access(&["src/lib.rs".into()], 42).unwrap();
+        drop(idx);
+
+        let idx = Index::rebuild_from_objects(&index_path, &store, commit_id).unwrap();
+        let entries = idx.frecency_entries().unwrap();
+        assert_eq!(entries["src/lib.rs"].last_access_unix, 42);
+    }
 }
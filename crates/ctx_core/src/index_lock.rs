@@ -0,0 +1,123 @@
+//! Coordination between processes sharing the index.
+//!
+//! redb locks a database file for as long as it is open, so only one
+//! process at a time can have `.ctx/index/index.redb` open. That process is
+//! the index's writer; the others read snapshots:
+//! - The writer holds `index.writer.lock` while it has the index open and
+//!   records its PID there. Another process that wants to write gets
+//!   [`CtxError::IndexLocked`], and can't delete or recreate the file
+//!   under the writer.
+//! - A reader copies the database to a temporary file and opens the copy.
+//!   Write transactions hold `index.commit.lock` exclusively and copies
+//!   hold it shared, so a copy never catches a transaction half-written.
+//!
+//! Both are advisory locks, so a crashed process never leaves the index
+//! locked.
+
+use crate::error::{CtxError, LockKind, Result};
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Distinguishes the snapshots of one process.
+static SNAPSHOT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The exclusive lock of the process that writes the index.
+pub(crate) struct WriterLock {
+    _file: File,
+}
+
+impl WriterLock {
+    /// Becomes the writer of the index at `index_path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::IndexLocked`] if another handle is the writer.
+    pub(crate) fn acquire(index_path: &Path) -> Result<Self> {
+        let mut file = open_lock(&index_path.with_extension("writer.lock"))?;
+        if let Err(e) = FileExt::try_lock_exclusive(&file) {
+            if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                return Err(CtxError::IndexLocked {
+                    pid: writer_pid(index_path).unwrap_or(0),
+                });
+            }
+            return Err(lock_error(e));
+        }
+        file.set_len(0).map_err(lock_error)?;
+        writeln!(file, "{}", std::process::id()).map_err(lock_error)?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// Returns the PID of the index's last writer, which may have exited.
+pub(crate) fn writer_pid(index_path: &Path) -> Option<u32> {
+    fs::read_to_string(index_path.with_extension("writer.lock"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Locks out snapshot copies (`exclusive`, for a write transaction) or
+/// write transactions (shared, for a copy) until the file is dropped.
+pub(crate) fn lock_commits(index_path: &Path, exclusive: bool) -> Result<File> {
+    let file = open_lock(&index_path.with_extension("commit.lock"))?;
+    // fs2's, not the newer std methods of the same names
+    if exclusive {
+        FileExt::lock_exclusive(&file).map_err(lock_error)?;
+    } else {
+        FileExt::lock_shared(&file).map_err(lock_error)?;
+    }
+    Ok(file)
+}
+
+fn open_lock(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(lock_error)
+}
+
+fn lock_error(e: std::io::Error) -> CtxError {
+    CtxError::LockError {
+        kind: LockKind::Index,
+        reason: e.to_string(),
+    }
+}
+
+/// A private copy of the index database, deleted when dropped.
+pub(crate) struct SnapshotFile {
+    path: PathBuf,
+}
+
+impl SnapshotFile {
+    /// Copies the index at `index_path` between write transactions.
+    pub(crate) fn copy(index_path: &Path) -> Result<Self> {
+        let snapshot = Self {
+            path: std::env::temp_dir().join(format!(
+                "ctx-index-{}-{}.redb",
+                std::process::id(),
+                SNAPSHOT_COUNTER.fetch_add(1, Ordering::Relaxed)
+            )),
+        };
+        let _lock = lock_commits(index_path, false)?;
+        // Removed on drop if the copy fails partway
+        fs::copy(index_path, &snapshot.path)?;
+        Ok(snapshot)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SnapshotFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,253 @@
+//! Policies for large and binary file content.
+//!
+//! Observed file content is normally stored as one blob. Content over
+//! `storage.max_blob_size`, or binary content, is handled by the policies
+//! in [`ContentLimits`] instead:
+//! - `store` keeps it as a blob anyway, up to the store's hard limit.
+//! - `skip` records nothing for writes and only the path for reads.
+//! - `stub` records a [`LargeFile`] with the path's size and hash but no
+//!   content.
+//! - `chunked` stores the content in content-defined chunks listed by a
+//!   [`LargeFile`], so no single object is large. The chunks are the ones
+//!   the object store would split a large blob into, so they are shared
+//!   with blobs and other chunked files.
+//!
+//! A [`LargeFile`] stands in for the blob wherever a content ID is expected.
+//! Packs show it as a [`ChunkKind::Stub`](crate::ChunkKind::Stub) rather
+//! than its content, and [`load_content`] reassembles chunked content.
+
+use crate::chunking;
+use crate::error::{CtxError, Result};
+use crate::{ObjectId, ObjectStore};
+use serde::{Deserialize, Serialize};
+
+/// How many leading bytes are checked for NUL when detecting binary
+/// content, as git does.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// What to do with content a limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentPolicy {
+    /// Store it as a blob anyway.
+    Store,
+    /// Don't store or record it.
+    Skip,
+    /// Record its size and hash only.
+    Stub,
+    /// Store it in chunks.
+    Chunked,
+}
+
+/// Limits on the file content observations store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLimits {
+    /// Largest content stored as a single blob, in bytes.
+    pub max_blob_size: u64,
+    /// Policy for content over `max_blob_size`.
+    pub large_file_policy: ContentPolicy,
+    /// Policy for binary content of any size. With `store`, large binary
+    /// content falls under `large_file_policy`.
+    pub binary_policy: ContentPolicy,
+}
+
+impl ContentLimits {
+    /// The policy for `content`, or None if it is stored as a plain blob.
+    pub fn policy_for(&self, content: &[u8]) -> Option<ContentPolicy> {
+        if self.binary_policy != ContentPolicy::Store && is_binary(content) {
+            return Some(self.binary_policy);
+        }
+        if content.len() as u64 > self.max_blob_size
+            && self.large_file_policy != ContentPolicy::Store
+        {
+            return Some(self.large_file_policy);
+        }
+        None
+    }
+}
+
+impl Default for ContentLimits {
+    fn default() -> Self {
+        crate::config::StorageConfig::default().content_limits()
+    }
+}
+
+/// Content stored as a stub or in chunks instead of as one blob.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LargeFile {
+    /// Content size in bytes.
+    pub size: u64,
+    /// ID the content would have as a blob.
+    pub hash: ObjectId,
+    /// Whether the content looked binary.
+    pub binary: bool,
+    /// Blobs holding the content in order. Empty for a stub.
+    pub chunks: Vec<ObjectId>,
+}
+
+impl LargeFile {
+    /// Returns true if the content itself wasn't stored.
+    pub fn is_stub(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// One-line description for packs and listings.
+    pub fn describe(&self) -> String {
+        let kind = if self.binary { "binary" } else { "text" };
+        let stored = if self.is_stub() {
+            "content not stored".to_string()
+        } else {
+            format!("stored in {} chunks", self.chunks.len())
+        };
+        format!(
+            "{} {} file, {} (blake3 {})",
+            format_size(self.size),
+            kind,
+            stored,
+            self.hash.as_hex()
+        )
+    }
+}
+
+/// File content as stored for a content ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileContent {
+    /// The full content.
+    Bytes(Vec<u8>),
+    /// Only the size and hash were kept.
+    Stub(LargeFile),
+}
+
+/// Returns true if `content` looks binary: it has a NUL byte near the start.
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// Stores `content` under `limits`.
+///
+/// Returns the content ID to record, or None if the content is skipped.
+pub(crate) fn store_content(
+    content: &[u8],
+    limits: &ContentLimits,
+    object_store: &ObjectStore,
+) -> Result<Option<ObjectId>> {
+    let policy = match limits.policy_for(content) {
+        None | Some(ContentPolicy::Store) => return object_store.put_blob(content).map(Some),
+        Some(ContentPolicy::Skip) => return Ok(None),
+        Some(policy) => policy,
+    };
+
+    let chunks = if policy == ContentPolicy::Chunked {
+        chunking::chunks(content)
+            .into_iter()
+            .map(|chunk| object_store.put_blob(chunk))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    let large = LargeFile {
+        size: content.len() as u64,
+        hash: ObjectId::hash_blob(content),
+        binary: is_binary(content),
+        chunks,
+    };
+    object_store.put_typed(&large).map(Some)
+}
+
+/// Loads the content recorded under `id`, reassembling chunked content.
+pub fn load_content(object_store: &ObjectStore, id: ObjectId) -> Result<FileContent> {
+    let err = match object_store.get_blob(id) {
+        Ok(bytes) => return Ok(FileContent::Bytes(bytes)),
+        Err(e @ CtxError::CorruptedObject { .. }) => e,
+        Err(e) => return Err(e),
+    };
+    // Not a blob; anything but a large file is an error
+    let Ok(large) = object_store.get_typed::<LargeFile>(id) else {
+        return Err(err);
+    };
+    if large.is_stub() {
+        return Ok(FileContent::Stub(large));
+    }
+    let mut bytes = Vec::with_capacity(large.size as usize);
+    for chunk in &large.chunks {
+        bytes.extend(object_store.get_blob(*chunk)?);
+    }
+    Ok(FileContent::Bytes(bytes))
+}
+
+/// Returns the large file recorded under `id`, or None for a plain blob or
+/// any other object.
+pub(crate) fn large_file(object_store: &ObjectStore, id: ObjectId) -> Option<LargeFile> {
+    // Blobs, chunked or not, fail as typed objects without being read whole
+    object_store.get_typed(id).ok()
+}
+
+/// Formats a byte count with a binary unit.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_store_content_policies() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let limits = |large_file_policy, binary_policy| ContentLimits {
+            max_blob_size: 8,
+            large_file_policy,
+            binary_policy,
+        };
+        let text = b"0123456789abcdef";
+        let binary = b"\x7fELF\0\0";
+
+        let small = store_content(
+            b"small",
+            &limits(ContentPolicy::Skip, ContentPolicy::Skip),
+            &store,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(store.get_blob(small).unwrap(), b"small");
+
+        let skip = limits(ContentPolicy::Skip, ContentPolicy::Skip);
+        assert_eq!(store_content(text, &skip, &store).unwrap(), None);
+        assert_eq!(store_content(binary, &skip, &store).unwrap(), None);
+
+        let stub = limits(ContentPolicy::Stub, ContentPolicy::Stub);
+        let id = store_content(binary, &stub, &store).unwrap().unwrap();
+        let FileContent::Stub(large) = load_content(&store, id).unwrap() else {
+            panic!("expected a stub");
+        };
+        assert_eq!(large.size, 6);
+        assert_eq!(large.hash, ObjectId::hash_blob(binary));
+        assert!(large.binary);
+        assert!(large
+            .describe()
+            .starts_with("6 B binary file, content not stored (blake3 "));
+
+        let chunked = limits(ContentPolicy::Chunked, ContentPolicy::Store);
+        let id = store_content(text, &chunked, &store).unwrap().unwrap();
+        assert_eq!(
+            load_content(&store, id).unwrap(),
+            FileContent::Bytes(text.to_vec())
+        );
+        assert_eq!(large_file(&store, id).unwrap().chunks.len(), 1);
+        assert!(large_file(&store, small).is_none());
+
+        assert_eq!(format_size(200 * 1024 * 1024), "200.0 MiB");
+    }
+}
@@ -0,0 +1,626 @@
+//! Stored layouts of commits, work steps and edge batches.
+//!
+//! postcard encodes structs positionally, so adding a field to a stored
+//! type changes how every object written before it decodes. [`Commit`],
+//! [`WorkCommit`] and [`EdgeBatch`] are therefore stored as a layout tag
+//! (`LAYOUT_TAG_BASE + version`) followed by their fields. Objects written
+//! before tags existed start with the length of their leading `Vec`
+//! instead, which is always far below the base, and are decoded with the
+//! original layout. Human-readable formats such as JSON use the plain field
+//! layout and are unaffected.
+//!
+//! Changing the fields of one of these types means bumping
+//! [`LAYOUT_VERSION`] and teaching [`TaggedVisitor`] to read the previous
+//! version.
+
+use crate::object_id::ObjectId;
+use crate::types::*;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Smallest layout tag. Far larger than any `Vec` an untagged object could
+/// start with.
+const LAYOUT_TAG_BASE: u64 = 0xC7C7_0000_0000_0000;
+
+/// Current layout version of the tagged types.
+const LAYOUT_VERSION: u64 = 1;
+
+/// A stored type with a tagged layout.
+trait TaggedLayout: Sized {
+    /// Name used in decode errors.
+    const NAME: &'static str;
+    /// Element of the leading `Vec` in the untagged layout.
+    type LegacyItem: DeserializeOwned;
+    /// Fields after the leading `Vec` in the untagged layout.
+    type LegacyRest: DeserializeOwned;
+
+    fn serialize_fields<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+    fn deserialize_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+    fn from_legacy(items: Vec<Self::LegacyItem>, rest: Self::LegacyRest) -> Self;
+}
+
+fn serialize_tagged<T: TaggedLayout, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        return value.serialize_fields(serializer);
+    }
+    let mut tuple = serializer.serialize_tuple(2)?;
+    tuple.serialize_element(&(LAYOUT_TAG_BASE + LAYOUT_VERSION))?;
+    tuple.serialize_element(&Fields(value))?;
+    tuple.end()
+}
+
+fn deserialize_tagged<'de, T: TaggedLayout, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    if deserializer.is_human_readable() {
+        return T::deserialize_fields(deserializer);
+    }
+    // The untagged layout has no fixed length, so read as long a tuple as
+    // the visitor asks for
+    deserializer.deserialize_tuple(usize::MAX, TaggedVisitor(PhantomData))
+}
+
+struct Fields<'a, T>(&'a T);
+
+impl<T: TaggedLayout> Serialize for Fields<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_fields(serializer)
+    }
+}
+
+struct FieldsSeed<T>(PhantomData<T>);
+
+impl<'de, T: TaggedLayout> DeserializeSeed<'de> for FieldsSeed<T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize_fields(deserializer)
+    }
+}
+
+struct TaggedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: TaggedLayout> Visitor<'de> for TaggedVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a stored {}", T::NAME)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let first: u64 = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+        if first == LAYOUT_TAG_BASE + LAYOUT_VERSION {
+            return seq
+                .next_element_seed(FieldsSeed(PhantomData))?
+                .ok_or_else(|| de::Error::invalid_length(1, &self));
+        }
+        if first >= LAYOUT_TAG_BASE {
+            return Err(de::Error::custom(format!(
+                "{} layout version {} is newer than this version of ctx understands",
+                T::NAME,
+                first - LAYOUT_TAG_BASE
+            )));
+        }
+
+        // Untagged: `first` is the length of the leading Vec
+        let len = usize::try_from(first)
+            .map_err(|_| de::Error::custom(format!("{} is too large", T::NAME)))?;
+        let mut items = Vec::with_capacity(len.min(1024));
+        for i in 0..len {
+            let item = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i + 1, &self))?;
+            items.push(item);
+        }
+        let rest = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(len + 1, &self))?;
+        Ok(T::from_legacy(items, rest))
+    }
+}
+
+macro_rules! impl_tagged_serde {
+    ($($ty:ty),*) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_tagged(self, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_tagged(deserializer)
+            }
+        }
+    )*};
+}
+
+impl_tagged_serde!(Commit, WorkCommit, EdgeBatch);
+
+// Current field layouts
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Commit")]
+struct CommitFields {
+    parents: Vec<ObjectId>,
+    timestamp_unix: u64,
+    message: String,
+    root_tree: ObjectId,
+    edge_batches: Vec<ObjectId>,
+    narrative_refs: Vec<NarrativeRef>,
+    cargo_snapshot: Option<ObjectId>,
+    rust_snapshot: Option<ObjectId>,
+    diagnostics_snapshot: Option<ObjectId>,
+    glossary: Option<ObjectId>,
+    decisions: Option<ObjectId>,
+    facts: Option<ObjectId>,
+    qa: Option<ObjectId>,
+    transcript: Option<ObjectId>,
+    commit_type: Option<CommitType>,
+    author: Option<AgentIdentity>,
+    task: Option<String>,
+    tags: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkCommit")]
+struct WorkCommitFields {
+    parents: Vec<ObjectId>,
+    base: ObjectId,
+    session_id: String,
+    created_at: u64,
+    step_kind: StepKind,
+    payload: Vec<u8>,
+    narrative_refs: Vec<NarrativeRef>,
+    session_state: SessionState,
+    task_description: String,
+    author: Option<AgentIdentity>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "EdgeBatch")]
+struct EdgeBatchFields {
+    edges: Vec<Edge>,
+    created_at: u64,
+}
+
+// Untagged layouts, as written before layout tags
+
+#[derive(Deserialize)]
+struct LegacyCommitRest {
+    timestamp_unix: u64,
+    message: String,
+    root_tree: ObjectId,
+    edge_batches: Vec<ObjectId>,
+    narrative_refs: Vec<NarrativeRef>,
+    cargo_snapshot: Option<ObjectId>,
+    rust_snapshot: Option<ObjectId>,
+    diagnostics_snapshot: Option<ObjectId>,
+    commit_type: Option<CommitType>,
+}
+
+#[derive(Deserialize)]
+struct LegacyWorkCommitRest {
+    base: ObjectId,
+    session_id: String,
+    created_at: u64,
+    step_kind: StepKind,
+    payload: Vec<u8>,
+    narrative_refs: Vec<NarrativeRef>,
+    session_state: SessionState,
+    task_description: String,
+}
+
+#[derive(Deserialize)]
+struct LegacyEdgeBatchRest {
+    created_at: u64,
+}
+
+#[derive(Deserialize)]
+struct LegacyEdge {
+    from: NodeId,
+    to: NodeId,
+    label: LegacyEdgeLabel,
+    weight: Option<u32>,
+    evidence: LegacyEvidence,
+}
+
+#[derive(Deserialize)]
+struct LegacyEvidence {
+    commit_id: ObjectId,
+    tool: EvidenceTool,
+    confidence: Confidence,
+    span: Option<Span>,
+    blob_id: Option<ObjectId>,
+}
+
+/// Edge labels in their original order; serde encodes the variant index.
+#[derive(Deserialize)]
+enum LegacyEdgeLabel {
+    Contains,
+    Defines,
+    HasVersion,
+    DependsOn,
+    TargetOf,
+    CrateFromTarget,
+    Imports,
+    References,
+    Calls,
+    Implements,
+    UsesType,
+    Mentions,
+    UpdatedIn,
+    DerivedFrom,
+}
+
+/// Observations as written into untagged work step payloads, before they
+/// carried metadata.
+#[derive(Deserialize)]
+enum LegacyObservation {
+    FileRead {
+        path: String,
+        content_id: Option<ObjectId>,
+    },
+    FileWrite {
+        path: String,
+        content_id: ObjectId,
+    },
+    Command {
+        command: String,
+        exit_code: Option<i32>,
+        output_id: Option<ObjectId>,
+    },
+    Note {
+        content: String,
+    },
+    Plan {
+        content: String,
+    },
+}
+
+impl From<LegacyEdgeLabel> for EdgeLabel {
+    fn from(label: LegacyEdgeLabel) -> Self {
+        match label {
+            LegacyEdgeLabel::Contains => EdgeLabel::Contains,
+            LegacyEdgeLabel::Defines => EdgeLabel::Defines,
+            LegacyEdgeLabel::HasVersion => EdgeLabel::HasVersion,
+            LegacyEdgeLabel::DependsOn => EdgeLabel::DependsOn,
+            LegacyEdgeLabel::TargetOf => EdgeLabel::TargetOf,
+            LegacyEdgeLabel::CrateFromTarget => EdgeLabel::CrateFromTarget,
+            LegacyEdgeLabel::Imports => EdgeLabel::Imports,
+            LegacyEdgeLabel::References => EdgeLabel::References,
+            LegacyEdgeLabel::Calls => EdgeLabel::Calls,
+            LegacyEdgeLabel::Implements => EdgeLabel::Implements,
+            LegacyEdgeLabel::UsesType => EdgeLabel::UsesType,
+            LegacyEdgeLabel::Mentions => EdgeLabel::Mentions,
+            LegacyEdgeLabel::UpdatedIn => EdgeLabel::UpdatedIn,
+            LegacyEdgeLabel::DerivedFrom => EdgeLabel::DerivedFrom,
+        }
+    }
+}
+
+impl From<LegacyObservation> for Observation {
+    fn from(observation: LegacyObservation) -> Self {
+        let metadata = Metadata::new();
+        match observation {
+            LegacyObservation::FileRead { path, content_id } => Observation::FileRead {
+                path,
+                content_id,
+                metadata,
+            },
+            LegacyObservation::FileWrite { path, content_id } => Observation::FileWrite {
+                path,
+                content_id,
+                metadata,
+            },
+            LegacyObservation::Command {
+                command,
+                exit_code,
+                output_id,
+            } => Observation::Command {
+                command,
+                exit_code,
+                output_id,
+                metadata,
+            },
+            LegacyObservation::Note { content } => Observation::Note { content, metadata },
+            LegacyObservation::Plan { content } => Observation::Plan { content, metadata },
+        }
+    }
+}
+
+/// Re-encodes an untagged work step payload in the current observation
+/// layout. Payloads that don't decode are kept as they are.
+fn upgrade_payload(payload: Vec<u8>) -> Vec<u8> {
+    postcard::from_bytes::<Vec<LegacyObservation>>(&payload)
+        .ok()
+        .map(|observations| {
+            observations
+                .into_iter()
+                .map(Observation::from)
+                .collect::<Vec<_>>()
+        })
+        .and_then(|observations| postcard::to_allocvec(&observations).ok())
+        .unwrap_or(payload)
+}
+
+impl TaggedLayout for Commit {
+    const NAME: &'static str = "commit";
+    type LegacyItem = ObjectId;
+    type LegacyRest = LegacyCommitRest;
+
+    fn serialize_fields<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CommitFields::serialize(self, serializer)
+    }
+
+    fn deserialize_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        CommitFields::deserialize(deserializer)
+    }
+
+    fn from_legacy(parents: Vec<ObjectId>, rest: LegacyCommitRest) -> Self {
+        Commit {
+            parents,
+            timestamp_unix: rest.timestamp_unix,
+            message: rest.message,
+            root_tree: rest.root_tree,
+            edge_batches: rest.edge_batches,
+            narrative_refs: rest.narrative_refs,
+            cargo_snapshot: rest.cargo_snapshot,
+            rust_snapshot: rest.rust_snapshot,
+            diagnostics_snapshot: rest.diagnostics_snapshot,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: rest.commit_type,
+            author: None,
+            task: None,
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+impl TaggedLayout for WorkCommit {
+    const NAME: &'static str = "work commit";
+    type LegacyItem = ObjectId;
+    type LegacyRest = LegacyWorkCommitRest;
+
+    fn serialize_fields<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WorkCommitFields::serialize(self, serializer)
+    }
+
+    fn deserialize_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        WorkCommitFields::deserialize(deserializer)
+    }
+
+    fn from_legacy(parents: Vec<ObjectId>, rest: LegacyWorkCommitRest) -> Self {
+        WorkCommit {
+            parents,
+            base: rest.base,
+            session_id: rest.session_id,
+            created_at: rest.created_at,
+            step_kind: rest.step_kind,
+            payload: upgrade_payload(rest.payload),
+            narrative_refs: rest.narrative_refs,
+            session_state: rest.session_state,
+            task_description: rest.task_description,
+            author: None,
+        }
+    }
+}
+
+impl TaggedLayout for EdgeBatch {
+    const NAME: &'static str = "edge batch";
+    type LegacyItem = LegacyEdge;
+    type LegacyRest = LegacyEdgeBatchRest;
+
+    fn serialize_fields<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EdgeBatchFields::serialize(self, serializer)
+    }
+
+    fn deserialize_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        EdgeBatchFields::deserialize(deserializer)
+    }
+
+    fn from_legacy(edges: Vec<LegacyEdge>, rest: LegacyEdgeBatchRest) -> Self {
+        let edges = edges
+            .into_iter()
+            .map(|edge| Edge {
+                from: edge.from,
+                to: edge.to,
+                label: edge.label.into(),
+                weight: edge.weight,
+                evidence: Evidence {
+                    commit_id: edge.evidence.commit_id,
+                    tool: edge.evidence.tool,
+                    confidence: edge.evidence.confidence,
+                    span: edge.evidence.span,
+                    blob_id: edge.evidence.blob_id,
+                    condition: None,
+                },
+            })
+            .collect();
+        EdgeBatch {
+            edges,
+            created_at: rest.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staging::decode_observations;
+    use crate::ObjectStore;
+    use tempfile::TempDir;
+
+    // Encoded by ctx before layout tags, from the values built in the tests
+    const COMMIT_V0: &str = "01010101010101010101010101010101010101010101010101010101010101010180e2cfaa060a41646420706172736572020202020202020202020202020202020202020202020202020202020202020201030303030303030303030303030303030303030303030303030303030303030301086c6f672f612e6d6400056167656e74040404040404040404040404040404040404040404040404040404040404040400010505050505050505050505050505050505050505050505050505050505050505000100";
+    const WORK_COMMIT_V0: &str = "000707070707070707070707070707070707070707070707070707070707070707027331e4e2cfaa06014603000a7372632f6c69622e7273010606060606060606060606060606060606060606060606060606060606060606020a636172676f207465737401000004077368697020697400000a66697820706172736572";
+    const EDGE_BATCH_V0: &str = "02000a7372632f6c69622e7273020570617273650801dc0b0808080808080808080808080808080808080808080808080808080808080808010000000101610101620d000808080808080808080808080808080808080808080808080808080808080808030200010909090909090909090909090909090909090909090909090909090909090909c8e3cfaa06";
+
+    fn id(byte: u8) -> ObjectId {
+        ObjectId::from_bytes([byte; 32])
+    }
+
+    fn decode<T: DeserializeOwned>(fixture: &str) -> T {
+        postcard::from_bytes(&hex::decode(fixture).unwrap()).unwrap()
+    }
+
+    fn commit() -> Commit {
+        Commit {
+            parents: vec![id(1)],
+            timestamp_unix: 1_700_000_000,
+            message: "Add parser".to_string(),
+            root_tree: id(2),
+            edge_batches: vec![id(3)],
+            narrative_refs: vec![NarrativeRef {
+                path: "log/a.md".to_string(),
+                stream: None,
+                role: "agent".to_string(),
+                blob_id: id(4),
+            }],
+            cargo_snapshot: None,
+            rust_snapshot: Some(id(5)),
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: Some(CommitType::Normal),
+            author: None,
+            task: None,
+            tags: BTreeMap::new(),
+        }
+    }
+
+    fn observations() -> Vec<Observation> {
+        vec![
+            Observation::FileRead {
+                path: "src/lib.rs".to_string(),
+                content_id: Some(id(6)),
+                metadata: Metadata::new(),
+            },
+            Observation::Command {
+                command: "cargo test".to_string(),
+                exit_code: Some(0),
+                output_id: None,
+                metadata: Metadata::new(),
+            },
+            Observation::Plan {
+                content: "ship it".to_string(),
+                metadata: Metadata::new(),
+            },
+        ]
+    }
+
+    fn edge_batch() -> EdgeBatch {
+        let evidence = |tool, confidence, blob_id| Evidence {
+            commit_id: id(8),
+            tool,
+            confidence,
+            span: None,
+            blob_id,
+            condition: None,
+        };
+        EdgeBatch {
+            edges: vec![
+                Edge {
+                    from: NodeId {
+                        kind: NodeKind::File,
+                        id: "src/lib.rs".to_string(),
+                    },
+                    to: NodeId {
+                        kind: NodeKind::Item,
+                        id: "parse".to_string(),
+                    },
+                    label: EdgeLabel::Calls,
+                    weight: Some(1500),
+                    evidence: evidence(EvidenceTool::Parser, Confidence::High, None),
+                },
+                Edge {
+                    from: NodeId {
+                        kind: NodeKind::Module,
+                        id: "a".to_string(),
+                    },
+                    to: NodeId {
+                        kind: NodeKind::Module,
+                        id: "b".to_string(),
+                    },
+                    label: EdgeLabel::DerivedFrom,
+                    weight: None,
+                    evidence: evidence(EvidenceTool::Human, Confidence::Low, Some(id(9))),
+                },
+            ],
+            created_at: 1_700_000_200,
+        }
+    }
+
+    #[test]
+    fn test_decodes_untagged_commit() {
+        assert_eq!(decode::<Commit>(COMMIT_V0), commit());
+    }
+
+    #[test]
+    fn test_decodes_untagged_work_commit_and_payload() {
+        let work: WorkCommit = decode(WORK_COMMIT_V0);
+        assert!(work.parents.is_empty());
+        assert_eq!(work.base, id(7));
+        assert_eq!(work.session_id, "s1");
+        assert_eq!(work.step_kind, StepKind::FileRead);
+        assert_eq!(work.session_state, SessionState::Running);
+        assert_eq!(work.task_description, "fix parser");
+        assert_eq!(work.author, None);
+        assert_eq!(decode_observations(&work.payload).unwrap(), observations());
+    }
+
+    #[test]
+    fn test_decodes_untagged_edge_batch() {
+        assert_eq!(decode::<EdgeBatch>(EDGE_BATCH_V0), edge_batch());
+    }
+
+    #[test]
+    fn test_tagged_layout_round_trips() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+
+        let mut commit = commit();
+        commit.parents.clear();
+        commit.task = Some("fix parser".to_string());
+        let id = store.put_typed(&commit).unwrap();
+        assert_eq!(store.get_typed::<Commit>(id).unwrap(), commit);
+
+        let batch = edge_batch();
+        let id = store.put_typed(&batch).unwrap();
+        assert_eq!(store.get_typed::<EdgeBatch>(id).unwrap(), batch);
+
+        // JSON keeps the plain field layout
+        let json = serde_json::to_value(&commit).unwrap();
+        assert_eq!(json["task"], "fix parser");
+        assert_eq!(serde_json::from_value::<Commit>(json).unwrap(), commit);
+    }
+
+    #[test]
+    fn test_rejects_newer_layout() {
+        let mut bytes = postcard::to_allocvec(&commit()).unwrap();
+        let newer = postcard::to_allocvec(&(LAYOUT_TAG_BASE + LAYOUT_VERSION + 1)).unwrap();
+        let current = postcard::to_allocvec(&(LAYOUT_TAG_BASE + LAYOUT_VERSION)).unwrap();
+        assert_eq!(&bytes[..current.len()], &current[..]);
+        bytes.splice(..current.len(), newer);
+
+        let err = postcard::from_bytes::<Commit>(&bytes).unwrap_err();
+        assert!(matches!(err, postcard::Error::SerdeDeCustom));
+    }
+}
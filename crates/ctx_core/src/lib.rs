@@ -23,6 +23,13 @@
 //! assert_eq!(data, b"hello world");
 //! ```
 //!
+//! For agent integrations, [`Ctx`] wraps a repository behind a small
+//! chainable API; `use ctx_core::prelude::*` brings it and the common types
+//! into scope.
+//!
+//! The `tokio` cargo feature adds `AsyncCtxRepo`, which runs repository IO
+//! on tokio's blocking pool so async agents don't stall their executor.
+//!
 //! # Features
 //!
 //! ## Content-Addressed Storage
@@ -67,52 +74,191 @@
 //! assert_eq!(loaded, config);
 //! ```
 
+#[cfg(feature = "tokio")]
+mod async_repo;
+mod audit;
+mod backend;
+mod backup;
+mod bench;
+mod bloom;
+mod cache;
+mod capabilities;
 mod cargo;
+mod cfg;
+mod chunking;
+mod citation;
+mod clone;
 mod config;
+mod decision;
+mod diff;
+mod digest;
+mod document;
+mod dry_run;
+mod du;
 mod error;
+mod events;
+mod export;
+mod facade;
+mod fact;
+mod feedback;
 mod gc;
+mod glob;
+mod glossary;
 mod graph;
+mod health;
+mod heuristic;
+mod hooks;
+mod ignore;
+mod impact;
+mod import;
 mod index;
+mod index_lock;
+mod large_file;
+mod layout;
+mod log;
 mod lsp;
+mod maintenance;
+mod metrics;
+mod model;
 mod narrative;
+mod narrative_search;
+mod notes;
 mod object_id;
 mod object_store;
 mod pack;
+mod pack_record;
+mod policy;
+pub mod prelude;
+mod progress;
+mod prune;
+mod qa;
 mod refs;
+mod rename;
 mod repo;
 mod session;
+mod session_handler;
+mod session_summary;
 mod staging;
+mod status;
+mod summary;
+mod transcript;
 mod types;
 mod verify;
+mod workspace;
 
+#[cfg(feature = "tokio")]
+pub use async_repo::{AsyncCtxRepo, AsyncObjectStore, ObservationQueue, QueuedObservation};
+pub use audit::{AuditConfig, AuditedChunk, RetrievalRecord};
+#[cfg(feature = "s3")]
+pub use backend::S3Backend;
+pub use backend::{FsBackend, ObjectBackend};
+pub use backup::{BackupInfo, RestoreReport, BACKUP_DIRECTORY};
+pub use bench::{
+    parse_expectations, parse_retrieval_config, BenchQuery, BenchQueryReport, BenchReport,
+    BenchRun, BenchTotals,
+};
+pub use cache::PackCache;
+pub use capabilities::Capabilities;
 pub use cargo::{
-    CargoAnalysisReport, CargoMetadataSnapshot, DepKind, DepKindInfo, Package, PackageDep, Resolve,
-    ResolveNode, ResolvedDep, Target, TargetKind,
+    CargoAnalysisReport, CargoMetadataSnapshot, DepKind, DepKindInfo, DependencyChain, Package,
+    PackageDep, Resolve, ResolveNode, ResolvedDep, Target, TargetKind,
 };
+pub use citation::Citation;
+pub use clone::CloneReport;
 pub use config::{
-    CleanupReport, Config, GcConfig as ConfigGcConfig, SearchConfig, SessionConfig,
-    StaleSessionConfig, StaleSessionStatus, StorageConfig,
+    AnalysisConfig, BackupConfig, CacheConfig, CleanupReport, Config, ExpiredSessionPolicy,
+    FlushPolicy, GcConfig as ConfigGcConfig, HealthConfig, SearchConfig, SessionConfig,
+    StaleSessionConfig, StaleSessionStatus, StorageConfig, SummaryConfig,
+};
+pub use decision::{Decision, DecisionLog, DecisionSource};
+pub use diff::{ChangeStatus, CommitDiff, PathChange};
+pub use digest::{DigestSession, WeeklyDigest};
+pub use document::{builtin_types, Document, DocumentType, FieldSchema};
+pub use dry_run::{DryRunCommit, DryRunObject, DryRunRefUpdate, DryRunReport};
+pub use du::{BlobUsage, CategoryUsage, ObjectCategory, PathUsage, StorageReport, UsageTotals};
+pub use error::{Capability, CtxError, LockKind, Result};
+pub use events::{Event, EventKind, EventPage};
+pub use export::{
+    export_corpus, export_dataset, export_tree, CorpusCommit, CorpusConfig, CorpusEdge,
+    CorpusRecord, CorpusReport, DatasetChunk, DatasetConfig, DatasetFileChange, DatasetRecord,
+    DatasetReport, FileChangeKind, Redactor, REDACTED,
+};
+pub use facade::Ctx;
+pub use fact::{Fact, FactEvidence, FactSet};
+pub use feedback::{Feedback, FeedbackLog, FeedbackScore, FEEDBACK_STEP, MAX_FEEDBACK_ADJUSTMENT};
+pub use gc::{gc, AutoGcReport, GcConfig, GcReport};
+pub use glossary::{
+    extract_candidates, Glossary, GlossaryCandidate, GlossaryEntry, GlossarySource,
+    DEFAULT_MIN_OCCURRENCES,
 };
-pub use error::{CtxError, Result};
-pub use gc::{gc, GcConfig, GcReport};
 pub use graph::{
-    adjacency_to_dot, compute_scc, expand_from_seeds, expansion_to_dot, AdjacencyList,
-    ExpansionConfig, ExpansionResult, SccId, SccView,
+    adjacency_to_dot, compute_scc, diff_graphs, expand_from_seeds, expansion_to_dot, find_orphans,
+    is_entry_point_declaration, shortest_paths, AdjacencyList, DiffEdge, EdgeDecayConfig,
+    EdgeGroupDiff, ExpansionConfig, ExpansionResult, ExpansionStep, GraphDiff, GraphPath,
+    OrphanItem, PathHop, SccId, SccView,
+};
+pub use health::{
+    GrowthHealth, HealthLevel, HealthReport, IndexHealth, ObjectHealth, SessionHealth, StageHealth,
+};
+pub use hooks::{HookEvent, HooksConfig};
+pub use ignore::{IgnoreRules, IGNORE_FILE};
+pub use impact::{impact_analysis, ImpactConfig, ImpactReport};
+pub use import::{ImportFormat, IMPORT_DIRECTORY, IMPORT_KIND};
+pub use index::{
+    CommitInfo, EdgeDirection, FrecencyEntry, Index, MergedEdge, NameNamespace,
+    INDEX_SCHEMA_VERSION,
 };
-pub use index::{CommitInfo, EdgeDirection, Index, NameNamespace, INDEX_SCHEMA_VERSION};
-pub use lsp::{AnalyzedItem, CallInfo, FileAnalysis, ItemKind, RustAnalyzer};
-pub use narrative::{NarrativeSpace, TaskInfo};
+pub use large_file::{
+    is_binary, load_content, ContentLimits, ContentPolicy, FileContent, LargeFile,
+};
+pub use log::{CommitLog, CommitTypeFilter, LogFilter, PathHistoryEntry};
+pub use lsp::{
+    AnalysisWarning, AnalyzedItem, AnalyzerPool, CallInfo, FileAnalysis, ItemKind, RustAnalyzer,
+    RustCall, RustFileSnapshot, RustSnapshot, RustSymbol, SourceRange,
+};
+pub use maintenance::{Maintenance, MaintenanceConfig, MaintenanceReport};
+pub use metrics::{CounterMetric, HistogramMetric, Metrics, MetricsRegistry, NoopMetrics};
+pub use model::{builtin_profiles, ModelProfile, PackFormat, Tokenizer};
+pub use narrative::{DocumentInfo, NarrativeSpace, TaskInfo};
+pub use narrative_search::NarrativeHit;
+pub use notes::{Annotation, NoteTable};
 pub use object_id::ObjectId;
-pub use object_store::ObjectStore;
+pub use object_store::{BlobReader, Grafts, ObjectStore};
 pub use pack::{
-    build_pack, estimate_tokens, parse_query_for_seeds, ChunkKind, GraphContext, PromptPack,
-    RetrievalConfig, RetrievedChunk, TokenBudget,
+    estimate_tokens, parse_query_for_seeds, AuthorFilter, CandidateExplanation, ChunkKind,
+    GraphContext, LayerBudgets, LayerKind, PackCursor, PackExplanation, PackLayer, PackSession,
+    PackStreamItem, PagedPack, PromptPack, RetrievalConfig, RetrievedChunk, SeedExplanation,
+    TokenBudget, UnchangedChunk,
 };
-pub use refs::Refs;
-pub use repo::{AnalysisReport, CtxRepo, FileAnalysisReport};
+pub use pack_record::{CitedChunk, PackHistoryEntry, PackRecord};
+pub use policy::{ExecConfig, ExecDecision, ExecMode, ExecPolicy, ExecPrompt};
+pub use progress::{CancellationToken, Progress, ProgressSink};
+pub use prune::{PruneReport, PruneUndoReport};
+pub use qa::{QaLog, QaPair};
+pub use refs::{RefUpdate, ReflogEntry, Refs};
+pub use repo::{AnalysisReport, CtxRepo, FileAnalysisReport, IndexGuard};
 pub use session::Session;
+pub use session_handler::{
+    apply_actions, MessageKind, PendingAction, SessionAction, SessionEvent, SessionHandler,
+    SessionResponse, UserChoice,
+};
+pub use session_summary::{
+    ActorActivity, CommandRun, CommandSummaryProvider, HeuristicSummaryProvider, SessionDigest,
+    SessionSummary, SummaryProvider,
+};
+pub use status::{
+    CargoStatus, HeadStatus, IndexFreshness, LockStatus, ObjectStatus, SessionStatus, StatusReport,
+};
+pub use summary::{
+    CommandSummarizer, HeuristicSummarizer, SummarizeReport, Summarizer, Summary, SummaryTable,
+};
+pub use transcript::{Transcript, TranscriptMessage, TranscriptRetention, Turn};
 pub use types::*;
-pub use verify::{recover_staging, verify, VerifyConfig, VerifyReport};
+pub use verify::{
+    recover_staging, recover_staging_summary, verify, RecoveredStep, RecoverySummary,
+    StepDisposition, VerifyConfig, VerifyReport, VerifySample,
+};
+pub use workspace::RepoScope;
 
 /// Time provider trait for testing.
 ///
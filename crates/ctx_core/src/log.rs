@@ -0,0 +1,314 @@
+//! Commit history traversal with filters.
+
+use crate::error::{CtxError, Result};
+use crate::pack::AuthorFilter;
+use crate::staging::lookup_tree_path;
+use crate::types::{AgentIdentity, Commit, CommitType, EdgeBatch, EdgeLabel, NodeKind};
+use crate::{ObjectId, ObjectStore};
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+
+/// Matches commits by how they were created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitTypeFilter {
+    /// Normal task completion.
+    Normal,
+    /// Abandoned sessions.
+    Abandoned,
+    /// Sessions auto-compacted due to staleness.
+    StaleAutoCompact,
+    /// Sessions compacted because a new task started.
+    InterruptedByNewTask,
+    /// Commits without a recorded type (initial, manual and analysis commits).
+    Untyped,
+}
+
+impl CommitTypeFilter {
+    /// Returns true if a commit with `commit_type` matches.
+    pub fn matches(&self, commit_type: Option<&CommitType>) -> bool {
+        matches!(
+            (self, commit_type),
+            (CommitTypeFilter::Normal, Some(CommitType::Normal))
+                | (CommitTypeFilter::Abandoned, Some(CommitType::Abandoned))
+                | (
+                    CommitTypeFilter::StaleAutoCompact,
+                    Some(CommitType::StaleAutoCompact { .. })
+                )
+                | (
+                    CommitTypeFilter::InterruptedByNewTask,
+                    Some(CommitType::InterruptedByNewTask { .. })
+                )
+                | (CommitTypeFilter::Untyped, None)
+        )
+    }
+}
+
+impl FromStr for CommitTypeFilter {
+    type Err = CtxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "normal" => Ok(CommitTypeFilter::Normal),
+            "abandoned" => Ok(CommitTypeFilter::Abandoned),
+            "stale" | "stale-auto-compact" => Ok(CommitTypeFilter::StaleAutoCompact),
+            "interrupted" | "interrupted-by-new-task" => {
+                Ok(CommitTypeFilter::InterruptedByNewTask)
+            }
+            "untyped" => Ok(CommitTypeFilter::Untyped),
+            _ => Err(CtxError::InvalidArgument(format!(
+                "unknown commit type '{}' (expected normal, abandoned, stale, interrupted, untyped)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Filters applied by [`CommitLog`]. All set filters must match.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// Only commits of this type.
+    pub commit_type: Option<CommitTypeFilter>,
+    /// Only commits at or after this Unix timestamp.
+    pub since: Option<u64>,
+    /// Only commits at or before this Unix timestamp.
+    pub until: Option<u64>,
+    /// Only commits that changed this path relative to their first parent.
+    pub path: Option<String>,
+    /// Only commits by matching authors.
+    pub author: AuthorFilter,
+}
+
+impl LogFilter {
+    /// Returns true if `commit` passes every filter.
+    ///
+    /// Loading trees for the path filter requires the object store.
+    pub fn matches(&self, commit: &Commit, object_store: &ObjectStore) -> Result<bool> {
+        if let Some(commit_type) = &self.commit_type {
+            if !commit_type.matches(commit.commit_type.as_ref()) {
+                return Ok(false);
+            }
+        }
+        if self
+            .since
+            .is_some_and(|since| commit.timestamp_unix < since)
+        {
+            return Ok(false);
+        }
+        if self
+            .until
+            .is_some_and(|until| commit.timestamp_unix > until)
+        {
+            return Ok(false);
+        }
+        if !self.author.matches(commit.author.as_ref()) {
+            return Ok(false);
+        }
+        if let Some(path) = &self.path {
+            return touches_path(commit, path, object_store);
+        }
+        Ok(true)
+    }
+}
+
+/// Returns true if `commit` changed `path` relative to its first parent.
+///
+/// Root commits touch every path they contain.
+pub(crate) fn touches_path(
+    commit: &Commit,
+    path: &str,
+    object_store: &ObjectStore,
+) -> Result<bool> {
+    let current = lookup_tree_path(commit.root_tree, path, object_store)?;
+    let previous = match object_store.first_parent(commit) {
+        Some(parent_id) => {
+            let parent: Commit = object_store.get_typed(parent_id)?;
+            lookup_tree_path(parent.root_tree, path, object_store)?
+        }
+        None => None,
+    };
+    Ok(current != previous)
+}
+
+/// A commit that wrote a given path, as returned by
+/// [`CtxRepo::path_history`](crate::CtxRepo::path_history).
+#[derive(Debug, Clone)]
+pub struct PathHistoryEntry {
+    /// The commit that wrote the path.
+    pub commit_id: ObjectId,
+    /// Commit timestamp.
+    pub timestamp_unix: u64,
+    /// Commit message.
+    pub message: String,
+    /// Author of the commit, if recorded.
+    pub author: Option<AgentIdentity>,
+    /// Blob the path pointed to after the commit.
+    pub blob_id: ObjectId,
+    /// Blob the path pointed to in the first parent (None if newly added).
+    pub previous_blob_id: Option<ObjectId>,
+    /// Path the file was moved from in this commit, if it was renamed.
+    pub renamed_from: Option<String>,
+}
+
+/// Compares `path` in `commit` against its first parent.
+///
+/// Returns `Some` only when the commit contains the path and its content
+/// differs from the parent's.
+pub(crate) fn path_change(
+    commit_id: ObjectId,
+    commit: &Commit,
+    path: &str,
+    object_store: &ObjectStore,
+) -> Result<Option<PathHistoryEntry>> {
+    let Some(blob_id) = lookup_tree_path(commit.root_tree, path, object_store)? else {
+        return Ok(None);
+    };
+    let previous_blob_id = match object_store.first_parent(commit) {
+        Some(parent_id) => {
+            let parent: Commit = object_store.get_typed(parent_id)?;
+            lookup_tree_path(parent.root_tree, path, object_store)?
+        }
+        None => None,
+    };
+    if previous_blob_id == Some(blob_id) {
+        return Ok(None);
+    }
+
+    Ok(Some(PathHistoryEntry {
+        commit_id,
+        timestamp_unix: commit.timestamp_unix,
+        message: commit.message.clone(),
+        author: commit.author.clone(),
+        blob_id,
+        previous_blob_id,
+        renamed_from: renamed_into(commit, path, object_store)?,
+    }))
+}
+
+/// The path `commit` records as moved to `path`, if any.
+fn renamed_into(commit: &Commit, path: &str, object_store: &ObjectStore) -> Result<Option<String>> {
+    for batch_id in &commit.edge_batches {
+        let batch: EdgeBatch = object_store.get_typed(*batch_id)?;
+        let from = batch.edges.into_iter().find(|edge| {
+            edge.label == EdgeLabel::RenamedTo
+                && edge.to.kind == NodeKind::File
+                && edge.to.id == path
+        });
+        if let Some(edge) = from {
+            return Ok(Some(edge.from.id));
+        }
+    }
+    Ok(None)
+}
+
+/// Iterator over commits reachable from a starting commit.
+///
+/// Visits commits breadth-first along parent links (newest first for linear
+/// history), yielding only those that pass the [`LogFilter`]. Created with
+/// [`CtxRepo::log`](crate::CtxRepo::log).
+pub struct CommitLog<'a> {
+    object_store: &'a ObjectStore,
+    filter: LogFilter,
+    queue: VecDeque<ObjectId>,
+    seen: HashSet<ObjectId>,
+}
+
+impl<'a> CommitLog<'a> {
+    /// Creates a log walking back from `start`.
+    pub fn new(object_store: &'a ObjectStore, start: ObjectId, filter: LogFilter) -> Self {
+        Self {
+            object_store,
+            filter,
+            queue: VecDeque::from([start]),
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl Iterator for CommitLog<'_> {
+    type Item = Result<(ObjectId, Commit)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(id) = self.queue.pop_front() {
+            if !self.seen.insert(id) {
+                continue;
+            }
+
+            let commit: Commit = match self.object_store.get_typed(id) {
+                Ok(commit) => commit,
+                Err(e) => return Some(Err(e)),
+            };
+            self.queue.extend(self.object_store.parents(&commit));
+
+            match self.filter.matches(&commit, self.object_store) {
+                Ok(true) => return Some(Ok((id, commit))),
+                Ok(false) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CtxRepo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_commit_type_filter_parse() {
+        assert_eq!(
+            "stale".parse::<CommitTypeFilter>().unwrap(),
+            CommitTypeFilter::StaleAutoCompact
+        );
+        assert_eq!(
+            "Normal".parse::<CommitTypeFilter>().unwrap(),
+            CommitTypeFilter::Normal
+        );
+        assert!("bogus".parse::<CommitTypeFilter>().is_err());
+    }
+
+    #[test]
+    fn test_log_filters() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Write main").unwrap();
+        repo.observe_file_write("src/main.rs", b"fn main() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let written = repo.compact_session("Wrote main").unwrap();
+
+        repo.start_session("Give up").unwrap();
+        repo.flush_active_session().unwrap();
+        let abandoned = repo.abort_session("not needed").unwrap();
+
+        let ids = |filter: LogFilter| -> Vec<ObjectId> {
+            repo.log(filter)
+                .unwrap()
+                .map(|entry| entry.unwrap().0)
+                .collect()
+        };
+
+        assert_eq!(ids(LogFilter::default()).len(), 3);
+        assert_eq!(
+            ids(LogFilter {
+                commit_type: Some(CommitTypeFilter::Abandoned),
+                ..Default::default()
+            }),
+            vec![abandoned]
+        );
+        assert_eq!(
+            ids(LogFilter {
+                path: Some("src/main.rs".into()),
+                ..Default::default()
+            }),
+            vec![written]
+        );
+        assert!(ids(LogFilter {
+            since: Some(u64::MAX),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+}
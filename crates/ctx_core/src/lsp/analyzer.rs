@@ -11,6 +11,8 @@ use crate::lsp::protocol::{
     TextDocumentClientCapabilities, Url,
 };
 use crate::lsp::queries::LspQueries;
+use crate::policy::ExecPolicy;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -30,16 +32,18 @@ pub struct RustAnalyzer {
     /// Whether rust-analyzer has completed initial indexing.
     /// Call hierarchy requests should only be made after this is true.
     indexing_complete: bool,
+    /// Items of each file seen so far with their full ranges, for finding
+    /// the item a reference is in.
+    file_scopes: HashMap<Url, Vec<ItemScope>>,
 }
 
 impl RustAnalyzer {
     /// Check if rust-analyzer is available on the system.
     ///
-    /// Returns true if rust-analyzer can be found and executed.
-    pub fn is_available() -> bool {
-        Command::new("rust-analyzer")
-            .arg("--version")
-            .output()
+    /// Returns true if rust-analyzer can be found and `policy` lets it run.
+    pub fn is_available(policy: &ExecPolicy) -> bool {
+        policy
+            .output(Command::new("rust-analyzer").arg("--version"))
             .map(|o| o.status.success())
             .unwrap_or(false)
     }
@@ -51,6 +55,7 @@ impl RustAnalyzer {
     /// # Arguments
     ///
     /// * `project_root` - Root directory of the Rust project (should contain Cargo.toml)
+    /// * `policy` - Execution policy that must allow rust-analyzer
     ///
     /// # Errors
     ///
@@ -58,8 +63,8 @@ impl RustAnalyzer {
     /// - rust-analyzer is not found
     /// - rust-analyzer fails to start
     /// - LSP initialization fails
-    pub fn start(project_root: &Path) -> Result<Self> {
-        let mut client = LspClient::spawn(project_root)?;
+    pub fn start(project_root: &Path, policy: &ExecPolicy) -> Result<Self> {
+        let mut client = LspClient::spawn(project_root, policy)?;
 
         // Initialize LSP connection
         #[allow(deprecated)]
@@ -102,6 +107,7 @@ impl RustAnalyzer {
             file_versions: HashMap::new(),
             file_content_hashes: HashMap::new(),
             indexing_complete: false,
+            file_scopes: HashMap::new(),
         })
     }
 
@@ -200,6 +206,8 @@ impl RustAnalyzer {
 
         // Extract items
         let items = Self::flatten_symbols(&symbols, &abs_path);
+        self.file_scopes
+            .insert(uri.clone(), item_scopes(&symbols, &abs_path));
 
         // Extract call information for functions
         // Only attempt call hierarchy if rust-analyzer has completed indexing
@@ -280,6 +288,7 @@ impl RustAnalyzer {
         // Only attempt references if rust-analyzer has completed indexing
         // References require full project analysis, so they're more sensitive to timing
         let mut references = Vec::new();
+        let mut uses_types = Vec::new();
         if self.indexing_complete {
             // Give rust-analyzer a moment after document_symbols to process
             thread::sleep(Duration::from_millis(100));
@@ -347,6 +356,36 @@ impl RustAnalyzer {
                         })
                         .collect();
 
+                    // Items whose code mentions a type use it
+                    if matches!(
+                        item.kind,
+                        ItemKind::Struct | ItemKind::Enum | ItemKind::Trait
+                    ) {
+                        for loc in &reference_locs {
+                            let Some(user) =
+                                enclosing_item(&mut queries, &mut self.file_scopes, loc)
+                            else {
+                                continue;
+                            };
+                            if user == item.qualified_name
+                                || uses_types.iter().any(|u: &TypeUseInfo| {
+                                    u.user == user && u.used_type == item.qualified_name
+                                })
+                            {
+                                continue;
+                            }
+                            uses_types.push(TypeUseInfo {
+                                user,
+                                use_location: loc.clone(),
+                                used_type: item.qualified_name.clone(),
+                                type_location: crate::lsp::protocol::Location {
+                                    uri: uri.clone(),
+                                    range: item.range,
+                                },
+                            });
+                        }
+                    }
+
                     if !reference_locs.is_empty() {
                         references.push(ReferenceInfo {
                             referenced_item: item.qualified_name.clone(),
@@ -364,17 +403,40 @@ impl RustAnalyzer {
             warnings.push(AnalysisWarning::ReferencesSkipped);
         }
 
-        // Extract trait implementation information for structs/enums
-        // Note: rust-analyzer doesn't support textDocument/prepareTypeHierarchy
-        // We would need to parse impl blocks manually or use goto_implementation
-        // For now, skip trait implementation extraction
-        let implements = Vec::new();
+        // Extract trait implementations from impl blocks. rust-analyzer
+        // doesn't support textDocument/prepareTypeHierarchy, so the trait
+        // and type come from the block's name ("impl Display for Foo") and
+        // the trait is located by going to the definition of its name.
+        let mut implements = Vec::new();
+        for item in &items {
+            let Some((trait_name, implementor)) = parse_impl_name(&item.name) else {
+                continue;
+            };
+            let impl_location = crate::lsp::protocol::Location {
+                uri: uri.clone(),
+                range: item.range,
+            };
+            let trait_location = if self.indexing_complete {
+                find_in_source(&content, item.range.start.line, &trait_name)
+                    .and_then(|position| queries.goto_definition(&uri, position).ok())
+                    .and_then(|locations| locations.into_iter().next())
+            } else {
+                None
+            };
+            implements.push(ImplementsInfo {
+                implementor,
+                implementor_location: impl_location.clone(),
+                trait_name,
+                trait_location: trait_location.unwrap_or(impl_location),
+            });
+        }
 
         Ok(FileAnalysis {
             items,
             calls,
             references,
             implements,
+            uses_types,
             warnings,
         })
     }
@@ -474,11 +536,168 @@ impl RustAnalyzer {
     }
 }
 
+/// An item with the full range of its definition.
+#[derive(Debug, Clone)]
+struct ItemScope {
+    name: String,
+    qualified_name: String,
+    kind: ItemKind,
+    range: Range,
+}
+
+/// Returns the items of a file with their full ranges, named as
+/// [`RustAnalyzer::flatten_symbols`] names them.
+fn item_scopes(symbols: &[DocumentSymbol], path: &Path) -> Vec<ItemScope> {
+    let mut full_ranges = Vec::new();
+    collect_full_ranges(symbols, &mut full_ranges);
+    RustAnalyzer::flatten_symbols(symbols, path)
+        .into_iter()
+        .zip(full_ranges)
+        .map(|(item, range)| ItemScope {
+            name: item.name,
+            qualified_name: item.qualified_name,
+            kind: item.kind,
+            range,
+        })
+        .collect()
+}
+
+/// Collects symbols' full ranges in the order `flatten_symbols` visits them.
+fn collect_full_ranges(symbols: &[DocumentSymbol], out: &mut Vec<Range>) {
+    for sym in symbols {
+        out.push(sym.range);
+        if let Some(children) = &sym.children {
+            collect_full_ranges(children, out);
+        }
+    }
+}
+
+/// Returns the innermost item, other than an impl block, whose definition
+/// contains `location`, loading the symbols of its file if needed.
+fn enclosing_item(
+    queries: &mut LspQueries<'_>,
+    file_scopes: &mut HashMap<Url, Vec<ItemScope>>,
+    location: &crate::lsp::protocol::Location,
+) -> Option<String> {
+    if !file_scopes.contains_key(&location.uri) {
+        let path = RustAnalyzer::uri_to_path(&location.uri)?;
+        let symbols = queries.document_symbols(&location.uri).ok()?;
+        file_scopes.insert(location.uri.clone(), item_scopes(&symbols, &path));
+    }
+    innermost_scope(&file_scopes[&location.uri], &location.range)
+        .map(|scope| scope.qualified_name.clone())
+}
+
+/// Returns the smallest scope containing `range`, skipping impl blocks,
+/// whose items are attributed to their methods.
+fn innermost_scope<'a>(scopes: &'a [ItemScope], range: &Range) -> Option<&'a ItemScope> {
+    let position_key = |p: &Position| (p.line, p.character);
+    scopes
+        .iter()
+        .filter(|scope| scope.kind != ItemKind::Impl && !is_impl_block(&scope.name))
+        .filter(|scope| {
+            position_key(&scope.range.start) <= position_key(&range.start)
+                && position_key(&range.end) <= position_key(&scope.range.end)
+        })
+        .max_by_key(|scope| position_key(&scope.range.start))
+}
+
+/// Returns true if `name` is the symbol name of an impl block.
+fn is_impl_block(name: &str) -> bool {
+    name.strip_prefix("impl")
+        .is_some_and(|rest| rest.starts_with([' ', '<']))
+}
+
+/// Splits an impl block's symbol name, such as
+/// `impl<T: Clone> fmt::Display for Wrapper<T>`, into the trait and type
+/// names (`Display`, `Wrapper`). Returns None for inherent impls.
+fn parse_impl_name(name: &str) -> Option<(String, String)> {
+    if !is_impl_block(name) {
+        return None;
+    }
+    let rest = &name["impl".len()..];
+    let rest = if rest.starts_with('<') {
+        &rest[generics_end(rest)?..]
+    } else {
+        rest
+    };
+
+    // Split at the first " for " outside angle brackets
+    let mut depth = 0i32;
+    let mut split = None;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            _ if depth == 0 && rest[i..].starts_with(" for ") => {
+                split = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let split = split?;
+    let trait_name = path_name(rest[..split].trim().trim_start_matches('!'));
+    let type_name = path_name(rest[split + " for ".len()..].trim());
+    (!trait_name.is_empty() && !type_name.is_empty()).then_some((trait_name, type_name))
+}
+
+/// Returns the byte index just past the generics that `s` starts with.
+fn generics_end(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the last segment of a path without generic arguments or
+/// references: `&mut std::fmt::Formatter<'_>` becomes `Formatter`.
+fn path_name(path: &str) -> String {
+    let mut path = path.trim_start_matches(['&', ' ']);
+    if path.starts_with('\'') {
+        // A reference's lifetime
+        path = path
+            .split_once(' ')
+            .map_or(path, |(_, rest)| rest.trim_start());
+    }
+    let path = path.trim_start_matches("mut ");
+    let path = path.split('<').next().unwrap_or(path).trim();
+    path.rsplit("::").next().unwrap_or(path).to_string()
+}
+
+/// Finds where `name` first appears as a word in `content`, searching from
+/// line `from_line` on. Columns are UTF-16 code units, as LSP expects.
+fn find_in_source(content: &str, from_line: u32, name: &str) -> Option<Position> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    for (line_no, line) in content.lines().enumerate().skip(from_line as usize) {
+        for (i, _) in line.match_indices(name) {
+            let before = line[..i].chars().next_back();
+            let after = line[i + name.len()..].chars().next();
+            if before.is_some_and(is_ident) || after.is_some_and(is_ident) {
+                continue;
+            }
+            let character = line[..i].encode_utf16().count() as u32;
+            return Some(Position::new(line_no as u32, character));
+        }
+    }
+    None
+}
+
 /// Warning generated during analysis.
 ///
 /// Warnings indicate that some analysis was skipped or incomplete,
 /// but the analysis could still produce useful partial results.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AnalysisWarning {
     /// Timed out waiting for rust-analyzer to complete initial indexing.
     DiagnosticsTimeout,
@@ -518,6 +737,8 @@ pub struct FileAnalysis {
     pub references: Vec<ReferenceInfo>,
     /// Trait implementation relationships (type implements trait).
     pub implements: Vec<ImplementsInfo>,
+    /// Types defined in the file and the items that use them.
+    pub uses_types: Vec<TypeUseInfo>,
     /// Warnings generated during analysis.
     ///
     /// If non-empty, the analysis results may be incomplete.
@@ -540,7 +761,7 @@ pub struct AnalyzedItem {
 }
 
 /// Kind of code item.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ItemKind {
     /// Function or free function.
     Function,
@@ -603,6 +824,19 @@ pub struct ImplementsInfo {
     pub trait_location: crate::lsp::protocol::Location,
 }
 
+/// Information about an item using a type.
+#[derive(Debug, Clone)]
+pub struct TypeUseInfo {
+    /// Name of the item whose code mentions the type.
+    pub user: String,
+    /// Where the type is mentioned.
+    pub use_location: crate::lsp::protocol::Location,
+    /// Name of the type (struct, enum or trait).
+    pub used_type: String,
+    /// Location of the type definition.
+    pub type_location: crate::lsp::protocol::Location,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -610,7 +844,7 @@ mod tests {
     #[test]
     fn test_is_available() {
         // This test will pass if rust-analyzer is installed, fail otherwise
-        let available = RustAnalyzer::is_available();
+        let available = RustAnalyzer::is_available(&ExecPolicy::default());
         eprintln!("rust-analyzer available: {}", available);
     }
 
@@ -630,13 +864,72 @@ mod tests {
         assert_eq!(path, PathBuf::from("/tmp/test.rs"));
     }
 
+    #[test]
+    fn test_parse_impl_name() {
+        let parse = |name| parse_impl_name(name);
+        assert_eq!(
+            parse("impl Display for MyStruct"),
+            Some(("Display".to_string(), "MyStruct".to_string()))
+        );
+        assert_eq!(
+            parse("impl<T: Clone> fmt::Debug for Wrapper<T>"),
+            Some(("Debug".to_string(), "Wrapper".to_string()))
+        );
+        assert_eq!(
+            parse("impl<'a> From<&'a str> for &'a Name"),
+            Some(("From".to_string(), "Name".to_string()))
+        );
+        assert_eq!(
+            parse("impl !Send for Handle"),
+            Some(("Send".to_string(), "Handle".to_string()))
+        );
+        assert_eq!(parse("impl MyStruct"), None);
+        assert_eq!(parse("impl_helper"), None);
+    }
+
+    #[test]
+    fn test_find_in_source() {
+        let content = "struct Fmt;\nimpl fmt::Display for DisplayFmt {}\n";
+        assert_eq!(
+            find_in_source(content, 1, "Display"),
+            Some(Position::new(1, 10))
+        );
+        assert_eq!(find_in_source(content, 0, "Fmt"), Some(Position::new(0, 7)));
+        assert_eq!(find_in_source(content, 1, "Missing"), None);
+    }
+
+    #[test]
+    fn test_innermost_scope() {
+        let range =
+            |start: u32, end: u32| Range::new(Position::new(start, 0), Position::new(end, 0));
+        let scope = |name: &str, kind, range| ItemScope {
+            name: name.rsplit("::").next().unwrap().to_string(),
+            qualified_name: name.to_string(),
+            kind,
+            range,
+        };
+        let scopes = vec![
+            scope("outer", ItemKind::Module, range(0, 20)),
+            scope("impl Widget", ItemKind::Other, range(2, 10)),
+            scope("impl Widget::new", ItemKind::Method, range(3, 5)),
+            scope("helper", ItemKind::Function, range(12, 14)),
+        ];
+
+        let find =
+            |line| innermost_scope(&scopes, &range(line, line)).map(|s| s.qualified_name.as_str());
+        assert_eq!(find(4), Some("impl Widget::new"));
+        assert_eq!(find(8), Some("outer"));
+        assert_eq!(find(13), Some("helper"));
+        assert_eq!(find(30), None);
+    }
+
     // Integration test with real rust-analyzer
     #[test]
     #[ignore]
     fn test_analyze_real_file() {
         use tempfile::TempDir;
 
-        if !RustAnalyzer::is_available() {
+        if !RustAnalyzer::is_available(&ExecPolicy::default()) {
             eprintln!("Skipping: rust-analyzer not installed");
             return;
         }
@@ -671,7 +964,7 @@ fn helper() {
         .unwrap();
 
         // Analyze
-        let mut analyzer = RustAnalyzer::start(tmp.path()).unwrap();
+        let mut analyzer = RustAnalyzer::start(tmp.path(), &ExecPolicy::default()).unwrap();
         let analysis = analyzer
             .analyze_file(&tmp.path().join("src/main.rs"))
             .unwrap();
@@ -5,6 +5,7 @@
 
 use crate::error::{CtxError, Result};
 use crate::lsp::protocol::{InitializeParams, InitializeResult, JsonRpcMessage};
+use crate::policy::ExecPolicy;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
@@ -41,12 +42,13 @@ impl LspClient {
     /// # Arguments
     ///
     /// * `project_root` - Root directory of the Rust project
+    /// * `policy` - Execution policy that must allow rust-analyzer
     ///
     /// # Errors
     ///
     /// Returns error if rust-analyzer cannot be found or fails to start.
-    pub fn spawn(project_root: &Path) -> Result<Self> {
-        Self::spawn_with_timeout(project_root, DEFAULT_READ_TIMEOUT)
+    pub fn spawn(project_root: &Path, policy: &ExecPolicy) -> Result<Self> {
+        Self::spawn_with_timeout(project_root, DEFAULT_READ_TIMEOUT, policy)
     }
 
     /// Spawn rust-analyzer for a project with custom timeout.
@@ -55,23 +57,31 @@ impl LspClient {
     ///
     /// * `project_root` - Root directory of the Rust project
     /// * `read_timeout` - Timeout for reading LSP messages
+    /// * `policy` - Execution policy that must allow rust-analyzer
     ///
     /// # Errors
     ///
-    /// Returns error if rust-analyzer cannot be found or fails to start.
-    pub fn spawn_with_timeout(project_root: &Path, read_timeout: Duration) -> Result<Self> {
-        let mut child = Command::new("rust-analyzer")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped()) // Capture stderr for debugging
-            .current_dir(project_root)
-            .spawn()
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::NotFound {
+    /// Returns error if rust-analyzer cannot be found, fails to start, or
+    /// is refused by `policy`.
+    pub fn spawn_with_timeout(
+        project_root: &Path,
+        read_timeout: Duration,
+        policy: &ExecPolicy,
+    ) -> Result<Self> {
+        let mut child = policy
+            .spawn(
+                Command::new("rust-analyzer")
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped()) // Capture stderr for debugging
+                    .current_dir(project_root),
+            )
+            .map_err(|e| match e {
+                CtxError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
                     CtxError::RustAnalyzerNotFound
-                } else {
-                    CtxError::RustAnalyzerStartFailed(e.to_string())
                 }
+                CtxError::Io(e) => CtxError::RustAnalyzerStartFailed(e.to_string()),
+                other => other,
             })?;
 
         let stdin = BufWriter::new(
@@ -462,7 +472,7 @@ edition = "2021"
         .unwrap();
 
         // Try to spawn rust-analyzer
-        let mut client = match LspClient::spawn(tmp.path()) {
+        let mut client = match LspClient::spawn(tmp.path(), &ExecPolicy::default()) {
             Ok(client) => client,
             Err(CtxError::RustAnalyzerNotFound) => {
                 eprintln!("Skipping test: rust-analyzer not installed");
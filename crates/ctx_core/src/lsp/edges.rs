@@ -3,10 +3,12 @@
 //! This module bridges the gap between LSP protocol types and CTX's graph representation,
 //! generating high-confidence edges from rust-analyzer's semantic analysis.
 
+use crate::cfg::{all_of, declaration_condition};
 use crate::lsp::analyzer::{FileAnalysis, ItemKind};
 use crate::lsp::protocol::Range;
 use crate::types::{Confidence, Edge, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind, Span};
 use crate::ObjectId;
+use std::collections::HashMap;
 use tracing::debug;
 
 #[cfg(test)]
@@ -29,6 +31,11 @@ use crate::lsp::analyzer::CallInfo;
 /// Vector of edges representing:
 /// - File --Defines--> Item (for each symbol in the file)
 /// - Item --Calls--> Item (for resolved function calls)
+/// - File --References--> Item (for each reference to an item in the file)
+/// - Item --Implements--> Trait (for trait impl blocks)
+/// - Item --UsesType--> Item (for items mentioning a type defined in the file)
+///
+/// See [`build_module_edges`] for the module hierarchy.
 pub fn build_edges_from_analysis(
     analysis: &FileAnalysis,
     file_path: &str,
@@ -40,6 +47,7 @@ pub fn build_edges_from_analysis(
     // Compute ObjectIds for this file
     let file_id = ObjectId::hash_blob(file_path.as_bytes());
     let file_version_id = ObjectId::hash_blob(file_content);
+    let conditions = item_conditions(analysis, file_content);
 
     // Generate Defines edges: File -> Item
     for item in &analysis.items {
@@ -61,6 +69,7 @@ pub fn build_edges_from_analysis(
                 confidence: Confidence::High,
                 span: Some(lsp_range_to_span(&item.range, file_id, file_version_id)),
                 blob_id: Some(file_version_id),
+                condition: conditions.get(item.qualified_name.as_str()).cloned(),
             },
         });
     }
@@ -89,6 +98,7 @@ pub fn build_edges_from_analysis(
                     file_version_id,
                 )),
                 blob_id: Some(file_version_id),
+                condition: conditions.get(call.caller.as_str()).cloned(),
             },
         });
     }
@@ -145,6 +155,7 @@ pub fn build_edges_from_analysis(
                         ref_file_version_id,
                     )),
                     blob_id: Some(ref_file_version_id),
+                    condition: None,
                 },
             });
         }
@@ -200,6 +211,58 @@ pub fn build_edges_from_analysis(
                     impl_file_version_id,
                 )),
                 blob_id: Some(impl_file_version_id),
+                condition: conditions.get(impl_info.implementor.as_str()).cloned(),
+            },
+        });
+    }
+
+    // Generate UsesType edges: Item -> Type, evidenced where the type is
+    // mentioned
+    for type_use in &analysis.uses_types {
+        let use_file_path = if type_use.use_location.uri.scheme() == "file" {
+            type_use.use_location.uri.path()
+        } else {
+            type_use.use_location.uri.as_str()
+        };
+        let use_file_id = ObjectId::hash_blob(use_file_path.as_bytes());
+        let use_file_version_id = if use_file_path == file_path {
+            file_version_id
+        } else {
+            match std::fs::read(use_file_path) {
+                Ok(content) => ObjectId::hash_blob(&content),
+                Err(e) => {
+                    debug!(
+                        path = use_file_path,
+                        error = %e,
+                        "Could not read cross-file type use, using placeholder"
+                    );
+                    ObjectId::hash_blob(b"")
+                }
+            }
+        };
+
+        edges.push(Edge {
+            from: NodeId {
+                kind: NodeKind::Item,
+                id: type_use.user.clone(),
+            },
+            to: NodeId {
+                kind: NodeKind::Item,
+                id: type_use.used_type.clone(),
+            },
+            label: EdgeLabel::UsesType,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool: EvidenceTool::RustAnalyzer,
+                confidence: Confidence::High,
+                span: Some(lsp_range_to_span(
+                    &type_use.use_location.range,
+                    use_file_id,
+                    use_file_version_id,
+                )),
+                blob_id: Some(use_file_version_id),
+                condition: conditions.get(type_use.user.as_str()).cloned(),
             },
         });
     }
@@ -207,6 +270,189 @@ pub fn build_edges_from_analysis(
     edges
 }
 
+/// Generate module hierarchy edges for an analyzed file.
+///
+/// The file's module path follows Cargo's layout from the last `src`
+/// directory in `file_path`: `src/lib.rs` and `src/main.rs` are the crate
+/// root, named after the package directory, and `src/a/b.rs` or
+/// `src/a/b/mod.rs` is `crate::a::b`. Files outside `src` get no edges.
+///
+/// # Returns
+///
+/// Vector of edges representing:
+/// - File --Defines--> Module (the module the file is)
+/// - Module --ChildOf--> Module (for the file's module and inline `mod`s)
+/// - Module --Contains--> Item (for items directly in a module; items
+///   nested in other items, such as methods, are not module members)
+///
+/// Items and inline modules keep the ids [`build_edges_from_analysis`]
+/// gives them, so these edges join its `Defines` edges.
+pub fn build_module_edges(
+    analysis: &FileAnalysis,
+    file_path: &str,
+    file_content: &[u8],
+    commit_id: ObjectId,
+) -> Vec<Edge> {
+    let Some(module) = module_path(file_path) else {
+        return Vec::new();
+    };
+    let file_id = ObjectId::hash_blob(file_path.as_bytes());
+    let file_version_id = ObjectId::hash_blob(file_content);
+    let module_node = NodeId {
+        kind: NodeKind::Module,
+        id: module.clone(),
+    };
+    let conditions = item_conditions(analysis, file_content);
+    let edge =
+        |from: NodeId, to: NodeId, label, range: Option<&Range>, condition: Option<&String>| Edge {
+            from,
+            to,
+            label,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool: EvidenceTool::RustAnalyzer,
+                confidence: Confidence::High,
+                span: range.map(|range| lsp_range_to_span(range, file_id, file_version_id)),
+                blob_id: Some(file_version_id),
+                condition: condition.cloned(),
+            },
+        };
+
+    let mut edges = vec![edge(
+        NodeId {
+            kind: NodeKind::File,
+            id: file_path.to_string(),
+        },
+        module_node.clone(),
+        EdgeLabel::Defines,
+        None,
+        None,
+    )];
+    if let Some((parent, _)) = module.rsplit_once("::") {
+        edges.push(edge(
+            module_node.clone(),
+            NodeId {
+                kind: NodeKind::Module,
+                id: parent.to_string(),
+            },
+            EdgeLabel::ChildOf,
+            None,
+            None,
+        ));
+    }
+
+    for item in &analysis.items {
+        // The innermost item of the file enclosing this one, if any
+        let parent = analysis
+            .items
+            .iter()
+            .filter(|other| {
+                item.qualified_name
+                    .strip_prefix(other.qualified_name.as_str())
+                    .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|other| other.qualified_name.len());
+        let container = match parent {
+            None => module_node.clone(),
+            Some(parent) if parent.kind == ItemKind::Module => NodeId {
+                kind: NodeKind::Module,
+                id: parent.qualified_name.clone(),
+            },
+            Some(_) => continue,
+        };
+        let node = NodeId {
+            kind: node_kind_for_item(item.kind),
+            id: item.qualified_name.clone(),
+        };
+        let condition = conditions.get(item.qualified_name.as_str());
+
+        if item.kind == ItemKind::Module {
+            edges.push(edge(
+                node.clone(),
+                container.clone(),
+                EdgeLabel::ChildOf,
+                Some(&item.range),
+                condition,
+            ));
+        }
+        edges.push(edge(
+            container,
+            node,
+            EdgeLabel::Contains,
+            Some(&item.range),
+            condition,
+        ));
+    }
+
+    edges
+}
+
+/// The `cfg` conditions the file's items are compiled under, keyed by
+/// qualified name: their own `#[cfg(...)]` attributes and those of the
+/// items enclosing them. Unconditional items are left out.
+fn item_conditions<'a>(
+    analysis: &'a FileAnalysis,
+    file_content: &[u8],
+) -> HashMap<&'a str, String> {
+    let source = String::from_utf8_lossy(file_content);
+    let own: HashMap<&str, String> = analysis
+        .items
+        .iter()
+        .filter_map(|item| {
+            declaration_condition(&source, item.range.start.line)
+                .map(|condition| (item.qualified_name.as_str(), condition))
+        })
+        .collect();
+    if own.is_empty() {
+        return own;
+    }
+
+    analysis
+        .items
+        .iter()
+        .filter_map(|item| {
+            let name = item.qualified_name.as_str();
+            let inherited = own
+                .iter()
+                .filter(|(other, _)| {
+                    *other == &name
+                        || name
+                            .strip_prefix(**other)
+                            .is_some_and(|rest| rest.starts_with("::"))
+                })
+                .map(|(_, condition)| condition.clone())
+                .collect();
+            all_of(inherited).map(|condition| (name, condition))
+        })
+        .collect()
+}
+
+/// Module path of the file at `file_path`, or None if it isn't under a
+/// `src` directory.
+fn module_path(file_path: &str) -> Option<String> {
+    let parts: Vec<&str> = file_path.split(['/', '\\']).collect();
+    let src = parts.iter().rposition(|&part| part == "src")?;
+    let mut segments = parts[src + 1..].to_vec();
+    let file = segments.pop()?.strip_suffix(".rs")?;
+    segments.push(file);
+
+    // Binaries in `src/bin` are crates of their own
+    let crate_name = if segments.len() > 1 && segments[0] == "bin" {
+        segments.remove(0);
+        segments.remove(0)
+    } else {
+        src.checked_sub(1).map_or("crate", |i| parts[i])
+    };
+    if matches!(segments[..], ["lib" | "main"]) || segments.last() == Some(&"mod") {
+        segments.pop();
+    }
+
+    let mut path = vec![crate_name.replace('-', "_")];
+    path.extend(segments.iter().map(|segment| segment.to_string()));
+    Some(path.join("::"))
+}
+
 /// Map ItemKind to NodeKind.
 fn node_kind_for_item(item_kind: ItemKind) -> NodeKind {
     match item_kind {
@@ -264,6 +510,7 @@ mod tests {
             calls: vec![],
             references: vec![],
             implements: vec![],
+            uses_types: vec![],
             warnings: vec![],
         };
 
@@ -322,6 +569,7 @@ mod tests {
             }],
             references: vec![],
             implements: vec![],
+            uses_types: vec![],
             warnings: vec![],
         };
 
@@ -430,6 +678,7 @@ mod tests {
                 ],
             }],
             implements: vec![],
+            uses_types: vec![],
             warnings: vec![],
         };
 
@@ -499,6 +748,7 @@ mod tests {
                     },
                 },
             }],
+            uses_types: vec![],
             warnings: vec![],
         };
 
@@ -516,4 +766,168 @@ mod tests {
         assert_eq!(edges[0].evidence.confidence, Confidence::High);
         assert_eq!(edges[0].evidence.tool, EvidenceTool::RustAnalyzer);
     }
+
+    #[test]
+    fn test_uses_type_edge_generation() {
+        use crate::lsp::analyzer::TypeUseInfo;
+        use lsp_types::Url;
+
+        let commit_id = ObjectId::from_bytes([7; 32]);
+        let file_path = "/src/types.rs";
+        let file_content = b"struct Config;\nfn load() -> Config { Config }";
+        let location = |line| Location {
+            uri: Url::parse("file:///src/types.rs").unwrap(),
+            range: Range::new(Position::new(line, 0), Position::new(line, 6)),
+        };
+
+        let analysis = FileAnalysis {
+            items: vec![],
+            calls: vec![],
+            references: vec![],
+            implements: vec![],
+            uses_types: vec![TypeUseInfo {
+                user: "load".to_string(),
+                use_location: location(1),
+                used_type: "Config".to_string(),
+                type_location: location(0),
+            }],
+            warnings: vec![],
+        };
+
+        let edges = build_edges_from_analysis(&analysis, file_path, file_content, commit_id);
+
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].from.id, "load");
+        assert_eq!(edges[0].to.id, "Config");
+        assert_eq!(edges[0].label, EdgeLabel::UsesType);
+        let span = edges[0].evidence.span.as_ref().unwrap();
+        assert_eq!(span.start_line, 1);
+        assert_eq!(
+            edges[0].evidence.blob_id,
+            Some(ObjectId::hash_blob(file_content))
+        );
+    }
+
+    #[test]
+    fn test_module_path() {
+        let path = |p| module_path(p);
+        assert_eq!(path("/w/my-crate/src/lib.rs").as_deref(), Some("my_crate"));
+        assert_eq!(path("/w/app/src/main.rs").as_deref(), Some("app"));
+        assert_eq!(path("/w/app/src/lsp/mod.rs").as_deref(), Some("app::lsp"));
+        assert_eq!(
+            path("/w/app/src/lsp/edges.rs").as_deref(),
+            Some("app::lsp::edges")
+        );
+        assert_eq!(path("/w/app/src/bin/tool.rs").as_deref(), Some("tool"));
+        assert_eq!(path("/w/app/src/bin/tool/main.rs").as_deref(), Some("tool"));
+        assert_eq!(
+            path("/w/app/src/bin/tool/cli.rs").as_deref(),
+            Some("tool::cli")
+        );
+        assert_eq!(path("src/util.rs").as_deref(), Some("crate::util"));
+        assert_eq!(path("/w/app/build.rs"), None);
+        assert_eq!(path("/w/app/src/data.txt"), None);
+    }
+
+    #[test]
+    fn test_module_edge_generation() {
+        let commit_id = ObjectId::from_bytes([8; 32]);
+        let file_path = "/w/app/src/graph/walk.rs";
+        let item = |name: &str, qualified_name: &str, kind| AnalyzedItem {
+            name: name.to_string(),
+            qualified_name: qualified_name.to_string(),
+            kind,
+            path: PathBuf::from(file_path),
+            range: Range::new(Position::new(0, 0), Position::new(1, 0)),
+        };
+        let analysis = FileAnalysis {
+            items: vec![
+                item("Walker", "Walker", ItemKind::Struct),
+                item("impl Walker", "impl Walker", ItemKind::Impl),
+                item("new", "impl Walker::new", ItemKind::Method),
+                item("tests", "tests", ItemKind::Module),
+                item("test_walk", "tests::test_walk", ItemKind::Function),
+            ],
+            calls: vec![],
+            references: vec![],
+            implements: vec![],
+            uses_types: vec![],
+            warnings: vec![],
+        };
+
+        let edges = build_module_edges(&analysis, file_path, b"", commit_id);
+        let found: Vec<_> = edges
+            .iter()
+            .map(|e| (e.from.id.as_str(), e.label, e.to.id.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (file_path, EdgeLabel::Defines, "app::graph::walk"),
+                ("app::graph::walk", EdgeLabel::ChildOf, "app::graph"),
+                ("app::graph::walk", EdgeLabel::Contains, "Walker"),
+                ("app::graph::walk", EdgeLabel::Contains, "impl Walker"),
+                ("tests", EdgeLabel::ChildOf, "app::graph::walk"),
+                ("app::graph::walk", EdgeLabel::Contains, "tests"),
+                ("tests", EdgeLabel::Contains, "tests::test_walk"),
+            ]
+        );
+        assert_eq!(edges[1].to.kind, NodeKind::Module);
+        assert_eq!(edges[5].to.kind, NodeKind::Module);
+        assert!(edges[2].evidence.span.is_some());
+
+        assert!(build_module_edges(&analysis, "/w/app/build.rs", b"", commit_id).is_empty());
+    }
+
+    #[test]
+    fn test_cfg_conditions_on_item_edges() {
+        let commit_id = ObjectId::from_bytes([8; 32]);
+        let file_path = "/w/app/src/walk.rs";
+        let source = b"pub struct Walker;\n#[cfg(test)]\nmod tests {\n    #[cfg(unix)]\n    fn test_walk() {}\n}\n";
+        let item = |qualified_name: &str, kind, line| AnalyzedItem {
+            name: qualified_name.to_string(),
+            qualified_name: qualified_name.to_string(),
+            kind,
+            path: PathBuf::from(file_path),
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+        };
+        let analysis = FileAnalysis {
+            items: vec![
+                item("Walker", ItemKind::Struct, 0),
+                item("tests", ItemKind::Module, 2),
+                item("tests::test_walk", ItemKind::Function, 4),
+            ],
+            calls: vec![],
+            references: vec![],
+            implements: vec![],
+            uses_types: vec![],
+            warnings: vec![],
+        };
+
+        let defines: Vec<_> = build_edges_from_analysis(&analysis, file_path, source, commit_id)
+            .into_iter()
+            .map(|e| (e.to.id, e.evidence.condition))
+            .collect();
+        assert_eq!(
+            defines,
+            vec![
+                ("Walker".to_string(), None),
+                ("tests".to_string(), Some("test".to_string())),
+                (
+                    "tests::test_walk".to_string(),
+                    Some("all(test, unix)".to_string())
+                ),
+            ]
+        );
+
+        // Module membership edges carry the same conditions
+        let contains = build_module_edges(&analysis, file_path, source, commit_id)
+            .into_iter()
+            .find(|e| e.label == EdgeLabel::Contains && e.to.id == "tests::test_walk")
+            .unwrap();
+        assert_eq!(
+            contains.evidence.condition.as_deref(),
+            Some("all(test, unix)")
+        );
+    }
 }
@@ -7,8 +7,12 @@
 pub mod analyzer;
 pub mod client;
 pub mod edges;
+pub mod pool;
 pub mod protocol;
 pub mod queries;
+pub mod snapshot;
 
-pub use analyzer::{AnalyzedItem, CallInfo, FileAnalysis, ItemKind, RustAnalyzer};
-pub use edges::build_edges_from_analysis;
+pub use analyzer::{AnalysisWarning, AnalyzedItem, CallInfo, FileAnalysis, ItemKind, RustAnalyzer};
+pub use edges::{build_edges_from_analysis, build_module_edges};
+pub use pool::AnalyzerPool;
+pub use snapshot::{RustCall, RustFileSnapshot, RustSnapshot, RustSymbol, SourceRange};
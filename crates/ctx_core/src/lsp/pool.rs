@@ -0,0 +1,156 @@
+//! Analyzing many files with several rust-analyzer processes.
+//!
+//! One rust-analyzer answers requests one at a time, so a large workspace
+//! takes as long as the sum of its files. [`AnalyzerPool`] runs several
+//! instances, each on its own thread, that take the next unanalyzed file
+//! from a shared list as soon as they finish one, so a worker stuck on a
+//! slow file doesn't hold up the rest.
+
+use crate::error::Result;
+use crate::lsp::analyzer::{FileAnalysis, RustAnalyzer};
+use crate::policy::ExecPolicy;
+use crate::progress::Progress;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Most workers started when the count is left to the pool, since each
+/// instance holds a copy of the workspace's analysis in memory.
+const MAX_DEFAULT_WORKERS: usize = 4;
+
+/// Several rust-analyzer instances sharing a list of files.
+pub struct AnalyzerPool {
+    workers: Vec<RustAnalyzer>,
+}
+
+impl AnalyzerPool {
+    /// Starts `workers` rust-analyzer instances for `project_root`, or one
+    /// per CPU up to 4 if `workers` is 0, but never more than `files`.
+    ///
+    /// In [`ExecMode::Confirm`](crate::policy::ExecMode::Confirm), only the
+    /// first instance asks; the rest run on that answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`RustAnalyzer::start`] returns. Instances already
+    /// started are shut down.
+    pub fn start(
+        project_root: &Path,
+        policy: &ExecPolicy,
+        workers: usize,
+        files: usize,
+    ) -> Result<Self> {
+        let count = worker_count(workers, files);
+        let mut pool = Self {
+            workers: vec![RustAnalyzer::start(project_root, policy)?],
+        };
+        let approved = policy.clone().with_prompt(|_| true);
+        for _ in 1..count {
+            match RustAnalyzer::start(project_root, &approved) {
+                Ok(analyzer) => pool.workers.push(analyzer),
+                Err(e) => {
+                    let _ = pool.shutdown();
+                    return Err(e);
+                }
+            }
+        }
+        debug!("Started {} rust-analyzer workers", count);
+        Ok(pool)
+    }
+
+    /// Returns the number of running instances.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Returns true if no instances are running.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Analyzes `files`, returning each one's result in the order given.
+    ///
+    /// Reports files done to `progress` as phase `analyze`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::Cancelled`](crate::CtxError::Cancelled) if
+    /// `progress` is cancelled; workers finish the file they are on first.
+    /// Failures of single files are returned in their place instead.
+    pub fn analyze_files(
+        &mut self,
+        files: &[PathBuf],
+        progress: &Progress,
+    ) -> Result<Vec<Result<FileAnalysis>>> {
+        let total = files.len() as u64;
+        let next = AtomicUsize::new(0);
+        let done = AtomicU64::new(0);
+        let results: Mutex<Vec<Option<Result<FileAnalysis>>>> =
+            Mutex::new(files.iter().map(|_| None).collect());
+
+        progress.tick("analyze", 0, total)?;
+        std::thread::scope(|scope| {
+            for analyzer in &mut self.workers {
+                let (next, done, results) = (&next, &done, &results);
+                scope.spawn(move || {
+                    while progress.check().is_ok() {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(file) = files.get(i) else {
+                            break;
+                        };
+                        let result = analyzer.analyze_file(file);
+                        results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+                        let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                        progress.report("analyze", done, total);
+                    }
+                });
+            }
+        });
+        progress.check()?;
+
+        Ok(results
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .map(|result| result.expect("every file is taken by a worker"))
+            .collect())
+    }
+
+    /// Shuts down every instance, returning the first error.
+    pub fn shutdown(self) -> Result<()> {
+        let mut first_error = None;
+        for analyzer in self.workers {
+            if let Err(e) = analyzer.shutdown() {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+/// Instances to start for `files` files when `requested` were asked for.
+fn worker_count(requested: usize, files: usize) -> usize {
+    let wanted = match requested {
+        0 => std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_DEFAULT_WORKERS),
+        n => n,
+    };
+    wanted.min(files).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_count() {
+        assert_eq!(worker_count(3, 100), 3);
+        assert_eq!(worker_count(8, 2), 2);
+        assert_eq!(worker_count(2, 0), 1);
+
+        let default = worker_count(0, 100);
+        assert!((1..=MAX_DEFAULT_WORKERS).contains(&default));
+    }
+}
@@ -0,0 +1,219 @@
+//! Stored results of Rust analysis.
+//!
+//! `ctx analyze rust` records what rust-analyzer found in each file as a
+//! [`RustSnapshot`], referenced by the commit's `rust_snapshot`. Symbol
+//! inventories can be read back from it without running rust-analyzer
+//! again, and since each file records the blob it analyzed, a snapshot
+//! that no longer matches the commit's tree can be detected.
+
+use crate::lsp::analyzer::{AnalysisWarning, FileAnalysis, ItemKind, RustAnalyzer};
+use crate::ObjectId;
+use lsp_types::Range;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Rust analysis results of every analyzed file (deterministically
+/// serializable).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustSnapshot {
+    /// Per-file results, by slash-separated path relative to the repository
+    /// root.
+    pub files: BTreeMap<String, RustFileSnapshot>,
+}
+
+/// Analysis results of one file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustFileSnapshot {
+    /// Content that was analyzed.
+    pub blob: ObjectId,
+    /// Symbols defined in the file, in document order.
+    pub items: Vec<RustSymbol>,
+    /// Calls made from the file's functions.
+    pub calls: Vec<RustCall>,
+    /// Why the results may be incomplete, if they may be.
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+/// A symbol defined in a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustSymbol {
+    /// Simple name (e.g., "foo").
+    pub name: String,
+    /// Qualified name (e.g., "module::foo").
+    pub qualified_name: String,
+    /// Item kind.
+    pub kind: ItemKind,
+    /// Where the symbol is defined.
+    pub range: SourceRange,
+}
+
+/// A call from a function in the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustCall {
+    /// Name of the calling function.
+    pub caller: String,
+    /// Name of the called function.
+    pub callee: String,
+    /// File defining the callee, relative to the repository root, or None
+    /// if it is outside it (e.g. in a dependency).
+    pub callee_path: Option<String>,
+    /// Where the calls occur in the file.
+    pub call_sites: Vec<SourceRange>,
+}
+
+/// A span of source, as zero-based lines and UTF-16 columns like LSP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceRange {
+    /// First line.
+    pub start_line: u32,
+    /// Column on the first line.
+    pub start_character: u32,
+    /// Last line.
+    pub end_line: u32,
+    /// Column just past the end on the last line.
+    pub end_character: u32,
+}
+
+impl From<Range> for SourceRange {
+    fn from(range: Range) -> Self {
+        Self {
+            start_line: range.start.line,
+            start_character: range.start.character,
+            end_line: range.end.line,
+            end_character: range.end.character,
+        }
+    }
+}
+
+impl RustFileSnapshot {
+    /// Records `analysis` of the content stored as `blob`. Callee paths are
+    /// made relative to `root`.
+    pub fn new(blob: ObjectId, analysis: &FileAnalysis, root: &Path) -> Self {
+        Self {
+            blob,
+            items: analysis
+                .items
+                .iter()
+                .map(|item| RustSymbol {
+                    name: item.name.clone(),
+                    qualified_name: item.qualified_name.clone(),
+                    kind: item.kind,
+                    range: item.range.into(),
+                })
+                .collect(),
+            calls: analysis
+                .calls
+                .iter()
+                .map(|call| RustCall {
+                    caller: call.caller.clone(),
+                    callee: call.callee.clone(),
+                    callee_path: RustAnalyzer::uri_to_path(&call.callee_location.uri)
+                        .and_then(|path| relative_path(&path, root)),
+                    call_sites: call.call_sites.iter().map(|&r| r.into()).collect(),
+                })
+                .collect(),
+            warnings: analysis.warnings.clone(),
+        }
+    }
+}
+
+impl RustSnapshot {
+    /// Returns the blobs the snapshot's files were analyzed from.
+    pub fn blobs(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        self.files.values().map(|file| file.blob)
+    }
+
+    /// Returns the files whose content in `tree_files` (as from a flattened
+    /// tree) differs from what was analyzed. Files missing from
+    /// `tree_files` are not compared.
+    pub fn drift<'a>(&'a self, tree_files: &BTreeMap<String, ObjectId>) -> Vec<&'a str> {
+        self.files
+            .iter()
+            .filter(|(path, file)| tree_files.get(*path).is_some_and(|&blob| blob != file.blob))
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+}
+
+/// Returns `path` relative to `root` with `/` separators, or None if it
+/// isn't under `root`.
+pub(crate) fn relative_path(path: &Path, root: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::analyzer::{AnalyzedItem, CallInfo};
+    use lsp_types::{Location, Position, Url};
+    use std::path::PathBuf;
+
+    fn range(line: u32) -> Range {
+        Range::new(Position::new(line, 0), Position::new(line, 10))
+    }
+
+    #[test]
+    fn test_snapshot_records_analysis_and_drift() {
+        let root = PathBuf::from("/work/project");
+        let location = |path: &str| Location {
+            uri: Url::from_file_path(path).unwrap(),
+            range: range(0),
+        };
+        let analysis = FileAnalysis {
+            items: vec![AnalyzedItem {
+                name: "main".to_string(),
+                qualified_name: "main".to_string(),
+                kind: ItemKind::Function,
+                path: root.join("src/main.rs"),
+                range: range(2),
+            }],
+            calls: vec![
+                CallInfo {
+                    caller: "main".to_string(),
+                    caller_location: location("/work/project/src/main.rs"),
+                    callee: "helper".to_string(),
+                    callee_location: location("/work/project/src/util/mod.rs"),
+                    call_sites: vec![range(3)],
+                },
+                CallInfo {
+                    caller: "main".to_string(),
+                    caller_location: location("/work/project/src/main.rs"),
+                    callee: "println".to_string(),
+                    callee_location: location("/rustlib/std/src/macros.rs"),
+                    call_sites: vec![range(4)],
+                },
+            ],
+            references: vec![],
+            implements: vec![],
+            uses_types: vec![],
+            warnings: vec![AnalysisWarning::CallHierarchySkipped],
+        };
+
+        let analyzed = ObjectId::hash_blob(b"fn main() {}");
+        let file = RustFileSnapshot::new(analyzed, &analysis, &root);
+        assert_eq!(file.items[0].range.start_line, 2);
+        assert_eq!(
+            file.calls[0].callee_path.as_deref(),
+            Some("src/util/mod.rs")
+        );
+        assert_eq!(file.calls[1].callee_path, None);
+        assert_eq!(file.warnings, vec![AnalysisWarning::CallHierarchySkipped]);
+
+        let snapshot = RustSnapshot {
+            files: BTreeMap::from([("src/main.rs".to_string(), file)]),
+        };
+        assert_eq!(snapshot.blobs().collect::<Vec<_>>(), vec![analyzed]);
+
+        let mut tree = BTreeMap::from([("src/main.rs".to_string(), analyzed)]);
+        assert!(snapshot.drift(&tree).is_empty());
+        tree.insert("src/main.rs".to_string(), ObjectId::hash_blob(b"changed"));
+        assert_eq!(snapshot.drift(&tree), vec!["src/main.rs"]);
+        assert!(snapshot.drift(&BTreeMap::new()).is_empty());
+    }
+}
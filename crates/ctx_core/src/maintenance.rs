@@ -0,0 +1,343 @@
+//! One-shot repository upkeep for cron jobs and systemd timers.
+//!
+//! [`Maintenance::run_once`] does the housekeeping nothing else triggers on
+//! an unattended repository:
+//!
+//! 1. Expires a session left staged by a process that has exited, as
+//!    `session.expired_sessions` says, with [`CtxRepo::expire_session`].
+//!    Otherwise recovers the session from STAGE and checks it against the
+//!    [`StaleSessionConfig`] thresholds. A session idle past the auto-compact
+//!    threshold is compacted with [`CtxRepo::cleanup_stale_sessions`]; one
+//!    past only the ask threshold is reported and left for a user to decide.
+//!    A session whose LOCK belongs to another running process is never
+//!    touched.
+//! 2. Optionally runs garbage collection.
+//! 3. Optionally checks the integrity of the next batch of objects, with
+//!    [`CtxRepo::verify_incremental`], so repeated runs cover the whole
+//!    store without a full scan.
+//! 4. Rebuilds the index if it is missing, unreadable or behind HEAD.
+//!
+//! Each step runs after the previous one, so a session compacted in step 1
+//! is indexed in step 4.
+
+use crate::config::{CleanupReport, Config, StaleSessionConfig, StaleSessionStatus};
+use crate::error::Result;
+use crate::gc::{GcConfig, GcReport};
+use crate::object_id::ObjectId;
+use crate::status::IndexFreshness;
+use crate::verify::VerifyReport;
+use crate::CtxRepo;
+use serde::Serialize;
+
+/// What a maintenance run does.
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    /// Thresholds for stale session handling.
+    pub stale: StaleSessionConfig,
+    /// Garbage collection to run, if any.
+    pub gc: Option<GcConfig>,
+    /// Objects to verify per run, continuing from the previous run (None
+    /// skips verification).
+    pub verify_objects: Option<usize>,
+    /// Rebuild the index when it lags behind HEAD.
+    pub catch_up_index: bool,
+    /// Report what would be done without changing anything. GC runs as a
+    /// dry run.
+    pub dry_run: bool,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            stale: StaleSessionConfig::default(),
+            gc: None,
+            verify_objects: None,
+            catch_up_index: true,
+            dry_run: false,
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    /// Builds a config from the repository's settings. GC is off unless
+    /// `gc` is set; it then uses the configured grace period and read
+    /// content retention.
+    pub fn from_config(config: &Config, gc: bool) -> Self {
+        Self {
+            stale: config.session.stale_config(),
+            gc: gc.then_some(GcConfig {
+                dry_run: false,
+                grace_period_days: config.gc.grace_period_days,
+                aggressive: false,
+                read_content_retention_days: config.gc.read_content_retention_days,
+                reflog_expire_days: config.gc.reflog_expire_days,
+            }),
+            ..Self::default()
+        }
+    }
+}
+
+/// What a maintenance run did.
+#[derive(Debug, Serialize)]
+pub struct MaintenanceReport {
+    /// Commit an expired session was saved or abandoned into, if one was.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub expired_session: Option<ObjectId>,
+    /// The session's staleness before the run.
+    pub session: StaleSessionStatus,
+    /// Sessions compacted because they were idle past the auto-compact
+    /// threshold.
+    pub cleanup: CleanupReport,
+    /// Why a stale session was left alone, if it was.
+    pub session_skipped: Option<String>,
+    /// Garbage collection results, if GC ran.
+    pub gc: Option<GcReport>,
+    /// Object integrity results for this run's batch, if verification ran.
+    pub verify: Option<VerifyReport>,
+    /// Index freshness before the run.
+    pub index: IndexFreshness,
+    /// Whether the index was rebuilt.
+    pub index_rebuilt: bool,
+    /// Whether this was a dry run.
+    pub dry_run: bool,
+}
+
+/// Runs repository maintenance.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ctx_core::{Config, CtxRepo, Maintenance, MaintenanceConfig};
+///
+/// let mut repo = CtxRepo::open(".").unwrap();
+/// let config = Config::load(&repo.ctx_dir()).unwrap();
+/// let maintenance = Maintenance::new(MaintenanceConfig::from_config(&config, true));
+/// let report = maintenance.run_once(&mut repo).unwrap();
+///
+/// println!("Compacted {} sessions", report.cleanup.sessions_compacted);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    config: MaintenanceConfig,
+}
+
+impl Maintenance {
+    /// Creates a maintenance runner.
+    pub fn new(config: MaintenanceConfig) -> Self {
+        Self { config }
+    }
+
+    /// The configuration runs use.
+    pub fn config(&self) -> &MaintenanceConfig {
+        &self.config
+    }
+
+    /// Runs every maintenance step once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session can't be recovered or compacted,
+    /// GC or verification fails, or the index can't be rebuilt.
+    pub fn run_once(&self, repo: &mut CtxRepo) -> Result<MaintenanceReport> {
+        let config = &self.config;
+
+        let expired_session = if config.dry_run || repo.has_active_session() {
+            None
+        } else {
+            repo.expire_session()?
+        };
+        if !repo.has_active_session() {
+            repo.recover_session()?;
+        }
+        let session = repo.check_stale_session(&config.stale);
+        let mut cleanup = CleanupReport::default();
+        let mut session_skipped = None;
+        if let StaleSessionStatus::ShouldAutoCompact { task, .. } = &session {
+            let lock = repo.lock_status()?;
+            if let Some(pid) = lock
+                .filter(|lock| lock.holder_alive && !lock.owned)
+                .map(|lock| lock.pid)
+            {
+                session_skipped = Some(match pid {
+                    Some(pid) => format!("locked by running process {}", pid),
+                    None => "locked by a running process".to_string(),
+                });
+            } else if config.dry_run {
+                cleanup.sessions_compacted += 1;
+                cleanup.compacted_tasks.push(task.clone());
+            } else {
+                cleanup = repo.cleanup_stale_sessions(config.stale.auto_compact_threshold())?;
+            }
+        }
+
+        let gc = match &config.gc {
+            Some(gc) => Some(repo.gc(GcConfig {
+                dry_run: gc.dry_run || config.dry_run,
+                ..gc.clone()
+            })?),
+            None => None,
+        };
+
+        let verify = match config.verify_objects {
+            Some(max_objects) => Some(repo.verify_incremental(max_objects, config.dry_run)?),
+            None => None,
+        };
+
+        let head_id = repo.head_id()?;
+        let index = repo.index_freshness(head_id);
+        let index_rebuilt =
+            config.catch_up_index && !config.dry_run && index != IndexFreshness::Current;
+        if index_rebuilt {
+            repo.rebuild_index()?;
+        }
+
+        Ok(MaintenanceReport {
+            expired_session,
+            session,
+            cleanup,
+            session_skipped,
+            gc,
+            verify,
+            index,
+            index_rebuilt,
+            dry_run: config.dry_run,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn test_run_once_compacts_stale_session_and_catches_up_index() {
+        let tmp = TempDir::new().unwrap();
+        let now = 1_769_083_200;
+        {
+            let mut repo = CtxRepo::init(tmp.path())
+                .unwrap()
+                .with_time_provider(move || now);
+            repo.start_session("Forgotten task").unwrap();
+            repo.observe_file_write("src/lib.rs", b"pub fn a() {}")
+                .unwrap();
+            repo.flush_active_session().unwrap();
+        }
+
+        // Two days later the session is only worth asking about
+        let mut repo = CtxRepo::open(tmp.path())
+            .unwrap()
+            .with_time_provider(move || now + 2 * DAY);
+        let report = Maintenance::default().run_once(&mut repo).unwrap();
+        assert!(matches!(
+            report.session,
+            StaleSessionStatus::ShouldAsk { .. }
+        ));
+        assert_eq!(report.cleanup.sessions_compacted, 0);
+        assert!(repo.has_active_session());
+        drop(repo);
+
+        // A week later it is compacted
+        let mut repo = CtxRepo::open(tmp.path())
+            .unwrap()
+            .with_time_provider(move || now + 8 * DAY);
+        let dry_run = Maintenance::new(MaintenanceConfig {
+            dry_run: true,
+            ..MaintenanceConfig::default()
+        });
+        let report = dry_run.run_once(&mut repo).unwrap();
+        assert_eq!(report.cleanup.compacted_tasks, vec!["Forgotten task"]);
+        assert!(repo.has_active_session());
+
+        let report = Maintenance::default().run_once(&mut repo).unwrap();
+        assert_eq!(report.cleanup.compacted_tasks, vec!["Forgotten task"]);
+        assert!(!repo.has_active_session());
+        assert!(repo
+            .head()
+            .unwrap()
+            .message
+            .starts_with("Auto-saved stale session"));
+        assert!(repo
+            .index()
+            .unwrap()
+            .lookup_path("src/lib.rs")
+            .unwrap()
+            .is_some());
+
+        // Plain commits leave the index behind until maintenance catches up
+        repo.commit("Notes", Some(vec![]), "user").unwrap();
+        let report = Maintenance::default().run_once(&mut repo).unwrap();
+        assert_eq!(report.session, StaleSessionStatus::NoSession);
+        assert_eq!(report.index, IndexFreshness::Behind { commits: 1 });
+        assert!(report.index_rebuilt);
+
+        let report = Maintenance::default().run_once(&mut repo).unwrap();
+        assert_eq!(report.index, IndexFreshness::Current);
+        assert!(!report.index_rebuilt);
+        assert!(report.verify.is_none());
+    }
+
+    #[test]
+    fn test_run_once_expires_session_of_exited_process() {
+        let tmp = TempDir::new().unwrap();
+        let now = 1_769_083_200;
+        {
+            let mut repo = CtxRepo::init(tmp.path())
+                .unwrap()
+                .with_time_provider(move || now);
+            repo.start_session("Forgotten task").unwrap();
+            repo.observe_file_write("src/lib.rs", b"pub fn a() {}")
+                .unwrap();
+            repo.flush_active_session().unwrap();
+        }
+
+        let mut repo = CtxRepo::open(tmp.path())
+            .unwrap()
+            .with_time_provider(move || now + 8 * DAY);
+        let dry_run = Maintenance::new(MaintenanceConfig {
+            dry_run: true,
+            ..MaintenanceConfig::default()
+        });
+        assert_eq!(dry_run.run_once(&mut repo).unwrap().expired_session, None);
+        drop(repo);
+
+        let mut repo = CtxRepo::open(tmp.path())
+            .unwrap()
+            .with_time_provider(move || now + 8 * DAY);
+        let report = Maintenance::default().run_once(&mut repo).unwrap();
+        assert_eq!(report.expired_session, Some(repo.head_id().unwrap()));
+        assert_eq!(report.session, StaleSessionStatus::NoSession);
+        assert!(repo
+            .head()
+            .unwrap()
+            .message
+            .starts_with("Auto-saved expired session"));
+    }
+
+    #[test]
+    fn test_run_once_verifies_objects_in_batches() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let total = repo.object_store().list_all_objects().unwrap().len();
+        let maintenance = Maintenance::new(MaintenanceConfig {
+            verify_objects: Some(1),
+            ..MaintenanceConfig::default()
+        });
+
+        let cursors: Vec<_> = (0..total)
+            .map(|_| {
+                let verify = maintenance.run_once(&mut repo).unwrap().verify.unwrap();
+                assert_eq!(verify.objects_checked, 1);
+                assert!(!verify.has_issues());
+                verify.next_cursor
+            })
+            .collect();
+        // The last batch reaches the end of the store, and the next one
+        // starts over
+        assert_eq!(cursors[total - 1], None);
+        let verify = maintenance.run_once(&mut repo).unwrap().verify.unwrap();
+        assert_eq!(verify.next_cursor, cursors[0]);
+    }
+}
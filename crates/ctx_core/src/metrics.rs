@@ -0,0 +1,275 @@
+//! Performance metrics.
+//!
+//! The repository reports object store traffic, prompt pack build time and
+//! index query latency to a [`Metrics`] sink. The default sink discards
+//! everything; [`MetricsRegistry`] keeps counters and histograms in memory
+//! and renders them in the Prometheus text format, which `ctx serve`
+//! exposes at `/metrics`.
+//!
+//! Install a sink with [`crate::CtxRepo::with_metrics`].
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A monotonically increasing count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CounterMetric {
+    /// Objects read from the object store.
+    ObjectReads,
+    /// Objects written to the object store, including ones already present.
+    ObjectWrites,
+    /// Uncompressed bytes read from the object store.
+    ObjectBytesRead,
+    /// Uncompressed bytes written to the object store.
+    ObjectBytesWritten,
+    /// Objects fetched from the remote backend into local disk.
+    RemoteFetches,
+}
+
+impl CounterMetric {
+    /// Every counter, in exposition order.
+    pub const ALL: [CounterMetric; 5] = [
+        CounterMetric::ObjectReads,
+        CounterMetric::ObjectWrites,
+        CounterMetric::ObjectBytesRead,
+        CounterMetric::ObjectBytesWritten,
+        CounterMetric::RemoteFetches,
+    ];
+
+    /// Prometheus metric name.
+    pub fn name(self) -> &'static str {
+        match self {
+            CounterMetric::ObjectReads => "ctx_object_reads_total",
+            CounterMetric::ObjectWrites => "ctx_object_writes_total",
+            CounterMetric::ObjectBytesRead => "ctx_object_read_bytes_total",
+            CounterMetric::ObjectBytesWritten => "ctx_object_written_bytes_total",
+            CounterMetric::RemoteFetches => "ctx_remote_fetches_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            CounterMetric::ObjectReads => "Objects read from the object store.",
+            CounterMetric::ObjectWrites => "Objects written to the object store.",
+            CounterMetric::ObjectBytesRead => "Uncompressed bytes read from the object store.",
+            CounterMetric::ObjectBytesWritten => "Uncompressed bytes written to the object store.",
+            CounterMetric::RemoteFetches => "Objects fetched from the remote object store.",
+        }
+    }
+}
+
+/// A distribution of durations, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HistogramMetric {
+    /// Time to build a prompt pack, including cache lookups.
+    PackBuildSeconds,
+    /// Time to answer one index lookup.
+    IndexQuerySeconds,
+}
+
+impl HistogramMetric {
+    /// Every histogram, in exposition order.
+    pub const ALL: [HistogramMetric; 2] = [
+        HistogramMetric::PackBuildSeconds,
+        HistogramMetric::IndexQuerySeconds,
+    ];
+
+    /// Prometheus metric name.
+    pub fn name(self) -> &'static str {
+        match self {
+            HistogramMetric::PackBuildSeconds => "ctx_pack_build_seconds",
+            HistogramMetric::IndexQuerySeconds => "ctx_index_query_seconds",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            HistogramMetric::PackBuildSeconds => "Time to build a prompt pack.",
+            HistogramMetric::IndexQuerySeconds => "Time to answer an index lookup.",
+        }
+    }
+}
+
+/// Receives metric updates from the repository.
+///
+/// Implementations must be cheap: object reads call
+/// [`increment`](Metrics::increment) on every access.
+pub trait Metrics: Send + Sync {
+    /// Adds `value` to a counter.
+    fn increment(&self, metric: CounterMetric, value: u64);
+
+    /// Records one observation of a histogram, in seconds.
+    fn observe(&self, metric: HistogramMetric, seconds: f64);
+}
+
+/// A sink that discards every update.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {
+    fn increment(&self, _metric: CounterMetric, _value: u64) {}
+
+    fn observe(&self, _metric: HistogramMetric, _seconds: f64) {}
+}
+
+/// Shared handle to a metrics sink.
+pub(crate) type SharedMetrics = Arc<dyn Metrics>;
+
+/// The sink used until one is installed.
+pub(crate) fn noop() -> SharedMetrics {
+    Arc::new(NoopMetrics)
+}
+
+/// Records the time since `started` in `metric` when dropped.
+pub(crate) struct Timer<'a> {
+    metrics: &'a dyn Metrics,
+    metric: HistogramMetric,
+    started: Instant,
+}
+
+impl<'a> Timer<'a> {
+    pub(crate) fn start(metrics: &'a dyn Metrics, metric: HistogramMetric) -> Self {
+        Self {
+            metrics,
+            metric,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        self.metrics
+            .observe(self.metric, self.started.elapsed().as_secs_f64());
+    }
+}
+
+/// Upper bounds of histogram buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations at or below each bucket bound (not cumulative).
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// In-memory metrics with a Prometheus text exporter.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    counters: Mutex<BTreeMap<CounterMetric, u64>>,
+    histograms: Mutex<BTreeMap<HistogramMetric, Histogram>>,
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current value of a counter.
+    pub fn counter(&self, metric: CounterMetric) -> u64 {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.get(&metric).copied().unwrap_or(0)
+    }
+
+    /// Number of observations and their sum for a histogram.
+    pub fn histogram(&self, metric: HistogramMetric) -> (u64, f64) {
+        let histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        histograms
+            .get(&metric)
+            .map_or((0, 0.0), |h| (h.count, h.sum))
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        {
+            let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+            for metric in CounterMetric::ALL {
+                let value = counters.get(&metric).copied().unwrap_or(0);
+                let name = metric.name();
+                let _ = writeln!(out, "# HELP {} {}", name, metric.help());
+                let _ = writeln!(out, "# TYPE {} counter", name);
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+        }
+
+        let histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        for metric in HistogramMetric::ALL {
+            let histogram = histograms.get(&metric).cloned().unwrap_or_default();
+            let name = metric.name();
+            let _ = writeln!(out, "# HELP {} {}", name, metric.help());
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+            }
+            let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, histogram.count);
+            let _ = writeln!(out, "{}_sum {}", name, histogram.sum);
+            let _ = writeln!(out, "{}_count {}", name, histogram.count);
+        }
+        out
+    }
+}
+
+impl Metrics for MetricsRegistry {
+    fn increment(&self, metric: CounterMetric, value: u64) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        *counters.entry(metric).or_insert(0) += value;
+    }
+
+    fn observe(&self, metric: HistogramMetric, seconds: f64) {
+        let mut histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = histograms.entry(metric).or_default();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_renders_prometheus_text() {
+        let registry = MetricsRegistry::new();
+        registry.increment(CounterMetric::ObjectReads, 2);
+        registry.increment(CounterMetric::ObjectReads, 1);
+        registry.observe(HistogramMetric::PackBuildSeconds, 0.003);
+        registry.observe(HistogramMetric::PackBuildSeconds, 2.0);
+        registry.observe(HistogramMetric::PackBuildSeconds, 60.0);
+
+        assert_eq!(registry.counter(CounterMetric::ObjectReads), 3);
+        assert_eq!(registry.counter(CounterMetric::ObjectWrites), 0);
+        let (count, sum) = registry.histogram(HistogramMetric::PackBuildSeconds);
+        assert_eq!(count, 3);
+        assert!((sum - 62.003).abs() < 1e-9);
+
+        let text = registry.render_prometheus();
+        assert!(text.contains("# TYPE ctx_object_reads_total counter\nctx_object_reads_total 3\n"));
+        assert!(text.contains("ctx_object_writes_total 0\n"));
+        assert!(text.contains("ctx_pack_build_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(text.contains("ctx_pack_build_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("ctx_pack_build_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(text.contains("ctx_pack_build_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("ctx_pack_build_seconds_count 3\n"));
+        assert!(text.contains("ctx_index_query_seconds_count 0\n"));
+    }
+
+    #[test]
+    fn test_timer_observes_on_drop() {
+        let registry = MetricsRegistry::new();
+        {
+            let _timer = Timer::start(&registry, HistogramMetric::IndexQuerySeconds);
+        }
+        assert_eq!(registry.histogram(HistogramMetric::IndexQuerySeconds).0, 1);
+    }
+}
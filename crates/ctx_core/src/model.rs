@@ -0,0 +1,232 @@
+//! Model profiles: the context window a prompt pack is built for.
+//!
+//! Rather than passing raw token budgets, callers name the model the pack
+//! is for and its profile supplies the budget, how tokens are counted and
+//! the pack format the model reads best. A few common models are built in
+//! (see [`builtin_profiles`]); others are declared under
+//! `[models.<name>]` in the config, and a configured profile of the same
+//! name replaces the built-in one:
+//!
+//! ```toml
+//! [models.local-llama]
+//! context_tokens = 8192
+//! reserved_output_tokens = 1024
+//! tokenizer = "dense"
+//! formats = ["text"]
+//! ```
+
+use crate::error::{CtxError, Result};
+use crate::pack::{estimate_tokens, RetrievalConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How tokens are counted when fitting a pack to its budget.
+///
+/// ctx doesn't ship model vocabularies, so each tokenizer is an estimate
+/// from the length of the text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    /// About four characters per token, typical of BPE vocabularies on
+    /// English text and code.
+    #[default]
+    Bpe,
+    /// About three characters per token, for vocabularies that split code
+    /// and non-English text more finely.
+    Dense,
+    /// One token per character, the worst case.
+    Char,
+}
+
+impl Tokenizer {
+    /// Estimated number of tokens in `text`.
+    pub fn count(self, text: &str) -> u32 {
+        match self {
+            Tokenizer::Bpe => estimate_tokens(text),
+            Tokenizer::Dense => (text.chars().count() / 3) as u32,
+            Tokenizer::Char => text.chars().count() as u32,
+        }
+    }
+}
+
+/// A format a prompt pack can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackFormat {
+    /// [`PromptPack::to_json`](crate::PromptPack::to_json).
+    Json,
+    /// [`PromptPack::to_text`](crate::PromptPack::to_text).
+    Text,
+}
+
+impl PackFormat {
+    /// The format's name, as in the config.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PackFormat::Json => "json",
+            PackFormat::Text => "text",
+        }
+    }
+}
+
+/// The context window of a model, and how to fill it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelProfile {
+    /// Tokens the model's context window holds (default: 128000).
+    pub context_tokens: u32,
+    /// Tokens kept free for the model's reply (default: 4096).
+    pub reserved_output_tokens: u32,
+    /// How tokens are counted (default: bpe).
+    pub tokenizer: Tokenizer,
+    /// Pack formats the model reads best, most preferred first
+    /// (default: text, then json).
+    pub formats: Vec<PackFormat>,
+}
+
+impl Default for ModelProfile {
+    fn default() -> Self {
+        Self {
+            context_tokens: 128_000,
+            reserved_output_tokens: 4096,
+            tokenizer: Tokenizer::Bpe,
+            formats: vec![PackFormat::Text, PackFormat::Json],
+        }
+    }
+}
+
+impl ModelProfile {
+    /// Tokens a pack's content may use: the context window less the
+    /// reply.
+    pub fn pack_budget(&self) -> u32 {
+        self.context_tokens
+            .saturating_sub(self.reserved_output_tokens)
+    }
+
+    /// The most preferred of the profile's formats, if it names any.
+    pub fn preferred_format(&self) -> Option<PackFormat> {
+        self.formats.first().copied()
+    }
+
+    /// Sets `config`'s token budget, response reserve and tokenizer from
+    /// the profile.
+    pub fn apply(&self, config: &mut RetrievalConfig) {
+        config.token_budget = self.context_tokens;
+        config.response_reserve = self.reserved_output_tokens;
+        config.tokenizer = self.tokenizer;
+    }
+}
+
+/// The built-in model profiles.
+pub fn builtin_profiles() -> BTreeMap<String, ModelProfile> {
+    let profile = |context_tokens, reserved_output_tokens, tokenizer| ModelProfile {
+        context_tokens,
+        reserved_output_tokens,
+        tokenizer,
+        ..Default::default()
+    };
+    BTreeMap::from([
+        (
+            "claude-3-5-haiku".to_string(),
+            profile(200_000, 8192, Tokenizer::Dense),
+        ),
+        (
+            "claude-3-5-sonnet".to_string(),
+            profile(200_000, 8192, Tokenizer::Dense),
+        ),
+        (
+            "gpt-4o".to_string(),
+            profile(128_000, 16_384, Tokenizer::Bpe),
+        ),
+        (
+            "gpt-4o-mini".to_string(),
+            profile(128_000, 16_384, Tokenizer::Bpe),
+        ),
+    ])
+}
+
+/// The profile named `name` among `configured` and the built-in ones.
+///
+/// # Errors
+///
+/// Returns [`CtxError::InvalidArgument`] naming the known profiles if
+/// there is none by that name.
+pub(crate) fn resolve_profile(
+    configured: &BTreeMap<String, ModelProfile>,
+    name: &str,
+) -> Result<ModelProfile> {
+    if let Some(profile) = configured.get(name) {
+        return Ok(profile.clone());
+    }
+    let mut builtin = builtin_profiles();
+    builtin.remove(name).ok_or_else(|| {
+        let mut known: Vec<&str> = builtin
+            .keys()
+            .chain(configured.keys())
+            .map(String::as_str)
+            .collect();
+        known.sort_unstable();
+        known.dedup();
+        CtxError::InvalidArgument(format!(
+            "unknown model profile '{}' (known: {})",
+            name,
+            known.join(", ")
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_profile() {
+        let configured = BTreeMap::from([
+            (
+                "gpt-4o".to_string(),
+                ModelProfile {
+                    context_tokens: 32_000,
+                    ..Default::default()
+                },
+            ),
+            (
+                "local".to_string(),
+                ModelProfile {
+                    context_tokens: 8192,
+                    reserved_output_tokens: 1024,
+                    tokenizer: Tokenizer::Char,
+                    formats: vec![PackFormat::Json],
+                },
+            ),
+        ]);
+
+        let sonnet = resolve_profile(&configured, "claude-3-5-sonnet").unwrap();
+        assert_eq!(sonnet.pack_budget(), 200_000 - 8192);
+        // Configured profiles replace built-in ones
+        let gpt = resolve_profile(&configured, "gpt-4o").unwrap();
+        assert_eq!(gpt.pack_budget(), 32_000 - 4096);
+
+        let local = resolve_profile(&configured, "local").unwrap();
+        assert_eq!(local.preferred_format(), Some(PackFormat::Json));
+        let mut config = RetrievalConfig::default();
+        local.apply(&mut config);
+        assert_eq!(config.token_budget, 8192);
+        assert_eq!(config.response_reserve, 1024);
+        assert_eq!(config.tokenizer, Tokenizer::Char);
+
+        let err = resolve_profile(&configured, "gpt-5")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("known: claude-3-5-haiku, claude-3-5-sonnet, gpt-4o, gpt-4o-mini, local")
+        );
+    }
+
+    #[test]
+    fn test_tokenizers_count() {
+        let text = "fn main() {}";
+        assert_eq!(Tokenizer::Bpe.count(text), 3);
+        assert_eq!(Tokenizer::Dense.count(text), 4);
+        assert_eq!(Tokenizer::Char.count(text), 12);
+    }
+}
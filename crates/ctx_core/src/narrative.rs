@@ -3,9 +3,13 @@
 //! Manages the `.ctx/narrative/` directory containing Markdown documents
 //! for logs, tasks, decisions, and other human-readable content.
 
-use crate::error::{CtxError, Result};
+use crate::document::{Document, DocumentType};
+use crate::error::{Capability, CtxError, Result};
+use crate::ignore::IgnoreRules;
 use crate::types::NarrativeRef;
 use crate::{ObjectId, ObjectStore};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -17,13 +21,18 @@ use std::path::{Path, PathBuf};
 /// - `tasks/` - Task documentation (task_NNNN.md)
 /// - `README.md` - Repository overview
 /// - `decisions.md` - Architectural decisions
+/// - one directory per document type, e.g. `incidents/` (incident_NNNN.md)
 pub struct NarrativeSpace {
     /// Root path to .ctx/narrative/
     root: PathBuf,
+    /// Document types by kind
+    document_types: BTreeMap<String, DocumentType>,
+    /// Whether writes are refused
+    read_only: bool,
 }
 
 /// Information about a task file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TaskInfo {
     /// Task ID (numeric part, e.g., 42 for task_0042.md)
     pub id: u32,
@@ -33,6 +42,19 @@ pub struct TaskInfo {
     pub relative_path: String,
 }
 
+/// Information about a created document.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentInfo {
+    /// Document type
+    pub kind: String,
+    /// Document ID (numeric part, e.g., 3 for incident_0003.md)
+    pub id: u32,
+    /// Full path to the document file
+    pub path: PathBuf,
+    /// Relative path from narrative root (e.g., "incidents/incident_0003.md")
+    pub relative_path: String,
+}
+
 impl NarrativeSpace {
     /// Creates a new NarrativeSpace for the given .ctx directory.
     ///
@@ -41,9 +63,40 @@ impl NarrativeSpace {
     pub fn new(ctx_dir: impl AsRef<Path>) -> Self {
         Self {
             root: ctx_dir.as_ref().join("narrative"),
+            document_types: crate::document::builtin_types(),
+            read_only: false,
         }
     }
 
+    /// Refuses every write with `PermissionDenied`, for a handle that
+    /// can't commit.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Returns `PermissionDenied` if the space is read-only.
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(CtxError::PermissionDenied {
+                capability: Capability::Commit,
+                target: "narrative".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds document types, replacing built-in types of the same kind.
+    pub fn with_document_types(mut self, types: BTreeMap<String, DocumentType>) -> Self {
+        self.document_types.extend(types);
+        self
+    }
+
+    /// The document types, by kind.
+    pub fn document_types(&self) -> &BTreeMap<String, DocumentType> {
+        &self.document_types
+    }
+
     /// Reads narrative content from a blob ID.
     ///
     /// This is useful for retrieving historical narrative content from commits.
@@ -98,6 +151,7 @@ impl NarrativeSpace {
     ///
     /// Creates `log/` and `tasks/` subdirectories if missing.
     pub fn ensure_structure(&self) -> Result<()> {
+        self.check_writable()?;
         fs::create_dir_all(self.root.join("log"))?;
         fs::create_dir_all(self.root.join("tasks"))?;
         Ok(())
@@ -118,6 +172,7 @@ impl NarrativeSpace {
     ///
     /// The relative path to the log file (e.g., "log/2026-01-22.md")
     pub fn append_log(&self, date: &str, time: &str, entry: &str) -> Result<String> {
+        self.check_writable()?;
         let filename = format!("{}.md", date);
         let path = self.root.join("log").join(&filename);
         let relative_path = format!("log/{}", filename);
@@ -155,6 +210,7 @@ impl NarrativeSpace {
     ///
     /// Information about the created task including its ID and path.
     pub fn create_task(&self, title: &str, body: &str) -> Result<TaskInfo> {
+        self.check_writable()?;
         let id = self.next_task_id()?;
         let filename = format!("task_{:04}.md", id);
         let path = self.root.join("tasks").join(&filename);
@@ -177,6 +233,117 @@ impl NarrativeSpace {
         })
     }
 
+    /// Creates a new document of a configured type.
+    ///
+    /// The document is written to `<directory>/<kind>_NNNN.md` with the
+    /// next free ID, its fields as frontmatter and the type's template as
+    /// its body.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] if `kind` isn't a known type
+    /// or `fields` don't match its schema.
+    pub fn create_document(
+        &self,
+        kind: &str,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<DocumentInfo> {
+        self.check_writable()?;
+        let document_type = self.document_types.get(kind).ok_or_else(|| {
+            CtxError::InvalidArgument(format!(
+                "unknown document type '{}' (known types: {})",
+                kind,
+                self.document_types
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })?;
+        document_type.validate(kind, fields)?;
+
+        let directory = document_type.directory_for(kind);
+        let prefix = format!("{}_", kind);
+        let id = numbered_ids(&self.root.join(&directory), &prefix)?
+            .last()
+            .copied()
+            .unwrap_or(0)
+            + 1;
+        let filename = format!("{}{:04}.md", prefix, id);
+        let path = self.root.join(&directory).join(&filename);
+        let relative_path = format!("{}/{}", directory, filename);
+
+        atomic_write(&path, document_type.render(kind, fields).as_bytes())?;
+
+        Ok(DocumentInfo {
+            kind: kind.to_string(),
+            id,
+            path,
+            relative_path,
+        })
+    }
+
+    /// Lists the documents in the narrative space: files with frontmatter
+    /// naming their kind, sorted by path.
+    pub fn list_documents(&self) -> Result<Vec<Document>> {
+        let mut documents = Vec::new();
+        for relative_path in self.list_files()? {
+            let Ok(content) = String::from_utf8(self.read_file(&relative_path)?) else {
+                continue;
+            };
+            documents.extend(Document::parse(&relative_path, &content));
+        }
+        Ok(documents)
+    }
+
+    /// Writes an imported document with `content`, from `source`.
+    ///
+    /// Replaces an earlier import of the same source, so importing a
+    /// document again updates it; otherwise writes
+    /// `imports/import_NNNN.md` with the next free ID.
+    pub fn write_import(&self, source: &str, content: &str) -> Result<DocumentInfo> {
+        self.check_writable()?;
+        use crate::import::{IMPORT_DIRECTORY, IMPORT_KIND};
+
+        let prefix = format!("{}_", IMPORT_KIND);
+        let previous = self.list_documents()?.into_iter().find(|document| {
+            document.kind == IMPORT_KIND
+                && document
+                    .relative_path
+                    .starts_with(&format!("{}/", IMPORT_DIRECTORY))
+                && document.fields.get("source").map(String::as_str) == Some(source)
+        });
+        let id = match previous.as_ref().and_then(|document| {
+            document
+                .relative_path
+                .strip_prefix(&format!("{}/{}", IMPORT_DIRECTORY, prefix))?
+                .strip_suffix(".md")?
+                .parse()
+                .ok()
+        }) {
+            Some(id) => id,
+            None => {
+                numbered_ids(&self.root.join(IMPORT_DIRECTORY), &prefix)?
+                    .last()
+                    .copied()
+                    .unwrap_or(0)
+                    + 1
+            }
+        };
+        let filename = format!("{}{:04}.md", prefix, id);
+        let path = self.root.join(IMPORT_DIRECTORY).join(&filename);
+        let relative_path = format!("{}/{}", IMPORT_DIRECTORY, filename);
+
+        atomic_write(&path, content.as_bytes())?;
+
+        Ok(DocumentInfo {
+            kind: IMPORT_KIND.to_string(),
+            id,
+            path,
+            relative_path,
+        })
+    }
+
     /// Updates an existing task file.
     ///
     /// # Arguments
@@ -192,6 +359,7 @@ impl NarrativeSpace {
     /// - The task file is not readable/writable
     /// - The task file doesn't contain a status line
     pub fn update_task(&self, id: u32, status: &str, note: &str) -> Result<String> {
+        self.check_writable()?;
         let filename = format!("task_{:04}.md", id);
         let path = self.root.join("tasks").join(&filename);
         let relative_path = format!("tasks/{}", filename);
@@ -278,6 +446,12 @@ impl NarrativeSpace {
         Ok(fs::read(&path)?)
     }
 
+    /// Writes a file in the narrative space, replacing any existing one.
+    pub(crate) fn write_file(&self, relative_path: &str, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        atomic_write(&self.root.join(relative_path), data)
+    }
+
     /// Lists all narrative files.
     ///
     /// Returns relative paths for all .md files in the narrative space.
@@ -299,6 +473,8 @@ impl NarrativeSpace {
     /// * `store` - ObjectStore for storing blobs and comparing hashes
     /// * `previous_refs` - NarrativeRefs from the previous commit (empty for initial)
     /// * `role` - Role string for the NarrativeRef (e.g., "agent", "user")
+    /// * `ignore` - Rules for files to leave out, matched against paths
+    ///   relative to the narrative root
     ///
     /// # Returns
     ///
@@ -308,6 +484,7 @@ impl NarrativeSpace {
         store: &ObjectStore,
         previous_refs: &[NarrativeRef],
         role: &str,
+        ignore: &IgnoreRules,
     ) -> Result<Vec<NarrativeRef>> {
         // Build lookup map from previous refs
         let previous_blobs: std::collections::HashMap<&str, ObjectId> = previous_refs
@@ -319,6 +496,9 @@ impl NarrativeSpace {
 
         // Walk all narrative files
         for relative_path in self.list_files()? {
+            if ignore.is_ignored(&relative_path) {
+                continue;
+            }
             let content = self.read_file(&relative_path)?;
 
             // Compute what the blob ID would be
@@ -350,32 +530,7 @@ impl NarrativeSpace {
     ///
     /// Returns a sorted vector of task IDs.
     fn list_task_ids(&self) -> Result<Vec<u32>> {
-        let tasks_dir = self.root.join("tasks");
-
-        if !tasks_dir.exists() {
-            return Ok(vec![]);
-        }
-
-        let mut ids = Vec::new();
-
-        for entry in fs::read_dir(&tasks_dir)? {
-            let entry = entry?;
-            let name = entry.file_name();
-            let name_str = name.to_string_lossy();
-
-            // Parse task_NNNN.md format
-            if let Some(num_str) = name_str
-                .strip_prefix("task_")
-                .and_then(|s| s.strip_suffix(".md"))
-            {
-                if let Ok(num) = num_str.parse::<u32>() {
-                    ids.push(num);
-                }
-            }
-        }
-
-        ids.sort_unstable();
-        Ok(ids)
+        numbered_ids(&self.root.join("tasks"), "task_")
     }
 
     /// Finds the next available task ID.
@@ -410,6 +565,77 @@ impl NarrativeSpace {
     }
 }
 
+/// IDs of the `<prefix>NNNN.md` files in `dir`, sorted.
+fn numbered_ids(dir: &Path, prefix: &str) -> Result<Vec<u32>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut ids = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+
+        if let Some(num_str) = name_str
+            .strip_prefix(prefix)
+            .and_then(|s| s.strip_suffix(".md"))
+        {
+            if let Ok(num) = num_str.parse::<u32>() {
+                ids.push(num);
+            }
+        }
+    }
+
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Day of a daily log file (`log/YYYY-MM-DD.md`), as days since the Unix
+/// epoch. Returns None for other paths.
+pub(crate) fn log_day(relative_path: &str) -> Option<i64> {
+    let date = relative_path.strip_prefix("log/")?.strip_suffix(".md")?;
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days from civil date (proleptic Gregorian), per Howard Hinnant
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// UTC date (`YYYY-MM-DD`) and time (`HH:MM`) of a Unix timestamp, in the
+/// form [`NarrativeSpace::append_log`] takes. The inverse of [`log_day`].
+pub(crate) fn log_date_time(unix_secs: u64) -> (String, String) {
+    let days = (unix_secs / 86_400) as i64;
+    let secs = unix_secs % 86_400;
+
+    // Civil date from days (proleptic Gregorian), per Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02}", secs / 3600, secs % 3600 / 60),
+    )
+}
+
 /// Writes data atomically using temp file + rename.
 fn atomic_write(path: &Path, data: &[u8]) -> Result<()> {
     // Ensure parent directory exists
@@ -447,6 +673,28 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_log_day() {
+        assert_eq!(log_day("log/1970-01-01.md"), Some(0));
+        assert_eq!(log_day("log/2000-03-01.md"), Some(11_017));
+        assert_eq!(log_day("log/2026-01-22.md"), Some(20_475));
+        assert_eq!(log_day("log/2026-13-01.md"), None);
+        assert_eq!(log_day("tasks/task_0001.md"), None);
+    }
+
+    #[test]
+    fn test_log_date_time_inverts_log_day() {
+        assert_eq!(log_date_time(0), ("1970-01-01".into(), "00:00".into()));
+        assert_eq!(
+            log_date_time(11_016 * 86_400 + 23 * 3600 + 59 * 60),
+            ("2000-02-29".into(), "23:59".into())
+        );
+        for day in [11_017, 20_475, 47_482] {
+            let (date, _) = log_date_time(day as u64 * 86_400 + 3600);
+            assert_eq!(log_day(&format!("log/{}.md", date)), Some(day));
+        }
+    }
+
     #[test]
     fn test_ensure_structure() {
         let tmp = TempDir::new().unwrap();
@@ -540,6 +788,68 @@ mod tests {
         assert!(content.contains("Started working"));
     }
 
+    #[test]
+    fn test_create_document() {
+        let tmp = TempDir::new().unwrap();
+        let custom = DocumentType {
+            directory: Some("research/notes".to_string()),
+            ..Default::default()
+        };
+        let ns = NarrativeSpace::new(tmp.path())
+            .with_document_types(BTreeMap::from([("study".to_string(), custom)]));
+        let fields = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let first = ns
+            .create_document(
+                "incident",
+                &fields(&[("title", "Outage"), ("severity", "high")]),
+            )
+            .unwrap();
+        let second = ns
+            .create_document(
+                "incident",
+                &fields(&[("title", "Again"), ("severity", "low")]),
+            )
+            .unwrap();
+        assert_eq!(first.relative_path, "incidents/incident_0001.md");
+        assert_eq!(second.id, 2);
+        let study = ns
+            .create_document("study", &fields(&[("anything", "goes")]))
+            .unwrap();
+        assert_eq!(study.relative_path, "research/notes/study_0001.md");
+
+        let err = ns
+            .create_document("postmortem", &BTreeMap::new())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("experiment, incident, meeting, study"));
+        assert!(ns
+            .create_document("incident", &fields(&[("title", "No severity")]))
+            .is_err());
+
+        // Tasks and logs aren't documents
+        ns.ensure_structure().unwrap();
+        ns.create_task("Task", "").unwrap();
+        let documents = ns.list_documents().unwrap();
+        let paths: Vec<_> = documents.iter().map(|d| d.relative_path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "incidents/incident_0001.md",
+                "incidents/incident_0002.md",
+                "research/notes/study_0001.md"
+            ]
+        );
+        assert_eq!(documents[0].title(), Some("Outage"));
+        assert_eq!(documents[0].fields["severity"], "high");
+    }
+
     #[test]
     fn test_list_files() {
         let tmp = TempDir::new().unwrap();
@@ -573,7 +883,9 @@ mod tests {
         ns.append_log("2026-01-22", "10:00", "Test").unwrap();
 
         // Snapshot with no previous refs
-        let refs = ns.snapshot_changed(&store, &[], "agent").unwrap();
+        let refs = ns
+            .snapshot_changed(&store, &[], "agent", &IgnoreRules::default())
+            .unwrap();
 
         assert_eq!(refs.len(), 1);
         assert_eq!(refs[0].path, "log/2026-01-22.md");
@@ -591,13 +903,17 @@ mod tests {
         ns.append_log("2026-01-22", "10:00", "Initial").unwrap();
 
         // Get initial snapshot
-        let refs1 = ns.snapshot_changed(&store, &[], "agent").unwrap();
+        let refs1 = ns
+            .snapshot_changed(&store, &[], "agent", &IgnoreRules::default())
+            .unwrap();
 
         // Modify file
         ns.append_log("2026-01-22", "11:00", "Added").unwrap();
 
         // Snapshot should detect change
-        let refs2 = ns.snapshot_changed(&store, &refs1, "agent").unwrap();
+        let refs2 = ns
+            .snapshot_changed(&store, &refs1, "agent", &IgnoreRules::default())
+            .unwrap();
 
         assert_eq!(refs2.len(), 1);
         assert_ne!(refs2[0].blob_id, refs1[0].blob_id);
@@ -614,14 +930,35 @@ mod tests {
         ns.append_log("2026-01-22", "10:00", "Content").unwrap();
 
         // Get snapshot
-        let refs1 = ns.snapshot_changed(&store, &[], "agent").unwrap();
+        let refs1 = ns
+            .snapshot_changed(&store, &[], "agent", &IgnoreRules::default())
+            .unwrap();
 
         // No modifications - should return empty
-        let refs2 = ns.snapshot_changed(&store, &refs1, "agent").unwrap();
+        let refs2 = ns
+            .snapshot_changed(&store, &refs1, "agent", &IgnoreRules::default())
+            .unwrap();
 
         assert!(refs2.is_empty());
     }
 
+    #[test]
+    fn test_snapshot_changed_skips_ignored() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let ns = NarrativeSpace::new(tmp.path());
+        ns.ensure_structure().unwrap();
+
+        ns.append_log("2026-01-22", "10:00", "Content").unwrap();
+        ns.create_task("Scratch", "Notes").unwrap();
+
+        let ignore = IgnoreRules::new(["tasks/"]);
+        let refs = ns.snapshot_changed(&store, &[], "agent", &ignore).unwrap();
+
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].path, "log/2026-01-22.md");
+    }
+
     // Edge case tests
 
     #[test]
@@ -636,7 +973,9 @@ mod tests {
             .unwrap();
 
         // Snapshot it
-        let refs = ns.snapshot_changed(&store, &[], "agent").unwrap();
+        let refs = ns
+            .snapshot_changed(&store, &[], "agent", &IgnoreRules::default())
+            .unwrap();
         let blob_id = refs[0].blob_id;
 
         // Read from blob
@@ -672,7 +1011,9 @@ mod tests {
         assert!(files.is_empty());
 
         // Snapshot should be empty
-        let refs = ns.snapshot_changed(&store, &[], "agent").unwrap();
+        let refs = ns
+            .snapshot_changed(&store, &[], "agent", &IgnoreRules::default())
+            .unwrap();
         assert!(refs.is_empty());
     }
 
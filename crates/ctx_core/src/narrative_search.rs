@@ -0,0 +1,310 @@
+//! Ranked search over narrative text.
+//!
+//! [`CtxRepo::search_narrative`](crate::CtxRepo::search_narrative) scans the
+//! narrative files, plus the last committed version of any that have since
+//! been deleted, so "when did we decide to switch to redb" can be answered
+//! without grepping `.ctx`. There is no persistent index: narrative text is
+//! small next to the code, and a scan always reflects the working files.
+//!
+//! Files are split into sections: each `### HH:MM` entry of a daily log,
+//! and each heading's content elsewhere. A section matches if it contains a
+//! word starting with any significant query term; sections matching more
+//! distinct terms rank first, then those with more matches, then newer ones.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Query words too common to search for.
+const STOP_WORDS: &[&str] = &[
+    "an", "and", "are", "at", "be", "did", "do", "does", "for", "from", "how", "in", "into", "is",
+    "it", "of", "on", "our", "the", "this", "that", "to", "was", "we", "what", "when", "where",
+    "which", "who", "why", "with",
+];
+
+/// A narrative file to search.
+#[derive(Debug, Clone)]
+pub(crate) struct NarrativeText {
+    /// Path relative to the narrative root.
+    pub path: String,
+    /// When the file was last committed (Unix seconds), if it was.
+    pub committed_at: Option<u64>,
+    /// The file's content.
+    pub content: String,
+}
+
+/// A section of narrative text matching a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NarrativeHit {
+    /// Path relative to the narrative root.
+    pub path: String,
+    /// Date of the entry (`YYYY-MM-DD`, with `HH:MM` for log entries), from
+    /// a log file's name or else the file's last commit.
+    pub date: Option<String>,
+    /// Heading of the matching section, if it has one.
+    pub heading: Option<String>,
+    /// Text around the first match.
+    pub excerpt: String,
+    /// Distinct query terms the section contains.
+    pub matched_terms: usize,
+    /// Total matching words in the section.
+    pub matches: usize,
+}
+
+/// Significant lowercased terms of a query.
+pub(crate) fn query_terms(query: &str) -> Vec<String> {
+    let terms: BTreeSet<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.len() >= 2 && !STOP_WORDS.contains(&word.as_str()))
+        .collect();
+    terms.into_iter().collect()
+}
+
+/// Searches `texts` for `terms`, best hits first, at most `limit` of them,
+/// with excerpts of about `excerpt_len` characters.
+pub(crate) fn search(
+    texts: &[NarrativeText],
+    terms: &[String],
+    limit: usize,
+    excerpt_len: usize,
+) -> Vec<NarrativeHit> {
+    if terms.is_empty() {
+        return Vec::new();
+    }
+
+    // (hit, sort time)
+    let mut hits: Vec<(NarrativeHit, u64)> = Vec::new();
+    for text in texts {
+        let log_day = crate::narrative::log_day(&text.path);
+        for section in sections(&text.path, &text.content) {
+            // A heading's own words count, so "Switch to redb" finds its section
+            let heading = section.heading.unwrap_or_default();
+            let words: Vec<String> = section_words(heading)
+                .into_iter()
+                .chain(section_words(section.body))
+                .collect();
+            let mut matched_terms = 0;
+            let mut matches = 0;
+            for term in terms {
+                let count = words
+                    .iter()
+                    .filter(|w| w.starts_with(term.as_str()))
+                    .count();
+                if count > 0 {
+                    matched_terms += 1;
+                    matches += count;
+                }
+            }
+            if matched_terms == 0 {
+                continue;
+            }
+
+            let (date, time) = match log_day {
+                Some(day) => {
+                    let date = crate::narrative::log_date_time(day as u64 * 86_400).0;
+                    let date = match section.heading {
+                        Some(time) => format!("{} {}", date, time),
+                        None => date,
+                    };
+                    (Some(date), day as u64 * 86_400 + section.line as u64)
+                }
+                None => (
+                    text.committed_at
+                        .map(|at| crate::narrative::log_date_time(at).0),
+                    text.committed_at.unwrap_or(0),
+                ),
+            };
+            hits.push((
+                NarrativeHit {
+                    path: text.path.clone(),
+                    date,
+                    heading: section.heading.map(str::to_string),
+                    excerpt: if section.body.trim().is_empty() {
+                        heading.to_string()
+                    } else {
+                        excerpt(section.body, terms, excerpt_len)
+                    },
+                    matched_terms,
+                    matches,
+                },
+                time,
+            ));
+        }
+    }
+
+    hits.sort_by(|(a, a_time), (b, b_time)| {
+        b.matched_terms
+            .cmp(&a.matched_terms)
+            .then(b.matches.cmp(&a.matches))
+            .then(b_time.cmp(a_time))
+            .then(a.path.cmp(&b.path))
+    });
+    hits.into_iter().take(limit).map(|(hit, _)| hit).collect()
+}
+
+/// A heading and the text under it.
+pub(crate) struct Section<'a> {
+    pub heading: Option<&'a str>,
+    pub body: &'a str,
+    /// Line number of the heading, to order sections within a file.
+    pub line: usize,
+}
+
+/// Splits `content` at headings: `###` entries in daily logs, any heading
+/// elsewhere. Text before the first heading is its own section.
+pub(crate) fn sections<'a>(path: &str, content: &'a str) -> Vec<Section<'a>> {
+    let is_log = crate::narrative::log_day(path).is_some();
+    let mut sections = Vec::new();
+    let mut heading = None;
+    let mut start = 0;
+    let mut heading_line = 0;
+    let mut offset = 0;
+    for (number, line) in content.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_end();
+        let new_heading = if is_log {
+            trimmed.strip_prefix("### ")
+        } else {
+            trimmed
+                .strip_prefix('#')
+                .map(|rest| rest.trim_start_matches('#'))
+                .filter(|rest| rest.starts_with(' '))
+        };
+        if let Some(title) = new_heading {
+            sections.push(Section {
+                heading,
+                body: &content[start..offset],
+                line: heading_line,
+            });
+            heading = Some(title.trim());
+            start = offset + line.len();
+            heading_line = number;
+        }
+        offset += line.len();
+    }
+    sections.push(Section {
+        heading,
+        body: &content[start..],
+        line: heading_line,
+    });
+
+    sections.retain(|section| !section.body.trim().is_empty() || section.heading.is_some());
+    sections
+}
+
+/// Lowercased words of `text`.
+fn section_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// About `len` characters of `body` around the first word matching a term,
+/// on one line.
+fn excerpt(body: &str, terms: &[String], len: usize) -> String {
+    let text: Vec<&str> = body.split_whitespace().collect();
+    let first = text
+        .iter()
+        .position(|word| {
+            let word = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            terms.iter().any(|term| word.starts_with(term.as_str()))
+        })
+        .unwrap_or(0);
+
+    // Start a few words before the match, then fill up to `len`
+    let mut begin = first;
+    let mut used = text[first].chars().count();
+    while begin > 0 && used < len / 3 {
+        begin -= 1;
+        used += text[begin].chars().count() + 1;
+    }
+    let mut end = first + 1;
+    while end < text.len() && used + text[end].chars().count() < len {
+        used += text[end].chars().count() + 1;
+        end += 1;
+    }
+
+    let mut excerpt = text[begin..end].join(" ");
+    if begin > 0 {
+        excerpt.insert_str(0, "… ");
+    }
+    if end < text.len() {
+        excerpt.push_str(" …");
+    }
+    excerpt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(path: &str, committed_at: Option<u64>, content: &str) -> NarrativeText {
+        NarrativeText {
+            path: path.to_string(),
+            committed_at,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_query_terms() {
+        assert_eq!(
+            query_terms("When did we decide to switch to redb?"),
+            ["decide", "redb", "switch"]
+        );
+        assert!(query_terms("what was it").is_empty());
+    }
+
+    #[test]
+    fn test_search_ranks_sections() {
+        let texts = vec![
+            text(
+                "log/2026-03-02.md",
+                None,
+                "# 2026-03-02\n\n### 09:15\n\nBenchmarked sled against redb.\n\n### 16:40\n\nDecided to switch the index to redb after the benchmark.\n",
+            ),
+            text(
+                "log/2026-03-09.md",
+                None,
+                "# 2026-03-09\n\n### 10:00\n\nFinished the redb switch.\n",
+            ),
+            text(
+                "incidents/incident_0001.md",
+                Some(20_000 * 86_400),
+                "---\nkind: incident\n---\n\n# Outage\n\n## Root cause\n\nThe sled tree was corrupted.\n",
+            ),
+        ];
+        let terms = query_terms("when did we decide to switch to redb");
+        let hits = search(&texts, &terms, 10, 150);
+
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].path, "log/2026-03-02.md");
+        assert_eq!(hits[0].date.as_deref(), Some("2026-03-02 16:40"));
+        assert_eq!(hits[0].matched_terms, 3);
+        assert!(hits[0].excerpt.starts_with("Decided to switch"));
+        assert_eq!(hits[1].path, "log/2026-03-09.md");
+        assert_eq!(hits[2].heading.as_deref(), Some("09:15"));
+
+        let hits = search(&texts, &query_terms("corrupt sled"), 10, 150);
+        assert_eq!(hits[0].path, "incidents/incident_0001.md");
+        assert_eq!(hits[0].heading.as_deref(), Some("Root cause"));
+        assert_eq!(
+            hits[0].date.as_deref(),
+            Some(crate::narrative::log_date_time(20_000 * 86_400).0.as_str())
+        );
+        assert_eq!(search(&texts, &query_terms("sled"), 1, 150).len(), 1);
+        assert!(search(&texts, &query_terms("the"), 10, 150).is_empty());
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let body = "one two three four five six seven eight nine ten redb eleven twelve";
+        assert_eq!(
+            excerpt(body, &["redb".to_string()], 20),
+            "… ten redb eleven …"
+        );
+        assert_eq!(excerpt("redb", &["redb".to_string()], 20), "redb");
+    }
+}
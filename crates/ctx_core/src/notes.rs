@@ -0,0 +1,93 @@
+//! Annotations attached to commits after the fact.
+//!
+//! Sometimes it only becomes clear later that a session introduced a bug or
+//! took a wrong turn. Commits are immutable, so like git notes, an
+//! [`Annotation`] is stored as its own object and listed in the
+//! [`NoteTable`] that `refs/notes/commits` points to, with
+//! [`CtxRepo::annotate`](crate::CtxRepo::annotate).
+//!
+//! Annotations are shown in history, and a prompt pack whose files an
+//! annotated commit changed carries the annotations along with them.
+
+use crate::error::{CtxError, Result};
+use crate::types::AgentIdentity;
+use crate::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A note attached to a commit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// The annotated commit.
+    pub commit_id: ObjectId,
+    /// The note.
+    pub text: String,
+    /// Who wrote the note, if known.
+    pub author: Option<AgentIdentity>,
+    /// Creation time (Unix seconds).
+    pub created_at: u64,
+}
+
+impl Annotation {
+    /// The annotation as one line, prefixed with the short commit ID.
+    pub fn render(&self) -> String {
+        format!("{}: {}", &self.commit_id.as_hex()[..8], self.text)
+    }
+}
+
+/// Every annotation, by the commit it is attached to.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteTable {
+    /// Commit ID to the IDs of its [`Annotation`] objects, oldest first.
+    pub entries: BTreeMap<ObjectId, Vec<ObjectId>>,
+}
+
+impl NoteTable {
+    /// IDs of the annotations attached to `commit_id`, oldest first.
+    pub fn get(&self, commit_id: ObjectId) -> &[ObjectId] {
+        self.entries.get(&commit_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every annotation ID in the table.
+    pub fn annotation_ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        self.entries.values().flatten().copied()
+    }
+
+    /// Returns true if no commit is annotated.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Checks that `text` is a note worth keeping.
+pub(crate) fn validate(text: &str) -> Result<()> {
+    if text.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "annotation text is empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_table_lookup() {
+        let commit = ObjectId::hash_typed(b"commit");
+        let other = ObjectId::hash_typed(b"other");
+        let first = ObjectId::hash_typed(b"first");
+        let second = ObjectId::hash_typed(b"second");
+        let table = NoteTable {
+            entries: BTreeMap::from([(commit, vec![first, second])]),
+        };
+
+        assert_eq!(table.get(commit), &[first, second]);
+        assert!(table.get(other).is_empty());
+        assert_eq!(table.annotation_ids().count(), 2);
+        assert!(!table.is_empty());
+        assert!(validate("  \n").is_err());
+        assert!(validate("Introduced the parser crash").is_ok());
+    }
+}
@@ -136,6 +136,37 @@ impl fmt::Debug for ObjectId {
     }
 }
 
+/// Serializes an ObjectId as a hex string.
+///
+/// The derived `Serialize` writes raw bytes for compact storage; report
+/// types meant for JSON output use this instead via
+/// `#[serde(serialize_with = "...")]`.
+pub(crate) fn serialize_hex<S: serde::Serializer>(
+    id: &ObjectId,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&id.as_hex())
+}
+
+/// Serializes a list of ObjectIds as hex strings.
+pub(crate) fn serialize_hex_vec<S: serde::Serializer>(
+    ids: &[ObjectId],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(ids.iter().map(ObjectId::as_hex))
+}
+
+/// Serializes an optional ObjectId as a hex string or null.
+pub(crate) fn serialize_hex_opt<S: serde::Serializer>(
+    id: &Option<ObjectId>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match id {
+        Some(id) => serializer.serialize_some(&id.as_hex()),
+        None => serializer.serialize_none(),
+    }
+}
+
 /// Object kind discriminant for the canonical envelope.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -144,6 +175,9 @@ pub(crate) enum ObjectKind {
     Blob = 1,
     /// Serialized typed object (commits, edges, etc.)
     Typed = 2,
+    /// A large blob stored as the IDs of its chunks, in order. Its ID is
+    /// the blob's own ID, so it is never hashed with this kind.
+    ChunkList = 3,
 }
 
 /// Canonical envelope magic bytes.
@@ -1,26 +1,61 @@
 //! Content-addressed object storage with integrity verification.
 
+use crate::backend::{self, FsBackend, ObjectBackend};
+use crate::bloom::{self, ExistenceFilter};
+use crate::chunking;
+use crate::config::StorageConfig;
 use crate::error::{CtxError, Result};
+use crate::metrics::{CounterMetric, SharedMetrics};
 use crate::object_id::{canonical_bytes, ObjectId, ObjectKind, MAGIC};
+use crate::types::Commit;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{self, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
 
 /// Maximum size for a single blob object (100 MB).
 /// This prevents OOM attacks from maliciously large inputs.
-const MAX_BLOB_SIZE: usize = 100 * 1024 * 1024;
+pub(crate) const MAX_BLOB_SIZE: usize = 100 * 1024 * 1024;
 
 /// Zstd compression level for object storage.
 /// Level 3 provides a good balance between compression ratio and speed.
 const COMPRESSION_LEVEL: i32 = 3;
 
+/// Size of the canonical envelope header: magic, kind and length.
+const HEADER_LEN: usize = 14;
+
+/// File in the store's root listing the commits left out of it, one per
+/// line as a hex ID, followed by the hex ID of its stand-in if it has one.
+pub(crate) const GRAFTS_FILE: &str = "grafts";
+
+/// Commits left out of an object store, each with the commit standing in
+/// for it, if any; see [`ObjectStore::grafts`].
+pub type Grafts = HashMap<ObjectId, Option<ObjectId>>;
+
 /// Content-addressed object storage.
 ///
 /// Objects are stored as zstd-compressed files with integrity verification.
 /// The file path is derived from the object's BLAKE3 hash, enabling
 /// deduplication and corruption detection.
 ///
+/// Blobs larger than 256 KiB are split into content-defined chunks, each
+/// stored as its own blob, and kept as a list of chunk IDs under the blob's
+/// ID. Blobs that share regions then share chunk objects. [`get_blob`]
+/// reassembles them, so callers never see the chunks.
+///
+/// With a remote backend (see [`with_remote`]), writes also go to the
+/// remote and objects missing on disk are fetched from it and kept. A
+/// write of an object already on disk still uploads it if the remote lacks
+/// it, so an upload that failed, or a remote configured later, catches up.
+///
+/// [`get_blob`]: ObjectStore::get_blob
+/// [`with_remote`]: ObjectStore::with_remote
+///
 /// # Examples
 ///
 /// ```
@@ -38,7 +73,19 @@ const COMPRESSION_LEVEL: i32 = 3;
 /// assert_eq!(data, b"hello world");
 /// ```
 pub struct ObjectStore {
-    root: PathBuf,
+    /// Objects on local disk.
+    local: FsBackend,
+    /// Shared store local disk caches, if any.
+    remote: Option<Arc<dyn ObjectBackend>>,
+    /// Receives read and write counts.
+    metrics: SharedMetrics,
+    /// Pool for batch writes, or None to use rayon's global pool.
+    pool: Option<Arc<ThreadPool>>,
+    /// Answers [`exists`](Self::exists) for missing objects without a
+    /// filesystem lookup.
+    filter: Mutex<ExistenceFilter>,
+    /// Commits left out of the store, read on first use.
+    grafts: Mutex<Option<Arc<Grafts>>>,
 }
 
 impl ObjectStore {
@@ -55,19 +102,199 @@ impl ObjectStore {
     /// ```
     pub fn new(root: impl AsRef<Path>) -> Self {
         Self {
-            root: root.as_ref().to_path_buf(),
+            local: FsBackend::new(root),
+            remote: None,
+            metrics: crate::metrics::noop(),
+            pool: None,
+            filter: Mutex::default(),
+            grafts: Mutex::default(),
+        }
+    }
+
+    /// Runs batch writes on `threads` threads, or one per CPU if 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ctx_core::ObjectStore;
+    ///
+    /// let store = ObjectStore::new("/tmp/objects").with_threads(4);
+    /// ```
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.pool = None;
+        if threads > 0 {
+            match ThreadPoolBuilder::new().num_threads(threads).build() {
+                Ok(pool) => self.pool = Some(Arc::new(pool)),
+                Err(e) => warn!(threads, error = %e, "Using the global thread pool"),
+            }
+        }
+        self
+    }
+
+    /// Writes objects through to `remote` as well, and fetches objects
+    /// missing on disk from it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ctx_core::{FsBackend, ObjectStore};
+    /// use std::sync::Arc;
+    /// use tempfile::TempDir;
+    ///
+    /// let tmp = TempDir::new().unwrap();
+    /// let shared = Arc::new(FsBackend::new(tmp.path().join("shared")));
+    /// let writer = ObjectStore::new(tmp.path().join("a")).with_remote(shared.clone());
+    /// let reader = ObjectStore::new(tmp.path().join("b")).with_remote(shared);
+    ///
+    /// let id = writer.put_blob(b"shared").unwrap();
+    /// assert_eq!(reader.get_blob(id).unwrap(), b"shared");
+    /// ```
+    pub fn with_remote(mut self, remote: Arc<dyn ObjectBackend>) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Applies the `[storage]` settings for threads and the remote backend.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `storage.remote` is invalid.
+    pub(crate) fn configure(self, storage: &StorageConfig) -> Result<Self> {
+        let store = self.with_threads(storage.threads);
+        Ok(match backend::open_remote(storage)? {
+            Some(remote) => store.with_remote(remote),
+            None => store,
+        })
+    }
+
+    /// Returns a store for trial writes: objects it writes go to `root`,
+    /// and objects missing there are read from this store. Nothing reaches
+    /// this store or its remote through it.
+    pub(crate) fn scratch(&self, root: impl AsRef<Path>) -> ObjectStore {
+        ObjectStore {
+            local: FsBackend::new(root),
+            remote: Some(Arc::new(ReadOnlyBackend {
+                local: self.local.clone(),
+                remote: self.remote.clone(),
+            })),
+            metrics: crate::metrics::noop(),
+            pool: self.pool.clone(),
+            filter: Mutex::default(),
+            grafts: Mutex::new(Some(self.grafts())),
         }
     }
 
+    /// Returns another handle on this store, with the same remote, thread
+    /// pool and metrics.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn share(&self) -> ObjectStore {
+        ObjectStore {
+            local: self.local.clone(),
+            remote: self.remote.clone(),
+            metrics: self.metrics.clone(),
+            pool: self.pool.clone(),
+            filter: Mutex::default(),
+            grafts: Mutex::new(Some(self.grafts())),
+        }
+    }
+
+    /// Reports reads and writes to `metrics`.
+    pub(crate) fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics;
+    }
+
+    fn record_write(&self, bytes: usize) {
+        self.metrics.increment(CounterMetric::ObjectWrites, 1);
+        self.metrics
+            .increment(CounterMetric::ObjectBytesWritten, bytes as u64);
+    }
+
     /// Returns the root directory of this object store.
     pub fn root(&self) -> &Path {
-        &self.root
+        self.local.root()
+    }
+
+    /// Commits left out of this store by a shallow clone or a history
+    /// prune, each with the commit that stands in for it, if any. History
+    /// ends before them: walks back from HEAD follow a parent link to a
+    /// left-out commit to its stand-in instead, or not at all.
+    pub fn grafts(&self) -> Arc<Grafts> {
+        let mut grafts = self.grafts.lock().unwrap_or_else(|e| e.into_inner());
+        grafts
+            .get_or_insert_with(|| Arc::new(self.read_grafts()))
+            .clone()
+    }
+
+    fn read_grafts(&self) -> Grafts {
+        let path = self.root().join(GRAFTS_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Grafts::new(),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Ignoring unreadable grafts");
+                return Grafts::new();
+            }
+        };
+        parse_grafts(&text).into_iter().collect()
+    }
+
+    /// Whether commit `id` was left out of this store; see
+    /// [`grafts`](Self::grafts).
+    pub fn is_grafted(&self, id: ObjectId) -> bool {
+        self.grafts().contains_key(&id)
+    }
+
+    /// Parents of `commit` whose history this store holds, with left-out
+    /// parents replaced by their stand-ins.
+    pub fn parents(&self, commit: &Commit) -> Vec<ObjectId> {
+        let grafts = self.grafts();
+        commit
+            .parents
+            .iter()
+            .filter_map(|id| grafts.get(id).copied().unwrap_or(Some(*id)))
+            .collect()
+    }
+
+    /// First parent of `commit` (or its stand-in), unless it's a root
+    /// commit or the parent was left out without one.
+    pub fn first_parent(&self, commit: &Commit) -> Option<ObjectId> {
+        let id = *commit.parents.first()?;
+        self.grafts().get(&id).copied().unwrap_or(Some(id))
+    }
+
+    /// Records `grafts` as the commits left out of this store and their
+    /// stand-ins, replacing any recorded before.
+    pub(crate) fn write_grafts(&self, grafts: &BTreeMap<ObjectId, Option<ObjectId>>) -> Result<()> {
+        let path = self.root().join(GRAFTS_FILE);
+        if grafts.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        } else {
+            let mut text = String::new();
+            for (id, stand_in) in grafts {
+                text.push_str(&id.as_hex());
+                if let Some(stand_in) = stand_in {
+                    text.push(' ');
+                    text.push_str(&stand_in.as_hex());
+                }
+                text.push('\n');
+            }
+            fs::create_dir_all(self.root())?;
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, text)?;
+            fs::rename(&tmp, &path)?;
+        }
+        *self.grafts.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(Arc::new(grafts.iter().map(|(k, v)| (*k, *v)).collect()));
+        Ok(())
     }
 
     /// Stores raw bytes and returns their content-addressed ID.
     ///
     /// If an object with the same content already exists, this is a no-op
-    /// and returns the existing ID (deduplication).
+    /// and returns the existing ID (deduplication). Large blobs are stored
+    /// in chunks, deduplicated the same way.
     ///
     /// # Errors
     ///
@@ -95,17 +322,63 @@ impl ObjectStore {
         }
 
         let id = ObjectId::hash_blob(data);
+        self.record_write(data.len());
 
         // Check for existing object (deduplication)
-        if self.exists(id) {
+        if self.stored_everywhere(id)? {
             return Ok(id);
         }
 
-        let canonical = canonical_bytes(ObjectKind::Blob, data);
-        self.write_object(id, &canonical)?;
+        if data.len() <= chunking::MAX_CHUNK_SIZE {
+            let canonical = canonical_bytes(ObjectKind::Blob, data);
+            self.write_object(id, &canonical)?;
+            return Ok(id);
+        }
+
+        let chunks = chunking::chunks(data);
+        let mut list = Vec::with_capacity(chunks.len() * 32);
+        for chunk in chunks {
+            let chunk_id = ObjectId::hash_blob(chunk);
+            if !self.stored_everywhere(chunk_id)? {
+                self.write_object(chunk_id, &canonical_bytes(ObjectKind::Blob, chunk))?;
+            }
+            list.extend_from_slice(chunk_id.as_bytes());
+        }
+        // Written last, so an interrupted write never leaves a list with
+        // missing chunks
+        self.write_object(id, &canonical_bytes(ObjectKind::ChunkList, &list))?;
         Ok(id)
     }
 
+    /// Stores several blobs at once, hashing and compressing them in
+    /// parallel, and returns their IDs in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error any write hits; the others may have been
+    /// stored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ctx_core::ObjectStore;
+    /// use tempfile::TempDir;
+    ///
+    /// let tmp = TempDir::new().unwrap();
+    /// let store = ObjectStore::new(tmp.path().join("objects"));
+    ///
+    /// let ids = store.put_blobs_parallel(&[b"one".as_slice(), b"two"]).unwrap();
+    /// assert_eq!(store.get_blob(ids[1]).unwrap(), b"two");
+    /// ```
+    pub fn put_blobs_parallel<B: AsRef<[u8]> + Sync>(&self, blobs: &[B]) -> Result<Vec<ObjectId>> {
+        self.install(|| {
+            blobs
+                .par_iter()
+                .map(|blob| self.put_blob(blob.as_ref()))
+                .collect()
+        })
+    }
+
     /// Retrieves raw bytes by their content ID.
     ///
     /// # Errors
@@ -129,14 +402,184 @@ impl ObjectStore {
     pub fn get_blob(&self, id: ObjectId) -> Result<Vec<u8>> {
         let (kind, payload) = self.read_object(id)?;
 
-        if kind != ObjectKind::Blob {
-            return Err(CtxError::CorruptedObject {
+        match kind {
+            ObjectKind::Blob => Ok(payload),
+            ObjectKind::ChunkList => {
+                let mut data = Vec::new();
+                for chunk_id in self.parse_chunk_list(id, &payload)? {
+                    data.extend(self.get_blob(chunk_id)?);
+                }
+                // The list isn't hashed itself; the reassembled blob is
+                let actual = ObjectId::hash_blob(&data);
+                if actual != id {
+                    return Err(CtxError::HashMismatch {
+                        expected: id.as_hex(),
+                        actual: actual.as_hex(),
+                    });
+                }
+                Ok(data)
+            }
+            ObjectKind::Typed => Err(CtxError::CorruptedObject {
                 path: self.object_path(id),
                 reason: format!("expected Blob, got {:?}", kind),
-            });
+            }),
+        }
+    }
+
+    /// Opens a blob for streaming, without loading it into memory.
+    ///
+    /// The content is decompressed as it is read, chunk by chunk for a
+    /// chunked blob. Its hash is checked when the reader reaches the end,
+    /// which fails with an [`io::ErrorKind::InvalidData`] error wrapping
+    /// [`CtxError::HashMismatch`] if it doesn't match.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ObjectNotFound` if the object doesn't exist, or
+    /// `CorruptedObject` if it isn't a blob or its header is damaged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ctx_core::ObjectStore;
+    /// use std::io::Read;
+    /// use tempfile::TempDir;
+    ///
+    /// let tmp = TempDir::new().unwrap();
+    /// let store = ObjectStore::new(tmp.path().join("objects"));
+    ///
+    /// let id = store.put_blob(b"streamed").unwrap();
+    /// let mut content = String::new();
+    /// store.get_blob_reader(id).unwrap().read_to_string(&mut content).unwrap();
+    /// assert_eq!(content, "streamed");
+    /// ```
+    pub fn get_blob_reader(&self, id: ObjectId) -> Result<BlobReader<'_>> {
+        let (kind, mut payload) = self.open_payload(id)?;
+        let (current, chunks, len) = match kind {
+            ObjectKind::Blob => {
+                let len = payload.remaining;
+                (Some(payload), Vec::new(), len)
+            }
+            ObjectKind::ChunkList => {
+                let mut list = Vec::new();
+                payload.read_to_end(&mut list)?;
+                let chunks = self.parse_chunk_list(id, &list)?;
+                // The blob's hash covers its total length, which only the
+                // chunks' headers give
+                let mut len = 0;
+                for chunk in &chunks {
+                    len += self.open_chunk(*chunk)?.remaining;
+                }
+                (None, chunks, len)
+            }
+            ObjectKind::Typed => {
+                return Err(CtxError::CorruptedObject {
+                    path: self.object_path(id),
+                    reason: format!("expected Blob, got {:?}", kind),
+                })
+            }
+        };
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(MAGIC);
+        hasher.update(&[ObjectKind::Blob as u8]);
+        hasher.update(&len.to_le_bytes());
+        Ok(BlobReader {
+            store: self,
+            id,
+            current,
+            chunks: chunks.into_iter(),
+            hasher,
+            verified: false,
+        })
+    }
+
+    /// Returns an object's envelope kind from its header alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object is missing or its header is damaged.
+    pub(crate) fn object_kind(&self, id: ObjectId) -> Result<ObjectKind> {
+        self.open_payload(id).map(|(kind, _)| kind)
+    }
+
+    /// Opens an object and reads its header, leaving the payload to stream.
+    fn open_payload(&self, id: ObjectId) -> Result<(ObjectKind, Payload)> {
+        self.fetch_missing(id)?;
+        let path = self.object_path(id);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(CtxError::ObjectNotFound(id.as_hex()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut decoder =
+            zstd::Decoder::new(file).map_err(|e| CtxError::Compression(e.to_string()))?;
+        self.metrics.increment(CounterMetric::ObjectReads, 1);
+
+        let mut header = [0u8; HEADER_LEN];
+        decoder
+            .read_exact(&mut header)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => CtxError::CorruptedObject {
+                    path: path.clone(),
+                    reason: "object too small".to_string(),
+                },
+                _ => CtxError::ObjectCorrupt {
+                    id: id.as_hex(),
+                    reason: format!("can't decompress: {}", e),
+                },
+            })?;
+        let (kind, len) = parse_header(&path, &header)?;
+        Ok((
+            kind,
+            Payload {
+                decoder,
+                remaining: len,
+            },
+        ))
+    }
+
+    /// Opens one chunk of a chunked blob.
+    fn open_chunk(&self, id: ObjectId) -> Result<Payload> {
+        match self.open_payload(id)? {
+            (ObjectKind::Blob, payload) => Ok(payload),
+            (kind, _) => Err(CtxError::CorruptedObject {
+                path: self.object_path(id),
+                reason: format!("expected a Blob chunk, got {:?}", kind),
+            }),
         }
+    }
 
-        Ok(payload)
+    /// Returns the IDs of the chunks a large blob is stored in, or None if
+    /// `id` isn't stored in chunks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object is missing or corrupted.
+    pub(crate) fn chunk_ids(&self, id: ObjectId) -> Result<Option<Vec<ObjectId>>> {
+        match self.read_object(id)? {
+            (ObjectKind::ChunkList, payload) => self.parse_chunk_list(id, &payload).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses the payload of the chunk list stored under `id`.
+    fn parse_chunk_list(&self, id: ObjectId, payload: &[u8]) -> Result<Vec<ObjectId>> {
+        if payload.len() % 32 != 0 {
+            return Err(CtxError::CorruptedObject {
+                path: self.object_path(id),
+                reason: format!(
+                    "chunk list length {} is not a multiple of 32",
+                    payload.len()
+                ),
+            });
+        }
+        Ok(payload
+            .chunks_exact(32)
+            .map(|bytes| ObjectId::from_bytes(bytes.try_into().unwrap()))
+            .collect())
     }
 
     /// Stores a typed object using deterministic serialization.
@@ -172,9 +615,10 @@ impl ObjectStore {
             postcard::to_allocvec(value).map_err(|e| CtxError::Serialization(e.to_string()))?;
 
         let id = ObjectId::hash_typed(&serialized);
+        self.record_write(serialized.len());
 
         // Check for existing object (deduplication)
-        if self.exists(id) {
+        if self.stored_everywhere(id)? {
             return Ok(id);
         }
 
@@ -183,12 +627,36 @@ impl ObjectStore {
         Ok(id)
     }
 
+    /// Stores several typed objects at once, serializing and compressing
+    /// them in parallel, and returns their IDs in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error any write hits; the others may have been
+    /// stored.
+    pub fn put_typed_parallel<T: Serialize + Sync>(&self, values: &[T]) -> Result<Vec<ObjectId>> {
+        self.install(|| {
+            values
+                .par_iter()
+                .map(|value| self.put_typed(value))
+                .collect()
+        })
+    }
+
+    /// Runs `op` on the store's thread pool.
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
     /// Retrieves and deserializes a typed object by ID.
     ///
     /// # Errors
     ///
-    /// Returns an error if the object doesn't exist, is corrupted,
-    /// or deserialization fails.
+    /// Returns an error if the object doesn't exist or is corrupted, and
+    /// [`CtxError::ObjectCorrupt`] if its payload doesn't decode as `T`.
     pub fn get_typed<T: DeserializeOwned>(&self, id: ObjectId) -> Result<T> {
         let (kind, payload) = self.read_object(id)?;
 
@@ -199,7 +667,10 @@ impl ObjectStore {
             });
         }
 
-        postcard::from_bytes(&payload).map_err(|e| CtxError::Deserialization(e.to_string()))
+        postcard::from_bytes(&payload).map_err(|e| CtxError::ObjectCorrupt {
+            id: id.as_hex(),
+            reason: format!("can't decode as {}: {}", std::any::type_name::<T>(), e),
+        })
     }
 
     /// Checks if an object exists in the store.
@@ -220,10 +691,72 @@ impl ObjectStore {
     /// assert!(store.exists(id));
     /// ```
     pub fn exists(&self, id: ObjectId) -> bool {
+        if self.exists_locally(id) {
+            return true;
+        }
+        let Some(remote) = &self.remote else {
+            return false;
+        };
+        remote.exists(id).unwrap_or_else(|e| {
+            warn!(id = %id.as_hex(), error = %e, "Remote existence check failed");
+            false
+        })
+    }
+
+    /// Checks if an object is on local disk and, with a remote, on the
+    /// remote too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the remote can't be asked.
+    fn stored_everywhere(&self, id: ObjectId) -> Result<bool> {
+        if !self.exists_locally(id) {
+            return Ok(false);
+        }
+        match &self.remote {
+            Some(remote) => remote.exists(id),
+            None => Ok(true),
+        }
+    }
+
+    /// Checks if an object is on local disk.
+    fn exists_locally(&self, id: ObjectId) -> bool {
+        if self.filter().excludes(self.root(), id) {
+            return false;
+        }
         self.object_path(id).exists()
     }
 
-    /// Lists all objects in the store.
+    /// Number of objects written since the existence filter was last
+    /// rebuilt plus the objects it was built from, or None if the store has
+    /// no filter yet. Objects written twice count twice, so this is an
+    /// estimate that is cheap to get on a large store.
+    pub fn object_count_estimate(&self) -> Option<u64> {
+        self.filter().count(self.root())
+    }
+
+    /// Rebuilds the existence filter from the objects in the store, sized
+    /// for their number. [`gc`](crate::gc::gc) calls this after deleting
+    /// objects; until a store's first rebuild, lookups check files only.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store can't be listed or the filter written.
+    pub fn rebuild_filter(&self) -> Result<()> {
+        let _lock = bloom::lock_rebuild(self.root())?;
+        let objects = self.list_all_objects()?;
+        bloom::rebuild(
+            self.root(),
+            objects.into_iter().map(|(id, _, _)| id),
+            &mut self.filter(),
+        )
+    }
+
+    fn filter(&self) -> std::sync::MutexGuard<'_, ExistenceFilter> {
+        self.filter.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Lists all objects in the store on local disk.
     ///
     /// Returns a vector of tuples containing:
     /// - ObjectId
@@ -241,12 +774,12 @@ impl ObjectStore {
         let mut objects = Vec::new();
 
         // Check if objects directory exists
-        if !self.root.exists() {
+        if !self.root().exists() {
             return Ok(objects);
         }
 
         // Iterate through shard directories
-        for shard_entry in fs::read_dir(&self.root)? {
+        for shard_entry in fs::read_dir(self.root())? {
             let shard_entry = shard_entry?;
             let shard_path = shard_entry.path();
 
@@ -288,7 +821,8 @@ impl ObjectStore {
         Ok(objects)
     }
 
-    /// Deletes an object from the store.
+    /// Deletes an object from the store on local disk. A remote backend
+    /// keeps it.
     ///
     /// # Safety
     ///
@@ -333,45 +867,71 @@ impl ObjectStore {
 
     /// Computes the filesystem path for an object.
     fn object_path(&self, id: ObjectId) -> PathBuf {
-        self.root.join(id.shard()).join(id.as_hex())
+        self.local.path(id)
     }
 
-    /// Writes compressed canonical bytes to disk atomically.
-    fn write_object(&self, id: ObjectId, canonical: &[u8]) -> Result<()> {
+    /// Returns the file holding an object on local disk, fetching it from
+    /// the remote first if it's only there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object isn't stored.
+    pub(crate) fn local_path(&self, id: ObjectId) -> Result<PathBuf> {
+        self.fetch_missing(id)?;
         let path = self.object_path(id);
-        let dir = path.parent().unwrap();
-
-        // Ensure shard directory exists
-        fs::create_dir_all(dir)?;
+        if !path.exists() {
+            return Err(CtxError::ObjectNotFound(id.as_hex()));
+        }
+        Ok(path)
+    }
 
-        // Compress with zstd
+    /// Compresses canonical bytes and writes them to disk and the remote.
+    fn write_object(&self, id: ObjectId, canonical: &[u8]) -> Result<()> {
         let compressed = zstd::encode_all(canonical, COMPRESSION_LEVEL)
             .map_err(|e| CtxError::Compression(e.to_string()))?;
-
-        // Atomic write: temp file + fsync + rename
-        let tmp_path = path.with_extension("tmp");
-
-        {
-            let mut file = File::create(&tmp_path)?;
-            file.write_all(&compressed)?;
-            file.sync_all()?;
+        self.store_locally(id, &compressed)?;
+        if let Some(remote) = &self.remote {
+            remote.put(id, &compressed)?;
         }
+        Ok(())
+    }
 
-        fs::rename(&tmp_path, &path)?;
+    /// Writes compressed bytes to disk atomically.
+    fn store_locally(&self, id: ObjectId, compressed: &[u8]) -> Result<()> {
+        // Logged before it appears, so the existence filter never misses it
+        let _guard = bloom::log_write(self.root(), id)?;
+        self.local.put(id, compressed)
+    }
 
-        // fsync parent directory (Unix-specific for crash safety)
-        #[cfg(unix)]
-        {
-            if let Ok(dir_file) = File::open(dir) {
-                let _ = dir_file.sync_all();
-            }
+    /// Fetches an object missing on disk from the remote, if there is one,
+    /// and keeps it on disk. Leaves a missing object missing.
+    fn fetch_missing(&self, id: ObjectId) -> Result<()> {
+        let Some(remote) = &self.remote else {
+            return Ok(());
+        };
+        if self.object_path(id).exists() {
+            return Ok(());
+        }
+        if let Some(compressed) = remote.get(id)? {
+            self.metrics.increment(CounterMetric::RemoteFetches, 1);
+            self.store_locally(id, &compressed)?;
         }
-
         Ok(())
     }
 
+    /// Returns an object's envelope kind and uncompressed payload size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object is missing or corrupted.
+    pub(crate) fn payload_size(&self, id: ObjectId) -> Result<(ObjectKind, u64)> {
+        let (kind, payload) = self.read_object(id)?;
+        Ok((kind, payload.len() as u64))
+    }
+
     /// Reads and verifies an object from disk.
     fn read_object(&self, id: ObjectId) -> Result<(ObjectKind, Vec<u8>)> {
+        self.fetch_missing(id)?;
         let path = self.object_path(id);
 
         if !path.exists() {
@@ -382,42 +942,26 @@ impl ObjectStore {
         let compressed = fs::read(&path)?;
 
         // Decompress
-        let canonical = zstd::decode_all(compressed.as_slice())
-            .map_err(|e| CtxError::Compression(e.to_string()))?;
+        let canonical =
+            zstd::decode_all(compressed.as_slice()).map_err(|e| CtxError::ObjectCorrupt {
+                id: id.as_hex(),
+                reason: format!("can't decompress: {}", e),
+            })?;
+        self.metrics.increment(CounterMetric::ObjectReads, 1);
+        self.metrics
+            .increment(CounterMetric::ObjectBytesRead, canonical.len() as u64);
 
         // Verify envelope format
-        if canonical.len() < 14 {
+        if canonical.len() < HEADER_LEN {
             return Err(CtxError::CorruptedObject {
                 path,
                 reason: "object too small".to_string(),
             });
         }
+        let (kind, len) = parse_header(&path, canonical[..HEADER_LEN].try_into().unwrap())?;
+        let payload = &canonical[HEADER_LEN..];
 
-        // Check magic bytes
-        if &canonical[..5] != MAGIC {
-            return Err(CtxError::CorruptedObject {
-                path,
-                reason: "invalid magic bytes".to_string(),
-            });
-        }
-
-        // Parse kind
-        let kind = match canonical[5] {
-            1 => ObjectKind::Blob,
-            2 => ObjectKind::Typed,
-            k => {
-                return Err(CtxError::CorruptedObject {
-                    path,
-                    reason: format!("unknown kind: {}", k),
-                })
-            }
-        };
-
-        // Parse and verify length
-        let len = u64::from_le_bytes(canonical[6..14].try_into().unwrap()) as usize;
-        let payload = &canonical[14..];
-
-        if payload.len() != len {
+        if payload.len() as u64 != len {
             return Err(CtxError::CorruptedObject {
                 path,
                 reason: format!(
@@ -428,10 +972,12 @@ impl ObjectStore {
             });
         }
 
-        // Verify hash
+        // Verify hash. A chunk list is stored under its blob's ID, which
+        // get_blob checks after reassembly.
         let expected = match kind {
             ObjectKind::Blob => ObjectId::hash_blob(payload),
             ObjectKind::Typed => ObjectId::hash_typed(payload),
+            ObjectKind::ChunkList => id,
         };
 
         if expected != id {
@@ -445,6 +991,191 @@ impl ObjectStore {
     }
 }
 
+/// A store's objects, readable but not writable, behind a
+/// [`ObjectStore::scratch`] store.
+struct ReadOnlyBackend {
+    local: FsBackend,
+    remote: Option<Arc<dyn ObjectBackend>>,
+}
+
+impl ObjectBackend for ReadOnlyBackend {
+    fn get(&self, id: ObjectId) -> Result<Option<Vec<u8>>> {
+        match (self.local.get(id)?, &self.remote) {
+            (None, Some(remote)) => remote.get(id),
+            (bytes, _) => Ok(bytes),
+        }
+    }
+
+    fn put(&self, _id: ObjectId, _bytes: &[u8]) -> Result<()> {
+        // Trial writes stay in the scratch store
+        Ok(())
+    }
+
+    fn exists(&self, id: ObjectId) -> Result<bool> {
+        if self.local.exists(id)? {
+            return Ok(true);
+        }
+        match &self.remote {
+            Some(remote) => remote.exists(id),
+            None => Ok(false),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<ObjectId>> {
+        self.local.list()
+    }
+}
+
+/// Parses the canonical envelope header of the object at `path` into its
+/// kind and payload length.
+fn parse_header(path: &Path, header: &[u8; HEADER_LEN]) -> Result<(ObjectKind, u64)> {
+    // Check magic bytes
+    if &header[..5] != MAGIC {
+        return Err(CtxError::CorruptedObject {
+            path: path.to_path_buf(),
+            reason: "invalid magic bytes".to_string(),
+        });
+    }
+
+    // Parse kind
+    let kind = match header[5] {
+        1 => ObjectKind::Blob,
+        2 => ObjectKind::Typed,
+        3 => ObjectKind::ChunkList,
+        k => {
+            return Err(CtxError::CorruptedObject {
+                path: path.to_path_buf(),
+                reason: format!("unknown kind: {}", k),
+            })
+        }
+    };
+
+    let len = u64::from_le_bytes(header[6..].try_into().unwrap());
+    Ok((kind, len))
+}
+
+/// An object's payload, decompressed as it is read.
+struct Payload {
+    decoder: zstd::Decoder<'static, BufReader<File>>,
+    /// Payload bytes not read yet.
+    remaining: u64,
+}
+
+impl Read for Payload {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let n = self.decoder.read(&mut buf[..max])?;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Streams a blob's content. Returned by [`ObjectStore::get_blob_reader`].
+pub struct BlobReader<'a> {
+    store: &'a ObjectStore,
+    id: ObjectId,
+    /// The blob, or the chunk being read.
+    current: Option<Payload>,
+    /// Chunks not opened yet.
+    chunks: std::vec::IntoIter<ObjectId>,
+    /// Hash of the canonical bytes read so far.
+    hasher: blake3::Hasher,
+    verified: bool,
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(payload) = &mut self.current {
+                if payload.remaining > 0 {
+                    let n = payload.read(buf)?;
+                    if n == 0 {
+                        return Err(self.corrupted("content shorter than its header says"));
+                    }
+                    self.hasher.update(&buf[..n]);
+                    self.store
+                        .metrics
+                        .increment(CounterMetric::ObjectBytesRead, n as u64);
+                    return Ok(n);
+                }
+                if payload.decoder.read(&mut [0u8])? != 0 {
+                    return Err(self.corrupted("content longer than its header says"));
+                }
+                self.current = None;
+            }
+
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.current = Some(self.store.open_chunk(chunk).map_err(into_io_error)?)
+                }
+                None => {
+                    if !self.verified {
+                        let actual = ObjectId::from_bytes(*self.hasher.finalize().as_bytes());
+                        if actual != self.id {
+                            return Err(into_io_error(CtxError::HashMismatch {
+                                expected: self.id.as_hex(),
+                                actual: actual.as_hex(),
+                            }));
+                        }
+                        self.verified = true;
+                    }
+                    return Ok(0);
+                }
+            }
+        }
+    }
+}
+
+impl BlobReader<'_> {
+    fn corrupted(&self, reason: &str) -> io::Error {
+        into_io_error(CtxError::CorruptedObject {
+            path: self.store.object_path(self.id),
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// Wraps an error for a [`Read`] implementation.
+fn into_io_error(e: CtxError) -> io::Error {
+    match e {
+        CtxError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// Recovers the [`CtxError`] from an error a [`BlobReader`] returned.
+/// Parses the contents of a grafts file, skipping invalid lines.
+pub(crate) fn parse_grafts(text: &str) -> BTreeMap<ObjectId, Option<ObjectId>> {
+    let mut grafts = BTreeMap::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let mut ids = line.split_whitespace().map(ObjectId::from_hex);
+        match (ids.next(), ids.next().transpose()) {
+            (Some(Ok(id)), Ok(stand_in)) => {
+                grafts.insert(id, stand_in);
+            }
+            _ => warn!(line, "Ignoring invalid graft"),
+        }
+    }
+    grafts
+}
+
+pub(crate) fn io_error(e: io::Error) -> CtxError {
+    if !e.get_ref().is_some_and(|inner| inner.is::<CtxError>()) {
+        return CtxError::Io(e);
+    }
+    let kind = e.kind();
+    match e.into_inner().map(|inner| inner.downcast::<CtxError>()) {
+        Some(Ok(e)) => *e,
+        Some(Err(inner)) => CtxError::Io(io::Error::new(kind, inner)),
+        None => CtxError::Io(kind.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -522,6 +1253,7 @@ mod tests {
                 err,
                 CtxError::CorruptedObject { .. }
                     | CtxError::HashMismatch { .. }
+                    | CtxError::ObjectCorrupt { .. }
                     | CtxError::Compression(_)
             ),
             "Expected corruption-related error, got: {:?}",
@@ -626,6 +1358,254 @@ mod tests {
         assert_eq!(data, retrieved);
     }
 
+    #[test]
+    fn test_large_blobs_share_chunks() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+
+        // Incompressible data, so chunking is what saves the space
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let shared: Vec<u8> = (0..1_500_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut lockfile_a = b"# branch a\n".to_vec();
+        lockfile_a.extend_from_slice(&shared);
+        let mut lockfile_b = b"# a different header on branch b\n".to_vec();
+        lockfile_b.extend_from_slice(&shared);
+
+        let id_a = store.put_blob(&lockfile_a).unwrap();
+        assert_eq!(id_a, ObjectId::hash_blob(&lockfile_a));
+        let chunks_a = store.chunk_ids(id_a).unwrap().unwrap();
+        let objects_after_a = store.list_all_objects().unwrap().len();
+        assert_eq!(objects_after_a, chunks_a.len() + 1);
+
+        let id_b = store.put_blob(&lockfile_b).unwrap();
+        let chunks_b = store.chunk_ids(id_b).unwrap().unwrap();
+        let new_chunks = chunks_b.iter().filter(|c| !chunks_a.contains(c)).count();
+        assert!(
+            new_chunks <= 2,
+            "{} of {} chunks new",
+            new_chunks,
+            chunks_b.len()
+        );
+        assert_eq!(
+            store.list_all_objects().unwrap().len(),
+            objects_after_a + new_chunks + 1
+        );
+
+        assert_eq!(store.get_blob(id_a).unwrap(), lockfile_a);
+        assert_eq!(store.get_blob(id_b).unwrap(), lockfile_b);
+        assert_eq!(
+            store.chunk_ids(store.put_blob(b"small").unwrap()).unwrap(),
+            None
+        );
+
+        // A damaged chunk fails the read of every blob using it
+        let damaged = chunks_b.iter().find(|c| chunks_a.contains(c)).unwrap();
+        let other = store.put_blob(&[1u8; 1000]).unwrap();
+        std::fs::copy(store.object_path(other), store.object_path(*damaged)).unwrap();
+        assert!(store.get_blob(id_a).is_err());
+        assert!(store.get_blob(id_b).is_err());
+    }
+
+    #[test]
+    fn test_blob_reader_streams_and_verifies() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let read = |id| -> Result<Vec<u8>> {
+            let mut bytes = Vec::new();
+            store
+                .get_blob_reader(id)?
+                .read_to_end(&mut bytes)
+                .map_err(io_error)?;
+            Ok(bytes)
+        };
+
+        let small = store.put_blob(b"small").unwrap();
+        assert_eq!(read(small).unwrap(), b"small");
+        assert_eq!(read(store.put_blob(b"").unwrap()).unwrap(), b"");
+
+        let large: Vec<u8> = (0..1_000_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let large_id = store.put_blob(&large).unwrap();
+        assert!(store.chunk_ids(large_id).unwrap().is_some());
+        assert_eq!(read(large_id).unwrap(), large);
+
+        let typed = store.put_typed(&42u32).unwrap();
+        assert!(matches!(
+            store.get_blob_reader(typed),
+            Err(CtxError::CorruptedObject { .. })
+        ));
+        assert!(matches!(
+            store.get_blob_reader(ObjectId::from_bytes([0; 32])),
+            Err(CtxError::ObjectNotFound(_))
+        ));
+
+        // Same-length content under the wrong ID only fails at the end
+        let other = store.put_blob(b"other").unwrap();
+        std::fs::copy(store.object_path(other), store.object_path(small)).unwrap();
+        let mut reader = store.get_blob_reader(small).unwrap();
+        let mut buf = [0u8; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"other");
+        let err = io_error(reader.read(&mut buf).unwrap_err());
+        assert!(matches!(err, CtxError::HashMismatch { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_existence_filter() {
+        let tmp = TempDir::new().unwrap();
+        let mut store = ObjectStore::new(tmp.path().join("objects"));
+        let missing = ObjectId::hash_blob(b"never stored");
+
+        let first = store.put_blob(b"first").unwrap();
+        assert_eq!(store.object_count_estimate(), None);
+        store.rebuild_filter().unwrap();
+        assert_eq!(store.object_count_estimate(), Some(1));
+        assert!(store.exists(first));
+        assert!(!store.exists(missing));
+
+        // Another store on the same directory, like another process, sees
+        // writes made after it loaded the filter
+        let other = ObjectStore::new(tmp.path().join("objects"));
+        assert!(!other.exists(missing));
+        let second = store.put_blob(b"second").unwrap();
+        assert!(other.exists(second));
+        assert_eq!(other.object_count_estimate(), Some(2));
+
+        // And rebuilds that drop deleted objects
+        store.delete(first).unwrap();
+        store.rebuild_filter().unwrap();
+        assert_eq!(other.object_count_estimate(), Some(1));
+        assert!(!other.exists(first));
+        assert!(other.exists(second));
+
+        // A log left from another generation turns the filter off
+        fs::write(tmp.path().join("objects/bloom.log"), [0u8; 8]).unwrap();
+        let fresh = ObjectStore::new(tmp.path().join("objects"));
+        assert_eq!(fresh.object_count_estimate(), None);
+        assert!(fresh.exists(second));
+    }
+
+    #[test]
+    fn test_put_parallel() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects")).with_threads(4);
+
+        // Duplicates race to write the same object
+        let blobs: Vec<Vec<u8>> = (0..64).map(|i| vec![(i % 8) as u8; 4096]).collect();
+        let ids = store.put_blobs_parallel(&blobs).unwrap();
+        for (blob, id) in blobs.iter().zip(&ids) {
+            assert_eq!(*id, ObjectId::hash_blob(blob));
+            assert_eq!(store.get_blob(*id).unwrap(), *blob);
+        }
+        assert_eq!(store.list_all_objects().unwrap().len(), 8);
+
+        let values: Vec<(u32, String)> = (0..16).map(|i| (i, format!("value {}", i))).collect();
+        let ids = store.put_typed_parallel(&values).unwrap();
+        assert_eq!(ids[3], store.put_typed(&values[3]).unwrap());
+        assert_eq!(
+            store.get_typed::<(u32, String)>(ids[15]).unwrap(),
+            values[15]
+        );
+
+        // No leftover temp files among the objects
+        let tmp_files = walk_files(store.root())
+            .into_iter()
+            .filter(|path| path.parent() != Some(store.root()) && path.extension().is_some())
+            .count();
+        assert_eq!(tmp_files, 0);
+    }
+
+    #[test]
+    fn test_writes_upload_objects_the_remote_lacks() {
+        let tmp = TempDir::new().unwrap();
+        let large: Vec<u8> = (0..1024 * 1024).map(|i| (i * 7 % 251) as u8).collect();
+
+        // Written before the remote was configured
+        let offline = ObjectStore::new(tmp.path().join("a"));
+        let small = offline.put_blob(b"small").unwrap();
+        let chunked = offline.put_blob(&large).unwrap();
+        let typed = offline.put_typed(&("typed", 1u32)).unwrap();
+        let on_disk = offline.list_all_objects().unwrap().len();
+
+        let shared = Arc::new(FsBackend::new(tmp.path().join("shared")));
+        let store = ObjectStore::new(tmp.path().join("a")).with_remote(shared.clone());
+        assert!(!shared.exists(small).unwrap());
+        assert_eq!(store.put_blob(b"small").unwrap(), small);
+        assert_eq!(store.put_blob(&large).unwrap(), chunked);
+        assert_eq!(store.put_typed(&("typed", 1u32)).unwrap(), typed);
+        assert_eq!(shared.list().unwrap().len(), on_disk);
+
+        // Another store reads all of it from the remote alone
+        let reader = ObjectStore::new(tmp.path().join("b")).with_remote(shared);
+        assert_eq!(reader.get_blob(small).unwrap(), b"small");
+        assert_eq!(reader.get_blob(chunked).unwrap(), large);
+        assert_eq!(
+            reader.get_typed::<(String, u32)>(typed).unwrap(),
+            ("typed".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_remote_read_through() {
+        let tmp = TempDir::new().unwrap();
+        let shared = Arc::new(FsBackend::new(tmp.path().join("shared")));
+        let writer = ObjectStore::new(tmp.path().join("a")).with_remote(shared.clone());
+        let small = writer.put_blob(b"small").unwrap();
+        let large: Vec<u8> = (0..1024 * 1024).map(|i| (i * 7 % 251) as u8).collect();
+        let chunked = writer.put_blob(&large).unwrap();
+        let typed = writer.put_typed(&("typed", 1u32)).unwrap();
+        assert!(shared.list().unwrap().len() > 4);
+
+        // A store with empty local disk reads through and keeps what it read
+        let reader = ObjectStore::new(tmp.path().join("b")).with_remote(shared.clone());
+        assert!(reader.exists(small));
+        assert!(reader.list_all_objects().unwrap().is_empty());
+        assert_eq!(reader.get_blob(small).unwrap(), b"small");
+        assert_eq!(reader.get_blob(chunked).unwrap(), large);
+        let mut streamed = Vec::new();
+        reader
+            .get_blob_reader(chunked)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert_eq!(streamed, large);
+        assert_eq!(
+            reader.get_typed::<(String, u32)>(typed).unwrap(),
+            ("typed".to_string(), 1)
+        );
+        let cached = reader.list_all_objects().unwrap().len();
+        assert_eq!(cached, shared.list().unwrap().len());
+
+        // Cached objects no longer need the remote
+        let offline = ObjectStore::new(tmp.path().join("b"));
+        assert_eq!(offline.get_blob(chunked).unwrap(), large);
+        let missing = ObjectId::hash_blob(b"missing");
+        assert!(!reader.exists(missing));
+        assert!(matches!(
+            reader.get_blob(missing),
+            Err(CtxError::ObjectNotFound(_))
+        ));
+    }
+
+    fn walk_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(walk_files(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files
+    }
+
     #[test]
     fn test_object_path_sharding() {
         let tmp = TempDir::new().unwrap();
@@ -639,6 +1619,48 @@ mod tests {
         assert!(path.to_string_lossy().contains("/ab/"));
     }
 
+    #[test]
+    fn test_grafts_redirect_parents() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let [kept, dropped, replaced, stand_in] =
+            [1u8, 2, 3, 4].map(|b| ObjectId::from_bytes([b; 32]));
+        let commit = Commit {
+            parents: vec![replaced, dropped, kept],
+            timestamp_unix: 0,
+            message: "Merge".into(),
+            root_tree: stand_in,
+            edge_batches: vec![],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: None,
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        assert_eq!(store.parents(&commit), commit.parents);
+
+        let grafts = BTreeMap::from([(dropped, None), (replaced, Some(stand_in))]);
+        store.write_grafts(&grafts).unwrap();
+        assert_eq!(store.parents(&commit), vec![stand_in, kept]);
+        assert_eq!(store.first_parent(&commit), Some(stand_in));
+        assert!(store.is_grafted(dropped));
+
+        // A fresh store reads them back
+        let reopened = ObjectStore::new(tmp.path().join("objects"));
+        assert_eq!(reopened.parents(&commit), vec![stand_in, kept]);
+        store.write_grafts(&BTreeMap::new()).unwrap();
+        assert!(store.grafts().is_empty());
+        assert!(!tmp.path().join("objects").join(GRAFTS_FILE).exists());
+    }
+
     #[test]
     fn test_compression_reduces_size() {
         let tmp = TempDir::new().unwrap();
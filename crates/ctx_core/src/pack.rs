@@ -1,11 +1,51 @@
 //! Prompt pack compilation for LLM context.
 
-use crate::error::Result;
-use crate::graph::{expand_from_seeds, ExpansionConfig};
-use crate::types::{EdgeLabel, NodeId, NodeKind};
+use crate::cache::PackCache;
+use crate::cargo::{CargoMetadataSnapshot, DepKind, TargetKind};
+use crate::citation::Citation;
+use crate::document::document_node;
+use crate::error::{CtxError, Result};
+use crate::glob::glob_match;
+use crate::glossary::{term_node, Glossary};
+use crate::graph::{expand_from_seeds, EdgeDecayConfig, ExpansionConfig, ExpansionResult};
+use crate::large_file::large_file;
+use crate::log::path_change;
+use crate::model::Tokenizer;
+use crate::notes::{Annotation, NoteTable};
+use crate::object_store::{io_error, BlobReader};
+use crate::progress::Progress;
+use crate::qa::QaPair;
+use crate::summary::{Summary, SummaryTable};
+use crate::types::{format_tag, AgentIdentity, Commit, EdgeLabel, NodeId, NodeKind, ACTOR_KEY};
 use crate::{CtxRepo, Index, NameNamespace, ObjectId};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Read;
+use std::ops::ControlFlow;
+use tracing::{debug, warn};
+
+/// Queries resolving to at most this many seeds count as vague, letting
+/// frecency influence ranking.
+const VAGUE_QUERY_MAX_SEEDS: usize = 1;
+
+/// Frecent files used as seeds when a query matches nothing.
+const FRECENT_SEED_LIMIT: usize = 5;
+
+/// Largest relevance bonus (fixed-point) given to the most frecent file.
+const MAX_FRECENCY_BONUS: u32 = 250;
+
+/// Most glossary entries included in a pack's glossary chunk.
+const MAX_GLOSSARY_ENTRIES: usize = 8;
+
+/// Most narrative documents seeded from a query.
+const MAX_DOCUMENT_SEEDS: usize = 4;
+
+/// Most past answers included in a pack.
+const MAX_PAST_ANSWERS: usize = 3;
+
+/// Similarity (fixed-point, 1000 = same words) a past question needs to the
+/// query for its answer to be included.
+const MIN_ANSWER_SIMILARITY: u32 = 500;
 
 /// Compiled retrieval result ready for LLM consumption.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +62,96 @@ pub struct PromptPack {
     pub recent_narrative: String,
     /// Token budget accounting.
     pub token_budget: TokenBudget,
+    /// Why each chunk was included or dropped, when requested with
+    /// [`RetrievalConfig::explain`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<PackExplanation>,
+    /// Chunks left out because an earlier turn already provided this exact
+    /// content (see [`build_delta_pack`](CtxRepo::build_delta_pack)).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchanged: Vec<UnchangedChunk>,
+    /// Overview and module layers above the file chunks (see
+    /// [`build_layered_pack`](CtxRepo::build_layered_pack)).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<PackLayer>,
+}
+
+/// A reference to content provided in an earlier turn, standing in for
+/// the chunk itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnchangedChunk {
+    /// Title of the chunk (the file path for file content).
+    pub title: String,
+    /// ObjectId of the content, as provided earlier.
+    pub object_id: ObjectId,
+    /// Turn that provided it.
+    pub turn: u32,
+}
+
+impl UnchangedChunk {
+    /// One-line description shown in place of the content.
+    pub fn describe(&self) -> String {
+        format!("{} unchanged since turn {}", self.title, self.turn)
+    }
+}
+
+/// A layer of a hierarchical pack, summarizing the repository above the
+/// file chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackLayer {
+    /// Which layer this is.
+    pub kind: LayerKind,
+    /// Rendered content.
+    pub content: String,
+    /// Token sub-budget for the entries. The closing note on omitted
+    /// entries may go slightly past it.
+    pub budget: u32,
+    /// Estimated tokens of the content.
+    pub tokens: u32,
+    /// Entries left out to stay within the budget.
+    pub omitted: usize,
+    /// Files named in this layer, which
+    /// [`build_zoom_pack`](CtxRepo::build_zoom_pack) can drill into.
+    pub anchors: Vec<String>,
+}
+
+/// The layers of a hierarchical pack, from broadest to narrowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayerKind {
+    /// Packages, targets and dependencies, or top-level directories.
+    Overview,
+    /// The module tree below each crate root.
+    Modules,
+}
+
+impl LayerKind {
+    /// Section heading in [`PromptPack::to_text`].
+    pub fn heading(self) -> &'static str {
+        match self {
+            LayerKind::Overview => "Workspace Overview",
+            LayerKind::Modules => "Module Graph",
+        }
+    }
+}
+
+/// Token sub-budgets for the layers of
+/// [`build_layered_pack`](CtxRepo::build_layered_pack). File chunks get what
+/// remains of [`RetrievalConfig::token_budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerBudgets {
+    /// Tokens for the workspace overview.
+    pub overview: u32,
+    /// Tokens for the module graph.
+    pub modules: u32,
+}
+
+impl Default for LayerBudgets {
+    fn default() -> Self {
+        Self {
+            overview: 1000,
+            modules: 2000,
+        }
+    }
 }
 
 /// A chunk of retrieved content.
@@ -39,6 +169,8 @@ pub struct RetrievedChunk {
     pub relevance_score: u32,
     /// What kind of content this is.
     pub chunk_kind: ChunkKind,
+    /// Where the snippet came from, for citing it.
+    pub citation: Citation,
 }
 
 /// Categorizes the type of content in a retrieved chunk.
@@ -52,12 +184,25 @@ pub enum ChunkKind {
     FileContent,
     /// Agent session logs, task descriptions, or decision records.
     NarrativeExcerpt,
-    /// Architectural decision records (ADRs).
+    /// Decision records: the rationale behind changes.
     Decision,
     /// Compiler errors, warnings, or LSP diagnostics.
     DiagnosticOutput,
     /// Function, struct, or type definitions from code analysis.
     SymbolDefinition,
+    /// Definitions of project terms used in the query.
+    Glossary,
+    /// A cached summary standing in for a file that didn't fit the budget.
+    Summary,
+    /// Known facts about the query's seeds and the candidate files.
+    Fact,
+    /// A past answer to a question similar to the query.
+    Answer,
+    /// A large or binary file described by its size and hash instead of
+    /// its content.
+    Stub,
+    /// Notes attached after the fact to commits that changed the files.
+    Annotation,
 }
 
 /// Graph expansion context for debugging/transparency.
@@ -73,22 +218,77 @@ pub struct GraphContext {
     pub scc_dag_used: bool,
 }
 
+/// Retrieval reasoning recorded for a pack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackExplanation {
+    /// Seeds the expansion started from, and why each was chosen.
+    pub seeds: Vec<SeedExplanation>,
+    /// Every candidate file, in ranking order, included or not.
+    pub candidates: Vec<CandidateExplanation>,
+    /// Whether expansion stopped at `max_expanded_nodes`.
+    pub expansion_truncated: bool,
+}
+
+/// Why a node was used as a seed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeedExplanation {
+    /// The seed node (`Kind::id`).
+    pub node: String,
+    /// What selected it.
+    pub reason: String,
+}
+
+/// How a candidate file was reached and scored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidateExplanation {
+    /// File path.
+    pub title: String,
+    /// Seed the expansion path starts from (`Kind::id`).
+    pub seed: String,
+    /// Hops from the seed, e.g. `Item::parse <-Defines- File::src/parser.rs`.
+    pub path: Vec<String>,
+    /// Hops from the nearest seed.
+    pub depth: u32,
+    /// Final relevance score (fixed-point, see [`RetrievedChunk`]).
+    pub relevance_score: u32,
+    /// Part of the score contributed by frecency.
+    pub frecency_bonus: u32,
+    /// Part of the score contributed by feedback (negative for files
+    /// marked useless).
+    pub feedback_adjustment: i32,
+    /// Estimated tokens, if the content was loaded.
+    pub tokens: Option<u32>,
+    /// Whether the file made it into the pack.
+    pub included: bool,
+    /// Why the file was left out, if it was.
+    pub dropped_reason: Option<String>,
+}
+
 /// Token budget tracking.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenBudget {
     /// Total budget.
     pub total: u32,
-    /// Tokens used by retrieved content.
+    /// Tokens used by retrieved content, narrative included.
     pub used: u32,
     /// Tokens reserved for LLM response.
     pub reserved_for_response: u32,
+    /// Tokens used by narrative content (part of `used`).
+    #[serde(default)]
+    pub narrative: u32,
 }
 
 /// Configuration for retrieval pipeline.
-#[derive(Debug, Clone)]
+///
+/// Deserializing fills unset fields from [`RetrievalConfig::default`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RetrievalConfig {
     /// Total token budget.
     pub token_budget: u32,
+    /// How tokens are counted against the budget (see
+    /// [`ModelProfile::apply`](crate::ModelProfile::apply)).
+    pub tokenizer: Tokenizer,
     /// Tokens to reserve for LLM response.
     pub response_reserve: u32,
     /// Graph expansion depth.
@@ -97,18 +297,116 @@ pub struct RetrievalConfig {
     pub expand_labels: Vec<EdgeLabel>,
     /// Maximum nodes to expand.
     pub max_expanded_nodes: usize,
-    /// Include narrative from last N days.
+    /// Include daily log entries from the last N days.
     pub narrative_days: u32,
+    /// Most tokens narrative content may use, within the overall budget.
+    pub narrative_budget: u32,
     /// Include active task content.
     pub include_active_task: bool,
     /// Include daily log entries.
     pub include_log: bool,
+    /// Only include files whose current version was written by these authors.
+    pub author_filter: AuthorFilter,
+    /// Only include files read or written in steps carrying at least one of
+    /// these tags, such as `ticket:PROJ-123`. A tag without a value matches
+    /// every value of its key. Pinned files are exempt.
+    pub tags: Vec<String>,
+    /// Only include files read or written by at least one of these
+    /// sub-agents of a session, such as `coder` (see
+    /// [`CtxRepo::observe_as`]). Pinned files are exempt.
+    pub actors: Vec<String>,
+    /// Boost frequently and recently used files when the query is vague,
+    /// and record pack inclusions. Disable for deterministic runs.
+    pub frecency_boost: bool,
+    /// Raise or lower the relevance of files by the feedback recorded on
+    /// earlier packs (see [`CtxRepo::record_feedback`]).
+    pub feedback_boost: bool,
+    /// Record why each chunk was included or dropped in
+    /// [`PromptPack::explanation`]. Only [`build_pack`](CtxRepo::build_pack) honours this.
+    pub explain: bool,
+    /// Nodes that always seed expansion. Pinned files are always included,
+    /// ahead of ranked content and even past the token budget.
+    pub pinned: Vec<NodeId>,
+    /// Gitignore-style patterns for files that are never included, such as
+    /// `target/` or `*.lock`. Pinned files are exempt.
+    pub exclude: Vec<String>,
+    /// Expand strongly connected components as units, so reaching one file
+    /// of an import cycle brings in the whole cycle. Refreshes the SCCs
+    /// persisted in the index if new edges have made them stale.
+    pub use_scc: bool,
+    /// Skip weak or long-unasserted edges during expansion.
+    pub edge_decay: EdgeDecayConfig,
+    /// Files scoring at most this that don't fit in the budget are replaced
+    /// by their cached summary (see [`CtxRepo::summarize`]) if it fits.
+    /// `None` never substitutes. Only [`build_pack`](CtxRepo::build_pack) honours this.
+    pub summary_max_relevance: Option<u32>,
+    /// Return only decision records (see [`CtxRepo::record_decision`])
+    /// about the files the query reaches or naming its words, without file
+    /// content, narrative or glossary. Only [`build_pack`](CtxRepo::build_pack) honours this.
+    pub decisions_only: bool,
+    /// Cargo features enabled in the build the pack is for. When set,
+    /// expansion skips edges that only exist under `cfg` conditions these
+    /// features fail, such as code behind `#[cfg(feature = "x")]` with `x`
+    /// off. Features are taken literally: `default` doesn't imply the
+    /// features it enables. `None` ignores conditions.
+    pub active_features: Option<Vec<String>>,
+}
+
+/// Restricts retrieval to content written by particular authors.
+///
+/// A file matches if the commit that introduced its current version was
+/// authored by a matching identity. Unattributed commits only match `Any`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthorFilter {
+    /// No restriction.
+    #[default]
+    Any,
+    /// Only content from human-authored commits.
+    Humans,
+    /// Only content from agent-authored commits.
+    Agents,
+    /// Only content from commits by the named author.
+    Named(String),
+}
+
+impl AuthorFilter {
+    /// Returns true if content authored by `author` passes this filter.
+    pub fn matches(&self, author: Option<&AgentIdentity>) -> bool {
+        match (self, author) {
+            (AuthorFilter::Any, _) => true,
+            (_, None) => false,
+            (AuthorFilter::Humans, Some(a)) => a.is_human(),
+            (AuthorFilter::Agents, Some(a)) => !a.is_human(),
+            (AuthorFilter::Named(name), Some(a)) => &a.name == name,
+        }
+    }
+}
+
+impl RetrievalConfig {
+    /// Returns true if `path` is a pinned file.
+    pub fn is_pinned(&self, path: &str) -> bool {
+        self.pinned
+            .iter()
+            .any(|node| node.kind == NodeKind::File && node.id == path)
+    }
+
+    /// Returns the first exclude pattern matching `path`, unless it is pinned.
+    pub fn excluded_by(&self, path: &str) -> Option<&str> {
+        if self.is_pinned(path) {
+            return None;
+        }
+        self.exclude
+            .iter()
+            .find(|pattern| glob_match(pattern, path))
+            .map(String::as_str)
+    }
 }
 
 impl Default for RetrievalConfig {
     fn default() -> Self {
         Self {
             token_budget: 16000,
+            tokenizer: Tokenizer::default(),
             response_reserve: 4000,
             expansion_depth: 2,
             expand_labels: vec![
@@ -116,11 +414,31 @@ impl Default for RetrievalConfig {
                 EdgeLabel::References,
                 EdgeLabel::DependsOn,
                 EdgeLabel::Defines, // Follow File -> Item edges to find source files
+                EdgeLabel::DeclaresModule,
+                EdgeLabel::Mentions,   // Glossary terms -> files they describe
+                EdgeLabel::Implements, // Types <-> traits they implement
+                EdgeLabel::UsesType,   // Items <-> types they mention
+                EdgeLabel::Contains,   // Modules <-> items they contain
+                EdgeLabel::ChildOf,    // Modules <-> their parent modules
             ],
             max_expanded_nodes: 50,
             narrative_days: 7,
+            narrative_budget: 4000,
             include_active_task: true,
             include_log: true,
+            author_filter: AuthorFilter::Any,
+            tags: Vec::new(),
+            actors: Vec::new(),
+            frecency_boost: true,
+            feedback_boost: true,
+            explain: false,
+            pinned: Vec::new(),
+            exclude: Vec::new(),
+            use_scc: false,
+            edge_decay: EdgeDecayConfig::default(),
+            summary_max_relevance: Some(500),
+            decisions_only: false,
+            active_features: None,
         }
     }
 }
@@ -139,9 +457,10 @@ impl PromptPack {
         output.push_str(&format!("# Prompt Pack: {}\n\n", self.task));
         output.push_str(&format!("**Commit:** {}\n", self.head_commit));
         output.push_str(&format!(
-            "**Tokens:** {}/{} (reserved: {})\n\n",
+            "**Tokens:** {}/{} (narrative: {}, reserved: {})\n\n",
             self.token_budget.used,
             self.token_budget.total,
+            self.token_budget.narrative,
             self.token_budget.reserved_for_response
         ));
 
@@ -165,22 +484,255 @@ impl PromptPack {
             output.push_str("\n\n");
         }
 
+        for layer in &self.layers {
+            output.push_str(&format!("## {}\n\n", layer.kind.heading()));
+            output.push_str(&layer.content);
+            output.push_str("\n\n");
+        }
+
+        // Numbered from 1 so answers can cite "[3]"
         output.push_str("## Retrieved Content\n\n");
-        for chunk in &self.retrieved {
+        for (i, chunk) in self.retrieved.iter().enumerate() {
             output.push_str(&format!(
-                "### {} (score: {:.3}, kind: {:?})\n\n",
+                "### [{}] {} (score: {:.3}, kind: {:?}, source: {})\n\n",
+                i + 1,
                 chunk.title,
                 chunk.relevance_score as f32 / 1000.0,
-                chunk.chunk_kind
+                chunk.chunk_kind,
+                chunk.citation
             ));
             output.push_str(&chunk.snippet);
             output.push_str("\n\n");
         }
 
+        if !self.unchanged.is_empty() {
+            output.push_str("## Provided Earlier\n\n");
+            for chunk in &self.unchanged {
+                output.push_str(&format!("- {}\n", chunk.describe()));
+            }
+            output.push('\n');
+        }
+
+        if let Some(explanation) = &self.explanation {
+            output.push_str("## Retrieval Explanation\n\n");
+            if explanation.seeds.is_empty() {
+                output.push_str("- No seeds matched the query\n");
+            }
+            for seed in &explanation.seeds {
+                output.push_str(&format!("- Seed {}: {}\n", seed.node, seed.reason));
+            }
+            if explanation.expansion_truncated {
+                output.push_str("- Expansion stopped at the node limit\n");
+            }
+            output.push('\n');
+
+            for candidate in &explanation.candidates {
+                let status = match &candidate.dropped_reason {
+                    None => "included".to_string(),
+                    Some(reason) => format!("dropped: {}", reason),
+                };
+                output.push_str(&format!(
+                    "### {} ({})\n\n- Score: {:.3} (frecency bonus {:.3}, feedback {:+.3}), depth {}\n",
+                    candidate.title,
+                    status,
+                    candidate.relevance_score as f32 / 1000.0,
+                    candidate.frecency_bonus as f32 / 1000.0,
+                    candidate.feedback_adjustment as f32 / 1000.0,
+                    candidate.depth
+                ));
+                if let Some(tokens) = candidate.tokens {
+                    output.push_str(&format!("- Tokens: {}\n", tokens));
+                }
+                if candidate.path.is_empty() {
+                    output.push_str("- Path: seed\n");
+                } else {
+                    output.push_str(&format!("- Path: {}\n", candidate.path.join(", ")));
+                }
+                output.push('\n');
+            }
+        }
+
         output
     }
 }
 
+/// Continuation state for paged retrieval.
+///
+/// Records which chunks have already been delivered and how far the graph
+/// frontier has been widened, so the next call to
+/// [`build_pack_paged`](CtxRepo::build_pack_paged) can return the next most
+/// relevant context without repetition. Cursors are
+/// bound to the HEAD commit, query and retrieval config they were issued
+/// for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackCursor {
+    /// Commit the paged retrieval runs against.
+    head_commit: ObjectId,
+    /// Hash of the query and retrieval config (see [`cursor_request_hash`]).
+    request: [u8; 32],
+    /// ObjectIds of chunks delivered on earlier pages.
+    delivered: BTreeSet<ObjectId>,
+    /// Expansion depth reached so far.
+    frontier_depth: u32,
+    /// Number of pages delivered so far.
+    page: u32,
+}
+
+impl PackCursor {
+    /// Number of pages delivered so far.
+    pub fn page(&self) -> u32 {
+        self.page
+    }
+
+    /// Number of chunks delivered so far.
+    pub fn delivered_count(&self) -> usize {
+        self.delivered.len()
+    }
+
+    /// Encode as an opaque token (hex of the postcard encoding).
+    pub fn to_token(&self) -> Result<String> {
+        let bytes =
+            postcard::to_allocvec(self).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        Ok(hex::encode(bytes))
+    }
+
+    /// Decode a token produced by [`PackCursor::to_token`].
+    pub fn from_token(token: &str) -> Result<Self> {
+        let bytes = hex::decode(token.trim())
+            .map_err(|e| CtxError::InvalidCursor(format!("not a valid cursor token: {}", e)))?;
+        postcard::from_bytes(&bytes)
+            .map_err(|e| CtxError::InvalidCursor(format!("not a valid cursor token: {}", e)))
+    }
+}
+
+/// One page of a paged retrieval.
+#[derive(Debug, Clone)]
+pub struct PagedPack {
+    /// The pack for this page.
+    pub pack: PromptPack,
+    /// Cursor for the next page, or `None` if retrieval is exhausted.
+    pub next_cursor: Option<PackCursor>,
+}
+
+/// What a conversation has been given so far, for building delta packs.
+///
+/// Call [`PackSession::build`] once per turn: it builds a pack with
+/// [`build_delta_pack`](CtxRepo::build_delta_pack) and records the chunks it
+/// delivered, so later turns only repeat content that changed. When the host drops or summarizes
+/// earlier turns, [`PackSession::forget_before`] makes their content
+/// eligible again. Sessions round-trip through an opaque token, like
+/// [`PackCursor`], so stateless callers can keep one between invocations.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackSession {
+    /// Turns recorded so far.
+    turn: u32,
+    /// ObjectIds of delivered chunks, with the turn that last delivered them.
+    provided: BTreeMap<ObjectId, u32>,
+}
+
+impl PackSession {
+    /// Creates a session for a new conversation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of turns recorded so far.
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    /// Delivered chunks and the turn that delivered each.
+    pub fn provided(&self) -> &BTreeMap<ObjectId, u32> {
+        &self.provided
+    }
+
+    /// Builds the pack for the next turn and records what it delivered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pack can't be built; the session is left
+    /// unchanged.
+    pub fn build(
+        &mut self,
+        repo: &CtxRepo,
+        query: &str,
+        config: &RetrievalConfig,
+    ) -> Result<PromptPack> {
+        let pack = repo.build_delta_pack(query, config, &self.provided)?;
+        self.record(&pack);
+        Ok(pack)
+    }
+
+    /// Records `pack` as the next turn, for packs built some other way.
+    pub fn record(&mut self, pack: &PromptPack) {
+        self.turn += 1;
+        for chunk in &pack.retrieved {
+            self.provided.insert(chunk.object_id, self.turn);
+        }
+    }
+
+    /// Forgets content delivered before `turn`, so the next pack includes
+    /// it again if it is still relevant.
+    pub fn forget_before(&mut self, turn: u32) {
+        self.provided.retain(|_, delivered| *delivered >= turn);
+    }
+
+    /// Encode as an opaque token (hex of the postcard encoding).
+    pub fn to_token(&self) -> Result<String> {
+        let bytes =
+            postcard::to_allocvec(self).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        Ok(hex::encode(bytes))
+    }
+
+    /// Decode a token produced by [`PackSession::to_token`].
+    pub fn from_token(token: &str) -> Result<Self> {
+        let bytes = hex::decode(token.trim()).map_err(|e| {
+            CtxError::InvalidArgument(format!("not a valid pack session token: {}", e))
+        })?;
+        postcard::from_bytes(&bytes).map_err(|e| {
+            CtxError::InvalidArgument(format!("not a valid pack session token: {}", e))
+        })
+    }
+}
+
+/// One part of a streamed prompt pack.
+///
+/// A stream starts with `Header`, then `Narrative` (if any), then chunks in
+/// the order [`build_pack`](CtxRepo::build_pack) would select them, and ends
+/// with `Done` unless the consumer stops early.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PackStreamItem {
+    /// Pack metadata, known before any content is loaded.
+    Header {
+        /// The task or query being addressed.
+        task: String,
+        /// Commit the pack is built from.
+        head_commit: ObjectId,
+        /// Graph expansion metadata.
+        graph_context: GraphContext,
+    },
+    /// Recent narrative excerpts.
+    Narrative {
+        /// The narrative content.
+        content: String,
+    },
+    /// A retrieved chunk.
+    Chunk(RetrievedChunk),
+    /// Final token accounting.
+    Done {
+        /// Token budget accounting for everything streamed.
+        token_budget: TokenBudget,
+    },
+}
+
+impl PackStreamItem {
+    /// Serialize as a single line of JSON (for NDJSON output).
+    pub fn to_json_line(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| CtxError::Serialization(e.to_string()))
+    }
+}
+
 /// Parse query to identify seed nodes.
 pub fn parse_query_for_seeds(query: &str, index: &Index) -> Result<Vec<NodeId>> {
     let mut seeds = Vec::new();
@@ -296,36 +848,6 @@ pub fn estimate_tokens(text: &str) -> u32 {
 /// query, it identifies relevant files, expands the context graph, and assembles
 /// a structured pack of information for LLM consumption.
 ///
-/// # Examples
-///
-/// ```no_run
-/// use ctx_core::{CtxRepo, RetrievalConfig, EdgeLabel, build_pack};
-///
-/// # fn main() -> ctx_core::Result<()> {
-/// let mut repo = CtxRepo::open(".")?;
-///
-/// let config = RetrievalConfig {
-///     token_budget: 10000,
-///     response_reserve: 2000,
-///     expansion_depth: 2,
-///     expand_labels: vec![EdgeLabel::Imports, EdgeLabel::Calls],
-///     max_expanded_nodes: 50,
-///     narrative_days: 7,
-///     include_active_task: true,
-///     include_log: false,
-/// };
-///
-/// let pack = build_pack(
-///     &mut repo,
-///     "authentication middleware",
-///     &config,
-/// )?;
-///
-/// println!("Retrieved {} chunks", pack.retrieved.len());
-/// # Ok(())
-/// # }
-/// ```
-///
 /// # Borrow Checker Notes
 ///
 /// This function needs to access multiple parts of CtxRepo (index, object_store, narrative).
@@ -334,177 +856,2074 @@ pub fn estimate_tokens(text: &str) -> u32 {
 /// 1. Borrow index, collect what we need, drop the borrow
 /// 2. Borrow object_store or narrative as needed
 /// 3. The scoped blocks make these borrow lifetimes explicit
-pub fn build_pack(repo: &mut CtxRepo, query: &str, config: &RetrievalConfig) -> Result<PromptPack> {
-    let head_commit = repo.head_id()?;
+pub(crate) fn build_pack(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+) -> Result<PromptPack> {
+    build_pack_with_progress(repo, query, config, &Progress::default())
+}
 
-    // Step 1: Identify seeds from the query
-    // Note: repo.index() takes &mut self for lazy loading, so we scope it
-    // to drop the borrow before subsequent operations
-    let seeds = {
-        let index = repo.index()?;
-        parse_query_for_seeds(query, index)?
+/// Build a prompt pack like [`build_pack`], reporting the files loaded to
+/// `progress` and stopping with [`CtxError::Cancelled`] if it is cancelled.
+pub(crate) fn build_pack_with_progress(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    progress: &Progress,
+) -> Result<PromptPack> {
+    assemble_pack(repo, query, config, &BTreeMap::new(), progress)
+}
+
+/// Build a prompt pack that leaves out content the caller already has.
+///
+/// `provided` maps the ObjectIds of chunks delivered earlier in a
+/// conversation to the turn that delivered them. Chunks whose content is
+/// in `provided` are listed in [`PromptPack::unchanged`] instead of being
+/// repeated, costing only the tokens of their one-line reference; a file
+/// that changed since has a new ObjectId and is included in full. Selection
+/// is otherwise the same as [`build_pack`], and the budget freed by
+/// references goes to further files. Narrative is always included.
+///
+/// [`PackSession`] keeps `provided` up to date across turns.
+pub(crate) fn build_delta_pack(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    provided: &BTreeMap<ObjectId, u32>,
+) -> Result<PromptPack> {
+    assemble_pack(repo, query, config, provided, &Progress::default())
+}
+
+/// Build a hierarchical prompt pack: a workspace overview and the module
+/// graph, followed by the file chunks [`build_pack`] would select.
+///
+/// The overview lists packages with their targets and dependencies from
+/// HEAD's Cargo snapshot, or top-level directories without one. The module
+/// layer is the tree of files linked by `DeclaresModule` edges below each
+/// crate root, with files in the pack marked `*`; if the tree doesn't fit,
+/// the branches leading to those files are kept first. Each layer stays
+/// within its sub-budget in `budgets`, and the file chunks get the rest of
+/// `config.token_budget`. The files a layer names are its
+/// [`PackLayer::anchors`], which a follow-up [`build_zoom_pack`] drills into.
+pub(crate) fn build_layered_pack(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    budgets: &LayerBudgets,
+) -> Result<PromptPack> {
+    let files_config = RetrievalConfig {
+        token_budget: config
+            .token_budget
+            .saturating_sub(budgets.overview.saturating_add(budgets.modules)),
+        ..config.clone()
     };
+    let mut pack = assemble_pack(
+        repo,
+        query,
+        &files_config,
+        &BTreeMap::new(),
+        &Progress::default(),
+    )?;
 
-    // Step 2: Expand graph from seeds
-    let expansion_config = ExpansionConfig {
-        max_depth: config.expansion_depth,
-        follow_labels: config.expand_labels.clone(),
-        max_nodes: config.max_expanded_nodes,
-        bidirectional: true, // Follow edges in both directions to find files that define items
+    let head: Commit = repo.object_store().get_typed(pack.head_commit)?;
+    let files = crate::staging::flatten_tree(head.root_tree, repo.object_store())?;
+    let snapshot: Option<CargoMetadataSnapshot> = head
+        .cargo_snapshot
+        .map(|id| repo.object_store().get_typed(id))
+        .transpose()?;
+    let in_pack: BTreeSet<&str> = pack
+        .retrieved
+        .iter()
+        .filter(|c| matches!(c.chunk_kind, ChunkKind::FileContent | ChunkKind::Summary))
+        .map(|c| c.title.as_str())
+        .collect();
+
+    let overview = overview_layer(
+        snapshot.as_ref(),
+        &files,
+        budgets.overview,
+        config.tokenizer,
+    );
+    let roots = crate_roots(snapshot.as_ref(), &files);
+    let modules = module_layer(repo, &roots, &in_pack, budgets.modules, config.tokenizer)?;
+
+    let layers: Vec<PackLayer> = [overview, modules]
+        .into_iter()
+        .filter(|layer| !layer.content.is_empty())
+        .collect();
+    pack.token_budget.total = config.token_budget;
+    pack.token_budget.used += layers.iter().map(|layer| layer.tokens).sum::<u32>();
+    pack.layers = layers;
+    Ok(pack)
+}
+
+/// Build a pack that drills into `anchor`, a file named by a layer of a
+/// [`build_layered_pack`] pack.
+///
+/// The anchor is pinned and seeds expansion on its own, so the pack holds
+/// the file and its neighbours. Narrative is left out, since the layered
+/// pack already carried it.
+///
+/// # Errors
+///
+/// Returns `InvalidArgument` if `anchor` isn't a file at HEAD.
+pub(crate) fn build_zoom_pack(
+    repo: &CtxRepo,
+    anchor: &str,
+    config: &RetrievalConfig,
+) -> Result<PromptPack> {
+    let anchor = anchor.trim_start_matches("./");
+    if repo.index()?.lookup_path(anchor)?.is_none() {
+        return Err(CtxError::InvalidArgument(format!(
+            "anchor '{}' is not a file at HEAD",
+            anchor
+        )));
+    }
+    let zoom_config = RetrievalConfig {
+        pinned: vec![NodeId {
+            kind: NodeKind::File,
+            id: anchor.to_string(),
+        }],
+        include_active_task: false,
+        include_log: false,
+        ..config.clone()
     };
+    assemble_pack(
+        repo,
+        anchor,
+        &zoom_config,
+        &BTreeMap::new(),
+        &Progress::default(),
+    )
+}
 
-    let expansion = if seeds.is_empty() {
-        // No seeds found - return empty expansion
-        crate::graph::ExpansionResult {
-            expanded_nodes: Vec::new(),
-            node_depths: std::collections::HashMap::new(),
-            seeds: Vec::new(),
-            truncated: false,
+/// Fills a layer with `entries` (line, anchor) in order until the budget
+/// runs out.
+fn fill_layer(
+    kind: LayerKind,
+    budget: u32,
+    tokenizer: Tokenizer,
+    header: String,
+    entries: Vec<(String, Option<String>)>,
+) -> PackLayer {
+    let mut content = header;
+    let mut anchors = Vec::new();
+    let mut omitted = 0;
+    for (line, anchor) in entries {
+        if omitted > 0 || tokenizer.count(&content) + tokenizer.count(&line) + 1 > budget {
+            omitted += 1;
+            continue;
         }
-    } else {
-        // Scope for index borrow
-        let index = repo.index()?;
-        expand_from_seeds(index, seeds.clone(), &expansion_config)?
-    };
+        content.push('\n');
+        content.push_str(&line);
+        anchors.extend(anchor);
+    }
+    if omitted > 0 {
+        content.push_str(&format!("\n- ... {} more", omitted));
+    }
+    PackLayer {
+        kind,
+        tokens: tokenizer.count(&content),
+        content,
+        budget,
+        omitted,
+        anchors,
+    }
+}
 
-    // Step 3: Retrieve file content for expanded nodes
-    // Strategy: First collect ObjectIds (requires index), then load content (requires object_store)
-    // We can't hold both borrows simultaneously, so we do it in two passes
-    let file_metadata: Vec<(NodeId, ObjectId, u32)> = {
-        let index = repo.index()?;
-        expansion
-            .expanded_nodes
-            .iter()
-            .filter_map(|node| {
-                if node.kind == NodeKind::File {
-                    let depth = expansion.node_depths.get(node).copied().unwrap_or(0);
-                    // Compute relevance as fixed-point: 1000 / (1 + depth)
-                    // depth=0: 1000 (1.0), depth=1: 500 (0.5), depth=2: 333 (0.333), etc.
-                    let relevance_score = 1000 / (1 + depth);
-                    if let Ok(Some(obj_id)) = index.lookup_path(&node.id) {
-                        Some((node.clone(), obj_id, relevance_score))
-                    } else {
-                        None
-                    }
+/// Path of `path` relative to the Cargo workspace root, if it is a file
+/// at HEAD.
+fn workspace_path(
+    snapshot: &CargoMetadataSnapshot,
+    path: &str,
+    files: &BTreeMap<String, ObjectId>,
+) -> Option<String> {
+    let relative = path
+        .strip_prefix(snapshot.workspace_root.as_str())
+        .map(|rest| rest.trim_start_matches('/'))
+        .unwrap_or(path);
+    files.contains_key(relative).then(|| relative.to_string())
+}
+
+/// The overview layer: packages from the Cargo snapshot, or top-level
+/// directories without one.
+fn overview_layer(
+    snapshot: Option<&CargoMetadataSnapshot>,
+    files: &BTreeMap<String, ObjectId>,
+    budget: u32,
+    tokenizer: Tokenizer,
+) -> PackLayer {
+    let Some(snapshot) = snapshot.filter(|s| !s.packages.is_empty()) else {
+        let mut dirs: BTreeMap<&str, usize> = BTreeMap::new();
+        for path in files.keys() {
+            let top = path.split_once('/').map_or(path.as_str(), |(dir, _)| dir);
+            *dirs.entry(top).or_default() += 1;
+        }
+        let entries = dirs
+            .into_iter()
+            .map(|(top, count)| {
+                if files.contains_key(top) {
+                    (format!("- {}", top), Some(top.to_string()))
                 } else {
-                    None
+                    (format!("- {}/ ({} files)", top, count), None)
                 }
             })
-            .collect()
+            .collect();
+        let header = format!("{} files", files.len());
+        return fill_layer(LayerKind::Overview, budget, tokenizer, header, entries);
     };
 
-    // Load file content using the collected ObjectIds
-    let object_store = repo.object_store();
-    let mut chunks = Vec::new();
-    for (node, obj_id, relevance_score) in file_metadata {
-        if let Ok(content_bytes) = object_store.get_blob(obj_id) {
-            if let Ok(content) = String::from_utf8(content_bytes) {
-                chunks.push(RetrievedChunk {
-                    title: node.id.clone(),
-                    object_id: obj_id,
-                    snippet: content,
-                    relevance_score,
-                    chunk_kind: ChunkKind::FileContent,
-                });
+    let members: BTreeSet<&str> = snapshot.packages.iter().map(|p| p.name.as_str()).collect();
+    let entries = snapshot
+        .packages
+        .iter()
+        .map(|package| {
+            let targets: Vec<String> = package
+                .targets
+                .iter()
+                .filter(|t| t.kind != TargetKind::CustomBuild)
+                .map(|t| match t.kind {
+                    TargetKind::Lib => "lib".to_string(),
+                    kind => format!("{} {}", format!("{:?}", kind).to_lowercase(), t.name),
+                })
+                .collect();
+            let deps: Vec<&str> = package
+                .dependencies
+                .iter()
+                .filter(|d| d.kind == DepKind::Normal)
+                .map(|d| d.name.as_str())
+                .collect();
+            let mut line = format!(
+                "- {} {} [{}]",
+                package.name,
+                package.version,
+                targets.join(", ")
+            );
+            if !deps.is_empty() {
+                // Workspace members first, since they are in this repository
+                let (local, external): (Vec<&str>, Vec<&str>) =
+                    deps.iter().partition(|d| members.contains(*d));
+                line.push_str(&format!(
+                    " depends on {}",
+                    local
+                        .into_iter()
+                        .chain(external)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
             }
-        }
-    }
+            let anchor = package
+                .targets
+                .iter()
+                .find(|t| matches!(t.kind, TargetKind::Lib | TargetKind::Bin))
+                .and_then(|t| workspace_path(snapshot, &t.src_path, files));
+            (line, anchor)
+        })
+        .collect();
+    let header = format!("{} packages", snapshot.packages.len());
+    fill_layer(LayerKind::Overview, budget, tokenizer, header, entries)
+}
 
-    // Step 4: Include narrative
-    let mut narrative_content = String::new();
+/// Crate root files: library and binary targets from the Cargo snapshot,
+/// or every `src/lib.rs` and `src/main.rs` without one.
+fn crate_roots(
+    snapshot: Option<&CargoMetadataSnapshot>,
+    files: &BTreeMap<String, ObjectId>,
+) -> Vec<String> {
+    let mut roots: Vec<String> = snapshot
+        .into_iter()
+        .flat_map(|s| {
+            s.packages
+                .iter()
+                .flat_map(|p| &p.targets)
+                .filter(|t| matches!(t.kind, TargetKind::Lib | TargetKind::Bin))
+                .filter_map(|t| workspace_path(s, &t.src_path, files))
+        })
+        .collect();
+    if roots.is_empty() {
+        roots = files
+            .keys()
+            .filter(|path| {
+                ["src/lib.rs", "src/main.rs"]
+                    .iter()
+                    .any(|root| *path == root || path.ends_with(&format!("/{}", root)))
+            })
+            .cloned()
+            .collect();
+    }
+    roots.sort();
+    roots.dedup();
+    roots
+}
 
-    if config.include_active_task || config.include_log {
-        let narrative = repo.narrative();
-        if let Ok(files) = narrative.list_files() {
-            // Look for task files
-            if config.include_active_task {
-                for file in &files {
-                    if file.starts_with("tasks/") && file.ends_with(".md") {
-                        if let Ok(content_bytes) = narrative.read_file(file) {
-                            if let Ok(content) = String::from_utf8(content_bytes) {
-                                narrative_content.push_str(&format!("## Task: {}\n\n", file));
-                                narrative_content.push_str(&content);
-                                narrative_content.push_str("\n\n");
-                                break; // Just include first task for now
-                            }
-                        }
-                    }
-                }
+/// The module layer: the `DeclaresModule` tree below each crate root.
+fn module_layer(
+    repo: &CtxRepo,
+    roots: &[String],
+    in_pack: &BTreeSet<&str>,
+    budget: u32,
+    tokenizer: Tokenizer,
+) -> Result<PackLayer> {
+    // Depth-first, so each module follows the file declaring it
+    let mut tree: Vec<(usize, String)> = Vec::new();
+    let mut parents: HashMap<String, String> = HashMap::new();
+    {
+        let index = repo.index()?;
+        let mut seen = HashSet::new();
+        let mut stack: Vec<(usize, String)> = roots.iter().rev().map(|r| (0, r.clone())).collect();
+        while let Some((depth, path)) = stack.pop() {
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let node = NodeId {
+                kind: NodeKind::File,
+                id: path.clone(),
+            };
+            let mut children: Vec<String> = index
+                .get_edges_from(&node, EdgeLabel::DeclaresModule)?
+                .into_iter()
+                .filter(|child| child.kind == NodeKind::File && !seen.contains(&child.id))
+                .map(|child| child.id)
+                .collect();
+            children.sort();
+            for child in children.into_iter().rev() {
+                parents.entry(child.clone()).or_insert_with(|| path.clone());
+                stack.push((depth + 1, child));
             }
+            tree.push((depth, path));
+        }
+    }
+    if tree.is_empty() {
+        return Ok(fill_layer(
+            LayerKind::Modules,
+            budget,
+            tokenizer,
+            String::new(),
+            vec![],
+        ));
+    }
 
-            // Look for log files
-            if config.include_log {
-                let mut log_files: Vec<_> = files
-                    .iter()
-                    .filter(|f| f.starts_with("log/") && f.ends_with(".md"))
-                    .collect();
-                log_files.sort();
-                log_files.reverse(); // Most recent first
-
-                for file in log_files.iter().take(5) {
-                    if let Ok(content_bytes) = narrative.read_file(file) {
-                        if let Ok(content) = String::from_utf8(content_bytes) {
-                            narrative_content.push_str(&format!("## Log: {}\n\n", file));
-                            narrative_content.push_str(&content);
-                            narrative_content.push_str("\n\n");
-                        }
-                    }
-                }
+    let line = |depth: usize, path: &str| {
+        let marker = if in_pack.contains(path) { " *" } else { "" };
+        format!("{}- {}{}", "  ".repeat(depth), path, marker)
+    };
+    let header = "Modules below each crate root (* = in this pack)".to_string();
+    let full: u32 = tree
+        .iter()
+        .map(|(depth, path)| tokenizer.count(&line(*depth, path)) + 1)
+        .sum();
+    if tokenizer.count(&header) + full <= budget {
+        let entries = tree
+            .iter()
+            .map(|(depth, path)| (line(*depth, path), Some(path.clone())))
+            .collect();
+        return Ok(fill_layer(
+            LayerKind::Modules,
+            budget,
+            tokenizer,
+            header,
+            entries,
+        ));
+    }
+
+    // Too big: keep the branches leading to files in the pack
+    let mut keep: HashSet<&str> = HashSet::new();
+    for path in in_pack {
+        let mut current = Some(*path);
+        while let Some(path) = current {
+            if !keep.insert(path) {
+                break;
             }
+            current = parents.get(path).map(String::as_str);
         }
     }
+    let omitted = tree.len()
+        - tree
+            .iter()
+            .filter(|(_, p)| keep.contains(p.as_str()))
+            .count();
+    let entries = tree
+        .iter()
+        .filter(|(_, path)| keep.contains(path.as_str()))
+        .map(|(depth, path)| (line(*depth, path), Some(path.clone())))
+        .collect();
+    let mut layer = fill_layer(LayerKind::Modules, budget, tokenizer, header, entries);
+    if omitted > 0 {
+        layer.content.push_str(&format!(
+            "\n- ... {} modules without files in this pack",
+            omitted
+        ));
+        layer.tokens = tokenizer.count(&layer.content);
+        layer.omitted += omitted;
+    }
+    Ok(layer)
+}
+
+/// Shared implementation of [`build_pack`] and [`build_delta_pack`].
+fn assemble_pack(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    provided: &BTreeMap<ObjectId, u32>,
+    progress: &Progress,
+) -> Result<PromptPack> {
+    let head_commit = repo.head_id()?;
+    progress.tick("seeds", 0, 0)?;
+
+    // Step 1: Identify seeds from the query
+    // Note: repo.index() takes &mut self for lazy loading, so we scope it
+    // to drop the borrow before subsequent operations
+    let mut seeds = {
+        let index = repo.index()?;
+        parse_query_for_seeds(query, &index)?
+    };
+    let query_seeds = seeds.len();
+    let glossary_chunk = glossary_context(repo, query, &mut seeds)?;
+    add_document_seeds(repo, query, &mut seeds)?;
+    let matched_seeds = seeds.len();
+    add_pinned_seeds(repo, config, &mut seeds)?;
+    let pinned_seeds = seeds.len();
+    let (seeds, frecency) = apply_frecency(repo, seeds, config)?;
+    let boosts = Boosts {
+        frecency,
+        feedback: apply_feedback(repo, config)?,
+    };
+
+    // Step 2: Expand graph from seeds
+    progress.tick("expand", 0, 0)?;
+    let expansion = expand_seeds(repo, &seeds, config, config.expansion_depth)?;
+
+    // Step 3: Retrieve file content for expanded nodes, and the decisions
+    // made about those files
+    let mut chunks = load_file_chunks(repo, &expansion, config, &boosts, progress)?;
+    let decisions = load_decision_chunks(repo, query, &chunks)?;
+    let facts_chunk = known_facts(repo, &seeds, &chunks)?.filter(|_| !config.decisions_only);
+    let notes_chunk = commit_notes(repo, &chunks)?.filter(|_| !config.decisions_only);
+    let glossary_chunk = glossary_chunk.filter(|_| !config.decisions_only);
+    let mut answer_chunks = if config.decisions_only {
+        Vec::new()
+    } else {
+        past_answers(repo, query)?
+    };
+    if config.decisions_only {
+        chunks = decisions;
+    } else {
+        chunks.extend(decisions);
+    }
+
+    // Step 4: Include narrative
+    let narrative_content = if config.decisions_only {
+        String::new()
+    } else {
+        collect_narrative(repo, config, query, &seeds)
+    };
 
     // Step 5: Budget allocation
+    progress.tick("budget", 0, 0)?;
     let available_tokens = config.token_budget.saturating_sub(config.response_reserve);
-    let narrative_tokens = estimate_tokens(&narrative_content);
+    let narrative_tokens = config.tokenizer.count(&narrative_content);
 
-    // Sort chunks by relevance score (descending)
-    chunks.sort_by(|a, b| b.relevance_score.cmp(&a.relevance_score));
+    // Pinned files first, then by relevance score (descending)
+    chunks.sort_by_key(|c| {
+        (
+            !config.is_pinned(&c.title),
+            std::cmp::Reverse(c.relevance_score),
+        )
+    });
 
-    // Greedily fill budget
+    // Greedily fill budget, leading with the glossary, known facts, commit
+    // notes and past answers if they fit
     let mut selected_chunks = Vec::new();
     let mut tokens_used = narrative_tokens;
 
-    for chunk in chunks {
-        let chunk_tokens = estimate_tokens(&chunk.snippet);
+    // Token count and drop reason (None if included) per file, for --explain
+    let mut outcomes: HashMap<String, (u32, Option<String>)> = HashMap::new();
+
+    // Content provided in an earlier turn becomes a reference, which is
+    // cheap enough to always include
+    let mut unchanged = Vec::new();
+    let mut is_fresh = |chunk: &RetrievedChunk| {
+        let Some(&turn) = provided.get(&chunk.object_id) else {
+            return true;
+        };
+        let reference = UnchangedChunk {
+            title: chunk.title.clone(),
+            object_id: chunk.object_id,
+            turn,
+        };
+        tokens_used += config.tokenizer.count(&reference.describe());
+        if config.explain && chunk.chunk_kind == ChunkKind::FileContent {
+            let reason = format!("unchanged since turn {}", turn);
+            let tokens = config.tokenizer.count(&chunk.snippet);
+            outcomes.insert(chunk.title.clone(), (tokens, Some(reason)));
+        }
+        unchanged.push(reference);
+        false
+    };
+    let glossary_chunk = glossary_chunk.filter(&mut is_fresh);
+    let facts_chunk = facts_chunk.filter(&mut is_fresh);
+    let notes_chunk = notes_chunk.filter(&mut is_fresh);
+    answer_chunks.retain(&mut is_fresh);
+    chunks.retain(&mut is_fresh);
+
+    for chunk in glossary_chunk
+        .into_iter()
+        .chain(facts_chunk)
+        .chain(notes_chunk)
+        .chain(answer_chunks)
+    {
+        let chunk_tokens = config.tokenizer.count(&chunk.snippet);
         if tokens_used + chunk_tokens <= available_tokens {
             tokens_used += chunk_tokens;
             selected_chunks.push(chunk);
-        } else {
-            // Budget exceeded
-            break;
         }
     }
 
-    // Build graph context
-    let graph_context = GraphContext {
-        seed_nodes: seeds
-            .iter()
-            .map(|n| format!("{:?}::{}", n.kind, n.id))
-            .collect(),
-        expanded_nodes: expansion
-            .expanded_nodes
-            .iter()
-            .map(|n| format!("{:?}::{}", n.kind, n.id))
-            .collect(),
-        expansion_depth: config.expansion_depth,
-        scc_dag_used: false,
+    // Low-relevance files that don't fit may be replaced by their summary
+    let summaries = match config.summary_max_relevance {
+        Some(_) => repo.summaries()?,
+        None => SummaryTable::default(),
     };
 
-    Ok(PromptPack {
-        task: query.to_string(),
-        head_commit,
+    let mut budget_exhausted = false;
+    for chunk in chunks {
+        let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+        let fits = !budget_exhausted && tokens_used + chunk_tokens <= available_tokens;
+        if fits || config.is_pinned(&chunk.title) {
+            tokens_used += chunk_tokens;
+            if config.explain {
+                outcomes.insert(chunk.title.clone(), (chunk_tokens, None));
+            }
+            selected_chunks.push(chunk);
+        } else {
+            if let Some(summary) = summary_chunk(repo, &summaries, &chunk, config)? {
+                let summary_tokens = config.tokenizer.count(&summary.snippet);
+                if tokens_used + summary_tokens <= available_tokens {
+                    tokens_used += summary_tokens;
+                    if config.explain {
+                        let reason = format!("replaced by a {}-token summary", summary_tokens);
+                        outcomes.insert(chunk.title, (chunk_tokens, Some(reason)));
+                    }
+                    selected_chunks.push(summary);
+                    continue;
+                }
+            }
+            // Budget exceeded; the rest is only visited to explain it or
+            // to find summaries that still fit
+            if !config.explain && summaries.is_empty() {
+                break;
+            }
+            budget_exhausted = true;
+            let reason = format!(
+                "token budget exhausted ({} tokens needed, {} left)",
+                chunk_tokens,
+                available_tokens.saturating_sub(tokens_used)
+            );
+            outcomes.insert(chunk.title, (chunk_tokens, Some(reason)));
+        }
+    }
+
+    record_inclusions(repo, &selected_chunks, config)?;
+
+    let explanation = if config.explain {
+        let sources = SeedSources {
+            query: query_seeds,
+            matched: matched_seeds,
+            pinned: pinned_seeds,
+        };
+        Some(explain_pack(
+            repo, config, &seeds, sources, &expansion, &boosts, &outcomes,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(PromptPack {
+        task: query.to_string(),
+        head_commit,
+        retrieved: selected_chunks,
+        graph_context: graph_context(&seeds, &expansion, config.expansion_depth),
+        recent_narrative: narrative_content,
+        token_budget: TokenBudget {
+            total: config.token_budget,
+            used: tokens_used,
+            reserved_for_response: config.response_reserve,
+            narrative: narrative_tokens,
+        },
+        explanation,
+        unchanged,
+        layers: Vec::new(),
+    })
+}
+
+/// Build one prompt pack across `repo` and the child repositories it was
+/// opened with (see [`CtxRepo::open_with_scope`]).
+///
+/// Each repository builds its own pack with the full budget, and the packs
+/// are merged under `config.token_budget`. File chunks from all of them are
+/// ranked together by relevance, with child paths prefixed by the child's
+/// location under `repo`'s root, so pins and excludes are given relative to
+/// that root. Narrative from `repo` comes first; each child's follows under
+/// a heading while the total stays within `narrative_budget`. A child whose
+/// pack can't be built is skipped with a warning. Merged packs carry no
+/// explanation.
+///
+/// Without child repositories this is [`build_pack`].
+///
+/// # Errors
+///
+/// Returns an error if `repo`'s own pack can't be built.
+pub(crate) fn build_federated_pack(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+) -> Result<PromptPack> {
+    let children: Vec<(String, std::path::PathBuf)> = repo
+        .child_repos()
+        .iter()
+        .map(|child| {
+            let prefix = child
+                .strip_prefix(repo.root())
+                .unwrap_or(child)
+                .to_string_lossy()
+                .replace('\\', "/");
+            (prefix, child.clone())
+        })
+        .collect();
+    if children.is_empty() {
+        return build_pack(repo, query, config);
+    }
+
+    // Route each pinned file to the repository that holds it
+    let owner = |path: &str| {
+        children
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(&format!("{}/", prefix)))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, _)| prefix.as_str())
+    };
+    let pins_for = |member: Option<&str>| -> Vec<NodeId> {
+        config
+            .pinned
+            .iter()
+            .filter_map(|node| {
+                if node.kind != NodeKind::File {
+                    return member.is_none().then(|| node.clone());
+                }
+                match (owner(&node.id), member) {
+                    (None, None) => Some(node.clone()),
+                    (Some(owner), Some(member)) if owner == member => Some(NodeId {
+                        kind: NodeKind::File,
+                        id: node.id[owner.len() + 1..].to_string(),
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    };
+
+    let member_config = |member: Option<&str>| RetrievalConfig {
+        explain: false,
+        pinned: pins_for(member),
+        ..config.clone()
+    };
+    let mut members = vec![(
+        String::new(),
+        build_pack(repo, query, &member_config(None))?,
+    )];
+    for (prefix, root) in &children {
+        let result = CtxRepo::open(root)
+            .and_then(|child| build_pack(&child, query, &member_config(Some(prefix))));
+        match result {
+            Ok(pack) => members.push((prefix.clone(), pack)),
+            Err(e) => warn!(
+                child = %root.display(),
+                error = %e,
+                "Skipping child repository in federated pack"
+            ),
+        }
+    }
+
+    let head_commit = members[0].1.head_commit;
+    let mut narrative = String::new();
+    let mut glossary_chunks = Vec::new();
+    let mut chunks = Vec::new();
+    let mut graph = GraphContext {
+        seed_nodes: Vec::new(),
+        expanded_nodes: Vec::new(),
+        expansion_depth: config.expansion_depth,
+        scc_dag_used: false,
+    };
+    for (prefix, pack) in members {
+        let qualify_node = |node: String| {
+            if prefix.is_empty() {
+                node
+            } else {
+                format!("{}:{}", prefix, node)
+            }
+        };
+        graph
+            .seed_nodes
+            .extend(pack.graph_context.seed_nodes.into_iter().map(qualify_node));
+        graph.expanded_nodes.extend(
+            pack.graph_context
+                .expanded_nodes
+                .into_iter()
+                .map(qualify_node),
+        );
+        graph.scc_dag_used |= pack.graph_context.scc_dag_used;
+
+        if !pack.recent_narrative.is_empty() {
+            let section = if prefix.is_empty() {
+                pack.recent_narrative
+            } else {
+                format!("## {}\n\n{}", prefix, pack.recent_narrative)
+            };
+            let combined = if narrative.is_empty() {
+                section
+            } else {
+                format!("{}\n\n{}", narrative, section)
+            };
+            if prefix.is_empty() || config.tokenizer.count(&combined) <= config.narrative_budget {
+                narrative = combined;
+            }
+        }
+
+        for mut chunk in pack.retrieved {
+            match chunk.chunk_kind {
+                ChunkKind::Glossary
+                | ChunkKind::Fact
+                | ChunkKind::Annotation
+                | ChunkKind::Answer => glossary_chunks.push(chunk),
+                ChunkKind::FileContent | ChunkKind::Summary | ChunkKind::Stub
+                    if !prefix.is_empty() =>
+                {
+                    chunk.title = format!("{}/{}", prefix, chunk.title);
+                    chunks.push(chunk);
+                }
+                _ => chunks.push(chunk),
+            }
+        }
+    }
+
+    chunks.retain(|chunk| config.excluded_by(&chunk.title).is_none());
+    chunks.sort_by_key(|c| {
+        (
+            !config.is_pinned(&c.title),
+            std::cmp::Reverse(c.relevance_score),
+        )
+    });
+
+    // Same filling rule as `build_pack`: glossaries, facts and past answers
+    // lead if they fit, then pins and the best-ranked files until the budget runs out
+    let available_tokens = config.token_budget.saturating_sub(config.response_reserve);
+    let narrative_tokens = config.tokenizer.count(&narrative);
+    let mut tokens_used = narrative_tokens;
+    let mut selected_chunks = Vec::new();
+    for chunk in glossary_chunks {
+        let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+        if tokens_used + chunk_tokens <= available_tokens {
+            tokens_used += chunk_tokens;
+            selected_chunks.push(chunk);
+        }
+    }
+    for chunk in chunks {
+        let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+        if tokens_used + chunk_tokens > available_tokens && !config.is_pinned(&chunk.title) {
+            break;
+        }
+        tokens_used += chunk_tokens;
+        selected_chunks.push(chunk);
+    }
+
+    Ok(PromptPack {
+        task: query.to_string(),
+        head_commit,
+        retrieved: selected_chunks,
+        graph_context: graph,
+        recent_narrative: narrative,
+        token_budget: TokenBudget {
+            total: config.token_budget,
+            used: tokens_used,
+            reserved_for_response: config.response_reserve,
+            narrative: narrative_tokens,
+        },
+        explanation: None,
+        unchanged: Vec::new(),
+        layers: Vec::new(),
+    })
+}
+
+/// Build a prompt pack, reusing a cached pack for an identical request.
+///
+/// The cache key covers the query, HEAD, every [`RetrievalConfig`] field,
+/// recorded feedback and (when narrative is included) the size and
+/// modification time of each narrative file, so any of those changing
+/// forces a rebuild. File frecency is not part of the key; the cache TTL
+/// bounds how stale frecency-ranked packs can get. Cache failures are logged and fall back to building.
+pub(crate) fn build_pack_cached(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    cache: &PackCache,
+) -> Result<PromptPack> {
+    let key = pack_cache_key(repo, query, config)?;
+    let now = repo.now_unix();
+
+    match cache.get(&key, now) {
+        Ok(Some(pack)) => {
+            debug!(key = %key, "Prompt pack cache hit");
+            record_inclusions(repo, &pack.retrieved, config)?;
+            return Ok(pack);
+        }
+        Ok(None) => {}
+        Err(e) => warn!(error = %e, "Failed to read prompt pack cache"),
+    }
+
+    let pack = build_pack(repo, query, config)?;
+    if let Err(e) = cache.put(&key, &pack, now) {
+        warn!(error = %e, "Failed to write prompt pack cache");
+    }
+    Ok(pack)
+}
+
+/// Hash of everything that determines the pack built for `query`.
+fn pack_cache_key(repo: &CtxRepo, query: &str, config: &RetrievalConfig) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"ctx-pack-v1\0");
+    hasher.update(query.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(repo.head_id()?.as_hex().as_bytes());
+    hasher.update(b"\0");
+    // Debug output names every field, so new config fields change the key
+    hasher.update(format!("{:?}", config).as_bytes());
+    // A restricted handle mustn't be served a pack built with wider access
+    if !repo.capabilities().is_empty() {
+        hasher.update(format!("\0{:?}", repo.capabilities()).as_bytes());
+    }
+    if config.summary_max_relevance.is_some() {
+        if let Some(summaries) = repo.refs().read_summaries()? {
+            hasher.update(format!("\0{}", summaries).as_bytes());
+        }
+    }
+    if config.feedback_boost {
+        if let Some(feedback) = repo.refs().read_feedback()? {
+            hasher.update(format!("\0feedback\0{}", feedback).as_bytes());
+        }
+    }
+
+    if config.include_active_task || config.include_log {
+        // Log selection depends on the day
+        hasher.update(format!("\0{}", repo.now_unix() / 86_400).as_bytes());
+        let narrative = repo.narrative();
+        for file in narrative.list_files()? {
+            let metadata = std::fs::metadata(narrative.root().join(&file))?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            hasher.update(format!("\0{}\0{}\0{}", file, metadata.len(), modified).as_bytes());
+        }
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Build one page of a prompt pack, continuing from a previous page.
+///
+/// Pass `None` for the first page. Each returned [`PagedPack`] carries a
+/// cursor for the next page (if more context is available); chunks already
+/// delivered on earlier pages are never repeated. Once the current expansion
+/// has been fully delivered, later pages widen the graph frontier one hop at a
+/// time until the expansion stops growing.
+///
+/// Narrative content is only included on the first page.
+///
+/// # Errors
+///
+/// Returns [`CtxError::InvalidCursor`](crate::CtxError::InvalidCursor) if the
+/// cursor was issued for a different HEAD commit, query or config.
+pub(crate) fn build_pack_paged(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    cursor: Option<&PackCursor>,
+) -> Result<PagedPack> {
+    let head_commit = repo.head_id()?;
+    let request = cursor_request_hash(query, config);
+
+    let mut cursor = match cursor {
+        Some(cursor) => {
+            if cursor.head_commit != head_commit {
+                return Err(CtxError::InvalidCursor(format!(
+                    "cursor was issued for commit {}, but HEAD is now {}",
+                    cursor.head_commit, head_commit
+                )));
+            }
+            if cursor.request != request {
+                return Err(CtxError::InvalidCursor(
+                    "cursor was issued for a different query or retrieval config".to_string(),
+                ));
+            }
+            cursor.clone()
+        }
+        None => PackCursor {
+            head_commit,
+            request,
+            delivered: BTreeSet::new(),
+            frontier_depth: config.expansion_depth,
+            page: 0,
+        },
+    };
+
+    let mut seeds = {
+        let index = repo.index()?;
+        parse_query_for_seeds(query, &index)?
+    };
+    let glossary_chunk = glossary_context(repo, query, &mut seeds)?;
+    add_document_seeds(repo, query, &mut seeds)?;
+    add_pinned_seeds(repo, config, &mut seeds)?;
+    let (seeds, frecency) = apply_frecency(repo, seeds, config)?;
+    let boosts = Boosts {
+        frecency,
+        feedback: apply_feedback(repo, config)?,
+    };
+
+    // Find the shallowest frontier that still has undelivered content
+    let mut expansion = expand_seeds(repo, &seeds, config, cursor.frontier_depth)?;
+    let mut chunks = undelivered_chunks(repo, &expansion, config, &boosts, &cursor)?;
+    while chunks.is_empty() && !seeds.is_empty() {
+        let deeper = expand_seeds(repo, &seeds, config, cursor.frontier_depth + 1)?;
+        if deeper.expanded_nodes.len() <= expansion.expanded_nodes.len() {
+            break;
+        }
+        cursor.frontier_depth += 1;
+        expansion = deeper;
+        chunks = undelivered_chunks(repo, &expansion, config, &boosts, &cursor)?;
+    }
+
+    let narrative_content = if cursor.page == 0 {
+        collect_narrative(repo, config, query, &seeds)
+    } else {
+        String::new()
+    };
+
+    let available_tokens = config.token_budget.saturating_sub(config.response_reserve);
+    let narrative_tokens = config.tokenizer.count(&narrative_content);
+    let mut tokens_used = narrative_tokens;
+
+    chunks.sort_by_key(|c| {
+        (
+            !config.is_pinned(&c.title),
+            std::cmp::Reverse(c.relevance_score),
+        )
+    });
+
+    let mut selected_chunks = Vec::new();
+    // Like narrative, the glossary only leads the first page
+    if let Some(chunk) = glossary_chunk.filter(|_| cursor.page == 0) {
+        let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+        if tokens_used + chunk_tokens <= available_tokens {
+            tokens_used += chunk_tokens;
+            selected_chunks.push(chunk);
+        }
+    }
+    let mut remaining = chunks.into_iter().peekable();
+    while let Some(chunk) = remaining.peek() {
+        let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+        // Pinned files are undelivered only until the first page
+        if tokens_used + chunk_tokens <= available_tokens || config.is_pinned(&chunk.title) {
+            tokens_used += chunk_tokens;
+            let chunk = remaining.next().expect("peeked chunk");
+            cursor.delivered.insert(chunk.object_id);
+            selected_chunks.push(chunk);
+        } else if selected_chunks.is_empty() && chunk_tokens > available_tokens {
+            // A chunk larger than the whole budget can never be delivered;
+            // skip it so paging always makes progress.
+            let chunk = remaining.next().expect("peeked chunk");
+            cursor.delivered.insert(chunk.object_id);
+        } else {
+            break;
+        }
+    }
+
+    let has_more = remaining.peek().is_some() || {
+        let deeper = expand_seeds(repo, &seeds, config, cursor.frontier_depth + 1)?;
+        deeper.expanded_nodes.len() > expansion.expanded_nodes.len()
+    };
+
+    record_inclusions(repo, &selected_chunks, config)?;
+
+    let pack = PromptPack {
+        task: query.to_string(),
+        head_commit,
         retrieved: selected_chunks,
-        graph_context,
+        graph_context: graph_context(&seeds, &expansion, cursor.frontier_depth),
         recent_narrative: narrative_content,
         token_budget: TokenBudget {
             total: config.token_budget,
             used: tokens_used,
             reserved_for_response: config.response_reserve,
+            narrative: narrative_tokens,
         },
+        explanation: None,
+        unchanged: Vec::new(),
+        layers: Vec::new(),
+    };
+
+    cursor.page += 1;
+    Ok(PagedPack {
+        pack,
+        next_cursor: has_more.then_some(cursor),
     })
 }
 
+/// Build a prompt pack incrementally, passing each part to `sink`.
+///
+/// Selects the same content as [`build_pack`], but file contents are loaded
+/// one at a time and handed over as soon as they fit the budget, so memory
+/// use doesn't grow with the budget. Returning `ControlFlow::Break` from
+/// `sink` stops the stream; only chunks already delivered are recorded for
+/// frecency. Returns the token accounting for what was streamed.
+pub(crate) fn build_pack_streaming<F>(
+    repo: &CtxRepo,
+    query: &str,
+    config: &RetrievalConfig,
+    mut sink: F,
+) -> Result<TokenBudget>
+where
+    F: FnMut(PackStreamItem) -> Result<ControlFlow<()>>,
+{
+    let head_commit = repo.head_id()?;
+
+    let mut seeds = {
+        let index = repo.index()?;
+        parse_query_for_seeds(query, &index)?
+    };
+    let glossary_chunk = glossary_context(repo, query, &mut seeds)?;
+    add_document_seeds(repo, query, &mut seeds)?;
+    add_pinned_seeds(repo, config, &mut seeds)?;
+    let (seeds, frecency) = apply_frecency(repo, seeds, config)?;
+    let boosts = Boosts {
+        frecency,
+        feedback: apply_feedback(repo, config)?,
+    };
+    let expansion = expand_seeds(repo, &seeds, config, config.expansion_depth)?;
+
+    // Only ids and scores are held up front; contents are loaded on demand
+    let mut candidates = file_candidates(repo, &expansion, &boosts)?;
+    candidates
+        .sort_by_key(|(node, _, score)| (!config.is_pinned(&node.id), std::cmp::Reverse(*score)));
+
+    let narrative_content = collect_narrative(repo, config, query, &seeds);
+    let narrative_tokens = config.tokenizer.count(&narrative_content);
+    let available_tokens = config.token_budget.saturating_sub(config.response_reserve);
+    let mut token_budget = TokenBudget {
+        total: config.token_budget,
+        used: narrative_tokens,
+        reserved_for_response: config.response_reserve,
+        narrative: narrative_tokens,
+    };
+
+    let mut delivered = Vec::new();
+    let finished = 'stream: {
+        let header = PackStreamItem::Header {
+            task: query.to_string(),
+            head_commit,
+            graph_context: graph_context(&seeds, &expansion, config.expansion_depth),
+        };
+        if sink(header)?.is_break() {
+            break 'stream false;
+        }
+        if !narrative_content.is_empty() {
+            let narrative = PackStreamItem::Narrative {
+                content: narrative_content,
+            };
+            if sink(narrative)?.is_break() {
+                break 'stream false;
+            }
+        }
+
+        if let Some(chunk) = glossary_chunk {
+            let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+            if token_budget.used + chunk_tokens <= available_tokens {
+                token_budget.used += chunk_tokens;
+                if sink(PackStreamItem::Chunk(chunk))?.is_break() {
+                    break 'stream false;
+                }
+            }
+        }
+
+        for (node, obj_id, relevance_score) in candidates {
+            let Some(chunk) =
+                load_file_chunk(repo, head_commit, node, obj_id, relevance_score, config)?
+            else {
+                continue;
+            };
+            let chunk_tokens = config.tokenizer.count(&chunk.snippet);
+            if token_budget.used + chunk_tokens > available_tokens
+                && !config.is_pinned(&chunk.title)
+            {
+                break;
+            }
+            token_budget.used += chunk_tokens;
+            delivered.push(chunk.title.clone());
+            if sink(PackStreamItem::Chunk(chunk))?.is_break() {
+                break 'stream false;
+            }
+        }
+        true
+    };
+
+    record_accessed_paths(repo, delivered, config)?;
+
+    if finished {
+        // The consumer may still stop here; there is nothing left to skip
+        let _ = sink(PackStreamItem::Done {
+            token_budget: token_budget.clone(),
+        })?;
+    }
+    Ok(token_budget)
+}
+
+/// How many of a pack's seeds came from each source.
+///
+/// Seeds are ordered query matches, glossary terms, pinned nodes, then
+/// frecent files.
+struct SeedSources {
+    /// Seeds named by the query.
+    query: usize,
+    /// Query seeds plus glossary term seeds.
+    matched: usize,
+    /// Matched seeds plus pinned nodes.
+    pinned: usize,
+}
+
+/// Describe how each candidate file was reached, scored and selected.
+///
+/// `outcomes` maps each loaded file to its token count and drop reason
+/// (`None` if included); candidates missing from it were filtered out
+/// before budgeting.
+fn explain_pack(
+    repo: &CtxRepo,
+    config: &RetrievalConfig,
+    seeds: &[NodeId],
+    sources: SeedSources,
+    expansion: &ExpansionResult,
+    boosts: &Boosts,
+    outcomes: &HashMap<String, (u32, Option<String>)>,
+) -> Result<PackExplanation> {
+    let node_label = |node: &NodeId| format!("{:?}::{}", node.kind, node.id);
+
+    let seeds = seeds
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let reason = if i < sources.query {
+                match node.kind {
+                    NodeKind::File => "path named in query",
+                    _ => "name in query",
+                }
+            } else if i < sources.matched {
+                "glossary term in query"
+            } else if i < sources.pinned {
+                "pinned"
+            } else {
+                "frequently used file (query matched nothing)"
+            };
+            SeedExplanation {
+                node: node_label(node),
+                reason: reason.to_string(),
+            }
+        })
+        .collect();
+
+    let head_id = repo.head_id()?;
+    let mut ranked = file_candidates(repo, expansion, boosts)?;
+    ranked.sort_by_key(|(_, _, score)| std::cmp::Reverse(*score));
+
+    let mut candidates = Vec::new();
+    for (node, _, relevance_score) in ranked {
+        let steps = expansion.path_to(&node);
+        let seed = steps.first().map_or(&node, |step| &step.from);
+        let path = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let to = steps.get(i + 1).map_or(&node, |next| &next.from);
+                match step.label {
+                    None => format!("{} ~SCC~ {}", node_label(&step.from), node_label(to)),
+                    Some(label) if step.reversed => format!(
+                        "{} <-{:?}- {}",
+                        node_label(&step.from),
+                        label,
+                        node_label(to)
+                    ),
+                    Some(label) => format!(
+                        "{} -{:?}-> {}",
+                        node_label(&step.from),
+                        label,
+                        node_label(to)
+                    ),
+                }
+            })
+            .collect();
+
+        let (tokens, included, dropped_reason) = match outcomes.get(&node.id) {
+            Some((tokens, reason)) => (Some(*tokens), reason.is_none(), reason.clone()),
+            None => {
+                let reason = if !repo.can_read(&node.id) {
+                    "not readable by this handle".to_string()
+                } else if let Some(pattern) = config.excluded_by(&node.id) {
+                    format!("matches exclude pattern '{}'", pattern)
+                } else if let Some(pattern) = ignored_by(repo, &node.id, config) {
+                    format!("ignored by '{}'", pattern)
+                } else if author_excluded(repo, head_id, &node.id, &config.author_filter)? {
+                    "excluded by author filter".to_string()
+                } else if tag_excluded(repo, &node.id, config)? {
+                    "excluded by tag filter".to_string()
+                } else if actor_excluded(repo, &node.id, config)? {
+                    "excluded by actor filter".to_string()
+                } else {
+                    "content is not UTF-8 text".to_string()
+                };
+                (None, false, Some(reason))
+            }
+        };
+
+        candidates.push(CandidateExplanation {
+            title: node.id.clone(),
+            seed: node_label(seed),
+            path,
+            depth: expansion.node_depths.get(&node).copied().unwrap_or(0),
+            relevance_score,
+            frecency_bonus: boosts.frecency.get(&node.id).copied().unwrap_or(0),
+            feedback_adjustment: boosts.feedback.get(&node.id).copied().unwrap_or(0),
+            tokens,
+            included,
+            dropped_reason,
+        });
+    }
+
+    Ok(PackExplanation {
+        seeds,
+        candidates,
+        expansion_truncated: expansion.truncated,
+    })
+}
+
+/// Add the configured pinned nodes to `seeds`.
+///
+/// # Errors
+///
+/// Returns `InvalidArgument` if a pinned file doesn't exist at HEAD.
+fn add_pinned_seeds(
+    repo: &CtxRepo,
+    config: &RetrievalConfig,
+    seeds: &mut Vec<NodeId>,
+) -> Result<()> {
+    if config.pinned.is_empty() {
+        return Ok(());
+    }
+    let index = repo.index()?;
+    for node in &config.pinned {
+        if node.kind == NodeKind::File && index.lookup_path(&node.id)?.is_none() {
+            return Err(CtxError::InvalidArgument(format!(
+                "pinned file not found: {}",
+                node.id
+            )));
+        }
+        if !seeds.contains(node) {
+            seeds.push(node.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Expand the graph from `seeds` up to `depth` hops.
+fn expand_seeds(
+    repo: &CtxRepo,
+    seeds: &[NodeId],
+    config: &RetrievalConfig,
+    depth: u32,
+) -> Result<ExpansionResult> {
+    if seeds.is_empty() {
+        // No seeds found - return empty expansion
+        return Ok(ExpansionResult {
+            expanded_nodes: Vec::new(),
+            node_depths: HashMap::new(),
+            seeds: Vec::new(),
+            truncated: false,
+            discovered_via: HashMap::new(),
+            scc_used: false,
+        });
+    }
+
+    let expansion_config = ExpansionConfig {
+        max_depth: depth,
+        follow_labels: config.expand_labels.clone(),
+        max_nodes: config.max_expanded_nodes,
+        bidirectional: true, // Follow edges in both directions to find files that define items
+        reverse: false,
+        use_scc: config.use_scc,
+        edge_decay: config.edge_decay.clone(),
+        now_unix: repo.now_unix(),
+        active_features: config
+            .active_features
+            .as_ref()
+            .map(|features| features.iter().cloned().collect()),
+    };
+
+    if config.use_scc {
+        repo.index()?.ensure_scc()?;
+    }
+    let index = repo.index()?;
+    expand_from_seeds(&index, seeds.to_vec(), &expansion_config)
+}
+
+/// Relevance adjustments applied when ranking candidate files.
+#[derive(Debug, Default)]
+struct Boosts {
+    /// Frecency bonus per path.
+    frecency: HashMap<String, u32>,
+    /// Feedback adjustment per path, negative for files marked useless.
+    feedback: HashMap<String, i32>,
+}
+
+impl Boosts {
+    /// `base` relevance of `path` with its bonuses applied, within 0..=1000.
+    fn apply(&self, path: &str, base: u32) -> u32 {
+        let frecency = self.frecency.get(path).copied().unwrap_or(0) as i64;
+        let feedback = self.feedback.get(path).copied().unwrap_or(0) as i64;
+        (base as i64 + frecency + feedback).clamp(0, 1000) as u32
+    }
+}
+
+/// Relevance adjustments from recorded feedback, or none when disabled.
+fn apply_feedback(repo: &CtxRepo, config: &RetrievalConfig) -> Result<HashMap<String, i32>> {
+    if !config.feedback_boost {
+        return Ok(HashMap::new());
+    }
+    Ok(repo.feedback_log()?.adjustments())
+}
+
+/// Adjust seeds and relevance for file frecency.
+///
+/// Only applies to vague queries (see [`VAGUE_QUERY_MAX_SEEDS`]): a query
+/// with no seeds falls back to the most frecent files, and the returned
+/// map gives each frecent path a relevance bonus proportional to its score.
+/// Returns the seeds unchanged and no bonuses when disabled.
+fn apply_frecency(
+    repo: &CtxRepo,
+    mut seeds: Vec<NodeId>,
+    config: &RetrievalConfig,
+) -> Result<(Vec<NodeId>, HashMap<String, u32>)> {
+    if !config.frecency_boost || seeds.len() > VAGUE_QUERY_MAX_SEEDS {
+        return Ok((seeds, HashMap::new()));
+    }
+
+    let now = repo.now_unix();
+    let index = repo.index()?;
+    let frecent = index.frecent_paths(now, usize::MAX)?;
+    let Some(&(_, max_score)) = frecent.first() else {
+        return Ok((seeds, HashMap::new()));
+    };
+
+    if seeds.is_empty() {
+        for (path, _) in &frecent {
+            if seeds.len() >= FRECENT_SEED_LIMIT {
+                break;
+            }
+            if index.lookup_path(path)?.is_some() {
+                seeds.push(NodeId {
+                    kind: NodeKind::File,
+                    id: path.clone(),
+                });
+            }
+        }
+    }
+
+    let boosts = frecent
+        .into_iter()
+        .map(|(path, score)| {
+            let bonus = (score as u64 * MAX_FRECENCY_BONUS as u64 / max_score as u64) as u32;
+            (path, bonus)
+        })
+        .collect();
+    Ok((seeds, boosts))
+}
+
+/// Returns the cached summary of `chunk`'s content as a chunk, if the chunk
+/// is a file scoring low enough to be replaced.
+fn summary_chunk(
+    repo: &CtxRepo,
+    summaries: &SummaryTable,
+    chunk: &RetrievedChunk,
+    config: &RetrievalConfig,
+) -> Result<Option<RetrievedChunk>> {
+    let replaceable = chunk.chunk_kind == ChunkKind::FileContent
+        && config
+            .summary_max_relevance
+            .is_some_and(|max| chunk.relevance_score <= max);
+    let Some(summary_id) = summaries.get(chunk.object_id).filter(|_| replaceable) else {
+        return Ok(None);
+    };
+    let summary: Summary = repo.object_store().get_typed(summary_id)?;
+    Ok(Some(RetrievedChunk {
+        title: chunk.title.clone(),
+        object_id: summary_id,
+        citation: Citation::whole(summary_id, &summary.text),
+        snippet: summary.text,
+        relevance_score: chunk.relevance_score,
+        chunk_kind: ChunkKind::Summary,
+    }))
+}
+
+/// Record the files delivered in a pack as accessed, for frecency.
+fn record_inclusions(
+    repo: &CtxRepo,
+    chunks: &[RetrievedChunk],
+    config: &RetrievalConfig,
+) -> Result<()> {
+    let paths = chunks
+        .iter()
+        .filter(|c| c.chunk_kind == ChunkKind::FileContent)
+        .map(|c| c.title.clone())
+        .collect();
+    record_accessed_paths(repo, paths, config)
+}
+
+/// Record `paths` as accessed now, unless frecency is disabled.
+fn record_accessed_paths(
+    repo: &CtxRepo,
+    paths: Vec<String>,
+    config: &RetrievalConfig,
+) -> Result<()> {
+    if !config.frecency_boost {
+        return Ok(());
+    }
+    repo.defer_access(paths)
+}
+
+/// Load file content for every file node in the expansion that passes the author filter.
+fn load_file_chunks(
+    repo: &CtxRepo,
+    expansion: &ExpansionResult,
+    config: &RetrievalConfig,
+    boosts: &Boosts,
+    progress: &Progress,
+) -> Result<Vec<RetrievedChunk>> {
+    let candidates = file_candidates(repo, expansion, boosts)?;
+    let head_id = repo.head_id()?;
+    let total = candidates.len() as u64;
+    let mut chunks = Vec::new();
+    for (loaded, (node, obj_id, relevance_score)) in candidates.into_iter().enumerate() {
+        progress.tick("load", loaded as u64, total)?;
+        if let Some(chunk) = load_file_chunk(repo, head_id, node, obj_id, relevance_score, config)?
+        {
+            chunks.push(chunk);
+        }
+    }
+    Ok(chunks)
+}
+
+/// File nodes in the expansion with their content ids and relevance scores.
+///
+/// Strategy: First collect ObjectIds (requires index), then load content (requires object_store)
+/// We can't hold both borrows simultaneously, so we do it in two passes
+fn file_candidates(
+    repo: &CtxRepo,
+    expansion: &ExpansionResult,
+    boosts: &Boosts,
+) -> Result<Vec<(NodeId, ObjectId, u32)>> {
+    let index = repo.index()?;
+    Ok(expansion
+        .expanded_nodes
+        .iter()
+        .filter_map(|node| {
+            if node.kind == NodeKind::File {
+                let depth = expansion.node_depths.get(node).copied().unwrap_or(0);
+                // Compute relevance as fixed-point: 1000 / (1 + depth)
+                // depth=0: 1000 (1.0), depth=1: 500 (0.5), depth=2: 333 (0.333), etc.
+                let relevance_score = boosts.apply(&node.id, 1000 / (1 + depth));
+                if let Ok(Some(obj_id)) = index.lookup_path(&node.id) {
+                    Some((node.clone(), obj_id, relevance_score))
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+/// Load one file chunk, or `None` if the handle can't read it, it is
+/// excluded or ignored, fails the author or tag filter or isn't readable
+/// UTF-8. Large files whose content was stubbed or chunked load as a
+/// [`ChunkKind::Stub`] describing them.
+fn load_file_chunk(
+    repo: &CtxRepo,
+    head_id: ObjectId,
+    node: NodeId,
+    obj_id: ObjectId,
+    relevance_score: u32,
+    config: &RetrievalConfig,
+) -> Result<Option<RetrievedChunk>> {
+    if !repo.can_read(&node.id)
+        || config.excluded_by(&node.id).is_some()
+        || ignored_by(repo, &node.id, config).is_some()
+        || author_excluded(repo, head_id, &node.id, &config.author_filter)?
+        || tag_excluded(repo, &node.id, config)?
+        || actor_excluded(repo, &node.id, config)?
+    {
+        return Ok(None);
+    }
+
+    let content = match repo
+        .object_store()
+        .get_blob_reader(obj_id)
+        .and_then(read_utf8)
+    {
+        Ok(content) => content,
+        // Large and binary files stand in for their content
+        Err(_) => {
+            return Ok(large_file(repo.object_store(), obj_id).map(|large| {
+                let snippet = format!("`{}`: {}", node.id, large.describe());
+                RetrievedChunk {
+                    citation: Citation::whole(obj_id, &snippet),
+                    snippet,
+                    title: node.id,
+                    object_id: obj_id,
+                    relevance_score,
+                    chunk_kind: ChunkKind::Stub,
+                }
+            }))
+        }
+    };
+    Ok(content.map(|snippet| RetrievedChunk {
+        title: node.id,
+        object_id: obj_id,
+        citation: Citation::whole(obj_id, &snippet),
+        snippet,
+        relevance_score,
+        chunk_kind: ChunkKind::FileContent,
+    }))
+}
+
+/// Reads a blob as UTF-8, or None if it isn't, stopping at the first
+/// invalid byte rather than reading the rest.
+fn read_utf8(mut reader: BlobReader<'_>) -> Result<Option<String>> {
+    let mut bytes = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut valid = 0;
+    loop {
+        let n = reader.read(&mut buf).map_err(io_error)?;
+        if n == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&buf[..n]);
+        match std::str::from_utf8(&bytes[valid..]) {
+            Ok(_) => valid = bytes.len(),
+            // A character split across reads
+            Err(e) if e.error_len().is_none() => valid += e.valid_up_to(),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(String::from_utf8(bytes).ok())
+}
+
+/// Hash binding a [`PackCursor`] to the query and config it pages through.
+fn cursor_request_hash(query: &str, config: &RetrievalConfig) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"ctx-cursor-v1\0");
+    hasher.update(query.as_bytes());
+    hasher.update(b"\0");
+    // Debug output names every field, so new config fields change the hash
+    hasher.update(format!("{:?}", config).as_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+/// File chunks in the expansion that the cursor has not delivered yet.
+fn undelivered_chunks(
+    repo: &CtxRepo,
+    expansion: &ExpansionResult,
+    config: &RetrievalConfig,
+    boosts: &Boosts,
+    cursor: &PackCursor,
+) -> Result<Vec<RetrievedChunk>> {
+    let mut chunks = load_file_chunks(repo, expansion, config, boosts, &Progress::default())?;
+    chunks.retain(|c| !cursor.delivered.contains(&c.object_id));
+    Ok(chunks)
+}
+
+/// Returns true if the current version of `path` fails the author filter.
+fn author_excluded(
+    repo: &CtxRepo,
+    head_id: ObjectId,
+    path: &str,
+    filter: &AuthorFilter,
+) -> Result<bool> {
+    if *filter == AuthorFilter::Any {
+        return Ok(false);
+    }
+    let introduced_by = introducing_commit(repo.object_store(), head_id, path)?;
+    let author = introduced_by.as_ref().and_then(|c| c.author.as_ref());
+    Ok(!filter.matches(author))
+}
+
+/// Returns the repository ignore rule matching `path`, unless it is pinned.
+fn ignored_by<'a>(repo: &'a CtxRepo, path: &str, config: &RetrievalConfig) -> Option<&'a str> {
+    if config.is_pinned(path) {
+        return None;
+    }
+    repo.ignore_rules().ignored_by(path)
+}
+
+/// Returns true if `path` carries none of the tags `config` filters on.
+fn tag_excluded(repo: &CtxRepo, path: &str, config: &RetrievalConfig) -> Result<bool> {
+    if config.tags.is_empty() || config.is_pinned(path) {
+        return Ok(false);
+    }
+    let index = repo.index()?;
+    for tag in &config.tags {
+        if index.tagged_paths(tag)?.contains(path) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns true if none of the actors `config` filters on read or wrote
+/// `path`.
+fn actor_excluded(repo: &CtxRepo, path: &str, config: &RetrievalConfig) -> Result<bool> {
+    if config.actors.is_empty() || config.is_pinned(path) {
+        return Ok(false);
+    }
+    let index = repo.index()?;
+    for actor in &config.actors {
+        let tag = format_tag(ACTOR_KEY, actor);
+        if index.tagged_paths(&tag)?.contains(path) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Find the commit that introduced the current version of `path`.
+///
+/// Walks first parents from `head` for as long as the path keeps the same
+/// content. Returns None if the path doesn't exist at `head`.
+fn introducing_commit(
+    object_store: &crate::ObjectStore,
+    head: ObjectId,
+    path: &str,
+) -> Result<Option<Commit>> {
+    let mut current: Commit = object_store.get_typed(head)?;
+    let content = match crate::staging::lookup_tree_path(current.root_tree, path, object_store)? {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    while let Some(parent_id) = object_store.first_parent(&current) {
+        let parent: Commit = object_store.get_typed(parent_id)?;
+        let parent_content =
+            crate::staging::lookup_tree_path(parent.root_tree, path, object_store)?;
+        if parent_content != Some(content) {
+            break;
+        }
+        current = parent;
+    }
+
+    Ok(Some(current))
+}
+
+/// Glossary entries whose terms appear in the query.
+///
+/// Adds a `Term` seed per matching entry, so expansion reaches the files
+/// each definition mentions, and returns a chunk listing the definitions.
+fn glossary_context(
+    repo: &CtxRepo,
+    query: &str,
+    seeds: &mut Vec<NodeId>,
+) -> Result<Option<RetrievedChunk>> {
+    let head: Commit = repo.object_store().get_typed(repo.head_id()?)?;
+    let Some(glossary_id) = head.glossary else {
+        return Ok(None);
+    };
+    let glossary: Glossary = repo.object_store().get_typed(glossary_id)?;
+
+    let matches = glossary.matching(query);
+    if matches.is_empty() {
+        return Ok(None);
+    }
+
+    let mut snippet = String::new();
+    for entry in matches.iter().take(MAX_GLOSSARY_ENTRIES) {
+        snippet.push_str(&format!("- **{}**: {}\n", entry.term, entry.definition));
+        let node = term_node(&entry.term);
+        if !seeds.contains(&node) {
+            seeds.push(node);
+        }
+    }
+
+    Ok(Some(RetrievedChunk {
+        title: "Glossary".to_string(),
+        object_id: glossary_id,
+        citation: Citation::whole(glossary_id, &snippet),
+        snippet,
+        relevance_score: 1000,
+        chunk_kind: ChunkKind::Glossary,
+    }))
+}
+
+/// Adds a `Document` seed for each narrative document whose title shares
+/// words with the query, best match first, so expansion reaches the files
+/// the document mentions and the narrative includes it.
+fn add_document_seeds(repo: &CtxRepo, query: &str, seeds: &mut Vec<NodeId>) -> Result<()> {
+    let query_words = narrative_words(std::iter::once(query));
+    let mut matches: Vec<(usize, String)> = repo
+        .narrative()
+        .list_documents()?
+        .into_iter()
+        .filter_map(|document| {
+            let score = narrative_words(document.title().into_iter())
+                .intersection(&query_words)
+                .count();
+            (score > 0).then_some((score, document.relative_path))
+        })
+        .collect();
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    for (_, path) in matches.into_iter().take(MAX_DOCUMENT_SEEDS) {
+        let node = document_node(&path);
+        if !seeds.contains(&node) {
+            seeds.push(node);
+        }
+    }
+    Ok(())
+}
+
+/// Valid facts about the seeds or the files in `chunks`, as one chunk.
+fn known_facts(
+    repo: &CtxRepo,
+    seeds: &[NodeId],
+    chunks: &[RetrievedChunk],
+) -> Result<Option<RetrievedChunk>> {
+    let Some(set_id) = repo.head()?.facts else {
+        return Ok(None);
+    };
+    let subjects: HashSet<NodeId> = seeds
+        .iter()
+        .cloned()
+        .chain(chunks.iter().map(|chunk| NodeId {
+            kind: NodeKind::File,
+            id: chunk.title.clone(),
+        }))
+        .collect();
+    let lines: Vec<String> = repo
+        .facts()?
+        .into_iter()
+        .filter(|(_, fact)| subjects.contains(&fact.subject))
+        .map(|(_, fact)| fact.render())
+        .collect();
+    if lines.is_empty() {
+        return Ok(None);
+    }
+
+    let snippet = lines.join("\n");
+    Ok(Some(RetrievedChunk {
+        title: "Known facts".to_string(),
+        object_id: set_id,
+        citation: Citation::whole(set_id, &snippet),
+        snippet,
+        relevance_score: 1000,
+        chunk_kind: ChunkKind::Fact,
+    }))
+}
+
+/// Annotations on commits that changed the files in `chunks`, as one
+/// chunk.
+fn commit_notes(repo: &CtxRepo, chunks: &[RetrievedChunk]) -> Result<Option<RetrievedChunk>> {
+    let Some(table_id) = repo.refs().read_notes()? else {
+        return Ok(None);
+    };
+    let table: NoteTable = repo.object_store().get_typed(table_id)?;
+    let files: Vec<&str> = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_kind == ChunkKind::FileContent)
+        .map(|chunk| chunk.title.as_str())
+        .collect();
+
+    let mut lines = Vec::new();
+    for (commit_id, annotation_ids) in &table.entries {
+        let Ok(commit) = repo.object_store().get_typed::<Commit>(*commit_id) else {
+            // Pruned since it was annotated
+            continue;
+        };
+        let mut changed = Vec::new();
+        for file in &files {
+            if path_change(*commit_id, &commit, file, repo.object_store())?.is_some() {
+                changed.push(*file);
+            }
+        }
+        if changed.is_empty() {
+            continue;
+        }
+        for annotation_id in annotation_ids {
+            let annotation: Annotation = repo.object_store().get_typed(*annotation_id)?;
+            lines.push(format!(
+                "{} (\"{}\", changed {})",
+                annotation.render(),
+                commit.message.lines().next().unwrap_or_default(),
+                changed.join(", ")
+            ));
+        }
+    }
+    if lines.is_empty() {
+        return Ok(None);
+    }
+
+    let snippet = lines.join("\n");
+    Ok(Some(RetrievedChunk {
+        title: "Commit notes".to_string(),
+        object_id: table_id,
+        citation: Citation::whole(table_id, &snippet),
+        snippet,
+        relevance_score: 1000,
+        chunk_kind: ChunkKind::Annotation,
+    }))
+}
+
+/// Past answers to questions similar to `query`, most similar first.
+///
+/// Each answer is its own chunk, scored by its question's similarity to the
+/// query.
+fn past_answers(repo: &CtxRepo, query: &str) -> Result<Vec<RetrievedChunk>> {
+    let mut answers: Vec<(u32, ObjectId, QaPair)> = repo
+        .answers()?
+        .into_iter()
+        .rev()
+        .map(|(id, pair)| (crate::qa::similarity(query, &pair.question), id, pair))
+        .filter(|(similarity, _, _)| *similarity >= MIN_ANSWER_SIMILARITY)
+        .collect();
+    // Stable, so equally similar answers stay newest first
+    answers.sort_by_key(|(similarity, _, _)| std::cmp::Reverse(*similarity));
+
+    Ok(answers
+        .into_iter()
+        .take(MAX_PAST_ANSWERS)
+        .map(|(similarity, id, pair)| {
+            let snippet = pair.render();
+            RetrievedChunk {
+                title: format!("Q: {}", pair.question),
+                object_id: id,
+                citation: Citation::whole(id, &snippet),
+                snippet,
+                relevance_score: similarity,
+                chunk_kind: ChunkKind::Answer,
+            }
+        })
+        .collect())
+}
+
+/// Decisions linked to the files in `chunks`, or naming a word of the
+/// query in their question, options or choice, newest first.
+///
+/// A decision scores like the best file it concerns; one matching the query
+/// scores 1000.
+fn load_decision_chunks(
+    repo: &CtxRepo,
+    query: &str,
+    chunks: &[RetrievedChunk],
+) -> Result<Vec<RetrievedChunk>> {
+    let decisions = repo.decisions()?;
+    if decisions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut scores: HashMap<String, u32> = HashMap::new();
+    {
+        let index = repo.index()?;
+        for chunk in chunks {
+            let file = NodeId {
+                kind: NodeKind::File,
+                id: chunk.title.clone(),
+            };
+            for node in index.get_edges_to(&file, EdgeLabel::Mentions)? {
+                if node.kind == NodeKind::Decision {
+                    let score = scores.entry(node.id).or_default();
+                    *score = (*score).max(chunk.relevance_score);
+                }
+            }
+        }
+    }
+    let words: Vec<String> = query
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| word.len() >= 4)
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut decision_chunks = Vec::new();
+    for (id, decision) in decisions.into_iter().rev() {
+        let relevance_score = if words.iter().any(|word| decision.mentions(word)) {
+            1000
+        } else {
+            match scores.get(&id.as_hex()) {
+                Some(&score) => score,
+                None => continue,
+            }
+        };
+        let snippet = decision.render();
+        decision_chunks.push(RetrievedChunk {
+            title: format!("Decision: {}", decision.title()),
+            object_id: id,
+            citation: Citation::whole(id, &snippet),
+            snippet,
+            relevance_score,
+            chunk_kind: ChunkKind::Decision,
+        });
+    }
+    Ok(decision_chunks)
+}
+
+/// Select task and log content from the narrative space.
+///
+/// Task files whose titles share words with the query or its seeds come
+/// first, best match first; if none match, the newest open task stands in
+/// as the active task. Documents seeded from the query come next, then
+/// daily logs from the last `narrative_days` days, newest first. Entries
+/// that don't fit the narrative budget are skipped.
+fn collect_narrative(
+    repo: &CtxRepo,
+    config: &RetrievalConfig,
+    query: &str,
+    seeds: &[NodeId],
+) -> String {
+    let mut narrative_content = String::new();
+    if !config.include_active_task && !config.include_log {
+        return narrative_content;
+    }
+
+    let narrative = repo.narrative();
+    let Ok(files) = narrative.list_files() else {
+        return narrative_content;
+    };
+    let budget = config
+        .narrative_budget
+        .min(config.token_budget.saturating_sub(config.response_reserve));
+    let mut tokens_used = 0;
+    let mut include = |heading: &str, file: &str, content: &str| {
+        let section = format!("## {}: {}\n\n{}\n\n", heading, file, content);
+        let section_tokens = config.tokenizer.count(&section);
+        if tokens_used + section_tokens <= budget {
+            tokens_used += section_tokens;
+            narrative_content.push_str(&section);
+        }
+    };
+
+    if config.include_active_task {
+        let query_words = narrative_words(
+            std::iter::once(query).chain(seeds.iter().map(|seed| seed.id.as_str())),
+        );
+
+        // (score, path, content, open), newest task first
+        let mut tasks: Vec<(usize, &String, String, bool)> = files
+            .iter()
+            .rev()
+            .filter(|f| f.starts_with("tasks/") && f.ends_with(".md"))
+            .filter_map(|file| {
+                let content = String::from_utf8(narrative.read_file(file).ok()?).ok()?;
+                let title = content
+                    .lines()
+                    .find_map(|line| line.strip_prefix("# "))
+                    .unwrap_or_default();
+                let score = narrative_words(std::iter::once(title))
+                    .intersection(&query_words)
+                    .count();
+                let open = !content.lines().any(|line| {
+                    line.strip_prefix("**Status:**")
+                        .is_some_and(|status| matches!(status.trim(), "done" | "closed"))
+                });
+                Some((score, file, content, open))
+            })
+            .collect();
+        tasks.sort_by_key(|(score, ..)| std::cmp::Reverse(*score));
+
+        if tasks.first().is_some_and(|(score, ..)| *score > 0) {
+            for (_, file, content, _) in tasks.iter().take_while(|(score, ..)| *score > 0) {
+                include("Task", file, content);
+            }
+        } else if let Some((_, file, content, _)) = tasks.iter().find(|(.., open)| *open) {
+            include("Task", file, content);
+        }
+    }
+
+    for seed in seeds.iter().filter(|seed| seed.kind == NodeKind::Document) {
+        if let Ok(Ok(content)) = narrative.read_file(&seed.id).map(String::from_utf8) {
+            let kind = crate::document::Document::parse(&seed.id, &content)
+                .map(|document| document.kind)
+                .unwrap_or_else(|| "Document".to_string());
+            let mut heading = kind.chars();
+            let heading: String = heading
+                .next()
+                .map(|first| first.to_uppercase().chain(heading).collect())
+                .unwrap_or_default();
+            include(&heading, &seed.id, &content);
+        }
+    }
+
+    if config.include_log {
+        let today = (repo.now_unix() / 86_400) as i64;
+        let oldest = today - i64::from(config.narrative_days);
+        // Sorted paths put the newest date last
+        for file in files.iter().rev() {
+            let Some(day) = crate::narrative::log_day(file) else {
+                continue;
+            };
+            if day < oldest {
+                break;
+            }
+            if let Ok(Ok(content)) = narrative.read_file(file).map(String::from_utf8) {
+                include("Log", file, &content);
+            }
+        }
+    }
+
+    narrative_content
+}
+
+/// Lowercased words of at least three letters, for matching task titles.
+fn narrative_words<'a>(texts: impl Iterator<Item = &'a str>) -> HashSet<String> {
+    const IGNORED: &[&str] = &["the", "and", "for", "with", "from", "into", "this", "that"];
+    texts
+        .flat_map(|text| text.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.len() >= 3)
+        .map(str::to_lowercase)
+        .filter(|word| !IGNORED.contains(&word.as_str()))
+        .collect()
+}
+
+/// Build the graph context summary for a pack.
+fn graph_context(seeds: &[NodeId], expansion: &ExpansionResult, depth: u32) -> GraphContext {
+    GraphContext {
+        seed_nodes: seeds
+            .iter()
+            .map(|n| format!("{:?}::{}", n.kind, n.id))
+            .collect(),
+        expanded_nodes: expansion
+            .expanded_nodes
+            .iter()
+            .map(|n| format!("{:?}::{}", n.kind, n.id))
+            .collect(),
+        expansion_depth: depth,
+        scc_dag_used: expansion.scc_used,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -541,4 +2960,87 @@ mod tests {
         assert_eq!(normalize_path("'test.py'"), "test.py");
         assert_eq!(normalize_path("normal.rs"), "normal.rs");
     }
+
+    #[test]
+    fn test_author_filter_matches() {
+        let human = AgentIdentity::human("alice");
+        let agent = AgentIdentity::agent("coder", "some-model");
+
+        assert!(AuthorFilter::Any.matches(None));
+        assert!(AuthorFilter::Humans.matches(Some(&human)));
+        assert!(!AuthorFilter::Humans.matches(Some(&agent)));
+        assert!(!AuthorFilter::Humans.matches(None));
+        assert!(AuthorFilter::Agents.matches(Some(&agent)));
+        assert!(AuthorFilter::Named("alice".into()).matches(Some(&human)));
+        assert!(!AuthorFilter::Named("alice".into()).matches(Some(&agent)));
+    }
+
+    #[test]
+    fn test_pack_cursor_token_roundtrip() {
+        let cursor = PackCursor {
+            head_commit: ObjectId::from_bytes([7; 32]),
+            request: cursor_request_hash("auth", &RetrievalConfig::default()),
+            delivered: [ObjectId::from_bytes([1; 32]), ObjectId::from_bytes([2; 32])]
+                .into_iter()
+                .collect(),
+            frontier_depth: 3,
+            page: 2,
+        };
+
+        let token = cursor.to_token().unwrap();
+        assert_eq!(PackCursor::from_token(&token).unwrap(), cursor);
+        assert!(matches!(
+            PackCursor::from_token("not-a-token"),
+            Err(CtxError::InvalidCursor(_))
+        ));
+    }
+
+    #[test]
+    fn test_pack_session_forgets_and_roundtrips() {
+        let chunk = |byte: u8| RetrievedChunk {
+            title: format!("src/{}.rs", byte),
+            object_id: ObjectId::from_bytes([byte; 32]),
+            citation: Citation::whole(ObjectId::from_bytes([byte; 32]), ""),
+            snippet: String::new(),
+            relevance_score: 1000,
+            chunk_kind: ChunkKind::FileContent,
+        };
+        let pack = |chunks: Vec<RetrievedChunk>| PromptPack {
+            task: "task".to_string(),
+            head_commit: ObjectId::from_bytes([0; 32]),
+            retrieved: chunks,
+            graph_context: GraphContext {
+                seed_nodes: vec![],
+                expanded_nodes: vec![],
+                expansion_depth: 2,
+                scc_dag_used: false,
+            },
+            recent_narrative: String::new(),
+            token_budget: TokenBudget {
+                total: 100,
+                used: 0,
+                reserved_for_response: 0,
+                narrative: 0,
+            },
+            explanation: None,
+            unchanged: vec![],
+            layers: vec![],
+        };
+
+        let mut session = PackSession::new();
+        session.record(&pack(vec![chunk(1), chunk(2)]));
+        session.record(&pack(vec![chunk(2), chunk(3)]));
+        assert_eq!(session.turn(), 2);
+        assert_eq!(session.provided()[&ObjectId::from_bytes([2; 32])], 2);
+
+        session.forget_before(2);
+        assert_eq!(session.provided().len(), 2);
+        assert!(!session
+            .provided()
+            .contains_key(&ObjectId::from_bytes([1; 32])));
+
+        let token = session.to_token().unwrap();
+        assert_eq!(PackSession::from_token(&token).unwrap(), session);
+        assert!(PackSession::from_token("zz").is_err());
+    }
 }
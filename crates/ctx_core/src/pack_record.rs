@@ -0,0 +1,354 @@
+//! Built prompt packs stored as objects.
+//!
+//! Every pack a repository builds is stored as a [`PackRecord`]: the query,
+//! the retrieval configuration, the chunks with their citations and scores,
+//! and the token accounting. Snippets are stored as blobs, so file content
+//! the repository already holds isn't stored twice. Each record is
+//! announced by a `pack_built` event in the journal, which is how
+//! [`CtxRepo::recent_packs`](crate::CtxRepo::recent_packs) finds them
+//! again; a record can be turned back into the pack it came from to compare
+//! retrieval quality over time.
+//!
+//! Garbage collection keeps every record the journal lists. The retrieval
+//! configuration is stored as versioned JSON, so records stay readable as
+//! fields are added to [`RetrievalConfig`].
+
+use crate::citation::Citation;
+use crate::error::{CtxError, Result};
+use crate::pack::{
+    ChunkKind, GraphContext, PackExplanation, PackLayer, PromptPack, RetrievalConfig,
+    RetrievedChunk, TokenBudget, UnchangedChunk,
+};
+use crate::{ObjectId, ObjectStore};
+use serde::{Deserialize, Serialize};
+
+/// Version of the JSON a record's [`RetrievalConfig`] is stored as.
+const CONFIG_VERSION: u32 = 1;
+
+/// A stored prompt pack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackRecord {
+    /// The query the pack was built for.
+    pub task: String,
+    /// Commit the pack was built from.
+    pub head_commit: ObjectId,
+    /// Retrieval settings the pack was built with.
+    #[serde(with = "stored_config")]
+    pub config: RetrievalConfig,
+    /// The pack's chunks, in the order they are numbered.
+    pub chunks: Vec<CitedChunk>,
+    /// Graph expansion metadata.
+    pub graph_context: GraphContext,
+    /// Blob holding the recent narrative excerpts, if there were any.
+    pub recent_narrative: Option<ObjectId>,
+    /// Token budget accounting.
+    pub token_budget: TokenBudget,
+    /// Retrieval explanation, if one was requested.
+    pub explanation: Option<PackExplanation>,
+    /// Chunks left out because an earlier turn provided them.
+    pub unchanged: Vec<UnchangedChunk>,
+    /// Overview and module layers.
+    pub layers: Vec<PackLayer>,
+}
+
+/// A numbered chunk of a stored pack.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CitedChunk {
+    /// The chunk's title.
+    pub title: String,
+    /// What kind of content the chunk holds.
+    pub chunk_kind: ChunkKind,
+    /// File the chunk stands for, if it stands for one.
+    pub path: Option<String>,
+    /// Where the snippet came from.
+    pub citation: Citation,
+    /// Blob holding the snippet.
+    pub snippet_id: ObjectId,
+    /// Relevance score (fixed-point, see [`RetrievedChunk`]).
+    pub relevance_score: u32,
+}
+
+/// A pack listed in the event journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackHistoryEntry {
+    /// ID of the [`PackRecord`].
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub pack_id: ObjectId,
+    /// When the pack was recorded (Unix seconds).
+    pub timestamp_unix: u64,
+    /// The query.
+    pub query: String,
+    /// Number of retrieved chunks.
+    pub chunks: usize,
+    /// Tokens the pack used.
+    pub tokens: u32,
+}
+
+/// Stores a [`RetrievalConfig`] as a JSON string tagged with
+/// [`CONFIG_VERSION`]. Fields missing from an older record take their
+/// defaults, and fields this version doesn't know are ignored.
+mod stored_config {
+    use super::{RetrievalConfig, CONFIG_VERSION};
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Stored<T> {
+        version: u32,
+        config: T,
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        config: &RetrievalConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let stored = Stored {
+            version: CONFIG_VERSION,
+            config,
+        };
+        let json = serde_json::to_string(&stored).map_err(S::Error::custom)?;
+        serializer.serialize_str(&json)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<RetrievalConfig, D::Error> {
+        let json = String::deserialize(deserializer)?;
+        let stored: Stored<serde_json::Value> =
+            serde_json::from_str(&json).map_err(D::Error::custom)?;
+        if stored.version > CONFIG_VERSION {
+            return Err(D::Error::custom(format!(
+                "retrieval config version {} is newer than this ctx supports ({})",
+                stored.version, CONFIG_VERSION
+            )));
+        }
+        serde_json::from_value(stored.config).map_err(D::Error::custom)
+    }
+}
+
+impl CitedChunk {
+    /// Path and line range, e.g. `src/index.rs:1-40`, or the title and
+    /// citation for chunks that aren't file content.
+    pub fn location(&self) -> String {
+        match (&self.path, self.chunk_kind) {
+            (Some(path), ChunkKind::FileContent) => format!(
+                "{}:{}-{}",
+                path, self.citation.start_line, self.citation.end_line
+            ),
+            (Some(path), _) => path.clone(),
+            (None, _) => format!("{} ({})", self.title, self.citation),
+        }
+    }
+}
+
+impl PackRecord {
+    /// Builds the record of `pack`, storing its snippets and narrative as
+    /// blobs.
+    pub(crate) fn new(
+        pack: &PromptPack,
+        config: &RetrievalConfig,
+        object_store: &ObjectStore,
+    ) -> Result<Self> {
+        let chunks = pack
+            .retrieved
+            .iter()
+            .map(|chunk| {
+                Ok(CitedChunk {
+                    title: chunk.title.clone(),
+                    chunk_kind: chunk.chunk_kind,
+                    // These kinds are titled with the file they stand for
+                    path: matches!(
+                        chunk.chunk_kind,
+                        ChunkKind::FileContent | ChunkKind::Stub | ChunkKind::Summary
+                    )
+                    .then(|| chunk.title.clone()),
+                    citation: chunk.citation,
+                    snippet_id: object_store.put_blob(chunk.snippet.as_bytes())?,
+                    relevance_score: chunk.relevance_score,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let recent_narrative = match pack.recent_narrative.as_str() {
+            "" => None,
+            narrative => Some(object_store.put_blob(narrative.as_bytes())?),
+        };
+
+        Ok(Self {
+            task: pack.task.clone(),
+            head_commit: pack.head_commit,
+            config: config.clone(),
+            chunks,
+            graph_context: pack.graph_context.clone(),
+            recent_narrative,
+            token_budget: pack.token_budget.clone(),
+            explanation: pack.explanation.clone(),
+            unchanged: pack.unchanged.clone(),
+            layers: pack.layers.clone(),
+        })
+    }
+
+    /// The chunk rendered as `[number]`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] if the pack has no such chunk.
+    pub fn resolve(&self, number: usize) -> Result<&CitedChunk> {
+        number
+            .checked_sub(1)
+            .and_then(|i| self.chunks.get(i))
+            .ok_or_else(|| {
+                CtxError::InvalidArgument(format!(
+                    "no citation [{}] in this pack (it has {} chunks)",
+                    number,
+                    self.chunks.len()
+                ))
+            })
+    }
+
+    /// Loads the snippets and narrative back into the pack this record was
+    /// made from.
+    pub fn materialize(&self, object_store: &ObjectStore) -> Result<PromptPack> {
+        let text = |id: ObjectId| -> Result<String> {
+            Ok(String::from_utf8_lossy(&object_store.get_blob(id)?).into_owned())
+        };
+        let retrieved = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                Ok(RetrievedChunk {
+                    title: chunk.title.clone(),
+                    object_id: chunk.citation.object_id,
+                    snippet: text(chunk.snippet_id)?,
+                    relevance_score: chunk.relevance_score,
+                    chunk_kind: chunk.chunk_kind,
+                    citation: chunk.citation,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(PromptPack {
+            task: self.task.clone(),
+            head_commit: self.head_commit,
+            retrieved,
+            graph_context: self.graph_context.clone(),
+            recent_narrative: self
+                .recent_narrative
+                .map(text)
+                .transpose()?
+                .unwrap_or_default(),
+            token_budget: self.token_budget.clone(),
+            explanation: self.explanation.clone(),
+            unchanged: self.unchanged.clone(),
+            layers: self.layers.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn pack(snippet: &str) -> PromptPack {
+        let object_id = ObjectId::from_bytes([0xab; 32]);
+        PromptPack {
+            task: "where is the index".to_string(),
+            head_commit: ObjectId::from_bytes([0; 32]),
+            retrieved: vec![
+                RetrievedChunk {
+                    title: "src/index.rs".to_string(),
+                    object_id,
+                    snippet: snippet.to_string(),
+                    relevance_score: 1000,
+                    chunk_kind: ChunkKind::FileContent,
+                    citation: Citation::whole(object_id, snippet),
+                },
+                RetrievedChunk {
+                    title: "Decision: Use redb".to_string(),
+                    object_id,
+                    snippet: "Chose redb.".to_string(),
+                    relevance_score: 500,
+                    chunk_kind: ChunkKind::Decision,
+                    citation: Citation::whole(object_id, "Chose redb."),
+                },
+            ],
+            graph_context: GraphContext {
+                seed_nodes: vec!["File::src/index.rs".to_string()],
+                expanded_nodes: vec![],
+                expansion_depth: 2,
+                scc_dag_used: false,
+            },
+            recent_narrative: "## Log\n".to_string(),
+            token_budget: TokenBudget {
+                total: 100,
+                used: 20,
+                reserved_for_response: 10,
+                narrative: 5,
+            },
+            explanation: None,
+            unchanged: vec![],
+            layers: vec![],
+        }
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let original = pack("pub struct Index;\n\nimpl Index {}\n");
+        let record = PackRecord::new(&original, &RetrievalConfig::default(), &store).unwrap();
+        let id = store.put_typed(&record).unwrap();
+
+        let record: PackRecord = store.get_typed(id).unwrap();
+        assert_eq!(
+            record.config.token_budget,
+            RetrievalConfig::default().token_budget
+        );
+        assert_eq!(
+            record.materialize(&store).unwrap().to_text(),
+            original.to_text()
+        );
+    }
+
+    #[test]
+    fn test_config_stored_as_versioned_json() {
+        #[derive(Serialize, Deserialize)]
+        struct Config(#[serde(with = "stored_config")] RetrievalConfig);
+
+        // An older record lacks fields added since, and a newer one may
+        // carry fields this version doesn't know
+        let json = r#"{"version":1,"config":{"token_budget":500,"added_later":true}}"#;
+        let bytes = postcard::to_allocvec(json).unwrap();
+        let Config(config) = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(config.token_budget, 500);
+        assert_eq!(
+            config.expansion_depth,
+            RetrievalConfig::default().expansion_depth
+        );
+
+        let json = r#"{"version":2,"config":{}}"#;
+        let bytes = postcard::to_allocvec(json).unwrap();
+        assert!(postcard::from_bytes::<Config>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let record = PackRecord::new(
+            &pack("one\ntwo\nthree\n"),
+            &RetrievalConfig::default(),
+            &store,
+        )
+        .unwrap();
+
+        assert_eq!(record.resolve(1).unwrap().location(), "src/index.rs:1-3");
+        assert_eq!(
+            record.resolve(2).unwrap().location(),
+            "Decision: Use redb (abababababab:1-1)"
+        );
+        assert!(record.resolve(0).is_err());
+        assert!(record.resolve(3).is_err());
+    }
+}
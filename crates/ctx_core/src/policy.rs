@@ -0,0 +1,499 @@
+//! Policy for running external commands.
+//!
+//! Every process CTX spawns (cargo, rust-analyzer, and anything added
+//! later) goes through an [`ExecPolicy`]. The policy checks the command line
+//! against deny and allow patterns, falls back to the configured
+//! [`ExecMode`], and appends a record of each attempt to an audit log.
+//!
+//! Patterns are matched against the command line `program arg1 arg2 ...`.
+//! `*` matches any run of characters; a pattern without `*` matches the
+//! command line exactly or as a whole-word prefix, so `cargo metadata`
+//! matches `cargo metadata --no-deps` but not `cargo metadatax`.
+//!
+//! Deny patterns are also matched against the command with common wrappers
+//! (`env`, `sudo`, `nice`, `timeout`, ...) stripped and the program reduced
+//! to its file name, so denying `touch` also refuses `/usr/bin/touch x` and
+//! `env touch x`. Allow patterns only match the literal command line: an
+//! allowed `cargo` doesn't let `/tmp/cargo` run. Shell strings such as
+//! `sh -c "touch x"` are not inspected; deny the shell itself to stop them.
+
+use crate::error::{CtxError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// What to do with commands that match neither allow nor deny patterns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecMode {
+    /// Run them.
+    #[default]
+    Allow,
+    /// Ask for confirmation; refuse if no prompt is available.
+    Confirm,
+    /// Refuse them.
+    Deny,
+}
+
+/// Command execution policy configuration (`[exec]` in `config.toml`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecConfig {
+    /// Fallback for unmatched commands (default: allow).
+    pub mode: ExecMode,
+
+    /// Patterns for commands that always run.
+    pub allow: Vec<String>,
+
+    /// Patterns for commands that never run. Deny wins over allow.
+    pub deny: Vec<String>,
+
+    /// Record every attempt in `.ctx/logs/exec.jsonl` (default: true).
+    pub audit: bool,
+}
+
+impl Default for ExecConfig {
+    fn default() -> Self {
+        Self {
+            mode: ExecMode::Allow,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            audit: true,
+        }
+    }
+}
+
+/// Outcome of checking a command against the policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecDecision {
+    /// The command may run.
+    Allowed,
+    /// The command may run because the prompt approved it.
+    Confirmed,
+    /// The command must not run, with the reason.
+    Denied(String),
+}
+
+impl ExecDecision {
+    /// Returns true if the command may run.
+    pub fn is_allowed(&self) -> bool {
+        !matches!(self, ExecDecision::Denied(_))
+    }
+
+    /// Short label used in audit records.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecDecision::Allowed => "allowed",
+            ExecDecision::Confirmed => "confirmed",
+            ExecDecision::Denied(_) => "denied",
+        }
+    }
+}
+
+/// Callback asking whether a command line may run.
+pub type ExecPrompt = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Decides whether commands may run, and runs them.
+///
+/// The default policy allows everything and keeps no audit log.
+#[derive(Clone, Default)]
+pub struct ExecPolicy {
+    config: ExecConfig,
+    audit_log: Option<PathBuf>,
+    prompt: Option<ExecPrompt>,
+}
+
+impl fmt::Debug for ExecPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecPolicy")
+            .field("config", &self.config)
+            .field("audit_log", &self.audit_log)
+            .field("prompt", &self.prompt.is_some())
+            .finish()
+    }
+}
+
+impl ExecPolicy {
+    /// Creates a policy from configuration, without an audit log.
+    pub fn new(config: ExecConfig) -> Self {
+        Self {
+            config,
+            audit_log: None,
+            prompt: None,
+        }
+    }
+
+    /// Appends audit records to `path` if auditing is enabled.
+    pub fn with_audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = self.config.audit.then(|| path.into());
+        self
+    }
+
+    /// Sets the callback used in [`ExecMode::Confirm`].
+    pub fn with_prompt(mut self, prompt: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.prompt = Some(Arc::new(prompt));
+        self
+    }
+
+    /// Returns the policy configuration.
+    pub fn config(&self) -> &ExecConfig {
+        &self.config
+    }
+
+    /// Decides whether `command_line` may run, prompting if required.
+    pub fn decide(&self, command_line: &str) -> ExecDecision {
+        let unwrapped = unwrap_command_line(command_line);
+        if let Some(pattern) = self.config.deny.iter().find(|p| {
+            pattern_matches(p, command_line)
+                || unwrapped
+                    .as_deref()
+                    .is_some_and(|line| pattern_matches(p, line))
+        }) {
+            return ExecDecision::Denied(format!("matches deny pattern '{}'", pattern));
+        }
+        if self
+            .config
+            .allow
+            .iter()
+            .any(|p| pattern_matches(p, command_line))
+        {
+            return ExecDecision::Allowed;
+        }
+
+        match self.config.mode {
+            ExecMode::Allow => ExecDecision::Allowed,
+            ExecMode::Deny => ExecDecision::Denied("not in the allowlist".to_string()),
+            ExecMode::Confirm => match &self.prompt {
+                Some(prompt) if prompt(command_line) => ExecDecision::Confirmed,
+                Some(_) => ExecDecision::Denied("declined at confirmation prompt".to_string()),
+                None => ExecDecision::Denied(
+                    "confirmation required but no prompt is available".to_string(),
+                ),
+            },
+        }
+    }
+
+    /// Runs `command` to completion if the policy allows it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CommandDenied` if the policy refuses the command, or the
+    /// I/O error from spawning it.
+    pub fn output(&self, command: &mut Command) -> Result<Output> {
+        let (command_line, decision) = self.check(command)?;
+        let result = command.output();
+        let outcome = match &result {
+            Ok(output) => match output.status.code() {
+                Some(code) => format!("exit {}", code),
+                None => "killed by signal".to_string(),
+            },
+            Err(e) => format!("spawn failed: {}", e),
+        };
+        self.audit(&command_line, command, &decision, &outcome);
+        Ok(result?)
+    }
+
+    /// Runs `command` to completion with `input` on its stdin, capturing
+    /// stdout and stderr, if the policy allows it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CommandDenied` if the policy refuses the command, or the
+    /// I/O error from spawning it.
+    pub fn output_with_input(&self, command: &mut Command, input: &[u8]) -> Result<Output> {
+        let mut child = self.spawn(
+            command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )?;
+
+        // Write from another thread so a command that answers before
+        // reading all of its input can't deadlock us
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = input.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // A command may legitimately stop reading early
+        let _ = writer.join();
+        Ok(output)
+    }
+
+    /// Spawns `command` if the policy allows it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CommandDenied` if the policy refuses the command, or the
+    /// I/O error from spawning it.
+    pub fn spawn(&self, command: &mut Command) -> Result<Child> {
+        let (command_line, decision) = self.check(command)?;
+        let result = command.spawn();
+        let outcome = match &result {
+            Ok(child) => format!("pid {}", child.id()),
+            Err(e) => format!("spawn failed: {}", e),
+        };
+        self.audit(&command_line, command, &decision, &outcome);
+        Ok(result?)
+    }
+
+    /// Decides on `command`, auditing and returning an error if it is
+    /// refused. Returns the command line and decision otherwise.
+    fn check(&self, command: &Command) -> Result<(String, ExecDecision)> {
+        let command_line = command_line(command);
+        let decision = self.decide(&command_line);
+        if let ExecDecision::Denied(reason) = &decision {
+            self.audit(&command_line, command, &decision, reason);
+            return Err(CtxError::CommandDenied {
+                command: command_line,
+                reason: reason.clone(),
+            });
+        }
+        Ok((command_line, decision))
+    }
+
+    /// Appends one JSON line to the audit log. Failures are logged, not
+    /// returned, so a read-only log never blocks work.
+    fn audit(&self, command_line: &str, command: &Command, decision: &ExecDecision, detail: &str) {
+        let Some(path) = &self.audit_log else {
+            return;
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let record = serde_json::json!({
+            "timestamp": timestamp,
+            "command": command_line,
+            "cwd": command.get_current_dir().map(|d| d.display().to_string()),
+            "decision": decision.as_str(),
+            "detail": detail,
+        });
+
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| writeln!(file, "{}", record));
+        if let Err(e) = result {
+            warn!(error = %e, path = %path.display(), "Failed to write exec audit log");
+        }
+    }
+}
+
+/// Renders a command as `program arg1 arg2 ...`.
+fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|s| s.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Wrappers that run their arguments as a command, with the options of
+/// each that take a separate value and the number of positional arguments
+/// before the wrapped command.
+const WRAPPERS: &[(&str, &[&str], usize)] = &[
+    ("env", &["-u", "--unset", "-C", "--chdir"], 0),
+    (
+        "sudo",
+        &["-u", "-g", "-h", "-p", "-C", "-D", "-r", "-t", "-U"],
+        0,
+    ),
+    ("doas", &["-u", "-C"], 0),
+    ("nice", &["-n", "--adjustment"], 0),
+    ("ionice", &["-c", "-n", "-p", "-P", "-u"], 0),
+    ("timeout", &["-s", "--signal", "-k", "--kill-after"], 1),
+    ("stdbuf", &["-i", "-o", "-e"], 0),
+    ("nohup", &[], 0),
+    ("time", &["-f", "--format", "-o", "--output"], 0),
+    ("command", &[], 0),
+    ("exec", &["-a"], 0),
+    ("builtin", &[], 0),
+];
+
+/// Strips wrappers from `command_line` and reduces the program to its file
+/// name, e.g. `env FOO=1 /usr/bin/touch x` becomes `touch x`.
+///
+/// Returns `None` if that leaves the command line unchanged.
+fn unwrap_command_line(command_line: &str) -> Option<String> {
+    let mut words: Vec<&str> = command_line.split_whitespace().collect();
+    loop {
+        let program = basename(words.first()?);
+        let Some(&(name, value_options, positional)) =
+            WRAPPERS.iter().find(|(name, _, _)| *name == program)
+        else {
+            break;
+        };
+        let mut rest = &words[1..];
+        while let Some((&word, tail)) = rest.split_first() {
+            if word == "--" {
+                rest = tail;
+                break;
+            } else if value_options.contains(&word) {
+                rest = tail.get(1..).unwrap_or_default();
+            } else if word.starts_with('-') || (name == "env" && word.contains('=')) {
+                rest = tail;
+            } else {
+                break;
+            }
+        }
+        rest = rest.get(positional..).unwrap_or_default();
+        if rest.is_empty() {
+            // A bare wrapper, such as `env` printing the environment
+            break;
+        }
+        words = rest.to_vec();
+    }
+
+    let mut unwrapped = vec![basename(words[0])];
+    unwrapped.extend_from_slice(&words[1..]);
+    let unwrapped = unwrapped.join(" ");
+    (unwrapped != command_line).then_some(unwrapped)
+}
+
+/// Returns the file name of a program path.
+fn basename(program: &str) -> &str {
+    program.rsplit('/').next().unwrap_or(program)
+}
+
+/// Matches a policy pattern against a command line.
+fn pattern_matches(pattern: &str, command_line: &str) -> bool {
+    let pattern = pattern.trim();
+    if !pattern.contains('*') {
+        return command_line == pattern
+            || command_line
+                .strip_prefix(pattern)
+                .is_some_and(|rest| rest.starts_with(' '));
+    }
+    wildcard_match(pattern.as_bytes(), command_line.as_bytes())
+}
+
+/// Glob-style matching where `*` matches any sequence of bytes.
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config(mode: ExecMode, allow: &[&str], deny: &[&str]) -> ExecConfig {
+        ExecConfig {
+            mode,
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+            audit: true,
+        }
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        assert!(pattern_matches(
+            "cargo metadata",
+            "cargo metadata --no-deps"
+        ));
+        assert!(pattern_matches("cargo metadata", "cargo metadata"));
+        assert!(!pattern_matches("cargo metadata", "cargo metadatax"));
+        assert!(pattern_matches("rust-analyzer*", "rust-analyzer --version"));
+        assert!(pattern_matches("* --version", "cargo --version"));
+        assert!(!pattern_matches("* --version", "cargo build"));
+    }
+
+    #[test]
+    fn test_unwrap_command_line() {
+        assert_eq!(unwrap_command_line("/usr/bin/touch x").unwrap(), "touch x");
+        assert_eq!(unwrap_command_line("env touch x").unwrap(), "touch x");
+        assert_eq!(
+            unwrap_command_line(
+                "env -i FOO=1 -u BAR sudo -u root nice -n 5 timeout -s KILL 10 ./touch x"
+            )
+            .unwrap(),
+            "touch x"
+        );
+        assert_eq!(
+            unwrap_command_line("command -- rm -rf /").unwrap(),
+            "rm -rf /"
+        );
+        assert_eq!(unwrap_command_line("touch x"), None);
+        assert_eq!(unwrap_command_line("env"), None);
+        assert_eq!(unwrap_command_line("/usr/bin/env").unwrap(), "env");
+    }
+
+    #[test]
+    fn test_deny_sees_through_wrappers() {
+        let policy = ExecPolicy::new(config(ExecMode::Allow, &[], &["touch", "cargo publish"]));
+        assert!(!policy.decide("touch x").is_allowed());
+        assert!(!policy.decide("/usr/bin/touch x").is_allowed());
+        assert!(!policy.decide("env touch x").is_allowed());
+        assert!(!policy.decide("env PATH=/tmp sudo touch x").is_allowed());
+        assert!(!policy
+            .decide("nice -n 10 /home/me/.cargo/bin/cargo publish")
+            .is_allowed());
+        assert!(policy.decide("cargo build").is_allowed());
+        assert!(policy.decide("env").is_allowed());
+
+        // Allow patterns stay literal, so a look-alike binary isn't allowed
+        let policy = ExecPolicy::new(config(ExecMode::Deny, &["cargo"], &[]));
+        assert!(policy.decide("cargo build").is_allowed());
+        assert!(!policy.decide("/tmp/cargo build").is_allowed());
+        assert!(!policy.decide("env cargo build").is_allowed());
+    }
+
+    #[test]
+    fn test_decisions() {
+        let policy = ExecPolicy::new(config(ExecMode::Deny, &["cargo"], &["cargo publish"]));
+        assert_eq!(policy.decide("cargo metadata"), ExecDecision::Allowed);
+        assert!(!policy.decide("cargo publish --dry-run").is_allowed());
+        assert!(!policy.decide("rm -rf /").is_allowed());
+
+        let confirm = ExecPolicy::new(config(ExecMode::Confirm, &[], &[]));
+        assert!(!confirm.decide("ls").is_allowed());
+        let confirm = confirm.with_prompt(|cmd| cmd.starts_with("ls"));
+        assert_eq!(confirm.decide("ls -la"), ExecDecision::Confirmed);
+        assert!(!confirm.decide("make").is_allowed());
+    }
+
+    #[test]
+    fn test_denied_commands_are_audited_and_not_run() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("logs/exec.jsonl");
+        let marker = tmp.path().join("ran");
+        let policy = ExecPolicy::new(config(ExecMode::Allow, &[], &["touch"])).with_audit_log(&log);
+
+        let err = policy
+            .output(Command::new("touch").arg(&marker))
+            .unwrap_err();
+        assert!(matches!(err, CtxError::CommandDenied { .. }));
+        assert!(!marker.exists());
+
+        let content = fs::read_to_string(&log).unwrap();
+        let record: serde_json::Value = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(record["decision"], "denied");
+        assert!(record["command"].as_str().unwrap().starts_with("touch "));
+    }
+}
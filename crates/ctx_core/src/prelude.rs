@@ -0,0 +1,14 @@
+//! Commonly used types, for glob import.
+//!
+//! ```
+//! use ctx_core::prelude::*;
+//! ```
+
+pub use crate::error::{CtxError, Result};
+pub use crate::facade::Ctx;
+pub use crate::pack::{
+    AuthorFilter, ChunkKind, PackCursor, PackStreamItem, PromptPack, RetrievalConfig,
+    RetrievedChunk,
+};
+pub use crate::types::{AgentIdentity, CommitType, EdgeLabel, NodeId, NodeKind};
+pub use crate::{CtxRepo, ObjectId};
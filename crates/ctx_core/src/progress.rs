@@ -0,0 +1,137 @@
+//! Progress reporting and cancellation for long operations.
+//!
+//! Garbage collection, index rebuilds, Rust analysis and pack building take
+//! a [`Progress`]: a [`ProgressSink`] that hears how far each phase has got,
+//! and a [`CancellationToken`] they check between units of work. A
+//! cancelled operation stops with [`CtxError::Cancelled`] before its next
+//! change to refs or the index, so the repository is left as it was; only
+//! unreferenced objects it already wrote remain, for GC to collect.
+
+use crate::error::{CtxError, Result};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Receives progress from long operations.
+pub trait ProgressSink: Send + Sync {
+    /// Called as `phase` gets through `current` of `total` units of work.
+    /// `total` is 0 when it isn't known in advance.
+    fn progress(&self, phase: &str, current: u64, total: u64);
+}
+
+impl<F: Fn(&str, u64, u64) + Send + Sync> ProgressSink for F {
+    fn progress(&self, phase: &str, current: u64, total: u64) {
+        self(phase, current, total)
+    }
+}
+
+/// A flag that asks operations to stop, shared by clones.
+///
+/// # Examples
+///
+/// ```
+/// use ctx_core::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let handler = token.clone();
+/// handler.cancel(); // e.g. from a Ctrl-C handler
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every operation holding a clone of this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true once [`cancel`](Self::cancel) has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Where a long operation reports progress, and how it is cancelled.
+///
+/// The default reports nowhere and is never cancelled.
+///
+/// # Examples
+///
+/// ```
+/// use ctx_core::{CancellationToken, Progress};
+///
+/// let token = CancellationToken::new();
+/// let progress = Progress::new(|phase: &str, current, total| {
+///     eprintln!("{}: {}/{}", phase, current, total);
+/// })
+/// .with_cancellation(token.clone());
+///
+/// assert!(progress.tick("scan", 1, 10).is_ok());
+/// token.cancel();
+/// assert!(progress.tick("scan", 2, 10).is_err());
+/// ```
+#[derive(Clone, Default)]
+pub struct Progress {
+    sink: Option<Arc<dyn ProgressSink>>,
+    cancellation: CancellationToken,
+}
+
+impl Progress {
+    /// Reports to `sink`.
+    pub fn new(sink: impl ProgressSink + 'static) -> Self {
+        Self {
+            sink: Some(Arc::new(sink)),
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    /// Stops at the next check once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// The token this checks.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Reports that `phase` is at `current` of `total`.
+    pub fn report(&self, phase: &str, current: u64, total: u64) {
+        if let Some(sink) = &self.sink {
+            sink.progress(phase, current, total);
+        }
+    }
+
+    /// Returns [`CtxError::Cancelled`] if the operation was cancelled.
+    /// Operations that may already have changed the repository say so in
+    /// the error they pass on.
+    pub fn check(&self) -> Result<()> {
+        if self.cancellation.is_cancelled() {
+            return Err(CtxError::Cancelled { changed: false });
+        }
+        Ok(())
+    }
+
+    /// Checks for cancellation, then reports progress.
+    pub fn tick(&self, phase: &str, current: u64, total: u64) -> Result<()> {
+        self.check()?;
+        self.report(phase, current, total);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("sink", &self.sink.is_some())
+            .field("cancelled", &self.cancellation.is_cancelled())
+            .finish()
+    }
+}
@@ -0,0 +1,345 @@
+//! Pruning old history.
+//!
+//! Retrieval reads HEAD's tree and the edges asserted across history, but
+//! rarely the old commits themselves. [`CtxRepo::prune_history`] folds the
+//! commits older than a cutoff into one snapshot commit: a root commit
+//! with the tree, narrative and analyses of the newest pruned commit and
+//! the edge batches of all of them, so the index rebuilt from the kept
+//! history still has every edge. The oldest kept commits' links to the
+//! pruned history are grafted onto the snapshot (see
+//! [`ObjectStore::grafts`]), which leaves the pruned commits and the
+//! objects only they reach unreachable for GC to reclaim.
+//!
+//! Before anything changes, those objects and the previous grafts are
+//! archived in [`BACKUP_DIRECTORY`](crate::BACKUP_DIRECTORY) as
+//! `prune-<time>.tar.zst`; [`CtxRepo::undo_prune`] puts them back.
+//!
+//! [`CtxRepo::prune_history`]: crate::CtxRepo::prune_history
+//! [`CtxRepo::undo_prune`]: crate::CtxRepo::undo_prune
+
+use crate::backup::{BACKUP_DIRECTORY, EXTENSION};
+use crate::error::{CtxError, Result};
+use crate::gc::{collect_roots, mark_reachable, GcReport};
+use crate::object_id::ObjectId;
+use crate::object_store::{parse_grafts, Grafts, ObjectStore, GRAFTS_FILE};
+use crate::progress::Progress;
+use crate::refs::Refs;
+use crate::types::Commit;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Prefix of the names of prune archives.
+const PREFIX: &str = "prune-";
+
+/// Outcome of [`CtxRepo::prune_history`](crate::CtxRepo::prune_history).
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneReport {
+    /// Commits made before this time (Unix seconds) were pruned.
+    pub keep_since: u64,
+    /// Commits kept on HEAD's first-parent history.
+    pub kept: usize,
+    /// Commits folded into the snapshot.
+    pub pruned: usize,
+    /// The snapshot commit standing in for them, if any were pruned.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub snapshot: Option<ObjectId>,
+    /// Objects GC can now reclaim.
+    pub objects: usize,
+    /// Stored (compressed) size of those objects.
+    pub bytes: u64,
+    /// Archive holding them, to undo the prune by.
+    pub backup: Option<String>,
+    /// Named refs that still point into the pruned history, keeping it
+    /// from GC.
+    pub retained_refs: Vec<String>,
+}
+
+/// Outcome of [`CtxRepo::undo_prune`](crate::CtxRepo::undo_prune).
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneUndoReport {
+    /// The archive restored from.
+    pub backup: String,
+    /// Objects put back that GC had reclaimed.
+    pub objects_restored: usize,
+    /// Objects in the archive that were still stored.
+    pub objects_present: usize,
+}
+
+/// Prunes the history of the repository in `ctx_dir` made before
+/// `keep_since`. HEAD is always kept.
+pub(crate) fn prune(
+    ctx_dir: &Path,
+    refs: &Refs,
+    store: &ObjectStore,
+    keep_since: u64,
+    now: u64,
+) -> Result<PruneReport> {
+    let mut report = PruneReport {
+        keep_since,
+        kept: 0,
+        pruned: 0,
+        snapshot: None,
+        objects: 0,
+        bytes: 0,
+        backup: None,
+        retained_refs: Vec::new(),
+    };
+
+    // Kept commits, newest first, and the newest one to prune
+    let mut kept = Vec::new();
+    let mut next = Some(refs.read_head()?);
+    let mut newest_pruned = None;
+    while let Some(id) = next {
+        let commit: Commit = store.get_typed(id)?;
+        if !kept.is_empty() && commit.timestamp_unix < keep_since {
+            newest_pruned = Some((id, commit));
+            break;
+        }
+        next = store.first_parent(&commit);
+        kept.push((id, commit));
+    }
+    report.kept = kept.len();
+    let Some((newest_id, newest)) = newest_pruned else {
+        return Ok(report);
+    };
+
+    let pruned = history(store, newest_id)?;
+    if pruned.len() == 1 && store.parents(&newest).is_empty() {
+        // Already a single root commit, such as an earlier snapshot
+        return Ok(report);
+    }
+    report.pruned = pruned.len();
+
+    // Pruned commits' edge batches, newest first
+    let mut by_age: Vec<&Commit> = pruned.values().collect();
+    by_age.sort_by_key(|commit| std::cmp::Reverse(commit.timestamp_unix));
+    let mut edge_batches = Vec::new();
+    let mut seen = HashSet::new();
+    for commit in by_age {
+        for batch in &commit.edge_batches {
+            if seen.insert(*batch) {
+                edge_batches.push(*batch);
+            }
+        }
+    }
+    let snapshot = Commit {
+        parents: vec![],
+        timestamp_unix: newest.timestamp_unix,
+        message: format!("Snapshot of {} pruned commits", pruned.len()),
+        root_tree: newest.root_tree,
+        edge_batches,
+        narrative_refs: newest.narrative_refs.clone(),
+        cargo_snapshot: newest.cargo_snapshot,
+        rust_snapshot: newest.rust_snapshot,
+        diagnostics_snapshot: newest.diagnostics_snapshot,
+        glossary: newest.glossary,
+        decisions: newest.decisions,
+        facts: newest.facts,
+        qa: newest.qa,
+        transcript: None,
+        commit_type: None,
+        author: None,
+        task: None,
+        tags: Default::default(),
+    };
+    let snapshot_id = store.put_typed(&snapshot)?;
+    report.snapshot = Some(snapshot_id);
+
+    // Kept commits' links into the pruned history: the first-parent one
+    // leads to the snapshot, any other nowhere
+    let old_grafts = store.grafts();
+    let mut grafts: BTreeMap<ObjectId, Option<ObjectId>> = old_grafts
+        .iter()
+        .map(|(id, stand_in)| (*id, *stand_in))
+        .collect();
+    for (_, commit) in &kept {
+        for (i, parent) in commit.parents.iter().enumerate() {
+            let followed = old_grafts.get(parent).copied().unwrap_or(Some(*parent));
+            if followed.is_some_and(|followed| pruned.contains_key(&followed)) {
+                let stand_in = (i == 0 && followed == Some(newest_id)).then_some(snapshot_id);
+                grafts.insert(*parent, stand_in);
+            }
+        }
+    }
+
+    for (name, id) in refs.list_refs()? {
+        if pruned.contains_key(&id) {
+            report.retained_refs.push(format!("refs/{}", name));
+        }
+    }
+
+    // What only the pruned history reaches, archived before it goes
+    let roots = collect_roots(refs, store, None)?;
+    let before = reachable(store, &roots, &old_grafts)?;
+    let after_grafts: Grafts = grafts
+        .iter()
+        .map(|(id, stand_in)| (*id, *stand_in))
+        .collect();
+    let after = reachable(store, &roots, &after_grafts)?;
+    let mut unreachable: Vec<ObjectId> = before
+        .difference(&after)
+        .copied()
+        .filter(|id| store.exists(*id))
+        .collect();
+    unreachable.sort();
+
+    let (name, bytes) = archive(ctx_dir, store, &unreachable, now)?;
+    report.objects = unreachable.len();
+    report.bytes = bytes;
+    report.backup = Some(name);
+
+    store.write_grafts(&grafts)?;
+    Ok(report)
+}
+
+/// Puts back the objects and grafts archived by prune `name`.
+pub(crate) fn undo(ctx_dir: &Path, store: &ObjectStore, name: &str) -> Result<PruneUndoReport> {
+    let name = name.strip_suffix(EXTENSION).unwrap_or(name);
+    let path = ctx_dir
+        .join(BACKUP_DIRECTORY)
+        .join(format!("{}{}", name, EXTENSION));
+    if !name.starts_with(PREFIX) || name.contains(['/', '\\']) || !path.exists() {
+        return Err(CtxError::InvalidArgument(format!(
+            "no prune backup named '{}'",
+            name
+        )));
+    }
+
+    let corrupt = |reason: String| CtxError::CorruptedObject {
+        path: path.clone(),
+        reason,
+    };
+    let decoder = zstd::Decoder::new(fs::File::open(&path)?)
+        .map_err(|e| corrupt(format!("can't decompress: {}", e)))?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut report = PruneUndoReport {
+        backup: name.to_string(),
+        objects_restored: 0,
+        objects_present: 0,
+    };
+    let mut old_grafts = None;
+    for entry in archive
+        .entries()
+        .map_err(|e| corrupt(format!("can't read archive: {}", e)))?
+    {
+        let mut entry = entry.map_err(|e| corrupt(format!("can't read archive: {}", e)))?;
+        let entry_path = entry
+            .path()
+            .map_err(|e| corrupt(format!("invalid path: {}", e)))?
+            .to_string_lossy()
+            .into_owned();
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .map_err(|e| corrupt(format!("can't read {}: {}", entry_path, e)))?;
+
+        if entry_path == GRAFTS_FILE {
+            old_grafts = Some(content);
+            continue;
+        }
+        let id = entry_path
+            .rsplit_once('/')
+            .and_then(|(_, hex)| ObjectId::from_hex(hex).ok())
+            .filter(|id| entry_path == format!("objects/{}/{}", id.shard(), id.as_hex()))
+            .ok_or_else(|| corrupt(format!("unexpected file {}", entry_path)))?;
+        let object_path = store.root().join(id.shard()).join(id.as_hex());
+        if object_path.exists() {
+            report.objects_present += 1;
+            continue;
+        }
+        fs::create_dir_all(store.root().join(id.shard()))?;
+        let tmp = object_path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &object_path)?;
+        report.objects_restored += 1;
+    }
+
+    let grafts = match old_grafts {
+        Some(content) => parse_grafts(&String::from_utf8_lossy(&content)),
+        None => BTreeMap::new(),
+    };
+    store.write_grafts(&grafts)?;
+    store.rebuild_filter()?;
+    Ok(report)
+}
+
+/// Commits reachable from `start` through parent links, by ID.
+fn history(store: &ObjectStore, start: ObjectId) -> Result<BTreeMap<ObjectId, Commit>> {
+    let mut commits = BTreeMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(id) = queue.pop_front() {
+        if commits.contains_key(&id) {
+            continue;
+        }
+        let commit: Commit = store.get_typed(id)?;
+        queue.extend(store.parents(&commit));
+        commits.insert(id, commit);
+    }
+    Ok(commits)
+}
+
+fn reachable(
+    store: &ObjectStore,
+    roots: &[ObjectId],
+    grafts: &Grafts,
+) -> Result<HashSet<ObjectId>> {
+    mark_reachable(
+        store,
+        roots,
+        grafts,
+        None,
+        &mut GcReport::default(),
+        &Progress::default(),
+    )
+}
+
+/// Archives `objects` and the current grafts, returning the archive's name
+/// and the objects' stored size.
+fn archive(
+    ctx_dir: &Path,
+    store: &ObjectStore,
+    objects: &[ObjectId],
+    now: u64,
+) -> Result<(String, u64)> {
+    let dir = ctx_dir.join(BACKUP_DIRECTORY);
+    fs::create_dir_all(&dir)?;
+    let mut name = format!("{}{}", PREFIX, now);
+    let mut n = 1;
+    while dir.join(format!("{}{}", name, EXTENSION)).exists() {
+        name = format!("{}{}-{}", PREFIX, now, n);
+        n += 1;
+    }
+    let path = dir.join(format!("{}{}", name, EXTENSION));
+    let tmp = path.with_extension("tmp");
+
+    let write = || -> Result<u64> {
+        let encoder = zstd::Encoder::new(fs::File::create(&tmp)?, 0)?;
+        let mut builder = tar::Builder::new(encoder);
+        let grafts = store.root().join(GRAFTS_FILE);
+        if grafts.exists() {
+            builder.append_path_with_name(&grafts, GRAFTS_FILE)?;
+        }
+        let mut bytes = 0;
+        for id in objects {
+            let object = store.local_path(*id)?;
+            bytes += fs::metadata(&object)?.len();
+            builder.append_path_with_name(
+                &object,
+                format!("objects/{}/{}", id.shard(), id.as_hex()),
+            )?;
+        }
+        builder.into_inner()?.finish()?.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(bytes)
+    };
+    match write() {
+        Ok(bytes) => Ok((name, bytes)),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Question/answer memory.
+//!
+//! When an agent asks a question and the user answers it, the pair is kept
+//! as a [`QaPair`] so the answer doesn't have to be given again. Pairs are
+//! recorded with [`CtxRepo::record_answer`](crate::CtxRepo::record_answer),
+//! which a [`SessionHandler`](crate::SessionHandler) emits for every
+//! response to a question, and join the [`QaLog`] referenced from each
+//! commit when the session is compacted. Each gets a `Qa` node with
+//! `Mentions` edges to the files the session had touched.
+//!
+//! Packs lead with past answers to questions similar to the query, ahead of
+//! any file content. Similarity is the share of significant words the
+//! query and question have in common.
+
+use crate::error::{CtxError, Result};
+use crate::types::{
+    Confidence, Edge, EdgeBatch, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind,
+};
+use crate::{ObjectId, ObjectStore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Words too common to make two questions similar.
+const STOP_WORDS: &[&str] = &[
+    "and", "are", "can", "did", "does", "for", "from", "how", "into", "the", "there", "this",
+    "what", "when", "where", "which", "who", "why", "with", "you",
+];
+
+/// A question asked in a session and the answer it got.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QaPair {
+    /// The agent's question.
+    pub question: String,
+    /// The user's answer.
+    pub answer: String,
+    /// Files the session had read or written when the answer came.
+    pub paths: Vec<String>,
+    /// Task of the session the question was asked in.
+    pub task: Option<String>,
+    /// Creation time (Unix seconds).
+    pub created_at: u64,
+}
+
+impl QaPair {
+    /// Renders the pair as markdown for a pack.
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            format!("**Q**: {}", self.question),
+            format!("**A**: {}", self.answer),
+        ];
+        if !self.paths.is_empty() {
+            lines.push(format!("**Files**: {}", self.paths.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Every recorded question and answer, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct QaLog {
+    /// IDs of the stored [`QaPair`] objects.
+    pub pairs: Vec<ObjectId>,
+}
+
+/// Similarity of two questions as fixed-point (1000 = same significant
+/// words), by the overlap of their significant words.
+pub(crate) fn similarity(a: &str, b: &str) -> u32 {
+    let a = significant_words(a);
+    let b = significant_words(b);
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0;
+    }
+    (a.intersection(&b).count() * 1000 / union) as u32
+}
+
+/// Lowercase words of three or more characters, minus stop words.
+fn significant_words(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Appends stored pairs to the log at `log_id`.
+///
+/// Returns the new log's ID (`log_id` itself if `pair_ids` is empty) and an
+/// edge batch linking the pairs to their files, if they name any.
+/// `commit_id` is recorded as the edges' evidence.
+pub(crate) fn append_pairs(
+    pair_ids: &[ObjectId],
+    log_id: Option<ObjectId>,
+    commit_id: ObjectId,
+    now: u64,
+    object_store: &ObjectStore,
+) -> Result<(Option<ObjectId>, Option<ObjectId>)> {
+    if pair_ids.is_empty() {
+        return Ok((log_id, None));
+    }
+    let mut log: QaLog = match log_id {
+        Some(id) => object_store.get_typed(id)?,
+        None => QaLog::default(),
+    };
+
+    let mut edges = Vec::new();
+    for &pair_id in pair_ids {
+        let pair: QaPair = object_store.get_typed(pair_id)?;
+        edges.extend(pair.paths.iter().map(|path| Edge {
+            from: qa_node(pair_id),
+            to: NodeId {
+                kind: NodeKind::File,
+                id: path.clone(),
+            },
+            label: EdgeLabel::Mentions,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool: EvidenceTool::Human,
+                confidence: Confidence::Medium,
+                span: None,
+                blob_id: Some(pair_id),
+                condition: None,
+            },
+        }));
+        log.pairs.push(pair_id);
+    }
+
+    let batch_id = if edges.is_empty() {
+        None
+    } else {
+        Some(object_store.put_typed(&EdgeBatch {
+            edges,
+            created_at: now,
+        })?)
+    };
+    Ok((Some(object_store.put_typed(&log)?), batch_id))
+}
+
+/// The graph node for a stored pair.
+fn qa_node(pair_id: ObjectId) -> NodeId {
+    NodeId {
+        kind: NodeKind::Qa,
+        id: pair_id.as_hex(),
+    }
+}
+
+/// Validates a pair before it is recorded.
+pub(crate) fn validate(question: &str, answer: &str) -> Result<()> {
+    if question.trim().is_empty() || answer.trim().is_empty() {
+        return Err(CtxError::InvalidArgument(
+            "question and answer must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_question_similarity() {
+        let asked = "Where is config parsing?";
+        assert_eq!(similarity(asked, "where is CONFIG parsing"), 1000);
+        assert_eq!(similarity(asked, "Where does config parsing happen?"), 666);
+        assert_eq!(similarity(asked, "How are packs cached?"), 0);
+        assert_eq!(similarity("Where is it?", "Where is it?"), 0);
+    }
+}
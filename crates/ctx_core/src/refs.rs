@@ -1,10 +1,147 @@
 //! Reference (pointer) management for HEAD, STAGE, and named refs.
+//!
+//! Each ref file is replaced atomically, but transitions that move several
+//! refs at once (compaction moves HEAD and `main` and clears STAGE) go
+//! through [`Refs::transaction`]: the full set of updates is first written
+//! to a journal file, then applied, then the journal is removed. If the
+//! process dies part-way, [`Refs::replay_journal`] finishes the transition
+//! the next time the repository is opened. Transactions and replays hold
+//! the `REFS_LOCK` file lock, so a replay never mistakes the journal of a
+//! transaction still in flight for a crashed one.
+//!
+//! Every transaction also appends one line per HEAD or named-ref move to
+//! the reflog (`.ctx/reflog`), with the previous and new commit, a
+//! timestamp and the reason, so a position HEAD was moved away from can be
+//! found again with [`Refs::reflog`] and `<ref>@{N}`.
 
 use crate::error::{CtxError, Result};
 use crate::ObjectId;
-use std::fs::{self, File};
-use std::io::Write;
+use fs2::FileExt;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the pending ref transaction journal in the .ctx directory.
+const JOURNAL_FILE: &str = "REFS_JOURNAL";
+
+/// Name of the lock file held by ref transactions and journal replay.
+const LOCK_FILE: &str = "REFS_LOCK";
+
+/// Name of the ref log in the .ctx directory.
+const REFLOG_FILE: &str = "reflog";
+
+/// Refs stored directly in the .ctx directory rather than under `refs/`.
+pub(crate) const TOP_LEVEL_REFS: [&str; 4] = ["HEAD", "STAGE", "SUMMARIES", "FEEDBACK"];
+
+/// Namespace under `refs/` for refs that point at note tables rather than
+/// commits.
+const NOTES_NAMESPACE: &str = "notes";
+
+/// Prefix of tag names under `refs/`.
+pub(crate) const TAGS_PREFIX: &str = "tags/";
+
+/// The ref holding the [`NoteTable`](crate::NoteTable) of commit
+/// annotations, relative to `refs/`.
+pub(crate) const COMMIT_NOTES_REF: &str = "notes/commits";
+
+/// One step of a ref transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefUpdate {
+    /// Point HEAD at a commit.
+    Head(ObjectId),
+    /// Point a named ref at a commit.
+    Ref(String, ObjectId),
+    /// Point STAGE at a work commit.
+    Stage(ObjectId),
+    /// Remove STAGE.
+    DeleteStage,
+}
+
+impl RefUpdate {
+    /// Journal line for this update.
+    fn encode(&self) -> String {
+        match self {
+            RefUpdate::Head(id) => format!("head {}", id.as_hex()),
+            RefUpdate::Ref(name, id) => format!("ref {} {}", id.as_hex(), name),
+            RefUpdate::Stage(id) => format!("stage {}", id.as_hex()),
+            RefUpdate::DeleteStage => "delete-stage".to_string(),
+        }
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let (op, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let id = |hex: &str| ObjectId::from_hex(hex).ok();
+        match op {
+            "head" => id(rest).map(RefUpdate::Head),
+            "ref" => {
+                let (hex, name) = rest.split_once(' ')?;
+                Some(RefUpdate::Ref(name.to_string(), id(hex)?))
+            }
+            "stage" => id(rest).map(RefUpdate::Stage),
+            "delete-stage" if rest.is_empty() => Some(RefUpdate::DeleteStage),
+            _ => None,
+        }
+    }
+}
+
+/// One move of HEAD or a named ref, as recorded in the reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// The ref that moved: `HEAD` or a name under `refs/`, such as `main`.
+    pub ref_name: String,
+    /// What the ref pointed at before, if it existed.
+    pub old: Option<ObjectId>,
+    /// What the ref points at after the move.
+    pub new: ObjectId,
+    /// When the ref moved (Unix seconds).
+    pub timestamp_unix: u64,
+    /// Why the ref moved, e.g. `compact: Fix parser`.
+    pub reason: String,
+}
+
+impl ReflogEntry {
+    /// Reflog line: `<old> <new> <timestamp> <ref>\t<reason>`, with an
+    /// all-zero old ID for a ref that didn't exist.
+    fn encode(&self) -> String {
+        let old = self
+            .old
+            .map(|id| id.as_hex())
+            .unwrap_or_else(|| "0".repeat(64));
+        // One entry per line, whatever the reason says
+        let reason = self.reason.replace(['\n', '\r'], " ");
+        format!(
+            "{} {} {} {}\t{}",
+            old,
+            self.new.as_hex(),
+            self.timestamp_unix,
+            self.ref_name,
+            reason
+        )
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let (head, reason) = line.split_once('\t')?;
+        let mut fields = head.splitn(4, ' ');
+        let old = fields.next()?;
+        let new = ObjectId::from_hex(fields.next()?).ok()?;
+        let timestamp_unix = fields.next()?.parse().ok()?;
+        let ref_name = fields.next()?.to_string();
+        let old = if old.bytes().all(|b| b == b'0') {
+            None
+        } else {
+            Some(ObjectId::from_hex(old).ok()?)
+        };
+        Some(Self {
+            ref_name,
+            old,
+            new,
+            timestamp_unix,
+            reason: reason.to_string(),
+        })
+    }
+}
 
 /// Manages references to commits.
 ///
@@ -22,6 +159,11 @@ impl Refs {
         }
     }
 
+    /// The .ctx directory these refs live in.
+    pub(crate) fn ctx_dir(&self) -> &Path {
+        &self.root
+    }
+
     /// Reads the HEAD reference.
     ///
     /// # Errors
@@ -58,7 +200,7 @@ impl Refs {
 
         // Ensure parent directory exists
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+            fs::create_dir_all(parent).map_err(ref_error(parent))?;
         }
 
         self.write_ref_file(&path, id)
@@ -76,13 +218,15 @@ impl Refs {
             return Err(CtxError::RefNotFound(name.to_string()));
         }
 
-        fs::remove_file(&path)?;
+        fs::remove_file(&path).map_err(ref_error(&path))?;
         Ok(())
     }
 
     /// Lists all named references.
     ///
-    /// Returns a sorted list of (name, ObjectId) pairs.
+    /// Returns a sorted list of (name, ObjectId) pairs. Refs under
+    /// `refs/notes/` aren't listed: they point at note tables, not commits
+    /// (see [`Refs::read_notes`]).
     pub fn list_refs(&self) -> Result<Vec<(String, ObjectId)>> {
         let refs_dir = self.root.join("refs");
 
@@ -92,6 +236,7 @@ impl Refs {
 
         let mut refs = Vec::new();
         self.collect_refs(&refs_dir, &refs_dir, &mut refs)?;
+        refs.retain(|(name, _)| name.split(['/', '\\']).next() != Some(NOTES_NAMESPACE));
 
         // Sort by name for deterministic output
         refs.sort_by(|a, b| a.0.cmp(&b.0));
@@ -99,6 +244,16 @@ impl Refs {
         Ok(refs)
     }
 
+    /// Lists tags, the refs under `refs/tags/`, as (tag name, ObjectId)
+    /// pairs sorted by name.
+    pub fn list_tags(&self) -> Result<Vec<(String, ObjectId)>> {
+        Ok(self
+            .list_refs()?
+            .into_iter()
+            .filter_map(|(name, id)| Some((name.strip_prefix(TAGS_PREFIX)?.to_string(), id)))
+            .collect())
+    }
+
     /// Reads the STAGE reference (optional staging area).
     ///
     /// Returns `None` if STAGE doesn't exist.
@@ -125,12 +280,351 @@ impl Refs {
         let path = self.root.join("STAGE");
 
         if path.exists() {
-            fs::remove_file(&path)?;
+            fs::remove_file(&path).map_err(ref_error(&path))?;
         }
 
         Ok(())
     }
 
+    /// Reads the SUMMARIES reference, which points at the
+    /// [`SummaryTable`](crate::SummaryTable).
+    ///
+    /// Returns `None` if nothing has been summarized yet.
+    pub fn read_summaries(&self) -> Result<Option<ObjectId>> {
+        let path = self.root.join("SUMMARIES");
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        self.read_ref_file(&path).map(Some)
+    }
+
+    /// Writes the SUMMARIES reference atomically.
+    pub fn write_summaries(&self, id: ObjectId) -> Result<()> {
+        let path = self.root.join("SUMMARIES");
+        self.write_ref_file(&path, id)
+    }
+
+    /// Reads the FEEDBACK reference, which points at the
+    /// [`FeedbackLog`](crate::FeedbackLog).
+    ///
+    /// Returns `None` if no feedback has been recorded yet.
+    pub fn read_feedback(&self) -> Result<Option<ObjectId>> {
+        let path = self.root.join("FEEDBACK");
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        self.read_ref_file(&path).map(Some)
+    }
+
+    /// Writes the FEEDBACK reference atomically.
+    pub fn write_feedback(&self, id: ObjectId) -> Result<()> {
+        let path = self.root.join("FEEDBACK");
+        self.write_ref_file(&path, id)
+    }
+
+    /// Reads the `refs/notes/commits` reference, which points at the
+    /// [`NoteTable`](crate::NoteTable) of commit annotations.
+    ///
+    /// Returns `None` if no commit has been annotated yet.
+    pub fn read_notes(&self) -> Result<Option<ObjectId>> {
+        let path = self.root.join("refs").join(COMMIT_NOTES_REF);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        self.read_ref_file(&path).map(Some)
+    }
+
+    /// Writes the `refs/notes/commits` reference atomically.
+    pub fn write_notes(&self, id: ObjectId) -> Result<()> {
+        self.write_ref(COMMIT_NOTES_REF, id)
+    }
+
+    /// Every ref that is set: `HEAD`, `STAGE`, `SUMMARIES`, `FEEDBACK`,
+    /// the notes ref and the named refs as `refs/<name>`.
+    pub(crate) fn all(&self) -> Result<BTreeMap<String, ObjectId>> {
+        let mut all = BTreeMap::new();
+        for name in TOP_LEVEL_REFS {
+            let path = self.root.join(name);
+            if path.exists() {
+                all.insert(name.to_string(), self.read_ref_file(&path)?);
+            }
+        }
+        if let Some(notes) = self.read_notes()? {
+            all.insert(format!("refs/{}", COMMIT_NOTES_REF), notes);
+        }
+        for (name, id) in self.list_refs()? {
+            all.insert(format!("refs/{}", name), id);
+        }
+        Ok(all)
+    }
+
+    /// Copies every ref to a new ref directory at `dest`, whose updates
+    /// don't affect these refs.
+    pub(crate) fn copy_to(&self, dest: &Path) -> Result<Refs> {
+        self.copy_matching_to(dest, |_, _| true)
+    }
+
+    /// Copies the refs `keep` accepts, by the names [`all`](Self::all)
+    /// gives them, to a new ref directory at `dest`.
+    pub(crate) fn copy_matching_to(
+        &self,
+        dest: &Path,
+        mut keep: impl FnMut(&str, ObjectId) -> bool,
+    ) -> Result<Refs> {
+        let copy = Refs::new(dest);
+        fs::create_dir_all(dest.join("refs")).map_err(ref_error(dest))?;
+        for (name, id) in self.all()? {
+            if !keep(&name, id) {
+                continue;
+            }
+            let path = dest.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(ref_error(parent))?;
+            }
+            copy.write_ref_file(&path, id)?;
+        }
+        Ok(copy)
+    }
+
+    /// Applies `updates` as one crash-safe transition, recording `reason`
+    /// in the reflog for each HEAD or named-ref move.
+    ///
+    /// The updates are journaled before any ref is touched, so a crash
+    /// part-way leaves a journal that [`Refs::replay_journal`] completes.
+    /// Every update is idempotent, which makes replaying safe even if some
+    /// of them already landed.
+    pub fn transaction(&self, updates: &[RefUpdate], reason: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let journal = self.root.join(JOURNAL_FILE);
+        let mut body = format!("reason {}\n", reason.replace(['\n', '\r'], " "));
+        body.extend(updates.iter().map(|u| u.encode() + "\n"));
+        self.write_file_atomic(&journal, body.as_bytes())?;
+
+        let moves = self.pending_moves(updates, reason)?;
+        self.apply_updates(updates)?;
+        self.append_reflog(&moves)?;
+
+        remove_journal(&journal)
+    }
+
+    /// Completes a transaction interrupted by a crash.
+    ///
+    /// Returns the updates that were replayed, or an empty list if no
+    /// transaction was pending.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidRef` if the journal is unreadable; it is left in
+    /// place so the refs can be repaired by hand.
+    pub fn replay_journal(&self) -> Result<Vec<RefUpdate>> {
+        let journal = self.root.join(JOURNAL_FILE);
+        if !journal.exists() {
+            return Ok(vec![]);
+        }
+
+        // Wait out a transaction in flight; it removes its own journal
+        let _lock = self.lock()?;
+        let content = match fs::read_to_string(&journal) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(ref_error(&journal)(e)),
+        };
+        let mut reason = "transaction";
+        let updates = content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .filter(|line| match line.strip_prefix("reason ") {
+                Some(text) => {
+                    reason = text;
+                    false
+                }
+                None => true,
+            })
+            .map(|line| {
+                RefUpdate::decode(line).ok_or_else(|| CtxError::InvalidRef {
+                    path: journal.clone(),
+                    reason: format!("unrecognized journal entry: {}", line),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Moves that already landed were logged before the crash, or are
+        // lost with it; only the ones finished now are logged
+        let moves = self.pending_moves(&updates, &format!("{} (replayed)", reason))?;
+        self.apply_updates(&updates)?;
+        self.append_reflog(&moves)?;
+        remove_journal(&journal)?;
+        Ok(updates)
+    }
+
+    /// Takes the refs lock, blocking until any other holder releases it.
+    fn lock(&self) -> Result<File> {
+        let path = self.root.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(ref_error(&path))?;
+        // fs2's, not the newer std method of the same name
+        FileExt::lock_exclusive(&file).map_err(ref_error(&path))?;
+        Ok(file)
+    }
+
+    /// Reflog entries for `ref_name` (`HEAD`, or a name under `refs/`),
+    /// newest first, so entry N is what `<ref_name>@{N}` names.
+    ///
+    /// Returns an empty list if the ref has never moved since the reflog
+    /// was introduced.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidRef` if the reflog holds a malformed line.
+    pub fn reflog(&self, ref_name: &str) -> Result<Vec<ReflogEntry>> {
+        let mut entries: Vec<_> = self
+            .reflog_entries()?
+            .into_iter()
+            .filter(|entry| entry.ref_name == ref_name)
+            .collect();
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Every reflog entry, of every ref, oldest first.
+    pub(crate) fn reflog_entries(&self) -> Result<Vec<ReflogEntry>> {
+        let path = self.root.join(REFLOG_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(ref_error(&path)(e)),
+        };
+
+        content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                ReflogEntry::decode(line).ok_or_else(|| CtxError::InvalidRef {
+                    path: path.clone(),
+                    reason: format!("unrecognized reflog entry: {}", line),
+                })
+            })
+            .collect()
+    }
+
+    /// Resolves `<ref>@{N}`, the commit `ref` pointed at N moves ago.
+    ///
+    /// Returns `None` if `spec` doesn't have that form.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RefNotFound` if the reflog has fewer than N + 1 entries for
+    /// the ref.
+    pub fn resolve_reflog(&self, spec: &str) -> Result<Option<ObjectId>> {
+        let Some((ref_name, n)) = spec
+            .strip_suffix('}')
+            .and_then(|rest| rest.split_once("@{"))
+        else {
+            return Ok(None);
+        };
+        let Ok(n) = n.parse::<usize>() else {
+            return Ok(None);
+        };
+
+        self.reflog(ref_name)?
+            .get(n)
+            .map(|entry| Some(entry.new))
+            .ok_or_else(|| CtxError::RefNotFound(spec.to_string()))
+    }
+
+    /// The reflog entries `updates` will add, with the values the refs
+    /// hold now. Updates that don't move a ref aren't logged.
+    fn pending_moves(&self, updates: &[RefUpdate], reason: &str) -> Result<Vec<ReflogEntry>> {
+        let timestamp_unix = now_unix();
+        let mut moves = Vec::new();
+        for update in updates {
+            let (ref_name, old, new) = match update {
+                RefUpdate::Head(id) => ("HEAD", self.read_head(), *id),
+                RefUpdate::Ref(name, id) => (name.as_str(), self.read_ref(name), *id),
+                RefUpdate::Stage(_) | RefUpdate::DeleteStage => continue,
+            };
+            let old = match old {
+                Ok(old) => Some(old),
+                Err(CtxError::RefNotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            if old == Some(new) {
+                continue;
+            }
+            moves.push(ReflogEntry {
+                ref_name: ref_name.to_string(),
+                old,
+                new,
+                timestamp_unix,
+                reason: reason.to_string(),
+            });
+        }
+        Ok(moves)
+    }
+
+    /// Logs a move of `ref_name` made outside a transaction, such as a
+    /// backup restore replacing the ref files. Does nothing if the ref
+    /// didn't move.
+    pub(crate) fn log_move(
+        &self,
+        ref_name: &str,
+        old: Option<ObjectId>,
+        new: ObjectId,
+        reason: &str,
+    ) -> Result<()> {
+        if old == Some(new) {
+            return Ok(());
+        }
+        self.append_reflog(&[ReflogEntry {
+            ref_name: ref_name.to_string(),
+            old,
+            new,
+            timestamp_unix: now_unix(),
+            reason: reason.to_string(),
+        }])
+    }
+
+    fn append_reflog(&self, entries: &[ReflogEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let path = self.root.join(REFLOG_FILE);
+        let body: String = entries.iter().map(|e| e.encode() + "\n").collect();
+
+        // A single append-mode write keeps concurrent writers from
+        // interleaving within a line
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(ref_error(&path))?;
+        file.write_all(body.as_bytes()).map_err(ref_error(&path))?;
+        Ok(())
+    }
+
+    fn apply_updates(&self, updates: &[RefUpdate]) -> Result<()> {
+        for update in updates {
+            match update {
+                RefUpdate::Head(id) => self.write_head(*id)?,
+                RefUpdate::Ref(name, id) => self.write_ref(name, *id)?,
+                RefUpdate::Stage(id) => self.write_stage(*id)?,
+                RefUpdate::DeleteStage => self.delete_stage()?,
+            }
+        }
+        Ok(())
+    }
+
     /// Reads an ObjectId from a ref file.
     fn read_ref_file(&self, path: &Path) -> Result<ObjectId> {
         if !path.exists() {
@@ -142,7 +636,7 @@ impl Refs {
             ));
         }
 
-        let content = fs::read_to_string(path)?;
+        let content = fs::read_to_string(path).map_err(ref_error(path))?;
         let trimmed = content.trim();
 
         if trimmed.len() != 64 {
@@ -162,17 +656,22 @@ impl Refs {
     ///
     /// Uses temp file + fsync + rename for crash safety.
     fn write_ref_file(&self, path: &Path, id: ObjectId) -> Result<()> {
+        self.write_file_atomic(path, format!("{}\n", id.as_hex()).as_bytes())
+    }
+
+    /// Replaces `path` with `content` using temp file + fsync + rename.
+    fn write_file_atomic(&self, path: &Path, content: &[u8]) -> Result<()> {
         let tmp_path = path.with_extension("tmp");
 
         // Write to temp file
         {
-            let mut file = File::create(&tmp_path)?;
-            writeln!(file, "{}", id.as_hex())?;
-            file.sync_all()?;
+            let mut file = File::create(&tmp_path).map_err(ref_error(&tmp_path))?;
+            file.write_all(content).map_err(ref_error(&tmp_path))?;
+            file.sync_all().map_err(ref_error(&tmp_path))?;
         }
 
         // Atomic rename
-        fs::rename(&tmp_path, path)?;
+        fs::rename(&tmp_path, path).map_err(ref_error(path))?;
 
         // fsync parent directory (Unix-specific for crash safety)
         #[cfg(unix)]
@@ -222,6 +721,30 @@ impl Refs {
     }
 }
 
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Wraps an I/O failure on the ref file at `path`.
+/// Removes a finished transaction's journal. One that's already gone was
+/// finished by someone else.
+fn remove_journal(journal: &Path) -> Result<()> {
+    match fs::remove_file(journal) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(ref_error(journal)(e)),
+        _ => Ok(()),
+    }
+}
+
+fn ref_error(path: &Path) -> impl FnOnce(std::io::Error) -> CtxError + '_ {
+    move |e| CtxError::RefError {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,6 +877,154 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_transaction_applies_and_clears_journal() {
+        let tmp = TempDir::new().unwrap();
+        let refs = Refs::new(tmp.path());
+
+        refs.write_stage(ObjectId::from_bytes([1; 32])).unwrap();
+        let id = ObjectId::from_bytes([2; 32]);
+        refs.transaction(
+            &[
+                RefUpdate::Head(id),
+                RefUpdate::Ref("main".into(), id),
+                RefUpdate::DeleteStage,
+            ],
+            "test",
+        )
+        .unwrap();
+
+        assert_eq!(refs.read_head().unwrap(), id);
+        assert_eq!(refs.read_ref("main").unwrap(), id);
+        assert_eq!(refs.read_stage().unwrap(), None);
+        assert!(!tmp.path().join(JOURNAL_FILE).exists());
+        assert!(refs.replay_journal().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replay_journal_completes_interrupted_transaction() {
+        let tmp = TempDir::new().unwrap();
+        let refs = Refs::new(tmp.path());
+
+        let old = ObjectId::from_bytes([1; 32]);
+        let new = ObjectId::from_bytes([2; 32]);
+        refs.write_head(old).unwrap();
+        refs.write_ref("heads/feature", old).unwrap();
+        refs.write_stage(old).unwrap();
+
+        // Crash after the journal and the HEAD update landed
+        let updates = vec![
+            RefUpdate::Head(new),
+            RefUpdate::Ref("heads/feature".into(), new),
+            RefUpdate::DeleteStage,
+        ];
+        let body: String = updates.iter().map(|u| u.encode() + "\n").collect();
+        fs::write(
+            tmp.path().join(JOURNAL_FILE),
+            format!("reason compact\n{}", body),
+        )
+        .unwrap();
+        refs.write_head(new).unwrap();
+
+        assert_eq!(refs.replay_journal().unwrap(), updates);
+        assert_eq!(refs.read_head().unwrap(), new);
+        assert_eq!(refs.read_ref("heads/feature").unwrap(), new);
+        assert_eq!(refs.read_stage().unwrap(), None);
+        assert!(!tmp.path().join(JOURNAL_FILE).exists());
+
+        // Only the move finished by the replay is logged
+        assert!(refs.reflog("HEAD").unwrap().is_empty());
+        let logged = refs.reflog("heads/feature").unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].old, Some(old));
+        assert_eq!(logged[0].reason, "compact (replayed)");
+    }
+
+    #[test]
+    fn test_reflog_records_moves_newest_first() {
+        let tmp = TempDir::new().unwrap();
+        let refs = Refs::new(tmp.path());
+
+        let first = ObjectId::from_bytes([1; 32]);
+        let second = ObjectId::from_bytes([2; 32]);
+        refs.transaction(
+            &[RefUpdate::Head(first), RefUpdate::Ref("main".into(), first)],
+            "init",
+        )
+        .unwrap();
+        refs.transaction(
+            &[
+                RefUpdate::Head(second),
+                RefUpdate::Ref("main".into(), second),
+                RefUpdate::Stage(second),
+            ],
+            "compact: Fix\nparser",
+        )
+        .unwrap();
+        // A no-op move isn't logged
+        refs.transaction(&[RefUpdate::Head(second)], "again")
+            .unwrap();
+
+        let head = refs.reflog("HEAD").unwrap();
+        assert_eq!(head.len(), 2);
+        assert_eq!(head[0].old, Some(first));
+        assert_eq!(head[0].new, second);
+        assert_eq!(head[0].reason, "compact: Fix parser");
+        assert_eq!(head[1].old, None);
+        assert_eq!(head[1].reason, "init");
+        assert_eq!(refs.reflog("main").unwrap().len(), 2);
+        assert!(refs.reflog("STAGE").unwrap().is_empty());
+
+        assert_eq!(refs.resolve_reflog("HEAD@{0}").unwrap(), Some(second));
+        assert_eq!(refs.resolve_reflog("main@{1}").unwrap(), Some(first));
+        assert!(matches!(
+            refs.resolve_reflog("HEAD@{2}"),
+            Err(CtxError::RefNotFound(_))
+        ));
+        assert_eq!(refs.resolve_reflog("HEAD").unwrap(), None);
+        assert_eq!(refs.resolve_reflog("HEAD@{x}").unwrap(), None);
+    }
+
+    #[test]
+    fn test_replay_journal_waits_for_transaction_in_flight() {
+        let tmp = TempDir::new().unwrap();
+        let refs = Refs::new(tmp.path());
+        let old = ObjectId::from_bytes([1; 32]);
+        let new = ObjectId::from_bytes([2; 32]);
+        refs.write_head(old).unwrap();
+
+        // A transaction that has journaled its updates but not applied them
+        let lock = refs.lock().unwrap();
+        let journal = tmp.path().join(JOURNAL_FILE);
+        fs::write(&journal, format!("reason compact\nhead {}\n", new.as_hex())).unwrap();
+
+        let root = tmp.path().to_path_buf();
+        let replay = std::thread::spawn(move || Refs::new(root).replay_journal().unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!replay.is_finished());
+
+        // It finishes, removes its journal and releases the lock
+        refs.write_head(new).unwrap();
+        remove_journal(&journal).unwrap();
+        drop(lock);
+
+        assert!(replay.join().unwrap().is_empty());
+        assert_eq!(refs.read_head().unwrap(), new);
+        // A journal that is already gone counts as removed
+        remove_journal(&journal).unwrap();
+    }
+
+    #[test]
+    fn test_replay_journal_rejects_garbage() {
+        let tmp = TempDir::new().unwrap();
+        let refs = Refs::new(tmp.path());
+
+        fs::write(tmp.path().join(JOURNAL_FILE), "head nothex\n").unwrap();
+        let result = refs.replay_journal();
+        assert!(matches!(result, Err(CtxError::InvalidRef { .. })));
+        assert!(tmp.path().join(JOURNAL_FILE).exists());
+    }
+
     #[test]
     fn test_invalid_ref_content() {
         let tmp = TempDir::new().unwrap();
@@ -0,0 +1,229 @@
+//! File rename detection on compaction.
+//!
+//! Sessions observe writes but never deletions, so a moved file shows up as
+//! a path the repository has not seen before whose content matches a file
+//! it already knows. Compaction pairs each new path with the most similar
+//! known file and records the move as a `File -RenamedTo-> File` edge.
+//! Identical blobs always match; otherwise files with the same extension
+//! must share at least [`RENAME_SIMILARITY`] of their lines.
+
+use crate::types::{Confidence, Edge, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind};
+use crate::{ObjectId, ObjectStore};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Minimum line similarity for a near-identical rename.
+pub(crate) const RENAME_SIMILARITY: f32 = 0.8;
+
+/// A file moved from one path to another.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Rename {
+    /// Path the file was known under.
+    pub from: String,
+    /// Path the file was written to.
+    pub to: String,
+    /// Line similarity of the two versions (1.0 for identical blobs).
+    pub similarity: f32,
+}
+
+/// Pairs each path in `added` with the known file it was most likely moved
+/// from.
+///
+/// Each known path is claimed by at most one rename. Empty files never
+/// match, and non-UTF-8 blobs only match by identical blob.
+pub(crate) fn detect_renames(
+    added: &BTreeMap<String, ObjectId>,
+    known: &BTreeMap<String, ObjectId>,
+    object_store: &ObjectStore,
+) -> Vec<Rename> {
+    let mut claimed = BTreeSet::new();
+    let mut contents: HashMap<ObjectId, Option<String>> = HashMap::new();
+    let mut load = |id: ObjectId| -> Option<String> {
+        contents
+            .entry(id)
+            .or_insert_with(|| {
+                let bytes = object_store.get_blob(id).ok()?;
+                String::from_utf8(bytes).ok().filter(|s| !s.is_empty())
+            })
+            .clone()
+    };
+
+    let mut renames = Vec::new();
+    for (to, blob_id) in added {
+        // An empty file says nothing about where it came from
+        let Some(bytes) = object_store
+            .get_blob(*blob_id)
+            .ok()
+            .filter(|b| !b.is_empty())
+        else {
+            continue;
+        };
+
+        let exact = known
+            .iter()
+            .filter(|(from, id)| *id == blob_id && !claimed.contains(*from))
+            // Prefer a move that keeps the file name
+            .min_by_key(|(from, _)| file_name(from) != file_name(to));
+        if let Some((from, _)) = exact {
+            claimed.insert(from.clone());
+            renames.push(Rename {
+                from: from.clone(),
+                to: to.clone(),
+                similarity: 1.0,
+            });
+            continue;
+        }
+
+        let Ok(content) = String::from_utf8(bytes) else {
+            continue;
+        };
+        let mut best: Option<(&String, f32)> = None;
+        for (from, id) in known {
+            if claimed.contains(from) || extension(from) != extension(to) {
+                continue;
+            }
+            let Some(old) = load(*id) else {
+                continue;
+            };
+            let score = similarity(&old, &content);
+            if score >= RENAME_SIMILARITY && best.map_or(true, |(_, s)| score > s) {
+                best = Some((from, score));
+            }
+        }
+        if let Some((from, similarity)) = best {
+            claimed.insert(from.clone());
+            renames.push(Rename {
+                from: from.clone(),
+                to: to.clone(),
+                similarity,
+            });
+        }
+    }
+    renames
+}
+
+/// `RenamedTo` edges for `renames`.
+///
+/// The edges cite no blob: a move stays true when either file is edited
+/// later, so it must not be superseded with the content it was read from.
+pub(crate) fn rename_edges(renames: &[Rename], commit_id: ObjectId) -> Vec<Edge> {
+    renames
+        .iter()
+        .map(|rename| Edge {
+            from: NodeId {
+                kind: NodeKind::File,
+                id: rename.from.clone(),
+            },
+            to: NodeId {
+                kind: NodeKind::File,
+                id: rename.to.clone(),
+            },
+            label: EdgeLabel::RenamedTo,
+            weight: None,
+            evidence: Evidence {
+                commit_id,
+                tool: EvidenceTool::Parser,
+                confidence: if rename.similarity >= 1.0 {
+                    Confidence::High
+                } else {
+                    Confidence::Medium
+                },
+                span: None,
+                blob_id: None,
+                condition: None,
+            },
+        })
+        .collect()
+}
+
+/// Share of lines two texts have in common, counting repeated lines
+/// (Dice coefficient over line multisets).
+fn similarity(a: &str, b: &str) -> f32 {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for line in a.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in b.lines() {
+        if let Some(count) = counts.get_mut(line).filter(|c| **c > 0) {
+            *count -= 1;
+            common += 1;
+        }
+    }
+
+    let total = a.lines().count() + b.lines().count();
+    if total == 0 {
+        return 0.0;
+    }
+    (2 * common) as f32 / total as f32
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn extension(path: &str) -> Option<&str> {
+    file_name(path).rsplit_once('.').map(|(_, ext)| ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn paths(store: &ObjectStore, files: &[(&str, &str)]) -> BTreeMap<String, ObjectId> {
+        files
+            .iter()
+            .map(|(path, content)| {
+                (
+                    path.to_string(),
+                    store.put_blob(content.as_bytes()).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("a\nb\nc\n", "a\nb\nc\n"), 1.0);
+        assert_eq!(similarity("a\nb\n", "c\nd\n"), 0.0);
+        assert_eq!(similarity("a\na\nb\n", "a\nb\nb\n"), 2.0 * 2.0 / 6.0);
+        assert_eq!(similarity("", ""), 0.0);
+    }
+
+    #[test]
+    fn test_detect_renames() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let body: String = (0..10).map(|i| format!("fn f{}() {{}}\n", i)).collect();
+        let known = paths(
+            &store,
+            &[
+                ("src/util.rs", body.as_str()),
+                ("src/copy.rs", "fn graph() {}\n"),
+                ("src/graph.rs", "fn graph() {}\n"),
+                ("src/other.rs", "fn unrelated() {}\n"),
+                ("src/blank.rs", ""),
+            ],
+        );
+        let edited = format!("{}fn extra() {{}}\n", body);
+        let added = paths(
+            &store,
+            &[
+                ("src/graph/graph.rs", "fn graph() {}\n"),
+                ("src/helpers.rs", edited.as_str()),
+                ("src/fresh.rs", "fn fresh() {}\n"),
+                ("src/empty.rs", ""),
+            ],
+        );
+
+        let renames = detect_renames(&added, &known, &store);
+        assert_eq!(renames.len(), 2);
+        // Identical content prefers the candidate with the same file name
+        assert_eq!(renames[0].from, "src/graph.rs");
+        assert_eq!(renames[0].to, "src/graph/graph.rs");
+        assert_eq!(renames[0].similarity, 1.0);
+        assert_eq!(renames[1].from, "src/util.rs");
+        assert_eq!(renames[1].to, "src/helpers.rs");
+        assert!(renames[1].similarity >= RENAME_SIMILARITY);
+    }
+}
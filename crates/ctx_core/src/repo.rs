@@ -1,19 +1,84 @@
 //! Repository handle providing the main CTX API.
 
-use crate::config::{CleanupReport, StaleSessionConfig, StaleSessionStatus};
-use crate::error::{CtxError, Result};
-use crate::index::Index;
-use crate::refs::Refs;
+use crate::audit::{AuditLog, AuditedChunk, RetrievalRecord, AUDIT_FILE, AUDIT_KEY_FILE};
+use crate::backup::{self, BackupInfo, RestoreReport};
+use crate::bench::{BenchQuery, BenchReport};
+use crate::capabilities::Capabilities;
+use crate::clone::{self, CloneReport};
+use crate::config::{CleanupReport, ExpiredSessionPolicy, StaleSessionConfig, StaleSessionStatus};
+use crate::decision::{Decision, DecisionLog};
+use crate::dry_run::{DryRunReport, ScratchDir};
+use crate::error::{Capability, CtxError, Result};
+use crate::events::{EventKind, EventLog, EventPage, EVENTS_FILE};
+use crate::fact::{Fact, FactSet};
+use crate::feedback::{Feedback, FeedbackLog};
+use crate::glossary::{Glossary, GlossaryCandidate, GlossaryEntry, GlossarySource};
+use crate::hooks::{self, HookEvent};
+use crate::ignore::IgnoreRules;
+use crate::index::{FrecencyEntry, Index};
+use crate::large_file::{self, ContentLimits, FileContent};
+use crate::metrics::{HistogramMetric, Metrics, SharedMetrics, Timer};
+use crate::notes::{Annotation, NoteTable};
+use crate::pack_record::{CitedChunk, PackHistoryEntry, PackRecord};
+use crate::policy::ExecPolicy;
+use crate::progress::Progress;
+use crate::prune::{self, PruneReport, PruneUndoReport};
+use crate::qa::{QaLog, QaPair};
+use crate::refs::{RefUpdate, ReflogEntry, Refs, TAGS_PREFIX};
+use crate::rename::{self, Rename};
 use crate::session::Session;
+use crate::session_summary::{
+    ActorActivity, CommandSummaryProvider, HeuristicSummaryProvider, SessionDigest, SessionSummary,
+    SummaryProvider,
+};
 use crate::staging;
-use crate::types::{Commit, CommitType, Tree};
+use crate::status::{
+    CargoStatus, HeadStatus, IndexFreshness, LockStatus, ObjectStatus, SessionStatus, StatusReport,
+};
+use crate::summary::{
+    CommandSummarizer, HeuristicSummarizer, SummarizeReport, Summarizer, Summary, SummaryTable,
+};
+use crate::transcript::{Transcript, TranscriptRetention};
+use crate::types::{
+    AgentIdentity, Commit, CommitType, EdgeBatch, EdgeLabel, Metadata, NarrativeRef, NodeId,
+    NodeKind, Observation, Tree, WorkCommit,
+};
+use crate::workspace::{self, RepoScope};
 use crate::{ObjectId, ObjectStore};
 use fs2::FileExt;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tracing::warn;
+use tracing::{debug, debug_span, warn};
+
+/// Packs whose inclusions are buffered before they're written to the
+/// frecency table.
+const ACCESS_FLUSH_PACKS: usize = 32;
+
+/// Pack inclusions not yet written to the frecency table.
+#[derive(Debug, Default)]
+struct PendingAccess {
+    /// Packs counted since the last flush.
+    packs: usize,
+    /// Accesses by path.
+    paths: BTreeMap<String, FrecencyEntry>,
+}
+
+impl PendingAccess {
+    /// Counts one access to each of `paths` at `now`; duplicates count once.
+    fn add(&mut self, paths: impl IntoIterator<Item = String>, now: u64) {
+        let unique: BTreeSet<String> = paths.into_iter().collect();
+        for path in unique {
+            let entry = self.paths.entry(path).or_default();
+            entry.access_count = entry.access_count.saturating_add(1);
+            entry.last_access_unix = entry.last_access_unix.max(now);
+        }
+    }
+}
 
 /// CTX repository handle.
 ///
@@ -26,13 +91,47 @@ pub struct CtxRepo {
     /// Reference management.
     refs: Refs,
     /// Index for fast lookups (lazy-loaded).
-    index: Option<Index>,
+    index: RwLock<Option<Index>>,
     /// Active session (if any).
     active_session: Option<Session>,
     /// Session lock guard (held while session is active to prevent concurrent access).
     session_lock: Option<LockGuard>,
     /// Time provider for testing (None = use system time).
     time_provider: Option<std::sync::Arc<dyn Fn() -> i64 + Send + Sync>>,
+    /// Identity recorded on new commits and sessions (None = unattributed).
+    identity: Option<AgentIdentity>,
+    /// Policy applied to every external command the repository runs.
+    exec_policy: ExecPolicy,
+    /// Retrieval audit settings, loaded once at open.
+    audit_config: crate::audit::AuditConfig,
+    /// Key audited queries are hashed with, loaded on first use.
+    audit_key: std::sync::OnceLock<[u8; 32]>,
+    capabilities: Vec<Capabilities>,
+    /// Paths that are never observed, analyzed or retrieved.
+    ignore_rules: IgnoreRules,
+    /// Limits on the file content observations store.
+    content_limits: ContentLimits,
+    /// Roots of the nested repositories federated retrieval also queries.
+    children: Vec<PathBuf>,
+    /// Sink for object store, pack and index metrics.
+    metrics: SharedMetrics,
+    /// Scratch directory of the dry run in progress, if any.
+    dry_run: Option<PathBuf>,
+    /// Scratch directory of the historical view in progress, if any.
+    view: Option<PathBuf>,
+    /// Automatic GC run by the last compaction, until taken.
+    auto_gc_report: Option<crate::gc::AutoGcReport>,
+    /// Pack inclusions counted for frecency but not yet written, so
+    /// building a pack doesn't open a write transaction.
+    pending_access: Mutex<PendingAccess>,
+}
+
+impl Drop for CtxRepo {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_access() {
+            warn!(error = %e, "Failed to record pack inclusions for frecency");
+        }
+    }
 }
 
 impl CtxRepo {
@@ -60,20 +159,102 @@ impl CtxRepo {
             )));
         }
 
-        let object_store = ObjectStore::new(ctx_dir.join("objects"));
+        let config = crate::config::Config::load(&ctx_dir)?;
+        let storage = config.storage;
+        let object_store = ObjectStore::new(ctx_dir.join("objects")).configure(&storage)?;
         let refs = Refs::new(&ctx_dir);
+        let exec_policy = load_exec_policy(&ctx_dir)?;
+        let ignore_rules = load_ignore_rules(&root, &ctx_dir)?;
+        let content_limits = storage.content_limits();
+
+        // Finish a ref transition interrupted by a crash
+        let replayed = refs.replay_journal()?;
+        if !replayed.is_empty() {
+            warn!(
+                updates = replayed.len(),
+                "Completed interrupted ref transaction"
+            );
+        }
 
         Ok(Self {
             root,
             object_store,
             refs,
-            index: None,
+            index: RwLock::new(None),
             active_session: None,
             session_lock: None,
             time_provider: None,
+            identity: None,
+            exec_policy,
+            audit_config: config.audit,
+            audit_key: std::sync::OnceLock::new(),
+            capabilities: Vec::new(),
+            ignore_rules,
+            content_limits,
+            children: Vec::new(),
+            metrics: crate::metrics::noop(),
+            dry_run: None,
+            view: None,
+            auto_gc_report: None,
+            pending_access: Mutex::default(),
         })
     }
 
+    /// Opens the repository covering `path` within `scope`.
+    ///
+    /// Unlike [`CtxRepo::open`], `path` may be any directory inside the
+    /// repository: the nearest ancestor containing `.ctx` is used, or the
+    /// outermost one for [`RepoScope::Workspace`]. Scopes that include
+    /// children record the repositories nested below the root, which
+    /// [`CtxRepo::build_federated_pack`] queries alongside it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no repository contains `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, RepoScope};
+    ///
+    /// let repo = CtxRepo::open_with_scope("crates/core/src", RepoScope::Workspace).unwrap();
+    /// println!("{} child repositories", repo.child_repos().len());
+    /// ```
+    pub fn open_with_scope(path: impl AsRef<Path>, scope: RepoScope) -> Result<Self> {
+        let path = path.as_ref();
+        let start = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let root = match scope {
+            RepoScope::Workspace => workspace::find_outermost_root(&start),
+            RepoScope::Nearest | RepoScope::WithChildren => workspace::find_repo_root(&start),
+        }
+        .ok_or_else(|| {
+            CtxError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Not inside a CTX repository: {}", path.display()),
+            ))
+        })?;
+
+        let mut repo = Self::open(&root)?;
+        if scope.includes_children() {
+            repo.children = workspace::find_child_roots(&root);
+        }
+        Ok(repo)
+    }
+
+    /// Returns the roots of the nested repositories this handle federates.
+    ///
+    /// Empty unless the handle was opened with a scope that includes
+    /// children.
+    pub fn child_repos(&self) -> &[PathBuf] {
+        &self.children
+    }
+
+    /// Returns the root of the nearest repository enclosing this one.
+    pub fn parent_repo(&self) -> Option<PathBuf> {
+        let root = fs::canonicalize(&self.root).unwrap_or_else(|_| self.root.clone());
+        workspace::find_parent_root(&root)
+    }
+
     /// Sets a custom time provider for testing.
     ///
     /// This allows injecting controlled time for testing stale session detection
@@ -87,6 +268,128 @@ impl CtxRepo {
         self
     }
 
+    /// Reports object store traffic, pack build time and index query
+    /// latency to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.object_store.set_metrics(metrics.clone());
+        if let Some(index) = self.index_slot().as_mut() {
+            index.set_metrics(metrics.clone());
+        }
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets the identity recorded on commits and sessions created through this handle.
+    pub fn with_identity(mut self, identity: AgentIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Replaces the identity recorded on new commits and sessions.
+    ///
+    /// Does not affect a session that is already active.
+    pub fn set_identity(&mut self, identity: Option<AgentIdentity>) {
+        self.identity = identity;
+    }
+
+    /// Returns the identity recorded on new commits and sessions.
+    pub fn identity(&self) -> Option<&AgentIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Replaces the policy for external commands.
+    ///
+    /// By default the policy comes from `[exec]` in `.ctx/config.toml` and
+    /// audits to `.ctx/logs/exec.jsonl`.
+    pub fn with_exec_policy(mut self, policy: ExecPolicy) -> Self {
+        self.exec_policy = policy;
+        self
+    }
+
+    /// Sets the callback that confirms commands when the policy mode is
+    /// `confirm`.
+    pub fn with_exec_prompt(
+        mut self,
+        prompt: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.exec_policy = std::mem::take(&mut self.exec_policy).with_prompt(prompt);
+        self
+    }
+
+    /// Returns the policy applied to external commands.
+    pub fn exec_policy(&self) -> &ExecPolicy {
+        &self.exec_policy
+    }
+
+    /// Replaces the ignore rules.
+    ///
+    /// By default they are `storage.ignore` from `.ctx/config.toml`
+    /// followed by the repository's `.ctxignore`, read when it is opened.
+    pub fn with_ignore_rules(mut self, rules: IgnoreRules) -> Self {
+        self.ignore_rules = rules;
+        self
+    }
+
+    /// Returns the rules for paths that are never observed, analyzed or
+    /// retrieved.
+    pub fn ignore_rules(&self) -> &IgnoreRules {
+        &self.ignore_rules
+    }
+
+    /// Replaces the limits on stored file content.
+    ///
+    /// By default they come from `[storage]` in `.ctx/config.toml`.
+    pub fn with_content_limits(mut self, limits: ContentLimits) -> Self {
+        self.content_limits = limits;
+        self
+    }
+
+    /// Returns the limits on the file content observations store.
+    pub fn content_limits(&self) -> &ContentLimits {
+        &self.content_limits
+    }
+
+    /// Restricts what this handle may do, for handing it to an agent.
+    ///
+    /// Refused operations return [`CtxError::PermissionDenied`]; see
+    /// [`Capabilities`] for what each capability covers. Restrictions only
+    /// add up: restricting an already restricted handle can't give back
+    /// anything an earlier restriction took away.
+    pub fn restricted(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities.push(capabilities);
+        self
+    }
+
+    /// Returns the restrictions on this handle, oldest first; an operation
+    /// must be allowed by each of them. Empty for an unrestricted handle.
+    pub fn capabilities(&self) -> &[Capabilities] {
+        &self.capabilities
+    }
+
+    /// Returns `PermissionDenied` unless every restriction on this handle
+    /// allows `capability` for `target`.
+    fn require(&self, capability: Capability, target: &str) -> Result<()> {
+        self.capabilities
+            .iter()
+            .try_for_each(|caps| caps.check(capability, target))
+    }
+
+    /// Returns true if this handle may see the content of `path`.
+    pub(crate) fn can_read(&self, path: &str) -> bool {
+        self.capabilities.iter().all(|caps| caps.can_read(path))
+    }
+
+    /// Returns `PathIgnored` if an ignore rule matches `path`.
+    fn check_not_ignored(&self, path: &str) -> Result<()> {
+        match self.ignore_rules.ignored_by(path) {
+            Some(pattern) => Err(CtxError::PathIgnored {
+                path: path.to_string(),
+                pattern: pattern.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Initializes a new CTX repository.
     ///
     /// Creates the .ctx directory structure and initial commit.
@@ -122,29 +425,64 @@ impl CtxRepo {
         fs::create_dir_all(ctx_dir.join("narrative/log"))?;
         fs::create_dir_all(ctx_dir.join("narrative/tasks"))?;
         fs::create_dir_all(ctx_dir.join("index"))?;
+        fs::create_dir_all(ctx_dir.join("hooks"))?;
 
         // Create default config
         let config = r#"# CTX Configuration
-[repository]
-version = "1"
-
-[ingestion]
-snapshot_on_read = true
-extract_on_read = true
-parse_diagnostics = true
+#
+# `ctx config list` shows every setting. Each key can be overridden by an
+# environment variable named after it, e.g. CTX_GC_GRACE_PERIOD_DAYS.
+
+[gc]
+# Days to keep unreferenced objects before GC deletes them
+grace_period_days = 7
+# Collect after a compaction once this many commits piled up since the
+# last GC, or the store grew past auto_objects or auto_size_mb
+auto_gc = true
+auto_commits = 50
 
 [session]
-idle_timeout_hours = 24
-stale_timeout_days = 7
+# Hours idle before asking whether to continue a stale session
+stale_session_threshold_hours = 24
+# Hours idle before `ctx maintenance` compacts a stale session
+auto_compact_threshold_hours = 168
+# Conversation turns recorded by agents: kept with the session's commit
+# ("keep"), dropped on compaction ("session"), or not recorded ("off")
+transcripts = "keep"
+# Collapse repeated reads of the same content, and repeated runs of the
+# same command, within a step into one observation with a count
+dedup_reads = true
+dedup_commands = true
+# What starting a session, `ctx maintenance` and `ctx stage expire` do with
+# a session staged past auto_compact_threshold_hours by a process that has
+# exited: "compact", "abandon", or "off"
+expired_sessions = "compact"
+
+[audit]
+# Record every prompt pack built, with its query, chunks and requesting
+# agent, in .ctx/logs/retrievals.jsonl (read with `ctx audit export`)
+retrievals = true
+# Keep only a hash of each query instead of its text, keyed with the
+# repository's random .ctx/audit.key
+redact_queries = false
+
+[backup]
+# Backups of refs and the narrative kept in .ctx/backups, one taken per
+# compaction (0 takes none; `ctx restore` puts one back)
+keep = 10
 "#;
         fs::write(ctx_dir.join("config.toml"), config)?;
 
         // Create .gitignore for rebuildable content
         let gitignore = r#"# CTX rebuildable indexes
 index/
+cache/
 DERIVED/
 LOCK
+REFS_LOCK
 *.tmp
+# Key for hashing queries in the retrieval audit log
+audit.key
 "#;
         fs::write(ctx_dir.join(".gitignore"), gitignore)?;
 
@@ -176,6 +514,8 @@ to store and retrieve context across sessions.
         let object_store = ObjectStore::new(ctx_dir.join("objects"));
         let refs = Refs::new(&ctx_dir);
 
+        object_store.rebuild_filter()?;
+
         // Create empty tree
         let empty_tree = Tree::new(vec![]);
         let tree_id = object_store.put_typed(&empty_tree)?;
@@ -196,23 +536,55 @@ to store and retrieve context across sessions.
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
 
         let commit_id = object_store.put_typed(&initial_commit)?;
 
         // Set HEAD and refs/main
-        refs.write_head(commit_id)?;
-        refs.write_ref("main", commit_id)?;
+        refs.transaction(
+            &[
+                RefUpdate::Head(commit_id),
+                RefUpdate::Ref("main".to_string(), commit_id),
+            ],
+            "init",
+        )?;
+        let exec_policy = load_exec_policy(&ctx_dir)?;
+        let ignore_rules = load_ignore_rules(&root, &ctx_dir)?;
+        let config = crate::config::Config::load(&ctx_dir)?;
+        let storage = config.storage;
+        let content_limits = storage.content_limits();
+        let object_store = object_store.configure(&storage)?;
 
         Ok(Self {
             root,
             object_store,
             refs,
-            index: None,
+            index: RwLock::new(None),
             active_session: None,
             session_lock: None,
             time_provider: None,
+            identity: None,
+            exec_policy,
+            audit_config: config.audit,
+            audit_key: std::sync::OnceLock::new(),
+            capabilities: Vec::new(),
+            ignore_rules,
+            content_limits,
+            children: Vec::new(),
+            metrics: crate::metrics::noop(),
+            dry_run: None,
+            view: None,
+            auto_gc_report: None,
+            pending_access: Mutex::default(),
         })
     }
 
@@ -229,20 +601,51 @@ to store and retrieve context across sessions.
     /// Returns a reference to the content-addressed object store.
     ///
     /// Use this to directly access stored objects when you already have ObjectIds.
+    /// This is raw access for the host: it bypasses the handle's
+    /// [capabilities](CtxRepo::restricted), so code acting for an agent
+    /// should use [`CtxRepo::checked_object_store`] instead.
     pub fn object_store(&self) -> &ObjectStore {
         &self.object_store
     }
 
     /// Returns a mutable reference to the object store.
+    ///
+    /// Like [`CtxRepo::object_store`], this bypasses the handle's
+    /// capabilities.
     pub fn object_store_mut(&mut self) -> &mut ObjectStore {
         &mut self.object_store
     }
 
     /// Returns a reference to the refs manager.
+    ///
+    /// Like [`CtxRepo::object_store`], this bypasses the handle's
+    /// capabilities; see [`CtxRepo::checked_refs`].
     pub fn refs(&self) -> &Refs {
         &self.refs
     }
 
+    /// Returns the object store if the handle may commit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PermissionDenied` if the handle can't commit, since the
+    /// store can write and delete objects behind the handle's checks.
+    pub fn checked_object_store(&self) -> Result<&ObjectStore> {
+        self.require(Capability::Commit, "object store")?;
+        Ok(&self.object_store)
+    }
+
+    /// Returns the refs manager if the handle may commit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PermissionDenied` if the handle can't commit, since the
+    /// refs manager can move HEAD and every other ref.
+    pub fn checked_refs(&self) -> Result<&Refs> {
+        self.require(Capability::Commit, "refs")?;
+        Ok(&self.refs)
+    }
+
     /// Returns the current HEAD commit ID.
     ///
     /// # Errors
@@ -267,6 +670,9 @@ to store and retrieve context across sessions.
 
     /// Returns a NarrativeSpace for this repository.
     ///
+    /// On a handle that can't commit, the space is
+    /// [read-only](crate::narrative::NarrativeSpace::read_only).
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -277,7 +683,19 @@ to store and retrieve context across sessions.
     /// ns.ensure_structure().unwrap();
     /// ```
     pub fn narrative(&self) -> crate::narrative::NarrativeSpace {
-        crate::narrative::NarrativeSpace::new(self.ctx_dir())
+        // Config warnings are reported where the config is loaded for its
+        // own sake; narrative access is too frequent to repeat them
+        let document_types = crate::config::Config::load_with_warnings(&self.ctx_dir())
+            .map(|(config, _)| config.documents)
+            .unwrap_or_default();
+        // A historical view reads the narrative it materialized instead
+        let dir = self.view.clone().unwrap_or_else(|| self.ctx_dir());
+        let narrative =
+            crate::narrative::NarrativeSpace::new(dir).with_document_types(document_types);
+        match self.require(Capability::Commit, "narrative") {
+            Ok(()) => narrative,
+            Err(_) => narrative.read_only(),
+        }
     }
 
     /// Creates a new commit with the given message and optional narrative refs.
@@ -306,6 +724,12 @@ to store and retrieve context across sessions.
         narrative_refs: Option<Vec<crate::types::NarrativeRef>>,
         role: &str,
     ) -> Result<ObjectId> {
+        self.require(Capability::Commit, "commit")?;
+        self.run_hooks(
+            HookEvent::PreCommit,
+            serde_json::json!({ "message": message }),
+        )?;
+
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("system time before Unix epoch")
@@ -320,7 +744,12 @@ to store and retrieve context across sessions.
             Some(r) => r,
             None => {
                 let ns = self.narrative();
-                ns.snapshot_changed(&self.object_store, &parent_commit.narrative_refs, role)?
+                ns.snapshot_changed(
+                    &self.object_store,
+                    &parent_commit.narrative_refs,
+                    role,
+                    &self.ignore_rules,
+                )?
             }
         };
 
@@ -335,881 +764,4780 @@ to store and retrieve context across sessions.
             cargo_snapshot: parent_commit.cargo_snapshot,
             rust_snapshot: parent_commit.rust_snapshot,
             diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: parent_commit.glossary,
+            decisions: parent_commit.decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
             commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
         };
 
         let commit_id = self.object_store.put_typed(&new_commit)?;
 
         // Update HEAD and refs/main
-        self.refs.write_head(commit_id)?;
-        self.refs.write_ref("main", commit_id)?;
+        self.advance_main(commit_id, &new_commit.message)?;
+
+        self.record_event(EventKind::Committed {
+            commit_id: commit_id.as_hex(),
+            message: message.to_string(),
+        });
+        self.notify_commit(commit_id, message);
 
         Ok(commit_id)
     }
 
-    /// Returns the index, creating it if it doesn't exist.
+    /// Runs `op` as a trial and reports what it would have changed.
     ///
-    /// The index is lazily loaded on first access.
+    /// `op` sees the repository as usual, but writes objects to a scratch
+    /// store, moves scratch copies of the refs and updates a scratch copy of
+    /// the index; all are discarded afterwards. Hooks don't run, no events
+    /// are recorded, and a session compacted by `op` stays active. See
+    /// [`DryRunReport`] for what's reported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let (commit_id, report) = repo
+    ///     .dry_run(|repo| repo.commit("Update docs", None, "user"))
+    ///     .unwrap();
+    /// println!("{} would write {} objects", commit_id, report.objects.len());
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the index can't be loaded or created.
-    pub fn index(&mut self) -> Result<&Index> {
-        if self.index.is_none() {
-            let index_path = self.ctx_dir().join("index/index.redb");
-
-            // Try to open existing index
-            match Index::open(&index_path)? {
-                Some(idx) => self.index = Some(idx),
-                None => {
-                    // Rebuild if missing
-                    let head = self.head_id()?;
-                    let idx = Index::rebuild_from_objects(&index_path, &self.object_store, head)?;
-                    self.index = Some(idx);
-                }
-            }
+    /// Returns [`CtxError::InvalidArgument`] if a dry run is already in
+    /// progress, `op`'s error if it fails, or an error if the scratch state
+    /// can't be set up or compared.
+    pub fn dry_run<T>(
+        &mut self,
+        op: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<(T, DryRunReport)> {
+        if self.dry_run.is_some() {
+            return Err(CtxError::InvalidArgument(
+                "a dry run is already in progress".to_string(),
+            ));
+        }
+
+        let scratch = ScratchDir::create()?;
+        let index_copy = Index::open_copy(&self.index_path(), &scratch.path().join("index.redb"))?;
+        let scratch_store = self.object_store.scratch(scratch.path().join("objects"));
+        let scratch_refs = self.refs.copy_to(&scratch.path().join("refs"))?;
+
+        let object_store = std::mem::replace(&mut self.object_store, scratch_store);
+        let refs = std::mem::replace(&mut self.refs, scratch_refs);
+        let index = self.index_slot().take();
+        self.dry_run = Some(scratch.path().to_path_buf());
+        // Without a copy, the index is rebuilt in the scratch directory
+        if let Some(copy) = index_copy {
+            self.set_index(copy);
         }
 
-        Ok(self.index.as_ref().unwrap())
+        let result = op(self);
+
+        self.dry_run = None;
+        *self.index_slot() = index;
+        let scratch_store = std::mem::replace(&mut self.object_store, object_store);
+        let scratch_refs = std::mem::replace(&mut self.refs, refs);
+
+        let value = result?;
+        let report = DryRunReport::collect(
+            &self.object_store,
+            &self.refs,
+            &scratch_store,
+            &scratch_refs,
+        )?;
+        Ok((value, report))
     }
 
-    /// Gets a mutable reference to the index, loading it if needed.
+    /// Runs `op` against the repository as it was at `commit`.
+    ///
+    /// `op` sees scratch refs with HEAD at `commit`, an index rebuilt from
+    /// it, and the narrative files as last committed at or before it, so
+    /// trees, edges, decisions and narrative all read as of that commit.
+    /// Sessions, config and the object store are the current ones; the
+    /// scratch state is discarded afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, RetrievalConfig};
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let pack = repo
+    ///     .at_commit("HEAD~3", |repo| {
+    ///         repo.build_pack("session handling", &RetrievalConfig::default())
+    ///     })
+    ///     .unwrap();
+    /// println!("{}", pack.to_text());
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the index can't be loaded or rebuilt.
-    pub fn index_mut(&mut self) -> Result<&mut Index> {
-        if self.index.is_none() {
-            let index_path = self.ctx_dir().join("index/index.redb");
-
-            // Try to open existing index
-            match Index::open(&index_path)? {
-                Some(idx) => self.index = Some(idx),
-                None => {
-                    // Rebuild if missing
-                    let head = self.head_id()?;
-                    let idx = Index::rebuild_from_objects(&index_path, &self.object_store, head)?;
-                    self.index = Some(idx);
+    /// Returns [`CtxError::InvalidArgument`] if a dry run or another view is
+    /// in progress, an error if `commit` doesn't resolve or the view can't
+    /// be set up, or `op`'s error.
+    pub fn at_commit<T>(&mut self, commit: &str, op: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        if self.dry_run.is_some() || self.view.is_some() {
+            return Err(CtxError::InvalidArgument(
+                "a dry run or historical view is already in progress".to_string(),
+            ));
+        }
+        let commit_id = self.resolve_commit(commit)?;
+        let _: Commit = self.object_store.get_typed(commit_id)?;
+
+        let scratch = ScratchDir::create()?;
+        let scratch_refs = self.refs.copy_to(&scratch.path().join("refs"))?;
+        scratch_refs.write_head(commit_id)?;
+
+        let refs = std::mem::replace(&mut self.refs, scratch_refs);
+        let index = self.index_slot().take();
+        // Without an index in the scratch directory, it's rebuilt from HEAD
+        self.view = Some(scratch.path().to_path_buf());
+
+        let result = self
+            .materialize_narrative(&scratch.path().join("narrative"))
+            .and_then(|()| op(self));
+
+        self.view = None;
+        *self.index_slot() = index;
+        self.refs = refs;
+        result
+    }
+
+    /// Writes the newest committed version of each narrative file reachable
+    /// from HEAD under `dest`.
+    fn materialize_narrative(&self, dest: &Path) -> Result<()> {
+        let mut seen = HashSet::new();
+        for entry in self.log(crate::log::LogFilter::default())? {
+            let (_, commit) = entry?;
+            for narrative_ref in commit.narrative_refs {
+                if !seen.insert(narrative_ref.path.clone()) {
+                    continue;
                 }
+                let path = dest.join(&narrative_ref.path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, self.object_store.get_blob(narrative_ref.blob_id)?)?;
             }
         }
-
-        Ok(self.index.as_mut().unwrap())
+        Ok(())
     }
 
-    /// Rebuilds the index from scratch.
-    ///
-    /// This is useful if the index is corrupted or out of date.
+    /// Walks commit history from HEAD, yielding commits that pass `filter`.
     ///
     /// # Errors
     ///
-    /// Returns an error if the index can't be rebuilt.
-    pub fn rebuild_index(&mut self) -> Result<()> {
-        let index_path = self.ctx_dir().join("index/index.redb");
+    /// Returns an error if HEAD can't be read. Errors loading individual
+    /// commits are yielded by the iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CommitTypeFilter, CtxRepo, LogFilter};
+    ///
+    /// # fn main() -> ctx_core::Result<()> {
+    /// let repo = CtxRepo::open(".")?;
+    /// let filter = LogFilter {
+    ///     commit_type: Some(CommitTypeFilter::Normal),
+    ///     path: Some("src/main.rs".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// for entry in repo.log(filter)? {
+    ///     let (id, commit) = entry?;
+    ///     println!("{} {}", id, commit.message);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn log(&self, filter: crate::log::LogFilter) -> Result<crate::log::CommitLog<'_>> {
         let head = self.head_id()?;
+        Ok(crate::log::CommitLog::new(&self.object_store, head, filter))
+    }
 
-        // Drop existing index handle
-        self.index = None;
+    /// Points the tag `name` at `commit`, writing `refs/tags/<name>`.
+    ///
+    /// Tags name milestones such as a release: anything that takes a
+    /// commit through [`CtxRepo::resolve_commit`] accepts the tag's name.
+    /// With `force`, an existing tag is moved; otherwise it's an error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// let repo = CtxRepo::open(".").unwrap();
+    /// repo.tag("v1-release-context", repo.head_id().unwrap(), false)
+    ///     .unwrap();
+    /// let commit = repo.resolve_commit("v1-release-context").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `name` isn't a valid tag name or the
+    /// tag already points at another commit, or an error if `commit`
+    /// isn't a stored commit.
+    pub fn tag(&self, name: &str, commit: ObjectId, force: bool) -> Result<()> {
+        self.require(Capability::Commit, "tag")?;
+        let valid = !name.is_empty()
+            && name != "HEAD"
+            && !name.starts_with(['.', '-'])
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(CtxError::InvalidArgument(format!(
+                "invalid tag name '{}': use letters, digits, '-', '_' and '.'",
+                name
+            )));
+        }
+        let _: Commit = self.object_store.get_typed(commit)?;
+
+        let ref_name = format!("{}{}", TAGS_PREFIX, name);
+        match self.refs.read_ref(&ref_name) {
+            Ok(existing) if existing != commit && !force => {
+                return Err(CtxError::InvalidArgument(format!(
+                    "tag '{}' already points at {}",
+                    name,
+                    &existing.as_hex()[..8]
+                )));
+            }
+            _ => {}
+        }
+        self.refs.transaction(
+            &[RefUpdate::Ref(ref_name, commit)],
+            &format!("tag: {}", name),
+        )
+    }
 
-        // Rebuild
-        let idx = Index::rebuild_from_objects(&index_path, &self.object_store, head)?;
-        self.index = Some(idx);
+    /// Returns every tag and the commit it points at, sorted by name.
+    pub fn tags(&self) -> Result<Vec<(String, ObjectId)>> {
+        self.refs.list_tags()
+    }
 
-        Ok(())
+    /// Returns the reflog of `ref_name` (`HEAD`, `main`, `tags/<name>`...),
+    /// newest first: entry N is the position `<ref_name>@{N}` names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reflog can't be read.
+    pub fn reflog(&self, ref_name: &str) -> Result<Vec<ReflogEntry>> {
+        self.refs.reflog(ref_name)
     }
 
-    /// Starts a new session for the given task.
+    /// Moves HEAD and refs/main back (or forward) to `spec`, typically a
+    /// reflog entry such as `HEAD@{1}`, then rebuilds the index.
     ///
-    /// Creates initial WorkCommit with SessionStart step kind.
-    /// Updates STAGE pointer.
+    /// Commits left behind stay in the reflog, so a reset can itself be
+    /// undone, and GC keeps them until their entries pass
+    /// `gc.reflog_expire_days`.
     ///
     /// # Errors
-    /// Returns error if a session is already active.
-    pub fn start_session(&mut self, task: &str) -> Result<&mut Session> {
-        if self.active_session.is_some() {
-            return Err(CtxError::SessionAlreadyActive(task.to_string()));
+    ///
+    /// Returns [`CtxError::SessionAlreadyActive`] with a session open,
+    /// [`CtxError::InvalidArgument`] if a staged session is pending,
+    /// [`CtxError::RepositoryLocked`] if another process holds the
+    /// repository, or an error if `spec` doesn't resolve to a stored
+    /// commit.
+    pub fn reset(&mut self, spec: &str) -> Result<ObjectId> {
+        self.require(Capability::Commit, "reset")?;
+        if let Some(session) = &self.active_session {
+            return Err(CtxError::SessionAlreadyActive(
+                session.task_description().to_string(),
+            ));
+        }
+        let _lock = self.acquire_lock()?;
+        if self.refs.read_stage()?.is_some() {
+            return Err(CtxError::InvalidArgument(
+                "a staged session is pending; compact or abandon it before resetting".to_string(),
+            ));
         }
 
-        // Acquire lock and store it to keep it alive
-        let lock = self.acquire_lock()?;
+        let commit_id = self.resolve_commit(spec)?;
+        let _: Commit = self.object_store.get_typed(commit_id)?;
+        self.refs.transaction(
+            &[
+                RefUpdate::Head(commit_id),
+                RefUpdate::Ref("main".to_string(), commit_id),
+            ],
+            &format!("reset: moving to {}", spec),
+        )?;
+        self.rebuild_index()?;
+        Ok(commit_id)
+    }
 
-        // Get current HEAD as base
-        let base_commit = self.head_id()?;
+    /// Resolves a commit specification to a commit ID.
+    ///
+    /// Accepts `HEAD`, `HEAD~N` (N first-parent steps back), `<ref>@{N}`
+    /// (where the ref was N moves ago, from the reflog), a ref name such as
+    /// `main`, a tag name, a full 64-character hex ID, or a unique hex
+    /// prefix of at least 4 characters.
+    pub fn resolve_commit(&self, spec: &str) -> Result<ObjectId> {
+        if spec == "HEAD" {
+            return self.head_id();
+        }
+        if let Some(id) = self.refs.resolve_reflog(spec)? {
+            return Ok(id);
+        }
 
-        // Generate session ID
-        let session_id = uuid::Uuid::new_v4().to_string();
+        if let Some(steps) = spec.strip_prefix("HEAD~") {
+            let steps: usize = steps.parse().map_err(|_| {
+                CtxError::InvalidArgument(format!("invalid ancestor count in '{}'", spec))
+            })?;
+            let mut id = self.head_id()?;
+            for _ in 0..steps {
+                let commit: Commit = self.object_store.get_typed(id)?;
+                id = self.object_store.first_parent(&commit).ok_or_else(|| {
+                    CtxError::InvalidArgument(format!("'{}' goes past the root commit", spec))
+                })?;
+            }
+            return Ok(id);
+        }
 
-        // Create session
-        let mut session = Session::new(
-            task.to_string(),
-            base_commit,
-            session_id,
-            self.time_provider.clone(),
-        );
+        if let Ok(id) = self.refs.read_ref(spec) {
+            return Ok(id);
+        }
+        if let Ok(id) = self.refs.read_ref(&format!("{}{}", TAGS_PREFIX, spec)) {
+            return Ok(id);
+        }
 
-        // Create initial WorkCommit (SessionStart)
-        session.flush_step(&self.object_store, &self.refs)?;
+        if spec.len() == 64 {
+            return ObjectId::from_hex(spec);
+        }
 
-        self.active_session = Some(session);
-        self.session_lock = Some(lock);
+        let is_hex = spec.chars().all(|c| c.is_ascii_hexdigit());
+        if spec.len() < 4 || !is_hex {
+            return Err(CtxError::InvalidArgument(format!(
+                "'{}' is not a ref, HEAD~N, or commit ID prefix",
+                spec
+            )));
+        }
 
-        Ok(self.active_session.as_mut().unwrap())
+        let prefix = spec.to_lowercase();
+        let mut matches = self
+            .object_store
+            .list_all_objects()?
+            .into_iter()
+            .map(|(id, _, _)| id)
+            .filter(|id| id.as_hex().starts_with(&prefix))
+            .filter(|id| self.object_store.get_typed::<Commit>(*id).is_ok());
+
+        match (matches.next(), matches.next()) {
+            (Some(id), None) => Ok(id),
+            (None, _) => Err(CtxError::ObjectNotFound(spec.to_string())),
+            (Some(_), Some(_)) => Err(CtxError::InvalidArgument(format!(
+                "commit prefix '{}' is ambiguous",
+                spec
+            ))),
+        }
     }
 
-    /// Compacts the current session into a canonical commit.
+    /// Computes the differences between two commits.
     ///
-    /// Walks staging chain, aggregates work, creates Commit,
-    /// updates HEAD and refs/main, deletes STAGE.
-    pub fn compact_session(&mut self, message: &str) -> Result<ObjectId> {
-        self.compact_session_with_type(message, CommitType::Normal)
+    /// See [`CommitDiff`](crate::diff::CommitDiff) for how files, edges and
+    /// narrative are compared. Files the handle can't read, and edges to or
+    /// from them, are left out.
+    pub fn diff(&self, from: ObjectId, to: ObjectId) -> Result<crate::diff::CommitDiff> {
+        let mut diff = crate::diff::CommitDiff::compute(from, to, &self.object_store)?;
+        let readable = |node: &NodeId| node.kind != NodeKind::File || self.can_read(&node.id);
+        diff.files.retain(|change| self.can_read(&change.path));
+        diff.edges_added
+            .retain(|edge| readable(&edge.from) && readable(&edge.to));
+        diff.edges_removed
+            .retain(|edge| readable(&edge.from) && readable(&edge.to));
+        Ok(diff)
     }
 
-    /// Compacts with a specific commit type.
-    pub fn compact_session_with_type(
-        &mut self,
-        message: &str,
-        commit_type: CommitType,
-    ) -> Result<ObjectId> {
-        let session = self
-            .active_session
-            .as_ref()
-            .ok_or(CtxError::NoActiveSession)?;
-
-        let staging_head = session.staging_head();
-        let base_commit = session.base_commit();
-
-        // Compact staging into canonical commit
-        let commit = staging::compact_staging(
-            staging_head,
-            base_commit,
-            message,
-            commit_type,
-            &self.object_store,
-        )?;
-
-        // Store the commit
-        let commit_id = self.object_store.put_typed(&commit)?;
-
-        // Update HEAD and refs/main
-        self.refs.write_head(commit_id)?;
-        self.refs.write_ref("main", commit_id)?;
-
-        // Delete STAGE
-        self.refs.delete_stage()?;
-
-        // Clear active session and release lock
-        self.active_session = None;
-        self.session_lock = None;
+    /// Compares the graph as of two commits, `from` and `to`.
+    ///
+    /// Unlike [`CtxRepo::diff`], which compares every edge each commit's
+    /// history recorded, this compares the edges live at each commit, so
+    /// edges superseded by a file's later content show as removed. See
+    /// [`diff_graphs`](crate::graph::diff_graphs).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either commit or its history can't be loaded.
+    pub fn graph_diff(&self, from: ObjectId, to: ObjectId) -> Result<crate::graph::GraphDiff> {
+        let before = crate::diff::graph_at(from, &self.object_store)?;
+        let after = crate::diff::graph_at(to, &self.object_store)?;
+        Ok(crate::graph::diff_graphs(&before, &after))
+    }
 
-        Ok(commit_id)
+    /// Writes the files of `commit`'s tree to `dest`, preserving paths.
+    ///
+    /// Reproduces exactly what was recorded at that commit, except files
+    /// the handle can't read. Returns the number of files written.
+    pub fn export_tree(&self, commit: ObjectId, dest: impl AsRef<Path>) -> Result<usize> {
+        crate::export::export_tree_where(&self.object_store, commit, dest.as_ref(), |path| {
+            self.can_read(path)
+        })
     }
 
-    /// Aborts the current session, discarding all work.
+    /// Lists the commits that wrote `path`, newest first.
     ///
-    /// # Warning
+    /// A commit is included when its tree has `path` with content that
+    /// differs from its first parent's. Renames are followed back, so the
+    /// history continues under the file's earlier paths. Use this to find
+    /// when a file last changed before something broke. Commits that wrote
+    /// the file under an earlier name the handle can't read are left out.
     ///
-    /// This operation creates a commit marked as `Abandoned`, which preserves
-    /// the work history for debugging but marks it as intentionally discarded.
-    /// The work is NOT lost (it's committed), but it's marked as abandoned.
-    ///
-    /// If you want to preserve the work for later continuation, use
-    /// `flush_active_session()` instead.
-    ///
-    /// # Arguments
+    /// # Errors
     ///
-    /// * `reason` - Explanation for why the session was aborted
-    pub fn abort_session(&mut self, reason: &str) -> Result<ObjectId> {
-        let message = format!("Aborted: {}", reason);
-        self.compact_session_with_type(&message, CommitType::Abandoned)
+    /// Returns `PermissionDenied` if the handle can't read `path`.
+    pub fn path_history(&self, path: &str) -> Result<Vec<crate::log::PathHistoryEntry>> {
+        let path = path.trim_start_matches("./");
+        self.require(Capability::ReadPath, path)?;
+        // Names the file had, growing as renames are found walking back
+        let mut names = vec![path.to_string()];
+        let mut entries = Vec::new();
+        for entry in self.log(crate::log::LogFilter::default())? {
+            let (id, commit) = entry?;
+            let mut found = None;
+            for name in &names {
+                found = crate::log::path_change(id, &commit, name, &self.object_store)?
+                    .map(|change| (name.clone(), change));
+                if found.is_some() {
+                    break;
+                }
+            }
+            let Some((name, change)) = found else {
+                continue;
+            };
+            if let Some(from) = &change.renamed_from {
+                if !names.contains(from) {
+                    names.push(from.clone());
+                }
+            }
+            if self.can_read(&name) {
+                entries.push(change);
+            }
+        }
+        Ok(entries)
     }
 
-    /// Recovers a session from staging (e.g., after crash).
-    ///
-    /// Returns None if no STAGE pointer exists.
-    pub fn recover_session(&mut self) -> Result<Option<&mut Session>> {
-        if let Some(staging_head) = self.refs.read_stage()? {
-            let session = Session::from_staging(
-                staging_head,
-                &self.object_store,
-                self.time_provider.clone(),
-            )?;
-
-            self.active_session = Some(session);
-            Ok(self.active_session.as_mut())
-        } else {
-            Ok(None)
+    /// Returns the glossary as of HEAD (empty if no terms are defined).
+    pub fn glossary(&self) -> Result<Glossary> {
+        let head: Commit = self.object_store.get_typed(self.head_id()?)?;
+        match head.glossary {
+            Some(id) => self.object_store.get_typed(id),
+            None => Ok(Glossary::default()),
         }
     }
 
-    /// Checks if there's an active session.
-    pub fn has_active_session(&self) -> bool {
-        self.active_session.is_some()
+    /// Defines a glossary term, replacing any existing definition.
+    ///
+    /// Creates a commit holding the updated glossary and `Mentions` edges
+    /// from the term to files named in the definition.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// repo.add_glossary_term("SCC", "strongly connected component used in graph.rs")
+    ///     .unwrap();
+    /// ```
+    pub fn add_glossary_term(&mut self, term: &str, definition: &str) -> Result<ObjectId> {
+        crate::glossary::validate_entry(term, definition)?;
+        let entry = GlossaryEntry {
+            term: term.trim().to_string(),
+            definition: definition.trim().to_string(),
+            source: GlossarySource::Manual,
+            created_at: self.now_unix(),
+        };
+        self.add_glossary_entries(vec![entry])
     }
 
-    /// Returns reference to active session.
-    pub fn active_session(&self) -> Option<&Session> {
-        self.active_session.as_ref()
+    /// Adds several glossary entries in a single commit.
+    pub fn add_glossary_entries(&mut self, entries: Vec<GlossaryEntry>) -> Result<ObjectId> {
+        self.require(Capability::Commit, "glossary")?;
+        if entries.is_empty() {
+            return Err(CtxError::InvalidArgument(
+                "no glossary entries to add".to_string(),
+            ));
+        }
+        let mut glossary = self.glossary()?;
+        let message = match entries.as_slice() {
+            [entry] => format!("Glossary: define {}", entry.term),
+            _ => format!("Glossary: define {} terms", entries.len()),
+        };
+        let added: Vec<String> = entries.iter().map(|e| e.term.clone()).collect();
+        for entry in entries {
+            glossary.upsert(entry);
+        }
+        self.commit_glossary(glossary, &added, message)
     }
 
-    /// Returns mutable reference to active session.
-    pub fn active_session_mut(&mut self) -> Option<&mut Session> {
-        self.active_session.as_mut()
+    /// Creates a narrative document of a configured type and commits it,
+    /// with `Mentions` edges from its `Document` node to the files its
+    /// fields and body name.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `kind` isn't a known document type or
+    /// `fields` don't match its schema.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let fields = BTreeMap::from([
+    ///     ("title".to_string(), "Index lost after crash".to_string()),
+    ///     ("severity".to_string(), "high".to_string()),
+    /// ]);
+    /// let info = repo.create_document("incident", &fields).unwrap();
+    /// println!("Created {}", info.relative_path);
+    /// ```
+    pub fn create_document(
+        &mut self,
+        kind: &str,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<crate::narrative::DocumentInfo> {
+        self.require(Capability::Commit, "create document")?;
+        let narrative = self.narrative();
+        let info = narrative.create_document(kind, fields)?;
+        let content = narrative.read_file(&info.relative_path)?;
+        let title = crate::document::Document::parse(
+            &info.relative_path,
+            &String::from_utf8_lossy(&content),
+        )
+        .and_then(|document| document.title().map(str::to_string))
+        .unwrap_or_else(|| info.relative_path.clone());
+
+        self.commit_document(
+            &info.relative_path,
+            &content,
+            |_, _| Vec::new(),
+            format!("Add {}: {}", kind, title),
+        )?;
+        Ok(info)
     }
 
-    /// Flushes the active session's current step.
+    /// Imports an external document, such as a design doc or RFC, into
+    /// the narrative and commits it.
     ///
-    /// Convenience method that handles the borrowing internally.
-    pub fn flush_active_session(&mut self) -> Result<ObjectId> {
-        let session = self
-            .active_session
-            .as_mut()
-            .ok_or(CtxError::NoActiveSession)?;
-        session.flush_step(&self.object_store, &self.refs)
+    /// `content` is converted according to `format` and written to
+    /// `imports/import_NNNN.md` with its title and `source` as frontmatter,
+    /// replacing an earlier import of the same source. The commit links the
+    /// document to the files it mentions, so packs for related queries can
+    /// reach it. See the [`ImportFormat`](crate::ImportFormat) variants for
+    /// what's accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `content` is a PDF, isn't UTF-8 or has
+    /// no text.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, ImportFormat};
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let content = std::fs::read("../rfcs/0007-storage.md").unwrap();
+    /// let info = repo
+    ///     .import_document("../rfcs/0007-storage.md", &content, ImportFormat::Markdown)
+    ///     .unwrap();
+    /// println!("Imported {}", info.relative_path);
+    /// ```
+    pub fn import_document(
+        &mut self,
+        source: &str,
+        content: &[u8],
+        format: crate::import::ImportFormat,
+    ) -> Result<crate::narrative::DocumentInfo> {
+        self.require(Capability::Commit, "import document")?;
+        let text = crate::import::ImportedText::convert(source, content, format)?;
+        let content = text.render(source);
+        let info = self.narrative().write_import(source, &content)?;
+
+        self.commit_document(
+            &info.relative_path,
+            content.as_bytes(),
+            |_, _| Vec::new(),
+            format!("Import: {}", text.title),
+        )?;
+        Ok(info)
     }
 
-    /// Observes a file write in the active session.
+    /// Searches the narrative for `query`, best matches first.
     ///
-    /// Convenience method that handles the borrowing internally.
-    pub fn observe_file_write(&mut self, path: &str, content: &[u8]) -> Result<ObjectId> {
-        let session = self
-            .active_session
-            .as_mut()
-            .ok_or(CtxError::NoActiveSession)?;
-        session.observe_file_write(path, content, &self.object_store)
-    }
+    /// Covers the narrative files and the last committed version of any
+    /// that were deleted, split into log entries and heading sections (see
+    /// the [`NarrativeHit`](crate::NarrativeHit) fields). Returns at most
+    /// `limit` hits, or `search.max_results` if None, with excerpts of about
+    /// `search.snippet_length` characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `search.enabled` is false.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// let repo = CtxRepo::open(".").unwrap();
+    /// for hit in repo.search_narrative("switch to redb", None).unwrap() {
+    ///     println!("{} {:?}: {}", hit.path, hit.date, hit.excerpt);
+    /// }
+    /// ```
+    pub fn search_narrative(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<crate::narrative_search::NarrativeHit>> {
+        use crate::narrative_search::NarrativeText;
+
+        let config = crate::config::Config::load(&self.ctx_dir())?.search;
+        if !config.enabled {
+            return Err(CtxError::InvalidArgument(
+                "narrative search is disabled (search.enabled = false)".to_string(),
+            ));
+        }
 
-    /// Observes a file read in the active session.
-    pub fn observe_file_read(&mut self, path: &str) -> Result<()> {
-        let session = self
-            .active_session
-            .as_mut()
-            .ok_or(CtxError::NoActiveSession)?;
-        session.observe_file_read(path)
-    }
+        // Newest committed version of each path
+        let mut committed: BTreeMap<String, (ObjectId, u64)> = BTreeMap::new();
+        for entry in self.log(crate::log::LogFilter::default())? {
+            let (_, commit) = entry?;
+            for narrative_ref in commit.narrative_refs {
+                committed
+                    .entry(narrative_ref.path)
+                    .or_insert((narrative_ref.blob_id, commit.timestamp_unix));
+            }
+        }
 
-    /// Observes a file read with content in the active session.
-    ///
-    /// This captures the exact content the agent read, enabling:
-    /// - Temporal reconstruction ("what did the agent see at step 5?")
-    /// - True context for decision analysis
-    /// - Reproducible agent behavior
-    pub fn observe_file_read_with_content(&mut self, path: &str, content: &[u8]) -> Result<()> {
-        let session = self
-            .active_session
-            .as_mut()
-            .ok_or(CtxError::NoActiveSession)?;
-        session.observe_file_read_with_content(path, content, &self.object_store)
-    }
+        let narrative = self.narrative();
+        let mut texts = Vec::new();
+        for path in narrative.list_files()? {
+            if self.ignore_rules.is_ignored(&path) {
+                continue;
+            }
+            let Ok(content) = String::from_utf8(narrative.read_file(&path)?) else {
+                continue;
+            };
+            texts.push(NarrativeText {
+                committed_at: committed.remove(&path).map(|(_, at)| at),
+                path,
+                content,
+            });
+        }
+        // Whatever is left was deleted since it was committed
+        for (path, (blob_id, at)) in committed {
+            if self.ignore_rules.is_ignored(&path) {
+                continue;
+            }
+            if let Ok(content) =
+                crate::narrative::NarrativeSpace::read_from_blob(&self.object_store, blob_id)
+            {
+                texts.push(NarrativeText {
+                    path,
+                    committed_at: Some(at),
+                    content,
+                });
+            }
+        }
 
-    /// Observes a note in the active session.
-    pub fn observe_note(&mut self, note: &str) -> Result<()> {
-        let session = self
-            .active_session
-            .as_mut()
-            .ok_or(CtxError::NoActiveSession)?;
-        session.observe_note(note)
+        let terms = crate::narrative_search::query_terms(query);
+        Ok(crate::narrative_search::search(
+            &texts,
+            &terms,
+            limit.unwrap_or(config.max_results),
+            config.snippet_length,
+        ))
     }
 
-    /// Observes a command in the active session.
-    pub fn observe_command(
-        &mut self,
-        command: &str,
-        exit_code: Option<i32>,
-        output: Option<&[u8]>,
-    ) -> Result<()> {
-        let session = self
-            .active_session
-            .as_mut()
-            .ok_or(CtxError::NoActiveSession)?;
-        session.observe_command(command, exit_code, output, &self.object_store)
+    /// Writes a digest of the week (Monday to Sunday, UTC) containing
+    /// `at_unix` to `digests/week_YYYY-MM-DD.md` in the narrative space
+    /// and commits it, replacing an earlier digest of the same week.
+    ///
+    /// The digest groups the week's sessions by task, lists the tasks
+    /// committed as done, the most changed files and the daily log entries.
+    /// Its `Document` node gets `DerivedFrom` edges to the sessions it
+    /// covers and `Mentions` edges to the files it names.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// use std::time::{SystemTime, UNIX_EPOCH};
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    /// let digest = repo.write_weekly_digest(now).unwrap();
+    /// println!("{}: {} sessions", digest.relative_path(), digest.sessions.len());
+    /// ```
+    pub fn write_weekly_digest(&mut self, at_unix: u64) -> Result<crate::digest::WeeklyDigest> {
+        self.require(Capability::Commit, "weekly digest")?;
+        let narrative = self.narrative();
+        let digest = crate::digest::WeeklyDigest::collect(
+            &self.object_store,
+            self.head_id()?,
+            &narrative,
+            at_unix,
+        )?;
+        let content = digest.render();
+        narrative.write_file(&digest.relative_path(), content.as_bytes())?;
+
+        self.commit_document(
+            &digest.relative_path(),
+            content.as_bytes(),
+            |blob_id, parent_id| digest.session_edges(blob_id, parent_id),
+            format!("Digest: {}", digest.title()),
+        )?;
+        Ok(digest)
     }
 
-    /// Checks if current session is stale.
-    pub fn check_stale_session(&self, config: &StaleSessionConfig) -> StaleSessionStatus {
-        let session = match &self.active_session {
-            Some(s) => s,
-            None => return StaleSessionStatus::NoSession,
-        };
-
-        let idle_secs = session.idle_time().as_secs();
-        let task = session.task_description().to_string();
+    /// Commits the narrative document at `relative_path` with `content`,
+    /// with `Mentions` edges from its node to the files it names and the
+    /// edges `extra_edges` returns for the document's blob and the parent
+    /// commit.
+    fn commit_document(
+        &mut self,
+        relative_path: &str,
+        content: &[u8],
+        extra_edges: impl FnOnce(ObjectId, ObjectId) -> Vec<crate::types::Edge>,
+        message: String,
+    ) -> Result<ObjectId> {
+        let now = self.now_unix();
+        let blob_id = self.object_store.put_blob(content)?;
+        let parent_id = self.head_id()?;
+        let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
 
-        if idle_secs >= config.auto_compact_threshold_secs {
-            StaleSessionStatus::ShouldAutoCompact { task, idle_secs }
-        } else if idle_secs >= config.ask_threshold_secs {
-            StaleSessionStatus::ShouldAsk { task, idle_secs }
+        let known_files = crate::glossary::historical_paths(parent_id, &self.object_store)?;
+        let mut edges =
+            crate::document::Document::parse(relative_path, &String::from_utf8_lossy(content))
+                .map(|document| {
+                    crate::document::mention_edges(&document, blob_id, parent_id, &known_files)
+                })
+                .unwrap_or_default();
+        edges.extend(extra_edges(blob_id, parent_id));
+        let edge_batches = if edges.is_empty() {
+            vec![]
         } else {
-            StaleSessionStatus::Fresh { task, idle_secs }
-        }
-    }
+            vec![self.object_store.put_typed(&EdgeBatch {
+                edges,
+                created_at: now,
+            })?]
+        };
 
-    /// Cleans up sessions that exceed max idle time.
-    pub fn cleanup_stale_sessions(&mut self, max_age: Duration) -> Result<CleanupReport> {
-        let mut report = CleanupReport::default();
+        let commit = Commit {
+            parents: vec![parent_id],
+            timestamp_unix: now,
+            message,
+            root_tree: parent_commit.root_tree,
+            edge_batches,
+            narrative_refs: vec![NarrativeRef {
+                path: relative_path.to_string(),
+                stream: None,
+                role: "user".to_string(),
+                blob_id,
+            }],
+            cargo_snapshot: parent_commit.cargo_snapshot,
+            rust_snapshot: parent_commit.rust_snapshot,
+            diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: parent_commit.glossary,
+            decisions: parent_commit.decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
+            commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
+        };
 
-        if let Some(session) = &self.active_session {
-            if session.idle_time() > max_age {
-                let task = session.task_description().to_string();
-                let idle_duration_secs = session.idle_time().as_secs();
+        let commit_id = self.object_store.put_typed(&commit)?;
 
-                let message = format!(
-                    "Auto-saved stale session (idle for {} seconds): {}",
-                    idle_duration_secs, task
-                );
+        self.advance_main(commit_id, &commit.message)?;
 
-                self.compact_session_with_type(
-                    &message,
-                    CommitType::StaleAutoCompact { idle_duration_secs },
-                )?;
+        let edge_batches: Vec<_> = commit
+            .edge_batches
+            .iter()
+            .map(|id| self.object_store.get_typed(*id))
+            .collect::<Result<_>>()?;
+        self.writable_index()?
+            .add_commit_edges(commit_id, &commit, &edge_batches)?;
 
-                report.sessions_compacted += 1;
-                report.compacted_tasks.push(task);
-            }
-        }
+        self.record_event(EventKind::Committed {
+            commit_id: commit_id.as_hex(),
+            message: commit.message.clone(),
+        });
+        self.notify_commit(commit_id, &commit.message);
 
-        Ok(report)
+        Ok(commit_id)
     }
 
-    /// Load edge batches from the object store.
-    ///
-    /// Edge batches are stored as separate objects to avoid duplication.
-    /// Commits and the index only store ObjectIds that reference them.
+    /// Removes a glossary term.
     ///
     /// # Errors
     ///
-    /// Returns an error if any edge batch cannot be loaded.
-    pub fn load_edge_batches(
-        &self,
-        batch_ids: &[ObjectId],
-    ) -> Result<Vec<crate::types::EdgeBatch>> {
-        let mut batches = Vec::with_capacity(batch_ids.len());
-        for batch_id in batch_ids {
-            let batch = self.object_store.get_typed(*batch_id)?;
-            batches.push(batch);
-        }
-        Ok(batches)
+    /// Returns `InvalidArgument` if the term is not defined.
+    pub fn remove_glossary_term(&mut self, term: &str) -> Result<ObjectId> {
+        self.require(Capability::Commit, "glossary")?;
+        let mut glossary = self.glossary()?;
+        let removed = glossary.remove(term).ok_or_else(|| {
+            CtxError::InvalidArgument(format!("glossary term '{}' is not defined", term))
+        })?;
+        self.commit_glossary(glossary, &[], format!("Glossary: remove {}", removed.term))
     }
 
-    /// Build a prompt pack from a query.
+    /// Returns every recorded decision with its ID, oldest first.
     ///
-    /// This runs the retrieval pipeline to compile relevant context for an LLM.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the pack can't be built.
-    pub fn build_pack(
-        &mut self,
-        query: &str,
-        config: &crate::pack::RetrievalConfig,
-    ) -> Result<crate::pack::PromptPack> {
-        crate::pack::build_pack(self, query, config)
+    /// Decisions recorded in the active session are included once it is
+    /// compacted.
+    pub fn decisions(&self) -> Result<Vec<(ObjectId, Decision)>> {
+        let head: Commit = self.object_store.get_typed(self.head_id()?)?;
+        let Some(log_id) = head.decisions else {
+            return Ok(Vec::new());
+        };
+        let log: DecisionLog = self.object_store.get_typed(log_id)?;
+        log.decisions
+            .into_iter()
+            .map(|id| Ok((id, self.object_store.get_typed(id)?)))
+            .collect()
     }
 
-    /// Analyze all Rust files in the project using rust-analyzer.
+    /// Records a decision and returns its ID.
     ///
-    /// Spawns rust-analyzer, analyzes all .rs files, extracts semantic edges,
-    /// and stores them as an EdgeBatch.
+    /// In an active session, an empty `paths` becomes the files the session
+    /// has read or written so far and the session's task is attached. The
+    /// session is flushed, and the decision reaches the log when it is
+    /// compacted. Without a
+    /// session it is committed right away. Either way it is linked to its
+    /// files with `Mentions` edges.
     ///
-    /// # Returns
+    /// # Examples
     ///
-    /// AnalysisReport with statistics about what was analyzed.
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, Decision};
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let mut decision = Decision::new(
+    ///     "Which storage engine?",
+    ///     "redb",
+    ///     "Single file, no compaction, pure Rust",
+    /// );
+    /// decision.options = vec!["redb".into(), "sled".into(), "rocksdb".into()];
+    /// repo.record_decision(decision).unwrap();
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns error if:
-    /// - rust-analyzer is not installed
-    /// - Analysis fails
-    /// - Edge storage fails
-    pub fn analyze_rust(&mut self) -> Result<AnalysisReport> {
-        use crate::lsp::{build_edges_from_analysis, RustAnalyzer};
-        use crate::types::EdgeBatch;
+    /// Returns `InvalidArgument` if the choice is empty.
+    pub fn record_decision(&mut self, mut decision: Decision) -> Result<ObjectId> {
+        self.require(Capability::Commit, "record decision")?;
+        crate::decision::validate(&decision)?;
+        if decision.created_at == 0 {
+            decision.created_at = self.now_unix();
+        }
 
-        // Check availability
-        if !RustAnalyzer::is_available() {
-            return Err(CtxError::RustAnalyzerNotFound);
+        if self.active_session.is_none() {
+            let decision_id = self.object_store.put_typed(&decision)?;
+            let message = format!("Decision: {}", decision.title());
+            self.commit_decisions(&[decision_id], message)?;
+            return Ok(decision_id);
         }
 
-        // Start rust-analyzer
-        let mut analyzer = RustAnalyzer::start(&self.root)?;
+        self.flush_active_session()?;
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        if decision.paths.is_empty() {
+            let observations = staging::collect_observations(
+                session.staging_head(),
+                session.base_commit(),
+                &self.object_store,
+            )?;
+            let paths: BTreeSet<&str> = observations.iter().filter_map(Observation::path).collect();
+            decision.paths = paths.into_iter().map(str::to_string).collect();
+        }
+        decision
+            .task
+            .get_or_insert_with(|| session.task_description().to_string());
+        let decision_id = self.object_store.put_typed(&decision)?;
+        session.observe_decision(decision_id)?;
+        self.flush_active_session()?;
+        Ok(decision_id)
+    }
 
-        // Find all Rust files
-        let rust_files = Self::find_rust_files(&self.root)?;
+    /// Observes a fact in the active session and returns its ID.
+    ///
+    /// Evidence without a blob is pinned to the path's latest content in
+    /// the session, or else at HEAD. The fact joins the [`FactSet`] when the
+    /// session is compacted, replacing any fact with the same subject and
+    /// predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, Fact, NodeId, NodeKind};
+    /// use std::time::Duration;
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// repo.start_session("Upgrade storage").unwrap();
+    /// let db = NodeId { kind: NodeKind::File, id: "src/db.rs".into() };
+    /// let fact = Fact::new(db, "uses", "redb 2.x")
+    ///     .with_evidence("Cargo.toml")
+    ///     .with_ttl(Duration::from_secs(30 * 24 * 60 * 60));
+    /// repo.observe_fact(fact).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` without a session, and `InvalidArgument`
+    /// for an empty subject or predicate or an evidence path with no
+    /// content.
+    pub fn observe_fact(&mut self, mut fact: Fact) -> Result<ObjectId> {
+        self.require(Capability::Commit, "observe fact")?;
+        crate::fact::validate(&fact)?;
+        if self.active_session.is_none() {
+            return Err(CtxError::NoActiveSession);
+        }
+        if fact.created_at == 0 {
+            fact.created_at = self.now_unix();
+        }
+        if let Some(evidence) = fact.evidence.as_mut().filter(|e| e.blob_id.is_none()) {
+            self.flush_active_session()?;
+            let session = self
+                .active_session
+                .as_ref()
+                .ok_or(CtxError::NoActiveSession)?;
+            let observations = staging::collect_observations(
+                session.staging_head(),
+                session.base_commit(),
+                &self.object_store,
+            )?;
+            let observed = observations.iter().rev().find_map(|obs| match obs {
+                Observation::FileWrite {
+                    path, content_id, ..
+                } if *path == evidence.path => Some(*content_id),
+                Observation::FileRead {
+                    path,
+                    content_id: Some(content_id),
+                    ..
+                } if *path == evidence.path => Some(*content_id),
+                _ => None,
+            });
+            evidence.blob_id = match observed {
+                Some(blob_id) => Some(blob_id),
+                None => staging::lookup_tree_path(
+                    self.head()?.root_tree,
+                    &evidence.path,
+                    &self.object_store,
+                )?,
+            };
+            if evidence.blob_id.is_none() {
+                return Err(CtxError::InvalidArgument(format!(
+                    "no content recorded for evidence path '{}'",
+                    evidence.path
+                )));
+            }
+        }
 
-        let mut all_edges = Vec::new();
-        let mut files_analyzed = 0;
-        let mut symbols_found = 0;
-        let mut calls_resolved = 0;
-        let mut file_blobs: Vec<(String, ObjectId)> = Vec::new(); // Store path→blob mappings
+        let fact_id = self.object_store.put_typed(&fact)?;
+        self.active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?
+            .observe_fact(fact_id)?;
+        Ok(fact_id)
+    }
 
-        for file in rust_files {
-            match analyzer.analyze_file(&file) {
-                Ok(analysis) => {
-                    files_analyzed += 1;
-                    symbols_found += analysis.items.len();
-                    calls_resolved += analysis.calls.len();
+    /// Returns the valid facts as of HEAD with their IDs, oldest first.
+    ///
+    /// Facts whose TTL has passed or whose evidence file has changed since
+    /// are left out.
+    pub fn facts(&self) -> Result<Vec<(ObjectId, Fact)>> {
+        self.valid_facts(|_| true)
+    }
 
-                    // Read file content for ObjectId computation
-                    // Canonicalize to ensure absolute paths (FIX for path matching)
-                    let file_canonical = file.canonicalize()?;
-                    let file_path = file_canonical.to_string_lossy().to_string();
-                    let file_content = std::fs::read(&file)?;
+    /// Returns the valid facts about `subject`, found through the index.
+    pub fn facts_about(&self, subject: &NodeId) -> Result<Vec<(ObjectId, Fact)>> {
+        let ids: HashSet<String> = self
+            .index()?
+            .get_edges_to(subject, EdgeLabel::Mentions)?
+            .into_iter()
+            .filter(|node| node.kind == NodeKind::Fact)
+            .map(|node| node.id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.valid_facts(|id| ids.contains(&id.as_hex()))
+    }
 
-                    // Store file content as blob (FIX for prompt pack retrieval)
-                    let file_blob_id = self.object_store.put_blob(&file_content)?;
-                    file_blobs.push((file_path.clone(), file_blob_id));
+    /// The facts in HEAD's set passing `filter` and still valid.
+    fn valid_facts(
+        &self,
+        mut filter: impl FnMut(ObjectId) -> bool,
+    ) -> Result<Vec<(ObjectId, Fact)>> {
+        let head = self.head()?;
+        let Some(set_id) = head.facts else {
+            return Ok(Vec::new());
+        };
+        let set: FactSet = self.object_store.get_typed(set_id)?;
+        let now = self.now_unix();
+        let mut facts = Vec::new();
+        for id in set.facts.into_iter().filter(|id| filter(*id)) {
+            let fact: Fact = self.object_store.get_typed(id)?;
+            if crate::fact::is_valid(&fact, now, head.root_tree, &self.object_store)? {
+                facts.push((id, fact));
+            }
+        }
+        Ok(facts)
+    }
 
-                    let commit_id = self.head_id()?;
-                    let edges =
-                        build_edges_from_analysis(&analysis, &file_path, &file_content, commit_id);
-                    all_edges.extend(edges);
-                }
-                Err(e) => {
-                    eprintln!("Warning: Failed to analyze {}: {}", file.display(), e);
-                }
+    /// Returns every answered question with its ID, oldest first.
+    ///
+    /// Answers given in the active session are included once it is
+    /// compacted.
+    pub fn answers(&self) -> Result<Vec<(ObjectId, QaPair)>> {
+        let head: Commit = self.object_store.get_typed(self.head_id()?)?;
+        let Some(log_id) = head.qa else {
+            return Ok(Vec::new());
+        };
+        let log: QaLog = self.object_store.get_typed(log_id)?;
+        log.pairs
+            .into_iter()
+            .map(|id| Ok((id, self.object_store.get_typed(id)?)))
+            .collect()
+    }
+
+    /// Records the user's answer to a question asked in the active session
+    /// and returns the pair's ID.
+    ///
+    /// The pair is linked with `Mentions` edges to the files the session
+    /// has read or written so far, and reaches the log when the session is
+    /// compacted. Packs for similar queries then lead with the answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` without a session, and `InvalidArgument`
+    /// for an empty question or answer.
+    pub fn record_answer(&mut self, question: &str, answer: &str) -> Result<ObjectId> {
+        self.require(Capability::Commit, "record answer")?;
+        crate::qa::validate(question, answer)?;
+        if self.active_session.is_none() {
+            return Err(CtxError::NoActiveSession);
+        }
+        let created_at = self.now_unix();
+        self.flush_active_session()?;
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        let observations = staging::collect_observations(
+            session.staging_head(),
+            session.base_commit(),
+            &self.object_store,
+        )?;
+        let paths: BTreeSet<&str> = observations.iter().filter_map(Observation::path).collect();
+        let pair = QaPair {
+            question: question.trim().to_string(),
+            answer: answer.trim().to_string(),
+            paths: paths.into_iter().map(str::to_string).collect(),
+            task: Some(session.task_description().to_string()),
+            created_at,
+        };
+        let pair_id = self.object_store.put_typed(&pair)?;
+        session.observe_qa(pair_id)?;
+        self.flush_active_session()?;
+        Ok(pair_id)
+    }
+
+    /// Records a conversation turn in the active session.
+    ///
+    /// The text is stored as a blob and kept according to
+    /// `session.transcripts`: with `off` nothing is recorded, with `session`
+    /// the turn is dropped when the session is compacted, and with `keep` it
+    /// becomes part of the commit's [`Transcript`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` without a session, and `InvalidArgument`
+    /// for a role that isn't a single word.
+    pub fn observe_message(&mut self, role: &str, content: &str) -> Result<()> {
+        if self.active_session.is_none() {
+            return Err(CtxError::NoActiveSession);
+        }
+        let role = crate::transcript::normalize_role(role)?;
+        let config = crate::config::Config::load(&self.ctx_dir())?;
+        if config.session.transcripts == TranscriptRetention::Off {
+            return Ok(());
+        }
+        let content_id = self.object_store.put_blob(content.as_bytes())?;
+        self.active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?
+            .observe_message(&role, content_id)?;
+        self.auto_flush()
+    }
+
+    /// Returns the conversation kept with `commit_id`, or None if the
+    /// commit's session recorded none or didn't keep it.
+    pub fn transcript(&self, commit_id: ObjectId) -> Result<Option<Transcript>> {
+        let commit: Commit = self.object_store.get_typed(commit_id)?;
+        commit
+            .transcript
+            .map(|id| self.object_store.get_typed(id))
+            .transpose()
+    }
+
+    /// Returns the conversation recorded so far in the active session,
+    /// including turns not yet flushed, or None if it has none.
+    pub fn active_transcript(&self) -> Result<Option<Transcript>> {
+        let session = self
+            .active_session
+            .as_ref()
+            .ok_or(CtxError::NoActiveSession)?;
+        let mut observations = staging::collect_observations(
+            session.staging_head(),
+            session.base_commit(),
+            &self.object_store,
+        )?;
+        observations.extend_from_slice(session.pending_observations());
+        Ok(Transcript::from_observations(
+            session.task_description(),
+            session.session_id(),
+            &observations,
+        ))
+    }
+
+    /// Suggests recurring narrative terms that have no glossary entry.
+    pub fn suggest_glossary_terms(&self, min_occurrences: usize) -> Result<Vec<GlossaryCandidate>> {
+        let ns = self.narrative();
+        let mut documents = Vec::new();
+        for path in ns.list_files()? {
+            if let Ok(content) = String::from_utf8(ns.read_file(&path)?) {
+                documents.push((path, content));
             }
         }
+        Ok(crate::glossary::extract_candidates(
+            &documents,
+            &self.glossary()?,
+            min_occurrences,
+        ))
+    }
 
-        // Shutdown analyzer
-        analyzer.shutdown()?;
+    /// Returns the table of cached file summaries (empty if nothing has
+    /// been summarized).
+    pub fn summaries(&self) -> Result<SummaryTable> {
+        match self.refs.read_summaries()? {
+            Some(id) => self.object_store.get_typed(id),
+            None => Ok(SummaryTable::default()),
+        }
+    }
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("system time before Unix epoch")
-            .as_secs();
+    /// Returns the cached summary of the blob `blob_id`, if any.
+    pub fn summary(&self, blob_id: ObjectId) -> Result<Option<Summary>> {
+        self.summaries()?
+            .get(blob_id)
+            .map(|id| self.object_store.get_typed(id))
+            .transpose()
+    }
 
-        // Store edges as EdgeBatch
-        let edge_batch = EdgeBatch {
-            edges: all_edges.clone(),
-            created_at: now,
-        };
+    /// Returns the summarizer configured under `[summaries]`: the
+    /// configured command, or [`HeuristicSummarizer`] if there is none.
+    pub fn summarizer(&self) -> Result<Box<dyn Summarizer>> {
+        let config = crate::config::Config::load(&self.ctx_dir())?.summaries;
+        match config.command {
+            Some(command) => Ok(Box::new(CommandSummarizer::new(
+                command.split_whitespace().map(str::to_string).collect(),
+                self.exec_policy.clone(),
+            )?)),
+            None => Ok(Box::new(HeuristicSummarizer {
+                max_lines: config.max_lines,
+            })),
+        }
+    }
 
-        let batch_id = self.object_store.put_typed(&edge_batch)?;
+    /// Summarizes the file at `path` as of HEAD, or every file under it if
+    /// it names a directory, and caches the summaries.
+    ///
+    /// Summaries are keyed by content, so a file whose current content was
+    /// summarized before is skipped unless `force` is set. Files that
+    /// aren't UTF-8 or that `summarizer` fails on are reported as skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, HeuristicSummarizer};
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// let report = repo
+    ///     .summarize("src", &HeuristicSummarizer::default(), false)
+    ///     .unwrap();
+    /// println!("{} summarized", report.summarized.len());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if no file at HEAD matches `path`.
+    pub fn summarize(
+        &mut self,
+        path: &str,
+        summarizer: &dyn Summarizer,
+        force: bool,
+    ) -> Result<SummarizeReport> {
+        self.require(Capability::Commit, "summarize")?;
+        let head: Commit = self.object_store.get_typed(self.head_id()?)?;
+        let files = staging::flatten_tree(head.root_tree, &self.object_store)?;
+        let prefix = path.trim_start_matches("./").trim_end_matches('/');
+        let matching: Vec<(String, ObjectId)> = files
+            .into_iter()
+            .filter(|(file, _)| {
+                prefix.is_empty()
+                    || prefix == "."
+                    || file == prefix
+                    || file.starts_with(&format!("{}/", prefix))
+            })
+            .collect();
+        if matching.is_empty() {
+            return Err(CtxError::InvalidArgument(format!(
+                "no file at HEAD matches '{}'",
+                path
+            )));
+        }
+
+        let mut table = self.summaries()?;
+        let mut report = SummarizeReport::default();
+        let now = self.now_unix();
+        for (file, blob_id) in matching {
+            if !force && table.get(blob_id).is_some() {
+                report.cached.push(file);
+                continue;
+            }
+            let FileContent::Bytes(bytes) = large_file::load_content(&self.object_store, blob_id)?
+            else {
+                report
+                    .skipped
+                    .push((file, "content not stored".to_string()));
+                continue;
+            };
+            let Ok(content) = String::from_utf8(bytes) else {
+                report.skipped.push((file, "not UTF-8 text".to_string()));
+                continue;
+            };
+            match summarizer.summarize(&file, &content) {
+                Ok(text) => {
+                    let summary = Summary {
+                        blob_id,
+                        text,
+                        summarizer: summarizer.name().to_string(),
+                        created_at: now,
+                    };
+                    table
+                        .entries
+                        .insert(blob_id, self.object_store.put_typed(&summary)?);
+                    report.summarized.push(file);
+                }
+                Err(e) => report.skipped.push((file, e.to_string())),
+            }
+        }
+
+        if !report.summarized.is_empty() {
+            let table_id = self.object_store.put_typed(&table)?;
+            self.refs.write_summaries(table_id)?;
+        }
+        Ok(report)
+    }
+
+    /// Stores `glossary` in a new commit, with mention edges for the terms
+    /// in `changed`.
+    fn commit_glossary(
+        &mut self,
+        glossary: Glossary,
+        changed: &[String],
+        message: String,
+    ) -> Result<ObjectId> {
+        let now = self.now_unix();
+        let glossary_id = self.object_store.put_typed(&glossary)?;
 
-        // Create commit with edge batch
         let parent_id = self.head_id()?;
         let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
 
+        let known_files = crate::glossary::historical_paths(parent_id, &self.object_store)?;
+        let edges: Vec<_> = changed
+            .iter()
+            .filter_map(|term| glossary.get(term))
+            .flat_map(|entry| {
+                crate::glossary::mention_edges(entry, glossary_id, parent_id, &known_files)
+            })
+            .collect();
+        let edge_batches = if edges.is_empty() {
+            vec![]
+        } else {
+            vec![self.object_store.put_typed(&EdgeBatch {
+                edges,
+                created_at: now,
+            })?]
+        };
+
         let commit = Commit {
             parents: vec![parent_id],
             timestamp_unix: now,
-            message: format!(
-                "Rust analysis: {} files, {} symbols, {} calls",
-                files_analyzed, symbols_found, calls_resolved
-            ),
+            message,
             root_tree: parent_commit.root_tree,
-            edge_batches: vec![batch_id],
+            edge_batches,
             narrative_refs: vec![],
             cargo_snapshot: parent_commit.cargo_snapshot,
             rust_snapshot: parent_commit.rust_snapshot,
             diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: Some(glossary_id),
+            decisions: parent_commit.decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
             commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
         };
 
         let commit_id = self.object_store.put_typed(&commit)?;
 
-        // Update HEAD and refs/main
-        self.refs.write_head(commit_id)?;
-        self.refs.write_ref("main", commit_id)?;
+        self.advance_main(commit_id, &commit.message)?;
 
-        // Load edge batches before we borrow the index mutably
         let edge_batches: Vec<_> = commit
             .edge_batches
             .iter()
             .map(|id| self.object_store.get_typed(*id))
             .collect::<Result<_>>()?;
+        self.writable_index()?
+            .add_commit_edges(commit_id, &commit, &edge_batches)?;
 
-        // Incrementally add edges from this commit to the index and index file paths
-        // This is far more efficient than rebuilding the entire index
-        let index = self.index_mut()?;
-        index.add_commit_edges(commit_id, &commit, &edge_batches)?;
-        index.index_file_paths(&file_blobs)?;
+        self.record_event(EventKind::Committed {
+            commit_id: commit_id.as_hex(),
+            message: commit.message.clone(),
+        });
+        self.notify_commit(commit_id, &commit.message);
 
-        Ok(AnalysisReport {
-            files_analyzed,
-            symbols_found,
-            calls_resolved,
-            edges_generated: all_edges.len(),
-            edge_batch_id: batch_id,
-            commit_id,
-        })
+        Ok(commit_id)
     }
 
-    /// Analyze a single Rust file.
-    pub fn analyze_rust_file(&mut self, path: &Path) -> Result<FileAnalysisReport> {
-        use crate::lsp::{build_edges_from_analysis, RustAnalyzer};
-        use crate::types::EdgeBatch;
-
-        if !RustAnalyzer::is_available() {
-            return Err(CtxError::RustAnalyzerNotFound);
-        }
-
-        let mut analyzer = RustAnalyzer::start(&self.root)?;
-        let analysis = analyzer.analyze_file(path)?;
-        analyzer.shutdown()?;
-
-        // Read file content for ObjectId computation
-        // Canonicalize to ensure absolute paths (FIX for path matching)
-        let path_canonical = path.canonicalize()?;
-        let file_path = path_canonical.to_string_lossy().to_string();
-        let file_content = std::fs::read(path)?;
-
-        // Store file content as blob (FIX for prompt pack retrieval)
-        let file_blob_id = self.object_store.put_blob(&file_content)?;
-
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("system time before Unix epoch")
-            .as_secs();
-
-        let current_head = self.head_id()?;
-        let edges = build_edges_from_analysis(&analysis, &file_path, &file_content, current_head);
-
-        let edge_batch = EdgeBatch {
-            edges: edges.clone(),
-            created_at: now,
-        };
-
-        let batch_id = self.object_store.put_typed(&edge_batch)?;
-
-        // Create commit with edge batch
+    /// Appends stored decisions to the log in a new commit, with mention
+    /// edges to their files.
+    fn commit_decisions(&mut self, decision_ids: &[ObjectId], message: String) -> Result<ObjectId> {
+        let now = self.now_unix();
         let parent_id = self.head_id()?;
         let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
+        let (decisions, decision_batch) = crate::decision::append_decisions(
+            decision_ids,
+            parent_commit.decisions,
+            parent_id,
+            now,
+            &self.object_store,
+        )?;
 
         let commit = Commit {
             parents: vec![parent_id],
             timestamp_unix: now,
-            message: format!(
-                "Rust analysis: {} ({} symbols, {} calls)",
-                file_path,
-                analysis.items.len(),
-                analysis.calls.len()
-            ),
+            message,
             root_tree: parent_commit.root_tree,
-            edge_batches: vec![batch_id],
+            edge_batches: decision_batch.into_iter().collect(),
             narrative_refs: vec![],
             cargo_snapshot: parent_commit.cargo_snapshot,
             rust_snapshot: parent_commit.rust_snapshot,
             diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: parent_commit.glossary,
+            decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
             commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
         };
 
-        let new_commit_id = self.object_store.put_typed(&commit)?;
+        let commit_id = self.object_store.put_typed(&commit)?;
 
-        // Update HEAD and refs/main
-        self.refs.write_head(new_commit_id)?;
-        self.refs.write_ref("main", new_commit_id)?;
+        self.advance_main(commit_id, &commit.message)?;
 
-        // Load edge batches before we borrow the index mutably
         let edge_batches: Vec<_> = commit
             .edge_batches
             .iter()
             .map(|id| self.object_store.get_typed(*id))
             .collect::<Result<_>>()?;
+        self.writable_index()?
+            .add_commit_edges(commit_id, &commit, &edge_batches)?;
 
-        // Incrementally add edges from this commit to the index
-        self.index_mut()?
-            .add_commit_edges(new_commit_id, &commit, &edge_batches)?;
-
-        // Index the file path → blob mapping for retrieval (FIX for prompt pack)
-        self.index_mut()?
-            .index_file_path(&file_path, file_blob_id)?;
+        self.record_event(EventKind::Committed {
+            commit_id: commit_id.as_hex(),
+            message: commit.message.clone(),
+        });
+        self.notify_commit(commit_id, &commit.message);
 
-        Ok(FileAnalysisReport {
-            path: path.to_path_buf(),
-            symbols: analysis.items.len(),
-            calls: analysis.calls.len(),
-            edges: edges.len(),
-            edge_batch_id: batch_id,
-            commit_id: new_commit_id,
-        })
+        Ok(commit_id)
     }
 
-    /// Find all Rust source files in a directory.
-    fn find_rust_files(dir: &Path) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
+    /// Reads journal events at or after `cursor`.
+    ///
+    /// Pass 0 to read from the beginning, then the returned
+    /// [`EventPage::next_cursor`] to pick up only newer events.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal can't be read or holds a malformed
+    /// event.
+    pub fn events_since(&self, cursor: u64) -> Result<EventPage> {
+        self.event_log().read_since(cursor)
+    }
 
-        if !dir.is_dir() {
-            return Ok(files);
-        }
+    fn event_log(&self) -> EventLog {
+        EventLog::new(self.ctx_dir().join(EVENTS_FILE))
+    }
 
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+    /// Reads the retrieval audit log: every pack built at or after `since`
+    /// (Unix seconds), oldest first. See [`RetrievalRecord`] for what's
+    /// recorded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log can't be read or holds a malformed
+    /// record.
+    pub fn retrieval_records(&self, since: u64) -> Result<Vec<RetrievalRecord>> {
+        AuditLog::new(self.ctx_dir().join(AUDIT_FILE)).read_since(since)
+    }
 
-            if path.is_dir() {
-                // Skip target directory
-                if path.file_name().and_then(|n| n.to_str()) == Some("target") {
-                    continue;
-                }
+    /// Moves HEAD and refs/main to `commit_id` together, logging the
+    /// commit's `message` as the reason.
+    fn advance_main(&self, commit_id: ObjectId, message: &str) -> Result<()> {
+        self.require(Capability::Commit, "commit")?;
+        self.refs.transaction(
+            &[
+                RefUpdate::Head(commit_id),
+                RefUpdate::Ref("main".to_string(), commit_id),
+            ],
+            &format!("commit: {}", message),
+        )
+    }
 
-                // Recurse
-                files.extend(Self::find_rust_files(&path)?);
-            } else if path.extension().and_then(|e| e.to_str()) == Some("rs") {
-                files.push(path);
-            }
+    /// Appends an event to the journal. Failures are logged, since the
+    /// operation being recorded has already happened.
+    fn record_event(&self, kind: EventKind) {
+        if self.dry_run.is_some() {
+            return;
+        }
+        if let Err(e) = self.event_log().append(kind) {
+            warn!(error = %e, "Failed to record repository event");
         }
-
-        Ok(files)
     }
 
-    /// Analyze Cargo workspace and extract dependency graph.
-    ///
-    /// Runs `cargo metadata`, parses the output, extracts edges,
-    /// and creates a commit with the edge batch.
-    ///
-    /// # Returns
-    ///
-    /// CargoAnalysisReport with statistics about what was analyzed.
+    /// Records a pack built for `query` with `config` in the retrieval
+    /// audit log and as a [`PackRecord`].
     ///
     /// # Errors
     ///
-    /// Returns error if:
-    /// - cargo is not installed
-    /// - No Cargo.toml found
-    /// - cargo metadata fails
-    /// - Edge storage fails
-    pub fn analyze_cargo(&mut self) -> Result<crate::cargo::CargoAnalysisReport> {
-        use crate::cargo::{extract_cargo_edges, parse_cargo_metadata, run_cargo_metadata};
-        use crate::types::EdgeBatch;
-
-        // Check availability
-        if !crate::cargo::is_available() {
-            return Err(CtxError::CargoNotFound);
+    /// Returns an error if the audit log can't be written, so a pack is
+    /// never handed over unaudited. Failing to store the [`PackRecord`] is
+    /// only logged.
+    fn pack_built(
+        &self,
+        kind: &str,
+        query: &str,
+        pack: &crate::pack::PromptPack,
+        config: &crate::pack::RetrievalConfig,
+    ) -> Result<()> {
+        let chunks = pack.retrieved.iter().map(AuditedChunk::new).collect();
+        self.audit_retrieval(kind, query, pack.head_commit, chunks)?;
+        if self.dry_run.is_some() {
+            return Ok(());
         }
+        if let Err(e) = self.record_pack(pack, config) {
+            warn!(error = %e, "Failed to record pack");
+        }
+        Ok(())
+    }
 
-        // Run cargo metadata
-        let json = run_cargo_metadata(&self.root)?;
-        let snapshot = parse_cargo_metadata(&json)?;
-
-        // Store snapshot as typed object
-        let snapshot_id = self.object_store.put_typed(&snapshot)?;
-
-        // Extract edges
-        let commit_id = self.head_id()?;
-        let edges = extract_cargo_edges(&snapshot, commit_id);
-
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("system time before Unix epoch")
-            .as_secs();
-
-        // Store edge batch
-        let edge_batch = EdgeBatch {
-            edges: edges.clone(),
-            created_at: now,
+    /// Appends a retrieval to the audit log unless `[audit] retrievals` is
+    /// off. Dry runs are audited too, since their packs are handed over
+    /// like any other.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audit log can't be written.
+    fn audit_retrieval(
+        &self,
+        kind: &str,
+        query: &str,
+        head_commit: ObjectId,
+        chunks: Vec<AuditedChunk>,
+    ) -> Result<()> {
+        if !self.audit_config.retrievals {
+            return Ok(());
+        }
+        let key = match self.audit_key.get() {
+            Some(key) => *key,
+            None => {
+                let key = crate::audit::load_or_create_key(&self.ctx_dir().join(AUDIT_KEY_FILE))?;
+                *self.audit_key.get_or_init(|| key)
+            }
         };
-        let batch_id = self.object_store.put_typed(&edge_batch)?;
-
-        // Create commit
-        let parent_id = self.head_id()?;
-        let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
+        let record = RetrievalRecord::new(
+            self.now_unix(),
+            self.identity.as_ref().map(ToString::to_string),
+            kind,
+            query,
+            self.audit_config.redact_queries,
+            &key,
+            head_commit,
+            chunks,
+        );
+        AuditLog::new(self.ctx_dir().join(AUDIT_FILE)).append(&record)
+    }
 
-        let commit = Commit {
-            parents: vec![parent_id],
-            timestamp_unix: now,
-            message: format!(
-                "Cargo analysis: {} packages, {} targets",
-                snapshot.packages.len(),
-                snapshot
-                    .packages
-                    .iter()
-                    .map(|p| p.targets.len())
-                    .sum::<usize>()
-            ),
-            root_tree: parent_commit.root_tree,
-            edge_batches: vec![batch_id],
-            narrative_refs: vec![],
-            cargo_snapshot: Some(snapshot_id), // Store snapshot reference
-            rust_snapshot: parent_commit.rust_snapshot,
-            diagnostics_snapshot: parent_commit.diagnostics_snapshot,
-            commit_type: None,
+    /// Runs the hooks for `event`, adding the repository root to `payload`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a hook vetoes the operation, or if the config
+    /// can't be loaded for an event that can veto.
+    fn run_hooks(&self, event: HookEvent, mut payload: serde_json::Value) -> Result<()> {
+        if self.dry_run.is_some() {
+            return Ok(());
+        }
+        let config = match crate::config::Config::load(&self.ctx_dir()) {
+            Ok(config) => config.hooks,
+            Err(e) if event.can_veto() => return Err(e),
+            Err(e) => {
+                warn!(error = %e, event = event.name(), "Failed to load hooks config");
+                return Ok(());
+            }
         };
+        payload["repo"] = self.root.display().to_string().into();
+        hooks::run_hooks(
+            event,
+            &self.root,
+            &self.ctx_dir().join("hooks"),
+            &config,
+            &self.exec_policy,
+            payload,
+        )
+    }
 
-        let new_commit_id = self.object_store.put_typed(&commit)?;
-
-        // Update refs
-        self.refs.write_head(new_commit_id)?;
-        self.refs.write_ref("main", new_commit_id)?;
-
-        // Load edge batches before we borrow the index mutably
-        let edge_batches: Vec<_> = commit
-            .edge_batches
-            .iter()
-            .map(|id| self.object_store.get_typed(*id))
-            .collect::<Result<_>>()?;
+    /// Runs a hook that can't veto, after the operation is durable.
+    fn notify_hooks(&self, event: HookEvent, payload: serde_json::Value) {
+        if let Err(e) = self.run_hooks(event, payload) {
+            warn!(error = %e, event = event.name(), "Failed to run hooks");
+        }
+    }
 
-        // Incrementally add edges from this commit to the index
-        self.index_mut()?
-            .add_commit_edges(new_commit_id, &commit, &edge_batches)?;
+    /// Runs `post-commit` hooks for a commit that moved HEAD.
+    fn notify_commit(&self, commit_id: ObjectId, message: &str) {
+        self.notify_hooks(
+            HookEvent::PostCommit,
+            serde_json::json!({ "commit_id": commit_id.as_hex(), "message": message }),
+        );
+    }
 
-        Ok(crate::cargo::CargoAnalysisReport {
-            packages_found: snapshot.packages.len(),
-            targets_found: snapshot.packages.iter().map(|p| p.targets.len()).sum(),
-            dependencies_found: snapshot.packages.iter().map(|p| p.dependencies.len()).sum(),
-            edges_generated: edges.len(),
-            snapshot_id,
-            edge_batch_id: batch_id,
-            commit_id: new_commit_id,
-        })
+    /// Counts `paths` as included in a pack, for frecency.
+    ///
+    /// Building a pack is a read, so the counts are buffered instead of
+    /// written right away. They're written every [`ACCESS_FLUSH_PACKS`]
+    /// packs, with the next compaction, by [`flush_access`](Self::flush_access)
+    /// and when the handle is dropped. Dry runs and historical views count
+    /// nothing.
+    pub(crate) fn defer_access(&self, paths: Vec<String>) -> Result<()> {
+        if paths.is_empty() || self.dry_run.is_some() || self.view.is_some() {
+            return Ok(());
+        }
+        let now = self.now_unix();
+        let full = {
+            let mut pending = self
+                .pending_access
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            pending.add(paths, now);
+            pending.packs += 1;
+            pending.packs >= ACCESS_FLUSH_PACKS
+        };
+        if full {
+            self.flush_access()?;
+        }
+        Ok(())
     }
 
-    /// Acquires exclusive lock on repository.
+    /// Writes the pack inclusions buffered since the last flush to the
+    /// frecency table in one transaction.
     ///
-    /// The lock file contains the PID of the owning process. If the lock is held
-    /// by a dead process (stale lock), it will be automatically cleaned up.
-    fn acquire_lock(&self) -> Result<LockGuard> {
-        let lock_path = self.ctx_dir().join("LOCK");
-        self.acquire_lock_with_retry(&lock_path, 0)
+    /// Nothing is written through a snapshot of an index another process
+    /// is writing; the buffered counts are dropped instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be opened or written.
+    pub fn flush_access(&self) -> Result<()> {
+        let pending = std::mem::take(
+            &mut *self
+                .pending_access
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+        if pending.paths.is_empty() {
+            return Ok(());
+        }
+        let index = self.index()?;
+        if index.is_snapshot() {
+            return Ok(());
+        }
+        index.merge_access(&pending.paths)
     }
 
-    /// Internal helper for lock acquisition with retry count.
-    fn acquire_lock_with_retry(&self, lock_path: &Path, retry_count: u32) -> Result<LockGuard> {
-        // Limit retries to prevent infinite loops
-        if retry_count > 2 {
-            return Err(CtxError::RepositoryLocked);
+    /// Current time in Unix seconds, honoring the injected time provider.
+    pub(crate) fn now_unix(&self) -> u64 {
+        match &self.time_provider {
+            Some(provider) => provider().max(0) as u64,
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
         }
+    }
 
-        // Try to create the lock file exclusively (fails if already exists)
-        match OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(lock_path)
+    /// Returns the index, creating it if it doesn't exist.
+    ///
+    /// The index is lazily loaded on first access. The returned guard
+    /// shares the index with other readers, so several threads can query
+    /// one handle at once; writers through [`CtxRepo::index_mut`] need
+    /// exclusive access to the handle instead.
+    ///
+    /// If another process is writing the index, this is a read-only
+    /// snapshot of it (see [`Index::open_shared`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be loaded or created.
+    pub fn index(&self) -> Result<IndexGuard<'_>> {
         {
-            Ok(mut file) => {
-                // Write our PID to the lock file
-                let pid = std::process::id();
-                writeln!(file, "{}", pid)?;
-                file.flush()?;
-
-                // Acquire file lock for additional safety
-                file.try_lock_exclusive()
-                    .map_err(|_| CtxError::RepositoryLocked)?;
-
-                Ok(LockGuard {
-                    file: Some(file),
-                    path: lock_path.to_path_buf(),
-                })
+            let guard = self.index.read().unwrap_or_else(|e| e.into_inner());
+            if guard.is_some() {
+                return Ok(IndexGuard(guard));
             }
-            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-                // Lock file exists - check if the holder is still alive
-                self.handle_existing_lock(lock_path, retry_count)
+        }
+
+        // Load under the write lock so racing readers open it once
+        {
+            let mut slot = self.index.write().unwrap_or_else(|e| e.into_inner());
+            if slot.is_none() {
+                *slot = Some(self.load_index(true)?);
             }
-            Err(e) => Err(CtxError::Io(e)),
         }
+        Ok(IndexGuard(
+            self.index.read().unwrap_or_else(|e| e.into_inner()),
+        ))
     }
 
-    /// Handle the case where a lock file already exists.
-    fn handle_existing_lock(&self, lock_path: &Path, retry_count: u32) -> Result<LockGuard> {
-        // Try to read the PID from the lock file
-        match fs::read_to_string(lock_path) {
-            Ok(content) => {
-                if let Ok(pid) = content.trim().parse::<u32>() {
-                    if is_process_alive(pid) {
-                        // Process is still alive - lock is legitimately held
-                        return Err(CtxError::SessionLockHeld { pid });
-                    }
+    /// Gets a mutable reference to the index, loading it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PermissionDenied` if the handle can't commit,
+    /// [`CtxError::IndexLocked`] if another process is writing the index,
+    /// or another error if it can't be loaded or rebuilt.
+    pub fn index_mut(&mut self) -> Result<&mut Index> {
+        self.require(Capability::Commit, "index")?;
+        self.writable_index()
+    }
 
-                    // Process is dead - stale lock
-                    warn!(
-                        pid = pid,
-                        "Detected stale lock from dead process, cleaning up"
-                    );
+    /// The index for writing, whatever the handle's capabilities: staging
+    /// a session indexes what it observes.
+    fn writable_index(&mut self) -> Result<&mut Index> {
+        if self.index_slot().as_ref().is_some_and(Index::is_snapshot) {
+            // Writes need the index itself
+            *self.index_slot() = None;
+        }
+        if self.index_slot().is_none() {
+            let index = self.load_index(false)?;
+            *self.index_slot() = Some(index);
+        }
 
-                    // Remove the stale lock and retry
-                    if let Err(e) = fs::remove_file(lock_path) {
-                        // If removal fails, it might have been cleaned up by another process
-                        if e.kind() != std::io::ErrorKind::NotFound {
-                            return Err(CtxError::Io(e));
-                        }
-                    }
+        Ok(self.index_slot().as_mut().unwrap())
+    }
 
-                    // Retry acquiring the lock
-                    return self.acquire_lock_with_retry(lock_path, retry_count + 1);
-                }
+    /// Opens the index, rebuilding it from objects if it's missing. With
+    /// `allow_snapshot`, reads a snapshot if another process is writing it.
+    fn load_index(&self, allow_snapshot: bool) -> Result<Index> {
+        let index_path = self.index_path();
 
-                // Lock file exists but has invalid content
-                // This could be a race condition or corruption - try to clean up
-                warn!("Lock file has invalid content, attempting cleanup");
-                let _ = fs::remove_file(lock_path);
-                self.acquire_lock_with_retry(lock_path, retry_count + 1)
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                // Lock file was removed between our check and read - retry
-                self.acquire_lock_with_retry(lock_path, retry_count + 1)
-            }
-            Err(_) => {
-                // Can't read lock file - assume it's locked
-                Err(CtxError::RepositoryLocked)
+        // Try to open existing index
+        let existing = if allow_snapshot {
+            Index::open_shared(&index_path)?
+        } else {
+            Index::open(&index_path)?
+        };
+        let mut index = match existing {
+            Some(idx) => idx,
+            None => {
+                // Rebuild if missing
+                let head = self.head_id()?;
+                Index::rebuild_from_objects(&index_path, &self.object_store, head)?
             }
+        };
+        index.set_metrics(self.metrics.clone());
+        Ok(index)
+    }
+
+    /// The index database, or its scratch copy during a dry run or
+    /// historical view.
+    fn index_path(&self) -> PathBuf {
+        match self.dry_run.as_ref().or(self.view.as_ref()) {
+            Some(scratch) => scratch.join("index.redb"),
+            None => self.ctx_dir().join("index/index.redb"),
         }
     }
 
-    /// Run garbage collection on the repository.
-    ///
-    /// See `crate::gc::gc` for details.
-    pub fn gc(&mut self, config: crate::gc::GcConfig) -> Result<crate::gc::GcReport> {
-        crate::gc::gc(&self.refs, &mut self.object_store, config, None)
+    /// The index slot, without locking: `&mut self` rules out readers.
+    fn index_slot(&mut self) -> &mut Option<Index> {
+        self.index.get_mut().unwrap_or_else(|e| e.into_inner())
     }
 
-    /// Run garbage collection with progress reporting.
-    pub fn gc_with_progress(
-        &mut self,
-        config: crate::gc::GcConfig,
-        progress: &crate::gc::GcProgressCallback,
-    ) -> Result<crate::gc::GcReport> {
-        crate::gc::gc(&self.refs, &mut self.object_store, config, Some(progress))
+    fn set_index(&mut self, mut index: Index) {
+        index.set_metrics(self.metrics.clone());
+        *self.index_slot() = Some(index);
     }
 
-    /// Verify repository integrity.
+    /// Rebuilds the index from scratch.
     ///
-    /// See `crate::verify::verify` for details.
-    pub fn verify(
-        &self,
-        config: crate::verify::VerifyConfig,
-    ) -> Result<crate::verify::VerifyReport> {
-        crate::verify::verify(&self.refs, &self.object_store, config)
+    /// This is useful if the index is corrupted or out of date.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can't be rebuilt.
+    pub fn rebuild_index(&mut self) -> Result<()> {
+        self.rebuild_index_with_progress(&Progress::default())
     }
-}
 
-/// RAII guard for repository lock.
-///
-/// Holds an exclusive lock on the repository's LOCK file. The lock is
-/// automatically released when dropped, and the lock file is removed.
-struct LockGuard {
-    /// The open lock file (holds the file lock).
-    /// Wrapped in Option to allow taking ownership in Drop.
-    file: Option<File>,
-    /// Path to the lock file (for cleanup on drop).
-    path: PathBuf,
-}
+    /// Rebuilds the index from scratch, reporting commits walked to
+    /// `progress`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::Cancelled`] if `progress` is cancelled, leaving
+    /// the previous index in place, or another error if the index can't be
+    /// rebuilt.
+    pub fn rebuild_index_with_progress(&mut self, progress: &Progress) -> Result<()> {
+        self.require(Capability::Commit, "rebuild index")?;
+        let index_path = self.index_path();
+        let head = self.head_id()?;
 
-impl Drop for LockGuard {
-    fn drop(&mut self) {
-        // Take ownership of the file to close it (releases the lock)
-        if let Some(file) = self.file.take() {
-            drop(file);
-        }
+        // Drop existing index handle
+        *self.index_slot() = None;
 
-        // Remove the lock file - ignore errors (file might already be gone)
-        let _ = fs::remove_file(&self.path);
+        // Rebuild
+        let config = crate::index::RebuildConfig {
+            progress: progress.clone(),
+            ..Default::default()
+        };
+        let (idx, _report) =
+            Index::rebuild_from_objects_with_config(&index_path, &self.object_store, head, config)?;
+        self.set_index(idx);
+
+        Ok(())
     }
-}
 
-/// Check if a process with the given PID is still alive.
-///
-/// On Linux, uses /proc/{pid}/stat to check process existence.
-/// On other Unix systems, uses /proc/{pid} directory existence.
-/// On non-Unix systems, conservatively assumes the process is alive.
-#[cfg(target_os = "linux")]
-fn is_process_alive(pid: u32) -> bool {
-    // On Linux, check if /proc/{pid}/stat exists
-    // This is more reliable than just /proc/{pid} because zombie processes
-    // still have a /proc entry but their stat file shows they're defunct
-    std::path::Path::new(&format!("/proc/{}/stat", pid)).exists()
-}
+    /// Starts a new session for the given task.
+    ///
+    /// Creates initial WorkCommit with SessionStart step kind.
+    /// Updates STAGE pointer. A staged session that has expired is first
+    /// saved or abandoned, as [`expire_session`](Self::expire_session) does.
+    ///
+    /// # Errors
+    /// Returns error if a session is already active, or `InvalidArgument`
+    /// if a staged session that hasn't expired is pending, since starting
+    /// another would overwrite it.
+    pub fn start_session(&mut self, task: &str) -> Result<&mut Session> {
+        if self.active_session.is_some() {
+            return Err(CtxError::SessionAlreadyActive(task.to_string()));
+        }
+        self.expire_session()?;
 
-#[cfg(all(unix, not(target_os = "linux")))]
-fn is_process_alive(pid: u32) -> bool {
+        // Acquire lock and store it to keep it alive
+        let lock = self.acquire_lock()?;
+        if self.refs.read_stage()?.is_some() {
+            return Err(CtxError::InvalidArgument(
+                "a staged session is pending; recover or abandon it before starting another"
+                    .to_string(),
+            ));
+        }
+
+        // Get current HEAD as base
+        let base_commit = self.head_id()?;
+
+        // Generate session ID
+        let session_id = uuid::Uuid::new_v4().to_string();
+
+        // Create session
+        let mut session = Session::new(
+            task.to_string(),
+            base_commit,
+            session_id,
+            self.time_provider.clone(),
+        );
+        session.set_author(self.identity.clone());
+        self.configure_session(&mut session)?;
+
+        // Create initial WorkCommit (SessionStart)
+        session.flush_step(&self.object_store, &self.refs)?;
+
+        self.record_event(EventKind::SessionStarted {
+            session_id: session.session_id().to_string(),
+            task: task.to_string(),
+        });
+        self.active_session = Some(session);
+        self.session_lock = Some(lock);
+
+        Ok(self.active_session.as_mut().unwrap())
+    }
+
+    /// Compacts the current session into a canonical commit.
+    ///
+    /// Walks staging chain, aggregates work, creates Commit,
+    /// updates HEAD and refs/main, deletes STAGE. With `analysis.auto_cargo`
+    /// set and a stale Cargo snapshot (see [`CtxRepo::cargo_status`]), a
+    /// Cargo analysis commit follows the returned one.
+    pub fn compact_session(&mut self, message: &str) -> Result<ObjectId> {
+        self.compact_session_with_type(message, CommitType::Normal)
+    }
+
+    /// Compacts with a specific commit type.
+    pub fn compact_session_with_type(
+        &mut self,
+        message: &str,
+        commit_type: CommitType,
+    ) -> Result<ObjectId> {
+        self.require(Capability::Commit, "compact session")?;
+        let session = self
+            .active_session
+            .as_ref()
+            .ok_or(CtxError::NoActiveSession)?;
+
+        let staging_head = session.staging_head();
+        let base_commit = session.base_commit();
+        let session_id = session.session_id().to_string();
+        let task = session.task_description().to_string();
+        let session_payload = serde_json::json!({
+            "session_id": session.session_id(),
+            "task": session.task_description(),
+            "steps": session.step_count(),
+            "message": message,
+        });
+
+        // Aborting must always succeed, so it isn't subject to veto
+        if !matches!(commit_type, CommitType::Abandoned) {
+            self.run_hooks(HookEvent::PreCompact, session_payload.clone())?;
+        }
+
+        let observations =
+            staging::collect_observations(staging_head, base_commit, &self.object_store)?;
+
+        // Paths the session touched, for frecency
+        let accessed: Vec<String> = observations
+            .iter()
+            .filter_map(|obs| match obs {
+                Observation::FileRead { path, .. } | Observation::FileWrite { path, .. } => {
+                    Some(path.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        // Latest content of each written path (abandoned work moves nothing)
+        let written: BTreeMap<String, ObjectId> = match commit_type {
+            CommitType::Abandoned => BTreeMap::new(),
+            _ => observations
+                .iter()
+                .filter_map(|obs| match obs {
+                    Observation::FileWrite {
+                        path, content_id, ..
+                    } => Some((path.clone(), *content_id)),
+                    _ => None,
+                })
+                .collect(),
+        };
+
+        // Compact staging into canonical commit
+        let mut commit = staging::compact_staging(
+            staging_head,
+            base_commit,
+            message,
+            commit_type.clone(),
+            &self.object_store,
+        )?;
+
+        // The conversation, unless the config says to drop it
+        let retention = crate::config::Config::load(&self.ctx_dir())
+            .map(|config| config.session.transcripts)
+            .unwrap_or_default();
+        if retention == TranscriptRetention::Keep {
+            if let Some(transcript) =
+                Transcript::from_observations(&task, &session_id, &observations)
+            {
+                commit.transcript = Some(self.object_store.put_typed(&transcript)?);
+            }
+        }
+
+        // Files moved this session, kept in their own batch like the
+        // heuristic edges
+        let renames = self.detect_renames(&written);
+        if !renames.is_empty() {
+            let batch = EdgeBatch {
+                edges: rename::rename_edges(&renames, base_commit),
+                created_at: commit.timestamp_unix,
+            };
+            commit
+                .edge_batches
+                .push(self.object_store.put_typed(&batch)?);
+        }
+
+        // Store the commit
+        let commit_id = self.object_store.put_typed(&commit)?;
+
+        // Move HEAD and refs/main and delete STAGE in one transition
+        self.refs.transaction(
+            &[
+                RefUpdate::Head(commit_id),
+                RefUpdate::Ref("main".to_string(), commit_id),
+                RefUpdate::DeleteStage,
+            ],
+            &format!("compact: {}", commit.message),
+        )?;
+
+        // Clear active session and release lock, unless this is a trial
+        if self.dry_run.is_none() {
+            self.active_session = None;
+            self.session_lock = None;
+        }
+
+        // Back up the refs just written; like index upkeep, best-effort
+        if self.dry_run.is_none() {
+            if let Err(e) = self.backup_after_compaction() {
+                warn!(error = %e, "Failed to back up refs and narrative");
+            }
+        }
+
+        // The commit is durable; index upkeep and access statistics are
+        // best-effort
+        let edges_indexed = commit
+            .edge_batches
+            .iter()
+            .map(|id| self.object_store.get_typed(*id))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|batches| {
+                let index = self.writable_index()?;
+                index.add_commit_edges(commit_id, &commit, &batches)?;
+                for rename in &renames {
+                    index.rename_file_path(&rename.from, &rename.to)?;
+                }
+                Ok(())
+            });
+        if let Err(e) = edges_indexed {
+            warn!(error = %e, "Failed to index edges of compacted commit");
+        }
+        // Session reads and the pack inclusions buffered since the last
+        // flush go in one write
+        let mut pending = std::mem::take(
+            self.pending_access
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+        pending.add(accessed, self.now_unix());
+        if !pending.paths.is_empty() {
+            if let Err(e) = self
+                .writable_index()
+                .and_then(|index| index.merge_access(&pending.paths))
+            {
+                warn!(error = %e, "Failed to record file access for frecency");
+            }
+        }
+
+        let abandoned = matches!(commit_type, CommitType::Abandoned);
+        self.record_event(EventKind::SessionCompacted {
+            session_id,
+            commit_id: commit_id.as_hex(),
+            commit_type,
+        });
+        let mut payload = session_payload;
+        payload["commit_id"] = commit_id.as_hex().into();
+        payload["files"] = written.keys().cloned().collect::<Vec<_>>().into();
+        self.notify_hooks(HookEvent::PostCompact, payload);
+        self.notify_commit(commit_id, message);
+
+        // Keep the Cargo snapshot in step with manifest edits; the session
+        // commit stands even if this fails
+        if !abandoned {
+            let auto_cargo = crate::config::Config::load(&self.ctx_dir())
+                .map(|config| config.analysis.auto_cargo)
+                .unwrap_or(false);
+            if auto_cargo {
+                if let Err(e) = self.refresh_stale_cargo() {
+                    warn!(error = %e, "Failed to re-run Cargo analysis after compaction");
+                }
+            }
+        }
+
+        // Collect garbage if enough has piled up; also best-effort
+        self.auto_gc_report = match self.auto_gc() {
+            Ok(report) => report,
+            Err(e) => {
+                warn!(error = %e, "Failed to run automatic GC after compaction");
+                None
+            }
+        };
+
+        Ok(commit_id)
+    }
+
+    /// Compacts the current session with a commit message written by
+    /// `provider`, and appends its log entry to today's narrative log.
+    ///
+    /// The provider sees a [`SessionDigest`] of the staged observations, so
+    /// flush first to include pending ones. The log entry is dated in UTC
+    /// and appended once the commit is durable; failing to write it is
+    /// logged rather than returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, HeuristicSummaryProvider};
+    ///
+    /// let mut repo = CtxRepo::open(".").unwrap();
+    /// repo.start_session("Fix the parser").unwrap();
+    /// repo.observe_command("cargo test", Some(0), None).unwrap();
+    /// repo.flush_active_session().unwrap();
+    /// let (commit_id, summary) = repo
+    ///     .compact_session_summarized(&HeuristicSummaryProvider)
+    ///     .unwrap();
+    /// println!("{}: {}", commit_id, summary.message);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` without a session, or the provider's error,
+    /// in which case the session is left as it was.
+    pub fn compact_session_summarized(
+        &mut self,
+        provider: &dyn SummaryProvider,
+    ) -> Result<(ObjectId, SessionSummary)> {
+        let session = self
+            .active_session
+            .as_ref()
+            .ok_or(CtxError::NoActiveSession)?;
+        let observations = staging::collect_observations(
+            session.staging_head(),
+            session.base_commit(),
+            &self.object_store,
+        )?;
+        let digest = SessionDigest::from_observations(
+            session.task_description(),
+            session.session_id(),
+            &observations,
+        );
+        let summary = provider.summarize_session(&digest)?;
+
+        let commit_id = self.compact_session(&summary.message)?;
+
+        if !summary.log_entry.trim().is_empty() && self.dry_run.is_none() {
+            let (date, time) = crate::narrative::log_date_time(self.now_unix());
+            let narrative = self.narrative();
+            let logged = narrative
+                .ensure_structure()
+                .and_then(|()| narrative.append_log(&date, &time, summary.log_entry.trim()));
+            if let Err(e) = logged {
+                warn!(error = %e, "Failed to append session summary to the log");
+            }
+        }
+        Ok((commit_id, summary))
+    }
+
+    /// Returns the session summary provider configured by
+    /// `session.summary_command`, or [`HeuristicSummaryProvider`] if it is
+    /// unset.
+    pub fn summary_provider(&self) -> Result<Box<dyn SummaryProvider>> {
+        let config = crate::config::Config::load(&self.ctx_dir())?;
+        match config.session.summary_command {
+            Some(command) => Ok(Box::new(CommandSummaryProvider::new(
+                command.split_whitespace().map(str::to_string).collect(),
+                self.exec_policy.clone(),
+            )?)),
+            None => Ok(Box::new(HeuristicSummaryProvider)),
+        }
+    }
+
+    /// Pairs paths first written this session with the indexed files they
+    /// were moved from.
+    ///
+    /// A path counts as new if no earlier commit updated it. Detection is
+    /// best-effort: if the index can't be read, no renames are recorded.
+    fn detect_renames(&mut self, written: &BTreeMap<String, ObjectId>) -> Vec<Rename> {
+        if written.is_empty() {
+            return Vec::new();
+        }
+
+        let candidates = self.index().and_then(|index| {
+            let mut known = index.indexed_paths()?;
+            known.retain(|path, _| !written.contains_key(path));
+            let mut added = BTreeMap::new();
+            for (path, blob_id) in written {
+                let node = NodeId {
+                    kind: NodeKind::File,
+                    id: path.clone(),
+                };
+                if index
+                    .get_edges_from(&node, EdgeLabel::UpdatedIn)?
+                    .is_empty()
+                {
+                    added.insert(path.clone(), *blob_id);
+                }
+            }
+            Ok((added, known))
+        });
+
+        match candidates {
+            Ok((added, known)) => rename::detect_renames(&added, &known, &self.object_store),
+            Err(e) => {
+                warn!(error = %e, "Failed to detect renamed files");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Aborts the current session, discarding all work.
+    ///
+    /// # Warning
+    ///
+    /// This operation creates a commit marked as `Abandoned`, which preserves
+    /// the work history for debugging but marks it as intentionally discarded.
+    /// The work is NOT lost (it's committed), but it's marked as abandoned.
+    ///
+    /// If you want to preserve the work for later continuation, use
+    /// `flush_active_session()` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Explanation for why the session was aborted
+    pub fn abort_session(&mut self, reason: &str) -> Result<ObjectId> {
+        let message = format!("Aborted: {}", reason);
+        self.compact_session_with_type(&message, CommitType::Abandoned)
+    }
+
+    /// Recovers a session from staging (e.g., after crash).
+    ///
+    /// Returns None if no STAGE pointer exists.
+    pub fn recover_session(&mut self) -> Result<Option<&mut Session>> {
+        if let Some(staging_head) = self.refs.read_stage()? {
+            let mut session = Session::from_staging(
+                staging_head,
+                &self.object_store,
+                self.time_provider.clone(),
+            )?;
+            self.configure_session(&mut session)?;
+
+            self.active_session = Some(session);
+            Ok(self.active_session.as_mut())
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Recovers a session from staging, repairing a broken staging chain
+    /// first, and reports what became of each step.
+    ///
+    /// See [`recover_staging_summary`](crate::recover_staging_summary) for
+    /// how a broken chain is cut. The session is active afterwards if any
+    /// step was kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionAlreadyActive` if a session is already active, and
+    /// `RepositoryLocked` if another process holds the lock.
+    pub fn recover_session_with_summary(&mut self) -> Result<crate::verify::RecoverySummary> {
+        if let Some(session) = &self.active_session {
+            return Err(CtxError::SessionAlreadyActive(
+                session.task_description().to_string(),
+            ));
+        }
+        let _lock = self.acquire_lock()?;
+        let summary = crate::verify::recover_staging_summary(&self.refs, &self.object_store)?;
+        if summary.recovered.is_some() {
+            self.recover_session()?;
+        }
+        Ok(summary)
+    }
+
+    /// Applies the `session` settings for deduplication and auto-flush to
+    /// `session`.
+    fn configure_session(&self, session: &mut Session) -> Result<()> {
+        let config = crate::config::Config::load(&self.ctx_dir())?.session;
+        session.set_dedup(config.dedup_reads, config.dedup_commands);
+        session.set_flush_policy(config.flush_policy());
+        Ok(())
+    }
+
+    /// Flushes the active session if its [flush
+    /// policy](crate::FlushPolicy) calls for it, after an observation.
+    fn auto_flush(&mut self) -> Result<()> {
+        if self.active_session.as_ref().is_some_and(Session::flush_due) {
+            self.flush_active_session()?;
+        }
+        Ok(())
+    }
+
+    /// Checks if there's an active session.
+    pub fn has_active_session(&self) -> bool {
+        self.active_session.is_some()
+    }
+
+    /// Returns reference to active session.
+    pub fn active_session(&self) -> Option<&Session> {
+        self.active_session.as_ref()
+    }
+
+    /// Returns mutable reference to active session.
+    pub fn active_session_mut(&mut self) -> Option<&mut Session> {
+        self.active_session.as_mut()
+    }
+
+    /// Flushes the active session's current step.
+    ///
+    /// Convenience method that handles the borrowing internally.
+    pub fn flush_active_session(&mut self) -> Result<ObjectId> {
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        let staging_head = session.flush_step(&self.object_store, &self.refs)?;
+        let event = EventKind::SessionFlushed {
+            session_id: session.session_id().to_string(),
+            steps: session.step_count(),
+            staging_head: staging_head.as_hex(),
+        };
+        self.record_event(event);
+        Ok(staging_head)
+    }
+
+    /// Observes a file write in the active session.
+    ///
+    /// Convenience method that handles the borrowing internally. The index
+    /// maps `path` to the new content right away, superseding edges derived
+    /// only from its previous content.
+    ///
+    /// Large or binary content is handled by the [content
+    /// limits](Self::content_limits): a skipped write isn't recorded, and a
+    /// stubbed or chunked one returns the ID of its
+    /// [`LargeFile`](crate::LargeFile) record.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PathIgnored` if an ignore rule matches `path`, and
+    /// `PermissionDenied` if the handle can't write files.
+    pub fn observe_file_write(&mut self, path: &str, content: &[u8]) -> Result<ObjectId> {
+        self.check_not_ignored(path)?;
+        self.require(Capability::WriteFiles, path)?;
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        let Some(content_id) =
+            large_file::store_content(content, &self.content_limits, &self.object_store)?
+        else {
+            debug!(
+                path,
+                size = content.len(),
+                "Skipping write under content limits"
+            );
+            return Ok(ObjectId::hash_blob(content));
+        };
+        session.record_file_write(path, content_id);
+
+        // The observation is already staged; index upkeep is best-effort
+        if let Err(e) = self
+            .writable_index()
+            .and_then(|index| index.index_file_path(path, content_id))
+        {
+            warn!(error = %e, path, "Failed to invalidate edges for written file");
+        }
+
+        self.auto_flush()?;
+        Ok(content_id)
+    }
+
+    /// Observes a file read in the active session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PathIgnored` if an ignore rule matches `path`, and
+    /// `PermissionDenied` if it isn't among the handle's readable paths.
+    pub fn observe_file_read(&mut self, path: &str) -> Result<()> {
+        self.check_not_ignored(path)?;
+        self.require(Capability::ReadPath, path)?;
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        session.observe_file_read(path)?;
+        self.auto_flush()
+    }
+
+    /// Observes a file read with content in the active session.
+    ///
+    /// This captures the exact content the agent read, enabling:
+    /// - Temporal reconstruction ("what did the agent see at step 5?")
+    /// - True context for decision analysis
+    /// - Reproducible agent behavior
+    ///
+    /// Large or binary content is handled by the [content
+    /// limits](Self::content_limits); a skipped read records the path only.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PathIgnored` if an ignore rule matches `path`, and
+    /// `PermissionDenied` if it isn't among the handle's readable paths.
+    pub fn observe_file_read_with_content(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        self.check_not_ignored(path)?;
+        self.require(Capability::ReadPath, path)?;
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        let content_id =
+            large_file::store_content(content, &self.content_limits, &self.object_store)?;
+        session.record_file_read(path, content_id);
+        self.auto_flush()
+    }
+
+    /// Observes a note in the active session.
+    pub fn observe_note(&mut self, note: &str) -> Result<()> {
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        session.observe_note(note)?;
+        self.auto_flush()
+    }
+
+    /// Observes a note tagged with `tags` in the active session.
+    ///
+    /// The tags apply to every file read or written in the same step, and
+    /// are indexed on compaction so [`RetrievalConfig::tags`] can filter on
+    /// them.
+    ///
+    /// [`RetrievalConfig::tags`]: crate::RetrievalConfig::tags
+    pub fn observe_note_with_tags(&mut self, note: &str, tags: Metadata) -> Result<()> {
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        session.observe_note_with_tags(note, tags)?;
+        self.auto_flush()
+    }
+
+    /// Runs `observe` with the observations it records in the active
+    /// session attributed to the sub-agent `actor`, such as `planner`,
+    /// `coder` or `reviewer`, for orchestrators that run several agents on
+    /// one task.
+    ///
+    /// The actor is kept under [`ACTOR_KEY`](crate::ACTOR_KEY) in each
+    /// observation's metadata. It's broken down in the
+    /// [session status](Self::session_status) and generated summaries, and
+    /// [`RetrievalConfig::actors`](crate::RetrievalConfig::actors) filters
+    /// on it.
+    ///
+    /// ```no_run
+    /// # use ctx_core::CtxRepo;
+    /// # let mut repo = CtxRepo::open(".").unwrap();
+    /// repo.observe_as("reviewer", |repo| {
+    ///     repo.observe_command("cargo test", Some(0), None)
+    /// })
+    /// .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoActiveSession` without a session, `InvalidArgument` for
+    /// an actor that isn't a single word, and whatever `observe` returns.
+    pub fn observe_as<T>(
+        &mut self,
+        actor: &str,
+        observe: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let actor = actor.trim();
+        if actor.is_empty() || actor.contains(char::is_whitespace) {
+            return Err(CtxError::InvalidArgument(format!(
+                "invalid actor '{}': expected a single word such as planner or coder",
+                actor
+            )));
+        }
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        let previous = session.actor().map(str::to_string);
+        session.set_actor(Some(actor.to_string()));
+        let result = observe(self);
+        // `observe` may have ended the session
+        if let Some(session) = self.active_session.as_mut() {
+            session.set_actor(previous);
+        }
+        result
+    }
+
+    /// Adds `metadata` to the most recent unflushed observation of the
+    /// active session. An observation the [flush
+    /// policy](crate::FlushPolicy) already flushed can't be tagged.
+    pub fn tag_last_observation(&mut self, metadata: Metadata) -> Result<()> {
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        session.tag_last_observation(metadata)
+    }
+
+    /// Observes a command in the active session.
+    pub fn observe_command(
+        &mut self,
+        command: &str,
+        exit_code: Option<i32>,
+        output: Option<&[u8]>,
+    ) -> Result<()> {
+        let session = self
+            .active_session
+            .as_mut()
+            .ok_or(CtxError::NoActiveSession)?;
+        session.observe_command(command, exit_code, output, &self.object_store)?;
+        self.auto_flush()
+    }
+
+    /// Checks if current session is stale.
+    pub fn check_stale_session(&self, config: &StaleSessionConfig) -> StaleSessionStatus {
+        let session = match &self.active_session {
+            Some(s) => s,
+            None => return StaleSessionStatus::NoSession,
+        };
+
+        let idle_secs = session.idle_time().as_secs();
+        let task = session.task_description().to_string();
+
+        if idle_secs >= config.auto_compact_threshold_secs {
+            StaleSessionStatus::ShouldAutoCompact { task, idle_secs }
+        } else if idle_secs >= config.ask_threshold_secs {
+            StaleSessionStatus::ShouldAsk { task, idle_secs }
+        } else {
+            StaleSessionStatus::Fresh { task, idle_secs }
+        }
+    }
+
+    /// Compacts or abandons an expired session, as `session.expired_sessions`
+    /// says.
+    ///
+    /// A session has expired when its last flushed step is older than the
+    /// auto-compact threshold, by this handle's clock (see
+    /// [`with_time_provider`](Self::with_time_provider)), and no running
+    /// process holds the LOCK. Its fate is logged in the narrative. Returns
+    /// the commit the session was compacted into, or None if nothing was
+    /// staged or it hasn't expired.
+    ///
+    /// [`start_session`](Self::start_session), `ctx maintenance` and
+    /// `ctx stage expire` run this.
+    pub fn expire_session(&mut self) -> Result<Option<ObjectId>> {
+        let config = crate::config::Config::load(&self.ctx_dir())?.session;
+        if self.active_session.is_some() || config.expired_sessions == ExpiredSessionPolicy::Off {
+            return Ok(None);
+        }
+        let Some(stage) = self.refs.read_stage()? else {
+            return Ok(None);
+        };
+        // A damaged chain is left for `ctx stage recover`
+        let Ok(last_step) = self.object_store.get_typed::<WorkCommit>(stage) else {
+            return Ok(None);
+        };
+        let idle_duration_secs = self.now_unix().saturating_sub(last_step.created_at);
+        if idle_duration_secs < config.stale_config().auto_compact_threshold_secs {
+            return Ok(None);
+        }
+        // Fails while the agent that staged the session is still running
+        let Ok(lock) = self.acquire_lock() else {
+            return Ok(None);
+        };
+
+        let task = last_step.task_description;
+        let idle_days = idle_duration_secs / (24 * 60 * 60);
+        self.recover_session()?;
+        let (commit_id, outcome) = match config.expired_sessions {
+            ExpiredSessionPolicy::Abandon => (
+                self.abort_session(&format!("session expired after {} days idle", idle_days))?,
+                "abandoned",
+            ),
+            _ => (
+                self.compact_session_with_type(
+                    &format!(
+                        "Auto-saved expired session (idle for {} days): {}",
+                        idle_days, task
+                    ),
+                    CommitType::StaleAutoCompact { idle_duration_secs },
+                )?,
+                "auto-compacted",
+            ),
+        };
+        drop(lock);
+        warn!(task = %task, commit = %commit_id, outcome, "Expired staged session");
+
+        let entry = format!(
+            "Session \"{}\" was {} into {} after {} days without activity; \
+             the process that staged it had exited.",
+            task,
+            outcome,
+            &commit_id.as_hex()[..12],
+            idle_days
+        );
+        let (date, time) = crate::narrative::log_date_time(self.now_unix());
+        let narrative = self.narrative();
+        let logged = narrative
+            .ensure_structure()
+            .and_then(|()| narrative.append_log(&date, &time, &entry));
+        if let Err(e) = logged {
+            warn!(error = %e, "Failed to log the expired session");
+        }
+        Ok(Some(commit_id))
+    }
+
+    /// Cleans up sessions that exceed max idle time.
+    pub fn cleanup_stale_sessions(&mut self, max_age: Duration) -> Result<CleanupReport> {
+        let mut report = CleanupReport::default();
+
+        if let Some(session) = &self.active_session {
+            if session.idle_time() > max_age {
+                let task = session.task_description().to_string();
+                let idle_duration_secs = session.idle_time().as_secs();
+
+                let message = format!(
+                    "Auto-saved stale session (idle for {} seconds): {}",
+                    idle_duration_secs, task
+                );
+
+                self.compact_session_with_type(
+                    &message,
+                    CommitType::StaleAutoCompact { idle_duration_secs },
+                )?;
+
+                report.sessions_compacted += 1;
+                report.compacted_tasks.push(task);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Load edge batches from the object store.
+    ///
+    /// Edge batches are stored as separate objects to avoid duplication.
+    /// Commits and the index only store ObjectIds that reference them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any edge batch cannot be loaded.
+    pub fn load_edge_batches(
+        &self,
+        batch_ids: &[ObjectId],
+    ) -> Result<Vec<crate::types::EdgeBatch>> {
+        let mut batches = Vec::with_capacity(batch_ids.len());
+        for batch_id in batch_ids {
+            let batch = self.object_store.get_typed(*batch_id)?;
+            batches.push(batch);
+        }
+        Ok(batches)
+    }
+
+    /// Build a prompt pack from a query.
+    ///
+    /// This runs the retrieval pipeline to compile relevant context for an
+    /// LLM: it identifies relevant files, expands the context graph, and
+    /// assembles a structured pack. Every pack built through a handle is
+    /// recorded in the retrieval audit log.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{AuthorFilter, CtxRepo, RetrievalConfig, EdgeLabel, Tokenizer};
+    ///
+    /// # fn main() -> ctx_core::Result<()> {
+    /// let repo = CtxRepo::open(".")?;
+    ///
+    /// let config = RetrievalConfig {
+    ///     token_budget: 10000,
+    ///     tokenizer: Tokenizer::default(),
+    ///     response_reserve: 2000,
+    ///     expansion_depth: 2,
+    ///     expand_labels: vec![EdgeLabel::Imports, EdgeLabel::Calls],
+    ///     max_expanded_nodes: 50,
+    ///     narrative_days: 7,
+    ///     narrative_budget: 2000,
+    ///     include_active_task: true,
+    ///     include_log: false,
+    ///     author_filter: AuthorFilter::Any,
+    ///     tags: vec![],
+    ///     actors: vec![],
+    ///     frecency_boost: false,
+    ///     feedback_boost: true,
+    ///     explain: false,
+    ///     pinned: vec![],
+    ///     exclude: vec!["*.lock".to_string()],
+    ///     use_scc: false,
+    ///     edge_decay: Default::default(),
+    ///     summary_max_relevance: Some(500),
+    ///     decisions_only: false,
+    ///     active_features: None,
+    /// };
+    ///
+    /// let pack = repo.build_pack("authentication middleware", &config)?;
+    ///
+    /// println!("Retrieved {} chunks", pack.retrieved.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pack can't be built.
+    pub fn build_pack(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+    ) -> Result<crate::pack::PromptPack> {
+        self.build_pack_with_progress(query, config, &Progress::default())
+    }
+
+    /// Build a prompt pack like [`CtxRepo::build_pack`], reporting the
+    /// retrieval steps to `progress`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::Cancelled`] if `progress` is cancelled, or another
+    /// error if the pack can't be built.
+    pub fn build_pack_with_progress(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+        progress: &Progress,
+    ) -> Result<crate::pack::PromptPack> {
+        let _span = debug_span!("build_pack", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let pack = crate::pack::build_pack_with_progress(self, query, config, progress)?;
+        self.pack_built("pack", query, &pack, config)?;
+        Ok(pack)
+    }
+
+    /// Build one prompt pack across this repository and its children.
+    ///
+    /// Each repository builds its own pack with the full budget, and the
+    /// packs are merged under `config.token_budget`, with file chunks from
+    /// all of them ranked together by relevance. Child paths are prefixed
+    /// by the child's location under this root, so pins and excludes are
+    /// given relative to it. A child whose pack can't be built is skipped
+    /// with a warning. Without child repositories this is
+    /// [`build_pack`](Self::build_pack).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this repository's pack can't be built.
+    pub fn build_federated_pack(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+    ) -> Result<crate::pack::PromptPack> {
+        let _span = debug_span!("build_federated_pack", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let pack = crate::pack::build_federated_pack(self, query, config)?;
+        self.pack_built("federated", query, &pack, config)?;
+        Ok(pack)
+    }
+
+    /// Build a prompt pack that references content provided in earlier
+    /// turns instead of repeating it.
+    ///
+    /// `provided` maps the ObjectIds of chunks delivered earlier to the turn
+    /// that delivered them. Those chunks are listed in
+    /// [`PromptPack::unchanged`](crate::PromptPack::unchanged) as one-line
+    /// references, and the budget freed goes to further files. A file that
+    /// changed since has a new ObjectId and is included in full.
+    /// [`PackSession`](crate::PackSession) keeps `provided` up to date
+    /// across turns.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pack can't be built.
+    pub fn build_delta_pack(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+        provided: &std::collections::BTreeMap<ObjectId, u32>,
+    ) -> Result<crate::pack::PromptPack> {
+        let _span = debug_span!("build_delta_pack", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let pack = crate::pack::build_delta_pack(self, query, config, provided)?;
+        self.pack_built("delta", query, &pack, config)?;
+        Ok(pack)
+    }
+
+    /// Build a prompt pack with overview and module layers above the file
+    /// chunks.
+    ///
+    /// Each layer stays within its sub-budget in `budgets`, and the file
+    /// chunks get the rest of `config.token_budget`. The files a layer names
+    /// are its [`PackLayer::anchors`](crate::PackLayer::anchors), which
+    /// [`build_zoom_pack`](Self::build_zoom_pack) drills into.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pack can't be built.
+    pub fn build_layered_pack(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+        budgets: &crate::pack::LayerBudgets,
+    ) -> Result<crate::pack::PromptPack> {
+        let _span = debug_span!("build_layered_pack", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let pack = crate::pack::build_layered_pack(self, query, config, budgets)?;
+        self.pack_built("layered", query, &pack, config)?;
+        Ok(pack)
+    }
+
+    /// Build a prompt pack focused on an anchor from a layered pack.
+    ///
+    /// The anchor is pinned and seeds expansion on its own, so the pack
+    /// holds the file and its neighbours. Narrative is left out, since the
+    /// layered pack already carried it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `anchor` isn't a file at HEAD.
+    pub fn build_zoom_pack(
+        &self,
+        anchor: &str,
+        config: &crate::pack::RetrievalConfig,
+    ) -> Result<crate::pack::PromptPack> {
+        let _span = debug_span!("build_zoom_pack", anchor).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let pack = crate::pack::build_zoom_pack(self, anchor, config)?;
+        self.pack_built("zoom", anchor, &pack, config)?;
+        Ok(pack)
+    }
+
+    /// Build a prompt pack, reusing the cached pack for identical requests.
+    ///
+    /// The cache key covers the query, HEAD, the retrieval config, recorded
+    /// feedback and the narrative files, so any of those changing forces a
+    /// rebuild. Frecency isn't part of the key; the cache TTL bounds how
+    /// stale frecency-ranked packs get. A cache hit is audited like a build.
+    pub fn build_pack_cached(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+        cache: &crate::cache::PackCache,
+    ) -> Result<crate::pack::PromptPack> {
+        let _span = debug_span!("build_pack_cached", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let pack = crate::pack::build_pack_cached(self, query, config, cache)?;
+        self.pack_built("cached", query, &pack, config)?;
+        Ok(pack)
+    }
+
+    /// Returns the prompt pack cache, configured from `.ctx/config.toml`.
+    pub fn pack_cache(&self) -> Result<crate::cache::PackCache> {
+        let config = crate::config::Config::load(&self.ctx_dir())?;
+        Ok(crate::cache::PackCache::new(
+            self.ctx_dir().join("cache/packs"),
+            config.cache,
+        ))
+    }
+
+    /// Stores `pack`, built with `config`, as a [`PackRecord`] and
+    /// returns its ID.
+    ///
+    /// Packs built through this handle are recorded already; this is for
+    /// packs built elsewhere. The record is announced in the event journal
+    /// so [`recent_packs`](Self::recent_packs) lists it and GC keeps it.
+    /// Recording the same pack again returns the same ID.
+    pub fn record_pack(
+        &self,
+        pack: &crate::pack::PromptPack,
+        config: &crate::pack::RetrievalConfig,
+    ) -> Result<ObjectId> {
+        let pack_id = self.pack_id(pack, config)?;
+        self.record_event(EventKind::PackBuilt {
+            pack_id: pack_id.as_hex(),
+            query: pack.task.clone(),
+            chunks: pack.retrieved.len(),
+            tokens: pack.token_budget.used,
+        });
+        Ok(pack_id)
+    }
+
+    /// Returns the ID `pack`, built with `config`, was recorded under,
+    /// storing its record again in case recording it failed. Unlike
+    /// [`record_pack`](Self::record_pack), this adds nothing to the
+    /// journal.
+    pub fn pack_id(
+        &self,
+        pack: &crate::pack::PromptPack,
+        config: &crate::pack::RetrievalConfig,
+    ) -> Result<ObjectId> {
+        let record = PackRecord::new(pack, config, &self.object_store)?;
+        self.object_store.put_typed(&record)
+    }
+
+    /// Stores `pack` as a [`PackRecord`] built with the default retrieval
+    /// configuration and returns its ID, without announcing it in the
+    /// journal.
+    #[deprecated(note = "packs are recorded when built; use `pack_id` or `record_pack`")]
+    pub fn store_pack(&self, pack: &crate::pack::PromptPack) -> Result<ObjectId> {
+        self.pack_id(pack, &crate::pack::RetrievalConfig::default())
+    }
+
+    /// Returns the pack recorded as `pack_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ObjectNotFound` if no pack is stored under `pack_id`, for
+    /// example because GC removed it.
+    pub fn pack_record(&self, pack_id: ObjectId) -> Result<PackRecord> {
+        self.object_store.get_typed(pack_id)
+    }
+
+    /// Rebuilds the pack recorded as `pack_id` from its record, as it was
+    /// when it was built, leaving out chunks for files the handle can't
+    /// read.
+    pub fn replay_pack(&self, pack_id: ObjectId) -> Result<crate::pack::PromptPack> {
+        let mut record = self.pack_record(pack_id)?;
+        record.chunks.retain(|chunk| {
+            chunk
+                .path
+                .as_deref()
+                .map_or(true, |path| self.can_read(path))
+        });
+        record.unchanged.retain(|chunk| self.can_read(&chunk.title));
+        record.materialize(&self.object_store)
+    }
+
+    /// Returns up to `limit` recorded packs from the event journal, newest
+    /// first.
+    pub fn recent_packs(&self, limit: usize) -> Result<Vec<PackHistoryEntry>> {
+        let mut packs = Vec::new();
+        for event in self.events_since(0)?.events.into_iter().rev() {
+            if packs.len() == limit {
+                break;
+            }
+            if let EventKind::PackBuilt {
+                pack_id,
+                query,
+                chunks,
+                tokens,
+            } = event.kind
+            {
+                packs.push(PackHistoryEntry {
+                    pack_id: ObjectId::from_hex(&pack_id)?,
+                    timestamp_unix: event.timestamp_unix,
+                    query,
+                    chunks,
+                    tokens,
+                });
+            }
+        }
+        Ok(packs)
+    }
+
+    /// Returns the chunk numbered `number` in the pack recorded as
+    /// `pack_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ObjectNotFound` if no pack is stored under `pack_id`,
+    /// `InvalidArgument` if the pack has no such chunk and
+    /// `PermissionDenied` if the chunk stands for a file the handle can't
+    /// read.
+    pub fn resolve_citation(&self, pack_id: ObjectId, number: usize) -> Result<CitedChunk> {
+        let chunk = self.pack_record(pack_id)?.resolve(number).cloned()?;
+        if let Some(path) = &chunk.path {
+            self.require(Capability::ReadPath, path)?;
+        }
+        Ok(chunk)
+    }
+
+    /// Builds a pack for each of `queries` under configurations `a` and
+    /// `b` and compares their token usage, overlap and hit rates.
+    ///
+    /// Frecency is off for both runs, so neither sees the other's packs as
+    /// recent use. Queries usually come from an expectations file read
+    /// with [`parse_expectations`](crate::parse_expectations).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{BenchQuery, CtxRepo, RetrievalConfig};
+    ///
+    /// let repo = CtxRepo::open(".").unwrap();
+    /// let queries = vec![BenchQuery {
+    ///     query: "where are packs cached".to_string(),
+    ///     expect: vec!["src/cache.rs".to_string()],
+    /// }];
+    /// let deeper = RetrievalConfig {
+    ///     expansion_depth: 3,
+    ///     ..Default::default()
+    /// };
+    /// let report = repo
+    ///     .bench_retrieval(&queries, &RetrievalConfig::default(), &deeper)
+    ///     .unwrap();
+    /// println!("{:?} vs {:?}", report.a.hit_rate, report.b.hit_rate);
+    /// ```
+    pub fn bench_retrieval(
+        &self,
+        queries: &[BenchQuery],
+        a: &crate::pack::RetrievalConfig,
+        b: &crate::pack::RetrievalConfig,
+    ) -> Result<BenchReport> {
+        crate::bench::run(self, queries, a, b)
+    }
+
+    /// Returns the table of commit annotations (empty if there are none).
+    pub fn note_table(&self) -> Result<NoteTable> {
+        match self.refs.read_notes()? {
+            Some(id) => self.object_store.get_typed(id),
+            None => Ok(NoteTable::default()),
+        }
+    }
+
+    /// Attaches a note to `commit` after the fact, such as that it
+    /// introduced a bug, and returns the annotation's ID.
+    ///
+    /// The commit itself doesn't change. Its annotations are listed in the
+    /// [`NoteTable`] `refs/notes/commits` points to, oldest first.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// let repo = CtxRepo::open(".").unwrap();
+    /// let commit = repo.resolve_commit("HEAD~2").unwrap();
+    /// repo.annotate(commit, "Introduced the parser crash fixed in HEAD")
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `text` is blank, or an error if
+    /// `commit` isn't a stored commit.
+    pub fn annotate(&self, commit: ObjectId, text: &str) -> Result<ObjectId> {
+        self.require(Capability::Commit, "annotate")?;
+        crate::notes::validate(text)?;
+        let _: Commit = self.object_store.get_typed(commit)?;
+
+        let annotation = Annotation {
+            commit_id: commit,
+            text: text.trim().to_string(),
+            author: self.identity.clone(),
+            created_at: self.now_unix(),
+        };
+        let annotation_id = self.object_store.put_typed(&annotation)?;
+
+        let mut table = self.note_table()?;
+        table.entries.entry(commit).or_default().push(annotation_id);
+        let table_id = self.object_store.put_typed(&table)?;
+        self.refs.write_notes(table_id)?;
+        Ok(annotation_id)
+    }
+
+    /// Returns the annotations attached to `commit`, oldest first.
+    pub fn annotations(&self, commit: ObjectId) -> Result<Vec<Annotation>> {
+        self.note_table()?
+            .get(commit)
+            .iter()
+            .map(|id| self.object_store.get_typed(*id))
+            .collect()
+    }
+
+    /// Returns the log of recorded feedback (empty if there is none).
+    pub fn feedback_log(&self) -> Result<FeedbackLog> {
+        match self.refs.read_feedback()? {
+            Some(id) => self.object_store.get_typed(id),
+            None => Ok(FeedbackLog::default()),
+        }
+    }
+
+    /// Records which chunks of the pack recorded as `pack_id` were useful
+    /// and which weren't, by their citation numbers, and returns the
+    /// feedback's ID.
+    ///
+    /// Later packs rank files marked useful higher and files marked useless
+    /// lower, unless [`RetrievalConfig::feedback_boost`] is off.
+    ///
+    /// [`RetrievalConfig::feedback_boost`]: crate::pack::RetrievalConfig::feedback_boost
+    ///
+    /// # Errors
+    ///
+    /// Returns `ObjectNotFound` if no pack is stored under `pack_id`, and
+    /// `InvalidArgument` if no chunk is rated, a chunk is rated both ways or
+    /// the pack has no such chunk.
+    pub fn record_feedback(
+        &self,
+        pack_id: ObjectId,
+        useful: &[usize],
+        useless: &[usize],
+    ) -> Result<ObjectId> {
+        self.require(Capability::Commit, "record feedback")?;
+        crate::feedback::validate(useful, useless)?;
+        let record = self.pack_record(pack_id)?;
+        let resolve = |numbers: &[usize]| -> Result<Vec<CitedChunk>> {
+            let numbers: BTreeSet<usize> = numbers.iter().copied().collect();
+            numbers
+                .into_iter()
+                .map(|number| record.resolve(number).cloned())
+                .collect()
+        };
+        let feedback = Feedback {
+            pack_id,
+            query: record.task.clone(),
+            useful: resolve(useful)?,
+            useless: resolve(useless)?,
+            created_at: self.now_unix(),
+        };
+
+        let feedback_id = self.object_store.put_typed(&feedback)?;
+        let mut log = self.feedback_log()?;
+        log.add(feedback_id, &feedback);
+        let log_id = self.object_store.put_typed(&log)?;
+        self.refs.write_feedback(log_id)?;
+        Ok(feedback_id)
+    }
+
+    /// Build one page of a prompt pack, continuing from `cursor`.
+    ///
+    /// Pass `None` for the first page. Chunks delivered on earlier pages are
+    /// never repeated; once the current expansion is used up, later pages
+    /// widen the graph one hop at a time. Narrative is only included on the
+    /// first page.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pack can't be built or the cursor is stale.
+    pub fn build_pack_paged(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+        cursor: Option<&crate::pack::PackCursor>,
+    ) -> Result<crate::pack::PagedPack> {
+        let _span = debug_span!("build_pack_paged", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        let paged = crate::pack::build_pack_paged(self, query, config, cursor)?;
+        self.pack_built("paged", query, &paged.pack, config)?;
+        Ok(paged)
+    }
+
+    /// Build a prompt pack incrementally, passing each part to `sink`.
+    ///
+    /// Selects the same content as [`build_pack`](Self::build_pack), but
+    /// file contents are loaded one at a time and handed over as soon as
+    /// they fit the budget. Returning `ControlFlow::Break` from `sink` stops
+    /// the stream; only chunks already delivered are recorded for frecency
+    /// and in the audit log.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::{CtxRepo, PackStreamItem, RetrievalConfig};
+    /// use std::ops::ControlFlow;
+    ///
+    /// # fn main() -> ctx_core::Result<()> {
+    /// let repo = CtxRepo::open(".")?;
+    /// let config = RetrievalConfig {
+    ///     token_budget: 200_000,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut files = 0;
+    /// repo.build_pack_streaming("storage layer", &config, |item| {
+    ///     if let PackStreamItem::Chunk(chunk) = item {
+    ///         println!("{}", chunk.title);
+    ///         files += 1;
+    ///     }
+    ///     Ok(if files < 10 { ControlFlow::Continue(()) } else { ControlFlow::Break(()) })
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if retrieval fails or `sink` returns one.
+    pub fn build_pack_streaming<F>(
+        &self,
+        query: &str,
+        config: &crate::pack::RetrievalConfig,
+        mut sink: F,
+    ) -> Result<crate::pack::TokenBudget>
+    where
+        F: FnMut(crate::pack::PackStreamItem) -> Result<std::ops::ControlFlow<()>>,
+    {
+        use crate::pack::PackStreamItem;
+
+        let _span = debug_span!("build_pack_streaming", query).entered();
+        let metrics = self.metrics.clone();
+        let _timer = Timer::start(&*metrics, HistogramMetric::PackBuildSeconds);
+        // Only what reached the sink was exposed, so an early stop or a
+        // failure part way still records the chunks handed over
+        let mut head_commit = None;
+        let mut chunks = Vec::new();
+        let result = crate::pack::build_pack_streaming(self, query, config, |item| {
+            match &item {
+                PackStreamItem::Header {
+                    head_commit: head, ..
+                } => head_commit = Some(*head),
+                PackStreamItem::Chunk(chunk) => chunks.push(AuditedChunk::new(chunk)),
+                _ => {}
+            }
+            sink(item)
+        });
+        if let Some(head_commit) = head_commit {
+            let audited = self.audit_retrieval("streaming", query, head_commit, chunks);
+            // A retrieval failure is the more useful error to report
+            if result.is_ok() {
+                audited?;
+            } else if let Err(e) = audited {
+                warn!(error = %e, "Failed to write retrieval audit log");
+            }
+        }
+        result
+    }
+
+    /// Analyze all Rust files in the project using rust-analyzer.
+    ///
+    /// Spawns rust-analyzer, analyzes all .rs files, extracts semantic edges,
+    /// and stores them as an EdgeBatch. Files are shared among the number of
+    /// rust-analyzer processes set by `analysis.workers` in the config (see
+    /// [`crate::lsp::AnalyzerPool`]).
+    ///
+    /// With `analysis.roots` set, each root is a separate Rust workspace
+    /// analyzed in turn. File nodes are absolute paths and snapshot entries
+    /// are relative to the repository root, so they include the root and
+    /// files from different roots never collide.
+    ///
+    /// # Returns
+    ///
+    /// AnalysisReport with statistics about what was analyzed.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - rust-analyzer is not installed
+    /// - Analysis fails
+    /// - Edge storage fails
+    /// - The exec policy refuses to run rust-analyzer
+    pub fn analyze_rust(&mut self) -> Result<AnalysisReport> {
+        self.analyze_rust_with_progress(&Progress::default())
+    }
+
+    /// Analyze all Rust files like [`CtxRepo::analyze_rust`], reporting
+    /// files analyzed to `progress`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::Cancelled`] if `progress` is cancelled before the
+    /// analysis is committed, leaving HEAD and the index unchanged, or any
+    /// error [`CtxRepo::analyze_rust`] returns.
+    pub fn analyze_rust_with_progress(&mut self, progress: &Progress) -> Result<AnalysisReport> {
+        self.require(Capability::Commit, "analyze")?;
+        use crate::lsp::{build_edges_from_analysis, build_module_edges, AnalyzerPool};
+        use crate::types::EdgeBatch;
+
+        let workers = crate::config::Config::load(&self.ctx_dir())?
+            .analysis
+            .workers;
+        let roots = self.analysis_roots()?;
+        let mut rust_files = Vec::new();
+        let mut analyses = Vec::new();
+        for root in &roots {
+            // Find the root's Rust files, leaving nested roots to themselves
+            let nested: Vec<PathBuf> = roots.iter().filter(|r| *r != root).cloned().collect();
+            let files = self.find_rust_files(root, &nested)?;
+
+            // Start rust-analyzer workers (reports RustAnalyzerNotFound if missing)
+            let mut pool = AnalyzerPool::start(root, &self.exec_policy, workers, files.len())?;
+            match pool.analyze_files(&files, progress) {
+                Ok(results) => analyses.extend(results),
+                Err(e) => {
+                    let _ = pool.shutdown();
+                    return Err(e);
+                }
+            }
+            pool.shutdown()?;
+            rust_files.extend(files);
+        }
+        let total = rust_files.len() as u64;
+
+        let mut all_edges = Vec::new();
+        let mut files_analyzed = 0;
+        let mut symbols_found = 0;
+        let mut calls_resolved = 0;
+        let mut file_paths = Vec::new();
+        let mut file_contents = Vec::new();
+        let mut file_analyses = Vec::new();
+
+        for (file, analysis) in rust_files.into_iter().zip(analyses) {
+            match analysis {
+                Ok(analysis) => {
+                    files_analyzed += 1;
+                    symbols_found += analysis.items.len();
+                    calls_resolved += analysis.calls.len();
+
+                    // Read file content for ObjectId computation
+                    // Canonicalize to ensure absolute paths (FIX for path matching)
+                    let file_canonical = file.canonicalize()?;
+                    let file_path = file_canonical.to_string_lossy().to_string();
+                    let file_content = std::fs::read(&file)?;
+
+                    let commit_id = self.head_id()?;
+                    let edges =
+                        build_edges_from_analysis(&analysis, &file_path, &file_content, commit_id);
+                    all_edges.extend(edges);
+                    all_edges.extend(build_module_edges(
+                        &analysis,
+                        &file_path,
+                        &file_content,
+                        commit_id,
+                    ));
+                    file_paths.push(file_path);
+                    file_contents.push(file_content);
+                    file_analyses.push((file_canonical, analysis));
+                }
+                Err(e) => {
+                    eprintln!("Warning: Failed to analyze {}: {}", file.display(), e);
+                }
+            }
+        }
+
+        progress.tick("analyze", total, total)?;
+
+        // Store file contents as blobs for pack retrieval, in parallel since
+        // compressing hundreds of files is the slow part
+        let blob_ids = self.object_store.put_blobs_parallel(&file_contents)?;
+        let snapshot_id = self.store_rust_snapshot(
+            None,
+            file_analyses
+                .iter()
+                .zip(&blob_ids)
+                .map(|((path, analysis), &blob)| (path.as_path(), blob, analysis)),
+        )?;
+        let file_blobs: Vec<(String, ObjectId)> = file_paths.into_iter().zip(blob_ids).collect();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before Unix epoch")
+            .as_secs();
+
+        // Store edges as EdgeBatch
+        let edge_batch = EdgeBatch {
+            edges: all_edges.clone(),
+            created_at: now,
+        };
+
+        let batch_id = self.object_store.put_typed(&edge_batch)?;
+
+        // Last chance to stop: past here HEAD and the index change
+        progress.check()?;
+
+        // Create commit with edge batch
+        let parent_id = self.head_id()?;
+        let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
+
+        let commit = Commit {
+            parents: vec![parent_id],
+            timestamp_unix: now,
+            message: format!(
+                "Rust analysis: {} files, {} symbols, {} calls",
+                files_analyzed, symbols_found, calls_resolved
+            ),
+            root_tree: parent_commit.root_tree,
+            edge_batches: vec![batch_id],
+            narrative_refs: vec![],
+            cargo_snapshot: parent_commit.cargo_snapshot,
+            rust_snapshot: Some(snapshot_id),
+            diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: parent_commit.glossary,
+            decisions: parent_commit.decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
+            commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
+        };
+
+        let commit_id = self.object_store.put_typed(&commit)?;
+
+        // Update HEAD and refs/main
+        self.advance_main(commit_id, &commit.message)?;
+
+        // Load edge batches before we borrow the index mutably
+        let edge_batches: Vec<_> = commit
+            .edge_batches
+            .iter()
+            .map(|id| self.object_store.get_typed(*id))
+            .collect::<Result<_>>()?;
+
+        // Incrementally add edges from this commit to the index and index file paths
+        // This is far more efficient than rebuilding the entire index
+        let index = self.writable_index()?;
+        index.add_commit_edges(commit_id, &commit, &edge_batches)?;
+        index.index_file_paths(&file_blobs)?;
+
+        let report = AnalysisReport {
+            files_analyzed,
+            symbols_found,
+            calls_resolved,
+            edges_generated: all_edges.len(),
+            edge_batch_id: batch_id,
+            commit_id,
+        };
+        self.notify_analysis("rust", &report, commit_id, &commit.message);
+        Ok(report)
+    }
+
+    /// Analyze a single Rust file.
+    pub fn analyze_rust_file(&mut self, path: &Path) -> Result<FileAnalysisReport> {
+        self.require(Capability::Commit, "analyze")?;
+        use crate::lsp::{build_edges_from_analysis, build_module_edges, RustAnalyzer};
+        use crate::types::EdgeBatch;
+
+        let root = self.analysis_root_for(path)?;
+        let mut analyzer = RustAnalyzer::start(&root, &self.exec_policy)?;
+        let analysis = analyzer.analyze_file(path)?;
+        analyzer.shutdown()?;
+
+        // Read file content for ObjectId computation
+        // Canonicalize to ensure absolute paths (FIX for path matching)
+        let path_canonical = path.canonicalize()?;
+        let file_path = path_canonical.to_string_lossy().to_string();
+        let file_content = std::fs::read(path)?;
+
+        // Store file content as blob (FIX for prompt pack retrieval)
+        let file_blob_id = self.object_store.put_blob(&file_content)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before Unix epoch")
+            .as_secs();
+
+        let current_head = self.head_id()?;
+        let mut edges =
+            build_edges_from_analysis(&analysis, &file_path, &file_content, current_head);
+        edges.extend(build_module_edges(
+            &analysis,
+            &file_path,
+            &file_content,
+            current_head,
+        ));
+
+        let edge_batch = EdgeBatch {
+            edges: edges.clone(),
+            created_at: now,
+        };
+
+        let batch_id = self.object_store.put_typed(&edge_batch)?;
+
+        // Create commit with edge batch, and the file's results added to
+        // the previous analysis
+        let parent_id = self.head_id()?;
+        let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
+        let snapshot_id = self.store_rust_snapshot(
+            parent_commit.rust_snapshot,
+            [(path_canonical.as_path(), file_blob_id, &analysis)],
+        )?;
+
+        let commit = Commit {
+            parents: vec![parent_id],
+            timestamp_unix: now,
+            message: format!(
+                "Rust analysis: {} ({} symbols, {} calls)",
+                file_path,
+                analysis.items.len(),
+                analysis.calls.len()
+            ),
+            root_tree: parent_commit.root_tree,
+            edge_batches: vec![batch_id],
+            narrative_refs: vec![],
+            cargo_snapshot: parent_commit.cargo_snapshot,
+            rust_snapshot: Some(snapshot_id),
+            diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: parent_commit.glossary,
+            decisions: parent_commit.decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
+            commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
+        };
+
+        let new_commit_id = self.object_store.put_typed(&commit)?;
+
+        // Update HEAD and refs/main
+        self.advance_main(new_commit_id, &commit.message)?;
+
+        // Load edge batches before we borrow the index mutably
+        let edge_batches: Vec<_> = commit
+            .edge_batches
+            .iter()
+            .map(|id| self.object_store.get_typed(*id))
+            .collect::<Result<_>>()?;
+
+        // Incrementally add edges from this commit to the index
+        self.writable_index()?
+            .add_commit_edges(new_commit_id, &commit, &edge_batches)?;
+
+        // Index the file path → blob mapping for retrieval (FIX for prompt pack)
+        self.writable_index()?
+            .index_file_path(&file_path, file_blob_id)?;
+
+        let report = FileAnalysisReport {
+            path: path.to_path_buf(),
+            symbols: analysis.items.len(),
+            calls: analysis.calls.len(),
+            edges: edges.len(),
+            edge_batch_id: batch_id,
+            commit_id: new_commit_id,
+        };
+        self.notify_analysis("rust_file", &report, new_commit_id, &commit.message);
+        Ok(report)
+    }
+
+    /// Stores the Rust analysis of `files` (absolute path, analyzed blob
+    /// and results), replacing their entries in the snapshot `base`.
+    fn store_rust_snapshot<'a>(
+        &self,
+        base: Option<ObjectId>,
+        files: impl IntoIterator<Item = (&'a Path, ObjectId, &'a crate::lsp::FileAnalysis)>,
+    ) -> Result<ObjectId> {
+        use crate::lsp::{RustFileSnapshot, RustSnapshot};
+
+        let mut snapshot: RustSnapshot = match base {
+            Some(id) => self.object_store.get_typed(id)?,
+            None => RustSnapshot::default(),
+        };
+        let root = fs::canonicalize(&self.root).unwrap_or_else(|_| self.root.clone());
+        for (path, blob, analysis) in files {
+            let key = crate::lsp::snapshot::relative_path(path, &root)
+                .unwrap_or_else(|| path.to_string_lossy().into_owned());
+            snapshot
+                .files
+                .insert(key, RustFileSnapshot::new(blob, analysis, &root));
+        }
+        self.object_store.put_typed(&snapshot)
+    }
+
+    /// Returns the Rust analysis recorded at HEAD, or None if Rust files
+    /// have not been analyzed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if HEAD or the snapshot can't be read.
+    pub fn rust_snapshot(&self) -> Result<Option<crate::lsp::RustSnapshot>> {
+        self.head()?
+            .rust_snapshot
+            .map(|id| self.object_store.get_typed(id))
+            .transpose()
+    }
+
+    /// Lists items nothing in the graph calls, references or otherwise
+    /// uses: likely dead code.
+    ///
+    /// Public API and tests are left out, judged from each item's
+    /// declaration in the analyzed content; see [`crate::find_orphans`].
+    /// Items whose definition has since been superseded are left out too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index or an analyzed blob can't be read.
+    pub fn orphan_items(&self) -> Result<Vec<crate::OrphanItem>> {
+        use crate::graph::{find_orphans, is_entry_point_declaration};
+
+        let index = self.index()?;
+        let mut orphans = Vec::new();
+        for (item, file) in find_orphans(&index.adjacency_list()?) {
+            if index
+                .get_superseded_by(&file, &item, EdgeLabel::Defines)?
+                .is_some()
+            {
+                continue;
+            }
+            let Some(evidence) = index
+                .get_edge_details(&file, &item, EdgeLabel::Defines)?
+                .pop()
+            else {
+                continue;
+            };
+
+            if let (Some(span), Some(blob)) = (&evidence.span, evidence.blob_id) {
+                if self.object_store.exists(blob) {
+                    let source = self.object_store.get_blob(blob)?;
+                    let source = String::from_utf8_lossy(&source);
+                    if is_entry_point_declaration(&source, span.start_line, span.start_col) {
+                        continue;
+                    }
+                }
+            }
+
+            let path = crate::lsp::snapshot::relative_path(Path::new(&file.id), &self.root)
+                .unwrap_or(file.id);
+            orphans.push(crate::OrphanItem {
+                item,
+                file: path,
+                line: evidence.span.as_ref().map(|span| span.start_line),
+                confidence: evidence.confidence,
+            });
+        }
+        orphans.sort_by(|a, b| (&a.file, a.line, &a.item).cmp(&(&b.file, b.line, &b.item)));
+        Ok(orphans)
+    }
+
+    /// Finds what a change to `target` could affect: see
+    /// [`crate::impact_analysis`].
+    ///
+    /// `target` is a file path relative to the repository root, or the
+    /// name of an item, module or package in the graph. A file stands for
+    /// itself and everything it defines.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] if `target` is neither a file
+    /// nor in the graph, or an error if the index or Cargo snapshot can't
+    /// be read.
+    pub fn impact(
+        &self,
+        target: &str,
+        config: &crate::ImpactConfig,
+    ) -> Result<crate::ImpactReport> {
+        let index = self.index()?;
+        let path = target.trim_start_matches("./");
+        let mut seeds = Vec::new();
+        if self.root.join(path).is_file() || index.lookup_path(path)?.is_some() {
+            let mut files = vec![NodeId {
+                kind: NodeKind::File,
+                id: path.to_string(),
+            }];
+            // Rust analysis names files by absolute path
+            if let Ok(absolute) = self.root.join(path).canonicalize() {
+                files.push(NodeId {
+                    kind: NodeKind::File,
+                    id: absolute.to_string_lossy().into_owned(),
+                });
+            }
+            for file in files {
+                seeds.extend(index.get_edges_from(&file, EdgeLabel::Defines)?);
+                seeds.push(file);
+            }
+        } else {
+            for (kind, label) in [
+                (NodeKind::Item, EdgeLabel::Defines),
+                (NodeKind::Module, EdgeLabel::Defines),
+                (NodeKind::Package, EdgeLabel::DependsOn),
+            ] {
+                let node = NodeId {
+                    kind,
+                    id: target.to_string(),
+                };
+                if !index.get_edges_to(&node, label)?.is_empty()
+                    || !index.get_edges_from(&node, label)?.is_empty()
+                {
+                    seeds.push(node);
+                }
+            }
+            if seeds.is_empty() {
+                return Err(CtxError::InvalidArgument(format!(
+                    "no file or item named '{}' in the graph",
+                    target
+                )));
+            }
+        }
+
+        let cargo: Option<crate::CargoMetadataSnapshot> = self
+            .head()?
+            .cargo_snapshot
+            .map(|id| self.object_store.get_typed(id))
+            .transpose()?;
+        crate::impact::impact_analysis(&index, target, seeds, cargo.as_ref(), &self.root, config)
+    }
+
+    /// The Rust workspace roots to analyze: `analysis.roots` under the
+    /// repository root, or the repository root itself if none are set.
+    fn analysis_roots(&self) -> Result<Vec<PathBuf>> {
+        let config = crate::config::Config::load(&self.ctx_dir())?;
+        if config.analysis.roots.is_empty() {
+            return Ok(vec![self.root.clone()]);
+        }
+        config
+            .analysis
+            .roots
+            .iter()
+            .map(|root| {
+                let path = root
+                    .split('/')
+                    .filter(|part| !part.is_empty() && *part != ".")
+                    .fold(self.root.clone(), |path, part| path.join(part));
+                if path.is_dir() {
+                    Ok(path)
+                } else {
+                    Err(CtxError::InvalidArgument(format!(
+                        "analysis root '{}' is not a directory",
+                        root
+                    )))
+                }
+            })
+            .collect()
+    }
+
+    /// The analysis root holding `path`: the innermost one containing it,
+    /// or the repository root if none does.
+    fn analysis_root_for(&self, path: &Path) -> Result<PathBuf> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        Ok(self
+            .analysis_roots()?
+            .into_iter()
+            .filter(|root| path.starts_with(root.canonicalize().unwrap_or_else(|_| root.clone())))
+            .max_by_key(|root| root.components().count())
+            .unwrap_or_else(|| self.root.clone()))
+    }
+
+    /// Find all Rust source files under `root` that aren't ignored, by the
+    /// repository's rules or by a `.ctxignore` in `root`. Directories in
+    /// `skip`, such as other analysis roots nested inside, aren't entered.
+    fn find_rust_files(&self, root: &Path, skip: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let root_rules = if root == self.root {
+            IgnoreRules::default()
+        } else {
+            IgnoreRules::load(root, &[])?
+        };
+        let root_prefix = root
+            .strip_prefix(&self.root)
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let join = |prefix: &str, name: &str| {
+            if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", prefix, name)
+            }
+        };
+
+        let mut files = Vec::new();
+        // Paths relative to the repository root and to `root`
+        let mut dirs = vec![(root.to_path_buf(), root_prefix, String::new())];
+        while let Some((dir, prefix, root_relative_prefix)) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let relative = join(&prefix, &name);
+                let root_relative = join(&root_relative_prefix, &name);
+
+                if path.is_dir() {
+                    if !self.ignore_rules.is_ignored_dir(&relative)
+                        && !root_rules.is_ignored_dir(&root_relative)
+                        && !skip.contains(&path)
+                    {
+                        dirs.push((path, relative, root_relative));
+                    }
+                } else if path.extension().and_then(|e| e.to_str()) == Some("rs")
+                    && !self.ignore_rules.is_ignored(&relative)
+                    && !root_rules.is_ignored(&root_relative)
+                {
+                    files.push(path);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Analyze Cargo workspace and extract dependency graph.
+    ///
+    /// Runs `cargo metadata`, parses the output, extracts edges,
+    /// and creates a commit with the edge batch.
+    ///
+    /// # Returns
+    ///
+    /// CargoAnalysisReport with statistics about what was analyzed.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - cargo is not installed
+    /// - No Cargo.toml found
+    /// - cargo metadata fails
+    /// - Edge storage fails
+    /// - The exec policy refuses to run cargo
+    pub fn analyze_cargo(&mut self) -> Result<crate::cargo::CargoAnalysisReport> {
+        self.analyze_cargo_with(false)
+    }
+
+    /// Analyze the Cargo workspace with its full resolved dependency graph.
+    ///
+    /// Like [`CtxRepo::analyze_cargo`], but external crates are included:
+    /// every resolved dependency becomes a versioned Package → DependsOn →
+    /// Package edge, and the snapshot records the features each package
+    /// ends up with, for [`crate::CargoMetadataSnapshot::why`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors [`CtxRepo::analyze_cargo`] does.
+    pub fn analyze_cargo_full(&mut self) -> Result<crate::cargo::CargoAnalysisReport> {
+        self.analyze_cargo_with(true)
+    }
+
+    fn analyze_cargo_with(&mut self, full: bool) -> Result<crate::cargo::CargoAnalysisReport> {
+        self.require(Capability::Commit, "analyze")?;
+        use crate::cargo::{
+            extract_cargo_edges, parse_cargo_metadata, run_cargo_metadata, run_cargo_metadata_full,
+        };
+        use crate::types::EdgeBatch;
+
+        // Run cargo metadata (reports CargoNotFound if cargo is missing)
+        let json = if full {
+            run_cargo_metadata_full(&self.root, &self.exec_policy)?
+        } else {
+            run_cargo_metadata(&self.root, &self.exec_policy)?
+        };
+        let mut snapshot = parse_cargo_metadata(&json)?;
+        snapshot.record_manifest_hashes();
+        let members: Vec<_> = snapshot
+            .packages
+            .iter()
+            .filter(|p| snapshot.is_workspace_member(p))
+            .collect();
+        let resolved_packages = snapshot.resolve.as_ref().map_or(0, |r| r.nodes.len());
+
+        // Store snapshot as typed object
+        let snapshot_id = self.object_store.put_typed(&snapshot)?;
+
+        // Extract edges
+        let commit_id = self.head_id()?;
+        let edges = extract_cargo_edges(&snapshot, commit_id);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before Unix epoch")
+            .as_secs();
+
+        // Store edge batch
+        let edge_batch = EdgeBatch {
+            edges: edges.clone(),
+            created_at: now,
+        };
+        let batch_id = self.object_store.put_typed(&edge_batch)?;
+
+        // Create commit
+        let parent_id = self.head_id()?;
+        let parent_commit: Commit = self.object_store.get_typed(parent_id)?;
+
+        let commit = Commit {
+            parents: vec![parent_id],
+            timestamp_unix: now,
+            message: if full {
+                format!(
+                    "Cargo analysis: {} packages, {} targets, {} resolved dependencies",
+                    members.len(),
+                    members.iter().map(|p| p.targets.len()).sum::<usize>(),
+                    resolved_packages
+                )
+            } else {
+                format!(
+                    "Cargo analysis: {} packages, {} targets",
+                    members.len(),
+                    members.iter().map(|p| p.targets.len()).sum::<usize>()
+                )
+            },
+            root_tree: parent_commit.root_tree,
+            edge_batches: vec![batch_id],
+            narrative_refs: vec![],
+            cargo_snapshot: Some(snapshot_id), // Store snapshot reference
+            rust_snapshot: parent_commit.rust_snapshot,
+            diagnostics_snapshot: parent_commit.diagnostics_snapshot,
+            glossary: parent_commit.glossary,
+            decisions: parent_commit.decisions,
+            facts: parent_commit.facts,
+            qa: parent_commit.qa,
+            transcript: None,
+            commit_type: None,
+            author: self.identity.clone(),
+            task: None,
+            tags: Default::default(),
+        };
+
+        let new_commit_id = self.object_store.put_typed(&commit)?;
+
+        // Update refs
+        self.advance_main(new_commit_id, &commit.message)?;
+
+        // Load edge batches before we borrow the index mutably
+        let edge_batches: Vec<_> = commit
+            .edge_batches
+            .iter()
+            .map(|id| self.object_store.get_typed(*id))
+            .collect::<Result<_>>()?;
+
+        // Incrementally add edges from this commit to the index
+        self.writable_index()?
+            .add_commit_edges(new_commit_id, &commit, &edge_batches)?;
+
+        let report = crate::cargo::CargoAnalysisReport {
+            packages_found: members.len(),
+            targets_found: members.iter().map(|p| p.targets.len()).sum(),
+            dependencies_found: members.iter().map(|p| p.dependencies.len()).sum(),
+            edges_generated: edges.len(),
+            resolved_packages,
+            snapshot_id,
+            edge_batch_id: batch_id,
+            commit_id: new_commit_id,
+        };
+        self.notify_analysis("cargo", &report, new_commit_id, &commit.message);
+        Ok(report)
+    }
+
+    /// Records an analysis commit and runs `post-analyze` and `post-commit`
+    /// hooks for it.
+    fn notify_analysis(
+        &self,
+        analysis: &str,
+        report: &impl Serialize,
+        commit_id: ObjectId,
+        message: &str,
+    ) {
+        self.record_event(EventKind::Analyzed {
+            analysis: analysis.to_string(),
+            commit_id: commit_id.as_hex(),
+        });
+        self.notify_hooks(
+            HookEvent::PostAnalyze,
+            serde_json::json!({ "analysis": analysis, "report": report }),
+        );
+        self.notify_commit(commit_id, message);
+    }
+
+    /// Acquires exclusive lock on repository.
+    ///
+    /// The lock file contains the PID of the owning process. If the lock is held
+    /// by a dead process (stale lock), it will be automatically cleaned up.
+    fn acquire_lock(&self) -> Result<LockGuard> {
+        let lock_path = self.ctx_dir().join("LOCK");
+        self.acquire_lock_with_retry(&lock_path, 0)
+    }
+
+    /// Internal helper for lock acquisition with retry count.
+    fn acquire_lock_with_retry(&self, lock_path: &Path, retry_count: u32) -> Result<LockGuard> {
+        // Limit retries to prevent infinite loops
+        if retry_count > 2 {
+            return Err(CtxError::RepositoryLocked);
+        }
+
+        // Try to create the lock file exclusively (fails if already exists)
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(lock_path)
+        {
+            Ok(mut file) => {
+                // Write our PID to the lock file
+                let pid = std::process::id();
+                writeln!(file, "{}", pid).map_err(repository_lock_error)?;
+                file.flush().map_err(repository_lock_error)?;
+
+                // Acquire file lock for additional safety
+                file.try_lock_exclusive()
+                    .map_err(|_| CtxError::RepositoryLocked)?;
+
+                Ok(LockGuard {
+                    file: Some(file),
+                    path: lock_path.to_path_buf(),
+                })
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                // Lock file exists - check if the holder is still alive
+                self.handle_existing_lock(lock_path, retry_count)
+            }
+            Err(e) => Err(repository_lock_error(e)),
+        }
+    }
+
+    /// Handle the case where a lock file already exists.
+    fn handle_existing_lock(&self, lock_path: &Path, retry_count: u32) -> Result<LockGuard> {
+        // Try to read the PID from the lock file
+        match fs::read_to_string(lock_path) {
+            Ok(content) => {
+                if let Ok(pid) = content.trim().parse::<u32>() {
+                    if is_process_alive(pid) {
+                        // Process is still alive - lock is legitimately held
+                        return Err(CtxError::SessionLockHeld { pid });
+                    }
+
+                    // Process is dead - stale lock
+                    warn!(
+                        pid = pid,
+                        "Detected stale lock from dead process, cleaning up"
+                    );
+
+                    // Remove the stale lock, unless another process took
+                    // it over meanwhile, and retry
+                    remove_lock_if_owned(lock_path, Some(pid))?;
+
+                    // Retry acquiring the lock
+                    return self.acquire_lock_with_retry(lock_path, retry_count + 1);
+                }
+
+                // Lock file exists but has invalid content
+                // This could be a race condition or corruption - try to clean up
+                warn!("Lock file has invalid content, attempting cleanup");
+                let _ = fs::remove_file(lock_path);
+                self.acquire_lock_with_retry(lock_path, retry_count + 1)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Lock file was removed between our check and read - retry
+                self.acquire_lock_with_retry(lock_path, retry_count + 1)
+            }
+            Err(_) => {
+                // Can't read lock file - assume it's locked
+                Err(CtxError::RepositoryLocked)
+            }
+        }
+    }
+
+    /// Describes the repository LOCK, or `None` if the repository is unlocked.
+    ///
+    /// The PID is unreadable while another process holds the lock on
+    /// platforms with mandatory file locking.
+    pub fn lock_status(&self) -> Result<Option<LockStatus>> {
+        let lock_path = self.ctx_dir().join("LOCK");
+        let modified = match fs::metadata(&lock_path) {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(CtxError::Io(e)),
+        };
+        let pid = fs::read_to_string(&lock_path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok());
+        let age_secs = modified
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map(|age| age.as_secs());
+
+        Ok(Some(LockStatus {
+            pid,
+            age_secs,
+            holder_alive: pid.is_some_and(is_process_alive),
+            owned: pid == Some(std::process::id()),
+        }))
+    }
+
+    /// Removes a LOCK left behind by a process that died without releasing it.
+    ///
+    /// Returns the removed lock, or `None` if there was none. A lock whose
+    /// holder still appears to be running is only removed with `force`,
+    /// which covers a PID reused by an unrelated process. A lock still held
+    /// open by a live process is never removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SessionLockHeld` if the holder is alive and `force` is not
+    /// set, and `RepositoryLocked` if the lock file is still held open.
+    pub fn unlock(&self, force: bool) -> Result<Option<LockStatus>> {
+        self.require(Capability::Commit, "unlock")?;
+        let Some(status) = self.lock_status()? else {
+            return Ok(None);
+        };
+        let lock_path = self.ctx_dir().join("LOCK");
+
+        // The OS releases the file lock when its holder exits, so a held
+        // file lock means the session really is in progress
+        if lock_file_in_use(&lock_path) {
+            return Err(match status.pid {
+                Some(pid) => CtxError::SessionLockHeld { pid },
+                None => CtxError::RepositoryLocked,
+            });
+        }
+        if let (Some(pid), true) = (status.pid, status.holder_alive && !force) {
+            return Err(CtxError::SessionLockHeld { pid });
+        }
+
+        debug!(pid = ?status.pid, age_secs = ?status.age_secs, force, "Removing repository lock");
+        Ok(remove_lock_if_owned(&lock_path, status.pid)?.then_some(status))
+    }
+
+    /// Run garbage collection on the repository.
+    ///
+    /// See `crate::gc::gc` for details.
+    ///
+    /// Read-content retention rewrites the staging chain, so it only runs
+    /// while no other process holds a session; otherwise it is skipped and
+    /// the report says why.
+    pub fn gc(&mut self, config: crate::gc::GcConfig) -> Result<crate::gc::GcReport> {
+        self.gc_with_progress(config, &Progress::default())
+    }
+
+    /// Run garbage collection with progress reporting and cancellation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::Cancelled`] if `progress` is cancelled, having
+    /// deleted only unreachable objects; `changed` says whether any were
+    /// deleted or the staging chain was already rewritten.
+    pub fn gc_with_progress(
+        &mut self,
+        mut config: crate::gc::GcConfig,
+        progress: &Progress,
+    ) -> Result<crate::gc::GcReport> {
+        self.require(Capability::Commit, "gc")?;
+        // Rewriting another process's staging chain would fork its session
+        let mut skipped = None;
+        let _lock = if config.read_content_retention_days.is_some()
+            && !config.dry_run
+            && self.active_session.is_none()
+        {
+            match self.acquire_lock() {
+                Ok(lock) => Some(lock),
+                Err(e) => {
+                    config.read_content_retention_days = None;
+                    skipped = Some(e.to_string());
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let dry_run = config.dry_run;
+        let mut report = crate::gc::gc(&self.refs, &mut self.object_store, config, progress)?;
+        report.retention.skipped = skipped;
+        if let Some(session) = &mut self.active_session {
+            if let Some(stage) = self.refs.read_stage()? {
+                session.set_staging_head(stage);
+            }
+        }
+        if !dry_run && self.dry_run.is_none() {
+            // Where automatic GC starts counting commits again
+            if let Ok(head) = self.head_id() {
+                let path = self.ctx_dir().join(crate::gc::LAST_GC_FILE);
+                if let Err(e) = fs::write(&path, format!("{}\n", head.as_hex())) {
+                    warn!(error = %e, "Failed to record the HEAD of this GC");
+                }
+            }
+        }
+        self.record_gc(&report, dry_run);
+        Ok(report)
+    }
+
+    /// Runs GC if one of the `gc.auto_*` thresholds in the config is
+    /// crossed, mirroring `git gc --auto`: enough commits since the last GC,
+    /// or an object store estimated to hold too many objects or bytes.
+    ///
+    /// GC keeps the configured grace period, so objects a concurrent
+    /// session just wrote survive, and is skipped while another process
+    /// holds the repository. Compaction calls this on its own; see
+    /// [`CtxRepo::take_auto_gc_report`].
+    ///
+    /// Returns `None` if `gc.auto_gc` is off, no threshold is crossed, or a
+    /// dry run or historical view is in progress.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config can't be loaded, the history or the
+    /// object store can't be read, or GC fails.
+    pub fn auto_gc(&mut self) -> Result<Option<crate::gc::AutoGcReport>> {
+        let settings = crate::config::Config::load(&self.ctx_dir())?.gc;
+        if !settings.auto_gc || self.dry_run.is_some() || self.view.is_some() {
+            return Ok(None);
+        }
+        let Some(reason) = self.auto_gc_reason(&settings)? else {
+            return Ok(None);
+        };
+
+        let busy = self
+            .lock_status()?
+            .filter(|lock| lock.holder_alive && !lock.owned);
+        if let Some(lock) = busy {
+            let holder = lock.pid.map_or_else(
+                || "a running process".to_string(),
+                |pid| format!("process {}", pid),
+            );
+            return Ok(Some(crate::gc::AutoGcReport {
+                reason,
+                skipped: Some(format!("repository is held by {}", holder)),
+                gc: None,
+            }));
+        }
+
+        let report = self.gc(crate::gc::GcConfig {
+            dry_run: false,
+            grace_period_days: settings.grace_period_days,
+            aggressive: false,
+            read_content_retention_days: settings.read_content_retention_days,
+            reflog_expire_days: settings.reflog_expire_days,
+        })?;
+        Ok(Some(crate::gc::AutoGcReport {
+            reason,
+            skipped: None,
+            gc: Some(report),
+        }))
+    }
+
+    /// Takes the report of the automatic GC the last compaction ran, if it
+    /// ran one.
+    pub fn take_auto_gc_report(&mut self) -> Option<crate::gc::AutoGcReport> {
+        self.auto_gc_report.take()
+    }
+
+    /// The first `gc.auto_*` threshold crossed, described for the report.
+    fn auto_gc_reason(&self, settings: &crate::config::GcConfig) -> Result<Option<String>> {
+        if settings.auto_commits > 0 {
+            let last_gc = fs::read_to_string(self.ctx_dir().join(crate::gc::LAST_GC_FILE))
+                .ok()
+                .and_then(|hex| ObjectId::from_hex(hex.trim()).ok());
+            // Walk no further than the threshold
+            let mut commits = 0;
+            let mut next = Some(self.head_id()?);
+            while let Some(id) = next {
+                if Some(id) == last_gc || commits >= settings.auto_commits {
+                    break;
+                }
+                commits += 1;
+                let commit: Commit = self.object_store.get_typed(id)?;
+                next = self.object_store.first_parent(&commit);
+            }
+            if commits >= settings.auto_commits {
+                return Ok(Some(format!(
+                    "{} commits since the last GC (gc.auto_commits = {})",
+                    commits, settings.auto_commits
+                )));
+            }
+        }
+
+        let (objects, bytes) = crate::gc::estimate_store(&self.object_store)?;
+        if settings.auto_objects > 0 && objects >= settings.auto_objects {
+            return Ok(Some(format!(
+                "about {} objects (gc.auto_objects = {})",
+                objects, settings.auto_objects
+            )));
+        }
+        if settings.auto_size_mb > 0 && bytes >= settings.auto_size_mb * 1024 * 1024 {
+            return Ok(Some(format!(
+                "about {} MB of objects (gc.auto_size_mb = {})",
+                bytes / (1024 * 1024),
+                settings.auto_size_mb
+            )));
+        }
+        Ok(None)
+    }
+
+    fn record_gc(&self, report: &crate::gc::GcReport, dry_run: bool) {
+        self.record_event(EventKind::GcCompleted {
+            objects_deleted: report.objects_deleted,
+            bytes_freed: report.bytes_freed,
+            dry_run,
+        });
+    }
+
+    /// Verify repository integrity.
+    ///
+    /// See `crate::verify::verify` for details.
+    pub fn verify(
+        &self,
+        config: crate::verify::VerifyConfig,
+    ) -> Result<crate::verify::VerifyReport> {
+        crate::verify::verify(&self.refs, &self.object_store, config)
+    }
+
+    /// Checks the next `max_objects` objects in ID order after the ones
+    /// the previous call checked, for continuous integrity checking that
+    /// never scans the whole store at once.
+    ///
+    /// The cursor is kept in `.ctx/verify.cursor`; once a batch reaches the
+    /// end of the store the next one starts over. With `dry_run`, the cursor
+    /// isn't advanced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object store can't be listed or the cursor
+    /// can't be saved.
+    pub fn verify_incremental(
+        &self,
+        max_objects: usize,
+        dry_run: bool,
+    ) -> Result<crate::verify::VerifyReport> {
+        let cursor_path = self.ctx_dir().join(crate::verify::VERIFY_CURSOR_FILE);
+        let resume_after = fs::read_to_string(&cursor_path)
+            .ok()
+            .and_then(|hex| ObjectId::from_hex(hex.trim()).ok());
+
+        let report = self.verify(crate::verify::VerifyConfig {
+            check_objects: true,
+            check_refs: false,
+            check_commits: false,
+            resume_after,
+            max_objects: Some(max_objects),
+            ..Default::default()
+        })?;
+
+        if !dry_run {
+            match report.next_cursor {
+                Some(next) => fs::write(&cursor_path, format!("{}\n", next.as_hex()))?,
+                None if cursor_path.exists() => fs::remove_file(&cursor_path)?,
+                None => {}
+            }
+        }
+        Ok(report)
+    }
+
+    /// Accounts for object store usage by category and reachability, with
+    /// the `top` largest blobs and paths.
+    ///
+    /// See [`crate::du::storage_report`] for how objects are categorized.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if refs or the object store can't be listed.
+    pub fn storage_report(&self, top: usize) -> Result<crate::du::StorageReport> {
+        crate::du::storage_report(&self.refs, &self.object_store, top)
+    }
+
+    /// Archives the refs and narrative into `.ctx/backups`, keeping the
+    /// newest `backup.keep` archives (at least this one).
+    ///
+    /// Compaction does this on its own; see [`crate::backup`] for what's
+    /// archived.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config can't be loaded or the archive can't
+    /// be written.
+    pub fn create_backup(&self) -> Result<BackupInfo> {
+        self.require(Capability::Commit, "backup")?;
+        let keep = crate::config::Config::load(&self.ctx_dir())?.backup.keep;
+        backup::create(&self.ctx_dir(), self.now_unix(), keep.max(1))
+    }
+
+    /// Takes the backup that follows a compaction, unless `backup.keep` is
+    /// 0.
+    fn backup_after_compaction(&self) -> Result<()> {
+        let keep = crate::config::Config::load(&self.ctx_dir())?.backup.keep;
+        if keep > 0 {
+            backup::create(&self.ctx_dir(), self.now_unix(), keep)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the backups in `.ctx/backups`, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or an archive can't be read.
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        backup::list(&self.ctx_dir())
+    }
+
+    /// Replaces the refs and narrative with those in backup `name`, then
+    /// rebuilds the index for the restored HEAD.
+    ///
+    /// The files being replaced are backed up first; the report names that
+    /// backup, which restores them again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::SessionAlreadyActive`] with a session open,
+    /// [`CtxError::RepositoryLocked`] if another process holds the
+    /// repository, [`CtxError::InvalidArgument`] if there's no such backup
+    /// or it has no HEAD, and [`CtxError::ObjectNotFound`] if its HEAD
+    /// commit isn't in the object store.
+    pub fn restore_backup(&mut self, name: &str) -> Result<RestoreReport> {
+        self.require(Capability::Commit, "restore backup")?;
+        if let Some(session) = &self.active_session {
+            return Err(CtxError::SessionAlreadyActive(
+                session.task_description().to_string(),
+            ));
+        }
+        let _lock = self.acquire_lock()?;
+
+        let ctx_dir = self.ctx_dir();
+        let files = backup::read(&ctx_dir, name)?;
+        let head_after = backup::head(&files).ok_or_else(|| {
+            CtxError::InvalidArgument(format!("backup '{}' has no readable HEAD", name))
+        })?;
+        if !self.object_store.exists(head_after) {
+            return Err(CtxError::ObjectNotFound(head_after.as_hex()));
+        }
+
+        let head_before = self.refs.read_head().ok();
+        let main_before = self.refs.read_ref("main").ok();
+        let previous = self.create_backup()?;
+        backup::restore_files(&ctx_dir, &files)?;
+        let reason = format!("restore: {}", name);
+        self.refs
+            .log_move("HEAD", head_before, head_after, &reason)?;
+        if let Ok(main_after) = self.refs.read_ref("main") {
+            self.refs
+                .log_move("main", main_before, main_after, &reason)?;
+        }
+        self.rebuild_index()?;
+
+        Ok(RestoreReport {
+            backup: name.strip_suffix(".tar.zst").unwrap_or(name).to_string(),
+            previous: previous.name,
+            head_before,
+            head_after,
+            files: files.len(),
+        })
+    }
+
+    /// Copies this repository into a new one at `dest`, which mustn't
+    /// contain a `.ctx` yet, and builds the new one's index.
+    ///
+    /// Objects reachable from the refs are hard-linked where possible and
+    /// copied otherwise; refs, the narrative, hooks and configuration are
+    /// copied. With a `depth`, only the last `depth` commits of HEAD's
+    /// first-parent history are kept, and the new repository records the
+    /// parents it left out as grafts, where its history ends.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] for a depth of 0, and an I/O
+    /// error if `dest` already has a repository or the copy fails, in which
+    /// case the partial `.ctx` is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ctx_core::CtxRepo;
+    ///
+    /// let repo = CtxRepo::open(".").unwrap();
+    /// let report = repo.clone_to("../experiment", Some(10)).unwrap();
+    /// println!("{} objects linked", report.linked);
+    /// ```
+    pub fn clone_to(&self, dest: impl AsRef<Path>, depth: Option<usize>) -> Result<CloneReport> {
+        self.require(Capability::Commit, "clone")?;
+        let dest = dest.as_ref();
+        let report = clone::clone(&self.ctx_dir(), &self.refs, &self.object_store, dest, depth)?;
+        CtxRepo::open(dest)?.rebuild_index()?;
+        Ok(report)
+    }
+
+    /// Folds the commits made before `keep_since` (Unix seconds) into one
+    /// snapshot commit that the kept history is grafted onto, so GC can
+    /// reclaim them, then rebuilds the index.
+    ///
+    /// HEAD is always kept. The snapshot has the tree of the newest pruned
+    /// commit and the edge batches of all of them. The objects left for GC
+    /// are archived first; [`CtxRepo::undo_prune`] with the report's
+    /// `backup` restores them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::SessionAlreadyActive`] with a session open and
+    /// [`CtxError::RepositoryLocked`] if another process holds the
+    /// repository.
+    pub fn prune_history(&mut self, keep_since: u64) -> Result<PruneReport> {
+        self.require(Capability::Commit, "prune history")?;
+        if let Some(session) = &self.active_session {
+            return Err(CtxError::SessionAlreadyActive(
+                session.task_description().to_string(),
+            ));
+        }
+        let _lock = self.acquire_lock()?;
+
+        let report = prune::prune(
+            &self.ctx_dir(),
+            &self.refs,
+            &self.object_store,
+            keep_since,
+            self.now_unix(),
+        )?;
+        if report.snapshot.is_some() {
+            self.rebuild_index()?;
+        }
+        Ok(report)
+    }
+
+    /// Undoes the prune whose archive is `backup`: puts back the objects GC
+    /// may have reclaimed since and the grafts from before it, then
+    /// rebuilds the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::InvalidArgument`] if there's no such archive,
+    /// [`CtxError::CorruptedObject`] if it can't be read, and the errors of
+    /// [`CtxRepo::prune_history`] for a busy repository.
+    pub fn undo_prune(&mut self, backup: &str) -> Result<PruneUndoReport> {
+        self.require(Capability::Commit, "undo prune")?;
+        if let Some(session) = &self.active_session {
+            return Err(CtxError::SessionAlreadyActive(
+                session.task_description().to_string(),
+            ));
+        }
+        let _lock = self.acquire_lock()?;
+
+        let report = prune::undo(&self.ctx_dir(), &self.object_store, backup)?;
+        self.rebuild_index()?;
+        Ok(report)
+    }
+
+    /// Summarizes HEAD, the active session, index freshness, and object
+    /// store usage.
+    ///
+    /// The GC estimate is a dry run under the configured grace period, so
+    /// this walks every reachable object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if HEAD, the staging chain, or the object store
+    /// can't be read. An index that fails to open is reported as
+    /// [`IndexFreshness::Unreadable`] rather than an error.
+    pub fn status(&mut self) -> Result<StatusReport> {
+        let config = crate::config::Config::load(&self.ctx_dir())?;
+
+        let head_id = self.head_id()?;
+        let head_commit = self.head()?;
+        let head = HeadStatus {
+            id: head_id,
+            message: head_commit.message.clone(),
+            timestamp_unix: head_commit.timestamp_unix,
+            age_secs: self.now_unix().saturating_sub(head_commit.timestamp_unix),
+        };
+
+        let session = self.session_status()?;
+
+        let index = self.index_freshness(head_id);
+        let cargo = self.cargo_status()?;
+
+        let mut objects = ObjectStatus::default();
+        for (_, size, _) in self.object_store.list_all_objects()? {
+            objects.total += 1;
+            objects.total_bytes += size;
+        }
+        // Not self.gc(), so the estimate isn't journaled as a GC run
+        let estimate = crate::gc::GcConfig {
+            dry_run: true,
+            grace_period_days: config.gc.grace_period_days,
+            aggressive: false,
+            read_content_retention_days: config.gc.read_content_retention_days,
+            reflog_expire_days: config.gc.reflog_expire_days,
+        };
+        let gc = crate::gc::gc(
+            &self.refs,
+            &mut self.object_store,
+            estimate,
+            &Progress::default(),
+        )?;
+        objects.reachable = gc.objects_reachable;
+        objects.collectable = gc.objects_deleted;
+        objects.collectable_bytes = gc.bytes_freed;
+
+        Ok(StatusReport {
+            head,
+            session,
+            index,
+            cargo,
+            objects,
+            lock: self.lock_status()?,
+        })
+    }
+
+    /// Rates the repository's health: index freshness, an orphaned STAGE,
+    /// corruption in a sample of objects, the share of abandoned sessions
+    /// and storage growth, against the `health` thresholds in the config.
+    ///
+    /// See [`HealthReport`](crate::HealthReport) for how each is rated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config, HEAD's history or the object store
+    /// can't be read. Problems with the index and STAGE are rated rather
+    /// than returned.
+    pub fn health(&mut self) -> Result<crate::health::HealthReport> {
+        let config = crate::config::Config::load(&self.ctx_dir())?.health;
+        let index = self.index_freshness(self.head_id()?);
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(self.now_unix());
+        crate::health::check(&self.refs, &self.object_store, index, &config, now)
+    }
+
+    /// Checks HEAD's Cargo snapshot against the manifests on disk.
+    ///
+    /// Returns None if HEAD has no Cargo snapshot. The snapshot is stale if
+    /// a `Cargo.toml` or `Cargo.lock` changed since it was taken; run
+    /// [`CtxRepo::analyze_cargo`] again to refresh it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if HEAD or the snapshot can't be read.
+    pub fn cargo_status(&self) -> Result<Option<CargoStatus>> {
+        let Some(snapshot_id) = self.head()?.cargo_snapshot else {
+            return Ok(None);
+        };
+        let snapshot: crate::cargo::CargoMetadataSnapshot =
+            self.object_store.get_typed(snapshot_id)?;
+        let changed_manifests = snapshot.changed_manifests();
+        Ok(Some(CargoStatus {
+            snapshot_id,
+            stale: !changed_manifests.is_empty(),
+            changed_manifests,
+        }))
+    }
+
+    /// Re-runs Cargo analysis if HEAD's snapshot is stale, keeping its
+    /// mode: snapshots with a resolved graph are refreshed in full.
+    ///
+    /// Returns true if the analysis ran.
+    fn refresh_stale_cargo(&mut self) -> Result<bool> {
+        let Some(snapshot_id) = self.head()?.cargo_snapshot else {
+            return Ok(false);
+        };
+        let snapshot: crate::cargo::CargoMetadataSnapshot =
+            self.object_store.get_typed(snapshot_id)?;
+        if snapshot.changed_manifests().is_empty() {
+            return Ok(false);
+        }
+        self.analyze_cargo_with(snapshot.resolve.is_some())?;
+        Ok(true)
+    }
+
+    /// Summarizes the active session, if any.
+    ///
+    /// Staleness uses the configured `session.stale_session_threshold_hours`
+    /// and `session.auto_compact_threshold_hours`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config or the staging chain can't be read.
+    pub fn session_status(&self) -> Result<Option<SessionStatus>> {
+        let Some(session) = &self.active_session else {
+            return Ok(None);
+        };
+        let config = crate::config::Config::load(&self.ctx_dir())?;
+
+        let chain = staging::walk_staging_chain(
+            session.staging_head(),
+            session.base_commit(),
+            &self.object_store,
+        )?;
+        let stale_config = config.session.stale_config();
+        let mut observations = staging::collect_observations(
+            session.staging_head(),
+            session.base_commit(),
+            &self.object_store,
+        )?;
+        observations.extend_from_slice(session.pending_observations());
+        Ok(Some(SessionStatus {
+            session_id: session.session_id().to_string(),
+            task: session.task_description().to_string(),
+            state: session.state().clone(),
+            steps: session.step_count(),
+            staging_chain_len: chain.len(),
+            staleness: self.check_stale_session(&stale_config),
+            actors: ActorActivity::by_actor(&observations),
+        }))
+    }
+
+    /// Counts commits reachable from `head_id` that the index hasn't seen.
+    ///
+    /// The walk stops at indexed commits: their ancestors were indexed
+    /// with them.
+    pub(crate) fn index_freshness(&mut self, head_id: ObjectId) -> IndexFreshness {
+        if self.index_slot().is_none() {
+            match Index::open_shared(self.index_path()) {
+                Ok(Some(index)) => self.set_index(index),
+                Ok(None) => return IndexFreshness::Missing,
+                Err(e) => {
+                    return IndexFreshness::Unreadable {
+                        reason: e.to_string(),
+                    }
+                }
+            }
+        }
+        let index = self.index.get_mut().unwrap_or_else(|e| e.into_inner());
+        let index = index.as_ref().unwrap();
+
+        let mut behind = 0;
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([head_id]);
+        while let Some(id) = queue.pop_front() {
+            if !seen.insert(id) {
+                continue;
+            }
+            match index.get_commit_info(id) {
+                Ok(Some(_)) => continue,
+                Ok(None) => behind += 1,
+                Err(e) => {
+                    return IndexFreshness::Unreadable {
+                        reason: e.to_string(),
+                    }
+                }
+            }
+            if let Ok(commit) = self.object_store.get_typed::<Commit>(id) {
+                queue.extend(self.object_store.parents(&commit));
+            }
+        }
+
+        if behind == 0 {
+            IndexFreshness::Current
+        } else {
+            IndexFreshness::Behind { commits: behind }
+        }
+    }
+}
+
+/// Shared access to a repository's index, returned by [`CtxRepo::index`].
+pub struct IndexGuard<'a>(RwLockReadGuard<'a, Option<Index>>);
+
+impl std::ops::Deref for IndexGuard<'_> {
+    type Target = Index;
+
+    fn deref(&self) -> &Index {
+        // Only constructed once the slot is filled
+        self.0.as_ref().expect("index loaded")
+    }
+}
+
+/// RAII guard for repository lock.
+///
+/// Holds an exclusive lock on the repository's LOCK file. The lock is
+/// automatically released when dropped, and the lock file is removed.
+struct LockGuard {
+    /// The open lock file (holds the file lock).
+    /// Wrapped in Option to allow taking ownership in Drop.
+    file: Option<File>,
+    /// Path to the lock file (for cleanup on drop).
+    path: PathBuf,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        // Take ownership of the file to close it (releases the lock)
+        if let Some(file) = self.file.take() {
+            drop(file);
+        }
+
+        // Remove the lock file - ignore errors (file might already be gone)
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Loads the exec policy from the repository config, auditing to
+/// `logs/exec.jsonl`.
+fn load_exec_policy(ctx_dir: &Path) -> Result<ExecPolicy> {
+    let config = crate::config::Config::load(ctx_dir)?;
+    Ok(ExecPolicy::new(config.exec).with_audit_log(ctx_dir.join("logs/exec.jsonl")))
+}
+
+fn load_ignore_rules(root: &Path, ctx_dir: &Path) -> Result<IgnoreRules> {
+    let config = crate::config::Config::load(ctx_dir)?;
+    IgnoreRules::load(root, &config.storage.ignore)
+}
+
+/// Wraps a failure to create or write the repository LOCK.
+fn repository_lock_error(e: std::io::Error) -> CtxError {
+    CtxError::LockError {
+        kind: crate::error::LockKind::Repository,
+        reason: e.to_string(),
+    }
+}
+
+/// Removes the repository LOCK at `lock_path` if it still belongs to `pid`
+/// and isn't held open. Returns false if it was already gone.
+///
+/// The lock is renamed aside before it is checked, so a lock taken by
+/// another process after the caller looked at it is never removed; it is
+/// put back instead.
+///
+/// # Errors
+///
+/// Returns `SessionLockHeld` or `RepositoryLocked` if the lock changed
+/// hands.
+fn remove_lock_if_owned(lock_path: &Path, pid: Option<u32>) -> Result<bool> {
+    let aside = lock_path.with_extension(format!("{}.unlock", std::process::id()));
+    match fs::rename(lock_path, &aside) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        // Windows refuses to rename a file that is held open
+        Err(_) if lock_file_in_use(lock_path) => return Err(CtxError::RepositoryLocked),
+        Err(e) => return Err(repository_lock_error(e)),
+    }
+
+    let owner = fs::read_to_string(&aside)
+        .ok()
+        .and_then(|content| content.trim().parse::<u32>().ok());
+    if owner == pid && !lock_file_in_use(&aside) {
+        fs::remove_file(&aside).map_err(repository_lock_error)?;
+        return Ok(true);
+    }
+
+    // Linking fails rather than replace a lock taken since the rename
+    let restored = fs::hard_link(&aside, lock_path);
+    let _ = fs::remove_file(&aside);
+    restored.map_err(repository_lock_error)?;
+    Err(match owner {
+        Some(pid) => CtxError::SessionLockHeld { pid },
+        None => CtxError::RepositoryLocked,
+    })
+}
+
+/// Whether another open handle holds the file lock on `path`.
+fn lock_file_in_use(path: &Path) -> bool {
+    match File::open(path) {
+        Ok(file) => {
+            let held = file.try_lock_exclusive().is_err();
+            if !held {
+                let _ = FileExt::unlock(&file);
+            }
+            held
+        }
+        // Unreadable while locked on Windows
+        Err(e) => e.kind() != std::io::ErrorKind::NotFound,
+    }
+}
+
+/// Check if a process with the given PID is still alive.
+///
+/// On Linux, uses /proc/{pid}/stat to check process existence.
+/// On other Unix systems, uses /proc/{pid} directory existence.
+/// On Windows, opens the process and checks it hasn't exited.
+/// Elsewhere, conservatively assumes the process is alive.
+#[cfg(target_os = "linux")]
+fn is_process_alive(pid: u32) -> bool {
+    // On Linux, check if /proc/{pid}/stat exists
+    // This is more reliable than just /proc/{pid} because zombie processes
+    // still have a /proc entry but their stat file shows they're defunct
+    std::path::Path::new(&format!("/proc/{}/stat", pid)).exists()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn is_process_alive(pid: u32) -> bool {
     // On other Unix systems (macOS, BSD), /proc may not exist
     // Use a command-based approach
     std::process::Command::new("kill")
@@ -1219,528 +5547,4309 @@ fn is_process_alive(pid: u32) -> bool {
         .unwrap_or(true) // Conservative: assume alive if we can't check
 }
 
-#[cfg(not(unix))]
-fn is_process_alive(_pid: u32) -> bool {
-    // On non-Unix systems (Windows), conservatively assume process is alive
-    // This means stale locks won't be auto-cleaned on Windows
-    // Users can manually delete the LOCK file if needed
-    true
-}
+#[cfg(windows)]
+fn is_process_alive(pid: u32) -> bool {
+    use std::ffi::c_void;
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+    const ERROR_ACCESS_DENIED: u32 = 5;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(desired_access: u32, inherit_handle: i32, process_id: u32) -> *mut c_void;
+        fn GetExitCodeProcess(process: *mut c_void, exit_code: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+        fn GetLastError() -> u32;
+    }
+
+    // SAFETY: the handle is checked for null and closed before returning,
+    // and exit_code outlives the call that writes it
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            // Denied means the process exists but belongs to someone else
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut exit_code = 0u32;
+        let ok = GetExitCodeProcess(handle, &mut exit_code);
+        CloseHandle(handle);
+        // A process that exits with 259 is indistinguishable from a live
+        // one; treat it as alive, the conservative choice
+        ok == 0 || exit_code == STILL_ACTIVE
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_process_alive(_pid: u32) -> bool {
+    // No way to check; stale locks must be removed with `ctx unlock --force`
+    true
+}
+
+/// Report from analyzing all Rust files in a project.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisReport {
+    /// Number of files successfully analyzed.
+    pub files_analyzed: usize,
+    /// Total symbols found (functions, structs, etc.).
+    pub symbols_found: usize,
+    /// Total function calls resolved.
+    pub calls_resolved: usize,
+    /// Total edges generated.
+    pub edges_generated: usize,
+    /// ObjectId of the stored EdgeBatch.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub edge_batch_id: ObjectId,
+    /// ObjectId of the created commit.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub commit_id: ObjectId,
+}
+
+/// Report from analyzing a single Rust file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileAnalysisReport {
+    /// Path to the analyzed file.
+    pub path: PathBuf,
+    /// Number of symbols found.
+    pub symbols: usize,
+    /// Number of calls resolved.
+    pub calls: usize,
+    /// Number of edges generated.
+    pub edges: usize,
+    /// ObjectId of the stored EdgeBatch.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub edge_batch_id: ObjectId,
+    /// ObjectId of the created commit.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub commit_id: ObjectId,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_init_creates_structure() {
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+
+        // Check all directories exist
+        assert!(tmp.path().join(".ctx/objects").exists());
+        assert!(tmp.path().join(".ctx/refs").exists());
+        assert!(tmp.path().join(".ctx/narrative/log").exists());
+        assert!(tmp.path().join(".ctx/narrative/tasks").exists());
+        assert!(tmp.path().join(".ctx/index").exists());
+
+        // Check files exist
+        assert!(tmp.path().join(".ctx/config.toml").exists());
+        assert!(tmp.path().join(".ctx/.gitignore").exists());
+        assert!(tmp.path().join(".ctx/narrative/README.md").exists());
+        assert!(tmp.path().join(".ctx/HEAD").exists());
+    }
+
+    #[test]
+    fn test_init_creates_initial_commit() {
+        let tmp = TempDir::new().unwrap();
+        let repo = CtxRepo::init(tmp.path()).unwrap();
+
+        let commit = repo.head().unwrap();
+
+        assert_eq!(commit.parents.len(), 0);
+        assert_eq!(commit.message, "Initial commit");
+        assert!(commit.edge_batches.is_empty());
+        assert!(commit.narrative_refs.is_empty());
+    }
+
+    #[test]
+    fn test_init_head_matches_main() {
+        let tmp = TempDir::new().unwrap();
+        let repo = CtxRepo::init(tmp.path()).unwrap();
+
+        let head_id = repo.refs.read_head().unwrap();
+        let main_id = repo.refs.read_ref("main").unwrap();
+
+        assert_eq!(head_id, main_id);
+    }
+
+    #[test]
+    fn test_init_fails_if_exists() {
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+
+        let result = CtxRepo::init(tmp.path());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_open_existing() {
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+
+        // Should be able to reopen
+        let repo = CtxRepo::open(tmp.path()).unwrap();
+        assert!(repo.head().is_ok());
+    }
+
+    #[test]
+    fn test_open_nonexistent_fails() {
+        let tmp = TempDir::new().unwrap();
+        let result = CtxRepo::open(tmp.path());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_initial_tree_is_empty() {
+        let tmp = TempDir::new().unwrap();
+        let repo = CtxRepo::init(tmp.path()).unwrap();
+
+        let commit = repo.head().unwrap();
+        let tree: Tree = repo.object_store.get_typed(commit.root_tree).unwrap();
+
+        assert!(tree.entries.is_empty());
+    }
+
+    #[test]
+    fn test_root_and_ctx_dir() {
+        let tmp = TempDir::new().unwrap();
+        let repo = CtxRepo::init(tmp.path()).unwrap();
+
+        assert_eq!(repo.root(), tmp.path());
+        assert_eq!(repo.ctx_dir(), tmp.path().join(".ctx"));
+    }
+
+    #[test]
+    fn test_session_start_and_compact() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session
+        let session = repo.start_session("Test task").unwrap();
+        assert_eq!(session.task_description(), "Test task");
+        assert_eq!(session.step_count(), 1); // Initial flush
+
+        // Verify STAGE exists
+        assert!(repo.refs.read_stage().unwrap().is_some());
+
+        // Compact session
+        let commit_id = repo.compact_session("Completed test").unwrap();
+
+        // Verify commit exists
+        let commit: crate::types::Commit = repo.object_store.get_typed(commit_id).unwrap();
+        assert_eq!(commit.message, "Completed test");
+
+        // Verify STAGE is gone
+        assert!(repo.refs.read_stage().unwrap().is_none());
+
+        // Verify no active session
+        assert!(!repo.has_active_session());
+    }
+
+    #[test]
+    fn test_session_crash_recovery() {
+        use crate::types::SessionState;
+
+        let tmp = TempDir::new().unwrap();
+
+        // Start session and do some work
+        {
+            let mut repo = CtxRepo::init(tmp.path()).unwrap();
+            repo.start_session("Recovery test").unwrap();
+
+            // Add some observations using the convenience methods
+            repo.observe_note("Before crash").unwrap();
+            repo.observe_file_write("test.rs", b"fn main() {}").unwrap();
+
+            // Flush the step
+            repo.flush_active_session().unwrap();
+
+            // Verify session has 2 steps (initial + this one)
+            assert_eq!(repo.active_session().unwrap().step_count(), 2);
+
+            // Simulate crash - drop repo without compacting
+        }
+
+        // Reopen and recover
+        {
+            let mut repo = CtxRepo::open(tmp.path()).unwrap();
+
+            // Should have no active session initially
+            assert!(!repo.has_active_session());
+
+            // But STAGE should exist
+            assert!(repo.refs.read_stage().unwrap().is_some());
+
+            // Recover the session
+            repo.recover_session().unwrap();
+            assert!(repo.has_active_session());
+
+            // Verify recovered state
+            {
+                let session = repo.active_session().unwrap();
+                assert_eq!(session.task_description(), "Recovery test");
+                assert_eq!(session.step_count(), 2);
+                assert!(matches!(session.state(), SessionState::Running));
+            }
+
+            // Can continue working
+            {
+                let session = repo.active_session_mut().unwrap();
+                session.observe_note("After recovery").unwrap();
+            }
+            repo.flush_active_session().unwrap();
+            assert_eq!(repo.active_session().unwrap().step_count(), 3);
+
+            // Compact successfully
+            repo.compact_session("Recovered and completed").unwrap();
+        }
+
+        // Verify commit was created
+        {
+            let repo = CtxRepo::open(tmp.path()).unwrap();
+            let commit = repo.head().unwrap();
+            assert_eq!(commit.message, "Recovered and completed");
+        }
+    }
+
+    #[test]
+    fn test_expire_session_saves_or_abandons_stage() {
+        const DAY: i64 = 24 * 60 * 60;
+        let now = 1_769_083_200;
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+        let open_at = |time: i64| {
+            CtxRepo::open(tmp.path())
+                .unwrap()
+                .with_time_provider(move || time)
+        };
+        let stage_at = |task: &str, time: i64| {
+            let mut repo = open_at(time);
+            repo.start_session(task).unwrap();
+            repo.observe_note("Started").unwrap();
+            repo.flush_active_session().unwrap();
+            repo
+        };
+
+        // A day-old session is only stale
+        drop(stage_at("Recent task", now - DAY));
+        let mut repo = open_at(now);
+        assert_eq!(repo.expire_session().unwrap(), None);
+        repo.recover_session().unwrap();
+        repo.compact_session("Recent task").unwrap();
+
+        // Nor is one whose agent is still running
+        let running = stage_at("Running task", now - 8 * DAY);
+        let mut repo = open_at(now);
+        assert_eq!(repo.expire_session().unwrap(), None);
+        drop(repo);
+
+        // Opening the repository leaves it alone once the agent is gone
+        drop(running);
+        let mut repo = open_at(now);
+        assert!(repo.refs.read_stage().unwrap().is_some());
+
+        // Expiring saves and logs it
+        let commit_id = repo.expire_session().unwrap().unwrap();
+        assert!(repo.refs.read_stage().unwrap().is_none());
+        assert_eq!(repo.head_id().unwrap(), commit_id);
+        let head = repo.head().unwrap();
+        assert!(head
+            .message
+            .starts_with("Auto-saved expired session (idle for 8 days): Running task"));
+        assert!(matches!(
+            head.commit_type,
+            Some(CommitType::StaleAutoCompact { .. })
+        ));
+        let (date, _) = crate::narrative::log_date_time(now as u64);
+        let log = repo
+            .narrative()
+            .read_file(&format!("log/{}.md", date))
+            .unwrap();
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.contains("Session \"Running task\" was auto-compacted"));
+
+        // The abandon policy discards it instead
+        let config_path = repo.ctx_dir().join("config.toml");
+        let config = std::fs::read_to_string(&config_path).unwrap();
+        std::fs::write(
+            &config_path,
+            config.replace(
+                "expired_sessions = \"compact\"",
+                "expired_sessions = \"abandon\"",
+            ),
+        )
+        .unwrap();
+        drop(stage_at("Zombie task", now - 30 * DAY));
+        let mut repo = open_at(now);
+        repo.expire_session().unwrap().unwrap();
+        assert!(repo.refs.read_stage().unwrap().is_none());
+        assert_eq!(
+            repo.head().unwrap().commit_type,
+            Some(CommitType::Abandoned)
+        );
+    }
+
+    #[test]
+    fn test_start_session_never_overwrites_pending_stage() {
+        const DAY: i64 = 24 * 60 * 60;
+        let now = 1_769_083_200;
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+        let open_at = |time: i64| {
+            CtxRepo::open(tmp.path())
+                .unwrap()
+                .with_time_provider(move || time)
+        };
+        let stage_at = |task: &str, time: i64| {
+            let mut repo = open_at(time);
+            repo.start_session(task).unwrap();
+            repo.observe_note("Started").unwrap();
+            repo.flush_active_session().unwrap();
+        };
+
+        // An unfinished session that hasn't expired is refused, not replaced
+        stage_at("Recent task", now - DAY);
+        let stage = open_at(now).refs.read_stage().unwrap();
+        let mut repo = open_at(now);
+        assert!(matches!(
+            repo.start_session("New task"),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        assert_eq!(repo.refs.read_stage().unwrap(), stage);
+        repo.recover_session().unwrap();
+        repo.abort_session("Not needed").unwrap();
+
+        // An expired one is saved first
+        stage_at("Forgotten task", now - 8 * DAY);
+        let mut repo = open_at(now);
+        repo.start_session("New task").unwrap();
+        let head = repo.head().unwrap();
+        assert!(head.message.ends_with("Forgotten task"));
+        assert!(matches!(
+            head.commit_type,
+            Some(CommitType::StaleAutoCompact { .. })
+        ));
+        assert_eq!(
+            repo.active_session().unwrap().task_description(),
+            "New task"
+        );
+    }
+
+    fn denied<T>(result: Result<T>, expected: Capability) {
+        match result {
+            Err(CtxError::PermissionDenied { capability, .. }) => {
+                assert_eq!(capability, expected)
+            }
+            other => panic!("expected PermissionDenied, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn read_only_repo(tmp: &TempDir) -> CtxRepo {
+        CtxRepo::init(tmp.path()).unwrap();
+        CtxRepo::open(tmp.path())
+            .unwrap()
+            .restricted(Capabilities::read_only())
+    }
+
+    #[test]
+    fn test_restricted_handle() {
+        let tmp = TempDir::new().unwrap();
+        let head = CtxRepo::init(tmp.path()).unwrap().head_id().unwrap();
+        let mut repo = CtxRepo::open(tmp.path()).unwrap().restricted(Capabilities {
+            can_commit: false,
+            can_write_files: false,
+            readable_paths: Some(vec!["src/".to_string()]),
+        });
+
+        repo.start_session("Restricted").unwrap();
+        repo.observe_file_read("src/lib.rs").unwrap();
+        denied(repo.observe_file_read(".env"), Capability::ReadPath);
+        denied(
+            repo.observe_file_read_with_content("secrets/key", b"hunter2"),
+            Capability::ReadPath,
+        );
+        denied(
+            repo.observe_file_write("src/lib.rs", b"pub fn a() {}"),
+            Capability::WriteFiles,
+        );
+        repo.flush_active_session().unwrap();
+
+        denied(repo.compact_session("Done"), Capability::Commit);
+        denied(repo.abort_session("Gave up"), Capability::Commit);
+        denied(
+            repo.commit("Notes", Some(vec![]), "user"),
+            Capability::Commit,
+        );
+        denied(repo.tag("v1", head, false), Capability::Commit);
+        denied(repo.annotate(head, "Looked fine"), Capability::Commit);
+        assert_eq!(repo.head_id().unwrap(), head);
+        assert!(repo.tags().unwrap().is_empty());
+
+        // Restricting again can't give back what was taken away
+        let mut repo = repo.restricted(Capabilities::default());
+        assert_eq!(repo.capabilities().len(), 2);
+        denied(repo.compact_session("Done"), Capability::Commit);
+        denied(repo.observe_file_read(".env"), Capability::ReadPath);
+        drop(repo);
+
+        // The host compacts what the agent staged with its own handle
+        let mut host = CtxRepo::open(tmp.path()).unwrap();
+        host.recover_session().unwrap().unwrap();
+        host.compact_session("Done").unwrap();
+        assert_eq!(host.head().unwrap().parents, vec![head]);
+    }
+
+    #[test]
+    fn test_restricted_handle_hides_unreadable_content() {
+        use crate::cache::PackCache;
+        use crate::config::CacheConfig;
+        use crate::pack::{ChunkKind, RetrievalConfig};
+
+        let tmp = TempDir::new().unwrap();
+        let mut host = CtxRepo::init(tmp.path()).unwrap();
+        let base = host.head_id().unwrap();
+        host.start_session("Add files").unwrap();
+        host.observe_file_write("src/lib.rs", b"pub fn key() {}")
+            .unwrap();
+        host.observe_file_write("secrets/key.txt", b"hunter2")
+            .unwrap();
+        host.flush_active_session().unwrap();
+        let head = host.compact_session("Added files").unwrap();
+
+        let pin = |path: &str| NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        };
+        let config = RetrievalConfig {
+            frecency_boost: false,
+            pinned: vec![pin("src/lib.rs"), pin("secrets/key.txt")],
+            ..Default::default()
+        };
+        let cache = PackCache::new(host.ctx_dir().join("cache/packs"), CacheConfig::default());
+        let host_pack = host.build_pack_cached("key", &config, &cache).unwrap();
+        assert!(host_pack
+            .retrieved
+            .iter()
+            .any(|c| c.title == "secrets/key.txt"));
+        let pack_id = host.pack_id(&host_pack, &config).unwrap();
+
+        let repo = CtxRepo::open(tmp.path()).unwrap().restricted(Capabilities {
+            readable_paths: Some(vec!["src/".to_string()]),
+            ..Capabilities::default()
+        });
+        let titles = |pack: &crate::pack::PromptPack| -> Vec<String> {
+            pack.retrieved
+                .iter()
+                .filter(|c| c.chunk_kind == ChunkKind::FileContent)
+                .map(|c| c.title.clone())
+                .collect()
+        };
+        let pack = repo.build_pack("key", &config).unwrap();
+        assert_eq!(titles(&pack), vec!["src/lib.rs"]);
+        let cached = repo.build_pack_cached("key", &config, &cache).unwrap();
+        assert_eq!(titles(&cached), vec!["src/lib.rs"]);
+        let replayed = repo.replay_pack(pack_id).unwrap();
+        assert_eq!(titles(&replayed), vec!["src/lib.rs"]);
+
+        let dest = tmp.path().join("export");
+        assert_eq!(repo.export_tree(head, &dest).unwrap(), 1);
+        assert!(!dest.join("secrets/key.txt").exists());
+
+        let diff = repo.diff(base, head).unwrap();
+        let paths: Vec<_> = diff.files.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/lib.rs"]);
+
+        denied(repo.path_history("secrets/key.txt"), Capability::ReadPath);
+        assert_eq!(repo.path_history("src/lib.rs").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_raw_access() {
+        let tmp = TempDir::new().unwrap();
+        let repo = read_only_repo(&tmp);
+        denied(repo.checked_refs(), Capability::Commit);
+        denied(repo.checked_object_store(), Capability::Commit);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_record_decision() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let head = repo.head_id().unwrap();
+        denied(
+            repo.record_decision(Decision::new("Store?", "redb", "Single file")),
+            Capability::Commit,
+        );
+        assert_eq!(repo.head_id().unwrap(), head);
+        assert!(repo.decisions().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_summarize() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        denied(
+            repo.summarize("", &HeuristicSummarizer::default(), false),
+            Capability::Commit,
+        );
+        assert!(repo.refs.read_summaries().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_record_feedback() {
+        let tmp = TempDir::new().unwrap();
+        let repo = read_only_repo(&tmp);
+        denied(
+            repo.record_feedback(ObjectId::from_bytes([7; 32]), &[1], &[]),
+            Capability::Commit,
+        );
+        assert!(repo.refs.read_feedback().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_gc() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        denied(repo.gc(crate::gc::GcConfig::default()), Capability::Commit);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_restore_backup() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        denied(repo.restore_backup("any"), Capability::Commit);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_prune_history() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let head = repo.head_id().unwrap();
+        denied(repo.prune_history(u64::MAX), Capability::Commit);
+        assert_eq!(repo.head_id().unwrap(), head);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_undo_prune() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        denied(repo.undo_prune("any"), Capability::Commit);
+    }
+    /// HEAD, the number of stored objects and the narrative files, to
+    /// check a refused operation left the repository as it was.
+    fn repo_state(repo: &CtxRepo) -> (ObjectId, usize, Vec<String>) {
+        (
+            repo.head_id().unwrap(),
+            repo.object_store.list_all_objects().unwrap().len(),
+            repo.narrative().list_files().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_glossary() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let before = repo_state(&repo);
+        denied(
+            repo.add_glossary_term("SCC", "strongly connected component"),
+            Capability::Commit,
+        );
+        denied(repo.remove_glossary_term("SCC"), Capability::Commit);
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_create_document() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let before = repo_state(&repo);
+        let fields = BTreeMap::from([
+            ("title".to_string(), "Index lost".to_string()),
+            ("severity".to_string(), "high".to_string()),
+        ]);
+        denied(
+            repo.create_document("incident", &fields),
+            Capability::Commit,
+        );
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_import_document() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let before = repo_state(&repo);
+        denied(
+            repo.import_document(
+                "rfc.md",
+                b"# Storage\n\nUse redb.",
+                crate::import::ImportFormat::Markdown,
+            ),
+            Capability::Commit,
+        );
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_weekly_digest() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let before = repo_state(&repo);
+        denied(repo.write_weekly_digest(1_769_083_200), Capability::Commit);
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_restricted_handle_narrative_is_read_only() {
+        let tmp = TempDir::new().unwrap();
+        let repo = read_only_repo(&tmp);
+        let before = repo_state(&repo);
+        let narrative = repo.narrative();
+        denied(narrative.create_task("Sneak", ""), Capability::Commit);
+        denied(
+            narrative.append_log("2026-01-22", "10:00", "Sneak"),
+            Capability::Commit,
+        );
+        assert_eq!(repo_state(&repo), before);
+        assert!(narrative.read_file("README.md").is_ok());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_observe_fact() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        repo.start_session("Facts").unwrap();
+        let stage = repo.refs.read_stage().unwrap();
+        let before = repo_state(&repo);
+        let db = NodeId {
+            kind: NodeKind::File,
+            id: "src/db.rs".to_string(),
+        };
+        denied(
+            repo.observe_fact(Fact::new(db, "uses", "redb")),
+            Capability::Commit,
+        );
+        assert_eq!(repo.refs.read_stage().unwrap(), stage);
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_record_answer() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        repo.start_session("Questions").unwrap();
+        let stage = repo.refs.read_stage().unwrap();
+        let before = repo_state(&repo);
+        denied(
+            repo.record_answer("Which engine?", "redb"),
+            Capability::Commit,
+        );
+        assert_eq!(repo.refs.read_stage().unwrap(), stage);
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_index_writes() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        drop(repo.index().unwrap());
+        let index_path = repo.ctx_dir().join("index/index.redb");
+        let modified = fs::metadata(&index_path).unwrap().modified().unwrap();
+        denied(repo.index_mut(), Capability::Commit);
+        denied(repo.rebuild_index(), Capability::Commit);
+        assert_eq!(
+            fs::metadata(&index_path).unwrap().modified().unwrap(),
+            modified
+        );
+        assert!(repo.index().is_ok());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_unlock() {
+        let tmp = TempDir::new().unwrap();
+        let repo = read_only_repo(&tmp);
+        let lock_path = repo.ctx_dir().join("LOCK");
+        fs::write(&lock_path, format!("{}\n", std::process::id())).unwrap();
+        denied(repo.unlock(true), Capability::Commit);
+        assert!(lock_path.exists());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_clone() {
+        let tmp = TempDir::new().unwrap();
+        let repo = read_only_repo(&tmp);
+        let dest = tmp.path().join("clone");
+        denied(repo.clone_to(&dest, None), Capability::Commit);
+        assert!(!dest.join(".ctx").exists());
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_backup() {
+        let tmp = TempDir::new().unwrap();
+        let repo = read_only_repo(&tmp);
+        let backups = repo.list_backups().unwrap();
+        denied(repo.create_backup(), Capability::Commit);
+        assert_eq!(repo.list_backups().unwrap(), backups);
+    }
+
+    #[test]
+    fn test_restricted_handle_refuses_analysis() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = read_only_repo(&tmp);
+        let before = repo_state(&repo);
+        denied(repo.analyze_rust(), Capability::Commit);
+        denied(repo.analyze_cargo(), Capability::Commit);
+        assert_eq!(repo_state(&repo), before);
+    }
+
+    #[test]
+    fn test_recover_session_with_summary_truncates_broken_chain() {
+        use crate::types::WorkCommit;
+        use crate::verify::StepDisposition;
+
+        let tmp = TempDir::new().unwrap();
+        let mut steps = Vec::new();
+        {
+            let mut repo = CtxRepo::init(tmp.path()).unwrap();
+            repo.start_session("Broken chain").unwrap();
+            steps.push(repo.refs.read_stage().unwrap().unwrap());
+            for note in ["one", "two", "three"] {
+                repo.observe_note(note).unwrap();
+                steps.push(repo.flush_active_session().unwrap());
+            }
+            // A step flushed by a process that died before moving STAGE
+            let mut orphan: WorkCommit = repo.object_store.get_typed(steps[3]).unwrap();
+            orphan.parents = vec![steps[3]];
+            orphan.created_at += 1;
+            steps.push(repo.object_store.put_typed(&orphan).unwrap());
+        }
+        let mut store = ObjectStore::new(tmp.path().join(".ctx").join("objects"));
+        store.delete(steps[2]).unwrap();
+
+        let mut repo = CtxRepo::open(tmp.path()).unwrap();
+        let summary = repo.recover_session_with_summary().unwrap();
+        let dispositions: Vec<_> = summary
+            .steps
+            .iter()
+            .map(|step| (step.id, step.disposition))
+            .collect();
+        assert_eq!(
+            dispositions,
+            vec![
+                (steps[0], StepDisposition::Kept),
+                (steps[1], StepDisposition::Kept),
+                (steps[2], StepDisposition::Truncated),
+                (steps[3], StepDisposition::Truncated),
+                (steps[4], StepDisposition::Orphaned),
+            ]
+        );
+        assert_eq!(summary.stage, Some(steps[3]));
+        assert_eq!(summary.recovered, Some(steps[1]));
+        assert!(summary.is_repaired());
+        assert_eq!(repo.refs.read_stage().unwrap(), Some(steps[1]));
+
+        // The truncated session resumes and compacts
+        assert_eq!(repo.active_session().unwrap().step_count(), 2);
+        repo.compact_session("Recovered").unwrap();
+        assert!(repo.refs.read_stage().unwrap().is_none());
+
+        // Nothing staged, nothing to report
+        let summary = repo.recover_session_with_summary().unwrap();
+        assert_eq!(summary, crate::verify::RecoverySummary::default());
+    }
+
+    #[test]
+    fn test_concurrent_session_prevention() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start first session
+        repo.start_session("First task").unwrap();
+
+        // Try to start second session - should fail
+        let result = repo.start_session("Second task");
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            CtxError::SessionAlreadyActive(_)
+        ));
+
+        // Verify first session is still active
+        assert!(repo.has_active_session());
+        assert_eq!(
+            repo.active_session().unwrap().task_description(),
+            "First task"
+        );
+    }
+
+    #[test]
+    fn test_file_locking_prevents_concurrent_access() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo1 = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session in first repo (acquires lock)
+        repo1.start_session("Task in repo1").unwrap();
+
+        // Try to open second repo instance and start session
+        let mut repo2 = CtxRepo::open(tmp.path()).unwrap();
+
+        // This should fail due to lock
+        // With PID-based locking, we get SessionLockHeld when we can detect
+        // the holding process, or RepositoryLocked if we can't
+        let result = repo2.start_session("Task in repo2");
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CtxError::RepositoryLocked | CtxError::SessionLockHeld { .. }
+            ),
+            "Expected RepositoryLocked or SessionLockHeld, got: {:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_unlock_checks_holder_before_removing() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo1 = CtxRepo::init(tmp.path()).unwrap();
+        let repo2 = CtxRepo::open(tmp.path()).unwrap();
+        assert_eq!(repo2.lock_status().unwrap(), None);
+        assert_eq!(repo2.unlock(false).unwrap(), None);
+
+        // A session in progress keeps its lock even with force
+        repo1.start_session("Task in repo1").unwrap();
+        let status = repo2.lock_status().unwrap().unwrap();
+        assert_eq!(status.pid, Some(std::process::id()));
+        assert!(status.holder_alive);
+        assert!(repo2.unlock(true).is_err());
+        drop(repo1);
+
+        // A lock file left by a live PID needs force
+        let lock_path = repo2.ctx_dir().join("LOCK");
+        fs::write(&lock_path, format!("{}\n", std::process::id())).unwrap();
+        assert!(matches!(
+            repo2.unlock(false),
+            Err(CtxError::SessionLockHeld { .. })
+        ));
+        let removed = repo2.unlock(true).unwrap().unwrap();
+        assert!(removed.owned);
+        assert!(!lock_path.exists());
+
+        // A lock that changed hands after it was inspected is put back
+        fs::write(&lock_path, format!("{}\n", std::process::id())).unwrap();
+        assert!(matches!(
+            remove_lock_if_owned(&lock_path, Some(std::process::id() + 1)),
+            Err(CtxError::SessionLockHeld { pid }) if pid == std::process::id()
+        ));
+        assert_eq!(
+            fs::read_to_string(&lock_path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        assert!(!fs::read_dir(repo2.ctx_dir())
+            .unwrap()
+            .any(|e| e.unwrap().path().extension() == Some("unlock".as_ref())));
+        assert!(remove_lock_if_owned(&lock_path, Some(std::process::id())).unwrap());
+        assert!(!remove_lock_if_owned(&lock_path, Some(std::process::id())).unwrap());
+    }
+
+    #[test]
+    fn test_tree_preserved_in_compaction() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session
+        repo.start_session("Build tree test").unwrap();
+
+        // Write multiple files with nested directory structure
+        let file1_id = repo
+            .observe_file_write("src/main.rs", b"fn main() { println!(\"Hello\"); }")
+            .unwrap();
+
+        let file2_id = repo
+            .observe_file_write("src/lib.rs", b"pub fn hello() {}")
+            .unwrap();
+
+        let file3_id = repo
+            .observe_file_write("tests/test.rs", b"#[test] fn test_hello() {}")
+            .unwrap();
+
+        let file4_id = repo
+            .observe_file_write("README.md", b"# My Project")
+            .unwrap();
+
+        // Flush the observations
+        repo.flush_active_session().unwrap();
+
+        // Compact into canonical commit
+        let commit_id = repo.compact_session("Added project files").unwrap();
+
+        // Verify tree was built correctly
+        let commit: crate::types::Commit = repo.object_store.get_typed(commit_id).unwrap();
+        let root_tree: crate::types::Tree = repo.object_store.get_typed(commit.root_tree).unwrap();
+
+        // Root should have: README.md, src/, tests/ (sorted alphabetically)
+        assert_eq!(root_tree.entries.len(), 3);
+        assert_eq!(root_tree.entries[0].name, "README.md");
+        assert_eq!(root_tree.entries[0].kind, crate::types::TreeEntryKind::Blob);
+        assert_eq!(root_tree.entries[0].id, file4_id);
+
+        assert_eq!(root_tree.entries[1].name, "src");
+        assert_eq!(root_tree.entries[1].kind, crate::types::TreeEntryKind::Tree);
+
+        assert_eq!(root_tree.entries[2].name, "tests");
+        assert_eq!(root_tree.entries[2].kind, crate::types::TreeEntryKind::Tree);
+
+        // Verify src/ subtree
+        let src_tree: crate::types::Tree = repo
+            .object_store
+            .get_typed(root_tree.entries[1].id)
+            .unwrap();
+        assert_eq!(src_tree.entries.len(), 2);
+
+        // Should be sorted: lib.rs, main.rs
+        assert_eq!(src_tree.entries[0].name, "lib.rs");
+        assert_eq!(src_tree.entries[0].kind, crate::types::TreeEntryKind::Blob);
+        assert_eq!(src_tree.entries[0].id, file2_id);
+
+        assert_eq!(src_tree.entries[1].name, "main.rs");
+        assert_eq!(src_tree.entries[1].kind, crate::types::TreeEntryKind::Blob);
+        assert_eq!(src_tree.entries[1].id, file1_id);
+
+        // Verify tests/ subtree
+        let tests_tree: crate::types::Tree = repo
+            .object_store
+            .get_typed(root_tree.entries[2].id)
+            .unwrap();
+        assert_eq!(tests_tree.entries.len(), 1);
+
+        assert_eq!(tests_tree.entries[0].name, "test.rs");
+        assert_eq!(
+            tests_tree.entries[0].kind,
+            crate::types::TreeEntryKind::Blob
+        );
+        assert_eq!(tests_tree.entries[0].id, file3_id);
+
+        // Verify content is preserved
+        let main_content = repo.object_store.get_blob(file1_id).unwrap();
+        assert_eq!(main_content, b"fn main() { println!(\"Hello\"); }");
+
+        let lib_content = repo.object_store.get_blob(file2_id).unwrap();
+        assert_eq!(lib_content, b"pub fn hello() {}");
+    }
+
+    #[test]
+    fn test_stale_session_detection() {
+        use std::thread;
+        use std::time::Duration;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session
+        repo.start_session("Stale test").unwrap();
+
+        // Check immediately - should be fresh
+        let config = crate::config::StaleSessionConfig {
+            ask_threshold_secs: 2,
+            auto_compact_threshold_secs: 5,
+        };
+
+        let status = repo.check_stale_session(&config);
+        assert!(matches!(
+            status,
+            crate::config::StaleSessionStatus::Fresh { .. }
+        ));
+
+        // Wait for it to become stale (ask threshold)
+        thread::sleep(Duration::from_secs(3));
+
+        let status = repo.check_stale_session(&config);
+        assert!(matches!(
+            status,
+            crate::config::StaleSessionStatus::ShouldAsk { .. }
+        ));
+    }
+
+    #[test]
+    fn test_abort_session() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session
+        repo.start_session("Aborted task").unwrap();
+
+        // Abort it
+        let commit_id = repo.abort_session("User cancelled").unwrap();
+
+        // Verify abort commit
+        let commit: crate::types::Commit = repo.object_store.get_typed(commit_id).unwrap();
+        assert!(commit.message.contains("Aborted"));
+        assert!(commit.message.contains("User cancelled"));
+        assert_eq!(
+            commit.commit_type,
+            Some(crate::types::CommitType::Abandoned)
+        );
+
+        // Verify session is gone
+        assert!(!repo.has_active_session());
+        assert!(repo.refs.read_stage().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cancelled_operations_leave_repo_unchanged() {
+        use crate::progress::{CancellationToken, Progress};
+        use std::sync::Mutex;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Cancel test").unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn hello() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Add lib").unwrap();
+        let head = repo.head_id().unwrap();
+
+        // Progress reaches the sink until the token is cancelled
+        let phases = Arc::new(Mutex::new(Vec::new()));
+        let seen = phases.clone();
+        let token = CancellationToken::new();
+        let progress = Progress::new(move |phase: &str, _: u64, _: u64| {
+            seen.lock().unwrap().push(phase.to_string());
+        })
+        .with_cancellation(token.clone());
+        repo.rebuild_index_with_progress(&progress).unwrap();
+        assert!(phases.lock().unwrap().iter().any(|p| p == "walk"));
+
+        token.cancel();
+        assert!(matches!(
+            repo.rebuild_index_with_progress(&progress),
+            Err(CtxError::Cancelled { changed: false })
+        ));
+        assert!(matches!(
+            repo.build_pack_with_progress("src/lib.rs", &Default::default(), &progress),
+            Err(CtxError::Cancelled { changed: false })
+        ));
+        assert!(matches!(
+            repo.gc_with_progress(crate::gc::GcConfig::default(), &progress),
+            Err(CtxError::Cancelled { changed: false })
+        ));
+
+        // The previous index is still in place
+        assert_eq!(repo.head_id().unwrap(), head);
+        assert!(repo
+            .index()
+            .unwrap()
+            .lookup_path("src/lib.rs")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_edge_batches_created_on_compact() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session
+        repo.start_session("Test edge extraction").unwrap();
+
+        // Observe file writes
+        repo.observe_file_write("src/main.rs", b"fn main() {}")
+            .unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn test() {}")
+            .unwrap();
+
+        // Flush observations
+        repo.flush_active_session().unwrap();
+
+        // Compact
+        let commit_id = repo.compact_session("Added source files").unwrap();
+
+        // Load commit
+        let commit: crate::types::Commit = repo.object_store.get_typed(commit_id).unwrap();
+
+        // Should have edge batches
+        assert_eq!(commit.edge_batches.len(), 1, "Should have 1 EdgeBatch");
+
+        // Load the edge batch
+        let edge_batch_id = commit.edge_batches[0];
+        let edge_batch: crate::types::EdgeBatch =
+            repo.object_store.get_typed(edge_batch_id).unwrap();
+
+        // Should have 2 edges (one per file)
+        assert_eq!(edge_batch.edges.len(), 2, "Should have 2 edges");
+
+        // Verify edges point to the files
+        assert_eq!(edge_batch.edges[0].from.kind, crate::types::NodeKind::File);
+        assert_eq!(edge_batch.edges[0].from.id, "src/lib.rs");
+        assert_eq!(
+            edge_batch.edges[0].label,
+            crate::types::EdgeLabel::UpdatedIn
+        );
+
+        assert_eq!(edge_batch.edges[1].from.kind, crate::types::NodeKind::File);
+        assert_eq!(edge_batch.edges[1].from.id, "src/main.rs");
+        assert_eq!(
+            edge_batch.edges[1].label,
+            crate::types::EdgeLabel::UpdatedIn
+        );
+
+        // Verify evidence points to base commit (where session started)
+        // Note: Evidence records where the observations were made (during session),
+        // not the final compacted commit ID
+        let base_commit = commit.parents[0];
+        assert_eq!(edge_batch.edges[0].evidence.commit_id, base_commit);
+        assert_eq!(
+            edge_batch.edges[0].evidence.tool,
+            crate::types::EvidenceTool::Human
+        );
+        assert_eq!(
+            edge_batch.edges[0].evidence.confidence,
+            crate::types::Confidence::High
+        );
+
+        // Note: To find which commit introduced this EdgeBatch, we would query
+        // which commit's edge_batches field contains edge_batch_id.
+        // This avoids self-reference issues in content-addressed storage.
+    }
+
+    #[test]
+    fn test_observe_file_read_with_content() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        // Start session
+        repo.start_session("Test file read observation").unwrap();
+
+        // Observe file read with content
+        let file_content = b"fn main() { println!(\"Hello\"); }";
+        repo.observe_file_read_with_content("src/main.rs", file_content)
+            .unwrap();
+
+        // Flush to create WorkCommit
+        repo.flush_active_session().unwrap();
+
+        // Compact to verify content is preserved
+        let commit_id = repo.compact_session("Read file").unwrap();
+
+        // Verify commit was created
+        let commit: crate::types::Commit = repo.object_store.get_typed(commit_id).unwrap();
+        assert!(commit.message.contains("Read file"));
+
+        // The content should be stored in the object store
+        // We can verify this by checking the staging observations
+        // (This is implicitly tested by the compaction succeeding)
+
+        // More importantly: in a real scenario, we'd walk the staging chain
+        // and find the FileRead observation with content_id
+        // For now, just verify the API works without panicking
+        assert!(repo.object_store.exists(commit.root_tree));
+    }
+
+    #[test]
+    fn test_build_pack_paged_delivers_each_chunk_once() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'a'; 300]).unwrap();
+        repo.observe_file_write("src/b.rs", &[b'b'; 300]).unwrap();
+        repo.observe_file_write("src/c.rs", &[b'c'; 300]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        // Each file is ~75 tokens, so only one fits per page
+        let config = crate::pack::RetrievalConfig {
+            token_budget: 100,
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+
+        let query = "src/a.rs src/b.rs src/c.rs";
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = repo
+                .build_pack_paged(query, &config, cursor.as_ref())
+                .unwrap();
+            assert_eq!(page.pack.retrieved.len(), 1);
+            seen.push(page.pack.retrieved[0].title.clone());
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        seen.sort();
+        assert_eq!(seen, vec!["src/a.rs", "src/b.rs", "src/c.rs"]);
+    }
+
+    #[test]
+    fn test_build_pack_paged_rejects_stale_cursor() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'a'; 300]).unwrap();
+        repo.observe_file_write("src/b.rs", &[b'b'; 300]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        let config = crate::pack::RetrievalConfig {
+            token_budget: 100,
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let page = repo
+            .build_pack_paged("src/a.rs src/b.rs", &config, None)
+            .unwrap();
+        let cursor = page.next_cursor.expect("second page should be available");
+
+        // A cursor only pages the query and config it was issued for
+        let err = repo
+            .build_pack_paged("src/b.rs", &config, Some(&cursor))
+            .unwrap_err();
+        assert!(matches!(err, CtxError::InvalidCursor(_)));
+        let wider = crate::pack::RetrievalConfig {
+            expansion_depth: config.expansion_depth + 1,
+            ..config.clone()
+        };
+        let err = repo
+            .build_pack_paged("src/a.rs src/b.rs", &wider, Some(&cursor))
+            .unwrap_err();
+        assert!(matches!(err, CtxError::InvalidCursor(_)));
+
+        // Moving HEAD invalidates the cursor
+        repo.commit("Another commit", None, "user").unwrap();
+        let err = repo
+            .build_pack_paged("src/a.rs src/b.rs", &config, Some(&cursor))
+            .unwrap_err();
+        assert!(matches!(err, CtxError::InvalidCursor(_)));
+    }
+
+    #[test]
+    fn test_delta_pack_references_unchanged_files() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'a'; 300]).unwrap();
+        repo.observe_file_write("src/b.rs", &[b'b'; 300]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        let config = crate::pack::RetrievalConfig {
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let query = "src/a.rs src/b.rs";
+        let mut session = crate::pack::PackSession::new();
+
+        let first = session.build(&repo, query, &config).unwrap();
+        assert_eq!(first.retrieved.len(), 2);
+        assert!(first.unchanged.is_empty());
+
+        // Nothing changed, so the second turn only references the files
+        let second = session.build(&repo, query, &config).unwrap();
+        assert!(second.retrieved.is_empty());
+        assert_eq!(second.unchanged.len(), 2);
+        assert!(second.unchanged.iter().all(|chunk| chunk.turn == 1));
+        assert!(second.token_budget.used < first.token_budget.used);
+        assert!(second.to_text().contains("src/a.rs unchanged since turn 1"));
+
+        repo.start_session("Edit a").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'c'; 300]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Edited a").unwrap();
+
+        let third = session.build(&repo, query, &config).unwrap();
+        let titles: Vec<_> = third.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["src/a.rs"]);
+        assert_eq!(third.unchanged.len(), 1);
+        assert_eq!(third.unchanged[0].title, "src/b.rs");
+
+        // Once earlier turns are dropped from the conversation, b.rs is sent again
+        session.forget_before(3);
+        let fourth = session.build(&repo, query, &config).unwrap();
+        let titles: Vec<_> = fourth.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["src/b.rs"]);
+        assert_eq!(fourth.unchanged[0].turn, 3);
+    }
+
+    #[test]
+    fn test_summaries_replace_files_that_dont_fit() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        let big = format!("pub fn big() {{}}\n{}", "// filler\n".repeat(400));
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/small.rs", &[b's'; 400])
+            .unwrap();
+        repo.observe_file_write("src/big.rs", big.as_bytes())
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        let summarizer = HeuristicSummarizer::default();
+        let report = repo.summarize("src/big.rs", &summarizer, false).unwrap();
+        assert_eq!(report.summarized, vec!["src/big.rs"]);
+        let again = repo.summarize("src/", &summarizer, false).unwrap();
+        assert_eq!(again.cached, vec!["src/big.rs"]);
+        assert_eq!(again.summarized, vec!["src/small.rs"]);
+        assert!(repo.summarize("docs", &summarizer, false).is_err());
+
+        let config = crate::pack::RetrievalConfig {
+            token_budget: 600,
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            summary_max_relevance: Some(1000),
+            explain: true,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/small.rs src/big.rs", &config).unwrap();
+        let kinds: BTreeMap<_, _> = pack
+            .retrieved
+            .iter()
+            .map(|c| (c.title.as_str(), c.chunk_kind))
+            .collect();
+        assert_eq!(kinds["src/small.rs"], crate::pack::ChunkKind::FileContent);
+        assert_eq!(kinds["src/big.rs"], crate::pack::ChunkKind::Summary);
+        let big_chunk = pack.retrieved.iter().find(|c| c.title == "src/big.rs");
+        assert!(big_chunk.unwrap().snippet.contains("pub fn big()"));
+        let explanation = pack.explanation.unwrap();
+        let dropped = explanation
+            .candidates
+            .iter()
+            .find(|c| c.title == "src/big.rs")
+            .unwrap();
+        assert!(dropped
+            .dropped_reason
+            .as_deref()
+            .unwrap()
+            .starts_with("replaced by a"));
+
+        let disabled = crate::pack::RetrievalConfig {
+            summary_max_relevance: None,
+            ..config
+        };
+        let pack = repo
+            .build_pack("src/small.rs src/big.rs", &disabled)
+            .unwrap();
+        assert_eq!(pack.retrieved.len(), 1);
+
+        // Summaries survive GC
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let big_id = repo.index().unwrap().lookup_path("src/big.rs").unwrap();
+        assert!(repo.summary(big_id.unwrap()).unwrap().is_some());
+        assert!(repo
+            .verify(Default::default())
+            .unwrap()
+            .refs_dangling
+            .is_empty());
+    }
+
+    #[test]
+    fn test_tags() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("First").unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let first = repo.compact_session("First").unwrap();
+
+        repo.start_session("Second").unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() { }")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let second = repo.compact_session("Second").unwrap();
+
+        repo.tag("v1-release-context", first, false).unwrap();
+        assert_eq!(repo.resolve_commit("v1-release-context").unwrap(), first);
+        assert_eq!(
+            repo.tags().unwrap(),
+            vec![("v1-release-context".to_string(), first)]
+        );
+
+        // Re-tagging the same commit is a no-op; moving a tag needs force
+        repo.tag("v1-release-context", first, false).unwrap();
+        assert!(matches!(
+            repo.tag("v1-release-context", second, false),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        repo.tag("v1-release-context", second, true).unwrap();
+        assert_eq!(repo.resolve_commit("v1-release-context").unwrap(), second);
+
+        for bad in ["", "HEAD", "-x", ".hidden", "a/b", "a b"] {
+            assert!(matches!(
+                repo.tag(bad, first, false),
+                Err(CtxError::InvalidArgument(_))
+            ));
+        }
+        let blob = repo.object_store.put_blob(b"not a commit").unwrap();
+        assert!(repo.tag("blob", blob, false).is_err());
+
+        // Tags keep their commits alive and are listed with the named refs
+        assert!(repo
+            .refs
+            .list_refs()
+            .unwrap()
+            .iter()
+            .any(|(name, _)| name == "tags/v1-release-context"));
+        assert!(repo
+            .verify(Default::default())
+            .unwrap()
+            .refs_dangling
+            .is_empty());
+    }
+
+    #[test]
+    fn test_reflog_and_reset() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let initial = repo.head_id().unwrap();
+
+        repo.start_session("First").unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let first = repo.compact_session("First").unwrap();
+        let second = repo.commit("Note the design", None, "user").unwrap();
+
+        let head = repo.reflog("HEAD").unwrap();
+        let reasons: Vec<&str> = head.iter().map(|e| e.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            vec!["commit: Note the design", "compact: First", "init"]
+        );
+        assert_eq!(head[0].old, Some(first));
+        assert_eq!(repo.reflog("main").unwrap().len(), 3);
+        assert_eq!(repo.resolve_commit("HEAD@{1}").unwrap(), first);
+
+        // Resetting is itself logged, so it can be undone
+        assert_eq!(repo.reset("HEAD@{2}").unwrap(), initial);
+        assert_eq!(repo.head_id().unwrap(), initial);
+        assert_eq!(repo.refs.read_ref("main").unwrap(), initial);
+        // The index follows HEAD
+        assert!(repo
+            .index()
+            .unwrap()
+            .get_commit_info(first)
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            repo.reflog("HEAD").unwrap()[0].reason,
+            "reset: moving to HEAD@{2}"
+        );
+        assert_eq!(repo.reset("HEAD@{1}").unwrap(), second);
+        assert!(repo
+            .index()
+            .unwrap()
+            .get_commit_info(first)
+            .unwrap()
+            .is_some());
+
+        assert!(repo.reset("HEAD@{99}").is_err());
+        repo.start_session("Busy").unwrap();
+        assert!(matches!(
+            repo.reset("HEAD@{1}"),
+            Err(CtxError::SessionAlreadyActive(_))
+        ));
+    }
+
+    #[test]
+    fn test_gc_keeps_commits_named_by_reflog() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let initial = repo.head_id().unwrap();
+        repo.start_session("First").unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let first = repo.compact_session("First").unwrap();
+
+        // Only the reflog still names the commit reset away from, and an
+        // aggressive GC ignores how recently it was written
+        repo.reset("HEAD@{1}").unwrap();
+        assert_eq!(repo.head_id().unwrap(), initial);
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(repo.resolve_commit("HEAD@{1}").unwrap(), first);
+        assert_eq!(repo.reset("HEAD@{1}").unwrap(), first);
+        // Its tree and content survived too
+        assert!(!repo.verify(Default::default()).unwrap().has_issues());
+
+        // Once the entries expire, the commit goes
+        assert_eq!(repo.reset("HEAD@{1}").unwrap(), initial);
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            reflog_expire_days: 0,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!repo.object_store.exists(first));
+    }
+
+    #[test]
+    fn test_auto_gc_after_commit_threshold() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let config_path = repo.ctx_dir().join("config.toml");
+        let config = fs::read_to_string(&config_path).unwrap();
+        fs::write(
+            &config_path,
+            config.replace("auto_commits = 50", "auto_commits = 2"),
+        )
+        .unwrap();
+
+        let compact = |repo: &mut CtxRepo, task: &str| {
+            repo.start_session(task).unwrap();
+            repo.observe_file_write("src/a.rs", task.as_bytes())
+                .unwrap();
+            repo.compact_session(task).unwrap();
+            repo.take_auto_gc_report()
+        };
+
+        // The initial commit and the first compaction make two
+        let report = compact(&mut repo, "First").unwrap();
+        assert!(report.reason.contains("2 commits"));
+        assert!(report.skipped.is_none());
+        assert_eq!(report.gc.unwrap().objects_deleted, 0);
+        assert!(repo
+            .events_since(0)
+            .unwrap()
+            .events
+            .iter()
+            .any(|e| matches!(e.kind, EventKind::GcCompleted { dry_run: false, .. })));
+
+        // Counting starts again from the GC
+        assert!(compact(&mut repo, "Second").is_none());
+        assert!(compact(&mut repo, "Third").is_some());
+        assert!(repo.auto_gc().unwrap().is_none());
+
+        // Opting out
+        let config = fs::read_to_string(&config_path).unwrap();
+        fs::write(
+            &config_path,
+            config.replace("auto_gc = true", "auto_gc = false"),
+        )
+        .unwrap();
+        assert!(compact(&mut repo, "Fourth").is_none());
+        assert!(compact(&mut repo, "Fifth").is_none());
+    }
+
+    #[test]
+    fn test_annotations() {
+        use crate::pack::{ChunkKind, RetrievalConfig};
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Rewrite parser").unwrap();
+        repo.observe_file_write("src/parser.rs", b"pub fn parse() {}")
+            .unwrap();
+        repo.observe_file_write("src/lexer.rs", b"pub fn lex() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let buggy = repo.compact_session("Rewrite parser").unwrap();
+
+        repo.start_session("Tweak lexer").unwrap();
+        repo.observe_file_write("src/lexer.rs", b"pub fn lex() { }")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let later = repo.compact_session("Tweak lexer").unwrap();
+
+        let first = repo
+            .annotate(buggy, "  Introduced the parser crash  ")
+            .unwrap();
+        repo.annotate(buggy, "Fixed by the next release").unwrap();
+        let annotations = repo.annotations(buggy).unwrap();
+        let texts: Vec<&str> = annotations.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Introduced the parser crash", "Fixed by the next release"]
+        );
+        assert_eq!(repo.note_table().unwrap().get(buggy)[0], first);
+        assert!(repo.annotations(later).unwrap().is_empty());
+        assert!(matches!(
+            repo.annotate(later, " "),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        let blob = repo.object_store.put_blob(b"not a commit").unwrap();
+        assert!(repo.annotate(blob, "Not a commit").is_err());
+
+        // The notes ref isn't a named commit ref
+        assert!(repo
+            .refs
+            .list_refs()
+            .unwrap()
+            .iter()
+            .all(|(name, _)| name == "main"));
+        assert!(repo.refs.all().unwrap().contains_key("refs/notes/commits"));
+
+        // Packs carry the notes of commits that changed their files
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/parser.rs", &config).unwrap();
+        let notes = pack
+            .retrieved
+            .iter()
+            .find(|c| c.chunk_kind == ChunkKind::Annotation)
+            .unwrap();
+        assert!(notes.snippet.contains("Introduced the parser crash"));
+        assert!(notes
+            .snippet
+            .contains("\"Rewrite parser\", changed src/parser.rs"));
+        let config = RetrievalConfig {
+            expansion_depth: 0,
+            ..config
+        };
+        let pack = repo.build_pack("unrelated", &config).unwrap();
+        assert!(pack
+            .retrieved
+            .iter()
+            .all(|c| c.chunk_kind != ChunkKind::Annotation));
+
+        // Annotations survive GC
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(repo.annotations(buggy).unwrap().len(), 2);
+        let report = repo.verify(Default::default()).unwrap();
+        assert!(report.refs_dangling.is_empty());
+        assert!(report.commits_invalid.is_empty());
+    }
+
+    #[test]
+    fn test_compact_session_summarized_writes_message_and_log() {
+        let tmp = TempDir::new().unwrap();
+        // 2026-01-22 12:00 UTC
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_time_provider(|| 1_769_083_200);
+
+        repo.start_session("Fix parser crash").unwrap();
+        repo.observe_file_write("src/parser.rs", b"fn parse() {}")
+            .unwrap();
+        repo.observe_command("cargo test", Some(0), None).unwrap();
+        repo.observe_note("Guarded the empty input case").unwrap();
+        repo.flush_active_session().unwrap();
+
+        let (commit_id, summary) = repo
+            .compact_session_summarized(&HeuristicSummaryProvider)
+            .unwrap();
+        assert!(!repo.has_active_session());
+        let commit = repo.head().unwrap();
+        assert_eq!(repo.head_id().unwrap(), commit_id);
+        assert_eq!(commit.message, summary.message);
+        assert!(commit
+            .message
+            .starts_with("Fix parser crash\n\nChanged: src/parser.rs"));
+
+        let log = repo
+            .narrative()
+            .read_file("log/2026-01-22.md")
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+            .unwrap();
+        assert!(log.contains("### 12:00"));
+        assert!(log.contains("- Note: Guarded the empty input case"));
+
+        assert!(matches!(
+            repo.compact_session_summarized(&HeuristicSummaryProvider),
+            Err(CtxError::NoActiveSession)
+        ));
+    }
+
+    #[test]
+    fn test_dry_run_leaves_repository_unchanged() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let head = repo.head_id().unwrap();
+        let refs = repo.refs.all().unwrap();
+
+        repo.start_session("Fix parser crash").unwrap();
+        repo.observe_file_write("src/parser.rs", b"fn parse() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let staged_refs = repo.refs.all().unwrap();
+
+        let (commit_id, report) = repo
+            .dry_run(|repo| repo.compact_session("Fix parser"))
+            .unwrap();
+        assert_eq!(report.commits.len(), 1);
+        assert_eq!(report.commits[0].commit_id, commit_id);
+        assert_eq!(report.commits[0].message, "Fix parser");
+        assert!(report.objects.iter().any(|object| object.id == commit_id));
+        assert!(report.object_bytes > 0);
+        assert!(report
+            .refs
+            .iter()
+            .any(|update| update.name == "STAGE" && update.after.is_none()));
+        assert!(report.refs.iter().any(|update| update.name == "HEAD"
+            && update.before == Some(head)
+            && update.after == Some(commit_id)));
+
+        // Nothing was written, and the session is still there to compact
+        assert_eq!(repo.refs.all().unwrap(), staged_refs);
+        assert!(!repo.object_store.exists(commit_id));
+        assert!(repo.has_active_session());
+        let node = NodeId {
+            kind: NodeKind::File,
+            id: "src/parser.rs".to_string(),
+        };
+        assert!(repo
+            .index()
+            .unwrap()
+            .get_edges_from(&node, EdgeLabel::UpdatedIn)
+            .unwrap()
+            .is_empty());
+
+        let compacted = repo.compact_session("Fix parser").unwrap();
+        assert_eq!(repo.head_id().unwrap(), compacted);
+        assert_ne!(repo.refs.all().unwrap(), refs);
+        assert!(matches!(
+            repo.dry_run(|repo| repo.dry_run(|_| Ok(()))),
+            Err(CtxError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_at_commit_builds_pack_from_past_state() {
+        let tmp = TempDir::new().unwrap();
+        // 2026-01-22 12:00 UTC
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_time_provider(|| 1_769_083_200);
+        let config = crate::pack::RetrievalConfig {
+            frecency_boost: false,
+            ..Default::default()
+        };
+
+        repo.start_session("Write parser").unwrap();
+        repo.observe_file_write("src/parser.rs", b"fn parse_tokens_v1() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Write parser").unwrap();
+        repo.narrative()
+            .append_log("2026-01-21", "10:00", "Started the parser")
+            .unwrap();
+        let first = repo.commit("Log parser work", None, "agent").unwrap();
+
+        repo.start_session("Rewrite parser").unwrap();
+        repo.observe_file_write("src/parser.rs", b"fn parse_tokens_v2() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Rewrite parser").unwrap();
+        repo.narrative()
+            .append_log("2026-01-21", "11:00", "Rewrote the parser")
+            .unwrap();
+        let second = repo.commit("Log parser rewrite", None, "agent").unwrap();
+
+        let pack = repo
+            .at_commit(&first.as_hex()[..8], |repo| {
+                repo.build_pack("src/parser.rs", &config)
+            })
+            .unwrap();
+        assert_eq!(pack.head_commit, first);
+        assert!(pack
+            .retrieved
+            .iter()
+            .any(|chunk| chunk.snippet.contains("parse_tokens_v1")));
+        assert!(!pack
+            .retrieved
+            .iter()
+            .any(|chunk| chunk.snippet.contains("parse_tokens_v2")));
+        assert!(pack.recent_narrative.contains("Started the parser"));
+        assert!(!pack.recent_narrative.contains("Rewrote the parser"));
+
+        // The current state is untouched
+        assert_eq!(repo.head_id().unwrap(), second);
+        let pack = repo.build_pack("src/parser.rs", &config).unwrap();
+        assert!(pack
+            .retrieved
+            .iter()
+            .any(|chunk| chunk.snippet.contains("parse_tokens_v2")));
+        assert!(pack.recent_narrative.contains("Rewrote the parser"));
+
+        assert!(repo.at_commit("no-such-ref", |_| Ok(())).is_err());
+        assert!(matches!(
+            repo.dry_run(|repo| repo.at_commit("HEAD", |_| Ok(()))),
+            Err(CtxError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_pack_scores_narrative() {
+        let tmp = TempDir::new().unwrap();
+        // 2026-01-22 12:00 UTC
+        let repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_time_provider(|| 1_769_083_200);
+
+        let narrative = repo.narrative();
+        narrative.create_task("Fix parser crash", "").unwrap();
+        narrative.create_task("Write release notes", "").unwrap();
+        narrative
+            .append_log("2026-01-21", "10:00", "Looked at the lexer")
+            .unwrap();
+        narrative
+            .append_log("2025-12-01", "10:00", "Old entry")
+            .unwrap();
+
+        let config = crate::pack::RetrievalConfig {
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo
+            .build_pack("why does the parser crash", &config)
+            .unwrap();
+        let narrative = &pack.recent_narrative;
+        assert!(narrative.contains("Fix parser crash"));
+        assert!(!narrative.contains("Write release notes"));
+        assert!(narrative.contains("log/2026-01-21.md"));
+        assert!(!narrative.contains("log/2025-12-01.md"));
+        assert_eq!(
+            pack.token_budget.narrative,
+            crate::pack::estimate_tokens(narrative)
+        );
+
+        // Without a matching title, the newest open task stands in
+        let pack = repo.build_pack("unrelated", &config).unwrap();
+        assert!(pack.recent_narrative.contains("Write release notes"));
+
+        let tight = crate::pack::RetrievalConfig {
+            narrative_budget: 1,
+            ..config
+        };
+        let pack = repo.build_pack("parser", &tight).unwrap();
+        assert!(pack.recent_narrative.is_empty());
+        assert_eq!(pack.token_budget.narrative, 0);
+    }
+
+    #[test]
+    fn test_build_pack_pins_and_excludes() {
+        use crate::types::{NodeId, NodeKind};
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'a'; 300]).unwrap();
+        repo.observe_file_write("src/b.rs", &[b'b'; 300]).unwrap();
+        repo.observe_file_write("Cargo.lock", &[b'l'; 40]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        let pin = |path: &str| NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        };
+        // Room for one file, but the pin is included regardless
+        let config = crate::pack::RetrievalConfig {
+            token_budget: 100,
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            pinned: vec![pin("src/b.rs")],
+            exclude: vec!["*.lock".to_string()],
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/a.rs Cargo.lock", &config).unwrap();
+        let titles: Vec<_> = pack.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["src/b.rs"]);
+
+        let roomy = crate::pack::RetrievalConfig {
+            token_budget: 1000,
+            ..config.clone()
+        };
+        let pack = repo.build_pack("src/a.rs Cargo.lock", &roomy).unwrap();
+        let titles: Vec<_> = pack.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["src/b.rs", "src/a.rs"]);
+
+        let missing = crate::pack::RetrievalConfig {
+            pinned: vec![pin("src/missing.rs")],
+            ..config
+        };
+        assert!(matches!(
+            repo.build_pack("src/a.rs", &missing),
+            Err(CtxError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_content_limits_stub_chunk_and_skip_large_files() {
+        use crate::large_file::{ContentLimits, ContentPolicy};
+        use crate::pack::{ChunkKind, RetrievalConfig};
+
+        let tmp = TempDir::new().unwrap();
+        let limits = ContentLimits {
+            max_blob_size: 16,
+            large_file_policy: ContentPolicy::Chunked,
+            binary_policy: ContentPolicy::Stub,
+        };
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_content_limits(limits);
+
+        repo.start_session("Add assets").unwrap();
+        let binary = [0x89, b'P', b'N', b'G', 0, 0, 0, 13];
+        let stub_id = repo.observe_file_write("assets/logo.png", &binary).unwrap();
+        let large_text = "x".repeat(40);
+        repo.observe_file_write("data/big.txt", large_text.as_bytes())
+            .unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added assets").unwrap();
+
+        let stub = crate::large_file::large_file(&repo.object_store, stub_id).unwrap();
+        assert!(stub.is_stub());
+        assert_eq!(stub.hash, ObjectId::hash_blob(&binary));
+
+        // Stubs show up in packs as descriptions, never as content
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("assets/logo.png", &config).unwrap();
+        let chunk = &pack.retrieved[0];
+        assert_eq!(chunk.chunk_kind, ChunkKind::Stub);
+        assert_eq!(chunk.title, "assets/logo.png");
+        assert!(chunk
+            .snippet
+            .starts_with("`assets/logo.png`: 8 B binary file, content not stored"));
+
+        // Chunked content survives GC and exports whole; stubs aren't exported
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let dest = tmp.path().join("export");
+        assert_eq!(repo.export_tree(repo.head_id().unwrap(), &dest).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(dest.join("data/big.txt")).unwrap(),
+            large_text
+        );
+        assert!(!dest.join("assets/logo.png").exists());
+
+        // Skipped writes aren't recorded, skipped reads keep only the path
+        let mut repo = repo.with_content_limits(ContentLimits {
+            binary_policy: ContentPolicy::Skip,
+            ..limits
+        });
+        repo.start_session("Skip assets").unwrap();
+        repo.observe_file_write("assets/icon.png", &binary).unwrap();
+        repo.observe_file_read_with_content("assets/logo.png", &binary)
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let session = repo.active_session().unwrap();
+        let observations = staging::collect_observations(
+            session.staging_head(),
+            session.base_commit(),
+            &repo.object_store,
+        )
+        .unwrap();
+        assert!(matches!(
+            observations.as_slice(),
+            [Observation::FileRead {
+                content_id: None,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_ignore_rules_apply_to_observation_and_retrieval() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.observe_file_write("gen/out.rs", b"pub fn out() {}")
+            .unwrap();
+        // Ignored by the storage.ignore defaults
+        assert!(matches!(
+            repo.observe_file_write("target/debug/build.rs", b""),
+            Err(CtxError::PathIgnored { pattern, .. }) if pattern == "target/"
+        ));
+        assert!(repo.observe_file_read("web/app.min.js").is_err());
+        repo.compact_session("Added files").unwrap();
+        drop(repo);
+
+        fs::write(
+            tmp.path().join(crate::IGNORE_FILE),
+            "# generated code\ngen/\n!target/keep.rs\n",
+        )
+        .unwrap();
+        let mut repo = CtxRepo::open(tmp.path()).unwrap();
+        repo.start_session("Edit files").unwrap();
+        assert!(repo
+            .observe_file_read_with_content("gen/out.rs", b"")
+            .is_err());
+        repo.observe_file_read("target/keep.rs").unwrap();
+
+        // Already committed content is no longer retrieved, unless pinned
+        let config = crate::pack::RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/a.rs gen/out.rs", &config).unwrap();
+        let titles: Vec<_> = pack.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["src/a.rs"]);
+
+        let pinned = crate::pack::RetrievalConfig {
+            pinned: vec![NodeId {
+                kind: NodeKind::File,
+                id: "gen/out.rs".into(),
+            }],
+            ..config
+        };
+        let pack = repo.build_pack("src/a.rs", &pinned).unwrap();
+        assert!(pack.retrieved.iter().any(|c| c.title == "gen/out.rs"));
+    }
+
+    #[test]
+    fn test_metrics_observe_store_traffic_and_pack_builds() {
+        use crate::metrics::{CounterMetric, MetricsRegistry};
+
+        let tmp = TempDir::new().unwrap();
+        let registry = Arc::new(MetricsRegistry::new());
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_metrics(registry.clone());
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.compact_session("Added files").unwrap();
+        assert!(registry.counter(CounterMetric::ObjectWrites) > 0);
+        assert!(registry.counter(CounterMetric::ObjectBytesWritten) > 0);
+
+        let reads = registry.counter(CounterMetric::ObjectReads);
+        repo.build_pack("src/a.rs", &crate::pack::RetrievalConfig::default())
+            .unwrap();
+        assert!(registry.counter(CounterMetric::ObjectReads) > reads);
+        assert_eq!(registry.histogram(HistogramMetric::PackBuildSeconds).0, 1);
+        assert!(registry.histogram(HistogramMetric::IndexQuerySeconds).0 > 0);
+    }
+
+    #[test]
+    fn test_concurrent_pack_builds_during_session() {
+        use std::sync::RwLock;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Add files").unwrap();
+        for i in 0..8 {
+            let content = format!("pub fn f{}() {{}}", i);
+            repo.observe_file_write(&format!("src/f{}.rs", i), content.as_bytes())
+                .unwrap();
+        }
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+        drop(repo);
+
+        // A fresh handle, so the readers race to load the index
+        let mut repo = CtxRepo::open(tmp.path()).unwrap();
+        repo.start_session("Keep working").unwrap();
+        let repo = Arc::new(RwLock::new(repo));
+
+        // The writer keeps its session while readers build packs in parallel
+        let writer = {
+            let repo = Arc::clone(&repo);
+            std::thread::spawn(move || {
+                for i in 0..20 {
+                    let content = format!("pub fn g{}() {{}}", i);
+                    let mut repo = repo.write().unwrap();
+                    repo.observe_file_write(&format!("src/g{}.rs", i), content.as_bytes())
+                        .unwrap();
+                    repo.flush_active_session().unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|t| {
+                let repo = Arc::clone(&repo);
+                std::thread::spawn(move || {
+                    let config = crate::pack::RetrievalConfig {
+                        use_scc: t % 2 == 0,
+                        ..Default::default()
+                    };
+                    for i in 0..10 {
+                        let query = format!("src/f{}.rs", (t + i) % 8);
+                        let pack = repo.read().unwrap().build_pack(&query, &config).unwrap();
+                        assert!(pack.retrieved.iter().any(|c| c.title == query));
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let mut repo = Arc::try_unwrap(repo).ok().unwrap().into_inner().unwrap();
+        // The session start plus one step per write
+        assert_eq!(repo.active_session().unwrap().step_count(), 21);
+        repo.compact_session("More files").unwrap();
+        assert!(repo
+            .index()
+            .unwrap()
+            .lookup_path("src/g19.rs")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_build_pack_explain_records_budget_drops() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'a'; 300]).unwrap();
+        repo.observe_file_write("src/b.rs", &[b'b'; 300]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        // Each file is ~75 tokens, so only one fits
+        let config = crate::pack::RetrievalConfig {
+            token_budget: 100,
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            explain: true,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/a.rs src/b.rs", &config).unwrap();
+        let explanation = pack.explanation.unwrap();
+
+        assert_eq!(explanation.seeds.len(), 2);
+        assert_eq!(explanation.seeds[0].node, "File::src/a.rs");
+        assert_eq!(explanation.seeds[0].reason, "path named in query");
+
+        assert_eq!(explanation.candidates.len(), 2);
+        let included: Vec<_> = explanation
+            .candidates
+            .iter()
+            .filter(|c| c.included)
+            .collect();
+        assert_eq!(included.len(), 1);
+        assert_eq!(included[0].title, pack.retrieved[0].title);
+        let dropped = explanation.candidates.iter().find(|c| !c.included).unwrap();
+        assert!(dropped
+            .dropped_reason
+            .as_deref()
+            .unwrap()
+            .starts_with("token budget exhausted"));
+        assert!(dropped.path.is_empty());
+
+        let unexplained = crate::pack::RetrievalConfig {
+            explain: false,
+            ..config
+        };
+        assert!(repo
+            .build_pack("src/a.rs", &unexplained)
+            .unwrap()
+            .explanation
+            .is_none());
+    }
+
+    #[test]
+    fn test_build_pack_streaming_matches_pack_and_stops_early() {
+        use crate::pack::PackStreamItem;
+        use std::ops::ControlFlow;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", &[b'a'; 300]).unwrap();
+        repo.observe_file_write("src/b.rs", &[b'b'; 300]).unwrap();
+        repo.observe_file_write("src/c.rs", &[b'c'; 300]).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        let config = crate::pack::RetrievalConfig {
+            token_budget: 200,
+            response_reserve: 0,
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let query = "src/a.rs src/b.rs src/c.rs";
+        let pack = repo.build_pack(query, &config).unwrap();
+
+        let mut items = Vec::new();
+        let budget = repo
+            .build_pack_streaming(query, &config, |item| {
+                items.push(item);
+                Ok(ControlFlow::Continue(()))
+            })
+            .unwrap();
+        assert!(matches!(items.first(), Some(PackStreamItem::Header { .. })));
+        assert!(matches!(items.last(), Some(PackStreamItem::Done { .. })));
+        let streamed: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                PackStreamItem::Chunk(chunk) => Some(chunk.title.clone()),
+                _ => None,
+            })
+            .collect();
+        let built: Vec<_> = pack.retrieved.iter().map(|c| c.title.clone()).collect();
+        assert_eq!(streamed, built);
+        assert_eq!(budget.used, pack.token_budget.used);
+
+        // Stopping after the first chunk ends the stream without `Done`
+        let mut chunks = 0;
+        let mut saw_done = false;
+        repo.build_pack_streaming(query, &config, |item| {
+            saw_done |= matches!(item, PackStreamItem::Done { .. });
+            if matches!(item, PackStreamItem::Chunk(_)) {
+                chunks += 1;
+                return Ok(ControlFlow::Break(()));
+            }
+            Ok(ControlFlow::Continue(()))
+        })
+        .unwrap();
+        assert_eq!(chunks, 1);
+        assert!(!saw_done);
+    }
+
+    #[test]
+    fn test_pack_builds_recorded_in_audit_log() {
+        use crate::pack::PackStreamItem;
+        use std::ops::ControlFlow;
 
-/// Report from analyzing all Rust files in a project.
-#[derive(Debug, Clone)]
-pub struct AnalysisReport {
-    /// Number of files successfully analyzed.
-    pub files_analyzed: usize,
-    /// Total symbols found (functions, structs, etc.).
-    pub symbols_found: usize,
-    /// Total function calls resolved.
-    pub calls_resolved: usize,
-    /// Total edges generated.
-    pub edges_generated: usize,
-    /// ObjectId of the stored EdgeBatch.
-    pub edge_batch_id: ObjectId,
-    /// ObjectId of the created commit.
-    pub commit_id: ObjectId,
-}
+        let tmp = TempDir::new().unwrap();
+        let agent = AgentIdentity::agent("coder", "test-model");
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_identity(agent.clone());
+
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() {}").unwrap();
+        repo.observe_file_write("src/b.rs", b"fn b() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
 
-/// Report from analyzing a single Rust file.
-#[derive(Debug, Clone)]
-pub struct FileAnalysisReport {
-    /// Path to the analyzed file.
-    pub path: PathBuf,
-    /// Number of symbols found.
-    pub symbols: usize,
-    /// Number of calls resolved.
-    pub calls: usize,
-    /// Number of edges generated.
-    pub edges: usize,
-    /// ObjectId of the stored EdgeBatch.
-    pub edge_batch_id: ObjectId,
-    /// ObjectId of the created commit.
-    pub commit_id: ObjectId,
-}
+        let config = crate::pack::RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let query = "src/a.rs src/b.rs";
+        let pack = repo.build_pack(query, &config).unwrap();
+        assert!(!pack.retrieved.is_empty());
+        repo.build_pack_streaming(query, &config, |item| {
+            if matches!(item, PackStreamItem::Chunk(_)) {
+                return Ok(ControlFlow::Break(()));
+            }
+            Ok(ControlFlow::Continue(()))
+        })
+        .unwrap();
+        // A dry run's pack is handed over too, so it's audited
+        repo.dry_run(|repo| repo.build_pack(query, &config))
+            .unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+        let records = repo.retrieval_records(0).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].kind, "pack");
+        assert_eq!(records[0].actor, Some(agent.to_string()));
+        assert_eq!(records[0].query.as_deref(), Some(query));
+        assert_eq!(records[0].head_commit, pack.head_commit.as_hex());
+        let ids: Vec<_> = pack
+            .retrieved
+            .iter()
+            .map(|c| c.object_id.as_hex())
+            .collect();
+        let recorded: Vec<_> = records[0]
+            .chunks
+            .iter()
+            .map(|c| c.object_id.clone())
+            .collect();
+        assert_eq!(recorded, ids);
+        // Only the chunk the sink took before stopping was exposed
+        assert_eq!(records[1].kind, "streaming");
+        assert_eq!(records[1].chunks.len(), 1);
+        assert_eq!(records[2].kind, "pack");
+
+        // The audit config is read when the repository is opened
+        let path = tmp.path().join(".ctx/config.toml");
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(
+            &path,
+            text.replace("redact_queries = false", "redact_queries = true"),
+        )
+        .unwrap();
+        let repo = CtxRepo::open(tmp.path()).unwrap();
+        repo.build_pack(query, &config).unwrap();
+        let records = repo.retrieval_records(0).unwrap();
+        assert_eq!(records[3].query, None);
+        assert_eq!(records[3].query_hash, records[0].query_hash);
+        assert_eq!(
+            repo.retrieval_records(records[3].timestamp_unix + 1)
+                .unwrap()
+                .len(),
+            0
+        );
+
+        std::fs::write(
+            &path,
+            text.replace("retrievals = true", "retrievals = false"),
+        )
+        .unwrap();
+        let repo = CtxRepo::open(tmp.path()).unwrap();
+        repo.build_pack(query, &config).unwrap();
+        assert_eq!(repo.retrieval_records(0).unwrap().len(), 4);
+    }
 
     #[test]
-    fn test_init_creates_structure() {
+    fn test_pack_not_handed_over_when_audit_fails() {
+        use std::ops::ControlFlow;
+
         let tmp = TempDir::new().unwrap();
-        CtxRepo::init(tmp.path()).unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Add files").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added files").unwrap();
+
+        // A directory where the log should be makes every append fail
+        std::fs::create_dir_all(tmp.path().join(".ctx").join(AUDIT_FILE)).unwrap();
+        let config = crate::pack::RetrievalConfig::default();
+        assert!(repo.build_pack("src/a.rs", &config).is_err());
+        assert!(repo
+            .dry_run(|repo| repo.build_pack("src/a.rs", &config))
+            .is_err());
+        let result =
+            repo.build_pack_streaming("src/a.rs", &config, |_| Ok(ControlFlow::<()>::Continue(())));
+        assert!(result.is_err());
+        assert!(repo.recent_packs(10).unwrap().is_empty());
+    }
 
-        // Check all directories exist
-        assert!(tmp.path().join(".ctx/objects").exists());
-        assert!(tmp.path().join(".ctx/refs").exists());
-        assert!(tmp.path().join(".ctx/narrative/log").exists());
-        assert!(tmp.path().join(".ctx/narrative/tasks").exists());
-        assert!(tmp.path().join(".ctx/index").exists());
+    #[test]
+    fn test_identity_recorded_and_filterable() {
+        use crate::pack::{AuthorFilter, RetrievalConfig};
 
-        // Check files exist
-        assert!(tmp.path().join(".ctx/config.toml").exists());
-        assert!(tmp.path().join(".ctx/.gitignore").exists());
-        assert!(tmp.path().join(".ctx/narrative/README.md").exists());
-        assert!(tmp.path().join(".ctx/HEAD").exists());
+        let tmp = TempDir::new().unwrap();
+        let agent = AgentIdentity::agent("coder", "test-model");
+        let human = AgentIdentity::human("alice");
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_identity(agent.clone());
+
+        repo.start_session("Agent work").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() {}").unwrap();
+        repo.observe_file_write("src/b.rs", b"fn b() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        let agent_commit = repo.compact_session("Agent commit").unwrap();
+
+        // Human rewrites a.rs; b.rs keeps the agent's content
+        repo.set_identity(Some(human.clone()));
+        repo.start_session("Human work").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() { todo!() }")
+            .unwrap();
+        repo.observe_file_write("src/b.rs", b"fn b() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        let human_commit = repo.compact_session("Human commit").unwrap();
+
+        let commit: Commit = repo.object_store.get_typed(agent_commit).unwrap();
+        assert_eq!(commit.author, Some(agent));
+        let commit: Commit = repo.object_store.get_typed(human_commit).unwrap();
+        assert_eq!(commit.author, Some(human));
+
+        let titles = |repo: &mut CtxRepo, author_filter: AuthorFilter| {
+            let config = RetrievalConfig {
+                include_active_task: false,
+                include_log: false,
+                author_filter,
+                ..Default::default()
+            };
+            let pack = repo.build_pack("src/a.rs src/b.rs", &config).unwrap();
+            let mut titles: Vec<String> = pack.retrieved.into_iter().map(|c| c.title).collect();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(
+            titles(&mut repo, AuthorFilter::Any),
+            vec!["src/a.rs", "src/b.rs"]
+        );
+        assert_eq!(titles(&mut repo, AuthorFilter::Humans), vec!["src/a.rs"]);
+        assert_eq!(titles(&mut repo, AuthorFilter::Agents), vec!["src/b.rs"]);
+    }
+
+    #[test]
+    fn test_repeated_observations_collapse() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Read around").unwrap();
+        for _ in 0..3 {
+            repo.observe_file_read_with_content("src/a.rs", b"pub fn a() {}")
+                .unwrap();
+            repo.observe_command("cargo check", Some(0), Some(b"ok"))
+                .unwrap();
+        }
+        let pending = repo.active_session.as_ref().unwrap().pending_observations();
+        assert_eq!(pending.len(), 2);
+        assert!(pending.iter().all(|observation| observation.count() == 3));
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Read around").unwrap();
+        assert!(repo.head().unwrap().tags.is_empty());
+
+        let config_path = repo.ctx_dir().join("config.toml");
+        let config = std::fs::read_to_string(&config_path)
+            .unwrap()
+            .replace("dedup_reads = true", "dedup_reads = false");
+        std::fs::write(&config_path, config).unwrap();
+        repo.start_session("Read again").unwrap();
+        for _ in 0..2 {
+            repo.observe_file_read("src/a.rs").unwrap();
+            repo.observe_command("cargo check", Some(0), None).unwrap();
+        }
+        let pending = repo.active_session.as_ref().unwrap().pending_observations();
+        assert_eq!(pending.len(), 3);
+    }
+
+    #[test]
+    fn test_auto_flush_policy() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let mut config = crate::config::Config::load(&repo.ctx_dir()).unwrap();
+        config.session.auto_flush_observations = Some(2);
+        config.session.auto_flush_after = vec![crate::ObservationKind::Command];
+        config.save(&repo.ctx_dir()).unwrap();
+
+        repo.start_session("Flush on its own").unwrap();
+        let steps = |repo: &CtxRepo| repo.active_session().unwrap().step_count();
+        assert_eq!(steps(&repo), 1);
+        repo.observe_note("first").unwrap();
+        assert_eq!(steps(&repo), 1);
+        repo.observe_file_read("src/a.rs").unwrap();
+        assert_eq!(steps(&repo), 2);
+        repo.observe_command("cargo test", Some(0), None).unwrap();
+        assert_eq!(steps(&repo), 3);
+        assert!(!repo.active_session().unwrap().has_pending_observations());
+
+        // A recovered session keeps the policy
+        let mut repo = CtxRepo::open(tmp.path()).unwrap();
+        repo.recover_session().unwrap();
+        repo.observe_command("cargo build", Some(0), None).unwrap();
+        assert_eq!(steps(&repo), 4);
+    }
+
+    #[test]
+    fn test_tags_filter_retrieval() {
+        use crate::pack::RetrievalConfig;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Fix login").unwrap();
+        let tags = Metadata::from([("ticket".to_string(), "PROJ-123".to_string())]);
+        repo.observe_note_with_tags("Working on PROJ-123", tags)
+            .unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.observe_file_write("src/b.rs", b"pub fn b() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.observe_file_read("src/c.rs").unwrap();
+        repo.tag_last_observation(Metadata::from([("tool".to_string(), "grep".to_string())]))
+            .unwrap();
+        repo.observe_file_write("src/c.rs", b"pub fn c() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        assert!(repo.tag_last_observation(Metadata::new()).is_err());
+        repo.compact_session("Fix login").unwrap();
+
+        let head = repo.head().unwrap();
+        assert_eq!(
+            head.tags.keys().collect::<Vec<_>>(),
+            vec!["ticket:PROJ-123", "tool:grep"]
+        );
+
+        let titles = |repo: &CtxRepo, tags: &[&str]| {
+            let config = RetrievalConfig {
+                include_active_task: false,
+                include_log: false,
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            };
+            let pack = repo
+                .build_pack("src/a.rs src/b.rs src/c.rs", &config)
+                .unwrap();
+            let mut titles: Vec<String> = pack.retrieved.into_iter().map(|c| c.title).collect();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(titles(&repo, &[]), vec!["src/a.rs", "src/b.rs", "src/c.rs"]);
+        assert_eq!(titles(&repo, &["ticket:PROJ-123"]), vec!["src/a.rs"]);
+        assert_eq!(titles(&repo, &["ticket"]), vec!["src/a.rs"]);
+        assert_eq!(
+            titles(&repo, &["ticket:PROJ-123", "tool:grep"]),
+            vec!["src/a.rs", "src/c.rs"]
+        );
+        assert!(titles(&repo, &["ticket:PROJ-1"]).is_empty());
+
+        // Tags survive an index rebuild
+        repo.rebuild_index().unwrap();
+        assert_eq!(titles(&repo, &["ticket"]), vec!["src/a.rs"]);
+    }
+
+    #[test]
+    fn test_actors_within_session() {
+        use crate::pack::RetrievalConfig;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        assert!(matches!(
+            repo.observe_as("coder", |repo| repo.observe_note("no session")),
+            Err(CtxError::NoActiveSession)
+        ));
+
+        repo.start_session("Add lexer").unwrap();
+        assert!(matches!(
+            repo.observe_as("two words", |repo| repo.observe_note("x")),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        repo.observe_as("planner", |repo| repo.observe_file_read("src/a.rs"))
+            .unwrap();
+        repo.observe_as("coder", |repo| {
+            repo.observe_file_write("src/b.rs", b"pub fn b() {}")?;
+            repo.observe_file_write("src/c.rs", b"pub fn c() {}")
+        })
+        .unwrap();
+        repo.observe_file_write("src/a.rs", b"pub fn a() {}")
+            .unwrap();
+        assert_eq!(repo.active_session().unwrap().actor(), None);
+        repo.flush_active_session().unwrap();
+        repo.observe_as("reviewer", |repo| {
+            repo.observe_command("cargo test", Some(1), None)
+        })
+        .unwrap();
+
+        let actors = repo.session_status().unwrap().unwrap().actors;
+        assert_eq!(
+            actors.keys().collect::<Vec<_>>(),
+            vec!["coder", "planner", "reviewer"]
+        );
+        assert_eq!(actors["coder"].files_written, vec!["src/b.rs", "src/c.rs"]);
+        assert_eq!(actors["planner"].files_read, vec!["src/a.rs"]);
+        assert_eq!(actors["reviewer"].failed_commands, 1);
+
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Add lexer").unwrap();
+        // Actor tags cover the actor's own paths, not the whole step
+        let head = repo.head().unwrap();
+        assert_eq!(
+            head.tags["actor:coder"].iter().collect::<Vec<_>>(),
+            vec!["src/b.rs", "src/c.rs"]
+        );
+
+        let titles = |repo: &CtxRepo, actors: &[&str]| {
+            let config = RetrievalConfig {
+                include_active_task: false,
+                include_log: false,
+                actors: actors.iter().map(|a| a.to_string()).collect(),
+                ..Default::default()
+            };
+            let pack = repo
+                .build_pack("src/a.rs src/b.rs src/c.rs", &config)
+                .unwrap();
+            let mut titles: Vec<String> = pack.retrieved.into_iter().map(|c| c.title).collect();
+            titles.sort();
+            titles
+        };
+        assert_eq!(titles(&repo, &[]), vec!["src/a.rs", "src/b.rs", "src/c.rs"]);
+        assert_eq!(titles(&repo, &["coder"]), vec!["src/b.rs", "src/c.rs"]);
+        assert_eq!(titles(&repo, &["planner"]), vec!["src/a.rs"]);
+        assert!(titles(&repo, &["reviewer"]).is_empty());
+    }
+
+    #[test]
+    fn test_facts_persist_until_invalidated() {
+        use crate::pack::{ChunkKind, RetrievalConfig};
+        use std::sync::atomic::{AtomicI64, Ordering};
+
+        let tmp = TempDir::new().unwrap();
+        let clock = Arc::new(AtomicI64::new(1_769_083_200));
+        let now = clock.clone();
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_time_provider(move || now.load(Ordering::SeqCst));
+        let file = |path: &str| NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        };
+
+        let fact = Fact::new(file("src/db.rs"), "uses", "redb");
+        assert!(matches!(
+            repo.observe_fact(fact.clone()),
+            Err(CtxError::NoActiveSession)
+        ));
+
+        repo.start_session("Storage").unwrap();
+        repo.observe_file_write("src/db.rs", b"use redb;").unwrap();
+        repo.observe_file_write("src/api.rs", b"pub fn api() {}")
+            .unwrap();
+        repo.observe_fact(fact.clone().with_evidence("src/db.rs"))
+            .unwrap();
+        let ttl = Fact::new(file("src/api.rs"), "owner", "platform team")
+            .with_ttl(Duration::from_secs(60));
+        repo.observe_fact(ttl).unwrap();
+        assert!(repo
+            .observe_fact(fact.clone().with_evidence("src/missing.rs"))
+            .is_err());
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Storage").unwrap();
+
+        assert_eq!(repo.facts().unwrap().len(), 2);
+        let about_db = repo.facts_about(&file("src/db.rs")).unwrap();
+        assert_eq!(about_db.len(), 1);
+        assert_eq!(about_db[0].1.value, "redb");
+
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/db.rs", &config).unwrap();
+        let known = pack
+            .retrieved
+            .iter()
+            .find(|c| c.chunk_kind == ChunkKind::Fact)
+            .unwrap();
+        assert_eq!(known.title, "Known facts");
+        assert_eq!(known.snippet, "- `src/db.rs` uses: redb");
+
+        // A newer fact with the same subject and predicate replaces it
+        repo.start_session("Upgrade").unwrap();
+        repo.observe_fact(Fact::new(file("src/db.rs"), "uses", "redb 2"))
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Upgrade").unwrap();
+        let about_db = repo.facts_about(&file("src/db.rs")).unwrap();
+        assert_eq!(about_db.len(), 1);
+        assert_eq!(about_db[0].1.value, "redb 2");
+
+        // Evidence changing invalidates a fact, and so does its TTL passing
+        repo.start_session("Swap store").unwrap();
+        repo.observe_file_write("src/api.rs", b"pub fn api2() {}")
+            .unwrap();
+        repo.observe_fact(fact.with_evidence("src/api.rs")).unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Swap store").unwrap();
+        assert_eq!(repo.facts().unwrap().len(), 2);
+        repo.start_session("Rewrite api").unwrap();
+        repo.observe_file_write("src/api.rs", b"pub fn api3() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Rewrite api").unwrap();
+        let facts = repo.facts().unwrap();
+        assert_eq!(facts.len(), 1);
+        assert_eq!(facts[0].1.predicate, "owner");
+
+        clock.fetch_add(60, Ordering::SeqCst);
+        assert!(repo.facts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decisions_reach_packs() {
+        use crate::pack::{ChunkKind, RetrievalConfig};
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Pick a store").unwrap();
+        repo.observe_file_write("src/store.rs", b"pub struct Store;")
+            .unwrap();
+        repo.observe_file_write("src/other.rs", b"pub fn other() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Add store").unwrap();
+
+        assert!(repo
+            .record_decision(Decision::new("Store?", " ", ""))
+            .is_err());
+        let mut decision = Decision::new(
+            "Which storage engine?",
+            "redb",
+            "Single file and no compaction",
+        );
+        decision.options = vec!["redb".into(), "sled".into()];
+        decision.paths = vec!["src/store.rs".into()];
+        let decision_id = repo.record_decision(decision).unwrap();
+        assert!(repo.head().unwrap().message.starts_with("Decision: redb"));
+        assert_eq!(repo.decisions().unwrap()[0].0, decision_id);
+
+        let pack = |repo: &CtxRepo, query: &str, decisions_only: bool| {
+            let config = RetrievalConfig {
+                include_active_task: false,
+                include_log: false,
+                decisions_only,
+                ..Default::default()
+            };
+            repo.build_pack(query, &config).unwrap().retrieved
+        };
+
+        // Linked through the files the query reaches
+        let chunks = pack(&repo, "src/store.rs", false);
+        assert!(chunks
+            .iter()
+            .any(|c| c.chunk_kind == ChunkKind::FileContent));
+        let decision = chunks
+            .iter()
+            .find(|c| c.chunk_kind == ChunkKind::Decision)
+            .unwrap();
+        assert_eq!(decision.object_id, decision_id);
+        assert!(decision.snippet.contains("**Reasoning**: Single file"));
+
+        let only = pack(&repo, "src/store.rs", true);
+        assert_eq!(only.len(), 1);
+        assert_eq!(only[0].title, "Decision: redb");
+
+        // Or by naming the decision, and never for unrelated files
+        assert_eq!(pack(&repo, "why redb", true).len(), 1);
+        assert!(pack(&repo, "src/other.rs", true).is_empty());
+
+        // Decision edges survive an index rebuild
+        repo.rebuild_index().unwrap();
+        assert_eq!(pack(&repo, "src/store.rs", true).len(), 1);
+    }
+
+    #[test]
+    fn test_past_answers_lead_packs() {
+        use crate::pack::{ChunkKind, RetrievalConfig};
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        assert!(matches!(
+            repo.record_answer("Where is config parsing?", "src/config.rs"),
+            Err(CtxError::NoActiveSession)
+        ));
+
+        repo.start_session("Add a config option").unwrap();
+        repo.observe_file_write("src/config.rs", b"pub fn parse() {}")
+            .unwrap();
+        assert!(repo.record_answer("Where is config parsing?", " ").is_err());
+        let pair_id = repo
+            .record_answer("Where is config parsing?", "In src/config.rs, see parse()")
+            .unwrap();
+        // Not in the log until the session is compacted
+        assert!(repo.answers().unwrap().is_empty());
+        repo.compact_session("Add option").unwrap();
+
+        let answers = repo.answers().unwrap();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].0, pair_id);
+        assert_eq!(answers[0].1.paths, vec!["src/config.rs"]);
+        assert_eq!(answers[0].1.task.as_deref(), Some("Add a config option"));
+
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let pack = repo
+            .build_pack("where does config parsing happen in src/config.rs", &config)
+            .unwrap();
+        assert_eq!(pack.retrieved[0].chunk_kind, ChunkKind::Answer);
+        assert_eq!(pack.retrieved[0].title, "Q: Where is config parsing?");
+        assert!(pack.retrieved[0]
+            .snippet
+            .contains("**A**: In src/config.rs, see parse()"));
+        assert!(pack.retrieved[1..]
+            .iter()
+            .any(|c| c.chunk_kind == ChunkKind::FileContent));
+
+        // Unrelated queries don't get it
+        let pack = repo.build_pack("how are packs cached", &config).unwrap();
+        assert!(pack
+            .retrieved
+            .iter()
+            .all(|c| c.chunk_kind != ChunkKind::Answer));
+
+        // Its file edges survive an index rebuild
+        repo.rebuild_index().unwrap();
+        let qa_nodes = repo
+            .index()
+            .unwrap()
+            .get_edges_to(
+                &NodeId {
+                    kind: NodeKind::File,
+                    id: "src/config.rs".into(),
+                },
+                EdgeLabel::Mentions,
+            )
+            .unwrap();
+        assert!(qa_nodes
+            .iter()
+            .any(|n| n.kind == NodeKind::Qa && n.id == pair_id.as_hex()));
+    }
+
+    #[test]
+    fn test_path_history() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        let write = |repo: &mut CtxRepo, task: &str, content: &[u8]| {
+            repo.start_session(task).unwrap();
+            repo.observe_file_write("src/lib.rs", content).unwrap();
+            repo.flush_active_session().unwrap();
+            repo.compact_session(task).unwrap()
+        };
+        let first = write(&mut repo, "Create lib", b"pub fn a() {}");
+        let unchanged = write(&mut repo, "Touch lib", b"pub fn a() {}");
+        let second = write(&mut repo, "Extend lib", b"pub fn a() {}\npub fn b() {}");
+
+        let history = repo.path_history("src/lib.rs").unwrap();
+        let ids: Vec<ObjectId> = history.iter().map(|e| e.commit_id).collect();
+        assert_eq!(ids, vec![second, first]);
+        assert!(!ids.contains(&unchanged));
+        assert_eq!(history[0].message, "Extend lib");
+        assert_eq!(history[0].previous_blob_id, Some(history[1].blob_id));
+        assert_eq!(history[1].previous_blob_id, None);
+
+        assert!(repo.path_history("src/missing.rs").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_compaction_detects_renames() {
+        use crate::types::{EdgeLabel, NodeId, NodeKind};
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        let write = |repo: &mut CtxRepo, task: &str, files: &[(&str, &[u8])]| {
+            repo.start_session(task).unwrap();
+            for (path, content) in files {
+                repo.observe_file_write(path, content).unwrap();
+            }
+            repo.flush_active_session().unwrap();
+            repo.compact_session(task).unwrap()
+        };
+        let edited: &[u8] = b"pub fn a() {}\npub fn b() {}\n";
+        write(
+            &mut repo,
+            "Create util",
+            &[("src/util.rs", b"pub fn a() {}\n")],
+        );
+        write(&mut repo, "Edit util", &[("src/util.rs", edited)]);
+        write(
+            &mut repo,
+            "Move util",
+            &[
+                ("src/helpers.rs", edited),
+                ("src/lib.rs", b"mod helpers;\n"),
+            ],
+        );
+
+        let file = |path: &str| NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        };
+        let index = repo.index().unwrap();
+        assert_eq!(
+            index
+                .get_edges_from(&file("src/util.rs"), EdgeLabel::RenamedTo)
+                .unwrap(),
+            vec![file("src/helpers.rs")]
+        );
+        assert!(index
+            .get_edges_to(&file("src/lib.rs"), EdgeLabel::RenamedTo)
+            .unwrap()
+            .is_empty());
+        assert!(!index.indexed_paths().unwrap().contains_key("src/util.rs"));
+        let current = index.lookup_path("src/helpers.rs").unwrap();
+        assert!(current.is_some());
+        assert_eq!(index.lookup_path("src/util.rs").unwrap(), current);
+
+        let history = repo.path_history("src/helpers.rs").unwrap();
+        let messages: Vec<&str> = history.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Move util", "Edit util", "Create util"]);
+        assert_eq!(history[0].renamed_from.as_deref(), Some("src/util.rs"));
+        assert_eq!(history[1].renamed_from, None);
+    }
+
+    #[test]
+    fn test_federated_pack_across_nested_repos() {
+        use crate::pack::RetrievalConfig;
+        use crate::types::{NodeId, NodeKind};
+
+        let tmp = TempDir::new().unwrap();
+        let root = fs::canonicalize(tmp.path()).unwrap();
+        let child_root = root.join("crates/core");
+        fs::create_dir_all(child_root.join("src")).unwrap();
+
+        let write = |repo: &mut CtxRepo, path: &str, content: &[u8]| {
+            repo.start_session("Write").unwrap();
+            repo.observe_file_write(path, content).unwrap();
+            repo.flush_active_session().unwrap();
+            repo.compact_session("Write").unwrap();
+        };
+        let mut parent = CtxRepo::init(&root).unwrap();
+        write(&mut parent, "src/auth.rs", b"pub fn login() {}");
+        let mut child = CtxRepo::init(&child_root).unwrap();
+        write(&mut child, "src/db.rs", b"pub fn connect() {}");
+        // Release the index locks
+        drop((parent, child));
+
+        let nearest = CtxRepo::open_with_scope(child_root.join("src"), RepoScope::Nearest).unwrap();
+        assert_eq!(nearest.root(), child_root);
+        assert!(nearest.child_repos().is_empty());
+        assert_eq!(nearest.parent_repo(), Some(root.clone()));
+
+        let repo = CtxRepo::open_with_scope(child_root.join("src"), RepoScope::Workspace).unwrap();
+        assert_eq!(repo.root(), root);
+        assert_eq!(repo.child_repos(), std::slice::from_ref(&child_root));
+
+        // The pin names the child's file relative to the workspace root
+        let config = RetrievalConfig {
+            pinned: vec![NodeId {
+                kind: NodeKind::File,
+                id: "crates/core/src/db.rs".to_string(),
+            }],
+            ..Default::default()
+        };
+        let pack = repo.build_federated_pack("src/auth.rs", &config).unwrap();
+        let titles: Vec<&str> = pack.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles[0], "crates/core/src/db.rs");
+        assert!(titles.contains(&"src/auth.rs"));
+        assert!(pack.token_budget.used <= config.token_budget);
+    }
+
+    #[test]
+    fn test_resolve_commit() {
+        let tmp = TempDir::new().unwrap();
+        let repo = CtxRepo::init(tmp.path()).unwrap();
+        let root = repo.head_id().unwrap();
+        let next = repo.commit("Second", None, "user").unwrap();
+
+        assert_eq!(repo.resolve_commit("HEAD").unwrap(), next);
+        assert_eq!(repo.resolve_commit("HEAD~1").unwrap(), root);
+        assert_eq!(repo.resolve_commit("main").unwrap(), next);
+        assert_eq!(repo.resolve_commit(&root.as_hex()).unwrap(), root);
+        assert_eq!(repo.resolve_commit(&root.as_hex()[..12]).unwrap(), root);
+        assert!(repo.resolve_commit("HEAD~2").is_err());
+        assert!(repo.resolve_commit("zz").is_err());
+    }
+
+    #[test]
+    fn test_compaction_adds_heuristic_module_edges() {
+        use crate::types::{EdgeLabel, NodeId, NodeKind};
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Split graph module").unwrap();
+        repo.observe_file_write("src/lib.rs", b"mod graph;\nmod util;\n")
+            .unwrap();
+        repo.observe_file_write("src/graph.rs", b"use crate::util::helper;\n")
+            .unwrap();
+        repo.observe_file_write("src/util.rs", b"pub fn helper() {}\n")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Split graph module").unwrap();
+
+        let file = |path: &str| NodeId {
+            kind: NodeKind::File,
+            id: path.to_string(),
+        };
+        let index = repo.index().unwrap();
+        let declared = index
+            .get_edges_from(&file("src/lib.rs"), EdgeLabel::DeclaresModule)
+            .unwrap();
+        assert_eq!(declared, vec![file("src/graph.rs"), file("src/util.rs")]);
+        let imports = index
+            .get_edges_from(&file("src/graph.rs"), EdgeLabel::Imports)
+            .unwrap();
+        assert_eq!(imports, vec![file("src/util.rs")]);
     }
 
     #[test]
-    fn test_init_creates_initial_commit() {
-        let tmp = TempDir::new().unwrap();
-        let repo = CtxRepo::init(tmp.path()).unwrap();
+    fn test_layered_pack_and_zoom() {
+        use crate::pack::{LayerBudgets, LayerKind, RetrievalConfig};
 
-        let commit = repo.head().unwrap();
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
 
-        assert_eq!(commit.parents.len(), 0);
-        assert_eq!(commit.message, "Initial commit");
-        assert!(commit.edge_batches.is_empty());
-        assert!(commit.narrative_refs.is_empty());
+        repo.start_session("Add modules").unwrap();
+        repo.observe_file_write("README.md", b"# Demo\n").unwrap();
+        repo.observe_file_write("src/lib.rs", b"mod graph;\nmod util;\n")
+            .unwrap();
+        repo.observe_file_write("src/graph.rs", b"mod scc;\n")
+            .unwrap();
+        repo.observe_file_write("src/graph/scc.rs", b"pub fn scc() {}\n")
+            .unwrap();
+        repo.observe_file_write("src/util.rs", b"pub fn helper() {}\n")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Added modules").unwrap();
+
+        let config = RetrievalConfig {
+            token_budget: 4000,
+            response_reserve: 0,
+            expansion_depth: 0,
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo
+            .build_layered_pack("src/graph/scc.rs", &config, &LayerBudgets::default())
+            .unwrap();
+        let kinds: Vec<_> = pack.layers.iter().map(|l| l.kind).collect();
+        assert_eq!(kinds, vec![LayerKind::Overview, LayerKind::Modules]);
+        assert!(pack.layers[0].content.contains("- src/ (4 files)"));
+        assert_eq!(pack.layers[0].anchors, vec!["README.md"]);
+        assert_eq!(
+            pack.layers[1].content,
+            "Modules below each crate root (* = in this pack)\n\
+             - src/lib.rs\n  - src/graph.rs\n    - src/graph/scc.rs *\n  - src/util.rs"
+        );
+        assert_eq!(pack.layers[1].anchors.len(), 4);
+        let layer_tokens: u32 = pack.layers.iter().map(|l| l.tokens).sum();
+        assert!(pack.token_budget.used >= layer_tokens);
+        assert!(pack.to_text().contains("## Module Graph"));
+
+        // A tight module budget keeps the branch leading to the pack's file
+        let tight = LayerBudgets {
+            overview: 20,
+            modules: 30,
+        };
+        let pack = repo
+            .build_layered_pack("src/graph/scc.rs", &config, &tight)
+            .unwrap();
+        let modules = &pack.layers[1];
+        assert_eq!(
+            modules.anchors,
+            vec!["src/lib.rs", "src/graph.rs", "src/graph/scc.rs"]
+        );
+        assert_eq!(modules.omitted, 1);
+        // Only the note on omitted entries may go past a sub-budget
+        assert!(pack.layers.iter().all(|l| l.tokens <= l.budget + 12));
+
+        let zoom = repo.build_zoom_pack("src/util.rs", &config).unwrap();
+        assert_eq!(zoom.retrieved[0].title, "src/util.rs");
+        assert!(zoom.layers.is_empty());
+        assert!(repo.build_zoom_pack("src/missing.rs", &config).is_err());
     }
 
     #[test]
-    fn test_init_head_matches_main() {
+    fn test_frecency_seeds_vague_queries() {
+        use crate::pack::RetrievalConfig;
+
         let tmp = TempDir::new().unwrap();
-        let repo = CtxRepo::init(tmp.path()).unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+
+        repo.start_session("Touch files").unwrap();
+        repo.observe_file_write("src/hot.rs", b"fn hot() {}")
+            .unwrap();
+        repo.observe_file_write("src/cold.rs", b"fn cold() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Touch files").unwrap();
 
-        let head_id = repo.refs().read_head().unwrap();
-        let main_id = repo.refs().read_ref("main").unwrap();
+        repo.start_session("Read hot file").unwrap();
+        repo.observe_file_read("src/hot.rs").unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Read hot file").unwrap();
 
-        assert_eq!(head_id, main_id);
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("what was I doing", &config).unwrap();
+        let titles: Vec<&str> = pack.retrieved.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles.first(), Some(&"src/hot.rs"));
+
+        let deterministic = RetrievalConfig {
+            frecency_boost: false,
+            ..config
+        };
+        let pack = repo.build_pack("what was I doing", &deterministic).unwrap();
+        assert!(pack.retrieved.is_empty());
     }
 
     #[test]
-    fn test_init_fails_if_exists() {
+    fn test_pack_inclusions_are_buffered_for_frecency() {
+        use crate::pack::RetrievalConfig;
+
         let tmp = TempDir::new().unwrap();
-        CtxRepo::init(tmp.path()).unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
 
-        let result = CtxRepo::init(tmp.path());
-        assert!(result.is_err());
+        repo.start_session("Write file").unwrap();
+        repo.observe_file_write("src/a.rs", b"fn a() {}").unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Write file").unwrap();
+
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let count = |repo: &CtxRepo| {
+            repo.index()
+                .unwrap()
+                .frecency_entries()
+                .unwrap()
+                .get("src/a.rs")
+                .map_or(0, |entry| entry.access_count)
+        };
+
+        // Building packs doesn't write to the index
+        let base = count(&repo);
+        repo.build_pack("src/a.rs", &config).unwrap();
+        repo.build_pack("src/a.rs", &config).unwrap();
+        assert_eq!(count(&repo), base);
+        repo.flush_access().unwrap();
+        assert_eq!(count(&repo), base + 2);
+
+        // Enough packs flush on their own, and dropping the handle flushes
+        // the rest
+        for _ in 0..ACCESS_FLUSH_PACKS + 1 {
+            repo.build_pack("src/a.rs", &config).unwrap();
+        }
+        assert_eq!(count(&repo), base + 2 + ACCESS_FLUSH_PACKS as u32);
+        drop(repo);
+        let repo = CtxRepo::open(tmp.path()).unwrap();
+        assert_eq!(count(&repo), base + 3 + ACCESS_FLUSH_PACKS as u32);
     }
 
     #[test]
-    fn test_open_existing() {
+    fn test_glossary_terms_reach_mentioned_files() {
+        use crate::pack::{ChunkKind, RetrievalConfig};
+
         let tmp = TempDir::new().unwrap();
-        CtxRepo::init(tmp.path()).unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
 
-        // Should be able to reopen
-        let repo = CtxRepo::open(tmp.path()).unwrap();
-        assert!(repo.head().is_ok());
+        repo.start_session("Write graph").unwrap();
+        repo.observe_file_write("src/graph.rs", b"pub fn compute_scc() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Write graph").unwrap();
+
+        repo.add_glossary_term("SCC", "strongly connected component used in graph.rs")
+            .unwrap();
+        assert!(repo.add_glossary_term(" ", "empty").is_err());
+
+        let glossary = repo.glossary().unwrap();
+        assert_eq!(glossary.entries.len(), 1);
+        let edges = repo
+            .index()
+            .unwrap()
+            .get_edges_from(
+                &crate::glossary::term_node("SCC"),
+                crate::types::EdgeLabel::Mentions,
+            )
+            .unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].id, "src/graph.rs");
+
+        let config = RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("how are sccs collapsed", &config).unwrap();
+        assert!(pack.retrieved.is_empty());
+
+        let pack = repo.build_pack("how is the SCC ordered", &config).unwrap();
+        assert_eq!(pack.retrieved[0].chunk_kind, ChunkKind::Glossary);
+        assert!(pack.retrieved[0].snippet.contains("strongly connected"));
+        assert!(pack.retrieved.iter().any(|c| c.title == "src/graph.rs"));
+
+        repo.remove_glossary_term("scc").unwrap();
+        assert!(repo.glossary().unwrap().is_empty());
+        assert!(repo.remove_glossary_term("scc").is_err());
     }
 
     #[test]
-    fn test_open_nonexistent_fails() {
+    fn test_documents_reach_mentioned_files() {
+        use crate::pack::RetrievalConfig;
+
         let tmp = TempDir::new().unwrap();
-        let result = CtxRepo::open(tmp.path());
-        assert!(result.is_err());
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let mut config = std::fs::read_to_string(repo.ctx_dir().join("config.toml")).unwrap();
+        config.push_str("\n[documents.study]\ndirectory = \"studies\"\n\n[documents.study.fields.title]\nrequired = true\n");
+        std::fs::write(repo.ctx_dir().join("config.toml"), config).unwrap();
+
+        repo.start_session("Write graph").unwrap();
+        repo.observe_file_write("src/graph.rs", b"pub fn compute_scc() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Write graph").unwrap();
+
+        let fields = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let info = repo
+            .create_document(
+                "incident",
+                &fields(&[
+                    ("title", "Cycle detection hangs in graph.rs"),
+                    ("severity", "high"),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(info.relative_path, "incidents/incident_0001.md");
+        assert!(repo
+            .head()
+            .unwrap()
+            .message
+            .starts_with("Add incident: Cycle"));
+        assert!(repo
+            .create_document("study", &fields(&[("owner", "me")]))
+            .is_err());
+        let study = repo
+            .create_document("study", &fields(&[("title", "Unrelated")]))
+            .unwrap();
+        assert_eq!(study.relative_path, "studies/study_0001.md");
+
+        let edges = repo
+            .index()
+            .unwrap()
+            .get_edges_from(
+                &crate::document::document_node(&info.relative_path),
+                crate::types::EdgeLabel::Mentions,
+            )
+            .unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].id, "src/graph.rs");
+
+        let config = RetrievalConfig {
+            include_active_task: true,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo
+            .build_pack("why does cycle detection hang", &config)
+            .unwrap();
+        assert!(pack.retrieved.iter().any(|c| c.title == "src/graph.rs"));
+        assert!(pack
+            .recent_narrative
+            .contains("## Incident: incidents/incident_0001.md"));
+        assert!(!pack.recent_narrative.contains("Unrelated"));
     }
 
     #[test]
-    fn test_initial_tree_is_empty() {
+    fn test_import_document() {
+        use crate::import::ImportFormat;
+        use crate::pack::RetrievalConfig;
+
         let tmp = TempDir::new().unwrap();
-        let repo = CtxRepo::init(tmp.path()).unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Write graph").unwrap();
+        repo.observe_file_write("src/graph.rs", b"pub fn compute_scc() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Write graph").unwrap();
 
-        let commit = repo.head().unwrap();
-        let tree: Tree = repo.object_store().get_typed(commit.root_tree).unwrap();
+        let html = b"<html><head><title>Cycle detection RFC</title></head>\
+            <body><h2>Proposal</h2><p>Rewrite graph.rs with Tarjan.</p></body></html>";
+        let source = "https://example.com/rfcs/cycles.html";
+        let info = repo
+            .import_document(source, html, ImportFormat::Html)
+            .unwrap();
+        assert_eq!(info.relative_path, "imports/import_0001.md");
+        assert_eq!(repo.head().unwrap().message, "Import: Cycle detection RFC");
+
+        let edges = repo
+            .index()
+            .unwrap()
+            .get_edges_from(
+                &crate::document::document_node(&info.relative_path),
+                crate::types::EdgeLabel::Mentions,
+            )
+            .unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].id, "src/graph.rs");
 
-        assert!(tree.entries.is_empty());
+        let config = RetrievalConfig {
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("cycle detection", &config).unwrap();
+        assert!(pack.retrieved.iter().any(|c| c.title == "src/graph.rs"));
+        assert!(pack
+            .recent_narrative
+            .contains("Rewrite graph.rs with Tarjan."));
+
+        // Importing the same source again updates the document
+        let again = repo
+            .import_document(
+                source,
+                b"<h1>Cycle detection RFC</h1><p>Withdrawn.</p>",
+                ImportFormat::Html,
+            )
+            .unwrap();
+        assert_eq!(again.relative_path, info.relative_path);
+        let other = repo
+            .import_document("notes.txt", b"Other notes.", ImportFormat::Text)
+            .unwrap();
+        assert_eq!(other.relative_path, "imports/import_0002.md");
+        let content = repo.narrative().read_file(&info.relative_path).unwrap();
+        assert!(String::from_utf8(content).unwrap().contains("Withdrawn."));
+
+        assert!(repo
+            .import_document("paper.pdf", b"%PDF-1.4", ImportFormat::Text)
+            .is_err());
     }
 
     #[test]
-    fn test_root_and_ctx_dir() {
+    fn test_search_narrative_includes_deleted_files() {
         let tmp = TempDir::new().unwrap();
         let repo = CtxRepo::init(tmp.path()).unwrap();
+        let narrative = repo.narrative();
+        narrative.ensure_structure().unwrap();
+        narrative
+            .append_log("2026-03-02", "16:40", "Decided to switch the index to redb")
+            .unwrap();
+        let task = narrative
+            .create_task("Evaluate redb", "Compare redb with sled")
+            .unwrap();
+        repo.commit("Narrative", None, "user").unwrap();
+        std::fs::remove_file(&task.path).unwrap();
 
-        assert_eq!(repo.root(), tmp.path());
-        assert_eq!(repo.ctx_dir(), tmp.path().join(".ctx"));
+        let hits = repo
+            .search_narrative("when did we switch to redb", None)
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].path, "log/2026-03-02.md");
+        assert_eq!(hits[0].date.as_deref(), Some("2026-03-02 16:40"));
+        assert_eq!(hits[1].path, "tasks/task_0001.md");
+        assert!(hits[1].date.is_some());
+        assert_eq!(repo.search_narrative("redb", Some(1)).unwrap().len(), 1);
+
+        let mut config = std::fs::read_to_string(repo.ctx_dir().join("config.toml")).unwrap();
+        config.push_str("\n[search]\nenabled = false\n");
+        std::fs::write(repo.ctx_dir().join("config.toml"), config).unwrap();
+        assert!(repo.search_narrative("redb", None).is_err());
     }
 
     #[test]
-    fn test_session_start_and_compact() {
+    fn test_weekly_digest_links_sessions() {
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
 
-        // Start session
-        let session = repo.start_session("Test task").unwrap();
-        assert_eq!(session.task_description(), "Test task");
-        assert_eq!(session.step_count(), 1); // Initial flush
-
-        // Verify STAGE exists
-        assert!(repo.refs().read_stage().unwrap().is_some());
-
-        // Compact session
-        let commit_id = repo.compact_session("Completed test").unwrap();
-
-        // Verify commit exists
-        let commit: crate::types::Commit = repo.object_store().get_typed(commit_id).unwrap();
-        assert_eq!(commit.message, "Completed test");
-
-        // Verify STAGE is gone
-        assert!(repo.refs().read_stage().unwrap().is_none());
+        for (task, path) in [
+            ("Fix lexer", "src/lexer.rs"),
+            ("Fix lexer", "src/token.rs"),
+            ("Docs", "README.md"),
+        ] {
+            repo.start_session(task).unwrap();
+            repo.observe_file_write(path, b"content").unwrap();
+            repo.flush_active_session().unwrap();
+            repo.compact_session(&format!("Update {}", path)).unwrap();
+        }
+        repo.start_session("Dead end").unwrap();
+        repo.observe_file_write("src/old.rs", b"gone").unwrap();
+        repo.flush_active_session().unwrap();
+        repo.abort_session("Dead end").unwrap();
+
+        let at = repo.head().unwrap().timestamp_unix;
+        let narrative = repo.narrative();
+        let task = narrative.create_task("Ship lexer", "").unwrap();
+        narrative.update_task(task.id, "done", "").unwrap();
+        let (date, time) = crate::narrative::log_date_time(at);
+        narrative
+            .append_log(&date, &time, "Lexer handles raw strings\nDetails")
+            .unwrap();
+        repo.commit("Close lexer task", None, "user").unwrap();
 
-        // Verify no active session
-        assert!(!repo.has_active_session());
+        let digest = repo.write_weekly_digest(at).unwrap();
+        assert_eq!(digest.sessions.len(), 3);
+        assert_eq!(
+            digest.completed_tasks,
+            [("tasks/task_0001.md".to_string(), "Ship lexer".to_string())]
+        );
+        assert_eq!(digest.log_entries[0].1, "Lexer handles raw strings");
+
+        let content =
+            String::from_utf8(repo.narrative().read_file(&digest.relative_path()).unwrap())
+                .unwrap();
+        assert!(content.contains("### Fix lexer (2 sessions)"));
+        assert!(!content.contains("Dead end"));
+        assert!(repo.head().unwrap().message.starts_with("Digest: Week of "));
+
+        let node = crate::document::document_node(&digest.relative_path());
+        let index = repo.index().unwrap();
+        let sessions = index.get_edges_from(&node, EdgeLabel::DerivedFrom).unwrap();
+        assert_eq!(sessions.len(), 3);
+        assert!(sessions.iter().all(|n| n.kind == NodeKind::Session));
+        let files = index.get_edges_from(&node, EdgeLabel::Mentions).unwrap();
+        assert!(files.iter().any(|n| n.id == "src/lexer.rs"));
+        assert!(!files.iter().any(|n| n.id == "src/old.rs"));
     }
 
     #[test]
-    fn test_session_crash_recovery() {
-        use crate::types::SessionState;
+    fn test_build_pack_cached_keys_on_head() {
+        use crate::cache::PackCache;
+        use crate::config::CacheConfig;
+        use crate::pack::RetrievalConfig;
 
         let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let cache_dir = repo.ctx_dir().join("cache/packs");
+        let cache = PackCache::new(&cache_dir, CacheConfig::default());
+        let entries = || std::fs::read_dir(&cache_dir).unwrap().count();
 
-        // Start session and do some work
-        {
-            let mut repo = CtxRepo::init(tmp.path()).unwrap();
-            repo.start_session("Recovery test").unwrap();
-
-            // Add some observations using the convenience methods
-            repo.observe_note("Before crash").unwrap();
-            repo.observe_file_write("test.rs", b"fn main() {}").unwrap();
+        repo.start_session("Write lib").unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn answer() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Write lib").unwrap();
 
-            // Flush the step
-            repo.flush_active_session().unwrap();
+        let config = RetrievalConfig {
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let first = repo
+            .build_pack_cached("src/lib.rs", &config, &cache)
+            .unwrap();
+        let second = repo
+            .build_pack_cached("src/lib.rs", &config, &cache)
+            .unwrap();
+        assert_eq!(first.to_json().unwrap(), second.to_json().unwrap());
+        assert_eq!(entries(), 1);
 
-            // Verify session has 2 steps (initial + this one)
-            assert_eq!(repo.active_session().unwrap().step_count(), 2);
+        let narrower = RetrievalConfig {
+            expansion_depth: 1,
+            ..config.clone()
+        };
+        repo.build_pack_cached("src/lib.rs", &narrower, &cache)
+            .unwrap();
+        assert_eq!(entries(), 2);
 
-            // Simulate crash - drop repo without compacting
-        }
+        repo.commit("Move HEAD", None, "user").unwrap();
+        let third = repo
+            .build_pack_cached("src/lib.rs", &config, &cache)
+            .unwrap();
+        assert_ne!(third.head_commit, first.head_commit);
+        assert_eq!(entries(), 3);
+    }
 
-        // Reopen and recover
-        {
-            let mut repo = CtxRepo::open(tmp.path()).unwrap();
+    #[test]
+    fn test_analysis_roots_and_their_ignore_rules() {
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+        let write = |path: &str, content: &str| {
+            let path = tmp.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("rust/src/lib.rs", "");
+        write("rust/gen/out.rs", "");
+        write("rust/target/debug/build.rs", "");
+        write("rust/.ctxignore", "gen/\n");
+        write("rust/tools/xtask/src/main.rs", "");
+        write("scripts/build.rs", "");
+
+        let config_path = tmp.path().join(".ctx/config.toml");
+        let mut config = fs::read_to_string(&config_path).unwrap();
+        config.push_str("\n[analysis]\nroots = [\"rust\", \"rust/tools/xtask/\"]\n");
+        fs::write(&config_path, config).unwrap();
 
-            // Should have no active session initially
-            assert!(!repo.has_active_session());
+        let repo = CtxRepo::open(tmp.path()).unwrap();
+        let roots = repo.analysis_roots().unwrap();
+        assert_eq!(
+            roots,
+            vec![
+                repo.root().join("rust"),
+                repo.root().join("rust/tools/xtask")
+            ]
+        );
+        let relative = |files: Vec<PathBuf>| -> Vec<String> {
+            let mut files: Vec<String> = files
+                .iter()
+                .map(|f| {
+                    f.strip_prefix(repo.root())
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect();
+            files.sort();
+            files
+        };
 
-            // But STAGE should exist
-            assert!(repo.refs().read_stage().unwrap().is_some());
+        // Nested roots are analyzed on their own, ignored paths not at all
+        assert_eq!(
+            relative(repo.find_rust_files(&roots[0], &roots[1..]).unwrap()),
+            vec!["rust/src/lib.rs"]
+        );
+        assert_eq!(
+            relative(repo.find_rust_files(&roots[1], &roots[..1]).unwrap()),
+            vec!["rust/tools/xtask/src/main.rs"]
+        );
+        assert_eq!(
+            repo.analysis_root_for(&tmp.path().join("rust/tools/xtask/src/main.rs"))
+                .unwrap(),
+            roots[1]
+        );
 
-            // Recover the session
-            repo.recover_session().unwrap();
-            assert!(repo.has_active_session());
+        let mut config = fs::read_to_string(&config_path).unwrap();
+        config = config.replace("\"rust\", ", "\"missing\", ");
+        fs::write(&config_path, config).unwrap();
+        let repo = CtxRepo::open(tmp.path()).unwrap();
+        assert!(matches!(
+            repo.analysis_roots(),
+            Err(CtxError::InvalidArgument(_))
+        ));
+    }
 
-            // Verify recovered state
-            {
-                let session = repo.active_session().unwrap();
-                assert_eq!(session.task_description(), "Recovery test");
-                assert_eq!(session.step_count(), 2);
-                assert!(matches!(session.state(), SessionState::Running));
-            }
+    #[test]
+    fn test_exec_policy_from_config_blocks_cargo() {
+        let tmp = TempDir::new().unwrap();
+        CtxRepo::init(tmp.path()).unwrap();
+        std::fs::write(tmp.path().join("Cargo.toml"), "[package]\nname = \"x\"\n").unwrap();
 
-            // Can continue working
-            {
-                let session = repo.active_session_mut().unwrap();
-                session.observe_note("After recovery").unwrap();
-            }
-            repo.flush_active_session().unwrap();
-            assert_eq!(repo.active_session().unwrap().step_count(), 3);
+        let config_path = tmp.path().join(".ctx/config.toml");
+        let mut config = std::fs::read_to_string(&config_path).unwrap();
+        config.push_str("\n[exec]\nmode = \"deny\"\nallow = [\"rust-analyzer\"]\n");
+        std::fs::write(&config_path, config).unwrap();
 
-            // Compact successfully
-            repo.compact_session("Recovered and completed").unwrap();
-        }
+        let mut repo = CtxRepo::open(tmp.path()).unwrap();
+        assert_eq!(repo.exec_policy().config().mode, crate::ExecMode::Deny);
+        let err = repo.analyze_cargo().unwrap_err();
+        assert!(matches!(err, CtxError::CommandDenied { .. }), "{err}");
 
-        // Verify commit was created
-        {
-            let repo = CtxRepo::open(tmp.path()).unwrap();
-            let commit = repo.head().unwrap();
-            assert_eq!(commit.message, "Recovered and completed");
-        }
+        let audit = std::fs::read_to_string(tmp.path().join(".ctx/logs/exec.jsonl")).unwrap();
+        assert!(audit.contains("cargo metadata"));
+        assert!(audit.contains("\"denied\""));
     }
 
     #[test]
-    fn test_concurrent_session_prevention() {
+    fn test_storage_report_categorizes_objects() {
+        use crate::du::ObjectCategory;
+
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        for version in [b'a', b'b'] {
+            repo.start_session("Edit a").unwrap();
+            repo.observe_file_write("src/a.rs", &[version; 4000])
+                .unwrap();
+            repo.flush_active_session().unwrap();
+            repo.compact_session("Edit a").unwrap();
+        }
+        // In flight in the staging chain
+        repo.start_session("Add b").unwrap();
+        repo.observe_file_write("src/b.rs", b"pub fn b() {}")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        let orphan = repo.object_store.put_blob(&[7; 9000]).unwrap();
 
-        // Start first session
-        repo.start_session("First task").unwrap();
-
-        // Try to start second session - should fail
-        let result = repo.start_session("Second task");
-        assert!(result.is_err());
-        assert!(matches!(
-            result.unwrap_err(),
-            CtxError::SessionAlreadyActive(_)
-        ));
-
-        // Verify first session is still active
-        assert!(repo.has_active_session());
+        let report = repo.storage_report(10).unwrap();
+        assert!(report.errors.is_empty());
         assert_eq!(
-            repo.active_session().unwrap().task_description(),
-            "First task"
+            report.total.objects,
+            repo.object_store.list_all_objects().unwrap().len()
+        );
+        assert_eq!(
+            report.total.objects,
+            report.referenced.objects + report.unreachable.objects
         );
+        let category = |category| {
+            report
+                .categories
+                .iter()
+                .find(|usage| usage.category == category)
+                .unwrap()
+        };
+        assert!(category(ObjectCategory::Commit).referenced.objects >= 3);
+        assert!(category(ObjectCategory::WorkCommit).referenced.objects >= 2);
+        assert!(category(ObjectCategory::Tree).referenced.objects >= 1);
+        assert_eq!(category(ObjectCategory::Blob).unreachable.objects, 1);
+
+        let orphan_usage = report
+            .largest_blobs
+            .iter()
+            .find(|blob| blob.id == orphan)
+            .unwrap();
+        assert!(!orphan_usage.referenced);
+        assert_eq!(orphan_usage.uncompressed_bytes, 9000);
+        assert!(report
+            .largest_blobs
+            .iter()
+            .any(|blob| blob.path.as_deref() == Some("src/a.rs")));
+
+        let a = &report.largest_paths[0];
+        assert_eq!(a.path, "src/a.rs");
+        assert_eq!(a.versions, 2);
+        assert_eq!(a.uncompressed_bytes, 8000);
+        assert!(report
+            .largest_paths
+            .iter()
+            .any(|usage| usage.path == "src/b.rs"));
     }
 
     #[test]
-    fn test_file_locking_prevents_concurrent_access() {
+    fn test_index_shared_between_processes() {
         let tmp = TempDir::new().unwrap();
-        let mut repo1 = CtxRepo::init(tmp.path()).unwrap();
-
-        // Start session in first repo (acquires lock)
-        repo1.start_session("Task in repo1").unwrap();
-
-        // Try to open second repo instance and start session
-        let mut repo2 = CtxRepo::open(tmp.path()).unwrap();
+        let mut writer = CtxRepo::init(tmp.path()).unwrap();
+        let blob = writer.object_store.put_blob(b"fn main() {}").unwrap();
+        writer
+            .index_mut()
+            .unwrap()
+            .index_file_path("src/main.rs", blob)
+            .unwrap();
 
-        // This should fail due to lock
-        // With PID-based locking, we get SessionLockHeld when we can detect
-        // the holding process, or RepositoryLocked if we can't
-        let result = repo2.start_session("Task in repo2");
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(
-            matches!(
-                err,
-                CtxError::RepositoryLocked | CtxError::SessionLockHeld { .. }
-            ),
-            "Expected RepositoryLocked or SessionLockHeld, got: {:?}",
-            err
-        );
+        // A second handle stands in for another process
+        let mut reader = CtxRepo::open(tmp.path()).unwrap();
+        {
+            let index = reader.index().unwrap();
+            assert!(index.is_snapshot());
+            assert_eq!(index.lookup_path("src/main.rs").unwrap(), Some(blob));
+        }
+        let err = reader.index_mut().err().unwrap();
+        assert!(matches!(err, CtxError::IndexLocked { .. }));
+        assert!(err.recovery_suggestion().is_some());
+
+        // Once the writer is gone the reader can write
+        drop(writer);
+        assert!(!reader.index_mut().unwrap().is_snapshot());
     }
 
     #[test]
-    fn test_tree_preserved_in_compaction() {
+    fn test_status_reports_session_and_index_freshness() {
+        use crate::status::IndexFreshness;
+
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
 
-        // Start session
-        repo.start_session("Build tree test").unwrap();
-
-        // Write multiple files with nested directory structure
-        let file1_id = repo
-            .observe_file_write("src/main.rs", b"fn main() { println!(\"Hello\"); }")
-            .unwrap();
-
-        let file2_id = repo
-            .observe_file_write("src/lib.rs", b"pub fn hello() {}")
+        let status = repo.status().unwrap();
+        assert_eq!(status.head.id, repo.head_id().unwrap());
+        assert!(status.session.is_none());
+        assert!(status.objects.total > 0);
+        assert_eq!(status.objects.collectable, 0);
+        // Reports serialize IDs as hex for JSON output
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["head"]["id"], status.head.id.as_hex());
+        assert_eq!(json["index"]["state"], "missing");
+
+        repo.start_session("Add lib").unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn a() {}")
             .unwrap();
+        repo.flush_active_session().unwrap();
+        let session = repo.status().unwrap().session.unwrap();
+        assert_eq!(session.task, "Add lib");
+        assert_eq!(session.staging_chain_len, 2);
+        assert!(matches!(
+            session.staleness,
+            StaleSessionStatus::Fresh { .. }
+        ));
 
-        let file3_id = repo
-            .observe_file_write("tests/test.rs", b"#[test] fn test_hello() {}")
-            .unwrap();
+        repo.compact_session("Add lib").unwrap();
+        let status = repo.status().unwrap();
+        assert_eq!(status.head.message, "Add lib");
+        assert!(status.session.is_none());
+        assert_eq!(status.index, IndexFreshness::Current);
 
-        let file4_id = repo
-            .observe_file_write("README.md", b"# My Project")
-            .unwrap();
+        // Plain commits don't update the index until it is rebuilt
+        repo.commit("Notes", Some(vec![]), "user").unwrap();
+        repo.commit("More notes", Some(vec![]), "user").unwrap();
+        assert_eq!(
+            repo.status().unwrap().index,
+            IndexFreshness::Behind { commits: 2 }
+        );
+        repo.rebuild_index().unwrap();
+        assert_eq!(repo.status().unwrap().index, IndexFreshness::Current);
+    }
 
-        // Flush the observations
-        repo.flush_active_session().unwrap();
+    #[test]
+    fn test_compaction_backs_up_and_restore_recovers_head() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_time_provider(|| 1_769_083_200);
+        assert!(repo.list_backups().unwrap().is_empty());
 
-        // Compact into canonical commit
-        let commit_id = repo.compact_session("Added project files").unwrap();
+        repo.start_session("Add lib").unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn a() {}")
+            .unwrap();
+        let good = repo.compact_session("Add lib").unwrap();
+        let backups = repo.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].head, Some(good));
+
+        // A bad write loses HEAD and a narrative file
+        fs::write(repo.ctx_dir().join("HEAD"), "garbage").unwrap();
+        fs::remove_file(repo.ctx_dir().join("narrative/README.md")).unwrap();
+        assert!(repo.head_id().is_err());
+
+        let report = repo.restore_backup(&backups[0].name).unwrap();
+        assert_eq!(report.head_before, None);
+        assert_eq!(report.head_after, good);
+        assert_eq!(repo.head_id().unwrap(), good);
+        assert!(repo.ctx_dir().join("narrative/README.md").exists());
+        // The broken state was kept too
+        let backups = repo.list_backups().unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].name, report.previous);
+        assert_eq!(backups[0].head, None);
 
-        // Verify tree was built correctly
-        let commit: crate::types::Commit = repo.object_store().get_typed(commit_id).unwrap();
-        let root_tree: crate::types::Tree =
-            repo.object_store().get_typed(commit.root_tree).unwrap();
+        assert!(matches!(
+            repo.restore_backup("backup-1"),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        repo.start_session("Busy").unwrap();
+        assert!(matches!(
+            repo.restore_backup(&report.backup),
+            Err(CtxError::SessionAlreadyActive(_))
+        ));
+    }
 
-        // Root should have: README.md, src/, tests/ (sorted alphabetically)
-        assert_eq!(root_tree.entries.len(), 3);
-        assert_eq!(root_tree.entries[0].name, "README.md");
-        assert_eq!(root_tree.entries[0].kind, crate::types::TreeEntryKind::Blob);
-        assert_eq!(root_tree.entries[0].id, file4_id);
+    #[test]
+    fn test_clone_full_and_shallow() {
+        use crate::verify::VerifyConfig;
 
-        assert_eq!(root_tree.entries[1].name, "src");
-        assert_eq!(root_tree.entries[1].kind, crate::types::TreeEntryKind::Tree);
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path().join("src")).unwrap();
+        let initial = repo.head_id().unwrap();
+        repo.refs.write_ref("start", initial).unwrap();
+        for (i, content) in ["pub fn a() {}", "pub fn b() {}", "pub fn c() {}"]
+            .iter()
+            .enumerate()
+        {
+            repo.start_session(&format!("Step {}", i)).unwrap();
+            repo.observe_file_write("src/lib.rs", content.as_bytes())
+                .unwrap();
+            repo.flush_active_session().unwrap();
+            repo.compact_session(&format!("Step {}", i)).unwrap();
+        }
+        let head = repo.head_id().unwrap();
+        let count = |repo: &CtxRepo| repo.log(Default::default()).unwrap().count();
+
+        let full = repo.clone_to(tmp.path().join("full"), None).unwrap();
+        assert_eq!(full.head, head);
+        assert!(full.grafts.is_empty());
+        assert_eq!(full.objects, full.linked + full.copied);
+        assert!(full.refs.contains(&"refs/start".to_string()));
+        let cloned = CtxRepo::open(tmp.path().join("full")).unwrap();
+        assert_eq!(cloned.head_id().unwrap(), head);
+        assert_eq!(count(&cloned), count(&repo));
+        assert!(cloned.ctx_dir().join("narrative/README.md").exists());
+        assert!(!cloned.verify(VerifyConfig::default()).unwrap().has_issues());
+
+        let shallow = repo.clone_to(tmp.path().join("shallow"), Some(2)).unwrap();
+        assert_eq!(shallow.grafts.len(), 1);
+        assert_eq!(shallow.skipped_refs, vec!["refs/start".to_string()]);
+        assert!(shallow.objects < full.objects);
+        let cloned = CtxRepo::open(tmp.path().join("shallow")).unwrap();
+        assert_eq!(count(&cloned), 2);
+        assert!(!cloned.verify(VerifyConfig::default()).unwrap().has_issues());
+        // History ends at the oldest kept commit
+        assert_eq!(cloned.path_history("src/lib.rs").unwrap().len(), 2);
+        assert!(cloned.resolve_commit("HEAD~2").is_err());
+
+        assert!(repo.clone_to(tmp.path().join("full"), None).is_err());
+        assert!(matches!(
+            repo.clone_to(tmp.path().join("empty"), Some(0)),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        assert!(!tmp.path().join("empty/.ctx").exists());
+    }
 
-        assert_eq!(root_tree.entries[2].name, "tests");
-        assert_eq!(root_tree.entries[2].kind, crate::types::TreeEntryKind::Tree);
+    #[test]
+    fn test_prune_history_and_undo() {
+        use crate::gc::GcConfig;
+        use crate::verify::VerifyConfig;
+        use std::sync::atomic::{AtomicI64, Ordering};
 
-        // Verify src/ subtree
-        let src_tree: crate::types::Tree = repo
-            .object_store()
-            .get_typed(root_tree.entries[1].id)
+        let tmp = TempDir::new().unwrap();
+        let clock = Arc::new(AtomicI64::new(2_000_000_000));
+        let now = clock.clone();
+        let mut repo = CtxRepo::init(tmp.path())
+            .unwrap()
+            .with_time_provider(move || now.load(Ordering::SeqCst));
+        let mut commits = Vec::new();
+        for (i, content) in ["pub fn a() {}", "pub fn b() {}", "pub fn c() {}"]
+            .iter()
+            .enumerate()
+        {
+            repo.start_session(&format!("Step {}", i)).unwrap();
+            repo.observe_file_write("src/lib.rs", content.as_bytes())
+                .unwrap();
+            repo.flush_active_session().unwrap();
+            commits.push(repo.compact_session(&format!("Step {}", i)).unwrap());
+            clock.fetch_add(100, Ordering::SeqCst);
+        }
+        let count = |repo: &CtxRepo| repo.log(Default::default()).unwrap().count();
+        assert_eq!(count(&repo), 4);
+
+        // Nothing is older than the initial commit
+        let report = repo.prune_history(0).unwrap();
+        assert_eq!((report.pruned, report.snapshot), (0, None));
+
+        let report = repo.prune_history(2_000_000_150).unwrap();
+        assert_eq!(report.kept, 1);
+        assert_eq!(report.pruned, 3);
+        assert!(report.objects > 0);
+        let snapshot: Commit = repo
+            .object_store
+            .get_typed(report.snapshot.unwrap())
             .unwrap();
-        assert_eq!(src_tree.entries.len(), 2);
+        assert!(snapshot.parents.is_empty());
+        let newest_pruned: Commit = repo.object_store.get_typed(commits[1]).unwrap();
+        assert_eq!(snapshot.root_tree, newest_pruned.root_tree);
+        assert_eq!(count(&repo), 2);
+        assert_eq!(repo.path_history("src/lib.rs").unwrap().len(), 2);
+        assert!(!repo.verify(VerifyConfig::default()).unwrap().has_issues());
+
+        // Once the reflog naming them expires, pruned commits go
+        repo.gc(GcConfig {
+            aggressive: true,
+            reflog_expire_days: 0,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!repo.object_store.exists(commits[0]));
 
-        // Should be sorted: lib.rs, main.rs
-        assert_eq!(src_tree.entries[0].name, "lib.rs");
-        assert_eq!(src_tree.entries[0].kind, crate::types::TreeEntryKind::Blob);
-        assert_eq!(src_tree.entries[0].id, file2_id);
+        let backup = report.backup.unwrap();
+        let undone = repo.undo_prune(&backup).unwrap();
+        assert!(undone.objects_restored > 0);
+        assert!(repo.object_store.exists(commits[0]));
+        assert_eq!(count(&repo), 4);
+        assert!(!repo.verify(VerifyConfig::default()).unwrap().has_issues());
 
-        assert_eq!(src_tree.entries[1].name, "main.rs");
-        assert_eq!(src_tree.entries[1].kind, crate::types::TreeEntryKind::Blob);
-        assert_eq!(src_tree.entries[1].id, file1_id);
+        assert!(matches!(
+            repo.undo_prune("backup-1"),
+            Err(CtxError::InvalidArgument(_))
+        ));
+    }
 
-        // Verify tests/ subtree
-        let tests_tree: crate::types::Tree = repo
-            .object_store()
-            .get_typed(root_tree.entries[2].id)
-            .unwrap();
-        assert_eq!(tests_tree.entries.len(), 1);
+    #[test]
+    fn test_health_rates_stage_and_abandoned_sessions() {
+        use crate::health::HealthLevel;
 
-        assert_eq!(tests_tree.entries[0].name, "test.rs");
-        assert_eq!(
-            tests_tree.entries[0].kind,
-            crate::types::TreeEntryKind::Blob
-        );
-        assert_eq!(tests_tree.entries[0].id, file3_id);
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.rebuild_index().unwrap();
 
-        // Verify content is preserved
-        let main_content = repo.object_store().get_blob(file1_id).unwrap();
-        assert_eq!(main_content, b"fn main() { println!(\"Hello\"); }");
+        let health = repo.health().unwrap();
+        assert_eq!(health.level, HealthLevel::Green);
+        assert!(health.objects.sampled > 0);
+        assert!(health.objects.corrupted.is_empty());
+        assert!(health.growth.bytes > 0);
+        assert_eq!(health.sessions.sessions, 0);
 
-        let lib_content = repo.object_store().get_blob(file2_id).unwrap();
-        assert_eq!(lib_content, b"pub fn hello() {}");
+        // A commit made under a running session orphans its STAGE
+        repo.start_session("Add lib").unwrap();
+        repo.flush_active_session().unwrap();
+        repo.commit("Notes", Some(vec![]), "user").unwrap();
+        let health = repo.health().unwrap();
+        assert_eq!(health.stage.level, HealthLevel::Yellow);
+        assert!(health.stage.problem.unwrap().contains("HEAD has moved"));
+        assert_eq!(health.index.level, HealthLevel::Yellow);
+        assert_eq!(health.level, HealthLevel::Yellow);
+
+        // One abandoned session of two is red under the default thresholds
+        repo.abort_session("wrong approach").unwrap();
+        repo.start_session("Add lib again").unwrap();
+        repo.compact_session("Add lib").unwrap();
+        let health = repo.health().unwrap();
+        assert_eq!(health.stage.level, HealthLevel::Green);
+        assert_eq!(health.sessions.sessions, 2);
+        assert_eq!(health.sessions.abandoned, 1);
+        assert_eq!(health.sessions.level, HealthLevel::Red);
+        assert_eq!(health.level, HealthLevel::Red);
+        let json = serde_json::to_value(&health).unwrap();
+        assert_eq!(json["level"], "red");
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_stale_session_detection() {
-        use std::thread;
-        use std::time::Duration;
+    fn test_pre_compact_hook_vetoes_compaction() {
+        use std::os::unix::fs::PermissionsExt;
 
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let hook = repo.ctx_dir().join("hooks").join("pre-compact");
+        fs::write(&hook, "#!/bin/sh\ngrep -q WIP && exit 1\nexit 0\n").unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        let head = repo.head_id().unwrap();
 
-        // Start session
-        repo.start_session("Stale test").unwrap();
+        repo.start_session("Add lib").unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn a() {}")
+            .unwrap();
+        let err = repo.compact_session("WIP lib").unwrap_err();
+        assert!(matches!(err, CtxError::HookRejected { .. }));
+        assert_eq!(repo.head_id().unwrap(), head);
+        assert!(repo.active_session().is_some());
 
-        // Check immediately - should be fresh
-        let config = crate::config::StaleSessionConfig {
-            ask_threshold_secs: 2,
-            auto_compact_threshold_secs: 5,
-        };
+        let commit_id = repo.compact_session("Add lib").unwrap();
+        assert_eq!(repo.head_id().unwrap(), commit_id);
+    }
 
-        let status = repo.check_stale_session(&config);
+    #[test]
+    fn test_events_journal_records_lifecycle() {
+        use crate::events::EventKind;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        assert!(repo.events_since(0).unwrap().events.is_empty());
+
+        repo.start_session("Add lib").unwrap();
+        repo.observe_file_write("src/lib.rs", b"pub fn a() {}")
+            .unwrap();
+        let staging_head = repo.flush_active_session().unwrap();
+        let page = repo.events_since(0).unwrap();
+        assert_eq!(page.events.len(), 2);
         assert!(matches!(
-            status,
-            crate::config::StaleSessionStatus::Fresh { .. }
+            &page.events[0].kind,
+            EventKind::SessionStarted { task, .. } if task == "Add lib"
+        ));
+        assert!(matches!(
+            &page.events[1].kind,
+            EventKind::SessionFlushed { staging_head: head, .. } if *head == staging_head.as_hex()
         ));
 
-        // Wait for it to become stale (ask threshold)
-        thread::sleep(Duration::from_secs(3));
+        let commit_id = repo.compact_session("Add lib").unwrap();
+        repo.gc(crate::gc::GcConfig {
+            dry_run: true,
+            ..Default::default()
+        })
+        .unwrap();
+        // Status estimates GC without journaling it
+        repo.status().unwrap();
 
-        let status = repo.check_stale_session(&config);
+        let newer = repo.events_since(page.next_cursor).unwrap();
+        let kinds: Vec<_> = newer.events.iter().map(|e| &e.kind).collect();
+        assert_eq!(kinds.len(), 2);
         assert!(matches!(
-            status,
-            crate::config::StaleSessionStatus::ShouldAsk { .. }
+            kinds[0],
+            EventKind::SessionCompacted { commit_id: id, commit_type: CommitType::Normal, .. }
+                if *id == commit_id.as_hex()
+        ));
+        assert!(matches!(
+            kinds[1],
+            EventKind::GcCompleted { dry_run: true, .. }
         ));
+        assert!(repo
+            .events_since(newer.next_cursor)
+            .unwrap()
+            .events
+            .is_empty());
     }
 
     #[test]
-    fn test_abort_session() {
+    fn test_transcript_retention() {
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        assert!(matches!(
+            repo.observe_message("user", "Hello"),
+            Err(CtxError::NoActiveSession)
+        ));
 
-        // Start session
-        repo.start_session("Aborted task").unwrap();
-
-        // Abort it
-        let commit_id = repo.abort_session("User cancelled").unwrap();
-
-        // Verify abort commit
-        let commit: crate::types::Commit = repo.object_store().get_typed(commit_id).unwrap();
-        assert!(commit.message.contains("Aborted"));
-        assert!(commit.message.contains("User cancelled"));
-        assert_eq!(
-            commit.commit_type,
-            Some(crate::types::CommitType::Abandoned)
-        );
-
-        // Verify session is gone
-        assert!(!repo.has_active_session());
-        assert!(repo.refs().read_stage().unwrap().is_none());
+        repo.start_session("Speed up the index").unwrap();
+        repo.observe_message("User", "Why is the index slow?")
+            .unwrap();
+        repo.observe_file_read("src/index.rs").unwrap();
+        repo.observe_message("assistant", "It rebuilds on every query.")
+            .unwrap();
+        assert!(repo.observe_message("tool call", "ls").is_err());
+        assert_eq!(repo.active_transcript().unwrap().unwrap().messages.len(), 2);
+        repo.flush_active_session().unwrap();
+        let commit_id = repo.compact_session("Cache the index").unwrap();
+
+        let transcript = repo.transcript(commit_id).unwrap().unwrap();
+        assert_eq!(transcript.task, "Speed up the index");
+        let turns = transcript.turns(&repo.object_store).unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].role, "user");
+        assert_eq!(turns[0].content, "Why is the index slow?");
+        assert_eq!(turns[1].role, "assistant");
+
+        // Kept messages survive garbage collection
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(repo
+            .transcript(commit_id)
+            .unwrap()
+            .unwrap()
+            .turns(&repo.object_store)
+            .is_ok());
+
+        // Session-only messages are staged but not kept
+        let config_path = repo.ctx_dir().join("config.toml");
+        let config = std::fs::read_to_string(&config_path).unwrap();
+        std::fs::write(
+            &config_path,
+            config.replace("transcripts = \"keep\"", "transcripts = \"session\""),
+        )
+        .unwrap();
+        repo.start_session("Tune the cache").unwrap();
+        repo.observe_message("user", "Make it smaller").unwrap();
+        repo.flush_active_session().unwrap();
+        assert!(repo.active_transcript().unwrap().is_some());
+        let commit_id = repo.compact_session("Tune cache").unwrap();
+        assert!(repo.transcript(commit_id).unwrap().is_none());
+
+        // Off records nothing
+        std::fs::write(
+            &config_path,
+            config.replace("transcripts = \"keep\"", "transcripts = \"off\""),
+        )
+        .unwrap();
+        repo.start_session("Tune the cache again").unwrap();
+        repo.observe_message("user", "Smaller still").unwrap();
+        assert!(repo.active_transcript().unwrap().is_none());
     }
 
     #[test]
-    fn test_edge_batches_created_on_compact() {
+    fn test_pack_citations_resolve_to_lines() {
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Add index").unwrap();
+        repo.observe_file_write("src/index.rs", b"pub struct Index;\n\nimpl Index {}\n")
+            .unwrap();
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Add index").unwrap();
 
-        // Start session
-        repo.start_session("Test edge extraction").unwrap();
+        let config = crate::pack::RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let pack = repo.build_pack("src/index.rs", &config).unwrap();
+        let chunk = &pack.retrieved[0];
+        assert_eq!(chunk.citation.object_id, chunk.object_id);
+        assert_eq!((chunk.citation.start_line, chunk.citation.end_line), (1, 3));
+        assert!(pack.to_text().contains(&format!(
+            "### [1] src/index.rs (score: 1.000, kind: FileContent, source: {})",
+            chunk.citation
+        )));
+
+        // Building the pack recorded it, and journaled the recording
+        let pack_id = repo.pack_id(&pack, &config).unwrap();
+        let recent = repo.recent_packs(1).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].pack_id, pack_id);
+        assert_eq!(recent[0].query, "src/index.rs");
+        assert_eq!(recent[0].chunks, pack.retrieved.len());
+
+        // The same pack is stored once; each recording is journaled
+        assert_eq!(repo.record_pack(&pack, &config).unwrap(), pack_id);
+        assert_eq!(repo.recent_packs(10).unwrap().len(), 2);
+
+        // It replays as it was built, and its numbers resolve later
+        assert_eq!(repo.replay_pack(pack_id).unwrap().to_text(), pack.to_text());
+        assert!(!repo.pack_record(pack_id).unwrap().config.include_log);
+
+        let cited = repo.resolve_citation(pack_id, 1).unwrap();
+        assert_eq!(cited.path.as_deref(), Some("src/index.rs"));
+        assert_eq!(cited.location(), "src/index.rs:1-3");
+        assert!(matches!(
+            repo.resolve_citation(pack_id, pack.retrieved.len() + 1),
+            Err(CtxError::InvalidArgument(_))
+        ));
+    }
 
-        // Observe file writes
-        repo.observe_file_write("src/main.rs", b"fn main() {}")
+    #[test]
+    fn test_gc_keeps_recorded_packs() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Add index").unwrap();
+        repo.observe_file_write("src/index.rs", b"pub struct Index;\n")
             .unwrap();
-        repo.observe_file_write("src/lib.rs", b"pub fn test() {}")
+        repo.flush_active_session().unwrap();
+        repo.compact_session("Add index").unwrap();
+
+        // A pack whose snippet nothing else references
+        let config = crate::pack::RetrievalConfig::default();
+        let mut pack = repo.build_pack("src/index.rs", &config).unwrap();
+        pack.retrieved[0].snippet = "pub struct Index; // trimmed\n".to_string();
+        let pack_id = repo.record_pack(&pack, &config).unwrap();
+
+        let report = repo
+            .gc(crate::gc::GcConfig {
+                grace_period_days: 0,
+                aggressive: true,
+                ..Default::default()
+            })
             .unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(repo.replay_pack(pack_id).unwrap().to_text(), pack.to_text());
+    }
 
-        // Flush observations
+    #[test]
+    fn test_feedback_adjusts_ranking() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Add index").unwrap();
+        repo.observe_file_write("src/index.rs", b"pub struct Index;\n")
+            .unwrap();
+        repo.observe_file_write("src/store.rs", b"pub struct Store;\n")
+            .unwrap();
         repo.flush_active_session().unwrap();
+        repo.compact_session("Add index").unwrap();
 
-        // Compact
-        let commit_id = repo.compact_session("Added source files").unwrap();
-
-        // Load commit
-        let commit: crate::types::Commit = repo.object_store().get_typed(commit_id).unwrap();
-
-        // Should have edge batches
-        assert_eq!(commit.edge_batches.len(), 1, "Should have 1 EdgeBatch");
-
-        // Load the edge batch
-        let edge_batch_id = commit.edge_batches[0];
-        let edge_batch: crate::types::EdgeBatch =
-            repo.object_store().get_typed(edge_batch_id).unwrap();
-
-        // Should have 2 edges (one per file)
-        assert_eq!(edge_batch.edges.len(), 2, "Should have 2 edges");
-
-        // Verify edges point to the files
-        assert_eq!(edge_batch.edges[0].from.kind, crate::types::NodeKind::File);
-        assert_eq!(edge_batch.edges[0].from.id, "src/lib.rs");
-        assert_eq!(
-            edge_batch.edges[0].label,
-            crate::types::EdgeLabel::UpdatedIn
-        );
+        let config = crate::pack::RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            frecency_boost: false,
+            ..Default::default()
+        };
+        let scores = |repo: &CtxRepo, config: &crate::pack::RetrievalConfig| {
+            let pack = repo
+                .build_pack("src/index.rs src/store.rs", config)
+                .unwrap();
+            pack.retrieved
+                .iter()
+                .map(|chunk| (chunk.title.clone(), chunk.relevance_score))
+                .collect::<Vec<_>>()
+        };
+        let pack = repo
+            .build_pack("src/index.rs src/store.rs", &config)
+            .unwrap();
+        assert_eq!(pack.retrieved.len(), 2);
+        let pack_id = repo.pack_id(&pack, &config).unwrap();
+        let useless = pack
+            .retrieved
+            .iter()
+            .position(|chunk| chunk.title == "src/index.rs")
+            .unwrap()
+            + 1;
+        let useful = 3 - useless;
 
-        assert_eq!(edge_batch.edges[1].from.kind, crate::types::NodeKind::File);
-        assert_eq!(edge_batch.edges[1].from.id, "src/main.rs");
-        assert_eq!(
-            edge_batch.edges[1].label,
-            crate::types::EdgeLabel::UpdatedIn
-        );
+        assert!(matches!(
+            repo.record_feedback(pack_id, &[useful], &[useful]),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            repo.record_feedback(pack_id, &[3], &[]),
+            Err(CtxError::InvalidArgument(_))
+        ));
+        repo.record_feedback(pack_id, &[useful], &[useless])
+            .unwrap();
+        let log = repo.feedback_log().unwrap();
+        assert_eq!(log.entries.len(), 1);
+        assert_eq!(log.scores["src/index.rs"].useless, 1);
 
-        // Verify evidence points to base commit (where session started)
-        // Note: Evidence records where the observations were made (during session),
-        // not the final compacted commit ID
-        let base_commit = commit.parents[0];
-        assert_eq!(edge_batch.edges[0].evidence.commit_id, base_commit);
-        assert_eq!(
-            edge_batch.edges[0].evidence.tool,
-            crate::types::EvidenceTool::Human
-        );
+        // The useless file now ranks below the useful one
         assert_eq!(
-            edge_batch.edges[0].evidence.confidence,
-            crate::types::Confidence::High
+            scores(&repo, &config),
+            vec![
+                ("src/store.rs".to_string(), 1000),
+                (
+                    "src/index.rs".to_string(),
+                    1000 - crate::feedback::FEEDBACK_STEP as u32
+                ),
+            ]
         );
+        let unranked = crate::pack::RetrievalConfig {
+            feedback_boost: false,
+            ..config.clone()
+        };
+        assert!(scores(&repo, &unranked)
+            .iter()
+            .all(|(_, score)| *score == 1000));
 
-        // Note: To find which commit introduced this EdgeBatch, we would query
-        // which commit's edge_batches field contains edge_batch_id.
-        // This avoids self-reference issues in content-addressed storage.
+        // Feedback and the chunks it rates survive garbage collection
+        repo.gc(crate::gc::GcConfig {
+            aggressive: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let log = repo.feedback_log().unwrap();
+        let feedback: Feedback = repo.object_store.get_typed(log.entries[0]).unwrap();
+        assert_eq!(feedback.pack_id, pack_id);
+        assert!(repo
+            .object_store
+            .get_blob(feedback.useless[0].snippet_id)
+            .is_ok());
     }
 
     #[test]
-    fn test_observe_file_read_with_content() {
+    fn test_bench_retrieval() {
         let tmp = TempDir::new().unwrap();
         let mut repo = CtxRepo::init(tmp.path()).unwrap();
-
-        // Start session
-        repo.start_session("Test file read observation").unwrap();
-
-        // Observe file read with content
-        let file_content = b"fn main() { println!(\"Hello\"); }";
-        repo.observe_file_read_with_content("src/main.rs", file_content)
+        repo.start_session("Add index").unwrap();
+        repo.observe_file_write("src/index.rs", b"pub struct Index;\n")
+            .unwrap();
+        repo.observe_file_write("src/store.rs", b"pub struct Store;\n")
             .unwrap();
-
-        // Flush to create WorkCommit
         repo.flush_active_session().unwrap();
-
-        // Compact to verify content is preserved
-        let commit_id = repo.compact_session("Read file").unwrap();
-
-        // Verify commit was created
-        let commit: crate::types::Commit = repo.object_store().get_typed(commit_id).unwrap();
-        assert!(commit.message.contains("Read file"));
-
-        // The content should be stored in the object store
-        // We can verify this by checking the staging observations
-        // (This is implicitly tested by the compaction succeeding)
-
-        // More importantly: in a real scenario, we'd walk the staging chain
-        // and find the FileRead observation with content_id
-        // For now, just verify the API works without panicking
-        assert!(repo.object_store().exists(commit.root_tree));
+        repo.compact_session("Add index").unwrap();
+
+        let queries = crate::bench::parse_expectations(
+            "[[queries]]\nquery = \"src/index.rs src/store.rs\"\nexpect = [\"src/index.rs\", \"src/store.rs\"]\n\n[[queries]]\nquery = \"src/index.rs\"\n",
+        )
+        .unwrap();
+        let a = crate::pack::RetrievalConfig {
+            include_active_task: false,
+            include_log: false,
+            ..Default::default()
+        };
+        let b = crate::pack::RetrievalConfig {
+            exclude: vec!["src/store.rs".to_string()],
+            ..a.clone()
+        };
+        let report = repo.bench_retrieval(&queries, &a, &b).unwrap();
+
+        assert_eq!(report.head_commit, repo.head_id().unwrap());
+        assert_eq!(report.queries.len(), 2);
+        let labeled = &report.queries[0];
+        assert_eq!(labeled.a.hits, Some(2));
+        assert_eq!(labeled.b.hits, Some(1));
+        assert_eq!(labeled.b.hit_rate, Some(500));
+        assert_eq!(labeled.overlap, 500);
+        assert!(labeled.a.tokens > labeled.b.tokens);
+        assert_eq!(report.queries[1].a.hit_rate, None);
+        assert_eq!(report.queries[1].overlap, 1000);
+        assert_eq!(report.mean_overlap, 750);
+        assert_eq!(report.a.hit_rate, Some(1000));
+        assert_eq!(report.b.hit_rate, Some(500));
+        assert!(!report.config_a.frecency_boost);
+
+        // Benchmarking doesn't record file use
+        let frecency = |repo: &CtxRepo| repo.index().unwrap().frecency_entries().unwrap();
+        let before = frecency(&repo);
+        repo.bench_retrieval(&queries, &a, &b).unwrap();
+        assert_eq!(frecency(&repo), before);
     }
 }
@@ -1,7 +1,11 @@
 //! Session lifecycle management for staging work.
 
+use crate::config::FlushPolicy;
 use crate::error::{CtxError, Result};
-use crate::types::{Observation, SessionState, StepKind, WorkCommit};
+use crate::types::{
+    AgentIdentity, Metadata, Observation, SessionState, StepKind, WorkCommit, ACTOR_KEY,
+    LAST_SEEN_KEY, OBSERVED_COUNT_KEY,
+};
 use crate::{ObjectId, ObjectStore, Refs};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -32,6 +36,9 @@ pub struct Session {
     /// Last activity timestamp.
     last_activity: i64,
 
+    /// When the last step was flushed (Unix timestamp).
+    last_flush: i64,
+
     /// Pending observations not yet flushed.
     pending_observations: Vec<Observation>,
 
@@ -40,6 +47,21 @@ pub struct Session {
 
     /// Time provider for testing (None = use system time).
     time_provider: Option<std::sync::Arc<dyn Fn() -> i64 + Send + Sync>>,
+
+    /// Who is performing this session's work.
+    author: Option<AgentIdentity>,
+
+    /// Sub-agent new observations are attributed to.
+    actor: Option<String>,
+
+    /// Whether repeated reads of the same content collapse into one.
+    dedup_reads: bool,
+
+    /// Whether repeated identical commands collapse into one.
+    dedup_commands: bool,
+
+    /// When pending observations should be flushed without being asked.
+    flush_policy: FlushPolicy,
 }
 
 impl Session {
@@ -67,9 +89,15 @@ impl Session {
             session_id,
             created_at: now,
             last_activity: now,
+            last_flush: now,
             pending_observations: Vec::new(),
             step_count: 0,
             time_provider,
+            author: None,
+            actor: None,
+            dedup_reads: false,
+            dedup_commands: false,
+            flush_policy: FlushPolicy::default(),
         }
     }
 
@@ -106,12 +134,6 @@ impl Session {
             step_count += 1;
         }
 
-        let last_activity = if let Some(ref provider) = time_provider {
-            provider()
-        } else {
-            current_timestamp()
-        };
-
         Ok(Self {
             task_description: head_work.task_description.clone(),
             state: head_work.session_state,
@@ -119,20 +141,24 @@ impl Session {
             staging_head,
             session_id: head_work.session_id,
             created_at: head_work.created_at as i64,
-            last_activity,
+            // Idle time runs from the last flushed step, so staleness
+            // survives the process that recorded it
+            last_activity: head_work.created_at as i64,
+            last_flush: head_work.created_at as i64,
             pending_observations: Vec::new(),
             step_count,
             time_provider,
+            author: head_work.author,
+            actor: None,
+            dedup_reads: false,
+            dedup_commands: false,
+            flush_policy: FlushPolicy::default(),
         })
     }
 
     /// Records that the agent read a file (path only, no content).
     pub fn observe_file_read(&mut self, path: &str) -> Result<()> {
-        self.update_last_activity();
-        self.pending_observations.push(Observation::FileRead {
-            path: path.to_string(),
-            content_id: None,
-        });
+        self.record_file_read(path, None);
         Ok(())
     }
 
@@ -143,13 +169,26 @@ impl Session {
         content: &[u8],
         object_store: &ObjectStore,
     ) -> Result<()> {
-        self.update_last_activity();
         let content_id = object_store.put_blob(content)?;
-        self.pending_observations.push(Observation::FileRead {
+        self.record_file_read(path, Some(content_id));
+        Ok(())
+    }
+
+    /// Records that the agent read a file whose content, if any, is already
+    /// stored under `content_id`.
+    pub(crate) fn record_file_read(&mut self, path: &str, content_id: Option<ObjectId>) {
+        self.update_last_activity();
+        if self.dedup_reads && self.collapse_repeat(|observation| {
+            matches!(observation, Observation::FileRead { path: seen, content_id: seen_content, .. }
+                    if seen == path && *seen_content == content_id)
+        }) {
+            return;
+        }
+        self.push(Observation::FileRead {
             path: path.to_string(),
-            content_id: Some(content_id),
+            content_id,
+            metadata: Metadata::new(),
         });
-        Ok(())
     }
 
     /// Records that the agent wrote a file, storing its content in the object store.
@@ -159,13 +198,20 @@ impl Session {
         content: &[u8],
         object_store: &ObjectStore,
     ) -> Result<ObjectId> {
-        self.update_last_activity();
         let content_id = object_store.put_blob(content)?;
-        self.pending_observations.push(Observation::FileWrite {
+        self.record_file_write(path, content_id);
+        Ok(content_id)
+    }
+
+    /// Records that the agent wrote a file whose content is already stored
+    /// under `content_id`.
+    pub(crate) fn record_file_write(&mut self, path: &str, content_id: ObjectId) {
+        self.update_last_activity();
+        self.push(Observation::FileWrite {
             path: path.to_string(),
             content_id,
+            metadata: Metadata::new(),
         });
-        Ok(content_id)
     }
 
     /// Record that a command was executed.
@@ -183,10 +229,19 @@ impl Session {
             None
         };
 
-        self.pending_observations.push(Observation::Command {
+        if self.dedup_commands
+            && self.collapse_repeat(|observation| {
+                matches!(observation, Observation::Command { command: seen, exit_code: seen_exit, output_id: seen_output, .. }
+                    if seen == command && *seen_exit == exit_code && *seen_output == output_id)
+            })
+        {
+            return Ok(());
+        }
+        self.push(Observation::Command {
             command: command.to_string(),
             exit_code,
             output_id,
+            metadata: Metadata::new(),
         });
         Ok(())
     }
@@ -194,17 +249,86 @@ impl Session {
     /// Record an agent note.
     pub fn observe_note(&mut self, note: &str) -> Result<()> {
         self.update_last_activity();
-        self.pending_observations.push(Observation::Note {
+        self.push(Observation::Note {
             content: note.to_string(),
+            metadata: Metadata::new(),
         });
         Ok(())
     }
 
+    /// Record an agent note tagged with `tags`.
+    ///
+    /// The tags apply to every file read or written in the same step.
+    pub fn observe_note_with_tags(&mut self, note: &str, tags: Metadata) -> Result<()> {
+        self.update_last_activity();
+        self.push(Observation::Note {
+            content: note.to_string(),
+            metadata: tags,
+        });
+        Ok(())
+    }
+
+    /// Adds `metadata` to the most recent unflushed observation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if nothing has been observed since the last
+    /// flush.
+    pub fn tag_last_observation(&mut self, metadata: Metadata) -> Result<()> {
+        let observation = self.pending_observations.last_mut().ok_or_else(|| {
+            CtxError::InvalidArgument("no unflushed observation to tag".to_string())
+        })?;
+        observation.metadata_mut().extend(metadata);
+        Ok(())
+    }
+
     /// Record an agent plan.
     pub fn observe_plan(&mut self, plan: &str) -> Result<()> {
         self.update_last_activity();
-        self.pending_observations.push(Observation::Plan {
+        self.push(Observation::Plan {
             content: plan.to_string(),
+            metadata: Metadata::new(),
+        });
+        Ok(())
+    }
+
+    /// Record a stored decision.
+    pub fn observe_decision(&mut self, decision_id: ObjectId) -> Result<()> {
+        self.update_last_activity();
+        self.push(Observation::Decision {
+            decision_id,
+            metadata: Metadata::new(),
+        });
+        Ok(())
+    }
+
+    /// Record a stored fact.
+    pub fn observe_fact(&mut self, fact_id: ObjectId) -> Result<()> {
+        self.update_last_activity();
+        self.push(Observation::Fact {
+            fact_id,
+            metadata: Metadata::new(),
+        });
+        Ok(())
+    }
+
+    /// Record a stored question and answer.
+    pub fn observe_qa(&mut self, qa_id: ObjectId) -> Result<()> {
+        self.update_last_activity();
+        self.push(Observation::Qa {
+            qa_id,
+            metadata: Metadata::new(),
+        });
+        Ok(())
+    }
+
+    /// Record a conversation turn whose text is stored in `content_id`.
+    pub fn observe_message(&mut self, role: &str, content_id: ObjectId) -> Result<()> {
+        self.update_last_activity();
+        self.push(Observation::Message {
+            role: role.to_string(),
+            content_id,
+            metadata: Metadata::new(),
         });
         Ok(())
     }
@@ -233,6 +357,7 @@ impl Session {
             narrative_refs: vec![],
             session_state: self.state.clone(),
             task_description: self.task_description.clone(),
+            author: self.author.clone(),
         };
 
         // Store WorkCommit
@@ -244,6 +369,7 @@ impl Session {
         // Update session state
         self.staging_head = work_id;
         self.step_count += 1;
+        self.last_flush = work_commit.created_at as i64;
         self.pending_observations.clear();
 
         Ok(work_id)
@@ -300,16 +426,91 @@ impl Session {
         self.staging_head
     }
 
+    /// Points the session at a rewritten staging chain with the same steps.
+    pub(crate) fn set_staging_head(&mut self, staging_head: ObjectId) {
+        self.staging_head = staging_head;
+    }
+
     /// Returns base commit.
     pub fn base_commit(&self) -> ObjectId {
         self.base_commit
     }
 
+    /// Returns who is performing this session's work.
+    pub fn author(&self) -> Option<&AgentIdentity> {
+        self.author.as_ref()
+    }
+
+    /// Sets who is performing this session's work.
+    ///
+    /// Recorded on every subsequent WorkCommit and on the compacted commit.
+    pub(crate) fn set_author(&mut self, author: Option<AgentIdentity>) {
+        self.author = author;
+    }
+
     /// Returns step count.
     pub fn step_count(&self) -> u32 {
         self.step_count
     }
 
+    /// Returns the sub-agent new observations are attributed to.
+    pub fn actor(&self) -> Option<&str> {
+        self.actor.as_deref()
+    }
+
+    /// Attributes new observations to the sub-agent `actor`, or to the
+    /// session as a whole with None.
+    pub(crate) fn set_actor(&mut self, actor: Option<String>) {
+        self.actor = actor;
+    }
+
+    /// Sets whether repeated reads of the same content and repeated
+    /// identical commands collapse into the observation already pending.
+    ///
+    /// A collapsed observation moves to the end of the step and counts its
+    /// repeats under [`OBSERVED_COUNT_KEY`], with the time of the latest
+    /// under [`LAST_SEEN_KEY`]. Only unflushed observations collapse: a
+    /// repeat after a flush is recorded again in the next step.
+    pub(crate) fn set_dedup(&mut self, reads: bool, commands: bool) {
+        self.dedup_reads = reads;
+        self.dedup_commands = commands;
+    }
+
+    /// Sets when pending observations should be flushed without being
+    /// asked; see [`flush_due`](Self::flush_due).
+    pub(crate) fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flush_policy = policy;
+    }
+
+    /// Whether the flush policy calls for flushing the pending
+    /// observations now: there are enough of them, the last one is of a
+    /// kind that ends a step, or the interval has passed since the last
+    /// step. The interval is only checked here, so it's the next
+    /// observation after it passes that triggers the flush.
+    pub fn flush_due(&self) -> bool {
+        let Some(last) = self.pending_observations.last() else {
+            return false;
+        };
+        let policy = &self.flush_policy;
+        policy
+            .observations
+            .is_some_and(|limit| self.pending_observations.len() >= limit)
+            || policy.after.contains(&last.kind())
+            || policy
+                .interval_secs
+                .is_some_and(|secs| self.now() - self.last_flush >= secs as i64)
+    }
+
+    /// Whether any observations are waiting to be flushed.
+    pub fn has_pending_observations(&self) -> bool {
+        !self.pending_observations.is_empty()
+    }
+
+    /// Observations not yet flushed to a step.
+    pub(crate) fn pending_observations(&self) -> &[Observation] {
+        &self.pending_observations
+    }
+
     /// Generates a progress summary from the staging chain.
     pub fn generate_progress_summary(&self, object_store: &ObjectStore) -> Result<String> {
         let mut summary = format!("Task: {}\n", self.task_description);
@@ -364,6 +565,38 @@ impl Session {
         }
     }
 
+    /// Adds `observation` to the pending ones, attributed to the current
+    /// actor.
+    fn push(&mut self, mut observation: Observation) {
+        if let Some(actor) = &self.actor {
+            observation
+                .metadata_mut()
+                .insert(ACTOR_KEY.to_string(), actor.clone());
+        }
+        self.pending_observations.push(observation);
+    }
+
+    /// Folds a repeat into the latest pending observation by the current
+    /// actor that `matches` accepts, returning false if there is none.
+    fn collapse_repeat(&mut self, matches: impl Fn(&Observation) -> bool) -> bool {
+        let actor = self.actor.as_deref();
+        let Some(index) = self
+            .pending_observations
+            .iter()
+            .rposition(|observation| observation.actor() == actor && matches(observation))
+        else {
+            return false;
+        };
+        let now = self.now();
+        let mut observation = self.pending_observations.remove(index);
+        let count = observation.count() + 1;
+        let metadata = observation.metadata_mut();
+        metadata.insert(OBSERVED_COUNT_KEY.to_string(), count.to_string());
+        metadata.insert(LAST_SEEN_KEY.to_string(), now.to_string());
+        self.pending_observations.push(observation);
+        true
+    }
+
     fn encode_observations(&self) -> Result<Vec<u8>> {
         postcard::to_allocvec(&self.pending_observations)
             .map_err(|e| CtxError::Serialization(format!("Failed to encode observations: {}", e)))
@@ -398,29 +631,7 @@ impl Session {
     }
 
     fn is_valid_transition(&self, new_state: &SessionState) -> bool {
-        use SessionState::*;
-
-        match (&self.state, new_state) {
-            // From Running
-            (Running, AwaitingUser { .. })
-            | (Running, Interrupted { .. })
-            | (Running, PendingComplete { .. })
-            | (Running, Aborted { .. }) => true,
-
-            // From AwaitingUser
-            (AwaitingUser { .. }, Running) | (AwaitingUser { .. }, Aborted { .. }) => true,
-
-            // From Interrupted
-            (Interrupted { .. }, Running) => true,
-
-            // From PendingComplete
-            (PendingComplete { .. }, Complete)
-            | (PendingComplete { .. }, Running)
-            | (PendingComplete { .. }, Aborted { .. }) => true,
-
-            // All other transitions are invalid
-            _ => false,
-        }
+        self.state.can_transition_to(new_state)
     }
 }
 
@@ -443,8 +654,14 @@ impl std::fmt::Debug for Session {
             .field("session_id", &self.session_id)
             .field("created_at", &self.created_at)
             .field("last_activity", &self.last_activity)
+            .field("last_flush", &self.last_flush)
             .field("pending_observations", &self.pending_observations)
             .field("step_count", &self.step_count)
+            .field("author", &self.author)
+            .field("actor", &self.actor)
+            .field("dedup_reads", &self.dedup_reads)
+            .field("dedup_commands", &self.dedup_commands)
+            .field("flush_policy", &self.flush_policy)
             .finish()
     }
 }
@@ -489,6 +706,7 @@ fn summarize_observations(observations: &[Observation]) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::ObservationKind;
     use tempfile::TempDir;
 
     #[test]
@@ -520,6 +738,116 @@ mod tests {
         assert_eq!(session.pending_observations.len(), 2);
     }
 
+    #[test]
+    fn test_repeated_observations_collapse() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let now = std::sync::Arc::new(std::sync::atomic::AtomicI64::new(100));
+        let clock = now.clone();
+        let mut session = Session::new(
+            "Test".to_string(),
+            ObjectId::from_bytes([0; 32]),
+            "s1".to_string(),
+            Some(std::sync::Arc::new(move || {
+                clock.load(std::sync::atomic::Ordering::SeqCst)
+            })),
+        );
+        session.set_dedup(true, true);
+
+        session
+            .observe_file_read_with_content("a.rs", b"one", &store)
+            .unwrap();
+        session
+            .observe_command("cargo test", Some(0), Some(b"ok"), &store)
+            .unwrap();
+        now.store(200, std::sync::atomic::Ordering::SeqCst);
+        session
+            .observe_file_read_with_content("a.rs", b"one", &store)
+            .unwrap();
+        session
+            .observe_file_read_with_content("a.rs", b"two", &store)
+            .unwrap();
+        session
+            .observe_command("cargo test", Some(1), Some(b"ok"), &store)
+            .unwrap();
+        session
+            .observe_command("cargo test", Some(0), Some(b"ok"), &store)
+            .unwrap();
+
+        let pending = &session.pending_observations;
+        assert_eq!(pending.len(), 4);
+        // Repeats move to the end of the step with their counters
+        assert!(matches!(&pending[0], Observation::FileRead { .. }));
+        assert_eq!(pending[0].count(), 2);
+        assert_eq!(pending[0].metadata()[LAST_SEEN_KEY], "200");
+        assert_eq!(pending[1].count(), 1);
+        assert!(matches!(
+            &pending[3],
+            Observation::Command {
+                exit_code: Some(0),
+                ..
+            }
+        ));
+        assert_eq!(pending[3].count(), 2);
+        assert_eq!(pending[3].tags().count(), 0);
+
+        let mut session = Session::new(
+            "Test".to_string(),
+            ObjectId::from_bytes([0; 32]),
+            "s2".to_string(),
+            None,
+        );
+        session.observe_file_read("a.rs").unwrap();
+        session.observe_file_read("a.rs").unwrap();
+        assert_eq!(session.pending_observations.len(), 2);
+    }
+
+    #[test]
+    fn test_flush_due_follows_policy() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let refs = Refs::new(tmp.path());
+        let now = std::sync::Arc::new(std::sync::atomic::AtomicI64::new(100));
+        let clock = now.clone();
+        let mut session = Session::new(
+            "Test".to_string(),
+            ObjectId::from_bytes([0; 32]),
+            "s1".to_string(),
+            Some(std::sync::Arc::new(move || {
+                clock.load(std::sync::atomic::Ordering::SeqCst)
+            })),
+        );
+        assert!(!session.flush_due());
+        session.observe_note("never by default").unwrap();
+        assert!(!session.flush_due());
+        session.flush_step(&store, &refs).unwrap();
+
+        session.set_flush_policy(FlushPolicy {
+            interval_secs: Some(60),
+            observations: Some(3),
+            after: vec![ObservationKind::Command],
+        });
+        session.observe_note("one").unwrap();
+        session.observe_file_read("a.rs").unwrap();
+        assert!(!session.flush_due());
+        session.observe_note("three").unwrap();
+        assert!(session.flush_due());
+        session.flush_step(&store, &refs).unwrap();
+
+        session
+            .observe_command("cargo test", Some(0), None, &store)
+            .unwrap();
+        assert!(session.flush_due());
+        session.flush_step(&store, &refs).unwrap();
+
+        session.observe_note("later").unwrap();
+        assert!(!session.flush_due());
+        now.store(160, std::sync::atomic::Ordering::SeqCst);
+        assert!(session.flush_due());
+        session.flush_step(&store, &refs).unwrap();
+        assert!(!session.flush_due());
+    }
+
     #[test]
     fn test_flush_creates_work_commit() {
         let tmp = TempDir::new().unwrap();
@@ -0,0 +1,1221 @@
+//! Session state machine for embedders building agent UIs.
+//!
+//! [`SessionHandler`] decides what should happen to the active session when
+//! something occurs in the conversation (a user message, an agent question, a
+//! clock tick). It performs no I/O: every [`SessionEvent`] carries its own
+//! timestamp and the handler only returns the [`SessionAction`]s the embedder
+//! should apply to the repository, plus an optional prompt to show the user.
+//! [`apply_actions`] applies them to a [`CtxRepo`].
+//!
+//! # Invariants
+//!
+//! - The output depends only on the handler's state and the event. Feeding the
+//!   same events to two handlers with the same configuration yields the same
+//!   responses.
+//! - A rejected event returns an error and leaves the handler unchanged.
+//! - Every [`SessionAction::SetState`] emitted is a transition allowed by
+//!   [`SessionState::can_transition_to`] from the state the handler tracks.
+//! - A [`SessionAction::Compact`] always ends the tracked session; a
+//!   [`SessionAction::StartSession`] is never emitted while one is tracked.
+//! - While a [`PendingAction`] is outstanding, only [`SessionEvent::UserChose`]
+//!   and [`SessionEvent::Tick`] are accepted.
+//!
+//! # Timeouts
+//!
+//! Idle time is measured from the last event that touched the session. Using
+//! the thresholds from [`StaleSessionConfig`]:
+//!
+//! | Idle time              | On user message                    | On tick       |
+//! |------------------------|------------------------------------|---------------|
+//! | below `ask`            | classified normally                | nothing       |
+//! | `ask` to `auto_compact`| ask to continue or start fresh     | nothing       |
+//! | above `auto_compact`   | auto-compact, then handle message  | auto-compact  |
+//!
+//! # Recovery
+//!
+//! After a crash, the embedder recovers the session from STAGE (see
+//! [`CtxRepo::recover_session_with_summary`]) and sends
+//! [`SessionEvent::SessionRecovered`]. The handler asks whether to resume the
+//! session, save it as a commit, or abandon it.
+
+use crate::config::StaleSessionConfig;
+use crate::decision::Decision;
+use crate::error::{CtxError, Result};
+use crate::types::{CommitType, SessionState};
+use crate::CtxRepo;
+
+/// How the embedder classified an incoming user message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// Direct answer to the agent's question.
+    Response,
+    /// Change request for the current work.
+    Modification,
+    /// Approval of completed work.
+    Confirmation,
+    /// Request to stop the current task.
+    Abandon,
+    /// Unrelated new request.
+    NewTask,
+    /// Request for more information (no state change).
+    Clarification,
+    /// A decision or the reasoning behind one, recorded as a
+    /// [`Decision`](crate::Decision).
+    Decision,
+}
+
+/// A question the handler is waiting for the user to answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PendingAction {
+    /// The session went idle; continue it or start fresh with `user_message`?
+    StaleSessionChoice {
+        /// The message that arrived after the idle period.
+        user_message: String,
+    },
+    /// A new task arrived while work was in progress; switch to it?
+    NewTaskChoice {
+        /// The new task the user asked for.
+        new_task: String,
+    },
+    /// A session was recovered after a crash; resume, save, or abandon it?
+    RecoveredSessionChoice,
+}
+
+/// The user's answer to a [`PendingAction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserChoice {
+    /// Keep working on the current session.
+    Continue,
+    /// Save the current session and start on the new request, if there is
+    /// one.
+    StartFresh,
+    /// Abandon the current session and start on the new request, if there
+    /// is one.
+    Abandon,
+}
+
+/// Something that happened in the conversation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// The user sent a message.
+    UserMessage {
+        /// Message text.
+        message: String,
+        /// Embedder's classification of the message.
+        kind: MessageKind,
+        /// Unix timestamp of the message.
+        at: i64,
+    },
+    /// The agent asked the user a question.
+    AgentAsked {
+        /// The question.
+        question: String,
+        /// Unix timestamp of the question.
+        at: i64,
+    },
+    /// The agent believes the task is done.
+    AgentCompleted {
+        /// Summary of what was accomplished.
+        summary: String,
+        /// Unix timestamp of completion.
+        at: i64,
+    },
+    /// The user answered a [`PendingAction`].
+    UserChose {
+        /// The choice made.
+        choice: UserChoice,
+        /// Unix timestamp of the choice.
+        at: i64,
+    },
+    /// The tracked session was recovered from STAGE after a crash.
+    SessionRecovered {
+        /// Steps kept on the recovered chain.
+        steps_kept: usize,
+        /// Steps truncated or orphaned by recovery.
+        steps_lost: usize,
+        /// Unix timestamp of the recovery.
+        at: i64,
+    },
+    /// Periodic clock tick, used to enforce timeouts.
+    Tick {
+        /// Current Unix timestamp.
+        at: i64,
+    },
+}
+
+/// A side effect the embedder should apply to the repository, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionAction {
+    /// Start a new session for `task`.
+    StartSession {
+        /// Task description.
+        task: String,
+    },
+    /// Record a note in the active session.
+    ObserveNote {
+        /// Note text.
+        note: String,
+    },
+    /// Record a decision, in the active session if there is one.
+    RecordDecision(Decision),
+    /// Record the user's answer to the question the agent asked.
+    RecordAnswer {
+        /// The agent's question.
+        question: String,
+        /// The user's answer.
+        answer: String,
+    },
+    /// Transition the active session to a new state.
+    SetState(SessionState),
+    /// Compact the active session into a canonical commit.
+    Compact {
+        /// Commit message.
+        message: String,
+        /// How the commit came about.
+        commit_type: CommitType,
+    },
+}
+
+/// Result of handling one event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionResponse {
+    /// Actions to apply, in order.
+    pub actions: Vec<SessionAction>,
+    /// Message to show the user, if the handler needs an answer.
+    pub prompt: Option<String>,
+}
+
+/// The session as tracked by the handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrackedSession {
+    task: String,
+    state: SessionState,
+    last_activity: i64,
+}
+
+/// Pure state machine deciding how conversation events affect the session.
+///
+/// See the [module documentation](self) for invariants and timeout rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionHandler {
+    config: StaleSessionConfig,
+    session: Option<TrackedSession>,
+    pending: Option<PendingAction>,
+}
+
+impl SessionHandler {
+    /// Creates a handler with no active session.
+    pub fn new(config: StaleSessionConfig) -> Self {
+        Self {
+            config,
+            session: None,
+            pending: None,
+        }
+    }
+
+    /// Creates a handler tracking an existing session.
+    pub fn with_session(
+        config: StaleSessionConfig,
+        task: impl Into<String>,
+        state: SessionState,
+        last_activity: i64,
+    ) -> Self {
+        Self {
+            config,
+            session: Some(TrackedSession {
+                task: task.into(),
+                state,
+                last_activity,
+            }),
+            pending: None,
+        }
+    }
+
+    /// Creates a handler mirroring the repository's active session, if any.
+    pub fn for_repo(config: StaleSessionConfig, repo: &CtxRepo) -> Self {
+        match repo.active_session() {
+            Some(session) => Self::with_session(
+                config,
+                session.task_description(),
+                session.state().clone(),
+                session.last_activity(),
+            ),
+            None => Self::new(config),
+        }
+    }
+
+    /// Returns the tracked session state, or None if no session is active.
+    pub fn state(&self) -> Option<&SessionState> {
+        self.session.as_ref().map(|s| &s.state)
+    }
+
+    /// Returns the tracked task description.
+    pub fn task(&self) -> Option<&str> {
+        self.session.as_ref().map(|s| s.task.as_str())
+    }
+
+    /// Returns the question awaiting the user's choice, if any.
+    pub fn pending(&self) -> Option<&PendingAction> {
+        self.pending.as_ref()
+    }
+
+    /// Handles one event and returns the actions to apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CtxError::NoActiveSession`] for agent events without a
+    /// session, and [`CtxError::InvalidStateTransition`] for events the
+    /// current state doesn't accept. The handler is unchanged on error.
+    pub fn handle(&mut self, event: SessionEvent) -> Result<SessionResponse> {
+        let mut next = self.clone();
+        let mut response = SessionResponse::default();
+        next.step(event, &mut response)?;
+        *self = next;
+        Ok(response)
+    }
+
+    fn step(&mut self, event: SessionEvent, out: &mut SessionResponse) -> Result<()> {
+        if let Some(pending) = self.pending.clone() {
+            return match event {
+                SessionEvent::UserChose { choice, at } => {
+                    self.pending = None;
+                    self.resolve(pending, choice, at, out);
+                    Ok(())
+                }
+                SessionEvent::Tick { at } => {
+                    self.tick(at, out);
+                    Ok(())
+                }
+                other => Err(CtxError::InvalidStateTransition {
+                    from: format!("{:?}", pending),
+                    to: event_name(&other).to_string(),
+                }),
+            };
+        }
+
+        match event {
+            SessionEvent::UserMessage { message, kind, at } => {
+                self.user_message(message, kind, at, out);
+                Ok(())
+            }
+            SessionEvent::AgentAsked { question, at } => self.transition(
+                SessionState::AwaitingUser {
+                    question,
+                    asked_at: at,
+                },
+                at,
+                out,
+            ),
+            SessionEvent::AgentCompleted { summary, at } => {
+                self.transition(SessionState::PendingComplete { summary }, at, out)
+            }
+            SessionEvent::SessionRecovered {
+                steps_kept,
+                steps_lost,
+                ..
+            } => {
+                let session = self.session.as_ref().ok_or(CtxError::NoActiveSession)?;
+                let lost = if steps_lost > 0 {
+                    format!(", {} lost", steps_lost)
+                } else {
+                    String::new()
+                };
+                out.prompt = Some(format!(
+                    "Recovered an interrupted session: {}\n\
+                     ({} steps kept{})\n\n\
+                     Would you like to:\n\
+                     A) Resume it\n\
+                     B) Save it as a commit\n\
+                     C) Abandon it",
+                    session.task, steps_kept, lost
+                ));
+                self.pending = Some(PendingAction::RecoveredSessionChoice);
+                Ok(())
+            }
+            SessionEvent::UserChose { .. } => Err(CtxError::InvalidStateTransition {
+                from: "no pending choice".to_string(),
+                to: "UserChose".to_string(),
+            }),
+            SessionEvent::Tick { at } => {
+                self.tick(at, out);
+                Ok(())
+            }
+        }
+    }
+
+    fn user_message(
+        &mut self,
+        message: String,
+        kind: MessageKind,
+        at: i64,
+        out: &mut SessionResponse,
+    ) {
+        let session = match &self.session {
+            Some(session) => session.clone(),
+            None => {
+                match kind {
+                    MessageKind::Clarification | MessageKind::Abandon => {}
+                    MessageKind::Decision => out
+                        .actions
+                        .push(SessionAction::RecordDecision(decision(&message, None, at))),
+                    _ => self.start(message, at, out),
+                }
+                return;
+            }
+        };
+
+        let idle = idle_secs(session.last_activity, at);
+        if idle >= self.config.auto_compact_threshold_secs {
+            self.compact(
+                format!("Auto-saved: session idle for {}s: {}", idle, session.task),
+                CommitType::StaleAutoCompact {
+                    idle_duration_secs: idle,
+                },
+                out,
+            );
+            return self.user_message(message, kind, at, out);
+        }
+
+        if idle >= self.config.ask_threshold_secs && kind != MessageKind::Clarification {
+            out.prompt = Some(format!(
+                "Welcome back! You were working on: {}\n\n\
+                 Would you like to:\n\
+                 A) Continue that work\n\
+                 B) Save it and start fresh",
+                session.task
+            ));
+            self.pending = Some(PendingAction::StaleSessionChoice {
+                user_message: message,
+            });
+            return;
+        }
+
+        self.touch(at);
+        match (kind, &session.state) {
+            (MessageKind::Response, SessionState::AwaitingUser { question, .. }) => {
+                out.actions.push(SessionAction::ObserveNote {
+                    note: format!("User: {}", message),
+                });
+                out.actions.push(SessionAction::RecordAnswer {
+                    question: question.clone(),
+                    answer: message,
+                });
+                self.ensure_running(out);
+            }
+            // A response outside AwaitingUser is treated as a modification
+            (MessageKind::Response, _) | (MessageKind::Modification, _) => {
+                out.actions.push(SessionAction::ObserveNote {
+                    note: format!("User: {}", message),
+                });
+                self.ensure_running(out);
+            }
+            (MessageKind::Confirmation, SessionState::PendingComplete { summary }) => {
+                let summary = summary.clone();
+                self.push_state(SessionState::Complete, out);
+                self.compact(summary, CommitType::Normal, out);
+            }
+            (MessageKind::Decision, _) => {
+                out.actions.push(SessionAction::ObserveNote {
+                    note: format!("User: {}", message),
+                });
+                out.actions.push(SessionAction::RecordDecision(decision(
+                    &message,
+                    Some(&session.task),
+                    at,
+                )));
+                self.ensure_running(out);
+            }
+            (MessageKind::Confirmation, _) | (MessageKind::Clarification, _) => {}
+            (MessageKind::Abandon, state) => {
+                let aborted = SessionState::Aborted {
+                    reason: message.clone(),
+                };
+                if state.can_transition_to(&aborted) {
+                    self.push_state(aborted, out);
+                }
+                self.compact(format!("Aborted: {}", message), CommitType::Abandoned, out);
+            }
+            (MessageKind::NewTask, _) => {
+                out.prompt = Some(format!(
+                    "You have unfinished work on: {}\n\n\
+                     Should I:\n\
+                     A) Save that and start on your new request\n\
+                     B) Continue where we left off",
+                    session.task
+                ));
+                self.pending = Some(PendingAction::NewTaskChoice { new_task: message });
+            }
+        }
+    }
+
+    fn resolve(
+        &mut self,
+        pending: PendingAction,
+        choice: UserChoice,
+        at: i64,
+        out: &mut SessionResponse,
+    ) {
+        let (new_task, note) = match pending {
+            PendingAction::StaleSessionChoice { user_message } => (
+                Some(user_message),
+                "User chose to continue after idle period",
+            ),
+            PendingAction::NewTaskChoice { new_task } => {
+                (Some(new_task), "User chose to continue the current task")
+            }
+            PendingAction::RecoveredSessionChoice => {
+                (None, "User chose to resume the recovered session")
+            }
+        };
+
+        match choice {
+            UserChoice::Continue => {
+                if self.session.is_some() {
+                    self.touch(at);
+                    out.actions.push(SessionAction::ObserveNote {
+                        note: note.to_string(),
+                    });
+                    self.ensure_running(out);
+                }
+            }
+            UserChoice::StartFresh => {
+                if let Some(session) = self.session.clone() {
+                    match &new_task {
+                        Some(new_task) => self.compact(
+                            format!("Saved before starting new task: {}", session.task),
+                            CommitType::InterruptedByNewTask {
+                                new_task_summary: new_task.clone(),
+                            },
+                            out,
+                        ),
+                        None => self.compact(
+                            format!("Recovered: {}", session.task),
+                            CommitType::Normal,
+                            out,
+                        ),
+                    }
+                }
+            }
+            UserChoice::Abandon => {
+                if let Some(session) = self.session.clone() {
+                    let reason = match &new_task {
+                        Some(_) => "abandoned for a new task",
+                        None => "abandoned after recovery",
+                    };
+                    let aborted = SessionState::Aborted {
+                        reason: reason.to_string(),
+                    };
+                    if session.state.can_transition_to(&aborted) {
+                        self.push_state(aborted, out);
+                    }
+                    self.compact(
+                        format!("Aborted: {}", session.task),
+                        CommitType::Abandoned,
+                        out,
+                    );
+                }
+            }
+        }
+        if choice != UserChoice::Continue {
+            if let Some(new_task) = new_task {
+                self.start(new_task, at, out);
+            }
+        }
+    }
+
+    fn tick(&mut self, at: i64, out: &mut SessionResponse) {
+        if let Some(session) = &self.session {
+            let idle = idle_secs(session.last_activity, at);
+            if idle >= self.config.auto_compact_threshold_secs {
+                let message = format!("Auto-saved: session idle for {}s: {}", idle, session.task);
+                self.pending = None;
+                self.compact(
+                    message,
+                    CommitType::StaleAutoCompact {
+                        idle_duration_secs: idle,
+                    },
+                    out,
+                );
+            }
+        }
+    }
+
+    fn transition(&mut self, to: SessionState, at: i64, out: &mut SessionResponse) -> Result<()> {
+        let session = self.session.as_ref().ok_or(CtxError::NoActiveSession)?;
+        if !session.state.can_transition_to(&to) {
+            return Err(CtxError::InvalidStateTransition {
+                from: format!("{:?}", session.state),
+                to: format!("{:?}", to),
+            });
+        }
+        self.touch(at);
+        self.push_state(to, out);
+        Ok(())
+    }
+
+    fn start(&mut self, task: String, at: i64, out: &mut SessionResponse) {
+        out.actions
+            .push(SessionAction::StartSession { task: task.clone() });
+        self.session = Some(TrackedSession {
+            task,
+            state: SessionState::Running,
+            last_activity: at,
+        });
+    }
+
+    fn ensure_running(&mut self, out: &mut SessionResponse) {
+        if let Some(session) = &self.session {
+            if session.state != SessionState::Running
+                && session.state.can_transition_to(&SessionState::Running)
+            {
+                self.push_state(SessionState::Running, out);
+            }
+        }
+    }
+
+    fn push_state(&mut self, state: SessionState, out: &mut SessionResponse) {
+        if let Some(session) = &mut self.session {
+            session.state = state.clone();
+            out.actions.push(SessionAction::SetState(state));
+        }
+    }
+
+    fn compact(&mut self, message: String, commit_type: CommitType, out: &mut SessionResponse) {
+        out.actions.push(SessionAction::Compact {
+            message,
+            commit_type,
+        });
+        self.session = None;
+    }
+
+    fn touch(&mut self, at: i64) {
+        if let Some(session) = &mut self.session {
+            session.last_activity = session.last_activity.max(at);
+        }
+    }
+}
+
+/// Applies handler actions to the repository, in order.
+///
+/// # Errors
+///
+/// Stops at the first action that fails and returns its error.
+pub fn apply_actions(repo: &mut CtxRepo, actions: &[SessionAction]) -> Result<()> {
+    for action in actions {
+        match action {
+            SessionAction::StartSession { task } => {
+                repo.start_session(task)?;
+            }
+            SessionAction::ObserveNote { note } => repo.observe_note(note)?,
+            SessionAction::RecordDecision(decision) => {
+                repo.record_decision(decision.clone())?;
+            }
+            SessionAction::RecordAnswer { question, answer } => {
+                repo.record_answer(question, answer)?;
+            }
+            SessionAction::SetState(state) => repo
+                .active_session_mut()
+                .ok_or(CtxError::NoActiveSession)?
+                .set_state(state.clone())?,
+            SessionAction::Compact {
+                message,
+                commit_type,
+            } => {
+                repo.compact_session_with_type(message, commit_type.clone())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The decision stated in `message`, answering the session's task.
+fn decision(message: &str, task: Option<&str>, at: i64) -> Decision {
+    Decision {
+        question: task.unwrap_or_default().to_string(),
+        task: task.map(str::to_string),
+        created_at: at.max(0) as u64,
+        ..Decision::from_message(message)
+    }
+}
+
+fn idle_secs(last_activity: i64, now: i64) -> u64 {
+    (now - last_activity).max(0) as u64
+}
+
+fn event_name(event: &SessionEvent) -> &'static str {
+    match event {
+        SessionEvent::UserMessage { .. } => "UserMessage",
+        SessionEvent::AgentAsked { .. } => "AgentAsked",
+        SessionEvent::AgentCompleted { .. } => "AgentCompleted",
+        SessionEvent::UserChose { .. } => "UserChose",
+        SessionEvent::SessionRecovered { .. } => "SessionRecovered",
+        SessionEvent::Tick { .. } => "Tick",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    const DAY: i64 = 24 * HOUR;
+
+    fn running() -> SessionHandler {
+        SessionHandler::with_session(
+            StaleSessionConfig::default(),
+            "Add retry logic",
+            SessionState::Running,
+            0,
+        )
+    }
+
+    fn in_state(state: SessionState) -> SessionHandler {
+        SessionHandler::with_session(StaleSessionConfig::default(), "Add retry logic", state, 0)
+    }
+
+    fn all_states() -> Vec<SessionState> {
+        vec![
+            SessionState::Running,
+            SessionState::AwaitingUser {
+                question: "A or B?".into(),
+                asked_at: 0,
+            },
+            SessionState::Interrupted {
+                user_message: "wait".into(),
+            },
+            SessionState::PendingComplete {
+                summary: "Done".into(),
+            },
+            SessionState::Complete,
+            SessionState::Aborted {
+                reason: "stop".into(),
+            },
+        ]
+    }
+
+    const ALL_KINDS: [MessageKind; 7] = [
+        MessageKind::Response,
+        MessageKind::Modification,
+        MessageKind::Confirmation,
+        MessageKind::Abandon,
+        MessageKind::NewTask,
+        MessageKind::Clarification,
+        MessageKind::Decision,
+    ];
+
+    fn message(kind: MessageKind, at: i64) -> SessionEvent {
+        SessionEvent::UserMessage {
+            message: "msg".into(),
+            kind,
+            at,
+        }
+    }
+
+    /// Checks the SetState/Compact/StartSession invariants for one response.
+    fn assert_actions_valid(before: Option<SessionState>, response: &SessionResponse) {
+        let mut state = before;
+        for action in &response.actions {
+            match action {
+                SessionAction::SetState(to) => {
+                    let from = state.as_ref().expect("SetState without a session");
+                    assert!(from.can_transition_to(to), "{:?} -> {:?}", from, to);
+                    state = Some(to.clone());
+                }
+                SessionAction::ObserveNote { .. } => {
+                    assert!(state.is_some(), "note without a session")
+                }
+                SessionAction::RecordDecision(_) => {}
+                SessionAction::RecordAnswer { .. } => {
+                    assert!(state.is_some(), "answer without a session")
+                }
+                SessionAction::Compact { .. } => {
+                    assert!(state.is_some(), "compact without a session");
+                    state = None;
+                }
+                SessionAction::StartSession { .. } => {
+                    assert!(state.is_none(), "start while a session is tracked");
+                    state = Some(SessionState::Running);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_state_and_message_kind_keeps_invariants() {
+        for state in all_states() {
+            for kind in ALL_KINDS {
+                for at in [HOUR, 2 * DAY, 8 * DAY] {
+                    let mut handler = in_state(state.clone());
+                    let response = handler.handle(message(kind, at)).unwrap();
+                    assert_actions_valid(Some(state.clone()), &response);
+                    // Prompts and pending choices always go together
+                    assert_eq!(response.prompt.is_some(), handler.pending().is_some());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_without_session_starts_one() {
+        for kind in ALL_KINDS {
+            let mut handler = SessionHandler::new(StaleSessionConfig::default());
+            let response = handler.handle(message(kind, 0)).unwrap();
+            assert_actions_valid(None, &response);
+
+            let starts = matches!(
+                response.actions.as_slice(),
+                [SessionAction::StartSession { .. }]
+            );
+            let expected = !matches!(
+                kind,
+                MessageKind::Clarification | MessageKind::Abandon | MessageKind::Decision
+            );
+            assert_eq!(starts, expected, "{:?}", kind);
+            assert_eq!(handler.state().is_some(), expected);
+        }
+    }
+
+    #[test]
+    fn test_response_resumes_from_awaiting_user() {
+        let mut handler = running();
+        handler
+            .handle(SessionEvent::AgentAsked {
+                question: "Fixed or exponential?".into(),
+                at: 10,
+            })
+            .unwrap();
+
+        let response = handler
+            .handle(SessionEvent::UserMessage {
+                message: "Exponential".into(),
+                kind: MessageKind::Response,
+                at: 20,
+            })
+            .unwrap();
+
+        assert_eq!(
+            response.actions,
+            vec![
+                SessionAction::ObserveNote {
+                    note: "User: Exponential".into()
+                },
+                SessionAction::RecordAnswer {
+                    question: "Fixed or exponential?".into(),
+                    answer: "Exponential".into(),
+                },
+                SessionAction::SetState(SessionState::Running),
+            ]
+        );
+        assert_eq!(handler.state(), Some(&SessionState::Running));
+    }
+
+    #[test]
+    fn test_confirmation_completes_and_compacts() {
+        let mut handler = running();
+        handler
+            .handle(SessionEvent::AgentCompleted {
+                summary: "Added retries".into(),
+                at: 10,
+            })
+            .unwrap();
+
+        let response = handler
+            .handle(message(MessageKind::Confirmation, 20))
+            .unwrap();
+
+        assert_eq!(
+            response.actions,
+            vec![
+                SessionAction::SetState(SessionState::Complete),
+                SessionAction::Compact {
+                    message: "Added retries".into(),
+                    commit_type: CommitType::Normal,
+                },
+            ]
+        );
+        assert_eq!(handler.state(), None);
+    }
+
+    #[test]
+    fn test_confirmation_outside_pending_complete_is_noop() {
+        let mut handler = running();
+        let response = handler
+            .handle(message(MessageKind::Confirmation, 10))
+            .unwrap();
+        assert!(response.actions.is_empty());
+        assert_eq!(handler.state(), Some(&SessionState::Running));
+    }
+
+    #[test]
+    fn test_abandon_aborts_and_compacts() {
+        let mut handler = running();
+        let response = handler.handle(message(MessageKind::Abandon, 10)).unwrap();
+
+        assert!(matches!(
+            response.actions.as_slice(),
+            [
+                SessionAction::SetState(SessionState::Aborted { .. }),
+                SessionAction::Compact {
+                    commit_type: CommitType::Abandoned,
+                    ..
+                }
+            ]
+        ));
+        assert_eq!(handler.state(), None);
+    }
+
+    #[test]
+    fn test_new_task_asks_then_switches() {
+        let mut handler = running();
+        let response = handler
+            .handle(SessionEvent::UserMessage {
+                message: "Add pooling".into(),
+                kind: MessageKind::NewTask,
+                at: 10,
+            })
+            .unwrap();
+        assert!(response.actions.is_empty());
+        assert!(response.prompt.is_some());
+        assert_eq!(
+            handler.pending(),
+            Some(&PendingAction::NewTaskChoice {
+                new_task: "Add pooling".into()
+            })
+        );
+
+        let response = handler
+            .handle(SessionEvent::UserChose {
+                choice: UserChoice::StartFresh,
+                at: 20,
+            })
+            .unwrap();
+        assert_eq!(
+            response.actions,
+            vec![
+                SessionAction::Compact {
+                    message: "Saved before starting new task: Add retry logic".into(),
+                    commit_type: CommitType::InterruptedByNewTask {
+                        new_task_summary: "Add pooling".into()
+                    },
+                },
+                SessionAction::StartSession {
+                    task: "Add pooling".into()
+                },
+            ]
+        );
+        assert_eq!(handler.task(), Some("Add pooling"));
+        assert_eq!(handler.pending(), None);
+    }
+
+    #[test]
+    fn test_new_task_continue_keeps_session() {
+        let mut handler = running();
+        handler.handle(message(MessageKind::NewTask, 10)).unwrap();
+        let response = handler
+            .handle(SessionEvent::UserChose {
+                choice: UserChoice::Continue,
+                at: 20,
+            })
+            .unwrap();
+
+        assert!(matches!(
+            response.actions.as_slice(),
+            [SessionAction::ObserveNote { .. }]
+        ));
+        assert_eq!(handler.task(), Some("Add retry logic"));
+    }
+
+    #[test]
+    fn test_recovered_session_choices() {
+        let recovered = SessionEvent::SessionRecovered {
+            steps_kept: 3,
+            steps_lost: 1,
+            at: 10,
+        };
+        assert!(matches!(
+            SessionHandler::new(StaleSessionConfig::default()).handle(recovered.clone()),
+            Err(CtxError::NoActiveSession)
+        ));
+
+        let choose = |choice| {
+            let mut handler = running();
+            let response = handler.handle(recovered.clone()).unwrap();
+            assert!(response.actions.is_empty());
+            assert!(response.prompt.unwrap().contains("3 steps kept, 1 lost"));
+            assert_eq!(
+                handler.pending(),
+                Some(&PendingAction::RecoveredSessionChoice)
+            );
+            let response = handler
+                .handle(SessionEvent::UserChose { choice, at: 20 })
+                .unwrap();
+            (handler, response.actions)
+        };
+
+        let (handler, actions) = choose(UserChoice::Continue);
+        assert!(matches!(
+            actions.as_slice(),
+            [SessionAction::ObserveNote { .. }]
+        ));
+        assert_eq!(handler.task(), Some("Add retry logic"));
+
+        let (handler, actions) = choose(UserChoice::StartFresh);
+        assert_eq!(
+            actions,
+            vec![SessionAction::Compact {
+                message: "Recovered: Add retry logic".into(),
+                commit_type: CommitType::Normal,
+            }]
+        );
+        assert_eq!(handler.state(), None);
+
+        let (handler, actions) = choose(UserChoice::Abandon);
+        assert_eq!(
+            actions,
+            vec![
+                SessionAction::SetState(SessionState::Aborted {
+                    reason: "abandoned after recovery".into()
+                }),
+                SessionAction::Compact {
+                    message: "Aborted: Add retry logic".into(),
+                    commit_type: CommitType::Abandoned,
+                },
+            ]
+        );
+        assert_eq!(handler.state(), None);
+    }
+
+    #[test]
+    fn test_new_task_abandon_discards_session() {
+        let mut handler = running();
+        handler.handle(message(MessageKind::NewTask, 10)).unwrap();
+        let response = handler
+            .handle(SessionEvent::UserChose {
+                choice: UserChoice::Abandon,
+                at: 20,
+            })
+            .unwrap();
+
+        assert!(matches!(
+            response.actions.as_slice(),
+            [
+                SessionAction::SetState(SessionState::Aborted { .. }),
+                SessionAction::Compact {
+                    commit_type: CommitType::Abandoned,
+                    ..
+                },
+                SessionAction::StartSession { .. },
+            ]
+        ));
+        assert_eq!(handler.state(), Some(&SessionState::Running));
+    }
+
+    #[test]
+    fn test_pending_choice_blocks_other_events() {
+        let mut handler = running();
+        handler.handle(message(MessageKind::NewTask, 10)).unwrap();
+        let before = handler.clone();
+
+        assert!(handler
+            .handle(message(MessageKind::Modification, 20))
+            .is_err());
+        assert!(handler
+            .handle(SessionEvent::AgentAsked {
+                question: "?".into(),
+                at: 20
+            })
+            .is_err());
+        assert_eq!(handler, before);
+    }
+
+    #[test]
+    fn test_idle_past_ask_threshold_prompts() {
+        let mut handler = running();
+        let response = handler
+            .handle(message(MessageKind::Modification, 2 * DAY))
+            .unwrap();
+
+        assert!(response.actions.is_empty());
+        assert!(matches!(
+            handler.pending(),
+            Some(PendingAction::StaleSessionChoice { .. })
+        ));
+    }
+
+    #[test]
+    fn test_idle_past_auto_compact_threshold_compacts_then_handles() {
+        let mut handler = running();
+        let response = handler
+            .handle(SessionEvent::UserMessage {
+                message: "Add pooling".into(),
+                kind: MessageKind::NewTask,
+                at: 8 * DAY,
+            })
+            .unwrap();
+
+        assert!(matches!(
+            response.actions.as_slice(),
+            [
+                SessionAction::Compact {
+                    commit_type: CommitType::StaleAutoCompact { .. },
+                    ..
+                },
+                SessionAction::StartSession { .. }
+            ]
+        ));
+        assert_eq!(handler.task(), Some("Add pooling"));
+    }
+
+    #[test]
+    fn test_tick_enforces_auto_compact_timeout() {
+        let mut handler = running();
+        assert!(handler
+            .handle(SessionEvent::Tick { at: DAY })
+            .unwrap()
+            .actions
+            .is_empty());
+
+        let response = handler.handle(SessionEvent::Tick { at: 7 * DAY }).unwrap();
+        assert_eq!(
+            response.actions,
+            vec![SessionAction::Compact {
+                message: "Auto-saved: session idle for 604800s: Add retry logic".into(),
+                commit_type: CommitType::StaleAutoCompact {
+                    idle_duration_secs: 7 * DAY as u64
+                },
+            }]
+        );
+        assert_eq!(handler.state(), None);
+    }
+
+    #[test]
+    fn test_agent_events_follow_transition_table() {
+        for state in all_states() {
+            let ask = SessionEvent::AgentAsked {
+                question: "?".into(),
+                at: 1,
+            };
+            let complete = SessionEvent::AgentCompleted {
+                summary: "done".into(),
+                at: 1,
+            };
+            for event in [ask, complete] {
+                let mut handler = in_state(state.clone());
+                let before = handler.clone();
+                match handler.handle(event) {
+                    Ok(response) => assert_actions_valid(Some(state.clone()), &response),
+                    Err(CtxError::InvalidStateTransition { .. }) => assert_eq!(handler, before),
+                    Err(e) => panic!("unexpected error: {}", e),
+                }
+            }
+        }
+
+        let mut handler = SessionHandler::new(StaleSessionConfig::default());
+        assert!(matches!(
+            handler.handle(SessionEvent::AgentAsked {
+                question: "?".into(),
+                at: 0
+            }),
+            Err(CtxError::NoActiveSession)
+        ));
+    }
+
+    #[test]
+    fn test_handler_is_deterministic() {
+        let events = [
+            message(MessageKind::NewTask, 0),
+            SessionEvent::AgentAsked {
+                question: "A or B?".into(),
+                at: 10,
+            },
+            message(MessageKind::Response, 20),
+            SessionEvent::AgentCompleted {
+                summary: "Done".into(),
+                at: 30,
+            },
+            message(MessageKind::Confirmation, 40),
+        ];
+
+        let run = || {
+            let mut handler = SessionHandler::new(StaleSessionConfig::default());
+            events
+                .iter()
+                .map(|e| handler.handle(e.clone()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_apply_actions_drives_repo() {
+        use tempfile::TempDir;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        let mut handler = SessionHandler::for_repo(StaleSessionConfig::default(), &repo);
+
+        let events = vec![
+            SessionEvent::UserMessage {
+                message: "Add retry logic".into(),
+                kind: MessageKind::NewTask,
+                at: 0,
+            },
+            SessionEvent::AgentCompleted {
+                summary: "Added retries".into(),
+                at: 10,
+            },
+            message(MessageKind::Confirmation, 20),
+        ];
+        for event in events {
+            let response = handler.handle(event).unwrap();
+            apply_actions(&mut repo, &response.actions).unwrap();
+        }
+
+        assert!(!repo.has_active_session());
+        assert_eq!(repo.head().unwrap().message, "Added retries");
+    }
+
+    #[test]
+    fn test_decision_message_records_decision() {
+        use tempfile::TempDir;
+
+        let tmp = TempDir::new().unwrap();
+        let mut repo = CtxRepo::init(tmp.path()).unwrap();
+        repo.start_session("Add retry logic").unwrap();
+        repo.observe_file_write("src/retry.rs", b"pub fn retry() {}")
+            .unwrap();
+        let mut handler = SessionHandler::for_repo(StaleSessionConfig::default(), &repo);
+
+        let response = handler
+            .handle(SessionEvent::UserMessage {
+                message: "Exponential backoff over fixed delays because the API rate limits".into(),
+                kind: MessageKind::Decision,
+                at: 10,
+            })
+            .unwrap();
+        let [SessionAction::ObserveNote { .. }, SessionAction::RecordDecision(decision)] =
+            response.actions.as_slice()
+        else {
+            panic!("unexpected actions: {:?}", response.actions);
+        };
+        assert_eq!(decision.question, "Add retry logic");
+        assert_eq!(decision.choice, "Exponential backoff");
+        assert_eq!(
+            decision.options,
+            vec!["Exponential backoff", "fixed delays"]
+        );
+        assert_eq!(decision.reasoning, "the API rate limits");
+
+        apply_actions(&mut repo, &response.actions).unwrap();
+        // Recorded in the session, logged on compaction
+        assert!(repo.decisions().unwrap().is_empty());
+        repo.compact_session("Add retries").unwrap();
+
+        let decisions = repo.decisions().unwrap();
+        assert_eq!(decisions.len(), 1);
+        let (_, recorded) = &decisions[0];
+        assert_eq!(recorded.paths, vec!["src/retry.rs"]);
+        assert_eq!(recorded.task.as_deref(), Some("Add retry logic"));
+        assert_eq!(recorded.created_at, 10);
+    }
+}
@@ -0,0 +1,509 @@
+//! Generated commit messages and log entries for compacted sessions.
+//!
+//! [`CtxRepo::compact_session_summarized`](crate::CtxRepo::compact_session_summarized)
+//! digests the session's observations into a [`SessionDigest`] and asks a
+//! [`SummaryProvider`] for the commit message and the narrative log entry.
+//! [`HeuristicSummaryProvider`] lists files, commands and notes;
+//! [`CommandSummaryProvider`] hands the digest to an external command, which
+//! is how a model writes them instead.
+
+use crate::error::{CtxError, Result};
+use crate::policy::ExecPolicy;
+use crate::types::Observation;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::Command;
+
+/// Most files or commands named before the rest are counted instead.
+const MAX_LISTED: usize = 5;
+
+/// What a session did, as given to a [`SummaryProvider`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionDigest {
+    /// The session's task.
+    pub task: String,
+    /// The session's ID.
+    pub session_id: String,
+    /// Paths written, sorted.
+    pub files_written: Vec<String>,
+    /// Paths read but not written, sorted.
+    pub files_read: Vec<String>,
+    /// Commands run, in order.
+    pub commands: Vec<CommandRun>,
+    /// Notes, in order.
+    pub notes: Vec<String>,
+    /// Plans, in order.
+    pub plans: Vec<String>,
+    /// What each named sub-agent did, by actor ID. Empty if no observation
+    /// was attributed to one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub actors: BTreeMap<String, ActorActivity>,
+}
+
+/// What one sub-agent of a session did; see
+/// [`CtxRepo::observe_as`](crate::CtxRepo::observe_as).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActorActivity {
+    /// Observations attributed to the actor, counting collapsed repeats.
+    pub observations: usize,
+    /// Paths written, sorted.
+    pub files_written: Vec<String>,
+    /// Paths read but not written, sorted.
+    pub files_read: Vec<String>,
+    /// Commands run.
+    pub commands: usize,
+    /// Of those, how many exited with a non-zero code.
+    pub failed_commands: usize,
+}
+
+impl ActorActivity {
+    /// Tallies `observations` by the actor they're attributed to, leaving
+    /// out those made by the session as a whole.
+    pub fn by_actor(observations: &[Observation]) -> BTreeMap<String, ActorActivity> {
+        let mut actors: BTreeMap<String, ActorActivity> = BTreeMap::new();
+        let mut written: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut read: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for observation in observations {
+            let Some(actor) = observation.actor() else {
+                continue;
+            };
+            let activity = actors.entry(actor.to_string()).or_default();
+            activity.observations += observation.count() as usize;
+            match observation {
+                Observation::FileWrite { path, .. } => {
+                    written.entry(actor).or_default().insert(path);
+                }
+                Observation::FileRead { path, .. } => {
+                    read.entry(actor).or_default().insert(path);
+                }
+                Observation::Command { exit_code, .. } => {
+                    let runs = observation.count() as usize;
+                    activity.commands += runs;
+                    if exit_code.is_some_and(|code| code != 0) {
+                        activity.failed_commands += runs;
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (actor, activity) in actors.iter_mut() {
+            let written = written.remove(actor.as_str()).unwrap_or_default();
+            let read = read.remove(actor.as_str()).unwrap_or_default();
+            activity.files_read = read.difference(&written).map(|p| p.to_string()).collect();
+            activity.files_written = written.into_iter().map(str::to_string).collect();
+        }
+        actors
+    }
+
+    /// One line naming what the actor did, for summaries.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.files_written.is_empty() {
+            parts.push(format!(
+                "changed {}",
+                list(self.files_written.iter().map(String::as_str))
+            ));
+        }
+        if !self.files_read.is_empty() {
+            parts.push(format!("read {} files", self.files_read.len()));
+        }
+        match (self.commands, self.failed_commands) {
+            (0, _) => {}
+            (runs, 0) => parts.push(format!("ran {} commands", runs)),
+            (runs, failed) => parts.push(format!("ran {} commands, {} failed", runs, failed)),
+        }
+        if parts.is_empty() {
+            parts.push(format!("{} observations", self.observations));
+        }
+        parts.join("; ")
+    }
+}
+
+/// A command run during a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandRun {
+    /// The command line.
+    pub command: String,
+    /// Its exit code, if known.
+    pub exit_code: Option<i32>,
+}
+
+impl SessionDigest {
+    /// Digests a session's observations.
+    pub fn from_observations(task: &str, session_id: &str, observations: &[Observation]) -> Self {
+        let mut written = BTreeSet::new();
+        let mut read = BTreeSet::new();
+        let mut digest = SessionDigest {
+            task: task.to_string(),
+            session_id: session_id.to_string(),
+            ..Default::default()
+        };
+        for observation in observations {
+            match observation {
+                Observation::FileWrite { path, .. } => {
+                    written.insert(path.clone());
+                }
+                Observation::FileRead { path, .. } => {
+                    read.insert(path.clone());
+                }
+                Observation::Command {
+                    command, exit_code, ..
+                } => digest.commands.push(CommandRun {
+                    command: command.clone(),
+                    exit_code: *exit_code,
+                }),
+                Observation::Note { content, .. } => digest.notes.push(content.clone()),
+                Observation::Plan { content, .. } => digest.plans.push(content.clone()),
+                // Decisions, facts and messages are kept in their own logs
+                Observation::Decision { .. }
+                | Observation::Fact { .. }
+                | Observation::Qa { .. }
+                | Observation::Message { .. } => {}
+            }
+        }
+        digest.files_read = read.difference(&written).cloned().collect();
+        digest.files_written = written.into_iter().collect();
+        digest.actors = ActorActivity::by_actor(observations);
+        digest
+    }
+
+    /// Commands that exited with a non-zero code.
+    pub fn failed_commands(&self) -> impl Iterator<Item = &CommandRun> {
+        self.commands
+            .iter()
+            .filter(|run| run.exit_code.is_some_and(|code| code != 0))
+    }
+}
+
+/// A generated commit message and narrative log entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Commit message: a subject line, optionally followed by a blank line
+    /// and a body.
+    pub message: String,
+    /// Markdown appended to the daily log. Empty to skip the log.
+    pub log_entry: String,
+}
+
+/// Writes the commit message and log entry for a compacted session.
+///
+/// Implement this to have a model describe sessions, and pass it to
+/// [`CtxRepo::compact_session_summarized`](crate::CtxRepo::compact_session_summarized).
+pub trait SummaryProvider {
+    /// Summarizes the session described by `digest`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no summary could be produced; the session is then
+    /// left uncompacted.
+    fn summarize_session(&self, digest: &SessionDigest) -> Result<SessionSummary>;
+}
+
+/// Describes a session by listing what it touched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicSummaryProvider;
+
+/// Joins the first [`MAX_LISTED`] items, counting the rest.
+fn list<'a>(items: impl ExactSizeIterator<Item = &'a str>) -> String {
+    let total = items.len();
+    let mut listed: Vec<&str> = items.take(MAX_LISTED).collect();
+    let more = format!("{} more", total - listed.len());
+    if total > MAX_LISTED {
+        listed.push(&more);
+    }
+    listed.join(", ")
+}
+
+/// The file name of `path`, for subject lines.
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl SummaryProvider for HeuristicSummaryProvider {
+    fn summarize_session(&self, digest: &SessionDigest) -> Result<SessionSummary> {
+        let task = digest.task.trim();
+        let subject = match (task.is_empty(), digest.files_written.as_slice()) {
+            (false, _) => task.lines().next().unwrap_or(task).to_string(),
+            (true, []) => format!("Session {}", digest.session_id),
+            (true, written) => format!("Update {}", list(written.iter().map(|p| file_name(p)))),
+        };
+
+        let mut body = Vec::new();
+        if !digest.files_written.is_empty() {
+            let files = list(digest.files_written.iter().map(String::as_str));
+            body.push(format!("Changed: {}", files));
+        }
+        if !digest.commands.is_empty() {
+            let failed = digest.failed_commands().count();
+            body.push(match failed {
+                0 => format!("Ran {} commands", digest.commands.len()),
+                _ => format!("Ran {} commands, {} failed", digest.commands.len(), failed),
+            });
+        }
+        if !digest.actors.is_empty() {
+            let actors: Vec<&str> = digest.actors.keys().map(String::as_str).collect();
+            body.push(format!("Actors: {}", actors.join(", ")));
+        }
+        let message = if body.is_empty() {
+            subject.clone()
+        } else {
+            format!("{}\n\n{}", subject, body.join("\n"))
+        };
+
+        let mut entry = vec![format!("**{}**", subject), String::new()];
+        if !digest.files_written.is_empty() {
+            let files = list(digest.files_written.iter().map(String::as_str));
+            entry.push(format!("- Changed: {}", files));
+        }
+        if !digest.files_read.is_empty() {
+            entry.push(format!("- Read {} other files", digest.files_read.len()));
+        }
+        let failed: Vec<&str> = digest
+            .failed_commands()
+            .map(|run| run.command.as_str())
+            .collect();
+        if !failed.is_empty() {
+            entry.push(format!("- Failed: {}", list(failed.into_iter())));
+        }
+        for (actor, activity) in &digest.actors {
+            entry.push(format!("- {}: {}", actor, activity.describe()));
+        }
+        for plan in &digest.plans {
+            entry.push(format!("- Plan: {}", plan.trim()));
+        }
+        for note in &digest.notes {
+            entry.push(format!("- Note: {}", note.trim()));
+        }
+        if entry.len() == 2 {
+            entry.push("- No recorded activity".to_string());
+        }
+
+        Ok(SessionSummary {
+            message,
+            log_entry: entry.join("\n"),
+        })
+    }
+}
+
+/// Summarizes by running an external command, such as a model CLI.
+///
+/// The [`SessionDigest`] is written to the command's stdin as JSON. The
+/// command prints either a JSON [`SessionSummary`], or plain text whose
+/// first line is the commit subject; plain text is used as both the
+/// message and the log entry. It runs through the repository's
+/// [`ExecPolicy`].
+#[derive(Debug, Clone)]
+pub struct CommandSummaryProvider {
+    command: Vec<String>,
+    policy: ExecPolicy,
+}
+
+impl CommandSummaryProvider {
+    /// Creates a provider running `command` (program, then arguments).
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `command` is empty.
+    pub fn new(command: Vec<String>, policy: ExecPolicy) -> Result<Self> {
+        if command.is_empty() {
+            return Err(CtxError::InvalidArgument(
+                "summary command is empty".to_string(),
+            ));
+        }
+        Ok(Self { command, policy })
+    }
+}
+
+impl SummaryProvider for CommandSummaryProvider {
+    fn summarize_session(&self, digest: &SessionDigest) -> Result<SessionSummary> {
+        let input =
+            serde_json::to_vec(digest).map_err(|e| CtxError::Serialization(e.to_string()))?;
+        let output = self.policy.output_with_input(
+            Command::new(&self.command[0]).args(&self.command[1..]),
+            &input,
+        )?;
+        if !output.status.success() {
+            return Err(CtxError::InvalidArgument(format!(
+                "summary command '{}' failed: {}",
+                self.command[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let summary = if text.starts_with('{') {
+            serde_json::from_str(&text).map_err(|e| {
+                CtxError::InvalidArgument(format!("summary command printed invalid JSON: {}", e))
+            })?
+        } else {
+            SessionSummary {
+                message: text.clone(),
+                log_entry: text,
+            }
+        };
+        if summary.message.trim().is_empty() {
+            return Err(CtxError::InvalidArgument(format!(
+                "summary command '{}' returned no commit message",
+                self.command[0]
+            )));
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Metadata, ACTOR_KEY, OBSERVED_COUNT_KEY};
+    use crate::ObjectId;
+
+    fn digest() -> SessionDigest {
+        let blob = ObjectId::from_bytes([1; 32]);
+        SessionDigest::from_observations(
+            "Fix parser",
+            "s1",
+            &[
+                Observation::FileRead {
+                    path: "src/lib.rs".into(),
+                    content_id: None,
+                    metadata: Default::default(),
+                },
+                Observation::FileRead {
+                    path: "src/parser.rs".into(),
+                    content_id: None,
+                    metadata: Default::default(),
+                },
+                Observation::FileWrite {
+                    path: "src/parser.rs".into(),
+                    content_id: blob,
+                    metadata: Default::default(),
+                },
+                Observation::Command {
+                    command: "cargo test".into(),
+                    exit_code: Some(101),
+                    output_id: None,
+                    metadata: Default::default(),
+                },
+                Observation::Command {
+                    command: "cargo test".into(),
+                    exit_code: Some(0),
+                    output_id: None,
+                    metadata: Default::default(),
+                },
+                Observation::Note {
+                    content: "Off-by-one in the tokenizer".into(),
+                    metadata: Default::default(),
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn test_heuristic_session_summary() {
+        let digest = digest();
+        assert_eq!(digest.files_written, vec!["src/parser.rs"]);
+        assert_eq!(digest.files_read, vec!["src/lib.rs"]);
+
+        let summary = HeuristicSummaryProvider.summarize_session(&digest).unwrap();
+        assert_eq!(
+            summary.message,
+            "Fix parser\n\nChanged: src/parser.rs\nRan 2 commands, 1 failed"
+        );
+        assert_eq!(
+            summary.log_entry,
+            "**Fix parser**\n\n- Changed: src/parser.rs\n- Read 1 other files\n\
+             - Failed: cargo test\n- Note: Off-by-one in the tokenizer"
+        );
+
+        let untitled = SessionDigest {
+            task: String::new(),
+            ..digest
+        };
+        let summary = HeuristicSummaryProvider
+            .summarize_session(&untitled)
+            .unwrap();
+        assert!(summary.message.starts_with("Update parser.rs\n"));
+    }
+
+    #[test]
+    fn test_actor_breakdown() {
+        let by = |actor: &str| Metadata::from([(ACTOR_KEY.to_string(), actor.to_string())]);
+        let digest = SessionDigest::from_observations(
+            "Add lexer",
+            "s1",
+            &[
+                Observation::Plan {
+                    content: "Lexer first".into(),
+                    metadata: by("planner"),
+                },
+                Observation::FileRead {
+                    path: "src/lib.rs".into(),
+                    content_id: None,
+                    metadata: by("coder"),
+                },
+                Observation::FileWrite {
+                    path: "src/lexer.rs".into(),
+                    content_id: ObjectId::hash_blob(b"lexer"),
+                    metadata: by("coder"),
+                },
+                Observation::Command {
+                    command: "cargo test".into(),
+                    exit_code: Some(101),
+                    output_id: None,
+                    metadata: by("reviewer")
+                        .into_iter()
+                        .chain([(OBSERVED_COUNT_KEY.to_string(), "2".to_string())])
+                        .collect(),
+                },
+                Observation::Note {
+                    content: "Unattributed".into(),
+                    metadata: Default::default(),
+                },
+            ],
+        );
+
+        assert_eq!(
+            digest.actors.keys().collect::<Vec<_>>(),
+            vec!["coder", "planner", "reviewer"]
+        );
+        let coder = &digest.actors["coder"];
+        assert_eq!(coder.files_written, vec!["src/lexer.rs"]);
+        assert_eq!(coder.files_read, vec!["src/lib.rs"]);
+        assert_eq!(digest.actors["reviewer"].observations, 2);
+        assert_eq!(digest.actors["reviewer"].failed_commands, 2);
+
+        let summary = HeuristicSummaryProvider.summarize_session(&digest).unwrap();
+        assert!(summary
+            .message
+            .ends_with("\nActors: coder, planner, reviewer"));
+        assert!(summary.log_entry.contains(
+            "- coder: changed src/lexer.rs; read 1 files\n- planner: 1 observations\n\
+             - reviewer: ran 2 commands, 2 failed\n"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_summary_provider_reads_json_or_text() {
+        let sh = |script: &str| {
+            CommandSummaryProvider::new(
+                vec!["sh".into(), "-c".into(), script.into()],
+                ExecPolicy::default(),
+            )
+            .unwrap()
+        };
+
+        let json = sh(r#"cat >/dev/null; echo '{"message": "Subject", "log_entry": "Entry"}'"#);
+        let summary = json.summarize_session(&digest()).unwrap();
+        assert_eq!(summary.message, "Subject");
+        assert_eq!(summary.log_entry, "Entry");
+
+        // The digest arrives as JSON on stdin
+        let text = sh(r#"grep -o '"task":"[^"]*"'"#);
+        let summary = text.summarize_session(&digest()).unwrap();
+        assert_eq!(summary.message, r#""task":"Fix parser""#);
+
+        assert!(sh("true").summarize_session(&digest()).is_err());
+        assert!(sh("exit 1").summarize_session(&digest()).is_err());
+    }
+}
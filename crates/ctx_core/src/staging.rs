@@ -1,9 +1,11 @@
 //! Staging area management for work-in-progress commits.
 
 use crate::error::{CtxError, Result};
-use crate::types::{Commit, CommitType, NarrativeRef, Observation, Tree, WorkCommit};
+use crate::types::{
+    format_tag, Commit, CommitType, NarrativeRef, Observation, Tree, WorkCommit, ACTOR_KEY,
+};
 use crate::{ObjectId, ObjectStore};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Walks the staging chain from STAGE back to base commit.
@@ -68,6 +70,10 @@ pub fn compact_staging(
     let chain = walk_staging_chain(staging_head, base_commit, object_store)?;
     let base: Commit = object_store.get_typed(base_commit)?;
     let narrative_refs = collect_narrative_refs_from_chain(&chain);
+    let tags = collect_tags_from_chain(&chain);
+    // The most recent step's author speaks for the session
+    let author = chain.iter().rev().find_map(|(_, work)| work.author.clone());
+    let task = chain.last().map(|(_, work)| work.task_description.clone());
     let observations = collect_observations_from_chain(&chain, object_store)?;
     let root_tree = build_tree_from_observations(&observations, base.root_tree, object_store)?;
 
@@ -76,24 +82,82 @@ pub fn compact_staging(
         .expect("system time before Unix epoch")
         .as_secs();
 
+    // Paths the heuristic import resolver may link to
+    let mut known_files: std::collections::BTreeSet<String> =
+        flatten_tree(base.root_tree, object_store)?
+            .into_keys()
+            .collect();
+    known_files.extend(flatten_tree(root_tree, object_store)?.into_keys());
+
     let edge_batch_ids = extract_edges_from_observations(
         &observations,
         base_commit, // Will be updated after commit is created
         now,
+        &known_files,
+        object_store,
+    )?;
+    let decision_ids: Vec<ObjectId> = observations
+        .iter()
+        .filter_map(|obs| match obs {
+            Observation::Decision { decision_id, .. } => Some(*decision_id),
+            _ => None,
+        })
+        .collect();
+    let (decisions, decision_batch) = crate::decision::append_decisions(
+        &decision_ids,
+        base.decisions,
+        base_commit,
+        now,
+        object_store,
+    )?;
+    let fact_ids: Vec<ObjectId> = observations
+        .iter()
+        .filter_map(|obs| match obs {
+            Observation::Fact { fact_id, .. } => Some(*fact_id),
+            _ => None,
+        })
+        .collect();
+    let (facts, fact_batch) = crate::fact::append_facts(
+        &fact_ids,
+        base.facts,
+        root_tree,
+        base_commit,
+        now,
         object_store,
     )?;
+    let qa_ids: Vec<ObjectId> = observations
+        .iter()
+        .filter_map(|obs| match obs {
+            Observation::Qa { qa_id, .. } => Some(*qa_id),
+            _ => None,
+        })
+        .collect();
+    let (qa, qa_batch) = crate::qa::append_pairs(&qa_ids, base.qa, base_commit, now, object_store)?;
 
     let commit = Commit {
         parents: vec![base_commit],
         timestamp_unix: now,
         message: message.to_string(),
         root_tree,
-        edge_batches: edge_batch_ids,
+        edge_batches: edge_batch_ids
+            .into_iter()
+            .chain(decision_batch)
+            .chain(fact_batch)
+            .chain(qa_batch)
+            .collect(),
         narrative_refs,
         cargo_snapshot: base.cargo_snapshot,
         rust_snapshot: base.rust_snapshot,
         diagnostics_snapshot: base.diagnostics_snapshot,
+        glossary: base.glossary,
+        decisions,
+        facts,
+        qa,
+        transcript: None,
         commit_type: Some(commit_type),
+        author,
+        task,
+        tags,
     };
 
     Ok(commit)
@@ -101,9 +165,7 @@ pub fn compact_staging(
 
 /// Collects all observations from a staging chain.
 ///
-/// This is an internal utility used by tests and debugging.
 /// Not part of the public API.
-#[allow(dead_code)]
 pub(crate) fn collect_observations(
     staging_head: ObjectId,
     base_commit: ObjectId,
@@ -124,7 +186,10 @@ pub fn build_tree_from_observations(
     // Collect file writes (latest wins)
     let mut file_map: HashMap<String, ObjectId> = HashMap::new();
     for obs in observations {
-        if let Observation::FileWrite { path, content_id } = obs {
+        if let Observation::FileWrite {
+            path, content_id, ..
+        } = obs
+        {
             file_map.insert(path.clone(), *content_id);
         }
     }
@@ -136,12 +201,72 @@ pub fn build_tree_from_observations(
     build_tree_from_paths(&file_map, object_store)
 }
 
+/// Resolves a slash-separated path within a tree.
+///
+/// Returns the ObjectId of the blob or subtree at `path`, or None if any
+/// component is missing.
+pub(crate) fn lookup_tree_path(
+    tree_id: ObjectId,
+    path: &str,
+    object_store: &ObjectStore,
+) -> Result<Option<ObjectId>> {
+    use crate::types::TreeEntryKind;
+
+    let mut current = tree_id;
+    let mut parts = path.split('/').filter(|p| !p.is_empty()).peekable();
+
+    while let Some(part) = parts.next() {
+        let tree: Tree = object_store.get_typed(current)?;
+        let entry = match tree.entries.iter().find(|e| e.name == part) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        if parts.peek().is_some() && entry.kind != TreeEntryKind::Tree {
+            return Ok(None);
+        }
+        current = entry.id;
+    }
+
+    Ok(Some(current))
+}
+
+/// Flattens a tree into a map of slash-separated file paths to blob IDs.
+pub(crate) fn flatten_tree(
+    tree_id: ObjectId,
+    object_store: &ObjectStore,
+) -> Result<std::collections::BTreeMap<String, ObjectId>> {
+    use crate::types::TreeEntryKind;
+
+    let mut files = std::collections::BTreeMap::new();
+    let mut stack = vec![(String::new(), tree_id)];
+
+    while let Some((prefix, id)) = stack.pop() {
+        let tree: Tree = object_store.get_typed(id)?;
+        for entry in tree.entries {
+            let path = if prefix.is_empty() {
+                entry.name
+            } else {
+                format!("{}/{}", prefix, entry.name)
+            };
+            match entry.kind {
+                TreeEntryKind::Tree => stack.push((path, entry.id)),
+                TreeEntryKind::Blob => {
+                    files.insert(path, entry.id);
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 /// Builds a tree structure from a map of file paths to content IDs.
 fn build_tree_from_paths(
     file_map: &HashMap<String, ObjectId>,
     object_store: &ObjectStore,
 ) -> Result<ObjectId> {
-    use crate::types::{TreeEntry, TreeEntryKind};
+    use crate::types::TreeEntryKind;
     use std::collections::BTreeMap;
 
     // Map: directory path -> (filename -> content_id or subtree_id)
@@ -197,51 +322,30 @@ fn build_tree_from_paths(
     // Build trees from deepest level up
     let mut tree_cache: BTreeMap<String, ObjectId> = BTreeMap::new();
 
-    // Sort directory paths by depth (deepest first)
-    let mut sorted_dirs: Vec<String> = dir_structure.keys().cloned().collect();
-    sorted_dirs.sort_by(|a, b| {
-        let depth_a = if a.is_empty() {
-            0
-        } else {
-            a.matches('/').count() + 1
-        };
-        let depth_b = if b.is_empty() {
+    // Group directory paths by depth
+    let depth = |path: &str| {
+        if path.is_empty() {
             0
         } else {
-            b.matches('/').count() + 1
-        };
-        depth_b.cmp(&depth_a) // Reverse order (deepest first)
-    });
-
-    // Build trees from leaves up
-    for dir_path in sorted_dirs {
-        let entries_map = dir_structure.get(&dir_path).unwrap();
-        let mut tree_entries = Vec::new();
-
-        for (name, (kind, id)) in entries_map {
-            let actual_id = if *kind == TreeEntryKind::Tree {
-                // Look up the actual tree ID for subdirectories
-                let subdir_path = if dir_path.is_empty() {
-                    name.clone()
-                } else {
-                    format!("{}/{}", dir_path, name)
-                };
-
-                tree_cache.get(&subdir_path).copied().unwrap_or(*id)
-            } else {
-                *id
-            };
-
-            tree_entries.push(TreeEntry {
-                name: name.clone(),
-                kind: *kind,
-                id: actual_id,
-            });
+            path.matches('/').count() + 1
         }
+    };
+    let mut levels: BTreeMap<usize, Vec<&String>> = BTreeMap::new();
+    for dir_path in dir_structure.keys() {
+        levels.entry(depth(dir_path)).or_default().push(dir_path);
+    }
 
-        let tree = Tree::new(tree_entries);
-        let tree_id = object_store.put_typed(&tree)?;
-        tree_cache.insert(dir_path, tree_id);
+    // Build trees from leaves up. The trees of one level only refer to
+    // deeper ones, so each level is stored in parallel.
+    for dirs in levels.into_values().rev() {
+        let trees: Vec<Tree> = dirs
+            .iter()
+            .map(|dir_path| build_tree(dir_path, &dir_structure[*dir_path], &tree_cache))
+            .collect();
+        let tree_ids = object_store.put_typed_parallel(&trees)?;
+        for (dir_path, tree_id) in dirs.into_iter().zip(tree_ids) {
+            tree_cache.insert(dir_path.clone(), tree_id);
+        }
     }
 
     tree_cache
@@ -252,6 +356,41 @@ fn build_tree_from_paths(
         })
 }
 
+/// Builds the tree for `dir_path` from its entries, taking subdirectory
+/// IDs from `tree_cache`.
+fn build_tree(
+    dir_path: &str,
+    entries_map: &BTreeMap<String, (crate::types::TreeEntryKind, ObjectId)>,
+    tree_cache: &BTreeMap<String, ObjectId>,
+) -> Tree {
+    use crate::types::{TreeEntry, TreeEntryKind};
+
+    let mut tree_entries = Vec::new();
+
+    for (name, (kind, id)) in entries_map {
+        let actual_id = if *kind == TreeEntryKind::Tree {
+            // Look up the actual tree ID for subdirectories
+            let subdir_path = if dir_path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", dir_path, name)
+            };
+
+            tree_cache.get(&subdir_path).copied().unwrap_or(*id)
+        } else {
+            *id
+        };
+
+        tree_entries.push(TreeEntry {
+            name: name.clone(),
+            kind: *kind,
+            id: actual_id,
+        });
+    }
+
+    Tree::new(tree_entries)
+}
+
 fn collect_observations_from_chain(
     chain: &[(ObjectId, WorkCommit)],
     _object_store: &ObjectStore,
@@ -267,6 +406,35 @@ fn collect_observations_from_chain(
     Ok(all_observations)
 }
 
+/// Maps each tag in the chain to the paths read or written in the steps
+/// that carry it. An actor's tag maps only to the paths its own
+/// observations read or wrote.
+fn collect_tags_from_chain(chain: &[(ObjectId, WorkCommit)]) -> BTreeMap<String, BTreeSet<String>> {
+    let mut tags: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    for (_id, work) in chain {
+        let Ok(observations) = decode_observations(&work.payload) else {
+            continue;
+        };
+        let paths: BTreeSet<&str> = observations.iter().filter_map(Observation::path).collect();
+        for observation in &observations {
+            let actor_tag = observation
+                .actor()
+                .map(|actor| format_tag(ACTOR_KEY, actor));
+            for tag in observation.tags() {
+                let tagged = tags.entry(tag.clone()).or_default();
+                if Some(&tag) == actor_tag.as_ref() {
+                    tagged.extend(observation.path().map(str::to_string));
+                } else {
+                    tagged.extend(paths.iter().map(|path| path.to_string()));
+                }
+            }
+        }
+    }
+
+    tags
+}
+
 fn collect_narrative_refs_from_chain(chain: &[(ObjectId, WorkCommit)]) -> Vec<NarrativeRef> {
     let mut all_refs = Vec::new();
 
@@ -277,7 +445,7 @@ fn collect_narrative_refs_from_chain(chain: &[(ObjectId, WorkCommit)]) -> Vec<Na
     all_refs
 }
 
-fn decode_observations(payload: &[u8]) -> Result<Vec<Observation>> {
+pub(crate) fn decode_observations(payload: &[u8]) -> Result<Vec<Observation>> {
     postcard::from_bytes(payload)
         .map_err(|e| CtxError::Deserialization(format!("Failed to decode observations: {}", e)))
 }
@@ -290,18 +458,23 @@ fn extract_edges_from_observations(
     observations: &[Observation],
     commit_id: ObjectId,
     created_at: u64,
+    known_files: &std::collections::BTreeSet<String>,
     object_store: &ObjectStore,
 ) -> Result<Vec<ObjectId>> {
     use crate::types::{
         Confidence, Edge, EdgeBatch, EdgeLabel, Evidence, EvidenceTool, NodeId, NodeKind,
     };
-    use std::collections::BTreeSet;
+    use std::collections::BTreeMap;
 
-    // Collect unique file paths that were written (using BTreeSet for determinism)
-    let mut written_files: BTreeSet<String> = BTreeSet::new();
+    // Collect unique file paths that were written (using BTreeMap for determinism,
+    // latest content wins)
+    let mut written_files: BTreeMap<String, ObjectId> = BTreeMap::new();
     for obs in observations {
-        if let Observation::FileWrite { path, .. } = obs {
-            written_files.insert(path.clone());
+        if let Observation::FileWrite {
+            path, content_id, ..
+        } = obs
+        {
+            written_files.insert(path.clone(), *content_id);
         }
     }
 
@@ -317,7 +490,7 @@ fn extract_edges_from_observations(
     // NodeKind::Commit doesn't exist yet. For Phase 5, we use self-references
     // with the commit tracked in the evidence.
     let mut edges = Vec::new();
-    for file_path in written_files {
+    for file_path in written_files.keys() {
         let file_node = NodeId {
             kind: NodeKind::File,
             id: file_path.clone(),
@@ -334,11 +507,12 @@ fn extract_edges_from_observations(
                 confidence: Confidence::High,
                 span: None,
                 blob_id: None,
+                condition: None,
             },
         });
     }
 
-    // Edges are already sorted (BTreeSet iteration is sorted)
+    // Edges are already sorted (BTreeMap iteration is sorted)
 
     // Create EdgeBatch
     // Note: We don't store which commit introduces this batch - that can be
@@ -348,8 +522,39 @@ fn extract_edges_from_observations(
 
     // Store EdgeBatch
     let edge_batch_id = object_store.put_typed(&edge_batch)?;
+    let mut batch_ids = vec![edge_batch_id];
+
+    // Heuristic module/import edges from the Rust sources written this session,
+    // kept in their own batch since they come from a different tool.
+    // Unreadable or non-UTF-8 blobs are skipped.
+    let mut heuristic_edges = Vec::new();
+    for (path, content_id) in &written_files {
+        if !path.ends_with(".rs") {
+            continue;
+        }
+        let Ok(bytes) = object_store.get_blob(*content_id) else {
+            continue;
+        };
+        let Ok(content) = std::str::from_utf8(&bytes) else {
+            continue;
+        };
+        heuristic_edges.extend(crate::heuristic::rust_source_edges(
+            path,
+            content,
+            *content_id,
+            commit_id,
+            known_files,
+        ));
+    }
+    if !heuristic_edges.is_empty() {
+        let batch = EdgeBatch {
+            edges: heuristic_edges,
+            created_at,
+        };
+        batch_ids.push(object_store.put_typed(&batch)?);
+    }
 
-    Ok(vec![edge_batch_id])
+    Ok(batch_ids)
 }
 
 #[cfg(test)]
@@ -376,6 +581,7 @@ mod tests {
             narrative_refs: vec![],
             session_state: SessionState::Running,
             task_description: "Test task".to_string(),
+            author: None,
         };
 
         store.put_typed(&work).unwrap()
@@ -395,6 +601,7 @@ mod tests {
             base,
             vec![Observation::Note {
                 content: "step 1".to_string(),
+                metadata: Default::default(),
             }],
         );
         let work2 = create_work_commit(
@@ -403,6 +610,7 @@ mod tests {
             base,
             vec![Observation::Note {
                 content: "step 2".to_string(),
+                metadata: Default::default(),
             }],
         );
         let work3 = create_work_commit(
@@ -411,6 +619,7 @@ mod tests {
             base,
             vec![Observation::Note {
                 content: "step 3".to_string(),
+                metadata: Default::default(),
             }],
         );
 
@@ -437,6 +646,7 @@ mod tests {
             vec![Observation::FileRead {
                 path: "test1.rs".to_string(),
                 content_id: None,
+                metadata: Default::default(),
             }],
         );
         let work2 = create_work_commit(
@@ -445,6 +655,7 @@ mod tests {
             base,
             vec![Observation::Note {
                 content: "note".to_string(),
+                metadata: Default::default(),
             }],
         );
 
@@ -474,7 +685,15 @@ mod tests {
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
         let base_id = store.put_typed(&base_commit).unwrap();
 
@@ -510,14 +729,17 @@ mod tests {
             Observation::FileWrite {
                 path: "src/main.rs".to_string(),
                 content_id: file1_content,
+                metadata: Default::default(),
             },
             Observation::FileWrite {
                 path: "src/lib.rs".to_string(),
                 content_id: file2_content,
+                metadata: Default::default(),
             },
             Observation::FileWrite {
                 path: "tests/test.rs".to_string(),
                 content_id: file3_content,
+                metadata: Default::default(),
             },
         ];
 
@@ -556,6 +778,31 @@ mod tests {
         assert_eq!(tests_tree.entries[0].id, file3_content);
     }
 
+    #[test]
+    fn test_lookup_tree_path() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+
+        let main_id = store.put_blob(b"fn main() {}").unwrap();
+        let mut files = HashMap::new();
+        files.insert("src/main.rs".to_string(), main_id);
+        let tree_id = build_tree_from_paths(&files, &store).unwrap();
+
+        assert_eq!(
+            lookup_tree_path(tree_id, "src/main.rs", &store).unwrap(),
+            Some(main_id)
+        );
+        assert!(lookup_tree_path(tree_id, "src", &store).unwrap().is_some());
+        assert_eq!(
+            lookup_tree_path(tree_id, "src/lib.rs", &store).unwrap(),
+            None
+        );
+        assert_eq!(
+            lookup_tree_path(tree_id, "src/main.rs/nested", &store).unwrap(),
+            None
+        );
+    }
+
     #[test]
     fn test_build_tree_latest_version_wins() {
         let tmp = TempDir::new().unwrap();
@@ -573,10 +820,12 @@ mod tests {
             Observation::FileWrite {
                 path: "test.rs".to_string(),
                 content_id: file1_v1,
+                metadata: Default::default(),
             },
             Observation::FileWrite {
                 path: "test.rs".to_string(),
-                content_id: file1_v2, // This should win
+                content_id: file1_v2, // This should win,
+                metadata: Default::default(),
             },
         ];
 
@@ -602,17 +851,21 @@ mod tests {
             Observation::FileWrite {
                 path: "src/main.rs".to_string(),
                 content_id: ObjectId::from_bytes([1; 32]),
+                metadata: Default::default(),
             },
             Observation::FileWrite {
                 path: "src/lib.rs".to_string(),
                 content_id: ObjectId::from_bytes([2; 32]),
+                metadata: Default::default(),
             },
             Observation::FileRead {
                 path: "README.md".to_string(),
                 content_id: None,
+                metadata: Default::default(),
             },
             Observation::Note {
                 content: "Updated source files".to_string(),
+                metadata: Default::default(),
             },
         ];
 
@@ -620,8 +873,14 @@ mod tests {
         let created_at = 1234567890;
 
         // Extract edges
-        let edge_batch_ids =
-            extract_edges_from_observations(&observations, commit_id, created_at, &store).unwrap();
+        let edge_batch_ids = extract_edges_from_observations(
+            &observations,
+            commit_id,
+            created_at,
+            &Default::default(),
+            &store,
+        )
+        .unwrap();
 
         // Should create one EdgeBatch
         assert_eq!(edge_batch_ids.len(), 1);
@@ -660,9 +919,11 @@ mod tests {
             Observation::FileRead {
                 path: "test.rs".to_string(),
                 content_id: None,
+                metadata: Default::default(),
             },
             Observation::Note {
                 content: "Just reading".to_string(),
+                metadata: Default::default(),
             },
         ];
 
@@ -670,8 +931,14 @@ mod tests {
         let created_at = 1234567890;
 
         // Extract edges
-        let edge_batch_ids =
-            extract_edges_from_observations(&observations, commit_id, created_at, &store).unwrap();
+        let edge_batch_ids = extract_edges_from_observations(
+            &observations,
+            commit_id,
+            created_at,
+            &Default::default(),
+            &store,
+        )
+        .unwrap();
 
         // Should create no EdgeBatches (no file writes)
         assert_eq!(edge_batch_ids.len(), 0);
@@ -691,9 +958,11 @@ mod tests {
             Observation::FileRead {
                 path: "test.rs".to_string(),
                 content_id: None,
+                metadata: Default::default(),
             },
             Observation::Note {
                 content: "Just a note".to_string(),
+                metadata: Default::default(),
             },
         ];
 
@@ -0,0 +1,127 @@
+//! Repository status summary.
+//!
+//! [`crate::CtxRepo::status`] gathers the state a user needs to diagnose a
+//! repository at a glance: where HEAD is, whether a session is in flight,
+//! how far the index lags behind HEAD, whether the Cargo snapshot predates
+//! manifest edits, and how much garbage collection would reclaim.
+
+use crate::config::StaleSessionStatus;
+use crate::object_id::ObjectId;
+use crate::session_summary::ActorActivity;
+use crate::types::SessionState;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Summary of repository state returned by [`crate::CtxRepo::status`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusReport {
+    /// The commit HEAD points to.
+    pub head: HeadStatus,
+    /// The active session, if one exists.
+    pub session: Option<SessionStatus>,
+    /// How current the index is.
+    pub index: IndexFreshness,
+    /// Whether HEAD's Cargo snapshot matches the manifests on disk, if HEAD
+    /// has one.
+    pub cargo: Option<CargoStatus>,
+    /// Object store counts and the pending GC estimate.
+    pub objects: ObjectStatus,
+    /// The repository LOCK, if one exists.
+    pub lock: Option<LockStatus>,
+}
+
+/// The commit HEAD points to.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadStatus {
+    /// Commit ID.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub id: ObjectId,
+    /// Commit message.
+    pub message: String,
+    /// Commit timestamp (Unix seconds).
+    pub timestamp_unix: u64,
+    /// Seconds since the commit was made.
+    pub age_secs: u64,
+}
+
+/// The active session, as returned by [`crate::CtxRepo::session_status`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStatus {
+    /// Session ID.
+    pub session_id: String,
+    /// Task description.
+    pub task: String,
+    /// Current session state.
+    pub state: SessionState,
+    /// Steps flushed so far.
+    pub steps: u32,
+    /// Number of work commits between the base commit and STAGE.
+    pub staging_chain_len: usize,
+    /// Staleness under the configured thresholds.
+    pub staleness: StaleSessionStatus,
+    /// What each named sub-agent has done so far, including unflushed
+    /// observations, by actor ID.
+    pub actors: BTreeMap<String, ActorActivity>,
+}
+
+/// How current the index is relative to HEAD.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum IndexFreshness {
+    /// No index exists yet; it is built on first use.
+    Missing,
+    /// Every commit reachable from HEAD is indexed.
+    Current,
+    /// Some commits reachable from HEAD are not indexed.
+    Behind {
+        /// Number of unindexed commits.
+        commits: usize,
+    },
+    /// The index exists but could not be opened.
+    Unreadable {
+        /// Why opening failed.
+        reason: String,
+    },
+}
+
+/// Freshness of HEAD's Cargo snapshot, as returned by
+/// [`crate::CtxRepo::cargo_status`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CargoStatus {
+    /// The snapshot HEAD points to.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub snapshot_id: ObjectId,
+    /// True if manifests changed since the snapshot was taken.
+    pub stale: bool,
+    /// `Cargo.toml` and `Cargo.lock` files that changed, relative to the
+    /// workspace root.
+    pub changed_manifests: Vec<String>,
+}
+
+/// The repository LOCK file, as returned by [`crate::CtxRepo::lock_status`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LockStatus {
+    /// PID recorded in the lock, if it could be read.
+    pub pid: Option<u32>,
+    /// Seconds since the lock was taken.
+    pub age_secs: Option<u64>,
+    /// Whether the recorded process is still running.
+    pub holder_alive: bool,
+    /// Whether the lock belongs to this process.
+    pub owned: bool,
+}
+
+/// Object store counts and the pending GC estimate.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ObjectStatus {
+    /// Total number of stored objects.
+    pub total: usize,
+    /// Total size of stored objects on disk.
+    pub total_bytes: u64,
+    /// Objects reachable from HEAD, STAGE, or refs.
+    pub reachable: usize,
+    /// Objects the next GC would delete under the configured grace period.
+    pub collectable: usize,
+    /// Bytes the next GC would free.
+    pub collectable_bytes: u64,
+}
@@ -0,0 +1,269 @@
+//! Cached summaries of file contents.
+//!
+//! A [`Summary`] describes one blob in a few lines. Summaries are stored as
+//! typed objects and listed in a [`SummaryTable`], keyed by the blob they
+//! describe, which the `SUMMARIES` ref points to. Because the key is the
+//! content ID, a summary stays valid until the file changes and is shared
+//! by every path with the same content. When a pack's budget is tight,
+//! [`build_pack`](crate::CtxRepo::build_pack) uses summaries in place of
+//! low-relevance files that don't fit.
+//!
+//! Summaries come from a [`Summarizer`]. [`HeuristicSummarizer`] extracts
+//! doc comments and top-level declarations without any model;
+//! [`CommandSummarizer`] pipes the file to an external command, which is
+//! how a model is plugged in from configuration.
+
+use crate::error::{CtxError, Result};
+use crate::policy::ExecPolicy;
+use crate::ObjectId;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// A short description of one blob.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The blob this summarizes.
+    pub blob_id: ObjectId,
+    /// The summary text.
+    pub text: String,
+    /// Name of the [`Summarizer`] that wrote it.
+    pub summarizer: String,
+    /// Creation time (Unix seconds).
+    pub created_at: u64,
+}
+
+/// Every cached summary, by the ID of the blob it describes.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SummaryTable {
+    /// Blob ID to the ID of its [`Summary`] object.
+    pub entries: BTreeMap<ObjectId, ObjectId>,
+}
+
+impl SummaryTable {
+    /// Returns the summary object ID for `blob_id`, if one is cached.
+    pub fn get(&self, blob_id: ObjectId) -> Option<ObjectId> {
+        self.entries.get(&blob_id).copied()
+    }
+
+    /// Number of cached summaries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no summaries are cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Produces summaries of file contents.
+///
+/// Implement this to summarize with your own model and pass it to
+/// [`CtxRepo::summarize`](crate::CtxRepo::summarize).
+pub trait Summarizer {
+    /// Short identifier recorded with each summary, such as a model name.
+    fn name(&self) -> &str;
+
+    /// Summarizes `content`, the current text of `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no summary could be produced; the file is then
+    /// skipped.
+    fn summarize(&self, path: &str, content: &str) -> Result<String>;
+}
+
+/// Summarizes from the text itself: the leading doc comment followed by
+/// top-level declarations.
+#[derive(Debug, Clone)]
+pub struct HeuristicSummarizer {
+    /// Most lines in a summary.
+    pub max_lines: usize,
+}
+
+impl Default for HeuristicSummarizer {
+    fn default() -> Self {
+        Self { max_lines: 12 }
+    }
+}
+
+/// Matches unindented declarations in common languages.
+fn declaration_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^(?:pub(?:\([^)]*\))?\s+|export\s+(?:default\s+)?)?(?:async\s+|unsafe\s+)*(?:fn|struct|enum|trait|type|union|mod|impl|class|def|interface|function)\b",
+        )
+        .expect("declaration regex is valid")
+    })
+}
+
+impl Summarizer for HeuristicSummarizer {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn summarize(&self, _path: &str, content: &str) -> Result<String> {
+        let mut lines: Vec<String> = Vec::new();
+
+        // The first paragraph of a leading `//!` or `#` comment
+        for line in content.lines().map(str::trim) {
+            let doc = line
+                .strip_prefix("//!")
+                .or_else(|| line.strip_prefix("# "))
+                .map(str::trim);
+            match doc {
+                Some("") if !lines.is_empty() => break,
+                Some("") => {}
+                Some(text) => lines.push(text.to_string()),
+                None if line.is_empty() && lines.is_empty() => {}
+                None => break,
+            }
+        }
+
+        let declarations: Vec<&str> = content
+            .lines()
+            .filter(|line| declaration_regex().is_match(line))
+            .map(|line| {
+                // Drop the body: `fn f() {}` becomes `fn f()`
+                let head = line.split('{').next().unwrap_or(line);
+                head.trim_end().trim_end_matches(':').trim_end()
+            })
+            .collect();
+        let total = declarations.len();
+        let room = self.max_lines.saturating_sub(lines.len()).max(1);
+        lines.extend(declarations.iter().take(room).map(|d| d.to_string()));
+        if total > room {
+            lines.push(format!("... and {} more declarations", total - room));
+        }
+
+        if lines.is_empty() {
+            lines.extend(
+                content
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .take(self.max_lines.min(3))
+                    .map(str::to_string),
+            );
+        }
+        lines.push(format!("({} lines)", content.lines().count()));
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Summarizes by running an external command, such as a model CLI.
+///
+/// The file content is written to the command's stdin and its trimmed
+/// stdout is the summary. `CTX_SUMMARY_PATH` holds the file's path. The
+/// command runs through the repository's [`ExecPolicy`].
+#[derive(Debug, Clone)]
+pub struct CommandSummarizer {
+    command: Vec<String>,
+    policy: ExecPolicy,
+}
+
+impl CommandSummarizer {
+    /// Creates a summarizer running `command` (program, then arguments).
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `command` is empty.
+    pub fn new(command: Vec<String>, policy: ExecPolicy) -> Result<Self> {
+        if command.is_empty() {
+            return Err(CtxError::InvalidArgument(
+                "summarizer command is empty".to_string(),
+            ));
+        }
+        Ok(Self { command, policy })
+    }
+}
+
+impl Summarizer for CommandSummarizer {
+    fn name(&self) -> &str {
+        &self.command[0]
+    }
+
+    fn summarize(&self, path: &str, content: &str) -> Result<String> {
+        let output = self.policy.output_with_input(
+            Command::new(&self.command[0])
+                .args(&self.command[1..])
+                .env("CTX_SUMMARY_PATH", path),
+            content.as_bytes(),
+        )?;
+
+        if !output.status.success() {
+            return Err(CtxError::InvalidArgument(format!(
+                "summarizer '{}' failed for {}: {}",
+                self.name(),
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let summary = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if summary.is_empty() {
+            return Err(CtxError::InvalidArgument(format!(
+                "summarizer '{}' returned nothing for {}",
+                self.name(),
+                path
+            )));
+        }
+        Ok(summary)
+    }
+}
+
+/// Outcome of [`CtxRepo::summarize`](crate::CtxRepo::summarize).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SummarizeReport {
+    /// Paths summarized by this run.
+    pub summarized: Vec<String>,
+    /// Paths whose current content already had a summary.
+    pub cached: Vec<String>,
+    /// Paths that couldn't be summarized, with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heuristic_summary_keeps_docs_and_declarations() {
+        let source = "//! Parses config files.\n//!\n//! Details follow.\n\nuse std::fs;\n\npub struct Config {\n    x: u32,\n}\n\nfn helper() {}\n\nimpl Config {\n    pub fn load() {}\n}\n";
+        let summary = HeuristicSummarizer::default()
+            .summarize("src/config.rs", source)
+            .unwrap();
+        assert_eq!(
+            summary,
+            "Parses config files.\npub struct Config\nfn helper()\nimpl Config\n(15 lines)"
+        );
+
+        let tight = HeuristicSummarizer { max_lines: 2 };
+        let summary = tight.summarize("src/config.rs", source).unwrap();
+        assert!(summary.contains("... and 2 more declarations"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_summarizer_pipes_content() {
+        let summarizer = CommandSummarizer::new(
+            vec![
+                "sh".into(),
+                "-c".into(),
+                "echo \"$CTX_SUMMARY_PATH: $(wc -l)\"".into(),
+            ],
+            ExecPolicy::default(),
+        )
+        .unwrap();
+        let summary = summarizer.summarize("a.txt", "one\ntwo\n").unwrap();
+        assert_eq!(
+            summary.split_whitespace().collect::<Vec<_>>(),
+            ["a.txt:", "2"]
+        );
+
+        let failing = CommandSummarizer::new(vec!["false".into()], ExecPolicy::default()).unwrap();
+        assert!(failing.summarize("a.txt", "x").is_err());
+    }
+}
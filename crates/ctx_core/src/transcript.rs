@@ -0,0 +1,168 @@
+//! Raw conversation turns of a session.
+//!
+//! Agents can record the prompts and responses of a session with
+//! [`CtxRepo::observe_message`](crate::CtxRepo::observe_message). Each turn
+//! is a `Message` observation whose content is stored as a blob. What
+//! happens to them is a privacy choice, set by `session.transcripts`:
+//!
+//! - `off`: messages aren't recorded at all.
+//! - `session`: messages are staged with the session but dropped when it
+//!   is compacted, and garbage collected with its steps.
+//! - `keep` (the default): compaction collects them into a [`Transcript`]
+//!   referenced from the session's commit, so the conversation can be read
+//!   back later with [`CtxRepo::transcript`](crate::CtxRepo::transcript).
+
+use crate::error::{CtxError, Result};
+use crate::types::Observation;
+use crate::{ObjectId, ObjectStore};
+use serde::{Deserialize, Serialize};
+
+/// How long recorded conversation turns are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptRetention {
+    /// Don't record messages.
+    Off,
+    /// Keep messages only while the session is staged.
+    Session,
+    /// Keep messages in the session's commit.
+    #[default]
+    Keep,
+}
+
+/// The conversation of a compacted session.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    /// The session's task.
+    pub task: String,
+    /// The session's ID.
+    pub session_id: String,
+    /// The turns, in order.
+    pub messages: Vec<TranscriptMessage>,
+}
+
+/// One recorded turn.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptMessage {
+    /// Who spoke, e.g. `user`, `assistant`, `system` or `tool`.
+    pub role: String,
+    /// Blob holding the message text.
+    pub content_id: ObjectId,
+}
+
+/// A turn with its text loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Turn {
+    /// Who spoke.
+    pub role: String,
+    /// What was said.
+    pub content: String,
+}
+
+impl Transcript {
+    /// Collects the `Message` observations of a session, or None if there
+    /// are none.
+    pub(crate) fn from_observations(
+        task: &str,
+        session_id: &str,
+        observations: &[Observation],
+    ) -> Option<Self> {
+        let messages: Vec<TranscriptMessage> = observations
+            .iter()
+            .filter_map(|observation| match observation {
+                Observation::Message {
+                    role, content_id, ..
+                } => Some(TranscriptMessage {
+                    role: role.clone(),
+                    content_id: *content_id,
+                }),
+                _ => None,
+            })
+            .collect();
+        if messages.is_empty() {
+            return None;
+        }
+        Some(Self {
+            task: task.to_string(),
+            session_id: session_id.to_string(),
+            messages,
+        })
+    }
+
+    /// Loads the text of every turn.
+    pub fn turns(&self, object_store: &ObjectStore) -> Result<Vec<Turn>> {
+        self.messages
+            .iter()
+            .map(|message| {
+                let bytes = object_store.get_blob(message.content_id)?;
+                Ok(Turn {
+                    role: message.role.clone(),
+                    content: String::from_utf8_lossy(&bytes).into_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Normalizes a message role: trimmed and lowercased.
+///
+/// # Errors
+///
+/// Returns [`CtxError::InvalidArgument`] for an empty role or one with
+/// whitespace inside.
+pub(crate) fn normalize_role(role: &str) -> Result<String> {
+    let role = role.trim();
+    if role.is_empty() || role.contains(char::is_whitespace) {
+        return Err(CtxError::InvalidArgument(format!(
+            "invalid message role '{}': expected a single word such as user or assistant",
+            role
+        )));
+    }
+    Ok(role.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Metadata;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_transcript_from_observations() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let question = store.put_blob(b"Why is the index slow?").unwrap();
+        let answer = store.put_blob(b"It rebuilds on every query.").unwrap();
+        let observations = vec![
+            Observation::Message {
+                role: "user".to_string(),
+                content_id: question,
+                metadata: Metadata::new(),
+            },
+            Observation::Note {
+                content: "Looked at index.rs".to_string(),
+                metadata: Metadata::new(),
+            },
+            Observation::Message {
+                role: "assistant".to_string(),
+                content_id: answer,
+                metadata: Metadata::new(),
+            },
+        ];
+
+        let transcript = Transcript::from_observations("Speed up", "s1", &observations).unwrap();
+        let turns = transcript.turns(&store).unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].role, "user");
+        assert_eq!(turns[1].content, "It rebuilds on every query.");
+
+        assert!(Transcript::from_observations("Speed up", "s1", &observations[1..2]).is_none());
+    }
+
+    #[test]
+    fn test_normalize_role() {
+        assert_eq!(normalize_role(" Assistant ").unwrap(), "assistant");
+        assert!(normalize_role("").is_err());
+        assert!(normalize_role("tool call").is_err());
+    }
+}
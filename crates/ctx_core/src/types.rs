@@ -2,6 +2,24 @@
 
 use crate::ObjectId;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Key/value metadata attached to an observation, such as the tool that
+/// produced it, a confidence, or a ticket id.
+pub type Metadata = BTreeMap<String, String>;
+
+/// Metadata key holding how many times a collapsed observation was made
+/// within its step. Absent means once.
+pub const OBSERVED_COUNT_KEY: &str = "ctx.count";
+
+/// Metadata key holding when a collapsed observation was last made (Unix
+/// timestamp).
+pub const LAST_SEEN_KEY: &str = "ctx.last_seen";
+
+/// Metadata key naming the sub-agent, such as `planner` or `reviewer`, that
+/// made an observation within its session. Its tag, `actor:<id>`, applies
+/// to the observation's own path rather than the whole step.
+pub const ACTOR_KEY: &str = "actor";
 
 /// Session state machine states.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -39,6 +57,51 @@ pub enum SessionState {
     },
 }
 
+impl SessionState {
+    /// Returns true if a session in this state may move to `next`.
+    ///
+    /// Allowed transitions:
+    ///
+    /// | From              | To                                                  |
+    /// |-------------------|-----------------------------------------------------|
+    /// | `Running`         | `AwaitingUser`, `Interrupted`, `PendingComplete`, `Aborted` |
+    /// | `AwaitingUser`    | `Running`, `Aborted`                                |
+    /// | `Interrupted`     | `Running`                                           |
+    /// | `PendingComplete` | `Complete`, `Running`, `Aborted`                    |
+    /// | `Complete`        | (terminal)                                          |
+    /// | `Aborted`         | (terminal)                                          |
+    pub fn can_transition_to(&self, next: &SessionState) -> bool {
+        use SessionState::*;
+
+        match (self, next) {
+            // From Running
+            (Running, AwaitingUser { .. })
+            | (Running, Interrupted { .. })
+            | (Running, PendingComplete { .. })
+            | (Running, Aborted { .. }) => true,
+
+            // From AwaitingUser
+            (AwaitingUser { .. }, Running) | (AwaitingUser { .. }, Aborted { .. }) => true,
+
+            // From Interrupted
+            (Interrupted { .. }, Running) => true,
+
+            // From PendingComplete
+            (PendingComplete { .. }, Complete)
+            | (PendingComplete { .. }, Running)
+            | (PendingComplete { .. }, Aborted { .. }) => true,
+
+            // All other transitions are invalid
+            _ => false,
+        }
+    }
+
+    /// Returns true for states no transition leaves (`Complete`, `Aborted`).
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, SessionState::Complete | SessionState::Aborted { .. })
+    }
+}
+
 /// How a commit was created (for distinguishing normal vs auto-compacted).
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CommitType {
@@ -61,7 +124,118 @@ pub enum CommitType {
     },
 }
 
+/// Identity of whoever produced a commit or work step.
+///
+/// Humans are recorded with a name only; agents additionally record the model
+/// they ran on, their own version, and a run identifier that groups every
+/// commit produced by one agent invocation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentIdentity {
+    /// Display name (user name or agent name).
+    pub name: String,
+    /// Model the agent ran on (None for humans).
+    pub model: Option<String>,
+    /// Agent version string.
+    pub version: Option<String>,
+    /// Identifier of the agent run.
+    pub run_id: Option<String>,
+}
+
+impl AgentIdentity {
+    /// Creates an identity for a human author.
+    pub fn human(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: None,
+            version: None,
+            run_id: None,
+        }
+    }
+
+    /// Creates an identity for an agent running on `model`.
+    pub fn agent(name: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: Some(model.into()),
+            version: None,
+            run_id: None,
+        }
+    }
+
+    /// Builds an identity from the environment.
+    ///
+    /// Reads `CTX_AGENT_NAME`, `CTX_AGENT_MODEL`, `CTX_AGENT_VERSION` and
+    /// `CTX_RUN_ID`. If no agent name is set, falls back to a human identity
+    /// named after `USER` (or `USERNAME` on Windows).
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        match var("CTX_AGENT_NAME") {
+            Some(name) => Self {
+                name,
+                model: var("CTX_AGENT_MODEL"),
+                version: var("CTX_AGENT_VERSION"),
+                run_id: var("CTX_RUN_ID"),
+            },
+            None => Self::human(
+                var("USER")
+                    .or_else(|| var("USERNAME"))
+                    .unwrap_or_else(|| "user".to_string()),
+            ),
+        }
+    }
+
+    /// Returns true if this identity describes a human (no model recorded).
+    pub fn is_human(&self) -> bool {
+        self.model.is_none()
+    }
+}
+
+impl std::fmt::Display for AgentIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        match (&self.model, &self.version) {
+            (Some(model), Some(version)) => write!(f, " ({}, v{})", model, version)?,
+            (Some(model), None) => write!(f, " ({})", model)?,
+            (None, Some(version)) => write!(f, " (v{})", version)?,
+            (None, None) => {}
+        }
+        if let Some(run_id) = &self.run_id {
+            write!(f, " [run {}]", run_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// What an [`Observation`] records, without its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObservationKind {
+    /// A file read.
+    FileRead,
+    /// A file write.
+    FileWrite,
+    /// A command run.
+    Command,
+    /// A note.
+    Note,
+    /// A plan.
+    Plan,
+    /// A decision.
+    Decision,
+    /// A fact.
+    Fact,
+    /// An answered question.
+    Qa,
+    /// A conversation turn.
+    Message,
+}
+
 /// Single observation during a session.
+///
+/// Every variant carries [`Metadata`]. Each entry is also a tag,
+/// `key:value` or just `key` for an empty value, that applies to the
+/// whole step the observation is flushed in; see [`Commit::tags`].
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Observation {
     /// File was read by the agent.
@@ -70,6 +244,9 @@ pub enum Observation {
         path: String,
         /// Optional content blob ID.
         content_id: Option<ObjectId>,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
     },
 
     /// File was written by the agent.
@@ -78,6 +255,9 @@ pub enum Observation {
         path: String,
         /// Content blob ID.
         content_id: ObjectId,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
     },
 
     /// Command was executed.
@@ -88,19 +268,165 @@ pub enum Observation {
         exit_code: Option<i32>,
         /// Output blob ID (if captured).
         output_id: Option<ObjectId>,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
     },
 
     /// Agent made a note.
     Note {
         /// Note content.
         content: String,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
     },
 
     /// Agent created a plan.
     Plan {
         /// Plan content.
         content: String,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
     },
+
+    /// A decision was recorded.
+    Decision {
+        /// ID of the stored [`Decision`](crate::Decision).
+        decision_id: ObjectId,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
+    },
+
+    /// Agent stated a fact.
+    Fact {
+        /// ID of the stored [`Fact`](crate::Fact).
+        fact_id: ObjectId,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
+    },
+
+    /// User answered a question.
+    Qa {
+        /// ID of the stored [`QaPair`](crate::QaPair).
+        qa_id: ObjectId,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
+    },
+    /// A conversation turn was recorded.
+    Message {
+        /// Who spoke, e.g. `user` or `assistant`.
+        role: String,
+        /// Blob holding the message text.
+        content_id: ObjectId,
+        /// Key/value metadata.
+        #[serde(default)]
+        metadata: Metadata,
+    },
+}
+
+impl Observation {
+    /// The observation's metadata.
+    pub fn metadata(&self) -> &Metadata {
+        match self {
+            Observation::FileRead { metadata, .. }
+            | Observation::FileWrite { metadata, .. }
+            | Observation::Command { metadata, .. }
+            | Observation::Note { metadata, .. }
+            | Observation::Plan { metadata, .. }
+            | Observation::Decision { metadata, .. }
+            | Observation::Fact { metadata, .. }
+            | Observation::Qa { metadata, .. }
+            | Observation::Message { metadata, .. } => metadata,
+        }
+    }
+
+    /// Mutable access to the observation's metadata.
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        match self {
+            Observation::FileRead { metadata, .. }
+            | Observation::FileWrite { metadata, .. }
+            | Observation::Command { metadata, .. }
+            | Observation::Note { metadata, .. }
+            | Observation::Plan { metadata, .. }
+            | Observation::Decision { metadata, .. }
+            | Observation::Fact { metadata, .. }
+            | Observation::Qa { metadata, .. }
+            | Observation::Message { metadata, .. } => metadata,
+        }
+    }
+
+    /// Returns the observation with `metadata` added to its own.
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata_mut().extend(metadata);
+        self
+    }
+
+    /// What the observation records.
+    pub fn kind(&self) -> ObservationKind {
+        match self {
+            Observation::FileRead { .. } => ObservationKind::FileRead,
+            Observation::FileWrite { .. } => ObservationKind::FileWrite,
+            Observation::Command { .. } => ObservationKind::Command,
+            Observation::Note { .. } => ObservationKind::Note,
+            Observation::Plan { .. } => ObservationKind::Plan,
+            Observation::Decision { .. } => ObservationKind::Decision,
+            Observation::Fact { .. } => ObservationKind::Fact,
+            Observation::Qa { .. } => ObservationKind::Qa,
+            Observation::Message { .. } => ObservationKind::Message,
+        }
+    }
+
+    /// The observation's metadata as tags, without the repeat counters.
+    pub fn tags(&self) -> impl Iterator<Item = String> + '_ {
+        self.metadata()
+            .iter()
+            .filter(|(key, _)| *key != OBSERVED_COUNT_KEY && *key != LAST_SEEN_KEY)
+            .map(|(key, value)| format_tag(key, value))
+    }
+
+    /// The sub-agent that made the observation, if one was named.
+    pub fn actor(&self) -> Option<&str> {
+        self.metadata().get(ACTOR_KEY).map(String::as_str)
+    }
+
+    /// How many times the observation was made within its step.
+    pub fn count(&self) -> u32 {
+        self.metadata()
+            .get(OBSERVED_COUNT_KEY)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(1)
+    }
+
+    /// The path of the file read or written, if any.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Observation::FileRead { path, .. } | Observation::FileWrite { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Formats a metadata entry as a tag: `key:value`, or `key` if `value` is
+/// empty.
+pub fn format_tag(key: &str, value: &str) -> String {
+    if value.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}:{}", key, value)
+    }
+}
+
+/// Parses a tag into a metadata entry, splitting at the first `:`.
+pub fn parse_tag(tag: &str) -> (String, String) {
+    match tag.split_once(':') {
+        Some((key, value)) => (key.to_string(), value.to_string()),
+        None => (tag.to_string(), String::new()),
+    }
 }
 
 /// Source location within a file.
@@ -206,6 +532,16 @@ pub enum NodeKind {
     Decision = 9,
     /// Diagnostic message.
     Diagnostic = 10,
+    /// Glossary term.
+    Term = 11,
+    /// Recorded fact.
+    Fact = 12,
+    /// Answered question.
+    Qa = 13,
+    /// Narrative document of a configured type.
+    Document = 14,
+    /// Compacted session, identified by the commit it was compacted into.
+    Session = 15,
 }
 
 /// Type of edge relationship.
@@ -219,6 +555,12 @@ pub enum EdgeLabel {
     Defines = 2,
     /// Version relationship.
     HasVersion = 3,
+    /// Module declaration (`mod foo;`) linking a file to the module's file.
+    DeclaresModule = 4,
+    /// File moved to a new path (old path → new path).
+    RenamedTo = 5,
+    /// Module nested in another (child module → parent module).
+    ChildOf = 6,
 
     // Dependencies (10-19)
     /// Package/crate dependency.
@@ -298,6 +640,9 @@ pub struct Evidence {
     pub span: Option<Span>,
     /// Related blob (if applicable).
     pub blob_id: Option<ObjectId>,
+    /// `cfg` predicate the edge only exists under, such as
+    /// `feature = "tokio"` (None if unconditional).
+    pub condition: Option<String>,
 }
 
 /// Edge in the knowledge graph.
@@ -320,7 +665,7 @@ pub struct Edge {
 /// Note: To find which commit introduced this EdgeBatch, query commits
 /// to see which one references this EdgeBatch's ObjectId. This avoids
 /// self-reference issues in content-addressed storage.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EdgeBatch {
     /// Edges in this batch.
     pub edges: Vec<Edge>,
@@ -329,7 +674,10 @@ pub struct EdgeBatch {
 }
 
 /// Canonical commit representing a stable checkpoint.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+///
+/// Stored behind a layout tag, so commits written before a field was
+/// added still decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Commit {
     /// Parent commit IDs.
     pub parents: Vec<ObjectId>,
@@ -354,8 +702,26 @@ pub struct Commit {
     pub rust_snapshot: Option<ObjectId>,
     /// Diagnostic snapshot (if applicable).
     pub diagnostics_snapshot: Option<ObjectId>,
+    /// Project glossary snapshot (if any terms have been defined).
+    pub glossary: Option<ObjectId>,
+    /// Log of recorded decisions (if any have been recorded).
+    pub decisions: Option<ObjectId>,
+    /// Set of valid facts (if any have been observed).
+    pub facts: Option<ObjectId>,
+    /// Log of answered questions (if any have been answered).
+    pub qa: Option<ObjectId>,
+    /// Conversation of the session compacted into this commit, if it was
+    /// kept (see [`Transcript`](crate::Transcript)).
+    pub transcript: Option<ObjectId>,
     /// How this commit was created (None for legacy commits).
     pub commit_type: Option<CommitType>,
+    /// Who created this commit (None for legacy or unattributed commits).
+    pub author: Option<AgentIdentity>,
+    /// Task description of the session compacted into this commit (None for non-session commits).
+    pub task: Option<String>,
+    /// Tags of the session's steps, each mapped to the paths read or
+    /// written in the steps that carry it (empty for non-session commits).
+    pub tags: BTreeMap<String, BTreeSet<String>>,
 }
 
 /// Type of work step.
@@ -379,7 +745,7 @@ pub enum StepKind {
 }
 
 /// Work commit in staging area.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WorkCommit {
     /// Parent work commits.
     pub parents: Vec<ObjectId>,
@@ -399,6 +765,8 @@ pub struct WorkCommit {
     pub session_state: SessionState,
     /// Task description for the session.
     pub task_description: String,
+    /// Who performed this step (None if unattributed).
+    pub author: Option<AgentIdentity>,
 }
 
 #[cfg(test)]
@@ -474,7 +842,15 @@ mod tests {
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
 
         let id = store.put_typed(&commit).unwrap();
@@ -506,6 +882,7 @@ mod tests {
                     confidence: Confidence::High,
                     span: None,
                     blob_id: None,
+                    condition: None,
                 },
             }],
             created_at: 1234567890,
@@ -532,6 +909,7 @@ mod tests {
             narrative_refs: vec![],
             session_state: SessionState::Running,
             task_description: "Test task".to_string(),
+            author: None,
         };
 
         let id = store.put_typed(&work_commit).unwrap();
@@ -556,6 +934,11 @@ mod tests {
             NodeKind::Note,
             NodeKind::Decision,
             NodeKind::Diagnostic,
+            NodeKind::Term,
+            NodeKind::Fact,
+            NodeKind::Qa,
+            NodeKind::Document,
+            NodeKind::Session,
         ];
 
         for kind in kinds {
@@ -1,13 +1,31 @@
 //! Repository verification and recovery tools.
 //!
 //! Provides functions to verify repository integrity and recover from corruption.
+//!
+//! Checking every object is slow on a large store, so the object check can
+//! be narrowed two ways. A [`VerifySample`] checks a percentage of the
+//! objects, a different slice each day, so a daily run covers the whole
+//! store over a cycle of days. A cursor ([`VerifyConfig::resume_after`] and
+//! [`VerifyConfig::max_objects`]) checks objects in ID order a batch at a
+//! time, picking up where the previous batch stopped.
+//!
+//! [`recover_staging_summary`] repairs a staging chain broken by a crash and
+//! reports what became of each work commit in a [`RecoverySummary`].
 
 use crate::error::{CtxError, Result};
-use crate::object_id::ObjectId;
-use crate::object_store::ObjectStore;
-use crate::refs::Refs;
-use crate::types::Commit;
-use std::collections::{HashSet, VecDeque};
+use crate::lsp::RustSnapshot;
+use crate::object_id::{ObjectId, ObjectKind};
+use crate::object_store::{io_error, ObjectStore};
+use crate::refs::{RefUpdate, Refs};
+use crate::types::{Commit, StepKind, WorkCommit};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File in the .ctx directory holding the cursor of
+/// [`CtxRepo::verify_incremental`](crate::CtxRepo::verify_incremental).
+pub(crate) const VERIFY_CURSOR_FILE: &str = "verify.cursor";
 
 /// Configuration for repository verification.
 #[derive(Debug, Clone)]
@@ -23,6 +41,16 @@ pub struct VerifyConfig {
 
     /// Print verbose output during verification.
     pub verbose: bool,
+
+    /// Check only the objects in this day's sample (None checks all).
+    pub sample: Option<VerifySample>,
+
+    /// Check only objects whose ID sorts after this one, continuing from
+    /// a previous report's [`VerifyReport::next_cursor`].
+    pub resume_after: Option<ObjectId>,
+
+    /// Stop after checking this many objects (None checks to the end).
+    pub max_objects: Option<usize>,
 }
 
 impl Default for VerifyConfig {
@@ -32,17 +60,56 @@ impl Default for VerifyConfig {
             check_refs: true,
             check_commits: true,
             verbose: false,
+            sample: None,
+            resume_after: None,
+            max_objects: None,
         }
     }
 }
 
+/// A day's share of the objects for a sampled object check.
+///
+/// Each object falls in one of 100 slots by its ID, and a sample covers
+/// `percent` consecutive slots starting where the previous day's sample
+/// ended, so consecutive days check different objects and every object is
+/// checked within `ceil(100 / percent)` days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifySample {
+    /// Share of the objects to check, from 1 to 100.
+    pub percent: u32,
+    /// Day the sample is for, in days since the Unix epoch.
+    pub day: u64,
+}
+
+impl VerifySample {
+    /// Today's sample of `percent` of the objects.
+    pub fn today(percent: u32) -> Self {
+        let day = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() / 86_400)
+            .unwrap_or(0);
+        Self { percent, day }
+    }
+
+    /// Whether `id` is in this sample.
+    pub fn contains(&self, id: ObjectId) -> bool {
+        let percent = u64::from(self.percent.clamp(1, 100));
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&id.as_bytes()[..8]);
+        let slot = u64::from_le_bytes(prefix) % 100;
+        let start = self.day.wrapping_mul(percent) % 100;
+        (slot + 100 - start) % 100 < percent
+    }
+}
+
 /// Report from repository verification.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct VerifyReport {
     /// Total number of objects checked.
     pub objects_checked: usize,
 
     /// List of corrupted objects.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_vec")]
     pub objects_corrupted: Vec<ObjectId>,
 
     /// Number of refs checked.
@@ -55,7 +122,18 @@ pub struct VerifyReport {
     pub commits_checked: usize,
 
     /// List of invalid commits (missing parents, etc.).
+    #[serde(serialize_with = "crate::object_id::serialize_hex_vec")]
     pub commits_invalid: Vec<ObjectId>,
+
+    /// Files in HEAD's Rust analysis whose analyzed content is missing or
+    /// no longer HEAD's (run `ctx analyze rust` to refresh).
+    pub rust_snapshot_drift: Vec<String>,
+
+    /// The last object checked, if [`VerifyConfig::max_objects`] stopped
+    /// the object check before the end of the store. Pass it as
+    /// [`VerifyConfig::resume_after`] to continue.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub next_cursor: Option<ObjectId>,
 }
 
 impl VerifyReport {
@@ -64,6 +142,7 @@ impl VerifyReport {
         !self.objects_corrupted.is_empty()
             || !self.refs_dangling.is_empty()
             || !self.commits_invalid.is_empty()
+            || !self.rust_snapshot_drift.is_empty()
     }
 
     /// Returns a summary message.
@@ -84,6 +163,12 @@ impl VerifyReport {
             if !self.commits_invalid.is_empty() {
                 issues.push(format!("{} invalid commits", self.commits_invalid.len()));
             }
+            if !self.rust_snapshot_drift.is_empty() {
+                issues.push(format!(
+                    "{} files changed since Rust analysis",
+                    self.rust_snapshot_drift.len()
+                ));
+            }
             format!("Repository has issues: {}", issues.join(", "))
         }
     }
@@ -118,14 +203,15 @@ pub fn verify(
         check_refs(refs, object_store, &mut report)?;
     }
 
-    // Check commit chain
+    // Check commit chain, and that HEAD's analysis is current
     if config.check_commits {
         check_commits(refs, object_store, &mut report)?;
+        check_rust_snapshot(refs, object_store, &mut report)?;
     }
 
-    // Check all objects (slow)
+    // Check all objects (slow), or the sample or batch asked for
     if config.check_objects {
-        check_all_objects(object_store, &mut report)?;
+        check_all_objects(object_store, &config, &mut report)?;
     }
 
     Ok(report)
@@ -149,6 +235,30 @@ fn check_refs(refs: &Refs, store: &ObjectStore, report: &mut VerifyReport) -> Re
         }
     }
 
+    // Check SUMMARIES
+    if let Ok(Some(summaries_id)) = refs.read_summaries() {
+        report.refs_checked += 1;
+        if !store.exists(summaries_id) {
+            report.refs_dangling.push("SUMMARIES".to_string());
+        }
+    }
+
+    // Check FEEDBACK
+    if let Ok(Some(feedback_id)) = refs.read_feedback() {
+        report.refs_checked += 1;
+        if !store.exists(feedback_id) {
+            report.refs_dangling.push("FEEDBACK".to_string());
+        }
+    }
+
+    // Check refs/notes/commits
+    if let Ok(Some(notes_id)) = refs.read_notes() {
+        report.refs_checked += 1;
+        if !store.exists(notes_id) {
+            report.refs_dangling.push("refs/notes/commits".to_string());
+        }
+    }
+
     // Check all refs/*
     for (name, id) in refs.list_refs()? {
         report.refs_checked += 1;
@@ -204,52 +314,103 @@ fn check_commits(refs: &Refs, store: &ObjectStore, report: &mut VerifyReport) ->
             }
         }
 
-        // Add parents to queue
-        for parent in &commit.parents {
-            queue.push_back(*parent);
+        // Check that the Rust analysis exists
+        if commit.rust_snapshot.is_some_and(|id| !store.exists(id)) {
+            report.commits_invalid.push(id);
         }
+
+        // Add parents to queue, except those left out by a shallow clone
+        queue.extend(store.parents(&commit));
     }
 
     Ok(())
 }
 
-/// Check integrity of all objects.
-fn check_all_objects(store: &ObjectStore, report: &mut VerifyReport) -> Result<()> {
-    let all_objects = store.list_all_objects()?;
+/// Check that HEAD's Rust analysis describes HEAD's files.
+///
+/// A file drifts when the blob it was analyzed from is missing, or HEAD's
+/// tree has different content at its path.
+fn check_rust_snapshot(refs: &Refs, store: &ObjectStore, report: &mut VerifyReport) -> Result<()> {
+    let Ok(head) = refs
+        .read_head()
+        .and_then(|id| store.get_typed::<Commit>(id))
+    else {
+        return Ok(());
+    };
+    // A missing snapshot is reported with the commit
+    let Some(snapshot) = head
+        .rust_snapshot
+        .and_then(|id| store.get_typed::<RustSnapshot>(id).ok())
+    else {
+        return Ok(());
+    };
 
-    for (id, _size, _mtime) in all_objects {
+    let tree_files = crate::staging::flatten_tree(head.root_tree, store)?;
+    let changed: HashSet<&str> = snapshot.drift(&tree_files).into_iter().collect();
+    report.rust_snapshot_drift = snapshot
+        .files
+        .iter()
+        .filter(|(path, file)| changed.contains(path.as_str()) || !store.exists(file.blob))
+        .map(|(path, _)| path.clone())
+        .collect();
+    Ok(())
+}
+
+/// Check integrity of all objects in the configured sample, in ID order
+/// from the cursor, up to the configured limit.
+fn check_all_objects(
+    store: &ObjectStore,
+    config: &VerifyConfig,
+    report: &mut VerifyReport,
+) -> Result<()> {
+    let mut ids: Vec<ObjectId> = store
+        .list_all_objects()?
+        .into_iter()
+        .map(|(id, _, _)| id)
+        .filter(|id| config.resume_after.map_or(true, |after| *id > after))
+        .filter(|id| config.sample.map_or(true, |sample| sample.contains(*id)))
+        .collect();
+    ids.sort();
+
+    let limit = config.max_objects.unwrap_or(usize::MAX);
+    for &id in ids.iter().take(limit) {
         report.objects_checked += 1;
 
-        // Try to read and verify object
-        // The read_object method in ObjectStore already does hash verification
-        if let Err(e) = verify_object(store, id) {
-            if matches!(
-                e,
-                CtxError::HashMismatch { .. } | CtxError::CorruptedObject { .. }
-            ) {
-                report.objects_corrupted.push(id);
-            }
+        if is_corrupted(store, id) {
+            report.objects_corrupted.push(id);
         }
     }
+    if ids.len() > limit && limit > 0 {
+        report.next_cursor = Some(ids[limit - 1]);
+    }
 
     Ok(())
 }
 
+/// Returns true if the object fails its integrity check. Objects that
+/// can't be read for other reasons, such as a remote being down, don't
+/// count.
+pub(crate) fn is_corrupted(store: &ObjectStore, id: ObjectId) -> bool {
+    // Reading an object verifies its hash
+    matches!(
+        verify_object(store, id),
+        Err(CtxError::HashMismatch { .. }
+            | CtxError::CorruptedObject { .. }
+            | CtxError::ObjectCorrupt { .. })
+    )
+}
+
 /// Verify a single object's integrity.
+///
+/// Blobs are streamed, so large ones are hashed without loading them into
+/// memory. Typed objects are small and read whole.
 fn verify_object(store: &ObjectStore, id: ObjectId) -> Result<()> {
-    // Try to read as blob first
-    if store.get_blob(id).is_ok() {
-        return Ok(());
-    }
-
-    // Try to read raw and verify envelope
-    // This is a bit hacky but we don't have a generic "verify" method
-    // For now, we just check if we can read it successfully
-    if store.exists(id) {
-        Ok(())
-    } else {
-        Err(CtxError::ObjectNotFound(id.as_hex()))
+    if store.object_kind(id)? == ObjectKind::Typed {
+        return store.payload_size(id).map(|_| ());
     }
+    let mut reader = store.get_blob_reader(id)?;
+    io::copy(&mut reader, &mut io::sink()).map_err(io_error)?;
+    Ok(())
 }
 
 /// Recover from a corrupted staging session.
@@ -258,7 +419,12 @@ fn verify_object(store: &ObjectStore, id: ObjectId) -> Result<()> {
 /// Returns the recovered session commit ID if successful.
 ///
 /// If STAGE is corrupted, it will be cleared automatically.
+///
+/// A ref transaction interrupted by a crash is completed first, so STAGE
+/// is never inspected half-way through a compaction.
 pub fn recover_staging(refs: &Refs, store: &ObjectStore) -> Result<Option<ObjectId>> {
+    refs.replay_journal()?;
+
     // Try to read STAGE
     let stage_id = match refs.read_stage()? {
         Some(id) => id,
@@ -276,6 +442,216 @@ pub fn recover_staging(refs: &Refs, store: &ObjectStore) -> Result<Option<Object
     }
 }
 
+/// What recovery did with one work commit of the staged session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepDisposition {
+    /// On the recovered chain; the session resumes with it.
+    Kept,
+    /// Unreadable, or only reachable through an unreadable step; dropped
+    /// from the chain.
+    Truncated,
+    /// Stored for the session but not on its chain, e.g. a step flushed by
+    /// a process that crashed before moving STAGE.
+    Orphaned,
+}
+
+/// One work commit examined by [`recover_staging_summary`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecoveredStep {
+    /// Work commit ID.
+    #[serde(serialize_with = "crate::object_id::serialize_hex")]
+    pub id: ObjectId,
+    /// What recovery did with it.
+    pub disposition: StepDisposition,
+    /// Kind of step, if the work commit could be read.
+    pub step_kind: Option<StepKind>,
+    /// When the step was flushed, if the work commit could be read.
+    pub created_at: Option<u64>,
+}
+
+/// Outcome of recovering the staged session.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RecoverySummary {
+    /// STAGE before recovery, or None if there was no staged session.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub stage: Option<ObjectId>,
+    /// STAGE after recovery, or None if nothing could be recovered.
+    #[serde(serialize_with = "crate::object_id::serialize_hex_opt")]
+    pub recovered: Option<ObjectId>,
+    /// Session identifier, if the staging head could be read.
+    pub session_id: Option<String>,
+    /// Task description, if the staging head could be read.
+    pub task: Option<String>,
+    /// Work commits examined: the chain oldest first, then orphans.
+    pub steps: Vec<RecoveredStep>,
+}
+
+impl RecoverySummary {
+    /// Number of steps with the given disposition.
+    pub fn count(&self, disposition: StepDisposition) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.disposition == disposition)
+            .count()
+    }
+
+    /// Returns true if recovery moved or removed STAGE.
+    pub fn is_repaired(&self) -> bool {
+        self.stage != self.recovered
+    }
+}
+
+impl RecoveredStep {
+    fn new(id: ObjectId, work: Option<&WorkCommit>, disposition: StepDisposition) -> Self {
+        Self {
+            id,
+            disposition,
+            step_kind: work.map(|work| work.step_kind),
+            created_at: work.map(|work| work.created_at),
+        }
+    }
+}
+
+/// Recovers the staged session and reports the fate of each work commit.
+///
+/// Walks the staging chain from STAGE back to its base. If a step can't be
+/// read, the chain is cut there: the steps above it are truncated, the
+/// readable steps below it are found by scanning the store for the
+/// session's work commits, and STAGE moves to the last of them. STAGE is
+/// removed when nothing is left. Work commits of the session that aren't on
+/// the chain are reported as orphaned and left in place for GC.
+///
+/// Like [`recover_staging`], an interrupted ref transaction is completed
+/// first.
+pub fn recover_staging_summary(refs: &Refs, store: &ObjectStore) -> Result<RecoverySummary> {
+    refs.replay_journal()?;
+
+    let mut summary = RecoverySummary::default();
+    let Some(stage) = refs.read_stage()? else {
+        return Ok(summary);
+    };
+    summary.stage = Some(stage);
+
+    let Ok(head) = store.get_typed::<WorkCommit>(stage) else {
+        summary
+            .steps
+            .push(RecoveredStep::new(stage, None, StepDisposition::Truncated));
+        refs.transaction(&[RefUpdate::DeleteStage], "recover: STAGE unreadable")?;
+        return Ok(summary);
+    };
+    summary.session_id = Some(head.session_id.clone());
+    summary.task = Some(head.task_description.clone());
+
+    // Newest first, down to the base or the first unreadable step
+    let mut chain = Vec::new();
+    let mut broken = None;
+    let mut current = stage;
+    while current != head.base {
+        match store.get_typed::<WorkCommit>(current) {
+            Ok(work) if work.session_id == head.session_id => {
+                let parent = work.parents.first().copied();
+                chain.push((current, work));
+                match parent {
+                    Some(parent) => current = parent,
+                    None => break,
+                }
+            }
+            _ => {
+                broken = Some(current);
+                break;
+            }
+        }
+    }
+
+    let session_steps = session_work_commits(store, &head)?;
+    let mut kept: Vec<(ObjectId, WorkCommit)> = Vec::new();
+    match broken {
+        None => {
+            chain.reverse();
+            kept = chain;
+        }
+        Some(broken) => {
+            // Rebuild the readable prefix from the base up; without a
+            // unique child the chain can't be followed further
+            let mut children: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+            for (id, work) in &session_steps {
+                if let Some(parent) = work.parents.first() {
+                    children.entry(*parent).or_default().push(*id);
+                }
+            }
+            let mut tip = head.base;
+            while let Some([child]) = children.get(&tip).map(Vec::as_slice) {
+                kept.push((*child, session_steps[child].clone()));
+                tip = *child;
+            }
+
+            summary
+                .steps
+                .push(RecoveredStep::new(broken, None, StepDisposition::Truncated));
+            for (id, work) in chain.iter().rev() {
+                summary.steps.push(RecoveredStep::new(
+                    *id,
+                    Some(work),
+                    StepDisposition::Truncated,
+                ));
+            }
+        }
+    }
+
+    let truncated: HashSet<ObjectId> = summary.steps.iter().map(|step| step.id).collect();
+    let on_chain: HashSet<ObjectId> = kept.iter().map(|(id, _)| *id).collect();
+    let mut steps: Vec<RecoveredStep> = kept
+        .iter()
+        .map(|(id, work)| RecoveredStep::new(*id, Some(work), StepDisposition::Kept))
+        .collect();
+    steps.append(&mut summary.steps);
+    let mut orphans: Vec<_> = session_steps
+        .iter()
+        .filter(|(id, _)| !on_chain.contains(id) && !truncated.contains(id))
+        .map(|(id, work)| RecoveredStep::new(*id, Some(work), StepDisposition::Orphaned))
+        .collect();
+    orphans.sort_by_key(|step| (step.created_at, step.id));
+    steps.extend(orphans);
+    summary.steps = steps;
+
+    summary.recovered = kept.last().map(|(id, _)| *id);
+    if broken.is_some() {
+        let (update, reason) = match summary.recovered {
+            Some(tip) => (
+                RefUpdate::Stage(tip),
+                format!("recover: truncated at {}", &tip.as_hex()[..12]),
+            ),
+            None => (
+                RefUpdate::DeleteStage,
+                "recover: no readable steps".to_string(),
+            ),
+        };
+        refs.transaction(&[update], &reason)?;
+    }
+
+    Ok(summary)
+}
+
+/// The work commits in the store belonging to `head`'s session.
+fn session_work_commits(
+    store: &ObjectStore,
+    head: &WorkCommit,
+) -> Result<HashMap<ObjectId, WorkCommit>> {
+    let mut steps = HashMap::new();
+    for (id, _, _) in store.list_all_objects()? {
+        if !matches!(store.object_kind(id), Ok(ObjectKind::Typed)) {
+            continue;
+        }
+        if let Ok(work) = store.get_typed::<WorkCommit>(id) {
+            if work.session_id == head.session_id && work.base == head.base {
+                steps.insert(id, work);
+            }
+        }
+    }
+    Ok(steps)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -327,7 +703,15 @@ mod tests {
             cargo_snapshot: None,
             rust_snapshot: None,
             diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
             commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
         };
         let commit_id = store.put_typed(&commit).unwrap();
         refs.write_head(commit_id).unwrap();
@@ -341,6 +725,159 @@ mod tests {
         assert_eq!(report.commits_checked, 1);
     }
 
+    #[test]
+    fn test_verify_detects_rust_snapshot_drift() {
+        use crate::lsp::RustFileSnapshot;
+        use crate::types::{TreeEntry, TreeEntryKind};
+
+        let tmp = TempDir::new().unwrap();
+        let ctx_root = tmp.path().join(".ctx");
+        std::fs::create_dir_all(&ctx_root).unwrap();
+        let store = ObjectStore::new(ctx_root.join("objects"));
+        let refs = Refs::new(&ctx_root);
+
+        let analyzed = store.put_blob(b"pub fn a() {}").unwrap();
+        let current = store.put_blob(b"pub fn b() {}").unwrap();
+        let file = |blob| RustFileSnapshot {
+            blob,
+            items: vec![],
+            calls: vec![],
+            warnings: vec![],
+        };
+        let snapshot = RustSnapshot {
+            files: [
+                ("a.rs".to_string(), file(analyzed)),
+                ("b.rs".to_string(), file(analyzed)),
+                ("gone.rs".to_string(), file(ObjectId::from_bytes([7; 32]))),
+            ]
+            .into(),
+        };
+        let entry = |name: &str, id| TreeEntry {
+            name: name.to_string(),
+            kind: TreeEntryKind::Blob,
+            id,
+        };
+        let tree = Tree {
+            entries: vec![entry("a.rs", analyzed), entry("b.rs", current)],
+        };
+
+        let commit = Commit {
+            parents: vec![],
+            timestamp_unix: 0,
+            message: "Rust analysis".into(),
+            root_tree: store.put_typed(&tree).unwrap(),
+            edge_batches: vec![],
+            narrative_refs: vec![],
+            cargo_snapshot: None,
+            rust_snapshot: Some(store.put_typed(&snapshot).unwrap()),
+            diagnostics_snapshot: None,
+            glossary: None,
+            decisions: None,
+            facts: None,
+            qa: None,
+            transcript: None,
+            commit_type: None,
+            author: None,
+            task: None,
+            tags: Default::default(),
+        };
+        refs.write_head(store.put_typed(&commit).unwrap()).unwrap();
+
+        let report = verify(&refs, &store, VerifyConfig::default()).unwrap();
+        assert!(report.commits_invalid.is_empty());
+        assert_eq!(report.rust_snapshot_drift, vec!["b.rs", "gone.rs"]);
+        assert!(report
+            .summary()
+            .contains("2 files changed since Rust analysis"));
+    }
+
+    #[test]
+    fn test_verify_objects_streams_chunked_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let ctx_root = tmp.path().join(".ctx");
+        std::fs::create_dir_all(&ctx_root).unwrap();
+
+        let store = ObjectStore::new(ctx_root.join("objects"));
+        let refs = Refs::new(&ctx_root);
+        let config = || VerifyConfig {
+            check_objects: true,
+            check_refs: false,
+            check_commits: false,
+            ..Default::default()
+        };
+
+        let large: Vec<u8> = (0..1_000_000u32).map(|i| (i * 13 % 241) as u8).collect();
+        let large_id = store.put_blob(&large).unwrap();
+        store.put_typed(&Tree { entries: vec![] }).unwrap();
+        let report = verify(&refs, &store, config()).unwrap();
+        assert!(!report.has_issues());
+        assert!(report.objects_checked > 3);
+
+        // Replace one chunk with another blob's content
+        let chunk = store.chunk_ids(large_id).unwrap().unwrap()[1];
+        let other = store.put_blob(b"not the chunk").unwrap();
+        let path = |id: ObjectId| ctx_root.join("objects").join(id.shard()).join(id.as_hex());
+        std::fs::copy(path(other), path(chunk)).unwrap();
+
+        let report = verify(&refs, &store, config()).unwrap();
+        let mut corrupted = report.objects_corrupted.clone();
+        corrupted.sort();
+        let mut expected = vec![chunk, large_id];
+        expected.sort();
+        assert_eq!(corrupted, expected);
+    }
+
+    #[test]
+    fn test_verify_sample_covers_store_over_cycle() {
+        let ids: Vec<ObjectId> = (0..200u8)
+            .map(|i| ObjectId::from_bytes([i.wrapping_mul(37); 32]))
+            .collect();
+        let mut seen = HashSet::new();
+        for day in 0..4 {
+            let sample = VerifySample { percent: 25, day };
+            let today: Vec<_> = ids.iter().filter(|id| sample.contains(**id)).collect();
+            // Consecutive days don't overlap
+            assert!(today.iter().all(|id| seen.insert(**id)));
+        }
+        assert_eq!(seen.len(), ids.len());
+
+        let all = VerifySample {
+            percent: 100,
+            day: 3,
+        };
+        assert!(ids.iter().all(|id| all.contains(*id)));
+    }
+
+    #[test]
+    fn test_verify_objects_in_batches_from_cursor() {
+        let tmp = TempDir::new().unwrap();
+        let store = ObjectStore::new(tmp.path().join("objects"));
+        let refs = Refs::new(tmp.path());
+        for i in 0..5u8 {
+            store.put_blob(&[i]).unwrap();
+        }
+
+        let batch = |resume_after| {
+            let config = VerifyConfig {
+                check_objects: true,
+                check_refs: false,
+                check_commits: false,
+                resume_after,
+                max_objects: Some(2),
+                ..Default::default()
+            };
+            verify(&refs, &store, config).unwrap()
+        };
+        let first = batch(None);
+        assert_eq!(first.objects_checked, 2);
+        let second = batch(first.next_cursor);
+        assert_eq!(second.objects_checked, 2);
+        assert!(second.next_cursor > first.next_cursor);
+        let last = batch(second.next_cursor);
+        assert_eq!(last.objects_checked, 1);
+        assert_eq!(last.next_cursor, None);
+    }
+
     #[test]
     fn test_verify_dangling_ref() {
         let tmp = TempDir::new().unwrap();
@@ -0,0 +1,117 @@
+//! Discovery of nested CTX repositories.
+//!
+//! A monorepo can keep a shared `.ctx` at its root and per-crate stores
+//! below it. The repository for a path is the nearest directory at or
+//! above it that contains `.ctx`; its parent is the next such directory
+//! further up. Child repositories are found by walking the directories
+//! below a root, skipping hidden directories and build output.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Which repositories a handle opened with [`crate::CtxRepo::open_with_scope`]
+/// covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepoScope {
+    /// Only the nearest repository at or above the path.
+    #[default]
+    Nearest,
+    /// The nearest repository and every repository nested below it.
+    WithChildren,
+    /// The outermost repository at or above the path and every repository
+    /// nested below it.
+    Workspace,
+}
+
+impl RepoScope {
+    /// Returns true if the scope includes nested repositories.
+    pub fn includes_children(self) -> bool {
+        !matches!(self, RepoScope::Nearest)
+    }
+}
+
+/// Directory names never searched for child repositories.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Returns the nearest directory at or above `path` containing `.ctx`.
+pub(crate) fn find_repo_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join(".ctx").is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// Returns the nearest repository root strictly above `root`.
+pub(crate) fn find_parent_root(root: &Path) -> Option<PathBuf> {
+    root.parent().and_then(find_repo_root)
+}
+
+/// Returns the outermost repository root at or above `path`.
+pub(crate) fn find_outermost_root(path: &Path) -> Option<PathBuf> {
+    let mut root = find_repo_root(path)?;
+    while let Some(parent) = find_parent_root(&root) {
+        root = parent;
+    }
+    Some(root)
+}
+
+/// Returns every repository root nested below `root`, sorted by path.
+///
+/// Unreadable directories are skipped.
+pub(crate) fn find_child_roots(root: &Path) -> Vec<PathBuf> {
+    let mut children = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_ref()) {
+                continue;
+            }
+            if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                continue;
+            }
+            let path = entry.path();
+            if path.join(".ctx").is_dir() {
+                children.push(path.clone());
+            }
+            stack.push(path);
+        }
+    }
+    children.sort();
+    children
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn make_repo(dir: &Path) {
+        fs::create_dir_all(dir.join(".ctx")).unwrap();
+    }
+
+    #[test]
+    fn test_discovery() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_path_buf();
+        let core = root.join("crates/core");
+        let nested = core.join("plugins/extra");
+        make_repo(&root);
+        make_repo(&core);
+        make_repo(&nested);
+        make_repo(&root.join("target/debug/junk"));
+        fs::create_dir_all(core.join("src/deep")).unwrap();
+
+        assert_eq!(find_repo_root(&core.join("src/deep")), Some(core.clone()));
+        assert_eq!(find_repo_root(&root), Some(root.clone()));
+        assert_eq!(find_parent_root(&core), Some(root.clone()));
+        assert_eq!(find_parent_root(&nested), Some(core.clone()));
+        assert_eq!(find_outermost_root(&nested), Some(root.clone()));
+
+        assert_eq!(find_child_roots(&root), vec![core.clone(), nested.clone()]);
+        assert_eq!(find_child_roots(&core), vec![nested]);
+    }
+}
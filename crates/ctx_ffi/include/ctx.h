@@ -0,0 +1,84 @@
+/*
+ * C API for embedding CTX in other runtimes.
+ *
+ * Strings passed in are NUL-terminated UTF-8. Strings returned through
+ * out_json parameters are owned by the caller and must be released with
+ * ctx_string_free; pass NULL for out_json to skip the output. After a call
+ * returns something other than CTX_OK, ctx_last_error describes the failure
+ * on the calling thread.
+ *
+ * A handle may be used from any thread, but not from two threads at once.
+ */
+
+#ifndef CTX_H
+#define CTX_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CTX_ABI_VERSION 1
+
+typedef enum CtxStatus {
+    CTX_OK = 0,
+    CTX_INVALID_ARGUMENT = 1,
+    CTX_NOT_FOUND = 2,
+    CTX_LOCKED = 3,
+    CTX_NO_ACTIVE_SESSION = 4,
+    CTX_SESSION_ALREADY_ACTIVE = 5,
+    CTX_REJECTED = 6,
+    CTX_CORRUPTED = 7,
+    CTX_IO = 8,
+    CTX_PANIC = 9,
+    CTX_OTHER = 10
+} CtxStatus;
+
+typedef struct CtxHandle CtxHandle;
+
+uint32_t ctx_abi_version(void);
+const char *ctx_last_error(void);
+void ctx_string_free(char *s);
+
+CtxStatus ctx_open(const char *path, CtxHandle **out);
+CtxStatus ctx_init(const char *path, CtxHandle **out);
+void ctx_close(CtxHandle *handle);
+
+/* {"session_id": "..."} */
+CtxStatus ctx_start_session(CtxHandle *handle, const char *task, char **out_json);
+/* {"recovered": true|false} */
+CtxStatus ctx_recover_session(CtxHandle *handle, char **out_json);
+
+/*
+ * observation_json is one of:
+ *   {"type": "read", "path": "...", "content": "optional"}
+ *   {"type": "write", "path": "...", "content": "..."}
+ *   {"type": "command", "command": "...", "exit_code": 0, "output": "optional"}
+ *   {"type": "note", "note": "..."}
+ * each optionally with "metadata": {"key": "value"} to tag the step.
+ */
+CtxStatus ctx_observe(CtxHandle *handle, const char *observation_json);
+/* {"staging_head": "<hex>"} */
+CtxStatus ctx_flush(CtxHandle *handle, char **out_json);
+/* message may be NULL to generate one; {"commit_id": "<hex>", "message": "..."} */
+CtxStatus ctx_compact(CtxHandle *handle, const char *message, char **out_json);
+/* reason may be NULL; {"commit_id": "<hex>"} */
+CtxStatus ctx_abort(CtxHandle *handle, const char *reason, char **out_json);
+
+/*
+ * options_json may be NULL or an object with any of token_budget,
+ * response_reserve, expansion_depth, include_narrative, exclude, tags and
+ * decisions_only.
+ * Writes the prompt pack.
+ */
+CtxStatus ctx_query(CtxHandle *handle, const char *query, const char *options_json,
+                    char **out_json);
+/* Same shape as `ctx status --json`. */
+CtxStatus ctx_status(CtxHandle *handle, char **out_json);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CTX_H */
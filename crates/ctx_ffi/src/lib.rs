@@ -0,0 +1,713 @@
+//! C API for embedding CTX in other runtimes.
+//!
+//! Node.js, Go and other agent hosts can link this library instead of
+//! shelling out to the `ctx` binary. The API is deliberately small:
+//!
+//! - A repository is an opaque [`CtxHandle`], opened with [`ctx_open`] or
+//!   [`ctx_init`] and released with [`ctx_close`].
+//! - Every fallible call returns a [`CtxStatus`]. On failure,
+//!   [`ctx_last_error`] describes what went wrong on the calling thread.
+//! - Structured input and output is JSON in UTF-8 C strings. Strings
+//!   returned through `out_json` parameters belong to the caller and must be
+//!   released with [`ctx_string_free`]; passing a null `out_json` skips the
+//!   output.
+//!
+//! `include/ctx.h` declares the same API for C callers. A handle may be
+//! used from any thread, but not from two threads at once.
+
+use ctx_core::{CtxError, CtxRepo, Metadata, RetrievalConfig};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Version of this API, bumped on incompatible changes.
+pub const CTX_ABI_VERSION: u32 = 1;
+
+/// Result of an API call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CtxStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer was null, a string wasn't UTF-8, or JSON input was invalid.
+    InvalidArgument = 1,
+    /// A repository, object or ref doesn't exist.
+    NotFound = 2,
+    /// Another process holds the repository lock.
+    Locked = 3,
+    /// The call needs an active session and there is none.
+    NoActiveSession = 4,
+    /// A session is already active.
+    SessionAlreadyActive = 5,
+    /// A hook, the exec policy or an ignore rule refused the operation.
+    Rejected = 6,
+    /// Stored data failed an integrity check.
+    Corrupted = 7,
+    /// A filesystem or remote object store error.
+    Io = 8,
+    /// The library panicked; the handle should be closed.
+    Panic = 9,
+    /// Any other error.
+    Other = 10,
+}
+
+impl From<&CtxError> for CtxStatus {
+    fn from(error: &CtxError) -> Self {
+        match error {
+            CtxError::InvalidArgument(_) | CtxError::InvalidCursor(_) | CtxError::InvalidHex(_) => {
+                CtxStatus::InvalidArgument
+            }
+            CtxError::ObjectNotFound(_) | CtxError::RefNotFound(_) => CtxStatus::NotFound,
+            CtxError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => CtxStatus::NotFound,
+            CtxError::RepositoryLocked
+            | CtxError::SessionLockHeld { .. }
+            | CtxError::IndexLocked { .. } => CtxStatus::Locked,
+            CtxError::NoActiveSession => CtxStatus::NoActiveSession,
+            CtxError::SessionAlreadyActive(_) => CtxStatus::SessionAlreadyActive,
+            CtxError::CommandDenied { .. }
+            | CtxError::HookRejected { .. }
+            | CtxError::PathIgnored { .. } => CtxStatus::Rejected,
+            CtxError::HashMismatch { .. }
+            | CtxError::CorruptedObject { .. }
+            | CtxError::ObjectCorrupt { .. }
+            | CtxError::InvalidRef { .. }
+            | CtxError::StagingCorrupted { .. }
+            | CtxError::IndexCorrupted { .. } => CtxStatus::Corrupted,
+            CtxError::Io(_)
+            | CtxError::RefError { .. }
+            | CtxError::LockError { .. }
+            | CtxError::RemoteStore(_) => CtxStatus::Io,
+            _ => CtxStatus::Other,
+        }
+    }
+}
+
+/// An open repository.
+pub struct CtxHandle {
+    repo: CtxRepo,
+}
+
+/// A failed call: the status to return and the message for
+/// [`ctx_last_error`].
+struct FfiError {
+    status: CtxStatus,
+    message: String,
+}
+
+impl From<CtxError> for FfiError {
+    fn from(error: CtxError) -> Self {
+        Self {
+            status: CtxStatus::from(&error),
+            message: error.to_string(),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> FfiError {
+    FfiError {
+        status: CtxStatus::InvalidArgument,
+        message: message.into(),
+    }
+}
+
+type FfiResult<T> = std::result::Result<T, FfiError>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str) {
+    // Interior NULs would truncate the message in C
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, recording its error and catching panics.
+fn call(f: impl FnOnce() -> FfiResult<()>) -> CtxStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = None);
+            CtxStatus::Ok
+        }
+        Ok(Err(e)) => {
+            set_last_error(&e.message);
+            e.status
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(&format!("panic: {}", message));
+            CtxStatus::Panic
+        }
+    }
+}
+
+/// Reads a required UTF-8 string argument.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn arg_str<'a>(ptr: *const c_char, name: &str) -> FfiResult<&'a str> {
+    if ptr.is_null() {
+        return Err(invalid(format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| invalid(format!("{} is not valid UTF-8", name)))
+}
+
+/// Reads an optional UTF-8 string argument.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn opt_str<'a>(ptr: *const c_char, name: &str) -> FfiResult<Option<&'a str>> {
+    if ptr.is_null() {
+        Ok(None)
+    } else {
+        arg_str(ptr, name).map(Some)
+    }
+}
+
+/// Borrows the repository behind a handle.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle from [`ctx_open`] or [`ctx_init`].
+unsafe fn repo<'a>(handle: *mut CtxHandle) -> FfiResult<&'a mut CtxRepo> {
+    handle
+        .as_mut()
+        .map(|handle| &mut handle.repo)
+        .ok_or_else(|| invalid("handle is null"))
+}
+
+/// Stores `value` in `out` as a caller-owned JSON string.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn write_json(out: *mut *mut c_char, value: &Value) -> FfiResult<()> {
+    write_string(out, value.to_string())
+}
+
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn write_string(out: *mut *mut c_char, value: String) -> FfiResult<()> {
+    if out.is_null() {
+        return Ok(());
+    }
+    let value = CString::new(value).map_err(|_| FfiError {
+        status: CtxStatus::Other,
+        message: "output contains a NUL byte".to_string(),
+    })?;
+    *out = value.into_raw();
+    Ok(())
+}
+
+/// Returns [`CTX_ABI_VERSION`].
+#[no_mangle]
+pub extern "C" fn ctx_abi_version() -> u32 {
+    CTX_ABI_VERSION
+}
+
+/// Describes the last failed call on this thread, or returns null if the
+/// last call succeeded.
+///
+/// The string stays valid until the next call on the same thread; don't
+/// free it.
+#[no_mangle]
+pub extern "C" fn ctx_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Releases a string returned through an `out_json` parameter.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library that hasn't been
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Opens the repository at `path` and stores its handle in `out`.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_open(path: *const c_char, out: *mut *mut CtxHandle) -> CtxStatus {
+    call(|| {
+        let path = arg_str(path, "path")?;
+        store_handle(out, CtxRepo::open(path)?)
+    })
+}
+
+/// Creates a repository at `path` and stores its handle in `out`.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_init(path: *const c_char, out: *mut *mut CtxHandle) -> CtxStatus {
+    call(|| {
+        let path = arg_str(path, "path")?;
+        store_handle(out, CtxRepo::init(path)?)
+    })
+}
+
+/// # Safety
+///
+/// `out` must be valid for writes.
+unsafe fn store_handle(out: *mut *mut CtxHandle, repo: CtxRepo) -> FfiResult<()> {
+    if out.is_null() {
+        return Err(invalid("out is null"));
+    }
+    *out = Box::into_raw(Box::new(CtxHandle { repo }));
+    Ok(())
+}
+
+/// Closes a handle, releasing its session lock. An active session stays
+/// staged and can be recovered later.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle; it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_close(handle: *mut CtxHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Starts a session for `task`. Writes `{"session_id": ...}`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle, `task` a NUL-terminated string and
+/// `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_start_session(
+    handle: *mut CtxHandle,
+    task: *const c_char,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let repo = repo(handle)?;
+        let task = arg_str(task, "task")?;
+        let session_id = repo.start_session(task)?.session_id().to_string();
+        write_json(out_json, &json!({ "session_id": session_id }))
+    })
+}
+
+/// Resumes the session left in the staging area by a previous handle.
+/// Writes `{"recovered": true|false}`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_recover_session(
+    handle: *mut CtxHandle,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let repo = repo(handle)?;
+        let recovered = repo.has_active_session() || repo.recover_session()?.is_some();
+        write_json(out_json, &json!({ "recovered": recovered }))
+    })
+}
+
+/// An observation passed to [`ctx_observe`].
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum Observation {
+    Read {
+        path: String,
+        content: Option<String>,
+        #[serde(default)]
+        metadata: Metadata,
+    },
+    Write {
+        path: String,
+        content: String,
+        #[serde(default)]
+        metadata: Metadata,
+    },
+    Command {
+        command: String,
+        exit_code: Option<i32>,
+        output: Option<String>,
+        #[serde(default)]
+        metadata: Metadata,
+    },
+    Note {
+        note: String,
+        #[serde(default)]
+        metadata: Metadata,
+    },
+}
+
+/// Records an observation in the active session.
+///
+/// `observation_json` is one of:
+///
+/// ```json
+/// {"type": "read", "path": "src/a.rs", "content": "optional text"}
+/// {"type": "write", "path": "src/a.rs", "content": "new text"}
+/// {"type": "command", "command": "cargo test", "exit_code": 0, "output": "optional"}
+/// {"type": "note", "note": "text"}
+/// ```
+///
+/// Each may also carry `"metadata": {"key": "value"}`, whose entries tag
+/// the step; see `ctx_core::Observation`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and `observation_json` a NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_observe(
+    handle: *mut CtxHandle,
+    observation_json: *const c_char,
+) -> CtxStatus {
+    call(|| {
+        let repo = repo(handle)?;
+        let observation: Observation =
+            serde_json::from_str(arg_str(observation_json, "observation_json")?)
+                .map_err(|e| invalid(format!("invalid observation: {}", e)))?;
+        let metadata = match observation {
+            Observation::Read {
+                path,
+                content: Some(content),
+                metadata,
+            } => {
+                repo.observe_file_read_with_content(&path, content.as_bytes())?;
+                metadata
+            }
+            Observation::Read {
+                path,
+                content: None,
+                metadata,
+            } => {
+                repo.observe_file_read(&path)?;
+                metadata
+            }
+            Observation::Write {
+                path,
+                content,
+                metadata,
+            } => {
+                repo.observe_file_write(&path, content.as_bytes())?;
+                metadata
+            }
+            Observation::Command {
+                command,
+                exit_code,
+                output,
+                metadata,
+            } => {
+                repo.observe_command(&command, exit_code, output.as_deref().map(str::as_bytes))?;
+                metadata
+            }
+            Observation::Note { note, metadata } => {
+                repo.observe_note_with_tags(&note, metadata)?;
+                Metadata::new()
+            }
+        };
+        if !metadata.is_empty() {
+            repo.tag_last_observation(metadata)?;
+        }
+        Ok(())
+    })
+}
+
+/// Flushes pending observations to the staging area. Writes
+/// `{"staging_head": "<hex>"}`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_flush(
+    handle: *mut CtxHandle,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let staging_head = repo(handle)?.flush_active_session()?;
+        write_json(out_json, &json!({ "staging_head": staging_head.as_hex() }))
+    })
+}
+
+/// Compacts the active session into a commit. `message` may be null to have
+/// the configured summary provider write it. Writes
+/// `{"commit_id": "<hex>", "message": "..."}`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle, `message` null or a NUL-terminated
+/// string and `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_compact(
+    handle: *mut CtxHandle,
+    message: *const c_char,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let repo = repo(handle)?;
+        let (commit_id, message) = match opt_str(message, "message")? {
+            Some(message) => (repo.compact_session(message)?, message.to_string()),
+            None => {
+                let provider = repo.summary_provider()?;
+                let (commit_id, summary) = repo.compact_session_summarized(provider.as_ref())?;
+                (commit_id, summary.message)
+            }
+        };
+        write_json(
+            out_json,
+            &json!({ "commit_id": commit_id.as_hex(), "message": message }),
+        )
+    })
+}
+
+/// Abandons the active session, recording it as an abandoned commit.
+/// `reason` may be null. Writes `{"commit_id": "<hex>"}`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle, `reason` null or a NUL-terminated
+/// string and `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_abort(
+    handle: *mut CtxHandle,
+    reason: *const c_char,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let repo = repo(handle)?;
+        let reason = opt_str(reason, "reason")?.unwrap_or("aborted");
+        let commit_id = repo.abort_session(reason)?;
+        write_json(out_json, &json!({ "commit_id": commit_id.as_hex() }))
+    })
+}
+
+/// Retrieval settings accepted by [`ctx_query`]; omitted fields keep their
+/// defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QueryOptions {
+    token_budget: Option<u32>,
+    response_reserve: Option<u32>,
+    expansion_depth: Option<u32>,
+    include_narrative: Option<bool>,
+    exclude: Vec<String>,
+    tags: Vec<String>,
+    decisions_only: bool,
+}
+
+impl QueryOptions {
+    fn apply(self, config: &mut RetrievalConfig) {
+        if let Some(budget) = self.token_budget {
+            config.token_budget = budget;
+        }
+        if let Some(reserve) = self.response_reserve {
+            config.response_reserve = reserve;
+        }
+        if let Some(depth) = self.expansion_depth {
+            config.expansion_depth = depth;
+        }
+        if let Some(false) = self.include_narrative {
+            config.include_active_task = false;
+            config.include_log = false;
+            config.narrative_budget = 0;
+        }
+        config.exclude.extend(self.exclude);
+        config.tags.extend(self.tags);
+        config.decisions_only = self.decisions_only;
+    }
+}
+
+/// Builds a prompt pack for `query` and writes it as JSON.
+///
+/// `options_json` may be null or an object with any of `token_budget`,
+/// `response_reserve`, `expansion_depth`, `include_narrative`, `exclude`
+/// (a list of gitignore-style patterns), `tags` (only files from steps
+/// with one of these tags) and `decisions_only` (only recorded decisions).
+///
+/// # Safety
+///
+/// `handle` must be a live handle, `query` a NUL-terminated string,
+/// `options_json` null or a NUL-terminated string and `out_json` null or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_query(
+    handle: *mut CtxHandle,
+    query: *const c_char,
+    options_json: *const c_char,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let repo = repo(handle)?;
+        let query = arg_str(query, "query")?;
+        let mut config = RetrievalConfig::default();
+        if let Some(options) = opt_str(options_json, "options_json")? {
+            let options: QueryOptions = serde_json::from_str(options)
+                .map_err(|e| invalid(format!("invalid query options: {}", e)))?;
+            options.apply(&mut config);
+        }
+        let pack = repo.build_pack(query, &config)?;
+        write_string(out_json, pack.to_json()?)
+    })
+}
+
+/// Summarizes repository state and writes it as JSON, in the shape of
+/// `ctx status --json`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ctx_status(
+    handle: *mut CtxHandle,
+    out_json: *mut *mut c_char,
+) -> CtxStatus {
+    call(|| {
+        let status = repo(handle)?.status()?;
+        let value = serde_json::to_value(&status).map_err(|e| FfiError {
+            status: CtxStatus::Other,
+            message: e.to_string(),
+        })?;
+        write_json(out_json, &value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cstr(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    /// Takes ownership of a returned JSON string.
+    unsafe fn take_json(s: *mut c_char) -> Value {
+        assert!(!s.is_null());
+        let value = serde_json::from_str(CStr::from_ptr(s).to_str().unwrap()).unwrap();
+        ctx_string_free(s);
+        value
+    }
+
+    unsafe fn last_error() -> String {
+        CStr::from_ptr(ctx_last_error())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_session_lifecycle_through_c_api() {
+        let tmp = TempDir::new().unwrap();
+        let path = cstr(tmp.path().to_str().unwrap());
+        unsafe {
+            let mut handle = ptr::null_mut();
+            assert_eq!(ctx_init(path.as_ptr(), &mut handle), CtxStatus::Ok);
+            assert!(ctx_last_error().is_null());
+
+            let mut out = ptr::null_mut();
+            let task = cstr("Add parser");
+            assert_eq!(
+                ctx_start_session(handle, task.as_ptr(), &mut out),
+                CtxStatus::Ok
+            );
+            assert!(take_json(out)["session_id"].is_string());
+
+            let write = cstr(
+                r#"{"type": "write", "path": "src/parser.rs", "content": "pub fn parse() {}"}"#,
+            );
+            assert_eq!(ctx_observe(handle, write.as_ptr()), CtxStatus::Ok);
+            let note = cstr(
+                r#"{"type": "note", "note": "parser added", "metadata": {"ticket": "PROJ-1"}}"#,
+            );
+            assert_eq!(ctx_observe(handle, note.as_ptr()), CtxStatus::Ok);
+            assert_eq!(ctx_flush(handle, ptr::null_mut()), CtxStatus::Ok);
+
+            let message = cstr("Add parser");
+            assert_eq!(
+                ctx_compact(handle, message.as_ptr(), &mut out),
+                CtxStatus::Ok
+            );
+            assert_eq!(take_json(out)["commit_id"].as_str().unwrap().len(), 64);
+
+            let query = cstr("src/parser.rs");
+            let options = cstr(
+                r#"{"token_budget": 2000, "response_reserve": 0, "include_narrative": false,
+                    "tags": ["ticket:PROJ-1"]}"#,
+            );
+            assert_eq!(
+                ctx_query(handle, query.as_ptr(), options.as_ptr(), &mut out),
+                CtxStatus::Ok
+            );
+            let pack = take_json(out);
+            assert!(pack.to_string().contains("pub fn parse() {}"));
+
+            let other = cstr(r#"{"include_narrative": false, "tags": ["ticket:PROJ-2"]}"#);
+            assert_eq!(
+                ctx_query(handle, query.as_ptr(), other.as_ptr(), &mut out),
+                CtxStatus::Ok
+            );
+            assert!(!take_json(out).to_string().contains("pub fn parse() {}"));
+
+            assert_eq!(ctx_status(handle, &mut out), CtxStatus::Ok);
+            assert!(take_json(out)["session"].is_null());
+
+            ctx_close(handle);
+        }
+    }
+
+    #[test]
+    fn test_errors_map_to_status_codes() {
+        let tmp = TempDir::new().unwrap();
+        let path = cstr(tmp.path().to_str().unwrap());
+        unsafe {
+            let mut handle = ptr::null_mut();
+            assert_eq!(ctx_open(path.as_ptr(), &mut handle), CtxStatus::NotFound);
+            assert!(last_error().contains("Not a CTX repository"));
+            assert_eq!(
+                ctx_open(ptr::null(), &mut handle),
+                CtxStatus::InvalidArgument
+            );
+
+            assert_eq!(ctx_init(path.as_ptr(), &mut handle), CtxStatus::Ok);
+            assert_eq!(
+                ctx_flush(handle, ptr::null_mut()),
+                CtxStatus::NoActiveSession
+            );
+
+            let bad = cstr(r#"{"type": "teleport"}"#);
+            assert_eq!(
+                ctx_observe(handle, bad.as_ptr()),
+                CtxStatus::InvalidArgument
+            );
+            assert!(last_error().contains("invalid observation"));
+
+            let query = cstr("anything");
+            let options = cstr(r#"{"budget": 10}"#);
+            assert_eq!(
+                ctx_query(handle, query.as_ptr(), options.as_ptr(), ptr::null_mut()),
+                CtxStatus::InvalidArgument
+            );
+            ctx_close(handle);
+        }
+        assert_eq!(ctx_abi_version(), CTX_ABI_VERSION);
+    }
+}
@@ -61,9 +61,12 @@ pub enum Assertion {
     NoPanic,
 
     // Custom (takes mutable reference to allow mutations)
-    Custom(Box<dyn Fn(&mut CtxRepo) -> Result<()> + Send + Sync>),
+    Custom(CustomAssertion),
 }
 
+/// Boxed closure used by [`Assertion::Custom`].
+pub type CustomAssertion = Box<dyn Fn(&mut CtxRepo) -> Result<()> + Send + Sync>;
+
 impl std::fmt::Debug for Assertion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
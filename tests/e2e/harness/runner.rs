@@ -490,15 +490,24 @@ impl ScenarioRunner {
 
         let actual = session.state();
 
-        let matches = match (expected, actual) {
-            (SessionStateMatch::Running, SessionState::Running) => true,
-            (SessionStateMatch::AwaitingUser, SessionState::AwaitingUser { .. }) => true,
-            (SessionStateMatch::Interrupted, SessionState::Interrupted { .. }) => true,
-            (SessionStateMatch::PendingComplete, SessionState::PendingComplete { .. }) => true,
-            (SessionStateMatch::Complete, SessionState::Complete) => true,
-            (SessionStateMatch::Aborted, SessionState::Aborted { .. }) => true,
-            _ => false,
-        };
+        let matches = matches!(
+            (expected, actual),
+            (SessionStateMatch::Running, SessionState::Running)
+                | (
+                    SessionStateMatch::AwaitingUser,
+                    SessionState::AwaitingUser { .. }
+                )
+                | (
+                    SessionStateMatch::Interrupted,
+                    SessionState::Interrupted { .. }
+                )
+                | (
+                    SessionStateMatch::PendingComplete,
+                    SessionState::PendingComplete { .. }
+                )
+                | (SessionStateMatch::Complete, SessionState::Complete)
+                | (SessionStateMatch::Aborted, SessionState::Aborted { .. })
+        );
 
         if !matches {
             return Err(anyhow!(
@@ -43,6 +43,7 @@ impl Scenario {
     ///
     /// Reads all files from `tests/fixtures/{fixture_name}/` and adds them
     /// to the initial workspace files.
+    #[allow(clippy::wrong_self_convention)]
     pub fn from_fixture(mut self, fixture_name: &str) -> Self {
         let fixture_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
             .join("fixtures")